@@ -0,0 +1,103 @@
+//! Parser benchmarks. Run them with `cargo bench`, and compare against a
+//! baseline with `cargo bench -- --save-baseline main` and
+//! `cargo bench -- --baseline main`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use futures::executor::block_on;
+use gpui::BackgroundExecutor;
+use gpui_markdown_preview::markdown_parser::{
+    parse_markdown_parallel, parse_markdown_with_options, ParseOptions,
+};
+use std::fmt::Write as _;
+
+const EXAMPLE: &str = include_str!("../assets/markdown/example.md");
+
+/// The example document repeated until it's about 5 MB long.
+fn large_document() -> String {
+    let mut text = String::new();
+    while text.len() < 5 * 1024 * 1024 {
+        text.push_str(EXAMPLE);
+        text.push_str("\n\n");
+    }
+    text
+}
+
+/// 200 tables of 50 rows, with some inline markup in their cells.
+fn table_document() -> String {
+    let mut text = String::new();
+    for table in 0..200 {
+        writeln!(text, "## Table {table}\n").unwrap();
+        text.push_str("| Name | Kind | `Code` | Size | Notes |\n");
+        text.push_str("|:-----|:----:|--------|-----:|-------|\n");
+        for row in 0..50 {
+            writeln!(
+                text,
+                "| item {row} | **bold** | `value_{row}` | {} | a [link](https://zed.dev/{row}) |",
+                row * 31
+            )
+            .unwrap();
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Lists nested 30 deep, with tasks and a paragraph under some items.
+fn nested_list_document() -> String {
+    let mut text = String::new();
+    for list in 0..50 {
+        for depth in 0..30 {
+            let indent = "  ".repeat(depth);
+            if depth % 3 == 0 {
+                writeln!(text, "{indent}- [ ] task {list}.{depth}").unwrap();
+            } else {
+                writeln!(text, "{indent}- item {list}.{depth} with *emphasis*").unwrap();
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn parse(text: &str, options: &ParseOptions) {
+    block_on(parse_markdown_with_options(text, None, None, options));
+}
+
+fn parse_parallel(text: &str, options: &ParseOptions, executor: &BackgroundExecutor) {
+    block_on(parse_markdown_parallel(text, None, None, options, executor));
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let options = ParseOptions::default();
+    let large = large_document();
+    let tables = table_document();
+    let nested_lists = nested_list_document();
+
+    let mut group = c.benchmark_group("parse");
+    for (name, text) in [
+        ("example", EXAMPLE),
+        ("tables", tables.as_str()),
+        ("nested_lists", nested_lists.as_str()),
+    ] {
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_function(name, |b| b.iter(|| parse(text, &options)));
+    }
+    group.finish();
+
+    // A parse of the large document takes long enough that fewer samples
+    // still give a stable result.
+    let mut group = c.benchmark_group("parse_large");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(large.len() as u64));
+    group.bench_function("5mb", |b| b.iter(|| parse(&large, &options)));
+    // The same document with its blocks parsed on every core, to compare
+    // against the one above.
+    let executor = gpui::App::headless().background_executor();
+    group.bench_function("5mb_parallel", |b| {
+        b.iter(|| parse_parallel(&large, &options, &executor))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,77 @@
+//! Frame benchmarks. Run them with `cargo bench --bench render`.
+//!
+//! A frame of a long document is laid out and painted the way a
+//! [`MarkdownView`] does it, which only builds the blocks near the viewport,
+//! and the way every block would be if they were all built, to compare the
+//! two.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use gpui::{div, point, px, size, AvailableSpace, ParentElement as _, Styled as _, TestAppContext};
+use gpui_markdown_preview::{
+    init, parse_markdown, render_markdown_block, MarkdownView, RenderContext,
+};
+use settings::SettingsStore;
+use std::fmt::Write as _;
+use theme::LoadThemes;
+
+/// 10,000 blocks of headings, paragraphs, list items and code.
+fn long_document() -> String {
+    let mut text = String::new();
+    for section in 0..2500 {
+        writeln!(text, "## Section {section}\n").unwrap();
+        writeln!(
+            text,
+            "Some *emphasized* and **bold** text with `code` and a [link](https://zed.dev/{section}).\n"
+        )
+        .unwrap();
+        writeln!(text, "- an item in section {section}\n").unwrap();
+        writeln!(text, "```\nlet section = {section};\n```\n").unwrap();
+    }
+    text
+}
+
+fn bench_render(c: &mut Criterion) {
+    let mut cx = TestAppContext::single();
+    cx.update(|cx| {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+        theme::init(LoadThemes::JustBase, cx);
+        init(cx);
+    });
+    let text = long_document();
+    let parsed = block_on(parse_markdown(&text, None, None));
+    let (view, cx) = cx.add_window_view(|cx| MarkdownView::new(text.clone(), None, cx));
+    cx.run_until_parked();
+
+    let viewport = size(
+        AvailableSpace::Definite(px(800.)),
+        AvailableSpace::Definite(px(600.)),
+    );
+    let mut group = c.benchmark_group("frame_10k_blocks");
+    group.sample_size(10);
+    group.bench_function("view", |b| {
+        b.iter(|| cx.draw(point(px(0.), px(0.)), viewport, |_| view.clone()))
+    });
+    group.bench_function("every_block", |b| {
+        b.iter(|| {
+            cx.draw(point(px(0.), px(0.)), viewport, |cx| {
+                div().w(px(800.)).children(
+                    parsed
+                        .children
+                        .iter()
+                        .enumerate()
+                        .map(|(block_ix, block)| {
+                            let mut render_cx = RenderContext::new(cx).with_block_index(block_ix);
+                            render_markdown_block(block, &mut render_cx)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_render);
+criterion_main!(benches);
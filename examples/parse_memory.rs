@@ -0,0 +1,65 @@
+//! Prints how much heap a parsed document takes up, next to the size of its
+//! source. Run it with `cargo run --release --example parse_memory`, or pass
+//! the path of a document to measure that instead of a generated 10 MB one.
+
+use futures::executor::block_on;
+use gpui_markdown_preview::markdown_parser::parse_markdown;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const EXAMPLE: &str = include_str!("../assets/markdown/example.md");
+
+/// Counts the bytes that are allocated and not yet freed.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn main() {
+    let text = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(&path).expect("failed to read the document"),
+        None => {
+            let mut text = String::new();
+            while text.len() < 10 * 1024 * 1024 {
+                text.push_str(EXAMPLE);
+                text.push_str("\n\n");
+            }
+            text
+        }
+    };
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let parsed = block_on(parse_markdown(&text, None, None));
+    let used = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    let megabytes = |bytes: usize| bytes as f64 / (1024. * 1024.);
+    println!(
+        "{} blocks from {:.1} MB of source take up {:.1} MB, {:.2}x the source",
+        parsed.children.len(),
+        megabytes(text.len()),
+        megabytes(used),
+        used as f64 / text.len() as f64
+    );
+}
@@ -0,0 +1,38 @@
+//! A Markdown preview for gpui applications.
+//!
+//! Call [`init`] when the application starts, or [`init_standalone`] in one
+//! without settings of its own, then show documents with a [`MarkdownView`].
+//! To render Markdown inside other views instead, parse it with
+//! [`parse_markdown`] and render its blocks with [`render_markdown_block`].
+
+mod markdown_preview;
+
+pub use markdown_preview::{
+    init, init_standalone, markdown_accessibility, markdown_diagnostics, markdown_diff,
+    markdown_editor, markdown_elements, markdown_fences, markdown_format, markdown_front_matter,
+    markdown_html, markdown_html_parser, markdown_images, markdown_link_check, markdown_locale,
+    markdown_parser, markdown_pdf, markdown_recent, markdown_renderer, markdown_search,
+    markdown_session, markdown_settings, markdown_sidebar, markdown_spellcheck, markdown_startup,
+    markdown_tabs, markdown_theme, markdown_ui_state, markdown_view, ActivateNextTab,
+    ActivatePreviousTab, CheckLinks, ClearRecent, CloseLightbox, CloseTab, Compare,
+    CompareWithSaved, Copy, CopyAsHtml, CopyAsMarkdown, CopyDiagnostics, DeployContextMenu,
+    DismissFind, ExportHtml, ExportPdf, Find, FoldAllSections, FormatDocument, GoBack, GoForward,
+    GoToHeading, NewFromClipboard, NewTab, NextChange, OpenFolder, OpenPreview,
+    OpenPreviewToTheSide, OpenRecent, OpenRecentFile, PreviousChange, Print, Reload, ResetZoom,
+    Save, SaveAs, ScrollLineDown, ScrollLineUp, ScrollPageDown, ScrollPageUp, ScrollToBottom,
+    ScrollToTop, SelectAll, SelectNextMatch, SelectPreviousMatch, ToggleCaseSensitive,
+    ToggleDebugOverlay, ToggleFollow, ToggleOutline, ToggleSidebar, ToggleTheme, UnfoldAllSections,
+    ZoomIn, ZoomOut,
+};
+
+#[cfg(any(test, feature = "test-support"))]
+pub use markdown_preview::markdown_layout;
+
+pub use markdown_editor::MarkdownEditor;
+pub use markdown_elements::ParsedMarkdown;
+pub use markdown_parser::parse_markdown;
+pub use markdown_renderer::{
+    render_markdown_block, render_markdown_inline, RenderContext, RenderMode,
+};
+pub use markdown_tabs::MarkdownTabs;
+pub use markdown_view::{MarkdownEvent, MarkdownView, MarkdownViewBuilder};
@@ -1,234 +1,581 @@
-mod markdown_preview;
-use anyhow::Result;
-use gpui::{div, prelude::*, Model, Task, ViewContext};
-
-use markdown_preview::{
-    markdown_elements::ParsedMarkdown, markdown_parser::parse_markdown,
-    markdown_renderer::render_markdown_block,
+use anyhow::{Context as _, Result};
+use futures::{channel::mpsc, StreamExt as _};
+use gpui::{prelude::*, App, AppContext, PathPromptOptions, WindowOptions};
+use gpui_markdown_preview::{
+    markdown_diagnostics::RecordingLogger,
+    markdown_diff::MarkdownDiffView,
+    markdown_elements::{expand_path, file_url_path, split_path_fragment},
+    markdown_recent::{app_menus, RecentFiles},
+    markdown_renderer::MarkdownStyle,
+    markdown_session::{missing_files_message, Session, SessionStore},
+    markdown_settings::UserSettingsFile,
+    markdown_startup::init_with_fallbacks,
+    markdown_ui_state::UiStateStore,
+    markdown_view::LoadError,
+    Compare, MarkdownEditor, MarkdownTabs, MarkdownView, NewFromClipboard, OpenFolder,
+    OpenRecentFile,
 };
-
-use gpui::App;
-use gpui::WindowOptions;
+use notify::{RecursiveMode, Watcher as _};
 use settings::SettingsStore;
-use theme::LoadThemes;
-
-use serde::de::DeserializeOwned;
-
-use std::fs::read_to_string;
-use util;
-
-const MARKDOWN_EXAMPLE: &str = r#"
-# Markdown Example Document
-
-## Headings
-Headings are created by adding one or more `#` symbols before your heading text. The number of `#` you use will determine the size of the heading.
-
-```rust
-gpui::window::ViewContext
-impl<'a, V> ViewContext<'a, V>
-pub fn on_blur(&mut self, handle: &FocusHandle, listener: impl FnMut(&mut V, &mut iewContext<V>) + 'static) -> Subscription
-where
-    // Bounds from impl:
-    V: 'static,
-```
-
-## Tables
-|  table1   | table2  |
-|  ----  | ----  |
-| item11  | item12 |
-| item21  | item22 |
-
-
-## Emphasis
-Emphasis can be added with italics or bold. *This text will be italic*. _This will also be italic_
-
-## Lists
-
-### Unordered Lists
-Unordered lists use asterisks `*`, plus `+`, or minus `-` as list markers.
-
-* Item 1
-* Item 2
-  * Item 2a
-  * Item 2b
-
-### Ordered Lists
-Ordered lists use numbers followed by a period.
-
-1. Item 1
-2. Item 2
-3. Item 3
-   1. Item 3a
-   2. Item 3b
-
-## Links
-Links are created using the format [http://zed.dev](https://zed.dev).
-
-They can also be detected automatically, for example https://zed.dev/blog.
-
-## Images
-Images are like links, but with an exclamation mark `!` in front.
-
-![This is an image](https://repology.org/badge/vertical-allrepos/zed-editor.svg?minversion=0.143.5)
-
-```todo!
-![This is an image](/images/logo.png)
-```
+use theme::SystemAppearance;
+
+use reqwest_client::ReqwestClient;
+use std::{
+    fs::read_to_string,
+    io::Read as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use util::{self, ResultExt as _};
 
-## Code
-Inline `code` can be wrapped with backticks `` ` ``.
+/// Sent with every request, since some servers turn away clients without one.
+const USER_AGENT: &str = concat!("gpui-markdown-preview/", env!("CARGO_PKG_VERSION"));
 
-```markdown
-Inline `code` has `back-ticks around` it.
-```
+const MARKDOWN_EXAMPLE: &str = include_str!("../assets/markdown/example.md");
 
-Code blocks can be created by indenting lines by four spaces or with triple backticks ```.
+/// Saving the settings file tends to produce a burst of change events.
+const SETTINGS_RELOAD_DEBOUNCE: Duration = Duration::from_millis(50);
 
-```javascript
-function test() {
-  console.log("notice the blank line before this function?");
+/// Applies `content` as the user settings. If it isn't valid, the defaults
+/// apply instead.
+fn set_user_settings(content: &str, cx: &mut AppContext) {
+    cx.update_global::<SettingsStore, _>(|store, cx| {
+        if let Err(error) = store.set_user_settings(content, cx) {
+            log::error!(fallback = "defaults"; "failed to load the user settings: {:#}", error);
+            store.set_user_settings("{}", cx).log_err();
+        }
+    });
+    cx.refresh();
 }
-```
-
-## Blockquotes
-Blockquotes are created with `>`.
-
-> This is a blockquote.
-
-## Horizontal Rules
-Horizontal rules are created using three or more asterisks `***`, dashes `---`, or underscores `___`.
-
-## Line breaks
-This is a
-\
-line break!
-
----
 
-Remember, markdown processors may have slight differences and extensions, so always refer to the specific documentation or guides relevant to your platform or editor for the best practices and additional features.
-"#;
+/// Applies the user settings at `path` again whenever the file changes.
+fn watch_user_settings(path: PathBuf, cx: &mut AppContext) {
+    let (changes_tx, mut changes_rx) = mpsc::unbounded();
+    let watcher = notify::recommended_watcher({
+        let path = path.clone();
+        move |event: notify::Result<notify::Event>| {
+            if event.map_or(false, |event| event.paths.contains(&path)) {
+                changes_tx.unbounded_send(()).ok();
+            }
+        }
+    });
 
-pub const EMPTY_THEME_NAME: &str = "empty-theme";
+    // Watching the directory also catches the file being created, and
+    // editors that save by renaming a new file over the old one.
+    let watched = watcher.and_then(|mut watcher| {
+        let directory = path.parent().unwrap_or(path.as_path());
+        std::fs::create_dir_all(directory).log_err();
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+    let watcher = match watched {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            log::error!("failed to watch {}: {}", path.display(), error);
+            return;
+        }
+    };
+
+    cx.spawn(|mut cx| async move {
+        let _watcher = watcher;
+        while changes_rx.next().await.is_some() {
+            cx.background_executor()
+                .timer(SETTINGS_RELOAD_DEBOUNCE)
+                .await;
+            while let Ok(Some(())) = changes_rx.try_next() {}
+            log::debug!(path:% = path.display(); "settings file changed");
+
+            // A deleted file leaves only the defaults.
+            let content = read_to_string(&path).unwrap_or_else(|_| "{}".to_string());
+            if cx.update(|cx| set_user_settings(&content, cx)).is_err() {
+                break;
+            }
+        }
+    })
+    .detach();
+}
 
-pub fn parse_json_with_comments<T: DeserializeOwned>(content: &str) -> Result<T> {
-    Ok(serde_json_lenient::from_str(content)?)
+/// The settings file in the platform's config directory, where the preview
+/// saves the settings changed from it, like its zoom.
+fn user_settings_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("settings.json"))
 }
 
-pub fn test_settings() -> String {
+/// Where the list of recently opened files is kept.
+fn recent_files_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("recent_files.json"))
+}
 
-    let contents = read_to_string("./assets/settings/default.json").unwrap();
+/// Where the windows that were open are kept, to reopen on the next launch.
+fn session_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("session.json"))
+}
 
-    let mut value = parse_json_with_comments::<serde_json::Value>(
-        contents.as_ref(),
-    )
-    .unwrap();
+/// Where each document's folds, zoom and scroll position are kept, to show
+/// it the same way the next time it's opened.
+fn ui_state_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("document_state.json"))
+}
 
-    util::merge_non_null_json_value_into(
-        serde_json::json!({
-            "ui_font_family": "Courier",
-            "ui_font_features": {},
-            "ui_font_size": 14,
-            "ui_font_fallback": [],
-            "buffer_font_family": "Courier",
-            "buffer_font_features": {},
-            "buffer_font_size": 14,
-            "buffer_font_fallback": [],
-            "theme": EMPTY_THEME_NAME,
-        }),
-        &mut value,
-    );
-    value.as_object_mut().unwrap().remove("languages");
-    serde_json::to_string(&value).unwrap()
+/// The client for downloading images and checking links, through the proxy
+/// set by `HTTPS_PROXY`, `HTTP_PROXY` or `ALL_PROXY`, except to the hosts
+/// `NO_PROXY` lists.
+fn http_client() -> ReqwestClient {
+    let proxy = http_client::read_proxy_from_env();
+    ReqwestClient::proxy_and_user_agent(proxy, USER_AGENT).unwrap_or_else(|error| {
+        log::error!("failed to set up the proxy: {:#}", error);
+        ReqwestClient::new()
+    })
 }
 
+fn config_dir() -> Option<PathBuf> {
+    let config_dir = if cfg!(target_os = "macos") {
+        PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+    } else if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?
+    };
+    Some(config_dir.join("gpui_markdown_preview"))
+}
 
-pub fn main() {
-    // env_logger::init();
-    App::new().run(|cx| {
-        let mut store = SettingsStore::new(cx);
+struct Document {
+    text: String,
+    /// The directory relative links and images are resolved against.
+    base_directory: Option<PathBuf>,
+    /// The file the document was read from, if it wasn't read from stdin.
+    path: Option<PathBuf>,
+    /// The heading or line to scroll to, from a path like
+    /// `guide.md#install` or `guide.md#L42`.
+    fragment: Option<String>,
+}
 
+/// Reads the document named on the command line, either a path, with `~`
+/// and environment variables expanded, or a `file://` URL, which can end in
+/// a `#fragment` naming a heading or a line, or `-` for stdin. Symlinks are
+/// resolved, so that the real file is watched.
+fn load_document(arg: &str) -> Result<Document> {
+    if arg == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .context("failed to read from stdin")?;
+        let text = String::from_utf8(bytes).context("stdin is not valid UTF-8")?;
+        return Ok(Document {
+            text,
+            base_directory: std::env::current_dir().ok(),
+            path: None,
+            fragment: None,
+        });
+    }
 
-        // let mut this = Self::new(cx);
-        store.set_default_settings(&test_settings(), cx)
-            .unwrap();
-        store.set_user_settings("{}", cx).unwrap();
-        
-        cx.set_global(store);
+    let arg = expand_path(arg, None);
+    let arg = arg.as_ref();
+    let (path, fragment) = file_url_path(arg).unwrap_or_else(|| {
+        let (path, fragment) = split_path_fragment(arg, None);
+        (PathBuf::from(path), fragment.map(str::to_string))
+    });
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("{} does not exist", arg))?;
+    let bytes = std::fs::read(&path).with_context(|| format!("failed to read {}", arg))?;
+    let text = String::from_utf8(bytes).with_context(|| format!("{} is not valid UTF-8", arg))?;
+    Ok(Document {
+        text,
+        base_directory: path.parent().map(Path::to_path_buf),
+        path: Some(path),
+        fragment,
+    })
+}
 
-        theme::init(LoadThemes::JustBase, cx);
+/// Shows the document named on the command line with nothing but the
+/// preview's own defaults, and colors from a preset for the appearance of
+/// the OS rather than from a theme.
+fn open_standalone(cx: &mut AppContext) {
+    gpui_markdown_preview::init_standalone(cx);
+    let document = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .map_or_else(
+            || {
+                Ok(Document {
+                    text: MARKDOWN_EXAMPLE.to_string(),
+                    base_directory: None,
+                    path: None,
+                    fragment: None,
+                })
+            },
+            |arg| load_document(&arg),
+        );
+    let document = match document {
+        Ok(document) => document,
+        Err(error) => {
+            log::error!("{:#}", error);
+            cx.quit();
+            return;
+        }
+    };
+    let style = if SystemAppearance::global(cx).0.is_light() {
+        MarkdownStyle::light()
+    } else {
+        MarkdownStyle::dark()
+    };
+
+    cx.activate(true);
+    cx.open_window(WindowOptions::default(), |cx| {
+        let mut builder = MarkdownView::builder(document.text).style(style);
+        if let Some(base_directory) = document.base_directory {
+            builder = builder.base_path(base_directory);
+        }
+        let view = builder.build(cx);
+        view.update(cx, |view, cx| {
+            if let Some(path) = document.path {
+                view.watch_file(path, cx);
+            }
+            if let Some(fragment) = document.fragment {
+                view.scroll_to_fragment(fragment, cx);
+            }
+        });
+        cx.focus_view(&view);
+        view
+    })
+    .log_err();
+}
 
-        cx.activate(true);
+/// Opens a window for each window of a session, with `notice` shown in the
+/// first.
+fn open_session(session: Session, mut notice: Option<String>, cx: &mut AppContext) {
+    for window in session.windows {
+        let notice = notice.take();
         cx.open_window(WindowOptions::default(), |cx| {
-            cx.new_view(|cx| {
-                MarkdownView::from(MARKDOWN_EXAMPLE.into(), cx)
-            })
+            let tabs = cx.new_view(|cx| {
+                let mut tabs = MarkdownTabs::new(cx);
+                tabs.restore_session(window, cx);
+                if let Some(notice) = notice {
+                    tabs.show_notice(notice, cx);
+                }
+                tabs
+            });
+            cx.focus_view(&tabs);
+            tabs
         })
-        .unwrap();
-    });
+        .log_err();
+    }
 }
 
+/// Opens a window with the Markdown files in `directory` in a sidebar, and
+/// its README in a tab if it has one, with `notice` shown above them.
+fn open_folder_window(
+    directory: PathBuf,
+    notice: Option<String>,
+    cx: &mut AppContext,
+) -> Result<()> {
+    cx.open_window(WindowOptions::default(), |cx| {
+        let tabs = cx.new_view(|cx| {
+            let mut tabs = MarkdownTabs::new(cx);
+            if let Some(notice) = notice {
+                tabs.show_notice(notice, cx);
+            }
+            let readme = ["README.md", "readme.md", "index.md"]
+                .iter()
+                .map(|name| directory.join(name))
+                .find(|path| path.is_file());
+            tabs.open_folder(directory, cx);
+            if let Some(readme) = readme {
+                tabs.open_paths(vec![readme], cx);
+            }
+            tabs
+        });
+        cx.focus_view(&tabs);
+        tabs
+    })?;
+    Ok(())
+}
 
+/// Opens a window comparing the document at `old_path` with the one at
+/// `new_path`.
+fn open_diff_window(old_path: PathBuf, new_path: PathBuf, cx: &mut AppContext) -> Result<()> {
+    cx.open_window(WindowOptions::default(), |cx| {
+        let view = cx.new_view(|cx| MarkdownDiffView::compare_files(old_path, new_path, cx));
+        cx.focus_view(&view);
+        view
+    })?;
+    Ok(())
+}
 
-pub struct MarkdownView {
-    raw_text: String,
-    contents: Option<ParsedMarkdown>,
-    parsing_markdown_task: Option<Task<Result<()>>>,
+/// Prints warnings and errors, or with `--verbose` everything about the
+/// preview down to debug messages, unless `RUST_LOG` says otherwise. The
+/// last lines logged are kept either way, for "Copy Diagnostics".
+fn init_logging() {
+    let verbose = std::env::args().any(|arg| arg == "--verbose");
+    let (filter, level) = if verbose {
+        ("info,gpui_markdown_preview=debug", log::LevelFilter::Debug)
+    } else {
+        ("warn", log::LevelFilter::Info)
+    };
+    let printer =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(filter)).build();
+    RecordingLogger::new(level, Some(Box::new(printer)))
+        .install()
+        .log_err();
 }
 
-impl MarkdownView {
-    pub fn from(text: String, cx: &mut ViewContext<Self>) -> Self {
-        let task = cx.spawn(|markdown_view, mut cx| {
-            let text = text.clone();
-            let parsed = cx
-                .background_executor()
-                .spawn(async move { parse_markdown(&text, None, None).await });
+pub fn main() {
+    init_logging();
+    App::new()
+        .with_http_client(Arc::new(http_client()))
+        .run(|cx| {
+            // `--standalone` skips the settings file, recent files and
+            // sessions, the way an application embedding the preview would.
+            if std::env::args().any(|arg| arg == "--standalone") {
+                open_standalone(cx);
+                return;
+            }
 
-            async move {
-                let content = parsed.await;
+            // Settings, themes or fonts that can't be loaded fall back to
+            // built-in ones, and the first window says which.
+            let settings_path = user_settings_path();
+            let user_settings = settings_path
+                .as_ref()
+                .and_then(|path| read_to_string(path).ok());
+            let warnings = init_with_fallbacks(user_settings.as_deref().unwrap_or("{}"), cx);
+            let mut notice = (!warnings.is_empty()).then(|| warnings.join("\n"));
+            if let Some(path) = settings_path {
+                watch_user_settings(path.clone(), cx);
+                cx.set_global(UserSettingsFile(path));
+            }
 
-                markdown_view.update(&mut cx, |markdown, cx| {
-                    markdown.parsing_markdown_task.take();
-                    markdown.contents = Some(content);
-                    cx.notify();
+            if let Some(path) = recent_files_path() {
+                cx.set_global(RecentFiles::load(path));
+            }
+            if let Some(path) = session_path() {
+                cx.set_global(SessionStore::new(path));
+                cx.on_app_quit(|cx| {
+                    let save = SessionStore::save(cx);
+                    async move {
+                        save.await.log_err();
+                    }
+                })
+                .detach();
+            }
+            if let Some(path) = ui_state_path() {
+                cx.set_global(UiStateStore::load(path));
+                cx.on_app_quit(|cx| {
+                    let save = UiStateStore::save(cx);
+                    async move {
+                        save.await.log_err();
+                    }
+                })
+                .detach();
+            }
+            // Windows with tabs open recent files in a new tab, and any other
+            // window leaves them to this.
+            cx.on_action(|action: &OpenRecentFile, cx| {
+                let path = action.path.clone();
+                cx.open_window(WindowOptions::default(), |cx| {
+                    let tabs = cx.new_view(|cx| {
+                        let mut tabs = MarkdownTabs::new(cx);
+                        tabs.open_paths(vec![path], cx);
+                        tabs
+                    });
+                    cx.focus_view(&tabs);
+                    tabs
+                })
+                .log_err();
+            });
+            cx.on_action(|_: &OpenFolder, cx| {
+                let paths = cx.prompt_for_paths(PathPromptOptions {
+                    files: false,
+                    directories: true,
+                    multiple: false,
+                });
+                cx.spawn(|cx| async move {
+                    let Some(path) = paths.await??.and_then(|paths| paths.into_iter().next())
+                    else {
+                        return Ok(());
+                    };
+                    cx.update(|cx| open_folder_window(path, None, cx))?
                 })
+                .detach_and_log_err(cx);
+            });
+            // Both files are picked at once, the older one first.
+            cx.on_action(|_: &Compare, cx| {
+                let paths = cx.prompt_for_paths(PathPromptOptions {
+                    files: true,
+                    directories: false,
+                    multiple: true,
+                });
+                cx.spawn(|cx| async move {
+                    let Some(paths) = paths.await?? else {
+                        return Ok(());
+                    };
+                    let [old_path, new_path] =
+                        <[PathBuf; 2]>::try_from(paths).map_err(|paths| {
+                            anyhow::anyhow!("comparing needs two files, not {}", paths.len())
+                        })?;
+                    cx.update(|cx| open_diff_window(old_path, new_path, cx))?
+                })
+                .detach_and_log_err(cx);
+            });
+            cx.on_action(|_: &NewFromClipboard, cx| {
+                let Some(text) = cx.read_from_clipboard().and_then(|item| item.text()) else {
+                    return;
+                };
+                cx.open_window(WindowOptions::default(), |cx| {
+                    let tabs = cx.new_view(|cx| {
+                        let mut tabs = MarkdownTabs::new(cx);
+                        let view = cx.new_view(|cx| MarkdownView::scratch(text, cx));
+                        tabs.add_tab(view, cx);
+                        tabs
+                    });
+                    cx.focus_view(&tabs);
+                    tabs
+                })
+                .log_err();
+            });
+            cx.set_menus(app_menus(cx));
+            cx.observe_global::<RecentFiles>(|cx| cx.set_menus(app_menus(cx)))
+                .detach();
+
+            // `--edit` opens the document in an editor next to its preview,
+            // `--diff` compares the two documents that follow it,
+            // `--new-window` (or `--no-restore`) skips reopening the last
+            // session, and `--verbose` logs more, read by `init_logging`.
+            let mut args: Vec<String> = std::env::args().skip(1).collect();
+            let mut edit = false;
+            let mut diff = false;
+            let mut restore = true;
+            while let Some(flag) = args.first() {
+                match flag.as_str() {
+                    "--edit" => edit = true,
+                    "--diff" => diff = true,
+                    "--new-window" | "--no-restore" => restore = false,
+                    "--standalone" | "--verbose" => {}
+                    _ => break,
+                }
+                args.remove(0);
+            }
+            if diff {
+                let [old_path, new_path] = [0, 1].map(|ix| {
+                    let arg = args.get(ix).map(String::as_str).unwrap_or_default();
+                    PathBuf::from(expand_path(arg, None).as_ref())
+                });
+                cx.activate(true);
+                open_diff_window(old_path, new_path, cx).log_err();
+                return;
+            }
+            // Launching without a document reopens what was open when the
+            // application last quit, leaving out the files that have since
+            // gone and listing them instead.
+            let session = (restore && args.is_empty() && !edit && cx.has_global::<SessionStore>())
+                .then(|| cx.global::<SessionStore>().load())
+                .flatten();
+            if let Some(mut session) = session {
+                let missing = session.prune();
+                if !missing.is_empty() {
+                    let message = missing_files_message(&missing);
+                    notice = Some(match notice {
+                        Some(notice) => format!("{}\n{}", notice, message),
+                        None => message,
+                    });
+                }
+                if !session.windows.is_empty() {
+                    cx.activate(true);
+                    open_session(session, notice, cx);
+                    return;
+                }
+            }
+            // A directory opens with its Markdown files in a sidebar.
+            if let Some(directory) = args
+                .first()
+                .map(|arg| PathBuf::from(expand_path(arg, None).as_ref()))
+                .filter(|path| path.is_dir())
+            {
+                cx.activate(true);
+                open_folder_window(directory, notice, cx).log_err();
+                return;
             }
+            // Failing to open the document still shows a window, explaining
+            // what went wrong.
+            let mut load_error = None;
+            let document = match args.first() {
+                Some(arg) => load_document(arg).unwrap_or_else(|error| {
+                    let path = (arg != "-").then(|| {
+                        let arg = expand_path(arg, None);
+                        let (path, _) = split_path_fragment(&arg, None);
+                        std::env::current_dir().unwrap_or_default().join(path)
+                    });
+                    load_error = Some(LoadError {
+                        message: format!("{:#}", error).into(),
+                        path,
+                    });
+                    Document {
+                        text: String::new(),
+                        base_directory: None,
+                        path: None,
+                        fragment: None,
+                    }
+                }),
+                None => Document {
+                    text: MARKDOWN_EXAMPLE.to_string(),
+                    base_directory: None,
+                    path: None,
+                    fragment: None,
+                },
+            };
+
+            cx.activate(true);
+            if edit {
+                cx.open_window(WindowOptions::default(), |cx| {
+                    let view =
+                        cx.new_view(|cx| MarkdownEditor::new(document.text, document.path, cx));
+                    // Editing starts over with an empty document, so there's
+                    // nothing to retry.
+                    if let Some(error) = load_error {
+                        view.read(cx).preview().clone().update(cx, |preview, cx| {
+                            preview.show_load_error(error.message, None, cx)
+                        });
+                    }
+                    if let Some(fragment) = document.fragment {
+                        view.read(cx)
+                            .preview()
+                            .clone()
+                            .update(cx, |preview, cx| preview.scroll_to_fragment(fragment, cx));
+                    }
+                    cx.focus_view(&view);
+                    view
+                })
+                .log_err();
+                return;
+            }
+            cx.open_window(WindowOptions::default(), |cx| {
+                let view = cx.new_view(|cx| {
+                    let mut view = MarkdownView::new(document.text, document.base_directory, cx);
+                    if let Some(path) = document.path {
+                        view.watch_file(path, cx);
+                    }
+                    if let Some(fragment) = document.fragment {
+                        view.scroll_to_fragment(fragment, cx);
+                    }
+                    if let Some(error) = load_error {
+                        view.show_load_error(error.message, error.path, cx);
+                    }
+                    view
+                });
+                let tabs = cx.new_view(|cx| {
+                    let mut tabs = MarkdownTabs::new(cx);
+                    tabs.add_tab(view, cx);
+                    if let Some(notice) = notice {
+                        tabs.show_notice(notice, cx);
+                    }
+                    tabs
+                });
+                cx.focus_view(&tabs);
+                tabs
+            })
+            .log_err();
         });
-
-        Self {
-            raw_text: text.clone(),
-            contents: None,
-            parsing_markdown_task: Some(task),
-        }
-    }
 }
-
-
-impl Render for MarkdownView {
-    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
-        let Some(parsed) = self.contents.as_ref() else {
-            return div().into_any_element();
-        };
-
-        let mut markdown_render_context =
-            markdown_preview::markdown_renderer::RenderContext::new(cx);
-
-        div()
-            .id("markdown-preview-example")
-            .debug_selector(|| "foo".into())
-            .relative()
-            .bg(gpui::white())
-            .size_full()
-            .p_4()
-            .overflow_y_scroll()
-            .children(
-                parsed.children.iter().map(|child| {
-                    div().relative().child(
-                        div()
-                            .relative()
-                            .child(render_markdown_block(child, &mut markdown_render_context)),
-                    )
-                })
-            )
-            .into_any_element()
-    }
-}
\ No newline at end of file
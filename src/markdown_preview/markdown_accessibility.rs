@@ -0,0 +1,377 @@
+use super::markdown_elements::{
+    HeadingLevel, ParsedMarkdown, ParsedMarkdownCodeBlock, ParsedMarkdownElement,
+    ParsedMarkdownImage, ParsedMarkdownListItem, ParsedMarkdownListItemType, ParsedMarkdownTable,
+    ParsedMarkdownText,
+};
+use std::ops::Range;
+
+/// What an element of the rendered document is, in the terms screen readers
+/// use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessibilityRole {
+    Document,
+    Heading { level: u8 },
+    Paragraph,
+    Link,
+    Image,
+    List { ordered: bool },
+    ListItem,
+    Checkbox { checked: bool },
+    Table,
+    Row,
+    Cell,
+    BlockQuote,
+    CodeBlock,
+    Button,
+    Separator,
+}
+
+/// An element of the rendered document as assistive technology sees it.
+///
+/// gpui doesn't hand elements to the platform's accessibility APIs yet, so
+/// the preview describes its content with this tree instead, built from the
+/// same parsed blocks it renders. [`MarkdownView::accessibility_tree`] is the
+/// place a platform bridge can read it from.
+///
+/// [`MarkdownView::accessibility_tree`]: super::markdown_view::MarkdownView::accessibility_tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessibilityNode {
+    pub role: AccessibilityRole,
+    /// The text announced for the element: a heading's text, an image's alt
+    /// text or the language of a code block.
+    pub label: String,
+    /// Where a link goes.
+    pub value: Option<String>,
+    pub source_range: Range<usize>,
+    pub children: Vec<AccessibilityNode>,
+}
+
+impl AccessibilityNode {
+    fn new(role: AccessibilityRole, label: impl Into<String>, source_range: Range<usize>) -> Self {
+        Self {
+            role,
+            label: label.into(),
+            value: None,
+            source_range,
+            children: Vec::new(),
+        }
+    }
+
+    /// Whether keyboard focus stops at the element.
+    pub fn is_focusable(&self) -> bool {
+        matches!(
+            self.role,
+            AccessibilityRole::Link
+                | AccessibilityRole::Checkbox { .. }
+                | AccessibilityRole::Button
+        )
+    }
+
+    /// The node and all the nodes under it, in reading order.
+    pub fn descendants(&self) -> Vec<&AccessibilityNode> {
+        let mut nodes = vec![self];
+        for child in &self.children {
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    /// The focusable nodes in the order keyboard focus moves through them,
+    /// which is the order they're read in.
+    pub fn focus_order(&self) -> Vec<&AccessibilityNode> {
+        self.descendants()
+            .into_iter()
+            .filter(|node| node.is_focusable())
+            .collect()
+    }
+}
+
+/// Describes a parsed document for assistive technology.
+pub fn accessibility_tree(parsed: &ParsedMarkdown) -> AccessibilityNode {
+    let end = parsed
+        .children
+        .last()
+        .map_or(0, |block| block.source_range().end);
+    let mut document = AccessibilityNode::new(AccessibilityRole::Document, "", 0..end);
+    document.children = block_nodes(&parsed.children);
+    document
+}
+
+fn block_nodes(blocks: &[ParsedMarkdownElement]) -> Vec<AccessibilityNode> {
+    let mut nodes = Vec::new();
+    let mut ix = 0;
+    while ix < blocks.len() {
+        if blocks[ix].is_list_item() {
+            // Like the HTML export, runs of list items become lists, nested
+            // inside the previous item wherever the depth increases.
+            let items: Vec<_> = blocks[ix..]
+                .iter()
+                .map_while(|block| match block {
+                    ParsedMarkdownElement::ListItem(item) => Some(item),
+                    _ => None,
+                })
+                .collect();
+            ix += items.len();
+            nodes.extend(list_nodes(&items));
+        } else {
+            nodes.push(block_node(&blocks[ix]));
+            ix += 1;
+        }
+    }
+    nodes
+}
+
+fn block_node(block: &ParsedMarkdownElement) -> AccessibilityNode {
+    match block {
+        ParsedMarkdownElement::Heading(heading) => {
+            let level = match heading.level {
+                HeadingLevel::H1 => 1,
+                HeadingLevel::H2 => 2,
+                HeadingLevel::H3 => 3,
+                HeadingLevel::H4 => 4,
+                HeadingLevel::H5 => 5,
+                HeadingLevel::H6 => 6,
+            };
+            text_node(AccessibilityRole::Heading { level }, &heading.contents)
+        }
+        ParsedMarkdownElement::ListItem(item) => list_nodes(&[item]).remove(0),
+        ParsedMarkdownElement::Table(table) => table_node(table),
+        ParsedMarkdownElement::BlockQuote(block_quote) => {
+            let mut node = AccessibilityNode::new(
+                AccessibilityRole::BlockQuote,
+                "",
+                block_quote.source_range.clone(),
+            );
+            node.children = block_nodes(&block_quote.children);
+            node
+        }
+        ParsedMarkdownElement::CodeBlock(code_block) => code_block_node(code_block),
+        ParsedMarkdownElement::Image(image) => image_node(image),
+        ParsedMarkdownElement::Paragraph(text) => text_node(AccessibilityRole::Paragraph, text),
+        ParsedMarkdownElement::HorizontalRule(range) => {
+            AccessibilityNode::new(AccessibilityRole::Separator, "", range.clone())
+        }
+    }
+}
+
+/// A node for a run of text, with a child for each of its links.
+fn text_node(role: AccessibilityRole, text: &ParsedMarkdownText) -> AccessibilityNode {
+    let mut node =
+        AccessibilityNode::new(role, text.contents.to_string(), text.source_range.clone());
+    for (range, region) in text.region_ranges.iter().zip(&text.regions) {
+        let Some(link) = &region.link else {
+            continue;
+        };
+        let label = text.contents.get(range.clone()).unwrap_or_default();
+        let mut link_node =
+            AccessibilityNode::new(AccessibilityRole::Link, label, text.source_range.clone());
+        link_node.value = Some(link.url());
+        node.children.push(link_node);
+    }
+    // The images of a row of badges are read out in it, inside the links
+    // they go to.
+    for image in &text.images {
+        match &image.target {
+            Some(target) => {
+                let mut link_node = AccessibilityNode::new(
+                    AccessibilityRole::Link,
+                    image.caption(),
+                    image.source_range.clone(),
+                );
+                link_node.value = Some(target.url());
+                link_node.children.push(image_node(image));
+                node.children.push(link_node);
+            }
+            None => node.children.push(image_node(image)),
+        }
+    }
+    node
+}
+
+fn image_node(image: &ParsedMarkdownImage) -> AccessibilityNode {
+    let mut node = AccessibilityNode::new(
+        AccessibilityRole::Image,
+        image.alt_text.clone(),
+        image.source_range.clone(),
+    );
+    node.value = image.link.as_ref().map(|link| link.url());
+    node
+}
+
+fn list_nodes(items: &[&ParsedMarkdownListItem]) -> Vec<AccessibilityNode> {
+    // The lists that are open, outermost first, with the depth of their
+    // items.
+    let mut open_lists: Vec<(u16, AccessibilityNode)> = Vec::new();
+    let mut lists = Vec::new();
+    for item in items {
+        let role = AccessibilityRole::List {
+            ordered: matches!(item.item_type, ParsedMarkdownListItemType::Ordered(_)),
+        };
+        while open_lists.last().map_or(false, |(depth, list)| {
+            *depth > item.depth || (*depth == item.depth && list.role != role)
+        }) {
+            close_list(&mut open_lists, &mut lists);
+        }
+        if open_lists
+            .last()
+            .map_or(true, |(depth, _)| *depth < item.depth)
+        {
+            let list = AccessibilityNode::new(role, "", item.source_range.clone());
+            open_lists.push((item.depth, list));
+        }
+
+        let mut node =
+            AccessibilityNode::new(AccessibilityRole::ListItem, "", item.source_range.clone());
+        if let ParsedMarkdownListItemType::Task(checked, range) = &item.item_type {
+            node.children.push(AccessibilityNode::new(
+                AccessibilityRole::Checkbox { checked: *checked },
+                if *checked { "Done" } else { "Not done" },
+                range.clone(),
+            ));
+        }
+        node.children.extend(block_nodes(&item.content));
+        node.label = node
+            .children
+            .iter()
+            .find(|child| child.role == AccessibilityRole::Paragraph)
+            .map(|child| child.label.clone())
+            .unwrap_or_default();
+
+        let (_, list) = open_lists.last_mut().unwrap();
+        list.source_range.end = item.source_range.end;
+        list.children.push(node);
+    }
+    while !open_lists.is_empty() {
+        close_list(&mut open_lists, &mut lists);
+    }
+    lists
+}
+
+/// Closes the innermost open list, nesting it in the last item of the list
+/// around it.
+fn close_list(open_lists: &mut Vec<(u16, AccessibilityNode)>, lists: &mut Vec<AccessibilityNode>) {
+    let (_, list) = open_lists.pop().unwrap();
+    match open_lists
+        .last_mut()
+        .and_then(|(_, parent)| parent.children.last_mut())
+    {
+        Some(parent_item) => parent_item.children.push(list),
+        None => lists.push(list),
+    }
+}
+
+fn table_node(table: &ParsedMarkdownTable) -> AccessibilityNode {
+    let mut node = AccessibilityNode::new(AccessibilityRole::Table, "", table.source_range.clone());
+    for row in std::iter::once(&table.header).chain(&table.body) {
+        let mut row_node =
+            AccessibilityNode::new(AccessibilityRole::Row, "", table.source_range.clone());
+        row_node.children = row
+            .children
+            .iter()
+            .map(|cell| text_node(AccessibilityRole::Cell, cell))
+            .collect();
+        node.children.push(row_node);
+    }
+    node.children.push(AccessibilityNode::new(
+        AccessibilityRole::Button,
+        "Copy as TSV",
+        table.source_range.clone(),
+    ));
+    node
+}
+
+fn code_block_node(code_block: &ParsedMarkdownCodeBlock) -> AccessibilityNode {
+    let label = match code_block.language.as_deref().filter(|l| !l.is_empty()) {
+        Some(language) => format!("{} code", language),
+        None => "Code".to_string(),
+    };
+    let mut node = AccessibilityNode::new(
+        AccessibilityRole::CodeBlock,
+        label,
+        code_block.source_range.clone(),
+    );
+    node.value = Some(code_block.contents.to_string());
+    node.children.push(AccessibilityNode::new(
+        AccessibilityRole::Button,
+        "Copy Code",
+        code_block.source_range.clone(),
+    ));
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use pretty_assertions::assert_eq;
+
+    const EXAMPLE: &str = include_str!("../../assets/markdown/example.md");
+
+    #[gpui::test]
+    async fn test_example_document_tree() {
+        let parsed = parse_markdown(EXAMPLE, None, None).await;
+        let tree = accessibility_tree(&parsed);
+        let nodes = tree.descendants();
+
+        let headings: Vec<_> = nodes
+            .iter()
+            .filter_map(|node| match node.role {
+                AccessibilityRole::Heading { level } => Some((level, node.label.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(headings.first(), Some(&(1, "Markdown Example Document")));
+        assert!(headings.contains(&(2, "Links")));
+        assert_eq!(headings.len(), parsed.outline().len());
+
+        let link = nodes
+            .iter()
+            .find(|node| node.role == AccessibilityRole::Link)
+            .unwrap();
+        assert_eq!(link.label, "http://zed.dev");
+        assert_eq!(link.value.as_deref(), Some("https://zed.dev"));
+
+        for role in [
+            AccessibilityRole::Image,
+            AccessibilityRole::Table,
+            AccessibilityRole::CodeBlock,
+            AccessibilityRole::BlockQuote,
+        ] {
+            assert!(nodes.iter().any(|node| node.role == role), "no {:?}", role);
+        }
+    }
+
+    #[gpui::test]
+    async fn test_lists_and_focus_order() {
+        let parsed = parse_markdown(
+            "- [x] [one](https://one.dev)\n    1. nested\n- two\n\n```rust\nfn main() {}\n```\n",
+            None,
+            None,
+        )
+        .await;
+        let tree = accessibility_tree(&parsed);
+
+        let list = &tree.children[0];
+        assert_eq!(list.role, AccessibilityRole::List { ordered: false });
+        assert_eq!(list.children.len(), 2);
+        let nested = list.children[0].children.last().unwrap();
+        assert_eq!(nested.role, AccessibilityRole::List { ordered: true });
+        assert_eq!(nested.children[0].label, "nested");
+        assert_eq!(tree.children[1].label, "rust code");
+
+        let focus_order: Vec<_> = tree
+            .focus_order()
+            .into_iter()
+            .map(|node| (node.role, node.label.as_str()))
+            .collect();
+        assert_eq!(
+            focus_order,
+            vec![
+                (AccessibilityRole::Checkbox { checked: true }, "Done"),
+                (AccessibilityRole::Link, "one"),
+                (AccessibilityRole::Button, "Copy Code"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,909 @@
+use super::{
+    markdown_elements::{ParsedMarkdown, ParsedMarkdownElement, ParsedMarkdownText},
+    markdown_parser::parse_markdown,
+    markdown_renderer::{render_markdown_block, PresentationState, RenderContext},
+    markdown_search::{SearchHighlights, SearchMatch},
+    NextChange, PreviousChange,
+};
+use anyhow::{Context as _, Result};
+use collections::{FxHashMap, FxHashSet};
+use gpui::{
+    div, list, prelude::*, px, AnyElement, AppContext, FocusHandle, FocusableView, Hsla,
+    ListAlignment, ListOffset, ListState, SharedString, Task, ViewContext, WindowContext,
+};
+use std::{
+    cell::RefCell,
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use theme::ActiveTheme;
+use ui::{
+    h_flex, v_flex, Clickable, Color, IconButton, IconName, IconSize, Label, LabelCommon,
+    LabelSize, Tooltip,
+};
+
+/// Runs of unchanged blocks longer than this are collapsed behind a
+/// separator.
+pub const MAX_UNCHANGED_BLOCKS: usize = 3;
+/// How many pairs of blocks, or words, are aligned at most. Past this, what
+/// changed between matching starts and ends is shown as removed and added
+/// wholesale.
+const MAX_ALIGNMENT_CELLS: usize = 1 << 22;
+
+/// How a top-level block of one document became one of the other, by their
+/// indices into [`ParsedMarkdown::children`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockChange {
+    Unchanged {
+        old_ix: usize,
+        new_ix: usize,
+    },
+    Removed {
+        old_ix: usize,
+    },
+    Added {
+        new_ix: usize,
+    },
+    /// A paragraph or heading whose words changed.
+    Modified {
+        old_ix: usize,
+        new_ix: usize,
+    },
+}
+
+impl BlockChange {
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self, Self::Unchanged { .. })
+    }
+}
+
+/// Aligns the top-level blocks of `old` with those of `new`, the documents
+/// parsed from `old_text` and `new_text`, keeping as many blocks in order as
+/// it can. Blocks are the same when their source is, as in
+/// [`ParsedMarkdown::block_hashes`], and a removed paragraph or heading
+/// followed by an added one is modified instead.
+pub fn diff_blocks(
+    old: &ParsedMarkdown,
+    old_text: &str,
+    new: &ParsedMarkdown,
+    new_text: &str,
+) -> Vec<BlockChange> {
+    let old_hashes = old.block_hashes(old_text);
+    let new_hashes = new.block_hashes(new_text);
+    let matches = align(&old_hashes, &new_hashes);
+
+    let mut changes = Vec::new();
+    let (mut old_ix, mut new_ix) = (0, 0);
+    for (matched_old_ix, matched_new_ix) in matches
+        .into_iter()
+        .chain([(old_hashes.len(), new_hashes.len())])
+    {
+        push_hunk(
+            old_ix..matched_old_ix,
+            new_ix..matched_new_ix,
+            old,
+            new,
+            &mut changes,
+        );
+        if matched_old_ix < old_hashes.len() {
+            changes.push(BlockChange::Unchanged {
+                old_ix: matched_old_ix,
+                new_ix: matched_new_ix,
+            });
+        }
+        old_ix = matched_old_ix + 1;
+        new_ix = matched_new_ix + 1;
+    }
+    changes
+}
+
+/// Pushes the changes between two matching blocks: the `removed` blocks of
+/// `old` and the `added` ones of `new`, paired up where they're both text.
+fn push_hunk(
+    removed: Range<usize>,
+    added: Range<usize>,
+    old: &ParsedMarkdown,
+    new: &ParsedMarkdown,
+    changes: &mut Vec<BlockChange>,
+) {
+    let mut removed = removed.peekable();
+    let mut added = added.peekable();
+    loop {
+        match (removed.peek().copied(), added.peek().copied()) {
+            (Some(old_ix), Some(new_ix))
+                if is_modification(&old.children[old_ix], &new.children[new_ix]) =>
+            {
+                changes.push(BlockChange::Modified { old_ix, new_ix });
+                removed.next();
+                added.next();
+            }
+            (None, None) => break,
+            (old_ix, new_ix) => {
+                if let Some(old_ix) = old_ix {
+                    changes.push(BlockChange::Removed { old_ix });
+                    removed.next();
+                }
+                if let Some(new_ix) = new_ix {
+                    changes.push(BlockChange::Added { new_ix });
+                    added.next();
+                }
+            }
+        }
+    }
+}
+
+fn is_modification(old: &ParsedMarkdownElement, new: &ParsedMarkdownElement) -> bool {
+    block_text(old).is_some()
+        && block_text(new).is_some()
+        && mem::discriminant(old) == mem::discriminant(new)
+}
+
+/// The text of a paragraph or heading, whose words are compared.
+fn block_text(block: &ParsedMarkdownElement) -> Option<&ParsedMarkdownText> {
+    match block {
+        ParsedMarkdownElement::Paragraph(text) => Some(text),
+        ParsedMarkdownElement::Heading(heading) => Some(&heading.contents),
+        _ => None,
+    }
+}
+
+/// The words of `old` that were removed and those of `new` that were added,
+/// as byte ranges. Neighboring words are merged into one range, along with
+/// the space between them.
+pub fn word_changes(old: &str, new: &str) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let old_words = words(old);
+    let new_words = words(new);
+    let matches = align(
+        &old_words
+            .iter()
+            .map(|range| &old[range.clone()])
+            .collect::<Vec<_>>(),
+        &new_words
+            .iter()
+            .map(|range| &new[range.clone()])
+            .collect::<Vec<_>>(),
+    );
+
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let (mut old_ix, mut new_ix) = (0, 0);
+    for (matched_old_ix, matched_new_ix) in matches
+        .into_iter()
+        .chain([(old_words.len(), new_words.len())])
+    {
+        if old_ix < matched_old_ix {
+            removed.push(old_words[old_ix].start..old_words[matched_old_ix - 1].end);
+        }
+        if new_ix < matched_new_ix {
+            added.push(new_words[new_ix].start..new_words[matched_new_ix - 1].end);
+        }
+        old_ix = matched_old_ix + 1;
+        new_ix = matched_new_ix + 1;
+    }
+    (removed, added)
+}
+
+/// The ranges of the runs of `text` between whitespace.
+fn words(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (ix, ch) in text.char_indices() {
+        match (ch.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                words.push(word_start..ix);
+                start = None;
+            }
+            (false, None) => start = Some(ix),
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push(word_start..text.len());
+    }
+    words
+}
+
+/// The indices of the longest run of items `old` and `new` have in common,
+/// in order. The items they start and end with are matched first, and only
+/// what's between them is aligned, unless that's too much to.
+fn align<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let prefix_len = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix_len = old[prefix_len..]
+        .iter()
+        .rev()
+        .zip(new[prefix_len..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_changed = &old[prefix_len..old.len() - suffix_len];
+    let new_changed = &new[prefix_len..new.len() - suffix_len];
+
+    let mut matches: Vec<(usize, usize)> = (0..prefix_len).map(|ix| (ix, ix)).collect();
+    let (n, m) = (old_changed.len(), new_changed.len());
+    if n > 0 && m > 0 && n.saturating_mul(m) <= MAX_ALIGNMENT_CELLS {
+        // `lengths[i][j]` is how many items `old_changed[i..]` and
+        // `new_changed[j..]` have in common.
+        let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+        let cell = |i: usize, j: usize| i * (m + 1) + j;
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[cell(i, j)] = if old_changed[i] == new_changed[j] {
+                    lengths[cell(i + 1, j + 1)] + 1
+                } else {
+                    lengths[cell(i + 1, j)].max(lengths[cell(i, j + 1)])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if old_changed[i] == new_changed[j] {
+                matches.push((prefix_len + i, prefix_len + j));
+                i += 1;
+                j += 1;
+            } else if lengths[cell(i + 1, j)] >= lengths[cell(i, j + 1)] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+    matches.extend(
+        (0..suffix_len).map(|ix| (old.len() - suffix_len + ix, new.len() - suffix_len + ix)),
+    );
+    matches
+}
+
+/// One of the documents being compared.
+#[derive(Debug, Clone)]
+pub struct DiffDocument {
+    /// What the document is called in the header, like its file name.
+    pub title: SharedString,
+    pub text: String,
+    /// Relative links and images resolve against it.
+    pub base_directory: Option<PathBuf>,
+}
+
+impl DiffDocument {
+    pub fn new(title: impl Into<SharedString>, text: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            text: text.into(),
+            base_directory: None,
+        }
+    }
+
+    /// Reads the document at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let title = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        Ok(Self {
+            title: title.into(),
+            text,
+            base_directory: path.parent().map(Path::to_path_buf),
+        })
+    }
+}
+
+/// Both documents, parsed, and how one became the other.
+struct Comparison {
+    old: ParsedMarkdown,
+    new: ParsedMarkdown,
+    changes: Vec<BlockChange>,
+    /// The words that changed in each modified block, by its index into
+    /// `changes`, as matches in the old block's text and the new one's.
+    word_changes: FxHashMap<usize, (Vec<SearchMatch>, Vec<SearchMatch>)>,
+}
+
+impl Comparison {
+    async fn new(old: &DiffDocument, new: &DiffDocument) -> Self {
+        let old_parsed = parse_markdown(&old.text, old.base_directory.clone(), None).await;
+        let new_parsed = parse_markdown(&new.text, new.base_directory.clone(), None).await;
+        let changes = diff_blocks(&old_parsed, &old.text, &new_parsed, &new.text);
+
+        let mut word_highlights = FxHashMap::default();
+        for (change_ix, change) in changes.iter().enumerate() {
+            let BlockChange::Modified { old_ix, new_ix } = *change else {
+                continue;
+            };
+            let (Some(old_text), Some(new_text)) = (
+                block_text(&old_parsed.children[old_ix]),
+                block_text(&new_parsed.children[new_ix]),
+            ) else {
+                continue;
+            };
+            let (removed, added) = word_changes(&old_text.contents, &new_text.contents);
+            let as_matches = |block_ix, text: &ParsedMarkdownText, ranges: Vec<Range<usize>>| {
+                ranges
+                    .into_iter()
+                    .map(|range| SearchMatch {
+                        block_ix,
+                        text_start: text.source_range.start,
+                        range,
+                    })
+                    .collect::<Vec<_>>()
+            };
+            word_highlights.insert(
+                change_ix,
+                (
+                    as_matches(old_ix, old_text, removed),
+                    as_matches(new_ix, new_text, added),
+                ),
+            );
+        }
+
+        Self {
+            old: old_parsed,
+            new: new_parsed,
+            changes,
+            word_changes: word_highlights,
+        }
+    }
+}
+
+/// A row of a [`MarkdownDiffView`].
+#[derive(Debug, Clone, PartialEq)]
+enum DiffRow {
+    /// A block, by its index into the changes.
+    Block(usize),
+    /// A run of unchanged blocks, by their indices into the changes, that's
+    /// collapsed behind a separator until it's clicked.
+    Collapsed(Range<usize>),
+}
+
+/// Shows how one Markdown document became another, as one document with
+/// the removed blocks tinted red, the added ones green, and the words that
+/// changed in modified paragraphs highlighted. Long runs of unchanged
+/// blocks are collapsed, and [`NextChange`] and [`PreviousChange`] jump
+/// between the changes.
+pub struct MarkdownDiffView {
+    old: DiffDocument,
+    new: DiffDocument,
+    comparison: Option<Comparison>,
+    rows: Vec<DiffRow>,
+    /// The runs of unchanged blocks that were expanded, by the index of
+    /// their first block into the changes.
+    expanded_runs: FxHashSet<usize>,
+    word_highlights: FxHashMap<usize, (Rc<SearchHighlights>, Rc<SearchHighlights>)>,
+    list_state: ListState,
+    /// The documents' presentation state is kept apart, as their source
+    /// ranges are into different texts.
+    old_presentation_state: Rc<RefCell<PresentationState>>,
+    new_presentation_state: Rc<RefCell<PresentationState>>,
+    load_error: Option<SharedString>,
+    focus_handle: FocusHandle,
+    comparing_task: Option<Task<()>>,
+}
+
+impl MarkdownDiffView {
+    /// Compares `old` with `new`.
+    pub fn new(old: DiffDocument, new: DiffDocument, cx: &mut ViewContext<Self>) -> Self {
+        let view = cx.view().downgrade();
+        let list_state = ListState::new(0, ListAlignment::Top, px(1000.), move |ix, cx| {
+            if let Some(view) = view.upgrade() {
+                view.update(cx, |this, cx| this.render_row(ix, cx))
+            } else {
+                div().into_any()
+            }
+        });
+        let mut this = Self {
+            old,
+            new,
+            comparison: None,
+            rows: Vec::new(),
+            expanded_runs: FxHashSet::default(),
+            word_highlights: FxHashMap::default(),
+            list_state,
+            old_presentation_state: Rc::default(),
+            new_presentation_state: Rc::default(),
+            load_error: None,
+            focus_handle: cx.focus_handle(),
+            comparing_task: None,
+        };
+        this.compare(cx);
+        this
+    }
+
+    /// Compares the files at `old_path` and `new_path`, once they're read.
+    pub fn compare_files(old_path: PathBuf, new_path: PathBuf, cx: &mut ViewContext<Self>) -> Self {
+        let mut this = Self::new(DiffDocument::new("", ""), DiffDocument::new("", ""), cx);
+        let load = cx.background_executor().spawn(async move {
+            Ok::<_, anyhow::Error>((
+                DiffDocument::load(&old_path)?,
+                DiffDocument::load(&new_path)?,
+            ))
+        });
+        this.comparing_task = Some(cx.spawn(|this, mut cx| async move {
+            let loaded = load.await;
+            this.update(&mut cx, |this, cx| match loaded {
+                Ok((old, new)) => {
+                    this.old = old;
+                    this.new = new;
+                    this.compare(cx);
+                }
+                Err(error) => {
+                    log::error!("{:#}", error);
+                    this.load_error = Some(format!("{:#}", error).into());
+                    cx.notify();
+                }
+            })
+            .ok();
+        }));
+        this
+    }
+
+    /// Replaces the newer document with `text`, like as it's edited, and
+    /// compares them again.
+    pub fn set_new_text(&mut self, text: String, cx: &mut ViewContext<Self>) {
+        if self.new.text != text {
+            self.new.text = text;
+            self.compare(cx);
+        }
+    }
+
+    /// How many blocks were added, removed and modified.
+    pub fn change_counts(&self) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for change in self.comparison.iter().flat_map(|c| &c.changes) {
+            match change {
+                BlockChange::Added { .. } => counts.0 += 1,
+                BlockChange::Removed { .. } => counts.1 += 1,
+                BlockChange::Modified { .. } => counts.2 += 1,
+                BlockChange::Unchanged { .. } => {}
+            }
+        }
+        counts
+    }
+
+    fn compare(&mut self, cx: &mut ViewContext<Self>) {
+        let old = self.old.clone();
+        let new = self.new.clone();
+        let comparison = cx
+            .background_executor()
+            .spawn(async move { Comparison::new(&old, &new).await });
+        self.comparing_task = Some(cx.spawn(|this, mut cx| async move {
+            let comparison = comparison.await;
+            this.update(&mut cx, |this, cx| this.show_comparison(comparison, cx))
+                .ok();
+        }));
+    }
+
+    fn show_comparison(&mut self, comparison: Comparison, cx: &mut ViewContext<Self>) {
+        self.word_highlights = comparison
+            .word_changes
+            .iter()
+            .map(|(change_ix, (removed, added))| {
+                (
+                    *change_ix,
+                    (
+                        Rc::new(SearchHighlights::new(removed, None)),
+                        Rc::new(SearchHighlights::new(added, None)),
+                    ),
+                )
+            })
+            .collect();
+        // Comparing again keeps the runs that were expanded only when the
+        // blocks are the same as before.
+        if self
+            .comparison
+            .as_ref()
+            .map_or(true, |previous| previous.changes != comparison.changes)
+        {
+            self.expanded_runs.clear();
+        }
+        self.comparison = Some(comparison);
+        self.old_presentation_state = Rc::default();
+        self.new_presentation_state = Rc::default();
+
+        // Re-comparing as the newer document is edited shouldn't lose the
+        // reader's place.
+        let scroll_top = self.list_state.logical_scroll_top();
+        self.rows = self.rows();
+        self.list_state.reset(self.rows.len());
+        self.list_state.scroll_to(ListOffset {
+            item_ix: scroll_top.item_ix.min(self.rows.len().saturating_sub(1)),
+            offset_in_item: scroll_top.offset_in_item,
+        });
+        cx.notify();
+    }
+
+    /// The rows of the comparison, with long runs of unchanged blocks that
+    /// weren't expanded collapsed.
+    fn rows(&self) -> Vec<DiffRow> {
+        let Some(comparison) = &self.comparison else {
+            return Vec::new();
+        };
+        let mut rows = Vec::new();
+        let mut change_ix = 0;
+        while change_ix < comparison.changes.len() {
+            let run_len = comparison.changes[change_ix..]
+                .iter()
+                .take_while(|change| change.is_unchanged())
+                .count();
+            if run_len > MAX_UNCHANGED_BLOCKS && !self.expanded_runs.contains(&change_ix) {
+                rows.push(DiffRow::Collapsed(change_ix..change_ix + run_len));
+                change_ix += run_len;
+            } else {
+                let run_len = run_len.max(1);
+                rows.extend((change_ix..change_ix + run_len).map(DiffRow::Block));
+                change_ix += run_len;
+            }
+        }
+        rows
+    }
+
+    /// The rows that start a run of changes.
+    fn hunk_starts(&self) -> Vec<usize> {
+        let Some(comparison) = &self.comparison else {
+            return Vec::new();
+        };
+        let is_change = |row: &DiffRow| matches!(row, DiffRow::Block(change_ix) if !comparison.changes[*change_ix].is_unchanged());
+        (0..self.rows.len())
+            .filter(|&ix| is_change(&self.rows[ix]) && (ix == 0 || !is_change(&self.rows[ix - 1])))
+            .collect()
+    }
+
+    fn next_change(&mut self, _: &NextChange, cx: &mut ViewContext<Self>) {
+        let top = self.list_state.logical_scroll_top().item_ix;
+        if let Some(row_ix) = self.hunk_starts().into_iter().find(|&ix| ix > top) {
+            self.scroll_to_row(row_ix, cx);
+        }
+    }
+
+    fn previous_change(&mut self, _: &PreviousChange, cx: &mut ViewContext<Self>) {
+        let top = self.list_state.logical_scroll_top().item_ix;
+        if let Some(row_ix) = self.hunk_starts().into_iter().rev().find(|&ix| ix < top) {
+            self.scroll_to_row(row_ix, cx);
+        }
+    }
+
+    fn scroll_to_row(&mut self, row_ix: usize, cx: &mut ViewContext<Self>) {
+        self.list_state.scroll_to(ListOffset {
+            item_ix: row_ix,
+            offset_in_item: px(0.),
+        });
+        cx.notify();
+    }
+
+    fn expand_run(&mut self, row_ix: usize, cx: &mut ViewContext<Self>) {
+        let Some(DiffRow::Collapsed(run)) = self.rows.get(row_ix).cloned() else {
+            return;
+        };
+        self.expanded_runs.insert(run.start);
+        self.rows
+            .splice(row_ix..row_ix + 1, run.clone().map(DiffRow::Block));
+        self.list_state.splice(row_ix..row_ix + 1, run.len());
+        cx.notify();
+    }
+
+    fn render_row(&mut self, ix: usize, cx: &mut ViewContext<Self>) -> AnyElement {
+        let (Some(comparison), Some(row)) = (&self.comparison, self.rows.get(ix)) else {
+            return div().into_any();
+        };
+        let change_ix = match row {
+            DiffRow::Block(change_ix) => *change_ix,
+            DiffRow::Collapsed(run) => {
+                let label = match run.len() {
+                    1 => "1 unchanged block".to_string(),
+                    count => format!("{count} unchanged blocks"),
+                };
+                let colors = cx.theme().colors();
+                let (background, hover_background) =
+                    (colors.surface_background, colors.element_hover);
+                return h_flex()
+                    .id(("markdown-diff-collapsed", ix))
+                    .w_full()
+                    .my_2()
+                    .py_1()
+                    .justify_center()
+                    .rounded_md()
+                    .bg(background)
+                    .hover(|this| this.bg(hover_background))
+                    .cursor_pointer()
+                    .child(Label::new(label).size(LabelSize::Small).color(Color::Muted))
+                    .on_click(cx.listener(move |this, _, cx| this.expand_run(ix, cx)))
+                    .into_any();
+            }
+        };
+
+        let status = cx.theme().status().clone();
+        let old_block = |old_ix: usize| &comparison.old.children[old_ix];
+        let new_block = |new_ix: usize| &comparison.new.children[new_ix];
+        let words = self.word_highlights.get(&change_ix);
+        match comparison.changes[change_ix] {
+            BlockChange::Unchanged { new_ix, .. } => render_block(
+                new_block(new_ix),
+                &self.new_presentation_state,
+                None,
+                None,
+                cx,
+            ),
+            BlockChange::Removed { old_ix } => render_block(
+                old_block(old_ix),
+                &self.old_presentation_state,
+                Some((status.deleted, status.deleted_background)),
+                None,
+                cx,
+            ),
+            BlockChange::Added { new_ix } => render_block(
+                new_block(new_ix),
+                &self.new_presentation_state,
+                Some((status.created, status.created_background)),
+                None,
+                cx,
+            ),
+            // The old text is shown above the new, each with its own words
+            // highlighted.
+            BlockChange::Modified { old_ix, new_ix } => v_flex()
+                .child(render_block(
+                    old_block(old_ix),
+                    &self.old_presentation_state,
+                    Some((status.deleted, status.deleted_background)),
+                    words.map(|(removed, _)| (removed.clone(), status.deleted.opacity(0.3))),
+                    cx,
+                ))
+                .child(render_block(
+                    new_block(new_ix),
+                    &self.new_presentation_state,
+                    Some((status.created, status.created_background)),
+                    words.map(|(_, added)| (added.clone(), status.created.opacity(0.3))),
+                    cx,
+                ))
+                .into_any(),
+        }
+    }
+
+    fn render_header(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let (added, removed, modified) = self.change_counts();
+        let summary = if self.comparison.is_none() {
+            "Comparing…".to_string()
+        } else if added + removed + modified == 0 {
+            "No changes".to_string()
+        } else {
+            format!("{added} added, {removed} removed, {modified} modified")
+        };
+        h_flex()
+            .gap_2()
+            .px_2()
+            .py_1()
+            .border_b_1()
+            .border_color(cx.theme().colors().border)
+            .child(
+                div().flex_1().min_w_0().child(
+                    Label::new(format!("{} → {}", self.old.title, self.new.title))
+                        .size(LabelSize::Small),
+                ),
+            )
+            .child(
+                Label::new(summary)
+                    .size(LabelSize::Small)
+                    .color(Color::Muted),
+            )
+            .child(
+                IconButton::new("markdown-diff-previous", IconName::ArrowUp)
+                    .icon_size(IconSize::Small)
+                    .on_click(cx.listener(|this, _, cx| this.previous_change(&PreviousChange, cx)))
+                    .tooltip(|cx| Tooltip::for_action("Previous Change", &PreviousChange, cx)),
+            )
+            .child(
+                IconButton::new("markdown-diff-next", IconName::ArrowDown)
+                    .icon_size(IconSize::Small)
+                    .on_click(cx.listener(|this, _, cx| this.next_change(&NextChange, cx)))
+                    .tooltip(|cx| Tooltip::for_action("Next Change", &NextChange, cx)),
+            )
+    }
+}
+
+/// Renders `block`, with a bar of `colors.0` beside it on a `colors.1`
+/// background if it changed, and `words` highlighted in their color.
+fn render_block(
+    block: &ParsedMarkdownElement,
+    presentation_state: &Rc<RefCell<PresentationState>>,
+    colors: Option<(Hsla, Hsla)>,
+    words: Option<(Rc<SearchHighlights>, Hsla)>,
+    cx: &WindowContext,
+) -> AnyElement {
+    let mut render_context =
+        RenderContext::new(cx).with_presentation_state(presentation_state.clone());
+    if let Some((highlights, color)) = words {
+        render_context = render_context
+            .with_search_highlights(Some(highlights))
+            .with_search_match_color(color);
+    }
+    div()
+        .px_2()
+        .pt_1()
+        .border_l_2()
+        .border_color(gpui::transparent_black())
+        .when_some(colors, |this, (bar, background)| {
+            this.border_color(bar).bg(background)
+        })
+        .child(render_markdown_block(block, &mut render_context))
+        .into_any()
+}
+
+impl FocusableView for MarkdownDiffView {
+    fn focus_handle(&self, _: &AppContext) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for MarkdownDiffView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let body = if let Some(error) = self.load_error.clone() {
+            div()
+                .p_4()
+                .child(Label::new(error).color(Color::Error))
+                .into_any_element()
+        } else if self
+            .comparison
+            .as_ref()
+            .is_some_and(|comparison| comparison.changes.iter().all(BlockChange::is_unchanged))
+        {
+            div()
+                .p_4()
+                .child(Label::new("The documents are the same.").color(Color::Muted))
+                .into_any_element()
+        } else {
+            list(self.list_state.clone())
+                .size_full()
+                .px_4()
+                .into_any_element()
+        };
+
+        v_flex()
+            .key_context("MarkdownDiff")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::next_change))
+            .on_action(cx.listener(Self::previous_change))
+            .size_full()
+            .bg(cx.theme().colors().editor_background)
+            .child(self.render_header(cx))
+            .child(div().flex_1().min_h_0().child(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gpui::{TestAppContext, VisualTestContext};
+    use pretty_assertions::assert_eq;
+    use settings::SettingsStore;
+    use theme::LoadThemes;
+
+    #[gpui::test]
+    async fn test_diff_blocks() {
+        let old_text = "# Title\n\nOne two three.\n\nKeep.\n\n```\ncode\n```\n";
+        let new_text = "# Title\n\nOne 2 three.\n\nKeep.\n\n---\n";
+        let old = parse_markdown(old_text, None, None).await;
+        let new = parse_markdown(new_text, None, None).await;
+        assert_eq!(
+            diff_blocks(&old, old_text, &new, new_text),
+            vec![
+                BlockChange::Unchanged {
+                    old_ix: 0,
+                    new_ix: 0
+                },
+                BlockChange::Modified {
+                    old_ix: 1,
+                    new_ix: 1
+                },
+                BlockChange::Unchanged {
+                    old_ix: 2,
+                    new_ix: 2
+                },
+                BlockChange::Removed { old_ix: 3 },
+                BlockChange::Added { new_ix: 3 },
+            ]
+        );
+
+        // Blocks that moved past others are removed and added again.
+        let old_text = "A.\n\nB.\n\nC.\n";
+        let new_text = "C.\n\nA.\n\nB.\n";
+        let old = parse_markdown(old_text, None, None).await;
+        let new = parse_markdown(new_text, None, None).await;
+        assert_eq!(
+            diff_blocks(&old, old_text, &new, new_text),
+            vec![
+                BlockChange::Added { new_ix: 0 },
+                BlockChange::Unchanged {
+                    old_ix: 0,
+                    new_ix: 1
+                },
+                BlockChange::Unchanged {
+                    old_ix: 1,
+                    new_ix: 2
+                },
+                BlockChange::Removed { old_ix: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_word_changes() {
+        assert_eq!(
+            word_changes("One two three.", "One 2 three."),
+            (vec![4..7], vec![4..5])
+        );
+        assert_eq!(word_changes("a b c", "a x  y c"), (vec![2..3], vec![2..6]));
+        assert_eq!(word_changes("same", "same"), (vec![], vec![]));
+    }
+
+    #[gpui::test]
+    async fn test_collapsing_and_navigation(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+
+        let paragraphs = |changed: &[usize]| {
+            (0..10)
+                .map(|ix| {
+                    if changed.contains(&ix) {
+                        format!("Paragraph {ix}, changed.")
+                    } else {
+                        format!("Paragraph {ix}.")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        };
+        let (view, cx) = cx.add_window_view(|cx| {
+            MarkdownDiffView::new(
+                DiffDocument::new("old.md", paragraphs(&[])),
+                DiffDocument::new("new.md", paragraphs(&[1, 8])),
+                cx,
+            )
+        });
+        cx.run_until_parked();
+
+        let top_row = |view: &gpui::View<MarkdownDiffView>, cx: &mut VisualTestContext| {
+            view.update(cx, |view, _| view.list_state.logical_scroll_top().item_ix)
+        };
+        view.update(cx, |view, _| {
+            assert_eq!(view.change_counts(), (0, 0, 2));
+            assert_eq!(
+                view.rows,
+                vec![
+                    DiffRow::Block(0),
+                    DiffRow::Block(1),
+                    DiffRow::Collapsed(2..8),
+                    DiffRow::Block(8),
+                    DiffRow::Block(9),
+                ]
+            );
+        });
+
+        view.update(cx, |view, cx| view.next_change(&NextChange, cx));
+        assert_eq!(top_row(&view, cx), 1);
+        view.update(cx, |view, cx| view.next_change(&NextChange, cx));
+        assert_eq!(top_row(&view, cx), 3);
+        view.update(cx, |view, cx| view.previous_change(&PreviousChange, cx));
+        assert_eq!(top_row(&view, cx), 1);
+
+        view.update(cx, |view, cx| {
+            view.expand_run(2, cx);
+            assert_eq!(view.rows.len(), 10);
+            assert_eq!(view.hunk_starts(), vec![1, 8]);
+        });
+
+        // Editing the newer document compares it again.
+        view.update(cx, |view, cx| view.set_new_text(paragraphs(&[]), cx));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.change_counts(), (0, 0, 0));
+            assert_eq!(view.hunk_starts(), Vec::<usize>::new());
+        });
+    }
+}
@@ -0,0 +1,456 @@
+use super::{
+    markdown_diff::{DiffDocument, MarkdownDiffView},
+    markdown_elements::HeadingLevel,
+    markdown_parser::parse_markdown,
+    markdown_theme::apply_system_appearance,
+    markdown_view::{window_title, MarkdownEvent, MarkdownView},
+    CompareWithSaved, FormatDocument, Save,
+};
+use editor::{Editor, EditorEvent};
+use gpui::{
+    div, point, prelude::*, AppContext, FocusHandle, FocusableView, Subscription, View, ViewContext,
+};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use theme::ActiveTheme;
+use ui::h_flex;
+
+/// How long typing has to pause before the preview catches up.
+const PREVIEW_PARSE_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// A plain-text editor for a document's Markdown, with a live preview of it
+/// to its right that scrolls along with the cursor.
+pub struct MarkdownEditor {
+    editor: View<Editor>,
+    preview: View<MarkdownView>,
+    /// The file the document is saved to.
+    path: Option<PathBuf>,
+    /// The text as of when it was last read or saved, to tell whether it
+    /// has been edited since.
+    saved_text: String,
+    edited: bool,
+    /// The title last given to the window, and whether it was marked as
+    /// edited.
+    window_title: Option<(String, bool)>,
+    /// Whether scrolling the preview scrolls the editor to the same place.
+    sync_editor_scroll: bool,
+    /// Shown in place of the preview while the text is compared with the
+    /// file, by [`CompareWithSaved`].
+    diff: Option<View<MarkdownDiffView>>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl MarkdownEditor {
+    /// Edits `text`, which was read from `path` if it's given.
+    pub fn new(text: String, path: Option<PathBuf>, cx: &mut ViewContext<Self>) -> Self {
+        let editor = cx.new_view(|cx| {
+            let mut editor = Editor::multi_line(cx);
+            editor.set_text(text.clone(), cx);
+            editor
+        });
+
+        let base_directory = path
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf);
+        let preview = cx.new_view(|cx| {
+            let mut preview = MarkdownView::new(text.clone(), base_directory, cx);
+            preview.set_parse_debounce(PREVIEW_PARSE_DEBOUNCE);
+            preview
+        });
+
+        // The window title includes the document's first heading, which
+        // changes as the preview parses the edits.
+        let subscriptions = vec![
+            cx.subscribe(&editor, Self::handle_editor_event),
+            cx.subscribe(&preview, Self::handle_preview_event),
+            cx.observe(&preview, |this, _, cx| this.update_window_title(cx)),
+            cx.observe_window_appearance(|_, cx| apply_system_appearance(cx)),
+        ];
+        Self {
+            editor,
+            preview,
+            path,
+            saved_text: text,
+            edited: false,
+            window_title: None,
+            sync_editor_scroll: true,
+            diff: None,
+            _subscriptions: subscriptions,
+        }
+    }
+
+    pub fn editor(&self) -> &View<Editor> {
+        &self.editor
+    }
+
+    pub fn preview(&self) -> &View<MarkdownView> {
+        &self.preview
+    }
+
+    /// Sets whether scrolling the preview scrolls the editor too. The
+    /// preview always follows the editor's cursor.
+    pub fn set_sync_editor_scroll(&mut self, sync_editor_scroll: bool) {
+        self.sync_editor_scroll = sync_editor_scroll;
+    }
+
+    /// The comparison of the text with the file, while it's shown.
+    pub fn diff(&self) -> Option<&View<MarkdownDiffView>> {
+        self.diff.as_ref()
+    }
+
+    /// Whether the text has been edited since it was last saved.
+    pub fn is_edited(&self) -> bool {
+        self.edited
+    }
+
+    fn update_window_title(&mut self, cx: &mut ViewContext<Self>) {
+        let title = {
+            let preview = self.preview.read(cx);
+            let heading = preview
+                .outline()
+                .iter()
+                .find(|entry| entry.level == HeadingLevel::H1)
+                .map(|entry| entry.text.as_ref());
+            window_title(self.path.as_deref(), heading, self.edited)
+        };
+        let title = Some((title, self.edited));
+        if self.window_title != title {
+            if let Some((title, edited)) = &title {
+                cx.set_window_title(title);
+                cx.set_window_edited(*edited);
+            }
+            self.window_title = title;
+        }
+    }
+
+    fn handle_editor_event(
+        &mut self,
+        editor: View<Editor>,
+        event: &EditorEvent,
+        cx: &mut ViewContext<Self>,
+    ) {
+        match event {
+            EditorEvent::Edited { .. } => {
+                let text = editor.read(cx).text(cx);
+                self.edited = text != self.saved_text;
+                self.update_window_title(cx);
+                if let Some(diff) = &self.diff {
+                    diff.update(cx, |diff, cx| diff.set_new_text(text.clone(), cx));
+                }
+                self.preview
+                    .update(cx, |preview, cx| preview.set_text(text, cx));
+            }
+            EditorEvent::SelectionsChanged { local: true } => {
+                let cursor = editor.read(cx).selections.newest::<usize>(cx).head();
+                self.preview
+                    .update(cx, |preview, cx| preview.reveal_source_offset(cursor, cx));
+            }
+            _ => {}
+        }
+    }
+
+    /// Makes the spelling corrections picked in the preview in the editor,
+    /// unless the word was edited since.
+    fn handle_preview_event(
+        &mut self,
+        _: View<MarkdownView>,
+        event: &MarkdownEvent,
+        cx: &mut ViewContext<Self>,
+    ) {
+        if let MarkdownEvent::SpellingCorrected {
+            word,
+            replacement,
+            source_range,
+        } = event
+        {
+            self.editor.update(cx, |editor, cx| {
+                let text = editor.text(cx);
+                if text.get(source_range.clone()) == Some(word.as_ref()) {
+                    editor.edit([(source_range.clone(), replacement.to_string())], cx);
+                }
+            });
+        }
+    }
+
+    /// Scrolls the editor so that the block at the top of the preview starts
+    /// on its first line.
+    fn sync_editor_to_preview(&mut self, cx: &mut ViewContext<Self>) {
+        if !self.sync_editor_scroll {
+            return;
+        }
+        let Some(offset) = self.preview.read(cx).top_source_offset() else {
+            return;
+        };
+
+        self.editor.update(cx, |editor, cx| {
+            let snapshot = editor.buffer().read(cx).snapshot(cx);
+            // The preview lags behind the editor while it parses, so its
+            // offsets may be past the end of the text.
+            let row = snapshot.offset_to_point(offset.min(snapshot.len())).row;
+            editor.set_scroll_position(point(0., row as f32), cx);
+        });
+    }
+
+    /// Writes the text back to the file it was read from, asking where to
+    /// save it if there is none.
+    fn save(&mut self, _: &Save, cx: &mut ViewContext<Self>) {
+        let text = self.editor.read(cx).text(cx);
+        let path = self.path.clone();
+        cx.spawn(|this, mut cx| async move {
+            let path = match path {
+                Some(path) => path,
+                None => {
+                    let directory = std::env::current_dir()?;
+                    let prompt =
+                        this.update(&mut cx, |_, cx| cx.prompt_for_new_path(&directory))?;
+                    let Some(path) = prompt.await?? else {
+                        return Ok(());
+                    };
+                    path
+                }
+            };
+
+            let written = cx
+                .background_executor()
+                .spawn({
+                    let path = path.clone();
+                    let text = text.clone();
+                    async move { std::fs::write(path, text) }
+                })
+                .await;
+            this.update(&mut cx, |this, cx| {
+                let message = match written {
+                    Ok(()) => {
+                        this.path = Some(path);
+                        this.edited = this.editor.read(cx).text(cx) != text;
+                        this.saved_text = text;
+                        this.update_window_title(cx);
+                        "Saved".to_string()
+                    }
+                    Err(error) => {
+                        log::error!("failed to save {}: {}", path.display(), error);
+                        format!("Could not save {}", path.display())
+                    }
+                };
+                this.preview
+                    .update(cx, |preview, cx| preview.show_toast(message, cx));
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Shows how the text differs from the file in place of the preview,
+    /// updating as it's edited, or goes back to the preview. Documents that
+    /// were never saved are compared with the text they started with.
+    fn compare_with_saved(&mut self, _: &CompareWithSaved, cx: &mut ViewContext<Self>) {
+        if self.diff.take().is_some() {
+            cx.notify();
+            return;
+        }
+
+        let text = self.editor.read(cx).text(cx);
+        let path = self.path.clone();
+        let saved_text = self.saved_text.clone();
+        cx.spawn(|this, mut cx| async move {
+            let saved = cx
+                .background_executor()
+                .spawn(async move {
+                    match &path {
+                        Some(path) => DiffDocument::load(path),
+                        None => Ok(DiffDocument::new("Saved", saved_text)),
+                    }
+                })
+                .await?;
+            this.update(&mut cx, |this, cx| {
+                let edited = DiffDocument {
+                    title: "Edited".into(),
+                    text,
+                    base_directory: saved.base_directory.clone(),
+                };
+                let diff = cx.new_view(|cx| MarkdownDiffView::new(saved, edited, cx));
+                // The text may have been edited while the file was read.
+                let text = this.editor.read(cx).text(cx);
+                diff.update(cx, |diff, cx| diff.set_new_text(text, cx));
+                this.diff = Some(diff);
+                cx.notify();
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Rewrites the text in one consistent style, with
+    /// [`ParsedMarkdown::to_markdown`](super::markdown_elements::ParsedMarkdown::to_markdown).
+    /// The edit can be undone like any other.
+    fn format_document(&mut self, _: &FormatDocument, cx: &mut ViewContext<Self>) {
+        let text = self.editor.read(cx).text(cx);
+        cx.spawn(|this, mut cx| async move {
+            let formatted = cx
+                .background_executor()
+                .spawn({
+                    let text = text.clone();
+                    async move {
+                        let parsed = parse_markdown(&text, None, None).await;
+                        parsed.to_markdown(&text)
+                    }
+                })
+                .await;
+            this.update(&mut cx, |this, cx| {
+                // Edits made while the text was formatted would be lost.
+                if formatted == text || this.editor.read(cx).text(cx) != text {
+                    return;
+                }
+                this.editor
+                    .update(cx, |editor, cx| editor.set_text(formatted, cx));
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+}
+
+impl FocusableView for MarkdownEditor {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.editor.focus_handle(cx)
+    }
+}
+
+impl Render for MarkdownEditor {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        h_flex()
+            .key_context("MarkdownEditor")
+            .on_action(cx.listener(Self::save))
+            .on_action(cx.listener(Self::format_document))
+            .on_action(cx.listener(Self::compare_with_saved))
+            .size_full()
+            .bg(cx.theme().colors().editor_background)
+            .child(
+                div()
+                    .flex_1()
+                    .min_w_0()
+                    .h_full()
+                    .p_2()
+                    .border_r_1()
+                    .border_color(cx.theme().colors().border)
+                    .child(self.editor.clone()),
+            )
+            .child(
+                div()
+                    .flex_1()
+                    .min_w_0()
+                    .h_full()
+                    // The list has scrolled by the time the event bubbles up
+                    // to here.
+                    .on_scroll_wheel(cx.listener(|this, _, cx| this.sync_editor_to_preview(cx)))
+                    .map(|this| match &self.diff {
+                        Some(diff) => this.child(diff.clone()),
+                        None => this.child(self.preview.clone()),
+                    }),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+    use settings::SettingsStore;
+    use theme::LoadThemes;
+
+    #[gpui::test]
+    async fn test_editing_and_saving(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+
+        let directory =
+            std::env::temp_dir().join(format!("markdown-editor-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("notes.md");
+        std::fs::write(&path, "# Notes\n").unwrap();
+
+        let (view, cx) = cx.add_window_view(|cx| {
+            MarkdownEditor::new("# Notes\n".to_string(), Some(path.clone()), cx)
+        });
+        cx.run_until_parked();
+        let preview = view.update(cx, |view, _| view.preview().clone());
+        assert_eq!(preview.update(cx, |preview, _| preview.title()), "Notes");
+
+        let editor = view.update(cx, |view, _| view.editor().clone());
+        editor.update(cx, |editor, cx| editor.set_text("# Plans\n", cx));
+        cx.executor().advance_clock(PREVIEW_PARSE_DEBOUNCE);
+        cx.run_until_parked();
+        assert_eq!(preview.update(cx, |preview, _| preview.title()), "Plans");
+        assert!(view.update(cx, |view, _| view.is_edited()));
+        assert_eq!(
+            view.update(cx, |view, _| view.window_title.clone()),
+            Some((window_title(Some(&path), Some("Plans"), true), true))
+        );
+
+        view.update(cx, |view, cx| view.save(&Save, cx));
+        cx.run_until_parked();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "# Plans\n");
+        assert!(!view.update(cx, |view, _| view.is_edited()));
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_format_document(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+
+        let (view, cx) = cx.add_window_view(|cx| {
+            MarkdownEditor::new("Notes\n=====\n\n* one\n* two\n".to_string(), None, cx)
+        });
+        view.update(cx, |view, cx| view.format_document(&FormatDocument, cx));
+        cx.run_until_parked();
+        let editor = view.update(cx, |view, _| view.editor().clone());
+        assert_eq!(
+            editor.update(cx, |editor, cx| editor.text(cx)),
+            "# Notes\n\n- one\n- two\n"
+        );
+        assert!(view.update(cx, |view, _| view.is_edited()));
+    }
+
+    #[gpui::test]
+    async fn test_compare_with_saved(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+
+        let (view, cx) =
+            cx.add_window_view(|cx| MarkdownEditor::new("# Notes\n\nOne.\n".to_string(), None, cx));
+        view.update(cx, |view, cx| {
+            view.compare_with_saved(&CompareWithSaved, cx)
+        });
+        cx.run_until_parked();
+        let diff = view.update(cx, |view, _| view.diff().cloned().unwrap());
+        assert_eq!(diff.update(cx, |diff, _| diff.change_counts()), (0, 0, 0));
+
+        let editor = view.update(cx, |view, _| view.editor().clone());
+        editor.update(cx, |editor, cx| {
+            editor.set_text("# Notes\n\nOne.\n\nTwo.\n", cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(diff.update(cx, |diff, _| diff.change_counts()), (1, 0, 0));
+
+        view.update(cx, |view, cx| {
+            view.compare_with_saved(&CompareWithSaved, cx)
+        });
+        assert!(view.update(cx, |view, _| view.diff().is_none()));
+    }
+}
@@ -1,10 +1,19 @@
+use super::markdown_front_matter::FrontMatter;
 use gpui::{
     px, FontStyle, FontWeight, HighlightStyle, SharedString, StrikethroughStyle, UnderlineStyle,
 };
 use language::HighlightId;
-use std::{fmt::Display, ops::Range, path::PathBuf};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::{Deref, Range},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use url::Url;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub enum ParsedMarkdownElement {
     Heading(ParsedMarkdownHeading),
@@ -35,25 +44,803 @@ impl ParsedMarkdownElement {
     pub fn is_list_item(&self) -> bool {
         matches!(self, Self::ListItem(_))
     }
+
+    /// How many words the block has, as it's shown.
+    pub fn word_count(&self) -> usize {
+        let mut lines = Vec::new();
+        push_block_text(self, &(0..usize::MAX), &mut lines);
+        lines.iter().map(|line| word_count(line)).sum()
+    }
+
+    /// Moves every source range in this element by `delta` bytes, for
+    /// elements parsed out of a slice of a larger document.
+    pub fn offset_source_ranges(&mut self, delta: isize) {
+        match self {
+            Self::Heading(heading) => {
+                offset_range(&mut heading.source_range, delta);
+                offset_range(&mut heading.contents.source_range, delta);
+            }
+            Self::ListItem(list_item) => {
+                offset_range(&mut list_item.source_range, delta);
+                if let ParsedMarkdownListItemType::Task(_, range) = &mut list_item.item_type {
+                    offset_range(range, delta);
+                }
+                for child in &mut list_item.content {
+                    child.offset_source_ranges(delta);
+                }
+            }
+            Self::Table(table) => {
+                offset_range(&mut table.source_range, delta);
+                for row in std::iter::once(&mut table.header).chain(&mut table.body) {
+                    for cell in &mut row.children {
+                        offset_range(&mut cell.source_range, delta);
+                    }
+                }
+            }
+            Self::BlockQuote(block_quote) => {
+                offset_range(&mut block_quote.source_range, delta);
+                for child in &mut block_quote.children {
+                    child.offset_source_ranges(delta);
+                }
+            }
+            Self::CodeBlock(code_block) => offset_range(&mut code_block.source_range, delta),
+            Self::Image(image) => offset_range(&mut image.source_range, delta),
+            Self::Paragraph(text) => {
+                offset_range(&mut text.source_range, delta);
+                for image in &mut text.images {
+                    offset_range(&mut image.source_range, delta);
+                }
+            }
+            Self::HorizontalRule(range) => offset_range(range, delta),
+        }
+    }
+
+    /// The runs of text in this element, including the ones in list items,
+    /// block quotes and table cells.
+    pub fn texts(&self) -> Vec<&ParsedMarkdownText> {
+        match self {
+            Self::Heading(heading) => vec![&heading.contents],
+            Self::ListItem(list_item) => list_item.content.iter().flat_map(Self::texts).collect(),
+            Self::Table(table) => std::iter::once(&table.header)
+                .chain(&table.body)
+                .flat_map(|row| &row.children)
+                .collect(),
+            Self::BlockQuote(block_quote) => {
+                block_quote.children.iter().flat_map(Self::texts).collect()
+            }
+            Self::Paragraph(text) => vec![text],
+            Self::CodeBlock(_) | Self::Image(_) | Self::HorizontalRule(_) => Vec::new(),
+        }
+    }
+
+    /// The code blocks in this element, including ones in list items and
+    /// block quotes.
+    pub fn code_blocks_mut(&mut self) -> Vec<&mut ParsedMarkdownCodeBlock> {
+        match self {
+            Self::CodeBlock(code_block) => vec![code_block],
+            Self::ListItem(list_item) => list_item
+                .content
+                .iter_mut()
+                .flat_map(Self::code_blocks_mut)
+                .collect(),
+            Self::BlockQuote(block_quote) => block_quote
+                .children
+                .iter_mut()
+                .flat_map(Self::code_blocks_mut)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn offset_range(range: &mut Range<usize>, delta: isize) {
+    range.start = range.start.saturating_add_signed(delta);
+    range.end = range.end.saturating_add_signed(delta);
+}
+
+/// A heading in [`ParsedMarkdown::outline`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineEntry {
+    /// The index of the heading among the document's blocks.
+    pub block_ix: usize,
+    pub level: HeadingLevel,
+    /// How many entries this one is nested under.
+    pub depth: usize,
+    pub text: SharedString,
+    pub slug: String,
+}
+
+/// The section number of each entry of `outline`, like `2.1` for the first
+/// entry nested under the second top-level one.
+pub fn section_numbers(outline: &[OutlineEntry]) -> Vec<String> {
+    let mut counters: Vec<usize> = Vec::new();
+    outline
+        .iter()
+        .map(|entry| {
+            counters.truncate(entry.depth + 1);
+            counters.resize(entry.depth + 1, 0);
+            counters[entry.depth] += 1;
+            counters
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+        .collect()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdown {
     pub children: Vec<ParsedMarkdownElement>,
+    /// The metadata at the top of the document, which isn't one of its
+    /// blocks.
+    pub front_matter: Option<FrontMatter>,
+    /// The runs of blocks that `!include` directives brought in from other
+    /// files, in order.
+    pub includes: Vec<IncludedBlocks>,
+    /// The places where the source was parsed in a way its author may not
+    /// have meant, in the order they're in the source.
+    pub diagnostics: Vec<ParseDiagnostic>,
+}
+
+/// Something in the source that's parsed correctly, but maybe not the way
+/// it looks like it should be, like a list item indented differently from
+/// the items next to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseDiagnostic {
+    pub source_range: Range<usize>,
+    pub message: String,
+}
+
+impl ParseDiagnostic {
+    /// Moves the diagnostic by `delta` bytes, like
+    /// [`ParsedMarkdownElement::offset_source_ranges`].
+    pub fn offset_source_range(&mut self, delta: isize) {
+        offset_range(&mut self.source_range, delta);
+    }
 }
 
-#[derive(Debug)]
+/// Blocks of a [`ParsedMarkdown`] that were read from another file. Their
+/// source ranges are into that file rather than the document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncludedBlocks {
+    pub path: PathBuf,
+    /// Where the directive that included the file is, in the file that has
+    /// it.
+    pub directive_range: Range<usize>,
+    /// The blocks, as indices into [`ParsedMarkdown::children`].
+    pub blocks: Range<usize>,
+}
+
+impl ParsedMarkdown {
+    /// Whether the block at `block_ix` was read from another file.
+    pub fn is_included(&self, block_ix: usize) -> bool {
+        self.includes
+            .iter()
+            .any(|include| include.blocks.contains(&block_ix))
+    }
+
+    /// Hashes each block, to tell which blocks two parses of a document have
+    /// in common. Blocks are hashed by their source in `text`, the text they
+    /// were parsed from, and included blocks by what they show.
+    pub fn block_hashes(&self, text: &str) -> Vec<u64> {
+        self.children
+            .iter()
+            .enumerate()
+            .map(|(block_ix, block)| {
+                let mut hasher = DefaultHasher::new();
+                match text
+                    .get(block.source_range())
+                    .filter(|_| !self.is_included(block_ix))
+                {
+                    Some(source) => source.hash(&mut hasher),
+                    None => {
+                        let mut lines = Vec::new();
+                        push_block_text(block, &(0..usize::MAX), &mut lines);
+                        lines.hash(&mut hasher);
+                    }
+                }
+                hasher.finish()
+            })
+            .collect()
+    }
+
+    /// Returns the index one past the last block of the section started by
+    /// the heading at `heading_ix`, which runs until the next heading of the
+    /// same or a shallower level.
+    pub fn section_end(&self, heading_ix: usize) -> usize {
+        let Some(ParsedMarkdownElement::Heading(heading)) = self.children.get(heading_ix) else {
+            return heading_ix + 1;
+        };
+
+        self.children[heading_ix + 1..]
+            .iter()
+            .position(|block| {
+                matches!(block, ParsedMarkdownElement::Heading(next) if next.level <= heading.level)
+            })
+            .map_or(self.children.len(), |offset| heading_ix + 1 + offset)
+    }
+
+    /// The Markdown source of the section started by the heading at
+    /// `heading_ix`, sliced from `text`, the source the document was parsed
+    /// from: the heading and everything up to the next heading of the same
+    /// or a shallower level, subsections included.
+    pub fn section_source(&self, text: &str, heading_ix: usize) -> Option<String> {
+        let ParsedMarkdownElement::Heading(heading) = self.children.get(heading_ix)? else {
+            return None;
+        };
+        // Included blocks have source ranges into other files.
+        if self.is_included(heading_ix) {
+            return None;
+        }
+
+        let end = (self.section_end(heading_ix)..self.children.len())
+            .find(|ix| !self.is_included(*ix))
+            .map_or(text.len(), |ix| self.children[ix].source_range().start);
+        let section = text.get(heading.source_range.start..end)?;
+        Some(format!("{}\n", section.trim_end()))
+    }
+
+    /// Lists the document's headings as a table of contents. Each entry is
+    /// nested under the closest heading before it with a shallower level, so
+    /// skipped levels don't indent entries further.
+    pub fn outline(&self) -> Vec<OutlineEntry> {
+        let mut entries = Vec::new();
+        let mut open_levels: Vec<HeadingLevel> = Vec::new();
+        for (block_ix, block) in self.children.iter().enumerate() {
+            let ParsedMarkdownElement::Heading(heading) = block else {
+                continue;
+            };
+
+            while open_levels
+                .last()
+                .map_or(false, |level| *level >= heading.level)
+            {
+                open_levels.pop();
+            }
+            entries.push(OutlineEntry {
+                block_ix,
+                level: heading.level,
+                depth: open_levels.len(),
+                text: heading.contents.contents.to_shared_string(),
+                slug: heading.slug.clone(),
+            });
+            open_levels.push(heading.level);
+        }
+        entries
+    }
+
+    /// The links in the document's text, in order. A link with some of its
+    /// text styled, which is split into several regions, is one link here.
+    pub fn links(&self) -> Vec<DocumentLink> {
+        let mut links: Vec<DocumentLink> = Vec::new();
+        for text in self.children.iter().flat_map(ParsedMarkdownElement::texts) {
+            let mut previous_end = None;
+            for (range, region) in text.region_ranges.iter().zip(&text.regions) {
+                let Some(link) = &region.link else {
+                    previous_end = None;
+                    continue;
+                };
+                let link_text = text.contents.get(range.clone()).unwrap_or_default();
+                match links.last_mut() {
+                    Some(last) if previous_end == Some(range.start) && last.link == *link => {
+                        last.text.push_str(link_text);
+                    }
+                    _ => links.push(DocumentLink {
+                        link: link.clone(),
+                        origin: region.origin,
+                        text: link_text.to_string(),
+                        source_range: text.source_range.clone(),
+                    }),
+                }
+                previous_end = Some(range.end);
+            }
+            for image in &text.images {
+                if let Some(target) = &image.target {
+                    links.push(DocumentLink {
+                        link: target.clone(),
+                        origin: LinkOrigin::Explicit,
+                        text: image.alt_text.clone(),
+                        source_range: image.source_range.clone(),
+                    });
+                }
+            }
+        }
+        links
+    }
+
+    /// Describes the element tree as indented plain text, one element per
+    /// line, for snapshot tests. The output only depends on the document, so
+    /// it is the same across runs and platforms.
+    pub fn to_debug_tree(&self) -> String {
+        let mut tree = String::new();
+        for child in &self.children {
+            write_debug_tree(child, 0, &mut tree);
+        }
+        tree
+    }
+
+    /// Flattens the document to plain text, like for copying it somewhere
+    /// that doesn't take Markdown or for indexing it. See
+    /// [`ParsedMarkdown::to_plain_text_with`].
+    pub fn to_plain_text(&self) -> String {
+        self.to_plain_text_with(&PlainTextOptions::default())
+    }
+
+    /// Flattens the document to plain text, with blocks separated by blank
+    /// lines and lines only broken where the document breaks them:
+    ///
+    /// - headings and paragraphs are their text,
+    /// - list items start with `- `, `- [x] ` or their number, and are
+    ///   indented by how deeply they're nested,
+    /// - table rows are their cells separated by tabs,
+    /// - code blocks are their code, indented by four spaces,
+    /// - block quotes have `> ` in front of each of their lines,
+    /// - images are their alt text.
+    pub fn to_plain_text_with(&self, options: &PlainTextOptions) -> String {
+        let mut text = plain_text_blocks(&self.children, options);
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Returns the Markdown source of `selection`, a range of `text`, which
+    /// this document was parsed from.
+    ///
+    /// Selections are copied as written, except that code blocks only keep
+    /// their fences when they are selected in full.
+    pub fn markdown_source(&self, text: &str, selection: Range<usize>) -> String {
+        let start = selection.start.min(text.len());
+        let end = selection.end.min(text.len()).max(start);
+
+        let mut source = String::new();
+        let mut ix = start;
+        for block in &self.children {
+            let ParsedMarkdownElement::CodeBlock(code_block) = block else {
+                continue;
+            };
+            let range = code_block.source_range.clone();
+            let whole = start <= range.start && range.end <= end;
+            if whole || range.end <= start || end <= range.start {
+                continue;
+            }
+
+            let body = fenced_code_body(text, range.clone());
+            for fence in [range.start..body.start, body.end..range.end] {
+                if fence.end <= ix || fence.start >= end {
+                    continue;
+                }
+                source.push_str(&text[ix..fence.start.max(ix)]);
+                ix = fence.end.min(end);
+            }
+        }
+        source.push_str(&text[ix..end.max(ix)]);
+        source
+    }
+
+    /// Returns the text of the blocks that `selection`, a range of the
+    /// source, touches, as they're shown rather than as they're written: one
+    /// block to a line, with table cells separated by tabs.
+    pub fn selected_text(&self, selection: Range<usize>) -> String {
+        let mut lines = Vec::new();
+        for block in &self.children {
+            push_block_text(block, &selection, &mut lines);
+        }
+        lines.join("\n")
+    }
+
+    /// Returns the indices of the headings whose sections contain the block
+    /// at `block_ix`, outermost first.
+    pub fn enclosing_headings(&self, block_ix: usize) -> Vec<usize> {
+        let mut headings: Vec<usize> = Vec::new();
+        for (ix, block) in self.children.iter().enumerate().take(block_ix + 1) {
+            if let ParsedMarkdownElement::Heading(heading) = block {
+                headings.retain(|&open_ix| match &self.children[open_ix] {
+                    ParsedMarkdownElement::Heading(open) => open.level < heading.level,
+                    _ => false,
+                });
+                if ix < block_ix {
+                    headings.push(ix);
+                }
+            }
+        }
+        headings
+    }
+
+    /// Returns the index of the block at `offset` in the source. Source that
+    /// isn't shown as a block, like front matter or link reference
+    /// definitions, belongs to the block before it, or to the first block if
+    /// there is none.
+    pub fn block_at_offset(&self, offset: usize) -> Option<usize> {
+        if self.children.is_empty() {
+            return None;
+        }
+        let following = self
+            .children
+            .partition_point(|block| block.source_range().start <= offset);
+        Some(following.saturating_sub(1))
+    }
+}
+
+/// Adds the text of `block` to `lines`, if `block` overlaps `selection`. A
+/// list item or block quote adds the lines of the blocks in it.
+fn push_block_text(
+    block: &ParsedMarkdownElement,
+    selection: &Range<usize>,
+    lines: &mut Vec<String>,
+) {
+    let range = block.source_range();
+    if range.end <= selection.start || selection.end <= range.start {
+        return;
+    }
+    match block {
+        ParsedMarkdownElement::Heading(heading) => {
+            lines.push(heading.contents.contents.to_string())
+        }
+        ParsedMarkdownElement::Paragraph(text) if !text.images.is_empty() => {
+            lines.push(alt_texts(&text.images))
+        }
+        ParsedMarkdownElement::Paragraph(text) => lines.push(text.contents.to_string()),
+        ParsedMarkdownElement::ListItem(item) => {
+            for child in &item.content {
+                push_block_text(child, selection, lines);
+            }
+        }
+        ParsedMarkdownElement::BlockQuote(quote) => {
+            for child in &quote.children {
+                push_block_text(child, selection, lines);
+            }
+        }
+        ParsedMarkdownElement::Table(table) => lines.push(table.to_tsv().trim_end().to_string()),
+        ParsedMarkdownElement::CodeBlock(code_block) => lines.push(code_block.contents.to_string()),
+        ParsedMarkdownElement::Image(image) => lines.push(image.alt_text.clone()),
+        ParsedMarkdownElement::HorizontalRule(_) => {}
+    }
+}
+
+/// The alt texts of a row of images, separated by spaces.
+fn alt_texts(images: &[ParsedMarkdownImage]) -> String {
+    images
+        .iter()
+        .map(|image| image.alt_text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// How many words `text` has, counting runs of characters between spaces
+/// that have a letter or digit in them, so that punctuation and Markdown
+/// symbols on their own aren't words.
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// How many minutes reading `word_count` words takes, at 200 words a minute,
+/// rounded up.
+pub fn reading_minutes(word_count: usize) -> usize {
+    word_count.div_ceil(200)
+}
+
+/// The part of the code block at `range` in `text` between its fences, or
+/// all of it for indented code blocks.
+fn fenced_code_body(text: &str, range: Range<usize>) -> Range<usize> {
+    let source = &text[range.clone()];
+    let trimmed = source.trim_start();
+    if !trimmed.starts_with("```") && !trimmed.starts_with("~~~") {
+        return range;
+    }
+
+    // Fences that are never closed run to the end of the document.
+    let fence = &trimmed[..3];
+    let start = source.find('\n').map_or(source.len(), |ix| ix + 1);
+    let body = source[start..].trim_end_matches('\n');
+    let last_line_start = body.rfind('\n').map_or(0, |ix| ix + 1);
+    let end = if body[last_line_start..].trim_start().starts_with(fence) {
+        start + last_line_start
+    } else {
+        source.len()
+    };
+    range.start + start..range.start + end
+}
+
+fn write_debug_tree(element: &ParsedMarkdownElement, depth: usize, tree: &mut String) {
+    let indent = "  ".repeat(depth);
+    let range = element.source_range();
+    match element {
+        ParsedMarkdownElement::Heading(heading) => {
+            tree.push_str(&format!(
+                "{indent}heading {:?} #{} {:?}\n",
+                heading.level, heading.slug, range
+            ));
+            write_debug_text(&heading.contents, depth + 1, tree);
+        }
+        ParsedMarkdownElement::ListItem(item) => {
+            let item_type = match &item.item_type {
+                ParsedMarkdownListItemType::Ordered(order) => format!("ordered {}", order),
+                ParsedMarkdownListItemType::Task(checked, _) => format!("task checked={}", checked),
+                ParsedMarkdownListItemType::Unordered => "unordered".to_string(),
+            };
+            tree.push_str(&format!(
+                "{indent}list-item {} depth={} {:?}\n",
+                item_type, item.depth, range
+            ));
+            for child in &item.content {
+                write_debug_tree(child, depth + 1, tree);
+            }
+        }
+        ParsedMarkdownElement::Table(table) => {
+            tree.push_str(&format!(
+                "{indent}table {}x{} {:?}\n",
+                table.column_alignments.len(),
+                table.body.len(),
+                range
+            ));
+            for row in std::iter::once(&table.header).chain(&table.body) {
+                tree.push_str(&format!("{indent}  row\n"));
+                for cell in &row.children {
+                    write_debug_text(cell, depth + 2, tree);
+                }
+            }
+        }
+        ParsedMarkdownElement::BlockQuote(block_quote) => {
+            tree.push_str(&format!("{indent}block-quote {:?}\n", range));
+            for child in &block_quote.children {
+                write_debug_tree(child, depth + 1, tree);
+            }
+        }
+        ParsedMarkdownElement::CodeBlock(code_block) => {
+            tree.push_str(&format!(
+                "{indent}code-block {:?} lines={} {:?}\n",
+                code_block.language.as_deref().unwrap_or(""),
+                code_block.contents.lines().count(),
+                range
+            ));
+            for lines in &code_block.emphasized_lines {
+                tree.push_str(&format!("{indent}  emphasized {:?}\n", lines));
+            }
+        }
+        ParsedMarkdownElement::Image(image) => {
+            let link = image.link.as_ref().map(ToString::to_string);
+            tree.push_str(&format!(
+                "{indent}image {:?} -> {:?} {:?}\n",
+                image.alt_text, link, range
+            ));
+        }
+        ParsedMarkdownElement::Paragraph(text) => {
+            tree.push_str(&format!("{indent}paragraph\n"));
+            write_debug_text(text, depth + 1, tree);
+            for image in &text.images {
+                let link = image.link.as_ref().map(ToString::to_string);
+                let target = image.target.as_ref().map(ToString::to_string);
+                tree.push_str(&format!(
+                    "{indent}  image {:?} -> {:?} target={:?} {:?}\n",
+                    image.alt_text, link, target, image.source_range
+                ));
+            }
+        }
+        ParsedMarkdownElement::HorizontalRule(_) => {
+            tree.push_str(&format!("{indent}rule {:?}\n", range));
+        }
+    }
+}
+
+fn write_debug_text(text: &ParsedMarkdownText, depth: usize, tree: &mut String) {
+    let indent = "  ".repeat(depth);
+    tree.push_str(&format!(
+        "{indent}text {:?} {:?}\n",
+        text.contents, text.source_range
+    ));
+
+    for (range, highlight) in &text.highlights {
+        let MarkdownHighlight::Style(style) = highlight else {
+            // Syntax highlight ids depend on the theme, so they're left out.
+            continue;
+        };
+
+        let mut flags = Vec::new();
+        if style.italic {
+            flags.push("italic".to_string());
+        }
+        if style.underline {
+            flags.push("underline".to_string());
+        }
+        if style.strikethrough {
+            flags.push("strikethrough".to_string());
+        }
+        if style.weight != FontWeight::default() {
+            flags.push(format!("weight={}", style.weight.0));
+        }
+        if style.highlight {
+            flags.push("highlight".to_string());
+        }
+        tree.push_str(&format!(
+            "{indent}  style {:?} {}\n",
+            range,
+            flags.join(" ")
+        ));
+    }
+
+    for (range, region) in text.region_ranges.iter().zip(&text.regions) {
+        if region.code {
+            tree.push_str(&format!("{indent}  code {:?}\n", range));
+        }
+        if let Some(link) = &region.link {
+            tree.push_str(&format!("{indent}  link {:?} -> {}\n", range, link));
+        }
+    }
+}
+
+/// What [`ParsedMarkdown::to_plain_text_with`] keeps besides the text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlainTextOptions {
+    /// Writes where each link goes in angle brackets after its text, unless
+    /// the text is the URL already.
+    pub link_urls: bool,
+}
+
+/// The plain text of `blocks`, separated by blank lines, except between the
+/// items of a list.
+fn plain_text_blocks(blocks: &[ParsedMarkdownElement], options: &PlainTextOptions) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&ParsedMarkdownElement> = None;
+    for block in blocks {
+        let block_text = plain_text_block(block, options);
+        if block_text.is_empty() {
+            continue;
+        }
+        if let Some(previous) = previous {
+            let in_list = previous.is_list_item() && block.is_list_item();
+            text.push_str(if in_list { "\n" } else { "\n\n" });
+        }
+        text.push_str(&block_text);
+        previous = Some(block);
+    }
+    text
+}
+
+fn plain_text_block(block: &ParsedMarkdownElement, options: &PlainTextOptions) -> String {
+    match block {
+        ParsedMarkdownElement::Heading(heading) => plain_text(&heading.contents, options),
+        ParsedMarkdownElement::Paragraph(text) if !text.images.is_empty() => {
+            alt_texts(&text.images)
+        }
+        ParsedMarkdownElement::Paragraph(text) => plain_text(text, options),
+        ParsedMarkdownElement::ListItem(item) => {
+            let indent = "  ".repeat(usize::from(item.depth.saturating_sub(1)));
+            let marker = match &item.item_type {
+                ParsedMarkdownListItemType::Ordered(number) => format!("{number}. "),
+                ParsedMarkdownListItemType::Task(true, _) => "- [x] ".to_string(),
+                ParsedMarkdownListItemType::Task(false, _) => "- [ ] ".to_string(),
+                ParsedMarkdownListItemType::Unordered => "- ".to_string(),
+            };
+            // The lines after the first line up with the text after the
+            // marker.
+            let hanging_indent = " ".repeat(indent.len() + marker.len());
+            let content = plain_text_blocks(&item.content, options);
+            let mut lines = content.lines();
+            let mut text = format!("{indent}{marker}{}", lines.next().unwrap_or_default());
+            for line in lines {
+                text.push('\n');
+                if !line.is_empty() {
+                    text.push_str(&hanging_indent);
+                    text.push_str(line);
+                }
+            }
+            text
+        }
+        ParsedMarkdownElement::Table(table) => std::iter::once(&table.header)
+            .chain(&table.body)
+            .map(|row| {
+                row.children
+                    .iter()
+                    .map(|cell| plain_text(cell, options))
+                    .collect::<Vec<_>>()
+                    .join("\t")
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ParsedMarkdownElement::BlockQuote(quote) => {
+            prefix_lines(&plain_text_blocks(&quote.children, options), "> ", ">")
+        }
+        ParsedMarkdownElement::CodeBlock(code_block) => {
+            prefix_lines(code_block.contents.trim_end_matches('\n'), "    ", "")
+        }
+        ParsedMarkdownElement::Image(image) => image.alt_text.clone(),
+        ParsedMarkdownElement::HorizontalRule(_) => "---".to_string(),
+    }
+}
+
+/// `text` with `prefix` in front of each of its lines, or `empty_prefix`
+/// in front of the empty ones.
+pub(crate) fn prefix_lines(text: &str, prefix: &str, empty_prefix: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                empty_prefix.to_string()
+            } else {
+                format!("{prefix}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn plain_text(text: &ParsedMarkdownText, options: &PlainTextOptions) -> String {
+    if !options.link_urls {
+        return text.contents.to_string();
+    }
+
+    let mut plain_text = String::new();
+    let mut ix = 0;
+    let mut links = text
+        .region_ranges
+        .iter()
+        .zip(&text.regions)
+        .filter_map(|(range, region)| Some((range.clone(), region.link.as_ref()?)))
+        .peekable();
+    let mut link_start = None;
+    while let Some((range, link)) = links.next() {
+        // A link with some of its text styled is split into several regions.
+        let start = *link_start.get_or_insert(range.start);
+        if links
+            .peek()
+            .is_some_and(|(next, next_link)| next.start == range.end && *next_link == link)
+        {
+            continue;
+        }
+        link_start = None;
+
+        let url = link.url();
+        plain_text.push_str(&text.contents[ix..range.end]);
+        ix = range.end;
+        if text.contents.get(start..range.end) != Some(url.as_str()) {
+            plain_text.push_str(&format!(" <{url}>"));
+        }
+    }
+    plain_text.push_str(&text.contents[ix..]);
+    plain_text
+}
+
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownImage {
     /// Where the text is located in the source Markdown document.
     pub source_range: Range<usize>,
     /// The link of the image contained in the Markdown document.
     pub link: Option<Link>,
-    pub title: String
+    pub title: String,
+    /// The text describing the image, stripped of any formatting.
+    pub alt_text: String,
+    /// Where the image links to, when it's wrapped in a link, like most
+    /// badges are.
+    pub target: Option<Link>,
+}
+
+impl ParsedMarkdownImage {
+    /// The text shown in place of the image when it can't be: its alt text,
+    /// or its title without one, or the name of its file without either.
+    pub fn caption(&self) -> String {
+        if !self.alt_text.trim().is_empty() {
+            return self.alt_text.clone();
+        }
+        if !self.title.trim().is_empty() {
+            return self.title.clone();
+        }
+        let url = self.link.as_ref().map(Link::url).unwrap_or_default();
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        path.trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .unwrap_or("image")
+            .to_string()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownListItem {
     pub source_range: Range<usize>,
@@ -63,7 +850,7 @@ pub struct ParsedMarkdownListItem {
     pub content: Vec<ParsedMarkdownElement>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub enum ParsedMarkdownListItemType {
     Ordered(u64),
@@ -71,24 +858,34 @@ pub enum ParsedMarkdownListItemType {
     Unordered,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownCodeBlock {
     pub source_range: Range<usize>,
     pub language: Option<String>,
     pub contents: SharedString,
+    /// How far into `source_range` `contents` starts, past the opening
+    /// fence. Code blocks that have no text of their own in the source, like
+    /// ones in HTML, leave it at 0.
+    pub contents_offset: usize,
     pub highlights: Option<Vec<(Range<usize>, HighlightId)>>,
+    /// Zero-based line ranges to draw attention to, from fence attributes
+    /// like `{2-4,7}`.
+    pub emphasized_lines: Vec<Range<usize>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownHeading {
     pub source_range: Range<usize>,
     pub level: HeadingLevel,
     pub contents: ParsedMarkdownText,
+    /// An identifier derived from the heading text, unique within the
+    /// document, like the anchors GitHub generates for headings.
+    pub slug: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HeadingLevel {
     H1,
     H2,
@@ -98,7 +895,7 @@ pub enum HeadingLevel {
     H6,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParsedMarkdownTable {
     pub source_range: Range<usize>,
     pub header: ParsedMarkdownTableRow,
@@ -106,6 +903,70 @@ pub struct ParsedMarkdownTable {
     pub column_alignments: Vec<ParsedMarkdownTableAlignment>,
 }
 
+impl ParsedMarkdownTable {
+    /// Serializes the header and body cells as tab-separated values, one row
+    /// per line. Tabs, newlines and backslashes inside of cells are escaped.
+    pub fn to_tsv(&self) -> String {
+        let mut tsv = String::new();
+        for row in std::iter::once(&self.header).chain(&self.body) {
+            let cells: Vec<String> = row
+                .children
+                .iter()
+                .map(|cell| escape_tsv_cell(&cell.contents))
+                .collect();
+            tsv.push_str(&cells.join("\t"));
+            tsv.push('\n');
+        }
+        tsv
+    }
+
+    /// The order in which to show the body rows when sorting by `column`.
+    ///
+    /// Columns where every cell is a number are compared numerically, anything
+    /// else is compared as text. The sort is stable, so rows that compare equal
+    /// keep their document order.
+    pub fn sorted_row_indices(&self, column: usize, ascending: bool) -> Vec<usize> {
+        let cell_text = |row: &ParsedMarkdownTableRow| {
+            row.children
+                .get(column)
+                .map_or("", |cell| cell.contents.trim())
+        };
+        let numbers: Option<Vec<f64>> = self
+            .body
+            .iter()
+            .map(|row| cell_text(row).parse::<f64>().ok())
+            .collect();
+
+        let mut indices: Vec<usize> = (0..self.body.len()).collect();
+        indices.sort_by(|&a, &b| {
+            let ordering = match &numbers {
+                Some(numbers) => numbers[a].total_cmp(&numbers[b]),
+                None => cell_text(&self.body[a]).cmp(cell_text(&self.body[b])),
+            };
+            if ascending {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+        indices
+    }
+}
+
+fn escape_tsv_cell(contents: &str) -> String {
+    let mut escaped = String::with_capacity(contents.len());
+    for c in contents.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(test, derive(PartialEq))]
 pub enum ParsedMarkdownTableAlignment {
@@ -116,7 +977,7 @@ pub enum ParsedMarkdownTableAlignment {
     Right,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownTableRow {
     pub children: Vec<ParsedMarkdownText>,
@@ -140,25 +1001,149 @@ impl ParsedMarkdownTableRow {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct ParsedMarkdownBlockQuote {
     pub source_range: Range<usize>,
     pub children: Vec<ParsedMarkdownElement>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParsedMarkdownText {
     /// Where the text is located in the source Markdown document.
     pub source_range: Range<usize>,
     /// The text content stripped of any formatting symbols.
-    pub contents: String,
+    pub contents: SourceText,
     /// The list of highlights contained in the Markdown document.
     pub highlights: Vec<(Range<usize>, MarkdownHighlight)>,
     /// The regions of the various ranges in the Markdown document.
     pub region_ranges: Vec<Range<usize>>,
     /// The regions of the Markdown document.
     pub regions: Vec<ParsedRegion>,
+    /// The images of a paragraph that's made up of nothing but images, like
+    /// a row of badges, which are shown on one line instead of as blocks of
+    /// their own. Its contents are empty then.
+    pub images: Vec<ParsedMarkdownImage>,
+    /// Whether the paragraph continues the one before it, which the parser
+    /// split in two because it was too long.
+    pub continued: bool,
+}
+
+/// The text of a [`ParsedMarkdownText`]. Text that's written out in the
+/// source as it's shown, like most table cells and plain paragraphs, is a
+/// range of the source the document was parsed from, which all the blocks of
+/// the parse share. Anything else has a string of its own.
+#[derive(Clone)]
+pub enum SourceText {
+    Source {
+        source: Arc<str>,
+        range: Range<usize>,
+    },
+    Owned(SharedString),
+}
+
+impl SourceText {
+    /// The text at `range` of `source`, or `None` if `range` isn't within
+    /// `source` on character boundaries.
+    pub fn from_source(source: &Arc<str>, range: Range<usize>) -> Option<Self> {
+        source.get(range.clone())?;
+        Some(Self::Source {
+            source: source.clone(),
+            range,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Source { source, range } => &source[range.clone()],
+            Self::Owned(text) => text,
+        }
+    }
+
+    /// The text as a [`SharedString`], for the elements that draw it. Text
+    /// from the source is copied.
+    pub fn to_shared_string(&self) -> SharedString {
+        match self {
+            Self::Source { .. } => self.as_str().to_string().into(),
+            Self::Owned(text) => text.clone(),
+        }
+    }
+}
+
+impl Default for SourceText {
+    fn default() -> Self {
+        Self::Owned(SharedString::default())
+    }
+}
+
+impl Deref for SourceText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for SourceText {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::fmt::Debug for SourceText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for SourceText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq for SourceText {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SourceText {}
+
+impl PartialEq<str> for SourceText {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SourceText {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for SourceText {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl From<SharedString> for SourceText {
+    fn from(text: SharedString) -> Self {
+        Self::Owned(text)
+    }
+}
+
+impl From<String> for SourceText {
+    fn from(text: String) -> Self {
+        Self::Owned(text.into())
+    }
+}
+
+impl From<&'static str> for SourceText {
+    fn from(text: &'static str) -> Self {
+        Self::Owned(text.into())
+    }
 }
 
 /// A run of highlighted Markdown text.
@@ -218,6 +1203,8 @@ pub struct MarkdownHighlightStyle {
     pub strikethrough: bool,
     /// The weight of the text.
     pub weight: FontWeight,
+    /// Whether the text should be highlighted (`==marked==`).
+    pub highlight: bool,
 }
 
 /// A parsed region in a Markdown document.
@@ -228,13 +1215,44 @@ pub struct ParsedRegion {
     pub code: bool,
     /// The link contained in this region, if it has one.
     pub link: Option<Link>,
+    /// How the link was written. Regions without a link leave it at its
+    /// default.
+    pub origin: LinkOrigin,
+}
+
+/// How a link was written in the document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkOrigin {
+    /// `[text](url)`, or an `<a href>` in HTML.
+    #[default]
+    Explicit,
+    /// A bare URL picked out of the text, or one in angle brackets, like
+    /// `<https://zed.dev>`.
+    Autolink,
+    /// `[text][label]`, `[label][]` or `[label]`, with the URL in a
+    /// `[label]: url` definition elsewhere.
+    ReferenceDef,
+    /// `[[Page]]`. pulldown-cmark doesn't parse these yet, so only elements
+    /// built outside the parser have them.
+    Wikilink,
+}
+
+/// A link in a [`ParsedMarkdown`], from [`ParsedMarkdown::links`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentLink {
+    pub link: Link,
+    pub origin: LinkOrigin,
+    /// The text of the link, as it's shown.
+    pub text: String,
+    /// The source range of the text the link is in.
+    pub source_range: Range<usize>,
 }
 
 /// A Markdown link.
-#[derive(Debug, Clone)]
-#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Link {
-    /// A link to a webpage.
+    /// A link to a webpage, or another URL that's opened outside the
+    /// preview, like a `mailto:` one.
     Web {
         /// The URL of the webpage.
         url: String,
@@ -245,43 +1263,319 @@ pub enum Link {
         display_path: PathBuf,
         /// The absolute path to the item.
         path: PathBuf,
+        /// The heading the link points to in the file, like the `usage` in
+        /// `other.md#usage`, still percent-encoded.
+        fragment: Option<String>,
+    },
+    /// Content embedded in the document as a `data:` URL.
+    Data {
+        /// The full `data:` URL, payload included.
+        uri: SharedString,
+        /// A hash of `uri`, taken once when it's parsed, which the image is
+        /// cached by so that its payload isn't hashed every frame.
+        key: u64,
     },
+    /// A link to a heading in the same document, like `#setup`.
+    Fragment {
+        /// The fragment without its `#`, still percent-encoded.
+        fragment: String,
+    },
+}
+
+/// What a [`Link`] points to, without the details of where.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Web,
+    Path,
+    Data,
+    Fragment,
 }
 
 impl Link {
+    pub fn kind(&self) -> LinkKind {
+        match self {
+            Link::Web { .. } => LinkKind::Web,
+            Link::Path { .. } => LinkKind::Path,
+            Link::Data { .. } => LinkKind::Data,
+            Link::Fragment { .. } => LinkKind::Fragment,
+        }
+    }
+
+    /// The link as a URL. Unlike its [`Display`] form, paths are absolute and
+    /// `data:` URLs keep their payload.
+    pub fn url(&self) -> String {
+        match self {
+            Link::Web { url } => url.clone(),
+            Link::Path { path, fragment, .. } => match fragment {
+                Some(fragment) => format!("{}#{}", path.display(), fragment),
+                None => path.to_string_lossy().into_owned(),
+            },
+            Link::Data { uri, .. } => uri.to_string(),
+            Link::Fragment { fragment } => format!("#{}", fragment),
+        }
+    }
+
+    /// The URL the operating system is given to open the link, with what
+    /// can't be in a URL, like spaces and other than ASCII, percent-encoded
+    /// once. Files are opened by their `file://` URL, and links to data and
+    /// headings aren't opened outside the preview.
+    pub fn os_url(&self) -> Option<String> {
+        match self {
+            Link::Web { url } => Some(encode_url(url).into_owned()),
+            Link::Path { path, fragment, .. } => {
+                let mut url = Url::from_file_path(path).ok()?;
+                url.set_fragment(fragment.as_deref());
+                Some(url.into())
+            }
+            Link::Data { .. } | Link::Fragment { .. } => None,
+        }
+    }
+
+    /// Like [`Link::identify`], except that in documents with no directory,
+    /// relative references are resolved against `base_url` the way a
+    /// browser would, so `../img.png` and `/img.png` both work.
+    pub fn resolve(
+        file_location_directory: Option<PathBuf>,
+        base_url: Option<&Url>,
+        text: String,
+    ) -> Option<Link> {
+        match base_url.filter(|_| file_location_directory.is_none()) {
+            Some(base_url)
+                if !text.starts_with('#')
+                    && !text.starts_with("http")
+                    && !has_url_scheme(&text) =>
+            {
+                let url = base_url.join(&text).ok()?;
+                Some(Link::Web {
+                    url: url.to_string(),
+                })
+            }
+            _ => Link::identify(file_location_directory, text),
+        }
+    }
+
     pub fn identify(file_location_directory: Option<PathBuf>, text: String) -> Option<Link> {
         if text.starts_with("http") {
             return Some(Link::Web { url: text });
         }
 
-        let path = PathBuf::from(&text);
-        if path.is_absolute() && path.exists() {
+        if text.starts_with("data:") {
+            let mut hasher = DefaultHasher::new();
+            text.hash(&mut hasher);
+            return Some(Link::Data {
+                key: hasher.finish(),
+                uri: text.into(),
+            });
+        }
+
+        // `file://` links name files the way paths do.
+        if let Some((path, fragment)) = file_url_path(&text) {
             return Some(Link::Path {
                 display_path: path.clone(),
                 path,
+                fragment,
+            });
+        }
+
+        // Other schemes, like `mailto:`, are opened outside the preview too,
+        // if the renderer's `LinkPolicy` allows them.
+        if has_url_scheme(&text) {
+            return Some(Link::Web { url: text });
+        }
+
+        if let Some(fragment) = text.strip_prefix('#') {
+            return Some(Link::Fragment {
+                fragment: fragment.to_string(),
             });
         }
 
-        if let Some(file_location_directory) = file_location_directory {
-            let display_path = path;
-            let path = file_location_directory.join(text);
+        let text = expand_path(&text, file_location_directory.as_deref());
+        let (path_text, fragment) = split_path_fragment(&text, file_location_directory.as_deref());
+        let fragment = fragment.map(str::to_string);
+        // Paths in links are usually percent-encoded, but files can have
+        // names that only look like they are.
+        let decoded = percent_encoding::percent_decode_str(path_text).decode_utf8_lossy();
+        let candidates = [path_text, decoded.as_ref()];
+
+        for candidate in candidates {
+            let path = PathBuf::from(candidate);
+            if path.is_absolute() && path.exists() {
+                return Some(Link::Path {
+                    display_path: path.clone(),
+                    path,
+                    fragment,
+                });
+            }
+        }
+
+        let file_location_directory = file_location_directory?;
+        for candidate in candidates {
+            let path = file_location_directory.join(candidate);
             if path.exists() {
-                return Some(Link::Path { display_path, path });
+                return Some(Link::Path {
+                    display_path: PathBuf::from(candidate),
+                    path,
+                    fragment,
+                });
+            }
+        }
+
+        // Relative links to files that don't exist are still links, so that
+        // following them can tell the reader the file is missing.
+        if Path::new(decoded.as_ref()).is_absolute() {
+            return None;
+        }
+        Some(Link::Path {
+            display_path: PathBuf::from(decoded.as_ref()),
+            path: file_location_directory.join(decoded.as_ref()),
+            fragment,
+        })
+    }
+}
+
+/// Splits a path followed by a fragment, like `docs/setup.md#install`, into
+/// the path and the fragment without its `#`. Links, the command line and
+/// dropped files all go through this, so that they agree on what a fragment
+/// is. A `#` that's part of the name of an existing file, relative to
+/// `directory` or the working directory, doesn't start one.
+pub fn split_path_fragment<'a>(
+    text: &'a str,
+    directory: Option<&Path>,
+) -> (&'a str, Option<&'a str>) {
+    let exists = |path: &str| match directory {
+        Some(directory) => directory.join(path).exists(),
+        None => Path::new(path).exists(),
+    };
+    if exists(text) {
+        return (text, None);
+    }
+    for (ix, _) in text.rmatch_indices('#') {
+        if ix > 0 && exists(&text[..ix]) {
+            return (&text[..ix], Some(&text[ix + 1..]));
+        }
+    }
+    match text.split_once('#') {
+        Some((path, fragment)) if !path.is_empty() => (path, Some(fragment)),
+        _ => (text, None),
+    }
+}
+
+/// Expands a `~` at the start of `text` to the home directory, and `$NAME`
+/// and `${NAME}` to the values of environment variables. Like
+/// [`split_path_fragment`], links, the command line and dropped files all
+/// go through this.
+///
+/// Text that names an existing file, relative to `directory` or the working
+/// directory, is left as it is, and so are variables that aren't set, so
+/// the path fails to resolve like any other missing file.
+pub fn expand_path<'a>(text: &'a str, directory: Option<&Path>) -> Cow<'a, str> {
+    expand_path_with(text, directory, |name| {
+        std::env::var_os(name).map(|value| value.to_string_lossy().into_owned())
+    })
+}
+
+/// [`expand_path`], with the environment variable named `name`, including
+/// the one for the home directory, looked up by `var(name)`.
+pub(crate) fn expand_path_with<'a>(
+    text: &'a str,
+    directory: Option<&Path>,
+    var: impl Fn(&str) -> Option<String>,
+) -> Cow<'a, str> {
+    if !(text.starts_with('~') || text.contains('$')) {
+        return Cow::Borrowed(text);
+    }
+    let exists = match directory {
+        Some(directory) => directory.join(text).exists(),
+        None => Path::new(text).exists(),
+    };
+    if exists {
+        return Cow::Borrowed(text);
+    }
+
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    let home = var(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    if let (Some(after), Some(home)) = (
+        rest.strip_prefix('~')
+            .filter(|after| after.is_empty() || after.starts_with(['/', '\\'])),
+        home,
+    ) {
+        expanded.push_str(&home);
+        rest = after;
+    }
+    while let Some(ix) = rest.find('$') {
+        expanded.push_str(&rest[..ix]);
+        let after = &rest[ix + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => braced.split_once('}').unwrap_or(("", after)),
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                after.split_at(end)
             }
+        };
+        let is_name =
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        match is_name.then(|| var(name)).flatten() {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[ix..rest.len() - remainder.len()]),
         }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+    Cow::Owned(expanded)
+}
 
-        None
+/// `url` with the characters that can't be in a URL, like spaces and
+/// letters other than ASCII, percent-encoded. What's already encoded, like
+/// the `%20` of `a%20b`, stays as it is, and URLs with nothing to encode are
+/// returned unchanged.
+pub fn encode_url(url: &str) -> Cow<'_, str> {
+    if url.bytes().all(|byte| byte.is_ascii_graphic()) {
+        return Cow::Borrowed(url);
+    }
+    match Url::parse(url) {
+        Ok(parsed) => Cow::Owned(parsed.into()),
+        Err(_) => Cow::Borrowed(url),
     }
 }
 
+/// The file and fragment a `file://` URL points to, like the links the
+/// preview copies to blocks.
+pub fn file_url_path(text: &str) -> Option<(PathBuf, Option<String>)> {
+    let url = Url::parse(text).ok().filter(|url| url.scheme() == "file")?;
+    Some((url.to_file_path().ok()?, url.fragment().map(str::to_string)))
+}
+
+/// Whether `text` starts with a URL scheme, like `mailto:`. Windows drive
+/// letters, like the `C:` of `C:\docs`, aren't schemes.
+fn has_url_scheme(text: &str) -> bool {
+    text.split_once(':').map_or(false, |(scheme, _)| {
+        scheme.len() > 1
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
 impl Display for Link {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Link::Web { url } => write!(f, "{}", url),
             Link::Path {
                 display_path,
-                path: _,
-            } => write!(f, "{}", display_path.display()),
+                fragment: Some(fragment),
+                ..
+            } => write!(f, "{}#{}", display_path.display(), fragment),
+            Link::Path { display_path, .. } => write!(f, "{}", display_path.display()),
+            // The payload can be megabytes long, so only show the media type.
+            Link::Data { uri, .. } => {
+                let header = uri.split_once(',').map_or(&**uri, |(header, _)| header);
+                write!(f, "{}", header)
+            }
+            Link::Fragment { fragment } => write!(f, "#{}", fragment),
         }
     }
 }
@@ -0,0 +1,193 @@
+use super::markdown_elements::{
+    ParsedMarkdownCodeBlock, ParsedMarkdownTable, ParsedMarkdownTableAlignment,
+    ParsedMarkdownTableRow, ParsedMarkdownText,
+};
+use super::markdown_renderer::{render_markdown_table, RenderContext};
+use collections::FxHashMap;
+use gpui::{AnyElement, SharedString};
+use std::{ops::Range, sync::Arc};
+
+/// Renders the body of a fenced code block in place of the code block. The
+/// raw body of the fence is in [`ParsedMarkdownCodeBlock::contents`].
+pub type FenceHandler = Arc<dyn Fn(&ParsedMarkdownCodeBlock, &mut RenderContext) -> AnyElement>;
+
+/// Maps fence languages, like the `csv` in ```` ```csv ````, to the handlers
+/// that render them. Fences in any other language are rendered as code.
+#[derive(Default, Clone)]
+pub struct FenceHandlers {
+    handlers: FxHashMap<SharedString, FenceHandler>,
+}
+
+impl FenceHandlers {
+    /// Returns a registry with the handlers that ship with the preview.
+    pub fn builtin() -> Self {
+        let mut handlers = Self::default();
+        handlers.register("csv", render_csv_fence);
+        handlers
+    }
+
+    /// Renders fences in `language` with `handler`, replacing any handler
+    /// that was registered for it before.
+    pub fn register(
+        &mut self,
+        language: impl Into<SharedString>,
+        handler: impl Fn(&ParsedMarkdownCodeBlock, &mut RenderContext) -> AnyElement + 'static,
+    ) {
+        self.handlers.insert(language.into(), Arc::new(handler));
+    }
+
+    pub fn get(&self, language: &str) -> Option<FenceHandler> {
+        self.handlers.get(language).cloned()
+    }
+}
+
+/// Renders a `csv` fence as a table, with its first record as the header.
+pub fn render_csv_fence(parsed: &ParsedMarkdownCodeBlock, cx: &mut RenderContext) -> AnyElement {
+    render_markdown_table(&csv_table(parsed), cx)
+}
+
+/// The table a `csv` fence is shown as, with each cell at the range of its
+/// field in the source.
+fn csv_table(parsed: &ParsedMarkdownCodeBlock) -> ParsedMarkdownTable {
+    let body_start = parsed.source_range.start + parsed.contents_offset;
+    let mut rows = parse_csv(&parsed.contents).into_iter().map(|record| {
+        let children = record
+            .into_iter()
+            .map(|(range, contents)| ParsedMarkdownText {
+                source_range: range.start + body_start..range.end + body_start,
+                contents: contents.into(),
+                highlights: Vec::new(),
+                region_ranges: Vec::new(),
+                regions: Vec::new(),
+                images: Vec::new(),
+                continued: false,
+            })
+            .collect();
+        ParsedMarkdownTableRow { children }
+    });
+
+    let header = rows.next().unwrap_or_default();
+    ParsedMarkdownTable {
+        source_range: parsed.source_range.clone(),
+        column_alignments: vec![ParsedMarkdownTableAlignment::None; header.children.len()],
+        header,
+        body: rows.collect(),
+    }
+}
+
+/// Splits CSV into records of fields, along with the range of each field in
+/// `text`. Fields may be quoted, in which case they can contain commas, line
+/// breaks and doubled quotes.
+fn parse_csv(text: &str) -> Vec<Vec<(Range<usize>, String)>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut field_start = 0;
+    let mut in_quotes = false;
+
+    let mut chars = text.char_indices().peekable();
+    while let Some((ix, c)) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if let Some((_, '"')) = chars.peek() {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field.is_empty() => in_quotes = true,
+            ',' if !in_quotes => {
+                record.push((field_start..ix, std::mem::take(&mut field)));
+                field_start = ix + 1;
+            }
+            '\n' if !in_quotes => {
+                let end = if text[..ix].ends_with('\r') {
+                    ix - 1
+                } else {
+                    ix
+                };
+                if end > field_start || !record.is_empty() {
+                    field.truncate(field.trim_end_matches('\r').len());
+                    record.push((field_start..end, std::mem::take(&mut field)));
+                    records.push(std::mem::take(&mut record));
+                }
+                field_start = ix + 1;
+            }
+            c => field.push(c),
+        }
+    }
+
+    if text.len() > field_start || !record.is_empty() {
+        record.push((field_start..text.len(), field));
+        records.push(record);
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::{
+        markdown_elements::ParsedMarkdownElement, markdown_parser::parse_markdown,
+    };
+
+    fn fields(text: &str) -> Vec<Vec<String>> {
+        parse_csv(text)
+            .into_iter()
+            .map(|record| record.into_iter().map(|(_, field)| field).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        assert_eq!(
+            fields("name,age\nAda,36\n\nGrace,85\n"),
+            vec![
+                vec!["name".to_string(), "age".to_string()],
+                vec!["Ada".to_string(), "36".to_string()],
+                vec!["Grace".to_string(), "85".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_with_quotes() {
+        assert_eq!(
+            fields("a,\"b, \"\"c\"\"\"\r\n\"multi\nline\",\n"),
+            vec![
+                vec!["a".to_string(), "b, \"c\"".to_string()],
+                vec!["multi\nline".to_string(), String::new()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_ranges() {
+        let text = "ab,cd\nef";
+        let ranges: Vec<_> = parse_csv(text)
+            .into_iter()
+            .flatten()
+            .map(|(range, _)| &text[range])
+            .collect();
+        assert_eq!(ranges, vec!["ab", "cd", "ef"]);
+    }
+
+    #[gpui::test]
+    async fn test_csv_cell_source_ranges() {
+        let text = "Intro\n\n```csv\nname,age\nAda,36\n```\n";
+        let parsed = parse_markdown(text, None, None).await;
+        let ParsedMarkdownElement::CodeBlock(code_block) = &parsed.children[1] else {
+            panic!("expected a code block");
+        };
+        let table = csv_table(code_block);
+        let cells: Vec<_> = [&table.header, &table.body[0]]
+            .into_iter()
+            .flat_map(|row| &row.children)
+            .map(|cell| &text[cell.source_range.clone()])
+            .collect();
+        assert_eq!(cells, vec!["name", "age", "Ada", "36"]);
+    }
+}
@@ -0,0 +1,687 @@
+//! Writes parsed documents back out as Markdown, to format them.
+
+use super::{
+    markdown_elements::{
+        prefix_lines, HeadingLevel, ParsedMarkdown, ParsedMarkdownCodeBlock, ParsedMarkdownElement,
+        ParsedMarkdownHeading, ParsedMarkdownListItem, ParsedMarkdownListItemType,
+        ParsedMarkdownTable, ParsedMarkdownTableAlignment,
+    },
+    markdown_parser::ParseOptions,
+};
+use pulldown_cmark::{Event, Parser, Tag};
+use std::{borrow::Cow, ops::Range};
+
+impl ParsedMarkdown {
+    /// Writes the document out as Markdown in one consistent style, for
+    /// `text`, the source it was parsed from. Parsing the result gives the
+    /// same blocks again.
+    ///
+    /// - headings are written with `#`s,
+    /// - list items start with `-`, `- [ ]` or their number and a `.`, and
+    ///   nested ones are indented to line up with the text of their parent,
+    /// - the pipes of tables line up, with the cells padded the way the
+    ///   column is aligned,
+    /// - code blocks are fenced with backticks,
+    /// - rules are `---`,
+    /// - link reference definitions are moved to the bottom.
+    ///
+    /// Text inside of blocks is kept as it's written, so that emphasis,
+    /// links and inline HTML stay as they are. What the blocks can't
+    /// represent, like front matter, HTML blocks, footnote definitions or
+    /// `!include` directives, is passed through from `text` unchanged.
+    pub fn to_markdown(&self, text: &str) -> String {
+        let (source_blocks, definitions) = source_blocks(text);
+
+        let mut writer = MarkdownWriter::new(text, 0);
+        let mut block_ix = 0;
+        for source_block in &source_blocks {
+            let start_ix = block_ix;
+            while block_ix < self.children.len()
+                && (self.is_included(block_ix)
+                    || self.children[block_ix].source_range().start < source_block.range.end)
+            {
+                block_ix += 1;
+            }
+
+            let blocks: Vec<_> = (start_ix..block_ix)
+                .filter(|&ix| !self.is_included(ix))
+                .map(|ix| &self.children[ix])
+                .collect();
+            // A paragraph parsed as something else, like an `!include`
+            // directive that couldn't be followed, is kept as it's written.
+            let replaced = source_block.paragraph
+                && blocks
+                    .iter()
+                    .any(|block| !matches!(block, ParsedMarkdownElement::Paragraph(_)));
+            if source_block.raw || replaced || blocks.is_empty() {
+                writer.push_raw(text[source_block.range.clone()].trim());
+            } else {
+                for block in blocks {
+                    writer.push_block(block);
+                }
+            }
+        }
+        for block in &self.children[block_ix..] {
+            writer.push_block(block);
+        }
+
+        let mut markdown = writer.markdown;
+        if !definitions.is_empty() {
+            if !markdown.is_empty() {
+                markdown.push_str("\n\n");
+            }
+            let definitions: Vec<String> = definitions
+                .into_iter()
+                .map(|range| single_line(&text[range]))
+                .collect();
+            markdown.push_str(&definitions.join("\n"));
+        }
+        if !markdown.is_empty() {
+            markdown.push('\n');
+        }
+        markdown
+    }
+}
+
+/// A top-level block of a document, as pulldown-cmark sees it.
+struct SourceBlock {
+    range: Range<usize>,
+    /// Whether the block is passed through as it's written.
+    raw: bool,
+    paragraph: bool,
+}
+
+/// The top-level blocks of `text`, and the source of its link reference
+/// definitions, which aren't blocks, in the order they're written.
+fn source_blocks(text: &str) -> (Vec<SourceBlock>, Vec<Range<usize>>) {
+    let parser = Parser::new_ext(text, ParseOptions::default().pulldown_options());
+    let mut definitions: Vec<Range<usize>> = parser
+        .reference_definitions()
+        .iter()
+        .map(|(_, definition)| definition.span.clone())
+        .collect();
+    definitions.sort_by_key(|range| range.start);
+
+    let mut blocks = Vec::new();
+    let mut depth = 0usize;
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(tag) => {
+                if depth == 0 {
+                    blocks.push(SourceBlock {
+                        range,
+                        raw: matches!(
+                            tag,
+                            Tag::HtmlBlock | Tag::FootnoteDefinition(_) | Tag::MetadataBlock(_)
+                        ),
+                        paragraph: matches!(tag, Tag::Paragraph),
+                    });
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Rule if depth == 0 => blocks.push(SourceBlock {
+                range,
+                raw: false,
+                paragraph: false,
+            }),
+            _ => {}
+        }
+    }
+    (blocks, definitions)
+}
+
+/// A list item that later items may be nested in.
+#[derive(Clone, Copy)]
+struct OpenListItem {
+    /// Where the text of the item starts, which nested items line up with.
+    content_column: usize,
+    /// The number of an ordered item, and the character after it.
+    number: Option<(u64, char)>,
+}
+
+/// Writes a run of blocks, like the blocks of a document or of a block
+/// quote, separated by blank lines.
+struct MarkdownWriter<'a> {
+    text: &'a str,
+    /// How many block quotes the blocks are in.
+    quote_depth: usize,
+    markdown: String,
+    previous: Option<&'a ParsedMarkdownElement>,
+    /// The last item at each depth of the list being written.
+    list_items: Vec<OpenListItem>,
+}
+
+impl<'a> MarkdownWriter<'a> {
+    fn new(text: &'a str, quote_depth: usize) -> Self {
+        Self {
+            text,
+            quote_depth,
+            markdown: String::new(),
+            previous: None,
+            list_items: Vec::new(),
+        }
+    }
+
+    fn push_raw(&mut self, source: &str) {
+        self.push_separator(false);
+        self.markdown.push_str(source);
+        self.previous = None;
+        self.list_items.clear();
+    }
+
+    fn push_block(&mut self, block: &'a ParsedMarkdownElement) {
+        let markdown = self.block_markdown(block);
+        self.push_markdown(block, &markdown);
+    }
+
+    fn push_markdown(&mut self, block: &'a ParsedMarkdownElement, markdown: &str) {
+        // The items of a list go on consecutive lines, and so do the parts of
+        // a paragraph that the parser split up.
+        let joined = match (self.previous, block) {
+            (Some(ParsedMarkdownElement::ListItem(_)), ParsedMarkdownElement::ListItem(_)) => true,
+            (Some(ParsedMarkdownElement::Paragraph(_)), ParsedMarkdownElement::Paragraph(text)) => {
+                text.continued
+            }
+            _ => false,
+        };
+        self.push_separator(joined);
+        self.markdown.push_str(markdown);
+        self.previous = Some(block);
+        if !block.is_list_item() {
+            self.list_items.clear();
+        }
+    }
+
+    fn push_separator(&mut self, joined: bool) {
+        if !self.markdown.is_empty() {
+            self.markdown.push_str(if joined { "\n" } else { "\n\n" });
+        }
+    }
+
+    fn block_markdown(&mut self, block: &'a ParsedMarkdownElement) -> String {
+        let text = self.text;
+        let source = |range: Range<usize>| text.get(range).unwrap_or_default();
+        match block {
+            ParsedMarkdownElement::Heading(heading) => self.heading(heading),
+            ParsedMarkdownElement::Paragraph(text) => {
+                self.inline(source(text.source_range.clone()))
+            }
+            ParsedMarkdownElement::ListItem(item) => self.list_item(item),
+            ParsedMarkdownElement::Table(table) => self.table(table),
+            ParsedMarkdownElement::BlockQuote(quote) => {
+                let mut writer = MarkdownWriter::new(self.text, self.quote_depth + 1);
+                for child in &quote.children {
+                    writer.push_block(child);
+                }
+                if writer.markdown.is_empty() {
+                    ">".to_string()
+                } else {
+                    prefix_lines(&writer.markdown, "> ", ">")
+                }
+            }
+            ParsedMarkdownElement::CodeBlock(code_block) => self.code_block(code_block),
+            ParsedMarkdownElement::Image(image) => self.inline(source(image.source_range.clone())),
+            ParsedMarkdownElement::HorizontalRule(_) => "---".to_string(),
+        }
+    }
+
+    /// The text of lines in a block, joined back up without the indentation
+    /// and the `>`s of the blocks they're in, which are written anew.
+    fn inline(&self, source: &str) -> String {
+        source
+            .trim()
+            .lines()
+            .enumerate()
+            .map(|(ix, line)| {
+                if ix == 0 {
+                    return line;
+                }
+                let mut line = line.trim_start();
+                for _ in 0..self.quote_depth {
+                    // Lines that continue a paragraph may leave out the `>`.
+                    match line.strip_prefix('>') {
+                        Some(rest) => line = rest.trim_start(),
+                        None => break,
+                    }
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn heading(&self, heading: &ParsedMarkdownHeading) -> String {
+        let source = self
+            .text
+            .get(heading.source_range.clone())
+            .unwrap_or_default()
+            .trim();
+        let first_line = source.lines().next().unwrap_or_default();
+        let text = if first_line.starts_with('#') {
+            let text = first_line.trim_start_matches('#').trim();
+            // The closing `#`s are optional, and only close the heading after
+            // a space.
+            let unclosed = text.trim_end_matches('#');
+            if unclosed.len() < text.len()
+                && (unclosed.is_empty() || unclosed.ends_with([' ', '\t']))
+            {
+                unclosed.trim_end()
+            } else {
+                text
+            }
+        } else {
+            // An underlined heading of several lines can't be written with
+            // `#`s, which end at the end of the line.
+            let lines: Vec<&str> = source.lines().collect();
+            if lines.len() != 2 {
+                return self.inline(source);
+            }
+            lines[0].trim()
+        };
+        let text = escape_closing_hashes(text);
+
+        let level = match heading.level {
+            HeadingLevel::H1 => 1,
+            HeadingLevel::H2 => 2,
+            HeadingLevel::H3 => 3,
+            HeadingLevel::H4 => 4,
+            HeadingLevel::H5 => 5,
+            HeadingLevel::H6 => 6,
+        };
+        let hashes = "#".repeat(level);
+        if text.is_empty() {
+            hashes
+        } else {
+            format!("{} {}", hashes, text)
+        }
+    }
+
+    fn list_item(&mut self, item: &'a ParsedMarkdownListItem) -> String {
+        let depth = usize::from(item.depth.max(1));
+        let sibling = self.list_items.get(depth - 1).copied();
+        self.list_items.truncate(depth - 1);
+        let indent = self
+            .list_items
+            .last()
+            .map_or(0, |parent| parent.content_column);
+
+        let (marker, number) = match &item.item_type {
+            ParsedMarkdownListItemType::Ordered(number) => {
+                // A list that doesn't continue the numbers of the one before
+                // it is told apart from it by what follows the number.
+                let delimiter = match sibling.and_then(|sibling| sibling.number) {
+                    Some((previous, delimiter)) if previous + 1 == *number => delimiter,
+                    Some((_, '.')) => ')',
+                    _ => '.',
+                };
+                (
+                    format!("{}{} ", number, delimiter),
+                    Some((*number, delimiter)),
+                )
+            }
+            ParsedMarkdownListItemType::Task(true, _) => ("- [x] ".to_string(), None),
+            ParsedMarkdownListItemType::Task(false, _) => ("- [ ] ".to_string(), None),
+            ParsedMarkdownListItemType::Unordered => ("- ".to_string(), None),
+        };
+        // The checkbox of a task is part of its text.
+        let content_column = indent
+            + match item.item_type {
+                ParsedMarkdownListItemType::Task(..) => 2,
+                _ => marker.len(),
+            };
+
+        let mut writer = MarkdownWriter::new(self.text, self.quote_depth);
+        let mut content = item.content.iter();
+        if let Some(text) = self.list_item_text(item) {
+            if let Some(first) = content.next() {
+                writer.push_markdown(first, &text);
+            }
+        }
+        for block in content {
+            writer.push_block(block);
+        }
+        self.list_items.push(OpenListItem {
+            content_column,
+            number,
+        });
+
+        let mut lines = writer.markdown.lines();
+        let first_line = lines.next().unwrap_or_default();
+        let mut markdown = " ".repeat(indent);
+        if first_line.is_empty() {
+            markdown.push_str(marker.trim_end());
+        } else {
+            markdown.push_str(&marker);
+            markdown.push_str(first_line);
+        }
+        for line in lines {
+            markdown.push('\n');
+            if !line.is_empty() {
+                markdown.push_str(&" ".repeat(content_column));
+                markdown.push_str(line);
+            }
+        }
+        markdown
+    }
+
+    /// The text of the paragraph that starts `item`, from the source of the
+    /// item, since the paragraph's own source range only covers the start
+    /// of it.
+    fn list_item_text(&self, item: &ParsedMarkdownListItem) -> Option<String> {
+        let Some(ParsedMarkdownElement::Paragraph(first)) = item.content.first() else {
+            return None;
+        };
+        if !first.images.is_empty() {
+            return None;
+        }
+
+        // Items leave out their last byte, which is their newline, unless
+        // they're at the end of a document without one.
+        let mut end = item.source_range.end;
+        if end + 1 == self.text.len() && !self.text.ends_with('\n') {
+            end = self.text.len();
+        }
+        if let Some(next) = item.content.get(1) {
+            end = end.min(next.source_range().start);
+        }
+        let source = self.text.get(item.source_range.start..end)?.trim_start();
+        let mut source = source
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .strip_prefix(['-', '+', '*', '.', ')'])?;
+        if let ParsedMarkdownListItemType::Task(..) = item.item_type {
+            let trimmed = source.trim_start();
+            source = ["[ ]", "[x]", "[X]"]
+                .iter()
+                .find_map(|checkbox| trimmed.strip_prefix(checkbox))
+                .unwrap_or(source);
+        }
+        Some(self.inline(source))
+    }
+
+    fn table(&self, table: &ParsedMarkdownTable) -> String {
+        let columns = table.column_alignments.len();
+        let rows: Vec<Vec<String>> = std::iter::once(&table.header)
+            .chain(&table.body)
+            .map(|row| {
+                (0..columns)
+                    .map(|column| {
+                        row.children
+                            .get(column)
+                            .map(|cell| table_cell(self.text, cell.source_range.clone()))
+                            .unwrap_or_default()
+                    })
+                    .collect()
+            })
+            .collect();
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or_default()
+                    .max(3)
+            })
+            .collect();
+
+        let delimiters: Vec<String> = table
+            .column_alignments
+            .iter()
+            .zip(&widths)
+            .map(|(alignment, &width)| match alignment {
+                ParsedMarkdownTableAlignment::None => "-".repeat(width),
+                ParsedMarkdownTableAlignment::Left => format!(":{}", "-".repeat(width - 1)),
+                ParsedMarkdownTableAlignment::Center => format!(":{}:", "-".repeat(width - 2)),
+                ParsedMarkdownTableAlignment::Right => format!("{}:", "-".repeat(width - 1)),
+            })
+            .collect();
+
+        let mut lines = Vec::with_capacity(rows.len() + 1);
+        for (row_ix, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .zip(&table.column_alignments)
+                .map(|((cell, &width), alignment)| {
+                    let padding = width - cell.chars().count();
+                    let (before, after) = match alignment {
+                        ParsedMarkdownTableAlignment::Right => (padding, 0),
+                        ParsedMarkdownTableAlignment::Center => {
+                            (padding / 2, padding - padding / 2)
+                        }
+                        _ => (0, padding),
+                    };
+                    format!("{}{}{}", " ".repeat(before), cell, " ".repeat(after))
+                })
+                .collect();
+            lines.push(format!("| {} |", cells.join(" | ")));
+            if row_ix == 0 {
+                lines.push(format!("| {} |", delimiters.join(" | ")));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn code_block(&self, code_block: &ParsedMarkdownCodeBlock) -> String {
+        let source = self
+            .text
+            .get(code_block.source_range.clone())
+            .unwrap_or_default()
+            .trim_start();
+        // The language and the attributes after it, like `rust {2-4}`.
+        let info = if source.starts_with("```") || source.starts_with("~~~") {
+            source
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches(['`', '~'])
+                .trim()
+        } else {
+            ""
+        };
+
+        // The fence has to be longer than any run of backticks that starts a
+        // line of the code.
+        let longest_run = code_block
+            .contents
+            .lines()
+            .map(|line| line.trim_start().chars().take_while(|&c| c == '`').count())
+            .max()
+            .unwrap_or_default();
+        let fence_char = if info.contains('`') { "~" } else { "`" };
+        let fence = fence_char.repeat((longest_run + 1).max(3));
+
+        if code_block.contents.is_empty() {
+            format!("{fence}{info}\n{fence}")
+        } else {
+            format!("{fence}{info}\n{}\n{fence}", code_block.contents)
+        }
+    }
+}
+
+/// `text` with a backslash in front of the `#`s it ends with, if they'd be
+/// taken for the closing `#`s of a heading.
+fn escape_closing_hashes(text: &str) -> Cow<'_, str> {
+    let unclosed = text.trim_end_matches('#');
+    if unclosed.len() < text.len() && (unclosed.is_empty() || unclosed.ends_with([' ', '\t'])) {
+        Cow::Owned(format!("{}\\{}", unclosed, &text[unclosed.len()..]))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// The text of the table cell at `range` in `text`, without the pipes
+/// around it.
+fn table_cell(text: &str, range: Range<usize>) -> String {
+    let cell = text.get(range).unwrap_or_default().trim();
+    let cell = cell.strip_prefix('|').unwrap_or(cell);
+    let cell = match cell.strip_suffix('|') {
+        Some(rest) if !rest.ends_with('\\') => rest,
+        _ => cell,
+    };
+    cell.trim().to_string()
+}
+
+/// `source` with its lines joined by spaces, for link reference definitions
+/// that are spread over several lines.
+fn single_line(source: &str) -> String {
+    source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use pretty_assertions::assert_eq;
+
+    const DOCUMENT: &str = "\
+---
+title: Notes
+---
+
+Title
+=====
+
+Some *emphasis*, a [reference link][docs] and `code`.
+   It goes on.
+
+[docs]: https://zed.dev/docs
+
+* one
+* [x] two
+    * nested
+1) first
+2) second
+
+<div>
+  <p>Some HTML</p>
+</div>
+
+| Name | Size |
+|:-----|-----:|
+| a | 1 |
+| longer name | 22 |
+
+> Quoted
+> text
+>
+> > Nested
+
+~~~rust {2}
+fn main() {
+    println!();
+}
+~~~
+
+Closed heading ##
+-----------------
+
+***
+";
+
+    /// The debug tree of `parsed` without the source ranges of its blocks,
+    /// which move when the document is formatted.
+    fn debug_tree_without_source_ranges(parsed: &ParsedMarkdown) -> String {
+        let is_range = |token: &str| {
+            token.split_once("..").is_some_and(|(start, end)| {
+                start.parse::<usize>().is_ok() && end.parse::<usize>().is_ok()
+            })
+        };
+        parsed
+            .to_debug_tree()
+            .lines()
+            .map(|line| {
+                // These ranges are into the text of the block, which stays
+                // the same.
+                let in_text = ["style ", "code ", "link ", "emphasized "]
+                    .iter()
+                    .any(|prefix| line.trim_start().starts_with(prefix));
+                match line.rsplit_once(' ') {
+                    Some((rest, last)) if !in_text && is_range(last) => rest,
+                    _ => line,
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[gpui::test]
+    async fn test_to_markdown() {
+        let parsed = parse_markdown(DOCUMENT, None, None).await;
+        let formatted = parsed.to_markdown(DOCUMENT);
+        assert_eq!(
+            formatted,
+            "\
+---
+title: Notes
+---
+
+# Title
+
+Some *emphasis*, a [reference link][docs] and `code`.
+It goes on.
+
+- one
+- [x] two
+  - nested
+1. first
+2. second
+
+<div>
+  <p>Some HTML</p>
+</div>
+
+| Name        | Size |
+| :---------- | ---: |
+| a           |    1 |
+| longer name |   22 |
+
+> Quoted
+> text
+>
+> > Nested
+
+```rust {2}
+fn main() {
+    println!();
+}
+```
+
+## Closed heading \\##
+
+---
+
+[docs]: https://zed.dev/docs
+"
+        );
+
+        let reparsed = parse_markdown(&formatted, None, None).await;
+        assert_eq!(
+            debug_tree_without_source_ranges(&reparsed),
+            debug_tree_without_source_ranges(&parsed)
+        );
+        assert_eq!(reparsed.to_markdown(&formatted), formatted);
+    }
+
+    #[gpui::test]
+    async fn test_to_markdown_keeps_lists_apart() {
+        let text = "1. one\n2. two\n1) again\n\n<!-- -->\n\n1. after\n- [ ] last";
+        let parsed = parse_markdown(text, None, None).await;
+        let formatted = parsed.to_markdown(text);
+        assert_eq!(
+            formatted,
+            "1. one\n2. two\n1) again\n\n<!-- -->\n\n1. after\n- [ ] last\n"
+        );
+        let reparsed = parse_markdown(&formatted, None, None).await;
+        assert_eq!(
+            debug_tree_without_source_ranges(&reparsed),
+            debug_tree_without_source_ranges(&parsed)
+        );
+    }
+}
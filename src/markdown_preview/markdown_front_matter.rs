@@ -0,0 +1,303 @@
+use pulldown_cmark::MetadataBlockKind;
+use std::ops::Range;
+
+/// The metadata at the top of a document, between `---` lines as YAML or
+/// between `+++` lines as TOML.
+///
+/// Only the common subset of either format is understood: keys with text
+/// values, lists, and maps nested under keys. Anything else is kept as the
+/// text it was written as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontMatter {
+    pub source_range: Range<usize>,
+    pub entries: Vec<(String, FrontMatterValue)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrontMatterValue {
+    Text(String),
+    List(Vec<FrontMatterValue>),
+    Map(Vec<(String, FrontMatterValue)>),
+}
+
+impl FrontMatter {
+    /// Parses the body of a metadata block, without its `---` or `+++`
+    /// lines.
+    pub fn parse(kind: MetadataBlockKind, text: &str, source_range: Range<usize>) -> Self {
+        let entries = match kind {
+            MetadataBlockKind::YamlStyle => parse_yaml(text),
+            MetadataBlockKind::PlusesStyle => parse_toml(text),
+        };
+        Self {
+            source_range,
+            entries,
+        }
+    }
+}
+
+fn parse_yaml(text: &str) -> Vec<(String, FrontMatterValue)> {
+    let mut lines: Vec<(usize, &str)> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|line| (line.len() - line.trim_start().len(), line.trim()))
+        .collect();
+    let mut ix = 0;
+    let mut entries = Vec::new();
+    // Lines that are indented less than the first key start another map at
+    // their indentation, and lines that aren't entries are skipped.
+    while ix < lines.len() {
+        let start = ix;
+        let indent = lines[ix].0;
+        entries.extend(parse_yaml_map(&mut lines, &mut ix, indent));
+        if ix == start {
+            ix += 1;
+        }
+    }
+    entries
+}
+
+/// Parses the entries of the map that starts at `lines[*ix]`, up to the
+/// first line that's indented less than `indent`.
+fn parse_yaml_map(
+    lines: &mut Vec<(usize, &str)>,
+    ix: &mut usize,
+    indent: usize,
+) -> Vec<(String, FrontMatterValue)> {
+    let mut entries = Vec::new();
+    while let Some(&(line_indent, line)) = lines.get(*ix) {
+        if line_indent < indent || (line_indent == indent && line.starts_with('-')) {
+            break;
+        }
+        *ix += 1;
+        if line_indent > indent {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        let value = match value.trim() {
+            "" => parse_yaml_nested(lines, ix, indent),
+            "|" | "|-" | ">" | ">-" => {
+                let mut block = Vec::new();
+                while let Some(&(_, line)) = lines.get(*ix).filter(|(i, _)| *i > indent) {
+                    block.push(line);
+                    *ix += 1;
+                }
+                let separator = if value.trim().starts_with('|') {
+                    "\n"
+                } else {
+                    " "
+                };
+                FrontMatterValue::Text(block.join(separator))
+            }
+            value => parse_flow_value(value),
+        };
+        entries.push((unquote(key.trim()), value));
+    }
+    entries
+}
+
+/// Parses the value of a key that's written on the lines after it: a list,
+/// which may be indented as much as the key, or a more indented map.
+fn parse_yaml_nested(
+    lines: &mut Vec<(usize, &str)>,
+    ix: &mut usize,
+    parent_indent: usize,
+) -> FrontMatterValue {
+    match lines.get(*ix).copied() {
+        Some((indent, line)) if indent >= parent_indent && is_yaml_list_item(line) => {
+            let mut items = Vec::new();
+            while let Some(&(item_indent, line)) = lines.get(*ix) {
+                if item_indent != indent || !is_yaml_list_item(line) {
+                    break;
+                }
+                let item = line[1..].trim_start();
+                let is_map = item.ends_with(':') || item.contains(": ");
+                if is_map {
+                    // The item's first entry is parsed along with the ones
+                    // under it, as though it were on a line of its own.
+                    let item_indent = indent + line.len() - item.len();
+                    lines[*ix] = (item_indent, item);
+                    items.push(FrontMatterValue::Map(parse_yaml_map(
+                        lines,
+                        ix,
+                        item_indent,
+                    )));
+                } else {
+                    *ix += 1;
+                    items.push(parse_flow_value(item));
+                }
+            }
+            FrontMatterValue::List(items)
+        }
+        Some((indent, _)) if indent > parent_indent => {
+            FrontMatterValue::Map(parse_yaml_map(lines, ix, indent))
+        }
+        _ => FrontMatterValue::Text(String::new()),
+    }
+}
+
+fn is_yaml_list_item(line: &str) -> bool {
+    line == "-" || line.starts_with("- ")
+}
+
+fn parse_toml(text: &str) -> Vec<(String, FrontMatterValue)> {
+    let mut entries = Vec::new();
+    let mut table: Option<(String, Vec<(String, FrontMatterValue)>)> = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            if let Some((name, table_entries)) = table.take() {
+                entries.push((name, FrontMatterValue::Map(table_entries)));
+            }
+            table = Some((unquote(name.trim()), Vec::new()));
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let entry = (unquote(key.trim()), parse_flow_value(value.trim()));
+        match &mut table {
+            Some((_, table_entries)) => table_entries.push(entry),
+            None => entries.push(entry),
+        }
+    }
+    if let Some((name, table_entries)) = table {
+        entries.push((name, FrontMatterValue::Map(table_entries)));
+    }
+    entries
+}
+
+/// Parses a value written on one line, which is a list if it's in square
+/// brackets, like `[rust, gpui]`.
+fn parse_flow_value(value: &str) -> FrontMatterValue {
+    match value
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(']'))
+    {
+        Some(items) => FrontMatterValue::List(
+            items
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| FrontMatterValue::Text(unquote(item)))
+                .collect(),
+        ),
+        None => FrontMatterValue::Text(unquote(value)),
+    }
+}
+
+fn unquote(text: &str) -> String {
+    for quote in ['"', '\''] {
+        if let Some(text) = text
+            .strip_prefix(quote)
+            .and_then(|text| text.strip_suffix(quote))
+        {
+            return text.to_string();
+        }
+    }
+    text.to_string()
+}
+
+/// Whether a value should be shown as a link.
+pub fn is_url(text: &str) -> bool {
+    (text.starts_with("https://") || text.starts_with("http://"))
+        && !text.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn text(text: &str) -> FrontMatterValue {
+        FrontMatterValue::Text(text.to_string())
+    }
+
+    #[test]
+    fn test_parse_yaml_front_matter() {
+        let yaml = "\
+title: \"Release notes\"
+date: 2024-03-05
+tags: [rust, 'gpui']
+authors:
+  - Ada
+  - name: Grace
+    url: https://example.com
+links:
+- https://zed.dev
+build:
+  os: linux
+  targets:
+    arch: x86_64
+summary: |
+  First line
+  Second line
+";
+        let front_matter = FrontMatter::parse(MetadataBlockKind::YamlStyle, yaml, 0..10);
+        assert_eq!(
+            front_matter.entries,
+            vec![
+                ("title".to_string(), text("Release notes")),
+                ("date".to_string(), text("2024-03-05")),
+                (
+                    "tags".to_string(),
+                    FrontMatterValue::List(vec![text("rust"), text("gpui")])
+                ),
+                (
+                    "authors".to_string(),
+                    FrontMatterValue::List(vec![
+                        text("Ada"),
+                        FrontMatterValue::Map(vec![
+                            ("name".to_string(), text("Grace")),
+                            ("url".to_string(), text("https://example.com")),
+                        ]),
+                    ])
+                ),
+                (
+                    "links".to_string(),
+                    FrontMatterValue::List(vec![text("https://zed.dev")])
+                ),
+                (
+                    "build".to_string(),
+                    FrontMatterValue::Map(vec![
+                        ("os".to_string(), text("linux")),
+                        (
+                            "targets".to_string(),
+                            FrontMatterValue::Map(vec![("arch".to_string(), text("x86_64"))])
+                        ),
+                    ])
+                ),
+                ("summary".to_string(), text("First line\nSecond line")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_toml_front_matter() {
+        let toml = "title = \"Notes\"\ntags = [\"a\", \"b\"]\n\n[extra]\ndraft = true\n";
+        let front_matter = FrontMatter::parse(MetadataBlockKind::PlusesStyle, toml, 0..10);
+        assert_eq!(
+            front_matter.entries,
+            vec![
+                ("title".to_string(), text("Notes")),
+                (
+                    "tags".to_string(),
+                    FrontMatterValue::List(vec![text("a"), text("b")])
+                ),
+                (
+                    "extra".to_string(),
+                    FrontMatterValue::Map(vec![("draft".to_string(), text("true"))])
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,788 @@
+use super::markdown_elements::{
+    HeadingLevel, Link, MarkdownHighlight, OutlineEntry, ParsedMarkdown, ParsedMarkdownCodeBlock,
+    ParsedMarkdownElement, ParsedMarkdownImage, ParsedMarkdownListItem, ParsedMarkdownListItemType,
+    ParsedMarkdownTable, ParsedMarkdownTableAlignment, ParsedMarkdownText,
+};
+use super::markdown_renderer::LinkPolicy;
+use base64::Engine as _;
+use gpui::FontWeight;
+use serde::Serialize;
+use std::{fmt::Write as _, ops::Range, path::Path};
+
+/// Styles the exported document like the preview: a readable column of
+/// text, bordered tables and tinted code blocks.
+const STYLESHEET: &str = "\
+body { margin: 0; background: #ffffff; color: #1f2328; }
+.markdown-body { box-sizing: border-box; max-width: 880px; margin: 0 auto; padding: 32px 16px; font: 16px/1.5 -apple-system, BlinkMacSystemFont, \"Segoe UI\", Helvetica, Arial, sans-serif; }
+h1, h2, h3, h4, h5, h6 { margin: 24px 0 16px; font-weight: 600; line-height: 1.25; }
+h1, h2 { padding-bottom: 0.3em; border-bottom: 1px solid #d1d9e0; }
+h1 { font-size: 2em; } h2 { font-size: 1.5em; } h3 { font-size: 1.25em; }
+h4 { font-size: 1em; } h5 { font-size: 0.875em; } h6 { font-size: 0.85em; color: #59636e; }
+p, blockquote, ul, ol, table, pre { margin: 0 0 16px; }
+a { color: #0969da; text-decoration: none; } a:hover { text-decoration: underline; }
+blockquote { padding: 0 1em; color: #59636e; border-left: 0.25em solid #d1d9e0; }
+code { padding: 0.2em 0.4em; font: 85% ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; background: #f6f8fa; border-radius: 6px; }
+pre { padding: 16px; overflow: auto; background: #f6f8fa; border-radius: 6px; line-height: 1.45; }
+pre code { padding: 0; font-size: 85%; background: transparent; }
+table { border-collapse: collapse; }
+th, td { padding: 6px 13px; border: 1px solid #d1d9e0; }
+th { font-weight: 600; } tr:nth-child(2n) { background: #f6f8fa; }
+hr { height: 0.25em; margin: 24px 0; padding: 0; background: #d1d9e0; border: 0; }
+img { max-width: 100%; }
+mark { background: #fff8c5; }
+.task-list-item { list-style-type: none; }
+.task-list-item input { margin: 0 0.2em 0.25em -1.4em; vertical-align: middle; }
+.table-of-contents { margin: 0 0 24px; padding: 8px 16px; border: 1px solid #d1d9e0; border-radius: 6px; }
+.table-of-contents ol { margin: 0; padding-left: 1.5em; }
+";
+
+/// How [`export_html`] writes the document.
+#[derive(Debug, Default, Clone)]
+pub struct HtmlExportOptions {
+    /// The title of the page. Falls back to the text of the first heading.
+    pub title: Option<String>,
+    /// Embeds images that are files on disk as `data:` URLs, so that the page
+    /// doesn't depend on them. Web images always keep their URLs.
+    pub inline_local_images: bool,
+    /// Writes the styles of code blocks on the elements themselves, for HTML
+    /// that's pasted somewhere without the stylesheet.
+    pub inline_styles: bool,
+    /// Starts the page with a table of contents linking to the headings
+    /// within these levels.
+    pub table_of_contents: Option<TocLevels>,
+}
+
+/// The levels of the headings a table of contents lists, from 1 to 6, like
+/// 2 to 4 to leave out the title and the smallest headings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TocLevels {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl Default for TocLevels {
+    fn default() -> Self {
+        Self { min: 1, max: 6 }
+    }
+}
+
+/// A heading in a table of contents, with the headings of its section.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    /// The `id` of the heading in the exported page.
+    pub slug: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// The styles of a code block's `<pre>` and `<code>` when they're written
+/// inline, matching the stylesheet.
+const PRE_STYLE: &str =
+    "padding: 16px; overflow: auto; background: #f6f8fa; border-radius: 6px; line-height: 1.45";
+const CODE_STYLE: &str =
+    "font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; font-size: 85%";
+
+/// Converts a parsed document into a standalone HTML5 page, styled with a
+/// small embedded stylesheet.
+pub fn export_html(parsed: &ParsedMarkdown, options: &HtmlExportOptions) -> String {
+    let title = options.title.clone().unwrap_or_else(|| {
+        parsed
+            .children
+            .iter()
+            .find_map(|block| match block {
+                ParsedMarkdownElement::Heading(heading) => {
+                    Some(heading.contents.contents.to_string())
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| "Document".to_string())
+    });
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    writeln!(html, "<title>{}</title>", escape(&title)).ok();
+    writeln!(html, "<style>\n{}</style>", STYLESHEET).ok();
+    html.push_str("</head>\n<body>\n<article class=\"markdown-body\">\n");
+    if let Some(levels) = options.table_of_contents {
+        let entries = table_of_contents(parsed, levels);
+        if !entries.is_empty() {
+            html.push_str("<nav class=\"table-of-contents\">\n");
+            write_toc(&entries, &mut html);
+            html.push_str("</nav>\n");
+        }
+    }
+    write_blocks(&parsed.children, options, &mut html);
+    html.push_str("</article>\n</body>\n</html>\n");
+    html
+}
+
+/// The document's headings within `levels`, each nested under the last
+/// heading of a higher level before it.
+pub fn table_of_contents(parsed: &ParsedMarkdown, levels: TocLevels) -> Vec<TocEntry> {
+    let headings: Vec<_> = parsed
+        .outline()
+        .into_iter()
+        .map(|entry| (level_number(entry.level), entry))
+        .filter(|(level, _)| (levels.min..=levels.max).contains(level))
+        .collect();
+
+    fn nest(headings: &[(u8, OutlineEntry)]) -> Vec<TocEntry> {
+        let mut entries = Vec::new();
+        let mut ix = 0;
+        while let Some((level, heading)) = headings.get(ix) {
+            let section_len = headings[ix + 1..]
+                .iter()
+                .take_while(|(child_level, _)| child_level > level)
+                .count();
+            entries.push(TocEntry {
+                level: *level,
+                text: heading.text.to_string(),
+                slug: heading.slug.clone(),
+                children: nest(&headings[ix + 1..ix + 1 + section_len]),
+            });
+            ix += 1 + section_len;
+        }
+        entries
+    }
+    nest(&headings)
+}
+
+/// The [`table_of_contents`] as JSON, for static site generators to build
+/// their navigation from.
+pub fn export_toc_json(parsed: &ParsedMarkdown, levels: TocLevels) -> String {
+    serde_json::to_string_pretty(&table_of_contents(parsed, levels)).unwrap_or_default()
+}
+
+fn write_toc(entries: &[TocEntry], html: &mut String) {
+    html.push_str("<ol>\n");
+    for entry in entries {
+        write!(
+            html,
+            "<li><a href=\"#{}\">{}</a>",
+            escape(&entry.slug),
+            escape(&entry.text)
+        )
+        .ok();
+        if !entry.children.is_empty() {
+            html.push('\n');
+            write_toc(&entry.children, html);
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ol>\n");
+}
+
+/// Converts the blocks that `selection`, a range of the source, touches into
+/// a fragment of HTML, to paste into rich text editors. Blocks are converted
+/// whole, and code blocks keep their look with inline styles.
+pub fn export_html_fragment(
+    parsed: &ParsedMarkdown,
+    selection: Range<usize>,
+    options: &HtmlExportOptions,
+) -> String {
+    let touches = |block: &ParsedMarkdownElement| {
+        let range = block.source_range();
+        range.start < selection.end && selection.start < range.end
+    };
+    let Some(start) = parsed.children.iter().position(touches) else {
+        return String::new();
+    };
+    let len = parsed.children[start..]
+        .iter()
+        .take_while(|block| touches(block))
+        .count();
+
+    let options = HtmlExportOptions {
+        inline_styles: true,
+        ..options.clone()
+    };
+    let mut html = String::new();
+    write_blocks(&parsed.children[start..start + len], &options, &mut html);
+    html
+}
+
+fn write_blocks(blocks: &[ParsedMarkdownElement], options: &HtmlExportOptions, html: &mut String) {
+    let mut ix = 0;
+    while ix < blocks.len() {
+        if blocks[ix].is_list_item() {
+            // Lists are flattened into a run of items, with nesting kept as
+            // each item's depth.
+            let run_len = blocks[ix..]
+                .iter()
+                .take_while(|block| block.is_list_item())
+                .count();
+            let items: Vec<_> = blocks[ix..ix + run_len]
+                .iter()
+                .filter_map(|block| match block {
+                    ParsedMarkdownElement::ListItem(item) => Some(item),
+                    _ => None,
+                })
+                .collect();
+            write_list(&items, options, html);
+            ix += run_len;
+        } else {
+            write_block(&blocks[ix], options, html);
+            ix += 1;
+        }
+    }
+}
+
+fn write_block(block: &ParsedMarkdownElement, options: &HtmlExportOptions, html: &mut String) {
+    match block {
+        ParsedMarkdownElement::Heading(heading) => {
+            let level = level_number(heading.level);
+            write!(html, "<h{} id=\"{}\">", level, escape(&heading.slug)).ok();
+            write_text(&heading.contents, html);
+            writeln!(html, "</h{}>", level).ok();
+        }
+        ParsedMarkdownElement::ListItem(item) => write_list(&[item], options, html),
+        ParsedMarkdownElement::Table(table) => write_table(table, html),
+        ParsedMarkdownElement::BlockQuote(block_quote) => {
+            html.push_str("<blockquote>\n");
+            write_blocks(&block_quote.children, options, html);
+            html.push_str("</blockquote>\n");
+        }
+        ParsedMarkdownElement::CodeBlock(code_block) => write_code_block(code_block, options, html),
+        ParsedMarkdownElement::Image(image) => {
+            html.push_str("<p>");
+            write_image(image, options, html);
+            html.push_str("</p>\n");
+        }
+        ParsedMarkdownElement::Paragraph(text) => {
+            html.push_str("<p>");
+            write_text(text, html);
+            for (ix, image) in text.images.iter().enumerate() {
+                if ix > 0 {
+                    html.push(' ');
+                }
+                let target = image
+                    .target
+                    .as_ref()
+                    .filter(|target| LinkPolicy::default().allows(target));
+                if let Some(target) = target {
+                    write!(html, "<a href=\"{}\">", escape(&link_href(target))).ok();
+                }
+                write_image(image, options, html);
+                if target.is_some() {
+                    html.push_str("</a>");
+                }
+            }
+            html.push_str("</p>\n");
+        }
+        ParsedMarkdownElement::HorizontalRule(_) => html.push_str("<hr>\n"),
+    }
+}
+
+/// Writes a run of list items, nesting a list inside the previous item
+/// whenever the depth increases.
+fn write_list(items: &[&ParsedMarkdownListItem], options: &HtmlExportOptions, html: &mut String) {
+    // The depth and closing tag of each list that's open.
+    let mut open_lists: Vec<(u16, &str)> = Vec::new();
+    for item in items {
+        let tag = match item.item_type {
+            ParsedMarkdownListItemType::Ordered(_) => "ol",
+            _ => "ul",
+        };
+
+        while open_lists
+            .last()
+            .map_or(false, |(depth, _)| *depth > item.depth)
+        {
+            let (_, tag) = open_lists.pop().unwrap();
+            writeln!(html, "</li>\n</{}>", tag).ok();
+        }
+        match open_lists.last() {
+            Some((depth, open_tag)) if *depth == item.depth && *open_tag == tag => {
+                html.push_str("</li>\n");
+            }
+            Some((depth, open_tag)) if *depth == item.depth => {
+                writeln!(html, "</li>\n</{}>", open_tag).ok();
+                open_lists.pop();
+                open_list(item, tag, html);
+                open_lists.push((item.depth, tag));
+            }
+            _ => {
+                open_list(item, tag, html);
+                open_lists.push((item.depth, tag));
+            }
+        }
+
+        match item.item_type {
+            ParsedMarkdownListItemType::Task(checked, _) => {
+                html.push_str("<li class=\"task-list-item\"><input type=\"checkbox\" disabled");
+                if checked {
+                    html.push_str(" checked");
+                }
+                html.push_str("> ");
+            }
+            _ => html.push_str("<li>"),
+        }
+
+        // Items of tight lists hold their text directly, the way Markdown
+        // renderers write them.
+        match item.content.split_first() {
+            Some((ParsedMarkdownElement::Paragraph(text), rest)) => {
+                write_text(text, html);
+                if !rest.is_empty() {
+                    html.push('\n');
+                    write_blocks(rest, options, html);
+                }
+            }
+            _ => write_blocks(&item.content, options, html),
+        }
+    }
+
+    while let Some((_, tag)) = open_lists.pop() {
+        writeln!(html, "</li>\n</{}>", tag).ok();
+    }
+}
+
+fn open_list(item: &ParsedMarkdownListItem, tag: &str, html: &mut String) {
+    match item.item_type {
+        ParsedMarkdownListItemType::Ordered(start) if start != 1 => {
+            writeln!(html, "<ol start=\"{}\">", start).ok();
+        }
+        _ => {
+            writeln!(html, "<{}>", tag).ok();
+        }
+    }
+}
+
+fn write_table(table: &ParsedMarkdownTable, html: &mut String) {
+    let write_row = |cells: &[ParsedMarkdownText], tag: &str, html: &mut String| {
+        html.push_str("<tr>");
+        for (column, cell) in cells.iter().enumerate() {
+            let alignment = match table.column_alignments.get(column) {
+                Some(ParsedMarkdownTableAlignment::Left) => Some("left"),
+                Some(ParsedMarkdownTableAlignment::Center) => Some("center"),
+                Some(ParsedMarkdownTableAlignment::Right) => Some("right"),
+                Some(ParsedMarkdownTableAlignment::None) | None => None,
+            };
+            match alignment {
+                Some(alignment) => {
+                    write!(html, "<{} style=\"text-align: {}\">", tag, alignment).ok()
+                }
+                None => write!(html, "<{}>", tag).ok(),
+            };
+            write_text(cell, html);
+            write!(html, "</{}>", tag).ok();
+        }
+        html.push_str("</tr>\n");
+    };
+
+    html.push_str("<table>\n<thead>\n");
+    write_row(&table.header.children, "th", html);
+    html.push_str("</thead>\n");
+    if !table.body.is_empty() {
+        html.push_str("<tbody>\n");
+        for row in &table.body {
+            write_row(&row.children, "td", html);
+        }
+        html.push_str("</tbody>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+fn write_code_block(
+    code_block: &ParsedMarkdownCodeBlock,
+    options: &HtmlExportOptions,
+    html: &mut String,
+) {
+    html.push_str("<pre");
+    if options.inline_styles {
+        write!(html, " style=\"{}\"", PRE_STYLE).ok();
+    }
+    html.push_str("><code");
+    if let Some(language) = code_block.language.as_deref().filter(|l| !l.is_empty()) {
+        write!(html, " class=\"language-{}\"", escape(language)).ok();
+    }
+    if options.inline_styles {
+        write!(html, " style=\"{}\"", CODE_STYLE).ok();
+    }
+    html.push('>');
+    html.push_str(&escape(&code_block.contents));
+    html.push_str("</code></pre>\n");
+}
+
+fn write_image(image: &ParsedMarkdownImage, options: &HtmlExportOptions, html: &mut String) {
+    let src = match &image.link {
+        Some(Link::Path { path, .. }) if options.inline_local_images => inline_image(path)
+            .unwrap_or_else(|| image.link.as_ref().map(link_href).unwrap_or_default()),
+        Some(link) => link_href(link),
+        None => String::new(),
+    };
+
+    write!(
+        html,
+        "<img src=\"{}\" alt=\"{}\"",
+        escape(&src),
+        escape(&image.alt_text)
+    )
+    .ok();
+    if !image.title.is_empty() {
+        write!(html, " title=\"{}\"", escape(&image.title)).ok();
+    }
+    html.push('>');
+}
+
+/// Reads the image at `path` into a `data:` URL.
+fn inline_image(path: &Path) -> Option<String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            log::error!("failed to inline image {}: {}", path.display(), error);
+            return None;
+        }
+    };
+
+    let is_svg = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("svg"));
+    let media_type = if is_svg {
+        "image/svg+xml"
+    } else {
+        image::guess_format(&bytes).ok()?.to_mime_type()
+    };
+    Some(format!(
+        "data:{};base64,{}",
+        media_type,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+/// Links to files keep the path written in the document, so the page works
+/// wherever it's opened from next to them.
+fn link_href(link: &Link) -> String {
+    match link {
+        Link::Web { url } => url.clone(),
+        Link::Path { display_path, .. } => display_path.to_string_lossy().into_owned(),
+        Link::Data { uri, .. } => uri.to_string(),
+        Link::Fragment { fragment } => format!("#{}", fragment),
+    }
+}
+
+/// Writes the inline contents of `text`, with its styles, code spans and
+/// links as HTML elements.
+fn write_text(text: &ParsedMarkdownText, html: &mut String) {
+    let contents: &str = &text.contents;
+
+    // Styles and regions overlap freely, so the text is split wherever any
+    // of them starts or ends and each piece is wrapped on its own.
+    let mut boundaries = vec![0, contents.len()];
+    for (range, _) in &text.highlights {
+        boundaries.extend([range.start, range.end]);
+    }
+    for range in &text.region_ranges {
+        boundaries.extend([range.start, range.end]);
+    }
+    boundaries.retain(|ix| *ix <= contents.len() && contents.is_char_boundary(*ix));
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut open_link: Option<usize> = None;
+    for piece in boundaries.windows(2) {
+        let (start, end) = (piece[0], piece[1]);
+        let region_ix = text
+            .region_ranges
+            .iter()
+            .position(|range| range.start <= start && end <= range.end);
+        let region = region_ix.and_then(|ix| text.regions.get(ix));
+
+        // Pages with `javascript:` links run them, so only the links the
+        // preview would open are exported as links.
+        let link = region
+            .and_then(|region| region.link.as_ref())
+            .filter(|link| LinkPolicy::default().allows(link));
+        let link_ix = region_ix.filter(|_| link.is_some());
+        if link_ix != open_link {
+            if open_link.is_some() {
+                html.push_str("</a>");
+            }
+            if let Some(link) = link {
+                write!(html, "<a href=\"{}\">", escape(&link_href(link))).ok();
+            }
+            open_link = link_ix;
+        }
+
+        let mut closing_tags = Vec::new();
+        let mut open = |tag: &'static str, html: &mut String| {
+            write!(html, "<{}>", tag).ok();
+            closing_tags.push(tag);
+        };
+        if region.map_or(false, |region| region.code) {
+            open("code", html);
+        }
+        for (range, highlight) in &text.highlights {
+            if range.start > start || end > range.end {
+                continue;
+            }
+            let MarkdownHighlight::Style(style) = highlight else {
+                continue;
+            };
+            if style.weight > FontWeight::NORMAL {
+                open("strong", html);
+            }
+            if style.italic {
+                open("em", html);
+            }
+            if style.strikethrough {
+                open("s", html);
+            }
+            if style.underline && link_ix.is_none() {
+                open("u", html);
+            }
+            if style.highlight {
+                open("mark", html);
+            }
+        }
+
+        html.push_str(&escape(&contents[start..end]));
+        for tag in closing_tags.iter().rev() {
+            write!(html, "</{}>", tag).ok();
+        }
+    }
+
+    if open_link.is_some() {
+        html.push_str("</a>");
+    }
+}
+
+fn level_number(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use pretty_assertions::assert_eq;
+
+    const EXAMPLE: &str = include_str!("../../assets/markdown/example.md");
+
+    /// Elements that HTML writes without a closing tag.
+    const VOID_ELEMENTS: &[&str] = &["br", "hr", "img", "input", "meta"];
+
+    /// Checks that every element in `html` is closed, in order, returning
+    /// the names of the elements that were opened.
+    fn check_nesting(html: &str) -> Vec<String> {
+        let mut opened = Vec::new();
+        let mut stack: Vec<String> = Vec::new();
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            let end = rest[start..].find('>').expect("unterminated tag") + start;
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+            if tag.starts_with('!') {
+                continue;
+            }
+
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(stack.pop().as_deref(), Some(name), "mismatched </{}>", name);
+            } else {
+                let name = tag.split_whitespace().next().unwrap().to_string();
+                if !VOID_ELEMENTS.contains(&name.as_str()) {
+                    stack.push(name.clone());
+                }
+                opened.push(name);
+            }
+
+            // Text in these elements isn't markup.
+            if tag == "style" {
+                rest = &rest[rest.find("</style>").unwrap()..];
+            }
+        }
+        assert!(stack.is_empty(), "unclosed elements: {:?}", stack);
+        opened
+    }
+
+    async fn export(text: &str) -> String {
+        let parsed = parse_markdown(text, None, None).await;
+        export_html(&parsed, &HtmlExportOptions::default())
+    }
+
+    fn body(html: &str) -> &str {
+        let start = html.find("<article class=\"markdown-body\">\n").unwrap();
+        let end = html.find("</article>").unwrap();
+        &html[start + "<article class=\"markdown-body\">\n".len()..end]
+    }
+
+    #[gpui::test]
+    async fn test_export_example_document() {
+        let parsed = parse_markdown(EXAMPLE, None, None).await;
+        let html = export_html(&parsed, &HtmlExportOptions::default());
+
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        let opened = check_nesting(&html);
+        for entry in parsed.outline() {
+            assert!(html.contains(&format!("id=\"{}\"", entry.slug)));
+        }
+        for tag in ["table", "blockquote", "pre", "ul", "ol"] {
+            assert!(opened.iter().any(|name| name == tag), "no <{}>", tag);
+        }
+    }
+
+    /// The values of every `name` attribute in `html`, in order.
+    fn attribute_values<'a>(html: &'a str, name: &str) -> Vec<&'a str> {
+        let prefix = format!(" {}=\"", name);
+        html.match_indices(&prefix)
+            .map(|(ix, _)| {
+                let value = &html[ix + prefix.len()..];
+                &value[..value.find('"').unwrap()]
+            })
+            .collect()
+    }
+
+    #[gpui::test]
+    async fn test_export_table_of_contents() {
+        let text = "# Title\n\n## Install & run\n\n### From source\n\n#### Deep\n\n\
+                    ##### Deeper\n\n## Usage\n\n## Usage\n";
+        let parsed = parse_markdown(text, None, None).await;
+        let levels = TocLevels { min: 2, max: 4 };
+        let options = HtmlExportOptions {
+            table_of_contents: Some(levels),
+            ..Default::default()
+        };
+        let html = export_html(&parsed, &options);
+        check_nesting(&html);
+
+        // The links go to the ids of the headings in the body, which are
+        // told apart even when their text is the same.
+        let toc_end = html.find("</nav>\n").unwrap() + "</nav>\n".len();
+        let (toc, rest) = html.split_at(toc_end);
+        let toc = &toc[toc.find("<nav").unwrap()..];
+        let ids = attribute_values(rest, "id");
+        let links: Vec<_> = attribute_values(toc, "href")
+            .into_iter()
+            .map(|href| href.strip_prefix('#').unwrap())
+            .collect();
+        assert_eq!(links, vec![ids[1], ids[2], ids[3], ids[5], ids[6]]);
+        assert_ne!(ids[5], ids[6]);
+        assert_eq!(
+            toc,
+            format!(
+                "<nav class=\"table-of-contents\">\n<ol>\n\
+                 <li><a href=\"#{}\">Install &amp; run</a>\n<ol>\n\
+                 <li><a href=\"#{}\">From source</a>\n<ol>\n\
+                 <li><a href=\"#{}\">Deep</a></li>\n</ol>\n</li>\n</ol>\n</li>\n\
+                 <li><a href=\"#{}\">Usage</a></li>\n\
+                 <li><a href=\"#{}\">Usage</a></li>\n</ol>\n</nav>\n",
+                ids[1], ids[2], ids[3], ids[5], ids[6]
+            )
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&export_toc_json(&parsed, levels)).unwrap();
+        assert_eq!(json.as_array().map(Vec::len), Some(3));
+        assert_eq!(json[0]["level"], 2);
+        assert_eq!(json[0]["children"][0]["children"][0]["text"], "Deep");
+        assert_eq!(json[2]["slug"], ids[6]);
+
+        // Every heading of a longer document is linked to.
+        let parsed = parse_markdown(EXAMPLE, None, None).await;
+        let options = HtmlExportOptions {
+            table_of_contents: Some(TocLevels::default()),
+            ..Default::default()
+        };
+        let html = export_html(&parsed, &options);
+        check_nesting(&html);
+        let toc_end = html.find("</nav>").unwrap();
+        let links = attribute_values(&html[..toc_end], "href");
+        assert_eq!(links.len(), parsed.outline().len());
+        let ids = attribute_values(&html[toc_end..], "id");
+        for link in links {
+            assert!(ids.contains(&link.strip_prefix('#').unwrap()), "{}", link);
+        }
+
+        // Documents without headings don't get one.
+        let html = export_html(&parse_markdown("Text\n", None, None).await, &options);
+        assert!(!html.contains("<nav"));
+    }
+
+    #[gpui::test]
+    async fn test_export_inline_text() {
+        let html = export("Some **bold** and `code` with a [link](https://zed.dev) & 1 < 2").await;
+        assert_eq!(
+            body(&html),
+            "<p>Some <strong>bold</strong> and <code>code</code> with a \
+             <a href=\"https://zed.dev\">link</a> &amp; 1 &lt; 2</p>\n"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_export_nested_lists() {
+        let html = export("1. one\n    - [x] done\n2. two\n").await;
+        check_nesting(&html);
+        assert_eq!(
+            body(&html),
+            "<ol>\n<li>one\
+             <ul>\n<li class=\"task-list-item\"><input type=\"checkbox\" disabled checked> done</li>\n</ul>\n\
+             </li>\n<li>two</li>\n</ol>\n"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_export_tables_and_code() {
+        let html = export("| a | b |\n|:--|--:|\n| 1 | 2 |\n\n```rust\nfn main() {}\n```\n").await;
+        assert_eq!(
+            body(&html),
+            "<table>\n<thead>\n\
+             <tr><th style=\"text-align: left\">a</th><th style=\"text-align: right\">b</th></tr>\n\
+             </thead>\n<tbody>\n\
+             <tr><td style=\"text-align: left\">1</td><td style=\"text-align: right\">2</td></tr>\n\
+             </tbody>\n</table>\n\
+             <pre><code class=\"language-rust\">fn main() {}</code></pre>\n"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_export_fragment() {
+        let text = "# Title\n\nSome *text*\n\n```rust\nfn main() {}\n```\n\nLast\n";
+        let parsed = parse_markdown(text, None, None).await;
+        let start = text.find("text").unwrap();
+        let end = text.find("main").unwrap();
+        let html = export_html_fragment(&parsed, start..end, &HtmlExportOptions::default());
+        check_nesting(&html);
+        assert_eq!(
+            html,
+            format!(
+                "<p>Some <em>text</em></p>\n\
+                 <pre style=\"{}\"><code class=\"language-rust\" style=\"{}\">fn main() {{}}</code></pre>\n",
+                PRE_STYLE, CODE_STYLE
+            )
+        );
+
+        assert_eq!(
+            export_html_fragment(
+                &parsed,
+                text.len()..text.len(),
+                &HtmlExportOptions::default()
+            ),
+            ""
+        );
+    }
+}
@@ -0,0 +1,1098 @@
+use super::{
+    markdown_elements::{
+        HeadingLevel, Link, LinkOrigin, MarkdownHighlight, MarkdownHighlightStyle,
+        ParsedMarkdownBlockQuote, ParsedMarkdownCodeBlock, ParsedMarkdownElement,
+        ParsedMarkdownHeading, ParsedMarkdownImage, ParsedMarkdownListItem,
+        ParsedMarkdownListItemType, ParsedMarkdownTable, ParsedMarkdownTableAlignment,
+        ParsedMarkdownTableRow, ParsedMarkdownText, ParsedRegion,
+    },
+    markdown_parser::heading_slug,
+};
+use gpui::FontWeight;
+use std::{ops::Range, path::PathBuf};
+use url::Url;
+
+/// Elements that are dropped along with everything in them.
+const DROPPED_ELEMENTS: &[&str] = &["script", "style", "head", "template", "noscript"];
+
+/// Elements that HTML writes without a closing tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements without a counterpart in Markdown that hold blocks rather than
+/// running text, so that the text in them becomes paragraphs of its own.
+const CONTAINER_ELEMENTS: &[&str] = &[
+    "html",
+    "body",
+    "div",
+    "section",
+    "article",
+    "main",
+    "header",
+    "footer",
+    "nav",
+    "aside",
+    "figure",
+    "figcaption",
+    "details",
+    "summary",
+    "center",
+    "dl",
+    "dt",
+    "dd",
+    "li",
+    "form",
+    "fieldset",
+    "address",
+];
+
+/// Converts a fragment of HTML into blocks, with source ranges offset by
+/// `offset` so that they point into the document the HTML is part of.
+///
+/// Only the elements Markdown has counterparts for are understood: headings,
+/// paragraphs, lists, code, block quotes, tables, links, images, emphasis and
+/// rules. Other elements contribute their text, and scripts and styles are
+/// dropped.
+pub fn parse_html(
+    html: &str,
+    offset: usize,
+    file_location_directory: Option<PathBuf>,
+    base_url: Option<Url>,
+) -> Vec<ParsedMarkdownElement> {
+    let nodes = build_tree(tokenize(html), html.len());
+    let converter = Converter {
+        file_location_directory,
+        base_url,
+        offset,
+    };
+    let mut blocks = Vec::new();
+    converter.convert_blocks(&nodes, &mut blocks);
+    blocks
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    End {
+        name: String,
+    },
+    Text(String),
+}
+
+fn tokenize(html: &str) -> Vec<(Token, Range<usize>)> {
+    let mut tokens = Vec::new();
+    let mut ix = 0;
+    while ix < html.len() {
+        let rest = &html[ix..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            ix = comment
+                .find("-->")
+                .map_or(html.len(), |end| ix + "<!--".len() + end + "-->".len());
+            continue;
+        }
+        // Doctypes and processing instructions.
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            ix = rest.find('>').map_or(html.len(), |end| ix + end + 1);
+            continue;
+        }
+
+        if let Some((token, len)) = parse_tag(rest) {
+            let range = ix..ix + len;
+            ix += len;
+            // The contents of scripts and styles aren't markup, and are
+            // dropped anyway.
+            if let Token::Start {
+                name,
+                self_closing: false,
+                ..
+            } = &token
+            {
+                if name == "script" || name == "style" {
+                    let closing_tag = format!("</{}", name);
+                    ix = html[ix..]
+                        .to_ascii_lowercase()
+                        .find(&closing_tag)
+                        .map_or(html.len(), |end| ix + end);
+                }
+            }
+            tokens.push((token, range));
+            continue;
+        }
+
+        // Text runs up to the next tag. A `<` that doesn't start one is text.
+        let first_len = rest.chars().next().map_or(1, char::len_utf8);
+        let end = rest[first_len..]
+            .find('<')
+            .map_or(html.len(), |end| ix + first_len + end);
+        tokens.push((Token::Text(decode_entities(&html[ix..end])), ix..end));
+        ix = end;
+    }
+    tokens
+}
+
+/// Parses the tag at the start of `text`, returning it and its length.
+fn parse_tag(text: &str) -> Option<(Token, usize)> {
+    if !text.starts_with('<') {
+        return None;
+    }
+    // A `>` in a quoted attribute value doesn't end the tag.
+    let mut quote = None;
+    let end = text.char_indices().skip(1).find_map(|(ix, c)| {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '>' => return Some(ix),
+            None => {}
+        }
+        None
+    })?;
+    let inner = &text[1..end];
+
+    if let Some(name) = inner.strip_prefix('/') {
+        let name = name.trim().to_ascii_lowercase();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        return Some((Token::End { name }, end + 1));
+    }
+
+    if !inner.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let name_end = inner
+        .find(|c: char| c.is_whitespace() || c == '/')
+        .unwrap_or(inner.len());
+    let self_closing = inner.trim_end().ends_with('/');
+    let attributes = inner[name_end..].trim_end().trim_end_matches('/');
+    Some((
+        Token::Start {
+            name: inner[..name_end].to_ascii_lowercase(),
+            attributes: parse_attributes(attributes),
+            self_closing,
+        },
+        end + 1,
+    ))
+}
+
+fn parse_attributes(text: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after_equals) = rest.strip_prefix('=') {
+            let after_equals = after_equals.trim_start();
+            let (raw_value, remaining) = match after_equals.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let quoted = &after_equals[1..];
+                    match quoted.find(quote) {
+                        Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                        None => (quoted, ""),
+                    }
+                }
+                _ => {
+                    let end = after_equals
+                        .find(char::is_whitespace)
+                        .unwrap_or(after_equals.len());
+                    (&after_equals[..end], &after_equals[end..])
+                }
+            };
+            value = decode_entities(raw_value);
+            rest = remaining;
+        } else if name_end == 0 {
+            // A stray `/`.
+            rest = &rest[1..];
+        }
+
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+        rest = rest.trim_start();
+    }
+    attributes
+}
+
+/// Replaces character references like `&amp;` and `&#x41;` with the
+/// characters they stand for. Ones that aren't known are left as they are.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| decode_entity(&rest[1..1 + end]).map(|c| (c, end + 2)));
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bull" => '•',
+        "middot" => '·',
+        "times" => '×',
+        _ => return None,
+    })
+}
+
+#[derive(Debug)]
+enum Node {
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<Node>,
+        source_range: Range<usize>,
+    },
+    Text(String, Range<usize>),
+}
+
+/// An element whose end tag hasn't been reached yet.
+struct OpenElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+    start: usize,
+}
+
+/// Nests the tokens into elements, forgiving the way HTML usually is: end
+/// tags can be left out, and ones that weren't opened are ignored.
+fn build_tree(tokens: Vec<(Token, Range<usize>)>, len: usize) -> Vec<Node> {
+    fn close(stack: &mut Vec<OpenElement>, roots: &mut Vec<Node>, end: usize) {
+        let Some(element) = stack.pop() else {
+            return;
+        };
+        let node = Node::Element {
+            name: element.name,
+            attributes: element.attributes,
+            children: element.children,
+            source_range: element.start..end,
+        };
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+
+    let mut stack: Vec<OpenElement> = Vec::new();
+    let mut roots = Vec::new();
+    for (token, range) in tokens {
+        match token {
+            Token::Text(text) => {
+                let node = Node::Text(text, range);
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(node),
+                    None => roots.push(node),
+                }
+            }
+            Token::Start {
+                name,
+                attributes,
+                self_closing,
+            } => {
+                // Starting an item or a cell ends the one before it, inside
+                // the same list or row.
+                if let Some(ix) = implicitly_closed(&stack, &name) {
+                    while stack.len() > ix {
+                        close(&mut stack, &mut roots, range.start);
+                    }
+                }
+                let is_void = self_closing || VOID_ELEMENTS.contains(&name.as_str());
+                stack.push(OpenElement {
+                    name,
+                    attributes,
+                    children: Vec::new(),
+                    start: range.start,
+                });
+                if is_void {
+                    close(&mut stack, &mut roots, range.end);
+                }
+            }
+            Token::End { name } => {
+                if let Some(ix) = stack.iter().rposition(|element| element.name == name) {
+                    while stack.len() > ix {
+                        close(&mut stack, &mut roots, range.end);
+                    }
+                }
+            }
+        }
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut roots, len);
+    }
+    roots
+}
+
+/// The index of the open element that starting a `name` element ends, if
+/// any.
+fn implicitly_closed(stack: &[OpenElement], name: &str) -> Option<usize> {
+    let (closed, scope): (&[&str], &[&str]) = match name {
+        "li" => (&["li"], &["ul", "ol"]),
+        "td" | "th" => (&["td", "th"], &["tr", "table"]),
+        "tr" => (&["tr"], &["table", "thead", "tbody", "tfoot"]),
+        "thead" | "tbody" | "tfoot" => (&["thead", "tbody", "tfoot"], &["table"]),
+        "dt" | "dd" => (&["dt", "dd"], &["dl"]),
+        name if is_block(name) => (&["p"], &["div", "blockquote", "li", "td", "th"]),
+        _ => return None,
+    };
+    let ix = stack.iter().rposition(|element| {
+        closed.contains(&element.name.as_str()) || scope.contains(&element.name.as_str())
+    })?;
+    closed.contains(&stack[ix].name.as_str()).then_some(ix)
+}
+
+fn is_block(name: &str) -> bool {
+    matches!(
+        name,
+        "h1" | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "p"
+            | "ul"
+            | "ol"
+            | "pre"
+            | "blockquote"
+            | "table"
+            | "hr"
+            | "img"
+    ) || CONTAINER_ELEMENTS.contains(&name)
+        || DROPPED_ELEMENTS.contains(&name)
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(attribute, _)| attribute == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Running text as it's collected from inline elements, along with the
+/// styles of the elements it's currently in.
+#[derive(Default)]
+struct InlineText {
+    contents: String,
+    highlights: Vec<(Range<usize>, MarkdownHighlight)>,
+    region_ranges: Vec<Range<usize>>,
+    regions: Vec<ParsedRegion>,
+    source_range: Option<Range<usize>>,
+    style: MarkdownHighlightStyle,
+    link: Option<Link>,
+    code: bool,
+}
+
+impl InlineText {
+    /// Appends `text` with whitespace collapsed, the way browsers show it.
+    fn push_text(&mut self, text: &str, source_range: Range<usize>) {
+        let start = self.contents.len();
+        for c in text.chars() {
+            if c.is_ascii_whitespace() {
+                if !self.contents.is_empty() && !self.contents.ends_with([' ', '\n']) {
+                    self.contents.push(' ');
+                }
+            } else if c == '\u{a0}' {
+                self.contents.push(' ');
+            } else {
+                self.contents.push(c);
+            }
+        }
+        self.styled(start..self.contents.len(), source_range);
+    }
+
+    fn push_line_break(&mut self, source_range: Range<usize>) {
+        if !self.contents.is_empty() {
+            let start = self.contents.len();
+            self.contents.push('\n');
+            self.styled(start..self.contents.len(), source_range);
+        }
+    }
+
+    /// Records the current styles for the text at `range`.
+    fn styled(&mut self, range: Range<usize>, source_range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.source_range = Some(match self.source_range.take() {
+            Some(existing) => existing.start.min(source_range.start)..source_range.end,
+            None => source_range,
+        });
+
+        if self.code || self.link.is_some() {
+            self.region_ranges.push(range.clone());
+            self.regions.push(ParsedRegion {
+                code: self.code,
+                link: self.link.clone(),
+                origin: LinkOrigin::Explicit,
+            });
+        }
+
+        let mut style = self.style.clone();
+        if self.link.is_some() {
+            style.underline = true;
+        }
+        if style == MarkdownHighlightStyle::default() {
+            return;
+        }
+        let highlight = MarkdownHighlight::Style(style);
+        match self.highlights.last_mut() {
+            Some((last_range, last_highlight))
+                if last_range.end == range.start && *last_highlight == highlight =>
+            {
+                last_range.end = range.end;
+            }
+            _ => self.highlights.push((range, highlight)),
+        }
+    }
+
+    /// Takes the text collected so far, keeping the styles that apply to
+    /// what comes next. Returns `None` if there's no text.
+    fn take_text(&mut self) -> Option<ParsedMarkdownText> {
+        let mut contents = std::mem::take(&mut self.contents);
+        let highlights = std::mem::take(&mut self.highlights);
+        let region_ranges = std::mem::take(&mut self.region_ranges);
+        let regions = std::mem::take(&mut self.regions);
+        let source_range = self.source_range.take()?;
+
+        // Trailing whitespace is dropped, along with the styles of it.
+        let len = contents.trim_end().len();
+        if len == 0 {
+            return None;
+        }
+        contents.truncate(len);
+        let highlights = highlights
+            .into_iter()
+            .map(|(range, highlight)| (range.start..range.end.min(len), highlight))
+            .filter(|(range, _)| !range.is_empty())
+            .collect();
+        let (region_ranges, regions) = region_ranges
+            .into_iter()
+            .zip(regions)
+            .map(|(range, region)| (range.start..range.end.min(len), region))
+            .filter(|(range, _)| !range.is_empty())
+            .unzip();
+
+        Some(ParsedMarkdownText {
+            source_range,
+            contents: contents.into(),
+            highlights,
+            region_ranges,
+            regions,
+            images: Vec::new(),
+            continued: false,
+        })
+    }
+
+    /// Takes the text collected so far, even if there's none, like for an
+    /// empty table cell.
+    fn into_text(mut self, source_range: Range<usize>) -> ParsedMarkdownText {
+        self.take_text().unwrap_or_else(|| ParsedMarkdownText {
+            source_range,
+            contents: Default::default(),
+            highlights: Vec::new(),
+            region_ranges: Vec::new(),
+            regions: Vec::new(),
+            images: Vec::new(),
+            continued: false,
+        })
+    }
+}
+
+struct Converter {
+    file_location_directory: Option<PathBuf>,
+    base_url: Option<Url>,
+    offset: usize,
+}
+
+impl Converter {
+    fn link(&self, url: &str) -> Option<Link> {
+        Link::resolve(
+            self.file_location_directory.clone(),
+            self.base_url.as_ref(),
+            url.to_string(),
+        )
+    }
+
+    fn range(&self, range: &Range<usize>) -> Range<usize> {
+        range.start + self.offset..range.end + self.offset
+    }
+
+    /// Converts nodes that may mix blocks with running text, which becomes
+    /// paragraphs between the blocks.
+    fn convert_blocks(&self, nodes: &[Node], blocks: &mut Vec<ParsedMarkdownElement>) {
+        let mut text = InlineText::default();
+        for node in nodes {
+            match node {
+                Node::Element { name, .. } if is_block(name) => {
+                    flush_paragraph(&mut text, blocks);
+                    self.convert_block(node, blocks);
+                }
+                node => self.convert_inline(node, &mut text, blocks),
+            }
+        }
+        flush_paragraph(&mut text, blocks);
+    }
+
+    fn convert_block(&self, node: &Node, blocks: &mut Vec<ParsedMarkdownElement>) {
+        let Node::Element {
+            name,
+            attributes,
+            children,
+            source_range,
+        } = node
+        else {
+            return;
+        };
+        let source_range = self.range(source_range);
+        match name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = match name.as_str() {
+                    "h1" => HeadingLevel::H1,
+                    "h2" => HeadingLevel::H2,
+                    "h3" => HeadingLevel::H3,
+                    "h4" => HeadingLevel::H4,
+                    "h5" => HeadingLevel::H5,
+                    _ => HeadingLevel::H6,
+                };
+                let mut text = InlineText::default();
+                let mut images = Vec::new();
+                for child in children {
+                    self.convert_inline(child, &mut text, &mut images);
+                }
+                if let Some(contents) = text.take_text() {
+                    blocks.push(ParsedMarkdownElement::Heading(ParsedMarkdownHeading {
+                        source_range,
+                        level,
+                        // Made unique along with the rest of the document's.
+                        slug: heading_slug(&contents.contents),
+                        contents,
+                    }));
+                }
+                blocks.extend(images);
+            }
+            "ul" | "ol" => self.convert_list(node, 1, blocks),
+            "pre" => blocks.push(ParsedMarkdownElement::CodeBlock(self.convert_code_block(
+                attributes,
+                children,
+                source_range,
+            ))),
+            "blockquote" => {
+                let mut quoted = Vec::new();
+                self.convert_blocks(children, &mut quoted);
+                blocks.push(ParsedMarkdownElement::BlockQuote(
+                    ParsedMarkdownBlockQuote {
+                        source_range,
+                        children: quoted,
+                    },
+                ));
+            }
+            "table" => blocks.extend(
+                self.convert_table(children, source_range)
+                    .map(ParsedMarkdownElement::Table),
+            ),
+            "hr" => blocks.push(ParsedMarkdownElement::HorizontalRule(source_range)),
+            "img" => blocks.push(ParsedMarkdownElement::Image(
+                self.convert_image(attributes, source_range),
+            )),
+            name if DROPPED_ELEMENTS.contains(&name) => {}
+            _ => self.convert_blocks(children, blocks),
+        }
+    }
+
+    /// Adds the text in `node` to `text`. Images can't be part of text, so
+    /// they end it and follow it as blocks of their own.
+    fn convert_inline(
+        &self,
+        node: &Node,
+        text: &mut InlineText,
+        blocks: &mut Vec<ParsedMarkdownElement>,
+    ) {
+        let (name, attributes, children, source_range) = match node {
+            Node::Text(contents, source_range) => {
+                text.push_text(contents, self.range(source_range));
+                return;
+            }
+            Node::Element {
+                name,
+                attributes,
+                children,
+                source_range,
+            } => (
+                name.as_str(),
+                attributes,
+                children,
+                self.range(source_range),
+            ),
+        };
+
+        let previous_style = text.style.clone();
+        let previous_link = text.link.clone();
+        let previous_code = text.code;
+        match name {
+            "br" => text.push_line_break(source_range),
+            "img" => {
+                flush_paragraph(text, blocks);
+                blocks.push(ParsedMarkdownElement::Image(
+                    self.convert_image(attributes, source_range),
+                ));
+            }
+            name if DROPPED_ELEMENTS.contains(&name) => {}
+            "strong" | "b" => text.style.weight = FontWeight::BOLD,
+            "em" | "i" | "cite" | "var" => text.style.italic = true,
+            "s" | "del" | "strike" => text.style.strikethrough = true,
+            "u" | "ins" => text.style.underline = true,
+            "mark" => text.style.highlight = true,
+            "code" | "kbd" | "samp" | "tt" => text.code = true,
+            "a" => {
+                if let Some(href) = attribute(attributes, "href") {
+                    text.link = self.link(href);
+                }
+            }
+            _ => {}
+        }
+        for child in children {
+            self.convert_inline(child, text, blocks);
+        }
+        text.style = previous_style;
+        text.link = previous_link;
+        text.code = previous_code;
+    }
+
+    /// Converts the items of a list, followed by the items of the lists
+    /// nested in each, the way the Markdown parser orders them.
+    fn convert_list(&self, list: &Node, depth: u16, blocks: &mut Vec<ParsedMarkdownElement>) {
+        let Node::Element {
+            name,
+            attributes,
+            children,
+            ..
+        } = list
+        else {
+            return;
+        };
+        let mut number = (name == "ol").then(|| {
+            attribute(attributes, "start")
+                .and_then(|start| start.trim().parse().ok())
+                .unwrap_or(1)
+        });
+
+        for child in children {
+            let Node::Element {
+                name,
+                children: item_children,
+                source_range,
+                ..
+            } = child
+            else {
+                // The whitespace between items.
+                continue;
+            };
+            if name == "ul" || name == "ol" {
+                self.convert_list(child, depth + 1, blocks);
+                continue;
+            }
+            if name != "li" {
+                continue;
+            }
+
+            let mut content = Vec::new();
+            let mut nested = Vec::new();
+            let mut task = None;
+            let mut text = InlineText::default();
+            for item_child in item_children {
+                match item_child {
+                    Node::Element {
+                        name,
+                        attributes,
+                        source_range,
+                        ..
+                    } if name == "input"
+                        && attribute(attributes, "type") == Some("checkbox")
+                        && task.is_none()
+                        && content.is_empty()
+                        && text.contents.is_empty() =>
+                    {
+                        let checked = attribute(attributes, "checked").is_some();
+                        task = Some((checked, self.range(source_range)));
+                    }
+                    Node::Element { name, .. } if name == "ul" || name == "ol" => {
+                        flush_paragraph(&mut text, &mut content);
+                        self.convert_list(item_child, depth + 1, &mut nested);
+                    }
+                    Node::Element { name, .. } if is_block(name) => {
+                        flush_paragraph(&mut text, &mut content);
+                        self.convert_block(item_child, &mut content);
+                    }
+                    node => self.convert_inline(node, &mut text, &mut content),
+                }
+            }
+            flush_paragraph(&mut text, &mut content);
+
+            let item_type = match (task, number) {
+                (Some((checked, range)), _) => ParsedMarkdownListItemType::Task(checked, range),
+                (None, Some(number)) => ParsedMarkdownListItemType::Ordered(number),
+                (None, None) => ParsedMarkdownListItemType::Unordered,
+            };
+            if let Some(number) = &mut number {
+                *number += 1;
+            }
+            blocks.push(ParsedMarkdownElement::ListItem(ParsedMarkdownListItem {
+                source_range: self.range(source_range),
+                depth,
+                item_type,
+                content,
+            }));
+            blocks.extend(nested);
+        }
+    }
+
+    fn convert_code_block(
+        &self,
+        attributes: &[(String, String)],
+        children: &[Node],
+        source_range: Range<usize>,
+    ) -> ParsedMarkdownCodeBlock {
+        fn collect_code(nodes: &[Node], code: &mut String, language: &mut Option<String>) {
+            for node in nodes {
+                match node {
+                    Node::Text(text, _) => code.push_str(text),
+                    Node::Element { name, .. } if name == "br" => code.push('\n'),
+                    Node::Element {
+                        name,
+                        attributes,
+                        children,
+                        ..
+                    } => {
+                        if name == "code" && language.is_none() {
+                            *language = code_language(attributes);
+                        }
+                        collect_code(children, code, language);
+                    }
+                }
+            }
+        }
+
+        let mut code = String::new();
+        let mut language = code_language(attributes);
+        collect_code(children, &mut code, &mut language);
+        ParsedMarkdownCodeBlock {
+            source_range,
+            language,
+            contents: code
+                .trim_start_matches(['\n', '\r'])
+                .trim_end()
+                .to_string()
+                .into(),
+            contents_offset: 0,
+            highlights: None,
+            emphasized_lines: Vec::new(),
+        }
+    }
+
+    fn convert_table(
+        &self,
+        children: &[Node],
+        source_range: Range<usize>,
+    ) -> Option<ParsedMarkdownTable> {
+        fn collect_rows<'a>(nodes: &'a [Node], rows: &mut Vec<&'a [Node]>) {
+            for node in nodes {
+                if let Node::Element { name, children, .. } = node {
+                    match name.as_str() {
+                        "tr" => rows.push(children),
+                        "thead" | "tbody" | "tfoot" => collect_rows(children, rows),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let mut rows = Vec::new();
+        collect_rows(children, &mut rows);
+        let mut alignments = Vec::new();
+        let mut rows = rows.into_iter().enumerate().map(|(row_ix, cells)| {
+            let mut row = ParsedMarkdownTableRow::new();
+            for cell in cells {
+                let Node::Element {
+                    name,
+                    attributes,
+                    children,
+                    source_range,
+                } = cell
+                else {
+                    continue;
+                };
+                if name != "td" && name != "th" {
+                    continue;
+                }
+                if row_ix == 0 {
+                    alignments.push(cell_alignment(attributes));
+                }
+                let mut text = InlineText::default();
+                // Images in cells aren't shown.
+                let mut images = Vec::new();
+                for child in children {
+                    self.convert_inline(child, &mut text, &mut images);
+                }
+                row.children.push(text.into_text(self.range(source_range)));
+            }
+            row
+        });
+
+        // Markdown tables always have a header, so the first row is one.
+        let header = rows.next()?;
+        let body: Vec<_> = rows.collect();
+        let column_count = body
+            .iter()
+            .map(|row| row.children.len())
+            .chain([header.children.len()])
+            .max()
+            .unwrap_or(0);
+        alignments.resize(column_count, ParsedMarkdownTableAlignment::None);
+        Some(ParsedMarkdownTable {
+            source_range,
+            header,
+            body,
+            column_alignments: alignments,
+        })
+    }
+
+    fn convert_image(
+        &self,
+        attributes: &[(String, String)],
+        source_range: Range<usize>,
+    ) -> ParsedMarkdownImage {
+        ParsedMarkdownImage {
+            source_range,
+            link: attribute(attributes, "src").and_then(|src| self.link(src)),
+            title: attribute(attributes, "title")
+                .unwrap_or_default()
+                .to_string(),
+            alt_text: attribute(attributes, "alt").unwrap_or_default().to_string(),
+            target: None,
+        }
+    }
+}
+
+fn flush_paragraph(text: &mut InlineText, blocks: &mut Vec<ParsedMarkdownElement>) {
+    if let Some(text) = text.take_text() {
+        blocks.push(ParsedMarkdownElement::Paragraph(text));
+    }
+}
+
+/// The language of a code element, from a class like `language-rust`.
+fn code_language(attributes: &[(String, String)]) -> Option<String> {
+    attribute(attributes, "class")?
+        .split_whitespace()
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        })
+        .map(str::to_string)
+}
+
+fn cell_alignment(attributes: &[(String, String)]) -> ParsedMarkdownTableAlignment {
+    let style_alignment = attribute(attributes, "style").and_then(|style| {
+        style.split(';').find_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            (property.trim() == "text-align").then(|| value.trim())
+        })
+    });
+    match attribute(attributes, "align").or(style_alignment) {
+        Some("left") => ParsedMarkdownTableAlignment::Left,
+        Some("center") => ParsedMarkdownTableAlignment::Center,
+        Some("right") => ParsedMarkdownTableAlignment::Right,
+        _ => ParsedMarkdownTableAlignment::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::html_to_parsed_markdown;
+    use pretty_assertions::assert_eq;
+
+    fn contents(block: &ParsedMarkdownElement) -> &str {
+        match block {
+            ParsedMarkdownElement::Heading(heading) => &heading.contents.contents,
+            ParsedMarkdownElement::Paragraph(text) => &text.contents,
+            ParsedMarkdownElement::ListItem(item) => item.content.first().map_or("", contents),
+            _ => "",
+        }
+    }
+
+    #[test]
+    fn test_html_to_parsed_markdown() {
+        let parsed = html_to_parsed_markdown(
+            "<!DOCTYPE html>
+<html><head><title>Ignored</title><style>p { color: red; }</style></head>
+<body>
+<h1>Release <em>notes</em></h1>
+<p>Some <strong>bold</strong> text and a <a href=\"https://zed.dev\">link</a>.
+<script>document.write('<p>nope</p>')</script>
+<ul>
+  <li>One
+  <li>Two
+    <ol start=\"3\"><li>Nested</li></ol>
+  </li>
+</ul>
+<pre><code class=\"language-rust\">fn main() {\n    println!(\"&lt;hi&gt;\");\n}\n</code></pre>
+<blockquote><p>Quoted</p></blockquote>
+<hr>
+<div>Loose <span>text</span></div>
+<img src=\"https://zed.dev/logo.png\" alt=\"Logo\">
+<table><tr><th align=\"right\">A</th><th>B</th></tr><tr><td>1</td><td>2</td></tr></table>
+</body></html>",
+        );
+
+        let blocks = &parsed.children;
+        let summary: Vec<_> = blocks
+            .iter()
+            .map(|block| {
+                let kind = match block {
+                    ParsedMarkdownElement::Heading(_) => "heading",
+                    ParsedMarkdownElement::Paragraph(_) => "paragraph",
+                    ParsedMarkdownElement::ListItem(_) => "item",
+                    ParsedMarkdownElement::CodeBlock(_) => "code",
+                    ParsedMarkdownElement::BlockQuote(_) => "quote",
+                    ParsedMarkdownElement::HorizontalRule(_) => "rule",
+                    ParsedMarkdownElement::Image(_) => "image",
+                    ParsedMarkdownElement::Table(_) => "table",
+                };
+                (kind, contents(block))
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("heading", "Release notes"),
+                ("paragraph", "Some bold text and a link."),
+                ("item", "One"),
+                ("item", "Two"),
+                ("item", "Nested"),
+                ("code", ""),
+                ("quote", ""),
+                ("rule", ""),
+                ("paragraph", "Loose text"),
+                ("image", ""),
+                ("table", ""),
+            ]
+        );
+
+        let ParsedMarkdownElement::Paragraph(paragraph) = &blocks[1] else {
+            unreachable!()
+        };
+        assert_eq!(
+            paragraph.regions[0].link,
+            Some(Link::Web {
+                url: "https://zed.dev".to_string()
+            })
+        );
+        assert_eq!(
+            &paragraph.contents[paragraph.region_ranges[0].clone()],
+            "link"
+        );
+
+        let ParsedMarkdownElement::ListItem(nested) = &blocks[4] else {
+            unreachable!()
+        };
+        assert_eq!(nested.depth, 2);
+        assert_eq!(nested.item_type, ParsedMarkdownListItemType::Ordered(3));
+
+        let ParsedMarkdownElement::CodeBlock(code_block) = &blocks[5] else {
+            unreachable!()
+        };
+        assert_eq!(code_block.language.as_deref(), Some("rust"));
+        assert_eq!(
+            code_block.contents.as_ref(),
+            "fn main() {\n    println!(\"<hi>\");\n}"
+        );
+
+        let ParsedMarkdownElement::Table(table) = &blocks[10] else {
+            unreachable!()
+        };
+        assert_eq!(
+            table.column_alignments,
+            vec![
+                ParsedMarkdownTableAlignment::Right,
+                ParsedMarkdownTableAlignment::None
+            ]
+        );
+        assert_eq!(table.body[0].children[1].contents, "2");
+    }
+
+    #[test]
+    fn test_task_list_items() {
+        let parsed = html_to_parsed_markdown(
+            "<ul><li><input type=\"checkbox\" checked disabled> Done</li><li><input type=checkbox>Not yet</li></ul>",
+        );
+        let item_types: Vec<_> = parsed
+            .children
+            .iter()
+            .map(|block| match block {
+                ParsedMarkdownElement::ListItem(item) => match &item.item_type {
+                    ParsedMarkdownListItemType::Task(checked, _) => (*checked, contents(block)),
+                    _ => panic!("expected a task"),
+                },
+                _ => panic!("expected a list item"),
+            })
+            .collect();
+        assert_eq!(item_types, vec![(true, "Done"), (false, "Not yet")]);
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("a &amp; b &#x41;&#66; &bogus; &amp"),
+            "a & b AB &bogus; &amp"
+        );
+    }
+}
@@ -0,0 +1,1953 @@
+use super::{
+    markdown_diagnostics::redact_url,
+    markdown_elements::{encode_url, Link},
+};
+use anyhow::{anyhow, Context as _, Result};
+use base64::Engine as _;
+use collections::{FxHashMap, FxHashSet};
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+    AsyncReadExt as _,
+};
+use gpui::{
+    div, img, px, AnyElement, BackgroundExecutor, Bounds, DevicePixels, Element, ElementId, Global,
+    GlobalElementId, Hsla, ImageSource, InteractiveElement, IntoElement, LayoutId, ParentElement,
+    Pixels, RenderImage, SharedString, Size, StatefulInteractiveElement, Styled, Task,
+    WindowContext,
+};
+use http_client::{
+    http::{header, Request},
+    AsyncBody, HttpClient, HttpRequestExt as _, RedirectPolicy,
+};
+use image::{codecs::gif::GifDecoder, AnimationDecoder, Frame, ImageFormat, RgbaImage};
+use smallvec::SmallVec;
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fmt,
+    hash::{Hash, Hasher},
+    io::Cursor,
+    path::PathBuf,
+    rc::{Rc, Weak},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use ui::{
+    h_flex, ButtonCommon, ButtonStyle, Clickable, FluentBuilder, Icon, IconButton, IconName,
+    IconSize, Label, LabelCommon, LabelSize, Tooltip,
+};
+use url::Url;
+
+/// Images embedded as `data:` URLs are rejected once decoded beyond this size.
+const MAX_DATA_URI_IMAGE_SIZE: usize = 10 * 1024 * 1024;
+
+/// Every frame of an animation is kept decoded, so animations stop after the
+/// frames that fit in this many bytes.
+const MAX_ANIMATION_SIZE: usize = 64 * 1024 * 1024;
+
+/// How many bytes of decoded images are kept around by default before the
+/// least recently used ones are dropped.
+pub const DEFAULT_IMAGE_CACHE_SIZE: usize = 256 * 1024 * 1024;
+
+/// How many times a download that failed in a way that might not happen
+/// again, like a server error, is tried again, and how long it waits before
+/// the first retry. Each retry waits twice as long as the one before.
+const MAX_FETCH_RETRIES: u32 = 2;
+const FETCH_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// How many redirects a download follows before it fails.
+const MAX_REDIRECTS: usize = 10;
+
+/// Browsers play frames with no delay, or a delay this short, at 10 fps, and
+/// GIFs in the wild are authored with that in mind.
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// SVGs shorter than this are taken for badges, which can be adapted to dark
+/// backgrounds. Taller ones are diagrams and illustrations, left as they are.
+const MAX_BADGE_HEIGHT: f32 = 64.;
+/// What's behind a colored badge on a dark background, in RGB.
+const BADGE_PLATE_COLOR: [u8; 3] = [0xf6, 0xf8, 0xfa];
+
+/// Where the bytes of an image come from.
+#[derive(Debug, Clone)]
+pub enum ImageLocation {
+    Web(SharedString),
+    Path(PathBuf),
+    /// The image is embedded in the document itself, as `uri`. It's told
+    /// apart from others by the `key` of its [`Link::Data`], rather than by
+    /// its payload, which can be megabytes long.
+    Data {
+        uri: SharedString,
+        key: u64,
+    },
+}
+
+impl PartialEq for ImageLocation {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Web(url), Self::Web(other)) => url == other,
+            (Self::Path(path), Self::Path(other)) => path == other,
+            (Self::Data { key, .. }, Self::Data { key: other, .. }) => key == other,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ImageLocation {}
+
+impl Hash for ImageLocation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Web(url) => url.hash(state),
+            Self::Path(path) => path.hash(state),
+            Self::Data { key, .. } => key.hash(state),
+        }
+    }
+}
+
+impl ImageLocation {
+    pub fn from_link(link: &Link) -> Self {
+        match link {
+            Link::Web { url } => Self::Web(encode_url(url).into_owned().into()),
+            Link::Path { path, .. } => Self::Path(path.clone()),
+            Link::Data { uri, key } => Self::Data {
+                uri: uri.clone(),
+                key: *key,
+            },
+            // Nothing can be loaded from these, so they fail like any other
+            // broken URL.
+            Link::Fragment { fragment } => Self::Web(format!("#{}", fragment).into()),
+        }
+    }
+
+    /// How many of the image's pixels make up a pixel of layout, from an
+    /// `@2x` or `@3x` at the end of its file name, like in `logo@2x.png`.
+    pub fn pixel_density(&self) -> f32 {
+        let name = match self {
+            Self::Web(url) => url
+                .split(['?', '#'])
+                .next()
+                .and_then(|path| path.rsplit('/').next())
+                .unwrap_or_default(),
+            Self::Path(path) => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default(),
+            Self::Data { .. } => return 1.,
+        };
+        let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+        match stem.rsplit_once('@') {
+            Some((_, "2x")) => 2.,
+            Some((_, "3x")) => 3.,
+            _ => 1.,
+        }
+    }
+}
+
+impl fmt::Display for ImageLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Web(url) => write!(f, "{}", redact_url(url)),
+            Self::Path(path) => write!(f, "{}", path.display()),
+            // The payload can be megabytes long, so leave it out.
+            Self::Data { .. } => write!(f, "embedded image"),
+        }
+    }
+}
+
+/// Which images are loaded when a document is rendered. Images that are not
+/// loaded are shown as their alt text, with a button to load them anyway.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ImagePolicy {
+    #[default]
+    LoadAll,
+    /// Load images from the filesystem or embedded in the document, but never
+    /// from the network.
+    LoadLocalOnly,
+    Never,
+}
+
+impl ImagePolicy {
+    pub fn allows(&self, location: &ImageLocation) -> bool {
+        match self {
+            Self::LoadAll => true,
+            Self::LoadLocalOnly => !matches!(location, ImageLocation::Web(_)),
+            Self::Never => false,
+        }
+    }
+}
+
+/// The state of an image as seen by the renderer.
+#[derive(Clone)]
+pub enum ImageState {
+    Loading,
+    /// The image and how tall it's laid out.
+    Loaded(Arc<RenderImage>, Pixels),
+    Failed(SharedString),
+}
+
+/// A decoded image. SVGs are kept as a parsed tree so that they can be
+/// rasterized at whatever size they end up being displayed at.
+#[derive(Clone)]
+enum DecodedImage {
+    Raster(Arc<RenderImage>),
+    Svg(Arc<usvg::Tree>),
+    Animated(Arc<Animation>),
+}
+
+struct Animation {
+    frames: Vec<(Arc<RenderImage>, Duration)>,
+    duration: Duration,
+    started_at: Instant,
+}
+
+impl Animation {
+    /// Returns the frame to show at `now`, along with when the frame after it
+    /// is due.
+    fn frame_at(&self, now: Instant) -> (Arc<RenderImage>, Instant) {
+        let elapsed = now.saturating_duration_since(self.started_at);
+        let mut position =
+            Duration::from_nanos((elapsed.as_nanos() % self.duration.as_nanos().max(1)) as u64);
+        for (frame, delay) in &self.frames {
+            if position < *delay {
+                return (frame.clone(), now + (*delay - position));
+            }
+            position -= *delay;
+        }
+
+        let (frame, delay) = &self.frames[0];
+        (frame.clone(), now + *delay)
+    }
+}
+
+impl DecodedImage {
+    /// How much memory the decoded pixels take up.
+    fn size_in_bytes(&self, rasterized: Option<&Arc<RenderImage>>) -> usize {
+        let image_size = |image: &RenderImage| {
+            let size = image.size(0);
+            size.width.0.max(0) as usize * size.height.0.max(0) as usize * 4
+        };
+        match self {
+            Self::Raster(image) => image_size(image),
+            // The tree itself is small next to its rasterization.
+            Self::Svg(_) => rasterized.map_or(0, |image| image_size(image)),
+            Self::Animated(animation) => animation
+                .frames
+                .iter()
+                .map(|(frame, _)| image_size(frame))
+                .sum(),
+        }
+    }
+}
+
+enum ImageEntry {
+    Loading {
+        /// The load, which is cancelled once nothing holds it. The viewers
+        /// waiting for the image hold it, and so does `unowned`. The entry
+        /// is loaded again if it's asked for after that.
+        task: Weak<Task<()>>,
+        /// The load of an image that was asked for without an
+        /// [`ImageViewer`], which every window is redrawn for once it's
+        /// loaded.
+        unowned: Option<Rc<Task<()>>>,
+    },
+    Loaded {
+        image: DecodedImage,
+        rasterized: Option<Arc<RenderImage>>,
+        /// Whether `rasterized` was adapted to a dark background, by
+        /// [`adapt_badge_to_dark_background`].
+        adapted: bool,
+        /// When the image was last asked for, on the store's clock.
+        last_used: u64,
+    },
+    Failed(SharedString),
+}
+
+/// Something that shows images from the [`ImageStore`], like a view.
+///
+/// The store tells it when an image it's waiting for has loaded, and never
+/// evicts the images it showed in its last frame. Call
+/// [`ImageViewer::start_frame`] each time the viewer renders, so images it
+/// no longer shows can be evicted again.
+#[derive(Clone)]
+pub struct ImageViewer(Rc<ImageViewerState>);
+
+struct ImageViewerState {
+    visible: RefCell<FxHashSet<ImageLocation>>,
+    waiting: RefCell<FxHashSet<ImageLocation>>,
+    /// The loads of the images the viewer is waiting for, which it keeps
+    /// going. Dropping the viewer cancels those no other viewer waits for.
+    loads: RefCell<FxHashMap<ImageLocation, Rc<Task<()>>>>,
+    on_loaded: Box<dyn Fn(&mut WindowContext)>,
+    request_headers: RefCell<Arc<RequestHeaders>>,
+}
+
+impl ImageViewer {
+    /// Creates a viewer that calls `on_loaded` whenever an image it asked
+    /// for finishes loading, which usually redraws it.
+    pub fn new(on_loaded: impl Fn(&mut WindowContext) + 'static) -> Self {
+        Self(Rc::new(ImageViewerState {
+            visible: RefCell::default(),
+            waiting: RefCell::default(),
+            loads: RefCell::default(),
+            on_loaded: Box::new(on_loaded),
+            request_headers: RefCell::default(),
+        }))
+    }
+
+    /// Sends `headers` with the requests for the web images the viewer asks
+    /// for from now on, to the origins they're for.
+    ///
+    /// Images are shared by every viewer, so one that has loaded is shown
+    /// to other viewers too, whatever they send.
+    pub fn set_request_headers(&self, headers: RequestHeaders) {
+        *self.0.request_headers.borrow_mut() = Arc::new(headers);
+    }
+
+    /// Forgets which images the viewer shows, before it renders again.
+    ///
+    /// The loads of images it didn't show in its last frame, which have
+    /// scrolled far out of view, are cancelled, and start again if the
+    /// images come back.
+    pub fn start_frame(&self) {
+        let mut visible = self.0.visible.borrow_mut();
+        self.0
+            .waiting
+            .borrow_mut()
+            .retain(|location| visible.contains(location));
+        self.0
+            .loads
+            .borrow_mut()
+            .retain(|location, _| visible.contains(location));
+        visible.clear();
+    }
+
+    /// Stops waiting for the images that haven't loaded yet, for when the
+    /// viewer shows another document. Their downloads are cancelled unless
+    /// another viewer is waiting for them too.
+    pub fn cancel_loads(&self) {
+        self.0.waiting.borrow_mut().clear();
+        self.0.loads.borrow_mut().clear();
+    }
+}
+
+/// Headers to send with the requests for web images, by the origin they're
+/// for, like an `Authorization` header for one image host. Images on other
+/// origins are requested without them, so that a document can't send them
+/// wherever it likes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestHeaders {
+    /// Each origin's headers, by its ASCII serialization.
+    by_origin: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl RequestHeaders {
+    /// Sends `headers` to the origin of `url`: its scheme, host and port.
+    /// They replace any headers that origin had.
+    pub fn insert(&mut self, url: &str, headers: Vec<(String, String)>) -> Result<()> {
+        let origin = Url::parse(url)?.origin();
+        if !origin.is_tuple() {
+            return Err(anyhow!("{url} has no origin to send headers to"));
+        }
+        let origin = origin.ascii_serialization();
+        self.by_origin.retain(|(other, _)| *other != origin);
+        self.by_origin.push((origin, headers));
+        Ok(())
+    }
+
+    /// The headers to send with a request for `url`.
+    pub fn for_url(&self, url: &Url) -> &[(String, String)] {
+        let origin = url.origin();
+        if !origin.is_tuple() {
+            return &[];
+        }
+        let origin = origin.ascii_serialization();
+        self.by_origin
+            .iter()
+            .find(|(other, _)| *other == origin)
+            .map_or(&[], |(_, headers)| headers.as_slice())
+    }
+}
+
+/// The limits on downloading images from the web.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchLimits {
+    /// How long a download can take before it fails.
+    pub timeout: Duration,
+    /// Downloads fail as soon as they're larger than this many bytes.
+    pub max_bytes: usize,
+    /// How many images are downloaded at once. The others wait their turn.
+    pub max_concurrent: usize,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_bytes: 50 * 1024 * 1024,
+            max_concurrent: 6,
+        }
+    }
+}
+
+/// Takes turns for downloads, so that only so many run at once.
+#[derive(Clone, Default)]
+struct FetchSlots(Arc<Mutex<FetchSlotsState>>);
+
+#[derive(Default)]
+struct FetchSlotsState {
+    in_use: usize,
+    /// The downloads waiting for a turn, which are handed the slot of the
+    /// next one to finish.
+    waiting: VecDeque<oneshot::Sender<FetchSlot>>,
+}
+
+/// A download's turn, which is passed on when it's dropped.
+struct FetchSlot(FetchSlots);
+
+impl FetchSlots {
+    async fn acquire(&self, max_concurrent: usize) -> FetchSlot {
+        loop {
+            let turn = {
+                let mut state = self.0.lock().unwrap();
+                if state.in_use < max_concurrent.max(1) {
+                    state.in_use += 1;
+                    return FetchSlot(self.clone());
+                }
+                let (tx, rx) = oneshot::channel();
+                state.waiting.push_back(tx);
+                rx
+            };
+            if let Ok(slot) = turn.await {
+                return slot;
+            }
+        }
+    }
+}
+
+impl Drop for FetchSlot {
+    fn drop(&mut self) {
+        let next = {
+            let mut state = (self.0).0.lock().unwrap();
+            let next = state.waiting.pop_front();
+            if next.is_none() {
+                state.in_use = state.in_use.saturating_sub(1);
+            }
+            next
+        };
+        // A download that was cancelled while it waited hands the slot on
+        // when it's dropped in turn.
+        if let Some(next) = next {
+            next.send(FetchSlot(self.0.clone())).ok();
+        }
+    }
+}
+
+/// Fetches and decodes the images of every open document, so that an image
+/// referenced more than once, or by more than one document, is only loaded
+/// once.
+///
+/// Decoded images are kept until they take up more than the store's byte
+/// budget, after which the least recently used ones are dropped, except for
+/// those some live [`ImageViewer`] is showing.
+pub struct ImageStore {
+    entries: FxHashMap<ImageLocation, ImageEntry>,
+    viewers: Vec<Weak<ImageViewerState>>,
+    byte_budget: usize,
+    fetch_limits: FetchLimits,
+    fetch_slots: FetchSlots,
+    clock: u64,
+    /// The pending redraw for the next frame of the visible animations.
+    /// Images only ask for one while they are painted, so animations that are
+    /// scrolled out of view stop costing anything.
+    next_animation_frame: Option<(Instant, Task<()>)>,
+    /// The SVGs being rasterized in the background, at the size and for
+    /// the background they're being rasterized for.
+    rasterizations: FxHashMap<ImageLocation, (Size<DevicePixels>, bool, Task<()>)>,
+}
+
+impl Default for ImageStore {
+    fn default() -> Self {
+        Self {
+            entries: FxHashMap::default(),
+            viewers: Vec::new(),
+            byte_budget: DEFAULT_IMAGE_CACHE_SIZE,
+            fetch_limits: FetchLimits::default(),
+            fetch_slots: FetchSlots::default(),
+            clock: 0,
+            next_animation_frame: None,
+            rasterizations: FxHashMap::default(),
+        }
+    }
+}
+
+impl Global for ImageStore {}
+
+impl ImageStore {
+    /// Changes how many bytes of decoded images are kept, evicting images
+    /// right away if they no longer fit.
+    pub fn set_byte_budget(&mut self, byte_budget: usize) {
+        self.byte_budget = byte_budget;
+        self.evict();
+    }
+
+    /// Changes the limits on downloading images, for the downloads that start
+    /// from now on.
+    pub fn set_fetch_limits(&mut self, fetch_limits: FetchLimits) {
+        self.fetch_limits = fetch_limits;
+    }
+
+    /// How many bytes the decoded images in the store take up.
+    pub fn used_bytes(&self) -> usize {
+        self.entries
+            .values()
+            .map(|entry| match entry {
+                ImageEntry::Loaded {
+                    image, rasterized, ..
+                } => image.size_in_bytes(rasterized.as_ref()),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Whether the image at `location` has loaded, and has more than one
+    /// frame.
+    fn is_animation(&self, location: &ImageLocation) -> bool {
+        matches!(
+            self.entries.get(location),
+            Some(ImageEntry::Loaded {
+                image: DecodedImage::Animated(_),
+                ..
+            })
+        )
+    }
+
+    /// How tall the image at `location` is laid out when nothing scales it
+    /// down, once it has loaded.
+    fn natural_height(&self, location: &ImageLocation) -> Option<Pixels> {
+        let ImageEntry::Loaded { image, .. } = self.entries.get(location)? else {
+            return None;
+        };
+        let unbounded = px(f32::INFINITY);
+        Some(match image {
+            DecodedImage::Raster(image) => {
+                raster_display_height(image, location.pixel_density(), unbounded)
+            }
+            DecodedImage::Animated(animation) => {
+                raster_display_height(&animation.frames[0].0, location.pixel_density(), unbounded)
+            }
+            DecodedImage::Svg(tree) => px(tree.size().height()),
+        })
+    }
+
+    /// Returns the image at `location`, and starts loading it if this is the
+    /// first time it has been asked for, or if it has been evicted since.
+    ///
+    /// Images are laid out at their own size, up to `max_height`. Raster
+    /// images named like `logo@2x.png` are laid out at half their size, so
+    /// that each of their pixels is a device pixel on a 2x display, and SVGs
+    /// are rasterized for the window's scale factor.
+    ///
+    /// For animations this is the frame due now, or the first frame if
+    /// `animate` is false. When `viewer` is given, it's told once the image
+    /// has loaded; otherwise every window is redrawn. With
+    /// `dark_background`, SVGs that look like badges are adapted to it, by
+    /// [`adapt_badge_to_dark_background`].
+    pub fn image(
+        location: &ImageLocation,
+        max_height: Pixels,
+        animate: bool,
+        dark_background: bool,
+        viewer: Option<&ImageViewer>,
+        cx: &mut WindowContext,
+    ) -> ImageState {
+        let scale_factor = cx.scale_factor();
+        let needs_load = match cx.default_global::<ImageStore>().entries.get(location) {
+            None => true,
+            Some(ImageEntry::Loading { task, .. }) => task.strong_count() == 0,
+            Some(_) => false,
+        };
+        if needs_load {
+            let request_headers = viewer
+                .map(|viewer| viewer.0.request_headers.borrow().clone())
+                .unwrap_or_default();
+            let task = Rc::new(Self::load(location.clone(), request_headers, cx));
+            let store = cx.global_mut::<ImageStore>();
+            store.entries.insert(
+                location.clone(),
+                ImageEntry::Loading {
+                    task: Rc::downgrade(&task),
+                    unowned: viewer.is_none().then(|| task.clone()),
+                },
+            );
+            if let Some(viewer) = viewer {
+                store.watch(viewer, location);
+            }
+            return ImageState::Loading;
+        }
+        if let Some(viewer) = viewer {
+            cx.global_mut::<ImageStore>().watch(viewer, location);
+        }
+
+        let store = cx.global_mut::<ImageStore>();
+        store.clock += 1;
+        let clock = store.clock;
+        let Some(entry) = store.entries.get_mut(location) else {
+            return ImageState::Loading;
+        };
+        if let ImageEntry::Loaded { last_used, .. } = entry {
+            *last_used = clock;
+        }
+
+        match entry {
+            ImageEntry::Loading { task, unowned } => {
+                if viewer.is_none() && unowned.is_none() {
+                    *unowned = task.upgrade();
+                }
+                ImageState::Loading
+            }
+            ImageEntry::Failed(error) => ImageState::Failed(error.clone()),
+            ImageEntry::Loaded {
+                image: DecodedImage::Raster(image),
+                ..
+            } => {
+                let height = raster_display_height(image, location.pixel_density(), max_height);
+                ImageState::Loaded(image.clone(), height)
+            }
+            ImageEntry::Loaded {
+                image: DecodedImage::Animated(animation),
+                ..
+            } => {
+                let first_frame = &animation.frames[0].0;
+                let height =
+                    raster_display_height(first_frame, location.pixel_density(), max_height);
+                if !animate {
+                    return ImageState::Loaded(first_frame.clone(), height);
+                }
+
+                let animation = animation.clone();
+                let (frame, next_frame_at) = animation.frame_at(Instant::now());
+                Self::schedule_animation_frame(next_frame_at, cx);
+                ImageState::Loaded(frame, height)
+            }
+            ImageEntry::Loaded {
+                image: DecodedImage::Svg(tree),
+                rasterized,
+                adapted,
+                ..
+            } => {
+                // SVG sizes are already in layout pixels. Moving the window
+                // to a display with another scale factor changes the target
+                // size, so the SVG is rasterized again, and so does the
+                // document's background changing from light to dark.
+                let height = px(tree.size().height()).min(max_height);
+                let target_size = svg_device_size(tree, height, scale_factor);
+                let adapt = dark_background && tree.size().height() < MAX_BADGE_HEIGHT;
+                if let Some(image) = rasterized.as_ref().filter(|image| {
+                    *adapted == adapt && is_close_enough(image.size(0), target_size)
+                }) {
+                    return ImageState::Loaded(image.clone(), height);
+                }
+
+                // Rasterizing a large SVG takes long enough to drop frames,
+                // so it's done in the background, and the old rasterization
+                // is scaled to the new size until then.
+                let state = match rasterized {
+                    Some(image) => ImageState::Loaded(image.clone(), height),
+                    None => ImageState::Loading,
+                };
+                let tree = tree.clone();
+                let is_pending = store.rasterizations.get(location).is_some_and(
+                    |(pending_size, pending_adapt, _)| {
+                        *pending_adapt == adapt && is_close_enough(*pending_size, target_size)
+                    },
+                );
+                if !is_pending {
+                    let task = Self::rasterize_in_background(
+                        location.clone(),
+                        tree,
+                        target_size,
+                        adapt,
+                        cx,
+                    );
+                    cx.global_mut::<ImageStore>()
+                        .rasterizations
+                        .insert(location.clone(), (target_size, adapt, task));
+                }
+                if let Some(viewer) = viewer {
+                    viewer.0.waiting.borrow_mut().insert(location.clone());
+                }
+                state
+            }
+        }
+    }
+
+    /// Rasterizes the SVG at `location` on the background executor, and
+    /// tells the viewers waiting for it once it's done. Dropping the task
+    /// cancels it.
+    fn rasterize_in_background(
+        location: ImageLocation,
+        tree: Arc<usvg::Tree>,
+        size: Size<DevicePixels>,
+        adapt: bool,
+        cx: &mut WindowContext,
+    ) -> Task<()> {
+        let rasterized = cx
+            .background_executor()
+            .spawn(async move { rasterize_svg(&tree, size, adapt) });
+        cx.spawn(|mut cx| async move {
+            let result = rasterized.await;
+            cx.update(|cx| {
+                let store = cx.global_mut::<ImageStore>();
+                let _task = store.rasterizations.remove(&location);
+                match result {
+                    Ok(image) => {
+                        let Some(ImageEntry::Loaded {
+                            rasterized,
+                            adapted,
+                            ..
+                        }) = store.entries.get_mut(&location)
+                        else {
+                            return;
+                        };
+                        *rasterized = Some(image);
+                        *adapted = adapt;
+                    }
+                    Err(error) => {
+                        if !matches!(
+                            store.entries.get(&location),
+                            Some(ImageEntry::Loaded { .. })
+                        ) {
+                            return;
+                        }
+                        log::error!("failed to rasterize image {}: {:#}", location, error);
+                        store.entries.insert(
+                            location.clone(),
+                            ImageEntry::Failed(format!("{error:#}").into()),
+                        );
+                    }
+                }
+                store.evict();
+
+                let waiting = store.take_waiting(&location);
+                // Like images that load without a viewer, ones rasterized
+                // for none are shown by redrawing every window.
+                if waiting.is_empty() {
+                    cx.refresh();
+                }
+                for viewer in waiting {
+                    (viewer.on_loaded)(cx);
+                }
+            })
+            .ok();
+        })
+    }
+
+    fn schedule_animation_frame(at: Instant, cx: &mut WindowContext) {
+        let now = Instant::now();
+        if let Some((scheduled_at, _)) = &cx.global::<ImageStore>().next_animation_frame {
+            if now < *scheduled_at && *scheduled_at <= at {
+                return;
+            }
+        }
+
+        let task = cx.spawn(|mut cx| async move {
+            cx.background_executor()
+                .timer(at.saturating_duration_since(Instant::now()))
+                .await;
+            cx.update(|cx| {
+                cx.global_mut::<ImageStore>().next_animation_frame = None;
+                cx.refresh();
+            })
+            .ok();
+        });
+        cx.global_mut::<ImageStore>().next_animation_frame = Some((at, task));
+    }
+
+    fn load(
+        location: ImageLocation,
+        request_headers: Arc<RequestHeaders>,
+        cx: &mut WindowContext,
+    ) -> Task<()> {
+        let http_client = cx.http_client();
+        let store = cx.global::<ImageStore>();
+        let fetch_limits = store.fetch_limits;
+        let fetch_slots = store.fetch_slots.clone();
+        let executor = cx.background_executor().clone();
+        // Dropping this task, along with the one awaiting it, cancels the
+        // download.
+        let decoded = cx.background_executor().spawn({
+            let location = location.clone();
+            async move {
+                let bytes = match &location {
+                    ImageLocation::Web(url) => {
+                        let _slot = fetch_slots.acquire(fetch_limits.max_concurrent).await;
+                        fetch_web_image(url, &request_headers, http_client, fetch_limits, &executor)
+                            .await?
+                    }
+                    location => read_image_bytes(location)?,
+                };
+                decode_image(&bytes)
+            }
+        });
+
+        cx.spawn(|mut cx| async move {
+            let entry = match decoded.await {
+                Ok(image) => ImageEntry::Loaded {
+                    image,
+                    rasterized: None,
+                    adapted: false,
+                    last_used: 0,
+                },
+                Err(error) => {
+                    log::error!("failed to load image {}: {:#}", location, error);
+                    ImageEntry::Failed(format!("{error:#}").into())
+                }
+            };
+
+            cx.update(|cx| {
+                let store = cx.global_mut::<ImageStore>();
+                store.clock += 1;
+                let entry = match entry {
+                    ImageEntry::Loaded {
+                        image,
+                        rasterized,
+                        adapted,
+                        ..
+                    } => ImageEntry::Loaded {
+                        image,
+                        rasterized,
+                        adapted,
+                        last_used: store.clock,
+                    },
+                    entry => entry,
+                };
+                let refresh = matches!(
+                    store.entries.insert(location.clone(), entry),
+                    Some(ImageEntry::Loading {
+                        unowned: Some(_),
+                        ..
+                    })
+                );
+                store.evict();
+
+                for viewer in store.take_waiting(&location) {
+                    (viewer.on_loaded)(cx);
+                }
+                if refresh {
+                    cx.refresh();
+                }
+            })
+            .ok();
+        })
+    }
+
+    /// Whether the image at `location` has loaded, failed to, or is loading.
+    fn has_started(&self, location: &ImageLocation) -> bool {
+        match self.entries.get(location) {
+            None => false,
+            Some(ImageEntry::Loading { task, .. }) => task.strong_count() > 0,
+            Some(_) => true,
+        }
+    }
+
+    /// Records that `viewer` shows the image at `location`, and is waiting
+    /// for it if it hasn't loaded yet.
+    fn watch(&mut self, viewer: &ImageViewer, location: &ImageLocation) {
+        let state = &viewer.0;
+        if !self
+            .viewers
+            .iter()
+            .any(|watching| watching.as_ptr() == Rc::as_ptr(state))
+        {
+            self.viewers.retain(|viewer| viewer.strong_count() > 0);
+            self.viewers.push(Rc::downgrade(state));
+        }
+        state.visible.borrow_mut().insert(location.clone());
+        match self.entries.get(location) {
+            None => {
+                state.waiting.borrow_mut().insert(location.clone());
+            }
+            Some(ImageEntry::Loading { task, .. }) => {
+                state.waiting.borrow_mut().insert(location.clone());
+                if let Some(task) = task.upgrade() {
+                    state.loads.borrow_mut().insert(location.clone(), task);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// The live viewers that were waiting for the image at `location`, which
+    /// stop waiting for it.
+    fn take_waiting(&mut self, location: &ImageLocation) -> Vec<Rc<ImageViewerState>> {
+        self.viewers.retain(|viewer| viewer.strong_count() > 0);
+        self.viewers
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|viewer| {
+                viewer.loads.borrow_mut().remove(location);
+                viewer.waiting.borrow_mut().remove(location)
+            })
+            .collect()
+    }
+
+    /// Drops the least recently used images until the rest fit in the byte
+    /// budget. Images that are loading, or that a live viewer shows, are
+    /// kept even if they don't fit.
+    fn evict(&mut self) {
+        let mut used_bytes = self.used_bytes();
+        if used_bytes <= self.byte_budget {
+            return;
+        }
+
+        let viewers: Vec<_> = self.viewers.iter().filter_map(Weak::upgrade).collect();
+        let is_visible = |location: &ImageLocation| {
+            viewers
+                .iter()
+                .any(|viewer| viewer.visible.borrow().contains(location))
+        };
+        let mut candidates: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|(location, entry)| match entry {
+                ImageEntry::Loaded {
+                    image,
+                    rasterized,
+                    last_used,
+                    ..
+                } if !is_visible(location) => Some((
+                    *last_used,
+                    location.clone(),
+                    image.size_in_bytes(rasterized.as_ref()),
+                )),
+                _ => None,
+            })
+            .collect();
+        candidates.sort_by_key(|(last_used, _, _)| *last_used);
+
+        for (_, location, size) in candidates {
+            if used_bytes <= self.byte_budget {
+                break;
+            }
+            self.entries.remove(&location);
+            used_bytes -= size;
+        }
+    }
+}
+
+fn read_image_bytes(location: &ImageLocation) -> Result<Vec<u8>> {
+    match location {
+        ImageLocation::Path(path) => {
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+        }
+        ImageLocation::Web(url) => Err(anyhow!("{url} has to be downloaded")),
+        ImageLocation::Data { uri, .. } => decode_data_uri(uri),
+    }
+}
+
+/// A failed download, and whether trying again might work.
+struct FetchError {
+    error: anyhow::Error,
+    transient: bool,
+}
+
+/// Downloads the image at `url` within `limits`, trying again a couple of
+/// times if the server or the connection fails.
+async fn fetch_web_image(
+    url: &str,
+    headers: &RequestHeaders,
+    http_client: Arc<dyn HttpClient>,
+    limits: FetchLimits,
+    executor: &BackgroundExecutor,
+) -> Result<Vec<u8>> {
+    let mut retry_delay = FETCH_RETRY_DELAY;
+    let mut attempt = 0;
+    let started = Instant::now();
+    let logged_url = redact_url(url);
+    log::debug!(url = logged_url.as_str(); "image download started");
+    loop {
+        let download = Box::pin(download_image(
+            url,
+            headers,
+            http_client.clone(),
+            limits.max_bytes,
+        ));
+        let error = match future::select(download, executor.timer(limits.timeout)).await {
+            Either::Left((Ok(bytes), _)) => {
+                log::debug!(
+                    url = logged_url.as_str(),
+                    bytes = bytes.len(),
+                    milliseconds = started.elapsed().as_secs_f64() * 1000.,
+                    attempts = attempt + 1;
+                    "image downloaded"
+                );
+                return Ok(bytes);
+            }
+            Either::Left((Err(error), _)) => error,
+            Either::Right(_) => {
+                log::warn!(url = logged_url.as_str(), timeout = limits.timeout.as_secs(); "image download timed out");
+                return Err(anyhow!(
+                    "the download took longer than {} seconds",
+                    limits.timeout.as_secs()
+                ));
+            }
+        };
+        if !error.transient || attempt == MAX_FETCH_RETRIES {
+            log::warn!(url = logged_url.as_str(), attempts = attempt + 1; "image download failed: {:#}", error.error);
+            return Err(error.error);
+        }
+        log::info!(url = logged_url.as_str(), attempt = attempt + 1; "retrying image download: {:#}", error.error);
+        executor.timer(retry_delay).await;
+        retry_delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Downloads the image at `url` with the `headers` for its origin, giving up
+/// as soon as it's larger than `max_bytes` rather than buffering all of it.
+///
+/// Redirects are followed here rather than by the client, so that the
+/// headers aren't sent on to another origin, even one with headers of its
+/// own.
+async fn download_image(
+    url: &str,
+    headers: &RequestHeaders,
+    http_client: Arc<dyn HttpClient>,
+    max_bytes: usize,
+) -> Result<Vec<u8>, FetchError> {
+    let too_large = || FetchError {
+        error: anyhow!("the image is larger than {} MB", max_bytes / (1024 * 1024)),
+        transient: false,
+    };
+    let failed = |error: anyhow::Error| FetchError {
+        error,
+        transient: false,
+    };
+
+    let mut url = Url::parse(url).map_err(|error| failed(error.into()))?;
+    let mut headers = headers.for_url(&url);
+    let mut redirects = 0;
+    let mut response = loop {
+        let mut request = Request::builder()
+            .uri(url.as_str())
+            .follow_redirects(RedirectPolicy::NoFollow);
+        for (name, value) in headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        let request = request
+            .body(AsyncBody::default())
+            .map_err(|error| failed(error.into()))?;
+        let response = http_client
+            .send(request)
+            .await
+            .map_err(|error| FetchError {
+                error,
+                transient: true,
+            })?;
+        let Some(location) = response
+            .status()
+            .is_redirection()
+            .then(|| response.headers().get(header::LOCATION))
+            .flatten()
+        else {
+            break response;
+        };
+
+        if redirects == MAX_REDIRECTS {
+            return Err(failed(anyhow!("the server redirected too many times")));
+        }
+        redirects += 1;
+        let next = location
+            .to_str()
+            .map_err(|error| failed(error.into()))
+            .and_then(|location| url.join(location).map_err(|error| failed(error.into())))?;
+        log::debug!(
+            from = redact_url(url.as_str()).as_str(),
+            to = redact_url(next.as_str()).as_str();
+            "image download redirected"
+        );
+        if next.origin() != url.origin() {
+            headers = &[];
+        }
+        url = next;
+    };
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FetchError {
+            error: anyhow!("server responded with {}", status),
+            transient: status.is_server_error() || status.as_u16() == 429,
+        });
+    }
+    let content_length = response
+        .headers()
+        .get(http_client::http::header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<usize>().ok());
+    if content_length.map_or(false, |length| length > max_bytes) {
+        return Err(too_large());
+    }
+
+    let mut bytes = Vec::with_capacity(content_length.unwrap_or(0));
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = response
+            .body_mut()
+            .read(&mut buffer)
+            .await
+            .map_err(|error| FetchError {
+                error: error.into(),
+                transient: true,
+            })?;
+        if read == 0 {
+            return Ok(bytes);
+        }
+        if bytes.len() + read > max_bytes {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&buffer[..read]);
+    }
+}
+
+/// Decodes the payload of a `data:[<media type>][;base64],<data>` URL.
+fn decode_data_uri(uri: &str) -> Result<Vec<u8>> {
+    let (header, payload) = uri
+        .strip_prefix("data:")
+        .and_then(|uri| uri.split_once(','))
+        .ok_or_else(|| anyhow!("malformed data URL"))?;
+
+    let bytes = if header.ends_with(";base64") {
+        // Base64 encodes every 3 bytes as 4 characters, so the decoded size is
+        // known up front and oversized payloads need not be decoded at all.
+        let payload: Vec<u8> = payload
+            .bytes()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect();
+        if payload.len() / 4 * 3 > MAX_DATA_URI_IMAGE_SIZE {
+            return Err(anyhow!("embedded image is larger than 10 MB"));
+        }
+        base64::engine::general_purpose::STANDARD
+            .decode(payload)
+            .context("malformed base64 in data URL")?
+    } else {
+        percent_encoding::percent_decode_str(payload).collect()
+    };
+
+    if bytes.len() > MAX_DATA_URI_IMAGE_SIZE {
+        return Err(anyhow!("embedded image is larger than 10 MB"));
+    }
+    Ok(bytes)
+}
+
+fn decode_image(bytes: &[u8]) -> Result<DecodedImage> {
+    if let Ok(ImageFormat::Gif) = image::guess_format(bytes) {
+        return decode_gif(bytes);
+    }
+
+    if let Ok(format) = image::guess_format(bytes) {
+        let image = image::load_from_memory_with_format(bytes, format)?.into_rgba8();
+        return Ok(DecodedImage::Raster(Arc::new(to_render_image(image))));
+    }
+
+    let tree = usvg::Tree::from_data(bytes, &usvg::Options::default())
+        .context("unsupported or malformed image")?;
+    Ok(DecodedImage::Svg(Arc::new(tree)))
+}
+
+fn decode_gif(bytes: &[u8]) -> Result<DecodedImage> {
+    let decoder = GifDecoder::new(Cursor::new(bytes))?;
+
+    let mut frames = Vec::new();
+    let mut size = 0;
+    for frame in decoder.into_frames() {
+        let frame = frame?;
+        size += frame.buffer().len();
+        if size > MAX_ANIMATION_SIZE && !frames.is_empty() {
+            log::warn!(
+                "animation is too large, only playing its first {} frames",
+                frames.len()
+            );
+            break;
+        }
+
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let delay = Duration::from_millis((numerator / denominator.max(1)) as u64);
+        let delay = if delay < MIN_FRAME_DELAY {
+            DEFAULT_FRAME_DELAY
+        } else {
+            delay
+        };
+        frames.push((Arc::new(to_render_image(frame.into_buffer())), delay));
+    }
+
+    match frames.len() {
+        0 => Err(anyhow!("GIF has no frames")),
+        1 => Ok(DecodedImage::Raster(frames.pop().unwrap().0)),
+        _ => Ok(DecodedImage::Animated(Arc::new(Animation {
+            duration: frames.iter().map(|(_, delay)| *delay).sum(),
+            frames,
+            started_at: Instant::now(),
+        }))),
+    }
+}
+
+/// gpui expects the pixels of a [`RenderImage`] in BGRA order.
+fn to_render_image(mut image: RgbaImage) -> RenderImage {
+    for pixel in image.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    RenderImage::new(SmallVec::from_elem(Frame::new(image), 1))
+}
+
+/// How tall a raster image is laid out: its height in pixels divided by its
+/// pixel density, up to `max_height`.
+fn raster_display_height(image: &RenderImage, pixel_density: f32, max_height: Pixels) -> Pixels {
+    px(image.size(0).height.0 as f32 / pixel_density).min(max_height)
+}
+
+fn svg_device_size(
+    tree: &usvg::Tree,
+    display_height: Pixels,
+    scale_factor: f32,
+) -> Size<DevicePixels> {
+    let svg_size = tree.size();
+    let height = (f32::from(display_height) * scale_factor).round().max(1.);
+    let width = (height * svg_size.width() / svg_size.height())
+        .round()
+        .max(1.);
+    Size {
+        width: DevicePixels(width as i32),
+        height: DevicePixels(height as i32),
+    }
+}
+
+/// Re-rasterizing on every small change in layout would be wasteful, so an
+/// existing rasterization is reused as long as it is within 10% of the target.
+fn is_close_enough(current: Size<DevicePixels>, target: Size<DevicePixels>) -> bool {
+    let difference = (current.height.0 - target.height.0).abs() as f32;
+    difference <= target.height.0 as f32 * 0.1
+}
+
+fn rasterize_svg(
+    tree: &usvg::Tree,
+    size: Size<DevicePixels>,
+    adapt_to_dark_background: bool,
+) -> Result<Arc<RenderImage>> {
+    let (width, height) = (size.width.0 as u32, size.height.0 as u32);
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| anyhow!("invalid SVG size {width}x{height}"))?;
+
+    let svg_size = tree.size();
+    let transform = resvg::tiny_skia::Transform::from_scale(
+        width as f32 / svg_size.width(),
+        height as f32 / svg_size.height(),
+    );
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+
+    let mut pixels = pixmap.take();
+    if adapt_to_dark_background {
+        adapt_badge_to_dark_background(&mut pixels);
+    }
+    let image = RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow!("failed to rasterize SVG"))?;
+    Ok(Arc::new(to_render_image(image)))
+}
+
+/// Makes a badge drawn for light backgrounds readable on a dark one, given
+/// its premultiplied RGBA pixels: a badge in shades of gray has its
+/// lightness inverted, keeping its transparency, and a colored one is put
+/// on a light plate, since inverting it would change its colors.
+fn adapt_badge_to_dark_background(pixels: &mut [u8]) {
+    // Antialiased edges are too faint to tell the color of.
+    let is_gray = pixels.chunks_exact(4).all(|pixel| {
+        let alpha = pixel[3] as u32;
+        if alpha < 32 {
+            return true;
+        }
+        let unpremultiplied = |channel: u8| channel as u32 * 255 / alpha;
+        let (red, green, blue) = (
+            unpremultiplied(pixel[0]),
+            unpremultiplied(pixel[1]),
+            unpremultiplied(pixel[2]),
+        );
+        red.max(green).max(blue) - red.min(green).min(blue) <= 24
+    });
+
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        for (ix, channel) in pixel[..3].iter_mut().enumerate() {
+            *channel = if is_gray {
+                alpha.saturating_sub(*channel)
+            } else {
+                let plate = BADGE_PLATE_COLOR[ix] as u32 * (255 - alpha as u32) / 255;
+                (*channel as u32 + plate).min(255) as u8
+            };
+        }
+        if !is_gray {
+            pixel[3] = 255;
+        }
+    }
+}
+
+/// The size of the placeholder of an image of a [`MarkdownImage`] without a
+/// `max_height`, which can't know how tall the image will be.
+const DEFAULT_PLACEHOLDER_HEIGHT: Pixels = px(180.);
+
+/// An image in a rendered document.
+///
+/// The renderer only has a [`RenderContext`](super::markdown_renderer::RenderContext),
+/// so this element resolves the image from the [`ImageStore`] once it is laid
+/// out and a window is available, then delegates to the element for its state.
+pub struct MarkdownImage {
+    location: ImageLocation,
+    alt_text: SharedString,
+    /// The most an image is scaled down to, if anything, and the size of the
+    /// placeholder shown while it loads, so the document doesn't move when
+    /// it has.
+    max_height: Option<Pixels>,
+    placeholder_color: Hsla,
+    animate: bool,
+    /// Plays a paused animation, from the button shown over it.
+    on_play: Option<Rc<dyn Fn(&mut WindowContext)>>,
+    /// Shows an image that was scaled down at its full size.
+    on_expand: Option<Rc<dyn Fn(&mut WindowContext)>>,
+    viewer: Option<ImageViewer>,
+    compact: bool,
+    dark_background: bool,
+    load_viewport: Option<Rc<Cell<Bounds<Pixels>>>>,
+    /// Whether the image's load hasn't started, because it was laid out too
+    /// far from the viewport.
+    deferred: bool,
+}
+
+/// Whether an image laid out at `bounds` is close enough to `viewport` to
+/// start loading: in it, or within one viewport height above or below it.
+/// Every image is, before the viewport has been measured.
+fn in_load_range(bounds: Bounds<Pixels>, viewport: Bounds<Pixels>) -> bool {
+    let margin = viewport.size.height;
+    margin <= Pixels::ZERO
+        || (bounds.bottom() >= viewport.top() - margin
+            && bounds.top() <= viewport.bottom() + margin)
+}
+
+impl MarkdownImage {
+    pub fn new(
+        location: ImageLocation,
+        alt_text: SharedString,
+        max_height: Option<Pixels>,
+        placeholder_color: Hsla,
+    ) -> Self {
+        Self {
+            location,
+            alt_text,
+            max_height,
+            placeholder_color,
+            animate: true,
+            on_play: None,
+            on_expand: None,
+            viewer: None,
+            compact: false,
+            dark_background: false,
+            load_viewport: None,
+            deferred: false,
+        }
+    }
+
+    /// Whether the image is shown small while it loads, and as a chip with
+    /// its alt text if it fails to, for images that sit next to each other,
+    /// like badges.
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    /// Whether an animated image plays, or only shows its first frame.
+    pub fn animated(mut self, animate: bool) -> Self {
+        self.animate = animate;
+        self
+    }
+
+    /// Shows a play button over an animation that isn't [`Self::animated`],
+    /// which calls `callback` when it's clicked.
+    pub fn on_play(mut self, callback: impl Fn(&mut WindowContext) + 'static) -> Self {
+        self.on_play = Some(Rc::new(callback));
+        self
+    }
+
+    /// Shows a button on an image that was scaled down to the
+    /// [`max_height`](Self::new), which calls `callback` when it's clicked.
+    pub fn on_expand(mut self, callback: impl Fn(&mut WindowContext) + 'static) -> Self {
+        self.on_expand = Some(Rc::new(callback));
+        self
+    }
+
+    /// The viewer the image is shown in, which is told when it has loaded.
+    pub fn viewer(mut self, viewer: Option<ImageViewer>) -> Self {
+        self.viewer = viewer;
+        self
+    }
+
+    /// Whether the image is shown on a dark background, which SVGs that look
+    /// like badges are adapted to.
+    pub fn adapted_to_dark_background(mut self, dark_background: bool) -> Self {
+        self.dark_background = dark_background;
+        self
+    }
+
+    /// Loads the image lazily, once its placeholder is laid out near
+    /// `viewport`, the bounds of the region the document is scrolled to.
+    /// Without one, the image loads as soon as it's laid out.
+    pub fn load_viewport(mut self, viewport: Option<Rc<Cell<Bounds<Pixels>>>>) -> Self {
+        self.load_viewport = viewport;
+        self
+    }
+
+    /// The paused first frame of an animation, under a button that plays it.
+    fn render_paused(
+        &self,
+        frame: AnyElement,
+        on_play: Rc<dyn Fn(&mut WindowContext)>,
+    ) -> AnyElement {
+        div()
+            .id("play-animation")
+            .relative()
+            .cursor_pointer()
+            .child(frame)
+            .child(
+                div()
+                    .absolute()
+                    .top_0()
+                    .left_0()
+                    .size_full()
+                    .flex()
+                    .items_center()
+                    .justify_center()
+                    .child(
+                        div()
+                            .p_1()
+                            .rounded_full()
+                            .bg(self.placeholder_color)
+                            .child(Icon::new(IconName::Play).size(IconSize::Medium)),
+                    ),
+            )
+            .on_click(move |_, cx| {
+                // The click plays the image, rather than opening it.
+                cx.stop_propagation();
+                on_play(cx);
+            })
+            .into_any_element()
+    }
+
+    /// An image that was scaled down, centered, with a button that shows it
+    /// at its full size when there's an `on_expand`.
+    fn render_scaled(
+        &self,
+        image: AnyElement,
+        on_expand: Option<Rc<dyn Fn(&mut WindowContext)>>,
+    ) -> AnyElement {
+        h_flex()
+            .w_full()
+            .justify_center()
+            .child(
+                div()
+                    .relative()
+                    .child(image)
+                    .when_some(on_expand, |this, on_expand| {
+                        this.child(
+                            div().absolute().top_1().right_1().child(
+                                IconButton::new("expand-image", IconName::Maximize)
+                                    .style(ButtonStyle::Filled)
+                                    .icon_size(IconSize::Small)
+                                    .tooltip(|cx| Tooltip::text("Show Full Size", cx))
+                                    .on_click(move |_, cx| {
+                                        cx.stop_propagation();
+                                        on_expand(cx);
+                                    }),
+                            ),
+                        )
+                    }),
+            )
+            .into_any_element()
+    }
+
+    /// The height of the placeholder shown while the image loads.
+    fn placeholder_height(&self) -> Pixels {
+        self.max_height.unwrap_or(DEFAULT_PLACEHOLDER_HEIGHT)
+    }
+
+    fn render_state(&self, state: ImageState) -> AnyElement {
+        match state {
+            ImageState::Loaded(image, height) => {
+                img(ImageSource::Render(image)).h(height).into_any_element()
+            }
+            ImageState::Loading if self.compact => div()
+                .h(px(20.))
+                .w(px(80.))
+                .rounded_sm()
+                .bg(self.placeholder_color)
+                .into_any_element(),
+            ImageState::Failed(_) if self.compact => h_flex()
+                .gap_1()
+                .px_1()
+                .rounded_sm()
+                .border_1()
+                .border_color(self.placeholder_color)
+                .child(Icon::new(IconName::Warning).size(IconSize::XSmall))
+                .child(Label::new(self.alt_text.clone()).size(LabelSize::XSmall))
+                .into_any_element(),
+            ImageState::Loading => div()
+                .h(self.placeholder_height())
+                .w(self.placeholder_height())
+                .rounded_md()
+                .bg(self.placeholder_color)
+                .into_any_element(),
+            ImageState::Failed(error) => h_flex()
+                .gap_1()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .border_1()
+                .border_color(self.placeholder_color)
+                .child(Icon::new(IconName::Warning).size(IconSize::Small))
+                .child(Label::new(self.alt_text.clone()).size(LabelSize::Small))
+                .child(Label::new(error).size(LabelSize::XSmall))
+                .into_any_element(),
+        }
+    }
+}
+
+impl IntoElement for MarkdownImage {
+    type Element = Self;
+
+    fn into_element(self) -> Self::Element {
+        self
+    }
+}
+
+impl Element for MarkdownImage {
+    type RequestLayoutState = AnyElement;
+    type PrepaintState = ();
+
+    fn id(&self) -> Option<ElementId> {
+        None
+    }
+
+    fn request_layout(
+        &mut self,
+        _id: Option<&GlobalElementId>,
+        cx: &mut WindowContext,
+    ) -> (LayoutId, Self::RequestLayoutState) {
+        self.deferred = self.load_viewport.is_some()
+            && !cx
+                .try_global::<ImageStore>()
+                .is_some_and(|store| store.has_started(&self.location));
+        let state = if self.deferred {
+            ImageState::Loading
+        } else {
+            ImageStore::image(
+                &self.location,
+                self.max_height.unwrap_or(px(f32::INFINITY)),
+                self.animate,
+                self.dark_background,
+                self.viewer.as_ref(),
+                cx,
+            )
+        };
+        let store = cx.try_global::<ImageStore>();
+        let paused = matches!(state, ImageState::Loaded(..))
+            && !self.animate
+            && store.is_some_and(|store| store.is_animation(&self.location));
+        let scaled = match &state {
+            ImageState::Loaded(_, height) => store
+                .and_then(|store| store.natural_height(&self.location))
+                .is_some_and(|natural_height| natural_height > *height + px(0.5)),
+            _ => false,
+        };
+        let mut element = self.render_state(state);
+        if let Some(on_play) = self.on_play.clone().filter(|_| paused) {
+            element = self.render_paused(element, on_play);
+        }
+        // Images in a row stay where they are, and are too small to put a
+        // button on.
+        if scaled && !self.compact {
+            element = self.render_scaled(element, self.on_expand.clone());
+        }
+        let layout_id = element.request_layout(cx);
+        (layout_id, element)
+    }
+
+    fn prepaint(
+        &mut self,
+        _id: Option<&GlobalElementId>,
+        bounds: Bounds<Pixels>,
+        element: &mut Self::RequestLayoutState,
+        cx: &mut WindowContext,
+    ) {
+        let in_range = self
+            .load_viewport
+            .as_ref()
+            .map_or(true, |viewport| in_load_range(bounds, viewport.get()));
+        if self.deferred && in_range {
+            // The placeholder stays for this frame, and the viewer redraws
+            // once the image has loaded.
+            ImageStore::image(
+                &self.location,
+                self.max_height.unwrap_or(px(f32::INFINITY)),
+                self.animate,
+                self.dark_background,
+                self.viewer.as_ref(),
+                cx,
+            );
+        }
+        element.prepaint(cx);
+    }
+
+    fn paint(
+        &mut self,
+        _id: Option<&GlobalElementId>,
+        _bounds: Bounds<Pixels>,
+        element: &mut Self::RequestLayoutState,
+        _prepaint: &mut Self::PrepaintState,
+        cx: &mut WindowContext,
+    ) {
+        element.paint(cx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::FutureExt as _;
+    use gpui::{point, size};
+    use http_client::{http::Response, FakeHttpClient};
+    use pretty_assertions::assert_eq;
+    use std::{
+        future::Future as _,
+        task::{Context, Poll},
+    };
+
+    #[test]
+    fn test_decode_data_uri() {
+        assert_eq!(
+            decode_data_uri("data:image/png;base64,aGVs bG8=").unwrap(),
+            b"hello"
+        );
+        assert_eq!(
+            decode_data_uri("data:image/svg+xml,%3Csvg%3E").unwrap(),
+            b"<svg>"
+        );
+        assert!(decode_data_uri("data:image/png;base64").is_err());
+        assert!(decode_data_uri("data:image/png;base64,not base64!").is_err());
+    }
+
+    #[test]
+    fn test_data_uri_locations() {
+        let location =
+            |uri: &str| ImageLocation::from_link(&Link::identify(None, uri.to_string()).unwrap());
+        let png = "data:image/png;base64,aGVsbG8=";
+        assert_eq!(location(png), location(png));
+        assert_ne!(location(png), location("data:image/png;base64,d29ybGQ="));
+
+        let ImageLocation::Data { uri, .. } = location(png) else {
+            panic!("{png} isn't embedded");
+        };
+        assert_eq!(&*uri, png);
+    }
+
+    #[test]
+    fn test_eviction_keeps_visible_images() {
+        let location = |name: &str| ImageLocation::Path(PathBuf::from(name));
+        let mut store = ImageStore::default();
+        // Each image is 10x10 pixels, or 400 bytes.
+        for (last_used, name) in ["a.png", "b.png", "c.png"].into_iter().enumerate() {
+            let image = Arc::new(to_render_image(RgbaImage::new(10, 10)));
+            store.entries.insert(
+                location(name),
+                ImageEntry::Loaded {
+                    image: DecodedImage::Raster(image),
+                    rasterized: None,
+                    adapted: false,
+                    last_used: last_used as u64,
+                },
+            );
+        }
+        assert_eq!(store.used_bytes(), 1200);
+
+        let viewer = ImageViewer::new(|_| {});
+        store.watch(&viewer, &location("a.png"));
+        store.watch(&viewer, &location("d.png"));
+        store.set_byte_budget(800);
+        let mut kept: Vec<_> = store.entries.keys().map(|l| l.to_string()).collect();
+        kept.sort();
+        assert_eq!(kept, ["a.png", "c.png"]);
+
+        // Once the viewer stops showing it, the oldest image goes too.
+        viewer.start_frame();
+        store.set_byte_budget(400);
+        let kept: Vec<_> = store.entries.keys().map(|l| l.to_string()).collect();
+        assert_eq!(kept, ["c.png"]);
+
+        assert_eq!(store.take_waiting(&location("d.png")).len(), 1);
+        assert!(store.take_waiting(&location("d.png")).is_empty());
+        drop(viewer);
+        store.watch(&ImageViewer::new(|_| {}), &location("e.png"));
+        assert_eq!(store.viewers.len(), 1);
+    }
+
+    #[test]
+    fn test_lazy_loads() {
+        let viewport = Bounds::new(point(px(0.), px(100.)), size(px(600.), px(400.)));
+        let image_at = |y: f32| Bounds::new(point(px(0.), px(y)), size(px(80.), px(20.)));
+        assert!(in_load_range(image_at(100.), viewport));
+        assert!(in_load_range(image_at(850.), viewport));
+        assert!(in_load_range(image_at(-310.), viewport));
+        assert!(!in_load_range(image_at(1000.), viewport));
+        assert!(!in_load_range(image_at(-400.), viewport));
+        // Before the viewport is measured, like in the first frame, images
+        // aren't held back.
+        assert!(in_load_range(image_at(5000.), Bounds::default()));
+
+        // A load the viewer stops showing is cancelled a frame later.
+        let location = ImageLocation::Path(PathBuf::from("a.png"));
+        let mut store = ImageStore::default();
+        let task = Rc::new(Task::ready(()));
+        store.entries.insert(
+            location.clone(),
+            ImageEntry::Loading {
+                task: Rc::downgrade(&task),
+                unowned: None,
+            },
+        );
+        let viewer = ImageViewer::new(|_| {});
+        store.watch(&viewer, &location);
+        drop(task);
+        viewer.start_frame();
+        assert!(store.has_started(&location));
+        viewer.start_frame();
+        assert!(!store.has_started(&location));
+        assert!(store.take_waiting(&location).is_empty());
+    }
+
+    #[test]
+    fn test_pixel_density() {
+        let density = |location: ImageLocation| location.pixel_density();
+        assert_eq!(
+            density(ImageLocation::Web("https://x.dev/logo@2x.png?v=1".into())),
+            2.
+        );
+        assert_eq!(density(ImageLocation::Path("docs/shot@3x.jpg".into())), 3.);
+        assert_eq!(density(ImageLocation::Path("me@home/shot.png".into())), 1.);
+        assert_eq!(
+            density(ImageLocation::Web("https://x.dev/logo.png".into())),
+            1.
+        );
+
+        // A 2x image is laid out at half its size, unless that's too tall.
+        let image = to_render_image(RgbaImage::new(40, 100));
+        assert_eq!(raster_display_height(&image, 2., px(180.)), px(50.));
+        assert_eq!(raster_display_height(&image, 1., px(80.)), px(80.));
+    }
+
+    #[test]
+    fn test_natural_height() {
+        let location = ImageLocation::Path(PathBuf::from("shot@2x.png"));
+        let mut store = ImageStore::default();
+        assert_eq!(store.natural_height(&location), None);
+
+        // A tall screenshot keeps its height, to tell that it was scaled
+        // down to the maximum.
+        let image = Arc::new(to_render_image(RgbaImage::new(40, 2000)));
+        store.entries.insert(
+            location.clone(),
+            ImageEntry::Loaded {
+                image: DecodedImage::Raster(image.clone()),
+                rasterized: None,
+                adapted: false,
+                last_used: 0,
+            },
+        );
+        assert_eq!(store.natural_height(&location), Some(px(1000.)));
+        assert_eq!(raster_display_height(&image, 2., px(180.)), px(180.));
+        assert!(!store.is_animation(&location));
+    }
+
+    #[test]
+    fn test_adapt_badge_to_dark_background() {
+        // Black text with a half-transparent edge, on nothing.
+        let mut gray = vec![0, 0, 0, 255, 0, 0, 0, 128, 0, 0, 0, 0];
+        adapt_badge_to_dark_background(&mut gray);
+        assert_eq!(
+            gray,
+            vec![255, 255, 255, 255, 128, 128, 128, 128, 0, 0, 0, 0]
+        );
+
+        // Inverting blue would make it orange, so it's put on the plate.
+        let mut colored = vec![0x03, 0x66, 0xd6, 255, 0, 0, 0, 255, 0, 0, 0, 0];
+        adapt_badge_to_dark_background(&mut colored);
+        let [red, green, blue] = BADGE_PLATE_COLOR;
+        assert_eq!(
+            colored,
+            vec![0x03, 0x66, 0xd6, 255, 0, 0, 0, 255, red, green, blue, 255]
+        );
+    }
+
+    #[test]
+    fn test_fetch_slots_take_turns() {
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let slots = FetchSlots::default();
+        let first = slots.acquire(1).now_or_never().unwrap();
+        let mut second = Box::pin(slots.acquire(1));
+        let mut third = Box::pin(slots.acquire(1));
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        assert!(third.as_mut().poll(&mut cx).is_pending());
+
+        // A download that's cancelled while it waits passes its turn on.
+        drop(second);
+        drop(first);
+        let Poll::Ready(third) = third.as_mut().poll(&mut cx) else {
+            panic!("the third download didn't get a turn");
+        };
+        assert_eq!(slots.0.lock().unwrap().in_use, 1);
+        drop(third);
+        assert_eq!(slots.0.lock().unwrap().in_use, 0);
+    }
+
+    /// A client that answers each request with `respond`, given its URL,
+    /// and records the URL and `Authorization` header of every request.
+    #[allow(clippy::type_complexity)]
+    fn recording_client(
+        respond: impl Fn(&str) -> Response<AsyncBody> + Send + Sync + 'static,
+    ) -> (
+        Arc<dyn HttpClient>,
+        Arc<Mutex<Vec<(String, Option<String>)>>>,
+    ) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let client = FakeHttpClient::create({
+            let requests = requests.clone();
+            move |request| {
+                let url = request.uri().to_string();
+                let authorization = request
+                    .headers()
+                    .get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                requests.lock().unwrap().push((url.clone(), authorization));
+                let response = respond(&url);
+                async move { Ok(response) }
+            }
+        });
+        (client, requests)
+    }
+
+    fn image_response() -> Response<AsyncBody> {
+        Response::builder()
+            .status(200)
+            .body(AsyncBody::from(vec![1, 2, 3]))
+            .unwrap()
+    }
+
+    fn redirect_response(location: &str) -> Response<AsyncBody> {
+        Response::builder()
+            .status(302)
+            .header(header::LOCATION, location)
+            .body(AsyncBody::default())
+            .unwrap()
+    }
+
+    fn authorization(token: &str) -> Vec<(String, String)> {
+        vec![("Authorization".to_string(), format!("Bearer {token}"))]
+    }
+
+    #[gpui::test]
+    async fn test_request_headers_only_go_to_their_origin() {
+        let mut headers = RequestHeaders::default();
+        headers
+            .insert("https://images.example.com", authorization("secret"))
+            .unwrap();
+        let (client, requests) = recording_client(|_| image_response());
+
+        for url in [
+            "https://images.example.com/a.png",
+            "https://attacker.example/x.png",
+            // Another scheme is another origin.
+            "http://images.example.com/b.png",
+        ] {
+            let bytes = download_image(url, &headers, client.clone(), 1024).await;
+            assert_eq!(bytes.ok(), Some(vec![1, 2, 3]));
+        }
+        assert_eq!(
+            requests.lock().unwrap().clone(),
+            vec![
+                (
+                    "https://images.example.com/a.png".to_string(),
+                    Some("Bearer secret".to_string())
+                ),
+                ("https://attacker.example/x.png".to_string(), None),
+                ("http://images.example.com/b.png".to_string(), None),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_request_headers_are_dropped_on_cross_origin_redirects() {
+        let mut headers = RequestHeaders::default();
+        headers
+            .insert("https://images.example.com", authorization("secret"))
+            .unwrap();
+        headers
+            .insert("https://cdn.example.net", authorization("other"))
+            .unwrap();
+        let (client, requests) = recording_client(|url| match url {
+            "https://images.example.com/a.png" => redirect_response("/b.png"),
+            "https://images.example.com/b.png" => {
+                redirect_response("https://cdn.example.net/b.png")
+            }
+            _ => image_response(),
+        });
+
+        let bytes =
+            download_image("https://images.example.com/a.png", &headers, client, 1024).await;
+        assert_eq!(bytes.ok(), Some(vec![1, 2, 3]));
+        // Once redirected to another origin, nothing is sent, not even that
+        // origin's own headers.
+        assert_eq!(
+            requests.lock().unwrap().clone(),
+            vec![
+                (
+                    "https://images.example.com/a.png".to_string(),
+                    Some("Bearer secret".to_string())
+                ),
+                (
+                    "https://images.example.com/b.png".to_string(),
+                    Some("Bearer secret".to_string())
+                ),
+                ("https://cdn.example.net/b.png".to_string(), None),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_redirects_are_capped() {
+        let (client, requests) = recording_client(|_| redirect_response("/loop.png"));
+
+        let result = download_image(
+            "https://images.example.com/loop.png",
+            &RequestHeaders::default(),
+            client,
+            1024,
+        )
+        .await;
+        let Err(error) = result else {
+            panic!("the redirects were followed forever");
+        };
+        assert_eq!(
+            error.error.to_string(),
+            "the server redirected too many times"
+        );
+        assert!(!error.transient);
+        assert_eq!(requests.lock().unwrap().len(), MAX_REDIRECTS + 1);
+    }
+}
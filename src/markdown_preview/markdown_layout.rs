@@ -0,0 +1,207 @@
+//! Lays documents out without a window on screen, to check how they render
+//! in tests and tools.
+
+use super::{
+    markdown_elements::ParsedMarkdown,
+    markdown_renderer::{
+        render_markdown_block, BlockLayouts, LaidOutText, MarkdownStyle, RenderContext,
+    },
+};
+use gpui::{
+    div, point, px, size, AvailableSpace, ParentElement, Pixels, Styled, VisualTestContext,
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// How a document was laid out by [`layout_markdown`].
+#[derive(Debug, Clone)]
+pub struct MarkdownLayout {
+    pub blocks: Vec<BlockLayout>,
+}
+
+/// A top-level block, and the text in it.
+#[derive(Debug, Clone)]
+pub struct BlockLayout {
+    pub block_ix: usize,
+    pub height: Pixels,
+    /// The paragraphs, headings and other text of the block, in the order
+    /// they were laid out. Code blocks aren't wrapped, and aren't included.
+    pub texts: Vec<LaidOutText>,
+}
+
+impl MarkdownLayout {
+    pub fn total_height(&self) -> Pixels {
+        self.blocks
+            .iter()
+            .fold(Pixels::ZERO, |total, block| total + block.height)
+    }
+}
+
+/// Lays the blocks of `parsed` out `width` wide, the way a
+/// [`MarkdownView`](super::markdown_view::MarkdownView) would, and returns
+/// how tall each is and where its text wraps.
+///
+/// The blocks are drawn by [`render_markdown_block`] in `cx`, a window of the
+/// headless test platform, so they're shaped with the same fonts and wrapped
+/// by the same rules as on screen. The application's theme and settings
+/// have to be set up first, as for any [`RenderContext`].
+pub fn layout_markdown(
+    parsed: &ParsedMarkdown,
+    style: &MarkdownStyle,
+    width: Pixels,
+    cx: &mut VisualTestContext,
+) -> MarkdownLayout {
+    let block_layouts = Rc::new(RefCell::new(BlockLayouts::default()));
+    let blocks = parsed
+        .children
+        .iter()
+        .enumerate()
+        .map(|(block_ix, block)| {
+            let texts = Rc::new(RefCell::new(Vec::new()));
+            cx.draw(
+                point(px(0.), px(0.)),
+                size(AvailableSpace::Definite(width), AvailableSpace::MinContent),
+                |cx| {
+                    let mut render_cx = RenderContext::new(cx)
+                        .with_style(style)
+                        .with_block_index(block_ix)
+                        .with_block_layouts(block_layouts.clone())
+                        .with_text_layouts(texts.clone());
+                    let element = render_markdown_block(block, &mut render_cx);
+                    div().w(width).child(render_cx.track_block_layout(element))
+                },
+            );
+            BlockLayout {
+                block_ix,
+                height: block_layouts.borrow().height(block_ix).unwrap_or_default(),
+                texts: texts.take(),
+            }
+        })
+        .collect();
+    MarkdownLayout { blocks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use crate::markdown_preview::markdown_renderer::RenderMode;
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+    use settings::SettingsStore;
+    use theme::LoadThemes;
+
+    #[gpui::test]
+    async fn test_layout_markdown(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+        let cx = cx.add_empty_window();
+        let parsed = parse_markdown(
+            "# A heading long enough to wrap in a narrow window\n\nShort **bold** text\n",
+            None,
+            None,
+        )
+        .await;
+
+        let wide = layout_markdown(&parsed, &MarkdownStyle::default(), px(2000.), cx);
+        let narrow = layout_markdown(&parsed, &MarkdownStyle::default(), px(200.), cx);
+        assert_eq!(
+            wide.blocks[0].texts[0].lines,
+            ["A heading long enough to wrap in a narrow window"]
+        );
+        assert!(narrow.blocks[0].texts[0].lines.len() >= 2);
+        assert_eq!(
+            narrow.blocks[0].texts[0].lines.concat().replace(' ', ""),
+            "Aheadinglongenoughtowrapinanarrowwindow"
+        );
+        assert!(narrow.blocks[0].height > wide.blocks[0].height);
+
+        let paragraph = &narrow.blocks[1].texts[0];
+        assert_eq!(paragraph.lines, ["Short bold text"]);
+        assert_eq!(
+            paragraph.runs.iter().map(|run| run.len).collect::<Vec<_>>(),
+            [6, 4, 5]
+        );
+        assert!(narrow.total_height() > Pixels::ZERO);
+    }
+
+    #[gpui::test]
+    async fn test_long_words_wrap(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+        let cx = cx.add_empty_window();
+        let url = format!("https://example.com/{}", "a".repeat(280));
+        let hash = "0123456789abcdef".repeat(8);
+        let text = format!("# {hash}\n\n- see [{url}]({url})\n");
+        let parsed = parse_markdown(&text, None, None).await;
+
+        let width = px(300.);
+        let layout = layout_markdown(&parsed, &MarkdownStyle::default(), width, cx);
+        let heading = &layout.blocks[0].texts[0];
+        assert!(heading.lines.len() >= 2);
+        assert_eq!(heading.lines.concat(), hash);
+        assert!(heading.bounds.right() <= width);
+
+        // The link breaks across lines, but stays one run, so it's one
+        // target with one underline.
+        let item = &layout.blocks[1].texts[0];
+        assert!(item.lines.len() >= 2);
+        assert_eq!(item.lines.concat().replace(' ', ""), format!("see{url}"));
+        assert!(item.bounds.right() <= width);
+        assert_eq!(
+            item.runs.iter().map(|run| run.len).collect::<Vec<_>>(),
+            [4, url.len()]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_inline_mode(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+        let cx = cx.add_empty_window();
+
+        for text in [
+            "**Bold** and a [link](https://example.com)\n",
+            "## A heading\n",
+        ] {
+            let parsed = parse_markdown(text, None, None).await;
+            let block_layouts = Rc::new(RefCell::new(BlockLayouts::default()));
+            let texts = Rc::new(RefCell::new(Vec::new()));
+            cx.draw(
+                point(px(0.), px(0.)),
+                size(
+                    AvailableSpace::Definite(px(400.)),
+                    AvailableSpace::MinContent,
+                ),
+                |cx| {
+                    let mut render_cx = RenderContext::new(cx)
+                        .with_render_mode(RenderMode::Inline)
+                        .with_block_layouts(block_layouts.clone())
+                        .with_text_layouts(texts.clone());
+                    let element = render_markdown_block(&parsed.children[0], &mut render_cx);
+                    render_cx.track_block_layout(element)
+                },
+            );
+
+            // The text is all there is to the block, with no space above or
+            // below it.
+            let block = block_layouts.borrow().bounds(0).unwrap();
+            let texts = texts.borrow();
+            assert_eq!(texts.len(), 1);
+            assert_eq!(texts[0].bounds.top(), block.top(), "{text}");
+            assert_eq!(texts[0].bounds.size.height, block.size.height, "{text}");
+        }
+    }
+}
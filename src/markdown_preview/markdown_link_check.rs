@@ -0,0 +1,217 @@
+use super::{markdown_diagnostics::redact_url, markdown_elements::Link};
+use anyhow::{anyhow, Result};
+use collections::FxHashMap;
+use futures::{
+    future::{self, Either},
+    stream, Stream, StreamExt as _,
+};
+use gpui::{BackgroundExecutor, Global, SharedString};
+use http_client::{
+    http::{Method, Request, StatusCode},
+    AsyncBody, HttpClient, HttpRequestExt as _, RedirectPolicy,
+};
+use std::{sync::Arc, time::Duration};
+
+/// How many links to the web are checked at once.
+const MAX_CONCURRENT_CHECKS: usize = 8;
+/// How long a server has to answer before its link counts as broken.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether a link works, or why it doesn't.
+pub type LinkHealth = Result<(), SharedString>;
+
+/// The links to the web that were checked, for every document, so that each
+/// URL is only requested once a session.
+#[derive(Default)]
+pub struct LinkCheckCache {
+    results: FxHashMap<String, LinkHealth>,
+}
+
+impl Global for LinkCheckCache {}
+
+impl LinkCheckCache {
+    pub fn get(&self, url: &str) -> Option<&LinkHealth> {
+        self.results.get(url)
+    }
+
+    pub fn insert(&mut self, url: String, health: LinkHealth) {
+        self.results.insert(url, health);
+    }
+}
+
+/// Whether `link` is checked over the network: links to web pages are, and
+/// other URLs, like `mailto:` ones, aren't checked at all.
+pub fn is_checked_online(link: &Link) -> bool {
+    matches!(link, Link::Web { url } if url.starts_with("https://") || url.starts_with("http://"))
+}
+
+/// Whether the file `link` points to exists. Links to headings in the same
+/// document are checked by the view, which knows them.
+pub fn check_path(link: &Link) -> Option<LinkHealth> {
+    let Link::Path { path, .. } = link else {
+        return None;
+    };
+    Some(if path.exists() {
+        Ok(())
+    } else {
+        Err("No such file".into())
+    })
+}
+
+/// Checks every one of `urls`, a few at a time, yielding each with whether
+/// it works as soon as that's known. Dropping the stream cancels the checks
+/// still running.
+pub fn check_urls(
+    urls: Vec<String>,
+    http_client: Arc<dyn HttpClient>,
+    executor: BackgroundExecutor,
+) -> impl Stream<Item = (String, LinkHealth)> {
+    stream::iter(urls)
+        .map(move |url| {
+            let http_client = http_client.clone();
+            let executor = executor.clone();
+            async move {
+                let request = Box::pin(check_url(&url, http_client));
+                let health = match future::select(request, executor.timer(CHECK_TIMEOUT)).await {
+                    Either::Left((health, _)) => health.map_err(|error| {
+                        log::info!("link to {} is broken: {:#}", redact_url(&url), error);
+                        SharedString::from(format!("{error:#}"))
+                    }),
+                    Either::Right(_) => Err(format!(
+                        "The server didn't answer within {} seconds",
+                        CHECK_TIMEOUT.as_secs()
+                    )
+                    .into()),
+                };
+                (url, health)
+            }
+        })
+        .buffer_unordered(MAX_CONCURRENT_CHECKS)
+}
+
+/// Asks for the headers of the page at `url`, or for the page itself from
+/// servers that don't answer such requests. Redirects are followed, so that a
+/// link to a page that moved works if the page it moved to does.
+async fn check_url(url: &str, http_client: Arc<dyn HttpClient>) -> Result<()> {
+    let request = Request::builder()
+        .method(Method::HEAD)
+        .uri(url)
+        .follow_redirects(RedirectPolicy::FollowAll)
+        .body(AsyncBody::default())?;
+    let mut status = http_client.send(request).await?.status();
+    if matches!(
+        status,
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+        status = http_client
+            .get(url, AsyncBody::default(), true)
+            .await?
+            .status();
+    }
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!("The server responded with {}", status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use http_client::{http::Response, FakeHttpClient};
+    use pretty_assertions::assert_eq;
+    use std::{path::PathBuf, sync::Mutex};
+
+    /// A client that answers every request with `respond`'s status, and the
+    /// methods of the requests it was sent.
+    fn recording_client(
+        respond: impl Fn(&Request<AsyncBody>) -> u16 + Send + Sync + 'static,
+    ) -> (Arc<dyn HttpClient>, Arc<Mutex<Vec<Method>>>) {
+        let methods = Arc::new(Mutex::new(Vec::new()));
+        let client = FakeHttpClient::create({
+            let methods = methods.clone();
+            move |request| {
+                methods.lock().unwrap().push(request.method().clone());
+                let response = Response::builder()
+                    .status(respond(&request))
+                    .body(AsyncBody::default())
+                    .unwrap();
+                async move { Ok(response) }
+            }
+        });
+        (client, methods)
+    }
+
+    #[gpui::test]
+    async fn test_check_url_follows_redirects() {
+        // The fake client doesn't follow redirects itself, so it answers
+        // with where they end up when asked to follow them.
+        let (client, methods) = recording_client(|request| {
+            let follows = matches!(request.extensions().get(), Some(RedirectPolicy::FollowAll));
+            if follows {
+                200
+            } else {
+                301
+            }
+        });
+        assert!(check_url("https://zed.dev/old", client).await.is_ok());
+        assert_eq!(methods.lock().unwrap().clone(), vec![Method::HEAD]);
+    }
+
+    #[gpui::test]
+    async fn test_check_url_falls_back_to_get() {
+        let (client, methods) = recording_client(|request| match *request.method() {
+            Method::HEAD => 405,
+            _ => 200,
+        });
+        assert!(check_url("https://zed.dev", client).await.is_ok());
+        assert_eq!(
+            methods.lock().unwrap().clone(),
+            vec![Method::HEAD, Method::GET]
+        );
+
+        let (client, _) = recording_client(|request| match *request.method() {
+            Method::HEAD => 405,
+            _ => 404,
+        });
+        assert_eq!(
+            check_url("https://zed.dev/missing", client)
+                .await
+                .map_err(|error| error.to_string()),
+            Err("The server responded with 404 Not Found".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_path() {
+        let path = |path: PathBuf| Link::Path {
+            display_path: path.clone(),
+            path,
+            fragment: None,
+        };
+        assert_eq!(
+            check_path(&path(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml")
+            )),
+            Some(Ok(()))
+        );
+        assert_eq!(
+            check_path(&path(PathBuf::from("/no/such/file.md"))),
+            Some(Err("No such file".into()))
+        );
+        assert_eq!(
+            check_path(&Link::Fragment {
+                fragment: "setup".to_string()
+            }),
+            None
+        );
+
+        assert!(is_checked_online(&Link::Web {
+            url: "https://zed.dev".to_string()
+        }));
+        assert!(!is_checked_online(&Link::Web {
+            url: "mailto:me@example.com".to_string()
+        }));
+    }
+}
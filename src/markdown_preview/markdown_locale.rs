@@ -0,0 +1,309 @@
+use std::sync::OnceLock;
+
+/// How dates, numbers and yes or no are written for the reader, in the front
+/// matter card and the status bar.
+///
+/// Formatting loses what was written, like whether a date had a time zone,
+/// so the raw values stay a click away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    /// `March 5, 2024`, `12,345.6`
+    EnglishUs,
+    /// `5 March 2024`, `12,345.6`
+    EnglishGb,
+    /// `5. März 2024`, `12.345,6`
+    German,
+    /// `5 mars 2024`, `12 345,6`
+    French,
+    /// `5 de marzo de 2024`, `12.345,6`
+    Spanish,
+}
+
+impl Locale {
+    /// The locale named by a language tag like `en-GB`, or a POSIX locale
+    /// like `de_DE.UTF-8`. Languages without a locale of their own here have
+    /// none.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.split(['.', '@']).next()?.to_ascii_lowercase();
+        let mut parts = tag.split(['-', '_']);
+        let language = parts.next()?;
+        let region = parts.next().unwrap_or_default();
+        Some(match language {
+            "en" if matches!(region, "gb" | "ie" | "au" | "nz" | "in" | "za") => Self::EnglishGb,
+            "en" => Self::EnglishUs,
+            "de" => Self::German,
+            "fr" => Self::French,
+            "es" => Self::Spanish,
+            _ => return None,
+        })
+    }
+
+    /// The locale of the environment the application was started in.
+    ///
+    /// gpui doesn't tell apps about the system's locale, so this reads the
+    /// `LC_ALL`, `LC_TIME` and `LANG` variables, and is US English without
+    /// them.
+    pub fn system() -> Self {
+        static SYSTEM: OnceLock<Locale> = OnceLock::new();
+        *SYSTEM.get_or_init(|| {
+            ["LC_ALL", "LC_TIME", "LANG"]
+                .into_iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|value| !value.is_empty())
+                .and_then(|value| Self::parse(&value))
+                .unwrap_or(Self::EnglishUs)
+        })
+    }
+
+    /// Formats a date written as `2024-03-05`, optionally followed by a time
+    /// like `T09:30:00Z`, like `March 5, 2024` or `March 5, 2024, 09:30`.
+    /// Returns `None` for text that isn't a date.
+    pub fn format_date(self, text: &str) -> Option<String> {
+        let date = text.get(..10)?;
+        let mut parts = date.split('-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+        let year: u32 = year.parse().ok()?;
+        let month: usize = month.parse().ok()?;
+        let day: u32 = day.parse().ok()?;
+        let month_name = self.month_names().get(month.checked_sub(1)?)?;
+        if !(1..=31).contains(&day) {
+            return None;
+        }
+
+        let date = match self {
+            Self::EnglishUs => format!("{} {}, {}", month_name, day, year),
+            Self::EnglishGb | Self::French => format!("{} {} {}", day, month_name, year),
+            Self::German => format!("{}. {} {}", day, month_name, year),
+            Self::Spanish => format!("{} de {} de {}", day, month_name, year),
+        };
+        let time = &text[10..];
+        if time.is_empty() {
+            return Some(date);
+        }
+        let time = time.strip_prefix(['T', ' '])?;
+        let hours_and_minutes = time.get(..5).filter(|time| {
+            time.as_bytes()[2] == b':' && time.chars().filter(char::is_ascii_digit).count() == 4
+        })?;
+        Some(format!("{}, {}", date, hours_and_minutes))
+    }
+
+    /// Groups the digits of a number written like `1234567.5`, as
+    /// `1,234,567.5`. Numbers of four digits or fewer, which are as often
+    /// years or codes as amounts, are only given the locale's decimal
+    /// separator. Returns `None` for text that isn't a number.
+    pub fn format_number(self, text: &str) -> Option<String> {
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", text),
+        };
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (digits, None),
+        };
+        let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        // Leading zeros, like in `007` or a postal code, aren't amounts.
+        if !is_digits(integer)
+            || (integer.len() > 1 && integer.starts_with('0'))
+            || !fraction.map_or(true, is_digits)
+        {
+            return None;
+        }
+
+        let mut formatted = sign.to_string();
+        if integer.len() > 4 {
+            for (ix, digit) in integer.chars().enumerate() {
+                if ix > 0 && (integer.len() - ix) % 3 == 0 {
+                    formatted.push_str(self.group_separator());
+                }
+                formatted.push(digit);
+            }
+        } else {
+            formatted.push_str(integer);
+        }
+        if let Some(fraction) = fraction {
+            formatted.push(self.decimal_separator());
+            formatted.push_str(fraction);
+        }
+        Some(formatted)
+    }
+
+    /// Formats a count, like the words in the document, with its digits
+    /// grouped.
+    pub fn format_count(self, count: usize) -> String {
+        let count = count.to_string();
+        self.format_number(&count).unwrap_or(count)
+    }
+
+    /// `Yes` or `No` for a value written as `true` or `false`.
+    pub fn format_bool(self, text: &str) -> Option<&'static str> {
+        let value = match text {
+            "true" => true,
+            "false" => false,
+            _ => return None,
+        };
+        Some(match (self, value) {
+            (Self::EnglishUs | Self::EnglishGb, true) => "Yes",
+            (Self::EnglishUs | Self::EnglishGb, false) => "No",
+            (Self::German, true) => "Ja",
+            (Self::German, false) => "Nein",
+            (Self::French, true) => "Oui",
+            (Self::French, false) => "Non",
+            (Self::Spanish, true) => "Sí",
+            (Self::Spanish, false) => "No",
+        })
+    }
+
+    /// A front matter value as the reader would write it, if it's a date, a
+    /// number or a boolean. Returns `None` for other text, which is shown as
+    /// it was written.
+    pub fn format_value(self, text: &str) -> Option<String> {
+        self.format_date(text)
+            .or_else(|| self.format_number(text))
+            .or_else(|| self.format_bool(text).map(str::to_string))
+            .filter(|formatted| formatted != text)
+    }
+
+    fn month_names(self) -> [&'static str; 12] {
+        match self {
+            Self::EnglishUs | Self::EnglishGb => [
+                "January",
+                "February",
+                "March",
+                "April",
+                "May",
+                "June",
+                "July",
+                "August",
+                "September",
+                "October",
+                "November",
+                "December",
+            ],
+            Self::German => [
+                "Januar",
+                "Februar",
+                "März",
+                "April",
+                "Mai",
+                "Juni",
+                "Juli",
+                "August",
+                "September",
+                "Oktober",
+                "November",
+                "Dezember",
+            ],
+            Self::French => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            Self::Spanish => [
+                "enero",
+                "febrero",
+                "marzo",
+                "abril",
+                "mayo",
+                "junio",
+                "julio",
+                "agosto",
+                "septiembre",
+                "octubre",
+                "noviembre",
+                "diciembre",
+            ],
+        }
+    }
+
+    fn group_separator(self) -> &'static str {
+        match self {
+            Self::EnglishUs | Self::EnglishGb => ",",
+            Self::German | Self::Spanish => ".",
+            // A narrow no-break space, so a number isn't wrapped.
+            Self::French => "\u{202f}",
+        }
+    }
+
+    fn decimal_separator(self) -> char {
+        match self {
+            Self::EnglishUs | Self::EnglishGb => '.',
+            Self::German | Self::French | Self::Spanish => ',',
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_date() {
+        let us = Locale::EnglishUs;
+        assert_eq!(
+            us.format_date("2024-03-05").as_deref(),
+            Some("March 5, 2024")
+        );
+        assert_eq!(
+            us.format_date("2024-12-31T09:30:00Z").as_deref(),
+            Some("December 31, 2024, 09:30")
+        );
+        assert_eq!(us.format_date("2024-13-01"), None);
+        assert_eq!(us.format_date("2024-03-05 is a date"), None);
+        assert_eq!(us.format_date("Release notes"), None);
+
+        assert_eq!(
+            Locale::EnglishGb.format_date("2024-03-05").as_deref(),
+            Some("5 March 2024")
+        );
+        assert_eq!(
+            Locale::German.format_date("2024-03-05").as_deref(),
+            Some("5. März 2024")
+        );
+        assert_eq!(
+            Locale::Spanish.format_date("2024-03-05 18:00").as_deref(),
+            Some("5 de marzo de 2024, 18:00")
+        );
+    }
+
+    #[test]
+    fn test_format_values() {
+        let us = Locale::EnglishUs;
+        assert_eq!(us.format_number("1234567").as_deref(), Some("1,234,567"));
+        assert_eq!(us.format_number("-12345.75").as_deref(), Some("-12,345.75"));
+        assert_eq!(
+            Locale::German.format_number("12345.5").as_deref(),
+            Some("12.345,5")
+        );
+        assert_eq!(
+            Locale::French.format_number("123456").as_deref(),
+            Some("123\u{202f}456")
+        );
+        assert_eq!(Locale::German.format_count(25000), "25.000");
+
+        // Years, versions and codes are left as they are.
+        assert_eq!(us.format_value("2024"), None);
+        assert_eq!(us.format_value("1.2.3"), None);
+        assert_eq!(us.format_value("01234"), None);
+        assert_eq!(us.format_value("true").as_deref(), Some("Yes"));
+        assert_eq!(Locale::French.format_value("false").as_deref(), Some("Non"));
+
+        assert_eq!(Locale::parse("en_GB.UTF-8"), Some(Locale::EnglishGb));
+        assert_eq!(Locale::parse("en"), Some(Locale::EnglishUs));
+        assert_eq!(Locale::parse("de-AT"), Some(Locale::German));
+        assert_eq!(Locale::parse("C"), None);
+    }
+}
@@ -1,29 +1,1071 @@
-use super::markdown_elements::*;
+use super::{
+    markdown_elements::*, markdown_front_matter::FrontMatter, markdown_html_parser::parse_html,
+};
 use async_recursion::async_recursion;
-use collections::FxHashMap;
-use gpui::FontWeight;
-use language::LanguageRegistry;
-use pulldown_cmark::{Alignment, Event, Options, Parser, Tag, TagEnd};
-use std::{ops::Range, path::PathBuf, sync::Arc};
+use collections::{FxHashMap, FxHashSet};
+use gpui::{BackgroundExecutor, FontWeight};
+use language::{HighlightId, LanguageRegistry};
+use pulldown_cmark::{Alignment, Event, LinkType, MetadataBlockKind, Options, Parser, Tag, TagEnd};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    task::Poll,
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// The parser lets other work run on its executor after about this many
+/// events, which is a few hundred blocks of prose.
+const EVENTS_PER_CHUNK: usize = 4096;
+
+/// [`parse_markdown_parallel`] gives each thread at least this many events,
+/// since for fewer, handing them out costs about as much as it saves.
+const MIN_EVENTS_PER_THREAD: usize = 16 * 1024;
+
+/// Called with runs of top-level blocks as they're parsed.
+type BlocksCallback<'a> = &'a mut (dyn FnMut(&[ParsedMarkdownElement]) + Send);
+
+/// The Markdown extensions [`parse_markdown_with_options`] recognizes on top
+/// of CommonMark. All of them but includes are on by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub task_lists: bool,
+    /// Turns straight quotes into curly ones, `--` into dashes and `...`
+    /// into ellipses.
+    pub smart_punctuation: bool,
+    /// `{#id .class}` after a heading's text.
+    pub heading_attributes: bool,
+    /// `$inline$` and `$$display$$` math.
+    pub math: bool,
+    /// Replaces paragraphs like `!include(./section.md)` with the blocks of
+    /// the file they name, relative to the document. Off by default, since
+    /// it reads other files. Only files in the document's folder, or in
+    /// folders inside of it, can be included.
+    pub includes: bool,
+    /// How deeply included files can include others in turn.
+    pub max_include_depth: usize,
+    /// The file the document was read from, if any, so that it can't include
+    /// itself.
+    pub document_path: Option<PathBuf>,
+    /// What relative links and images resolve against in documents that
+    /// aren't files, like a README fetched from
+    /// `https://raw.githubusercontent.com/org/repo/main/`.
+    pub base_url: Option<Url>,
+    /// Paragraphs longer than this many bytes, like a minified document
+    /// that's all one line, are split into consecutive paragraphs at the
+    /// first space past each this many bytes, so that none of them takes
+    /// long to lay out.
+    pub max_paragraph_len: usize,
+    /// Shows every line break in a paragraph, the way GitHub shows comments,
+    /// instead of joining the lines with spaces. List items, tables and code
+    /// are unaffected.
+    pub hard_wrap: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            task_lists: true,
+            smart_punctuation: true,
+            heading_attributes: true,
+            math: true,
+            includes: false,
+            max_include_depth: 8,
+            document_path: None,
+            base_url: None,
+            max_paragraph_len: 16 * 1024,
+            hard_wrap: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub(crate) fn pulldown_options(&self) -> Options {
+        let mut options = Options::all();
+        options.remove(Options::ENABLE_DEFINITION_LIST);
+        let extensions = [
+            (self.tables, Options::ENABLE_TABLES),
+            (
+                self.footnotes,
+                Options::ENABLE_FOOTNOTES | Options::ENABLE_OLD_FOOTNOTES,
+            ),
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.task_lists, Options::ENABLE_TASKLISTS),
+            (self.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+            (self.heading_attributes, Options::ENABLE_HEADING_ATTRIBUTES),
+            (self.math, Options::ENABLE_MATH),
+        ];
+        for (enabled, extension) in extensions {
+            if !enabled {
+                options.remove(extension);
+            }
+        }
+        options
+    }
+}
+
+/// Where the time went while parsing a document, from
+/// [`parse_markdown_timed`].
+///
+/// The parts don't overlap, so together they make up about all of `total`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ParseTiming {
+    /// Tokenizing the document with pulldown-cmark, which finds its blocks
+    /// and the inline markup in them.
+    pub block_scan: Duration,
+    /// Turning the inline markup of paragraphs, headings and list items into
+    /// styled text.
+    pub inline: Duration,
+    /// Tables, including the text in their cells.
+    pub tables: Duration,
+    /// Code blocks, including syntax highlighting them.
+    pub code_blocks: Duration,
+    /// What the extensions on top of CommonMark cost, by name: `highlights`,
+    /// `html`, `front_matter`, `includes` and `heading_slugs`. Extensions
+    /// that were never needed aren't listed.
+    pub extensions: BTreeMap<&'static str, Duration>,
+    pub total: Duration,
+}
+
+impl ParseTiming {
+    fn add(&mut self, other: ParseTiming) {
+        self.block_scan += other.block_scan;
+        self.inline += other.inline;
+        self.tables += other.tables;
+        self.code_blocks += other.code_blocks;
+        for (name, duration) in other.extensions {
+            *self.extensions.entry(name).or_default() += duration;
+        }
+        self.total += other.total;
+    }
+
+    /// The time spent in all the parts but `total`.
+    fn accounted(&self) -> Duration {
+        self.block_scan
+            + self.inline
+            + self.tables
+            + self.code_blocks
+            + self.extensions.values().sum::<Duration>()
+    }
+}
+
+/// Adds the time since `started_at` to the part of `timing` that `part`
+/// picks, when the parse is being timed.
+fn record_time(
+    timing: Option<&mut ParseTiming>,
+    started_at: Option<Instant>,
+    part: impl FnOnce(&mut ParseTiming) -> &mut Duration,
+) {
+    if let (Some(timing), Some(started_at)) = (timing, started_at) {
+        *part(timing) += started_at.elapsed();
+    }
+}
 
 pub async fn parse_markdown(
     markdown_input: &str,
     file_location_directory: Option<PathBuf>,
     language_registry: Option<Arc<LanguageRegistry>>,
 ) -> ParsedMarkdown {
-    let mut options = Options::all();
-    options.remove(pulldown_cmark::Options::ENABLE_DEFINITION_LIST);
+    parse_markdown_with_options(
+        markdown_input,
+        file_location_directory,
+        language_registry,
+        &ParseOptions::default(),
+    )
+    .await
+}
+
+pub async fn parse_markdown_with_options(
+    markdown_input: &str,
+    file_location_directory: Option<PathBuf>,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    options: &ParseOptions,
+) -> ParsedMarkdown {
+    parse_with_timing(
+        markdown_input,
+        file_location_directory,
+        language_registry,
+        options,
+        None,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Like [`parse_markdown_with_options`], but parses the blocks of long
+/// documents on all of `executor`'s threads. The result is exactly the same.
+///
+/// The document is still tokenized in one pass, which is what resolves its
+/// reference links and footnotes, and then split between the threads at
+/// top-level blocks, which don't depend on each other.
+pub async fn parse_markdown_parallel(
+    markdown_input: &str,
+    file_location_directory: Option<PathBuf>,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    options: &ParseOptions,
+    executor: &BackgroundExecutor,
+) -> ParsedMarkdown {
+    parse_with_timing(
+        markdown_input,
+        file_location_directory,
+        language_registry,
+        options,
+        None,
+        None,
+        Some(executor),
+    )
+    .await
+}
+
+/// Like [`parse_markdown_with_options`], but hands `on_blocks` the
+/// top-level blocks parsed since it was last called, chunk by chunk, so the
+/// start of a long document can be shown while the rest is parsed.
+///
+/// Together, the blocks it's given are the first blocks of the result, but
+/// their headings don't have slugs yet and their includes aren't expanded.
+/// The last chunk is only in the result.
+pub async fn parse_markdown_in_chunks(
+    markdown_input: &str,
+    file_location_directory: Option<PathBuf>,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    options: &ParseOptions,
+    mut on_blocks: impl FnMut(&[ParsedMarkdownElement]) + Send,
+) -> ParsedMarkdown {
+    parse_with_timing(
+        markdown_input,
+        file_location_directory,
+        language_registry,
+        options,
+        None,
+        Some(&mut on_blocks),
+        None,
+    )
+    .await
+}
+
+/// Like [`parse_markdown_with_options`], but also measures how long each
+/// part of parsing took, for finding out what makes a document slow.
+pub async fn parse_markdown_timed(
+    markdown_input: &str,
+    file_location_directory: Option<PathBuf>,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    options: &ParseOptions,
+) -> (ParsedMarkdown, ParseTiming) {
+    let mut timing = ParseTiming::default();
+    let started_at = Instant::now();
+    let parsed = parse_with_timing(
+        markdown_input,
+        file_location_directory,
+        language_registry,
+        options,
+        Some(&mut timing),
+        None,
+        None,
+    )
+    .await;
+    timing.total = started_at.elapsed();
+    (parsed, timing)
+}
+
+async fn parse_with_timing(
+    markdown_input: &str,
+    file_location_directory: Option<PathBuf>,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    options: &ParseOptions,
+    mut timing: Option<&mut ParseTiming>,
+    on_blocks: Option<BlocksCallback<'_>>,
+    executor: Option<&BackgroundExecutor>,
+) -> ParsedMarkdown {
+    let mut parsed = parse_blocks(
+        markdown_input,
+        file_location_directory.clone(),
+        language_registry.clone(),
+        options,
+        timing.as_deref_mut(),
+        on_blocks,
+        executor,
+    )
+    .await;
+    if let Some(directory) = file_location_directory.filter(|_| options.includes) {
+        let mut including = Including {
+            root: directory
+                .canonicalize()
+                .unwrap_or_else(|_| directory.clone()),
+            files: options
+                .document_path
+                .iter()
+                .filter_map(|path| path.canonicalize().ok())
+                .collect(),
+            depth: 0,
+        };
+        expand_includes(
+            &mut parsed,
+            &directory,
+            language_registry,
+            options,
+            &mut including,
+            timing.as_deref_mut(),
+        )
+        .await;
+    }
+    let started_at = timing.is_some().then(Instant::now);
+    assign_heading_slugs(&mut parsed.children);
+    record_time(timing, started_at, |timing| {
+        timing.extensions.entry("heading_slugs").or_default()
+    });
+    parsed
+}
 
-    let parser = Parser::new_ext(markdown_input, options);
-    let parser = MarkdownParser::new(
-        parser.into_offset_iter().collect(),
+async fn parse_blocks(
+    markdown_input: &str,
+    file_location_directory: Option<PathBuf>,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    options: &ParseOptions,
+    mut timing: Option<&mut ParseTiming>,
+    on_blocks: Option<BlocksCallback<'_>>,
+    executor: Option<&BackgroundExecutor>,
+) -> ParsedMarkdown {
+    let parser = Parser::new_ext(markdown_input, options.pulldown_options());
+    let tokens = if timing.is_some() {
+        // pulldown-cmark tokenizes lazily, so the events are collected first
+        // to tell its time apart from splitting out the highlights.
+        let started_at = Some(Instant::now());
+        let events: Vec<_> = parser.into_offset_iter().collect();
+        record_time(timing.as_deref_mut(), started_at, |timing| {
+            &mut timing.block_scan
+        });
+        let started_at = Some(Instant::now());
+        let tokens = split_highlight_delimiters(events.into_iter());
+        record_time(timing.as_deref_mut(), started_at, |timing| {
+            timing.extensions.entry("highlights").or_default()
+        });
+        tokens
+    } else {
+        split_highlight_delimiters(parser.into_offset_iter())
+    };
+    let tokens = if options.hard_wrap {
+        hard_wrap_paragraphs(tokens)
+    } else {
+        tokens
+    };
+    let (tokens, split_paragraphs) = split_long_paragraphs(tokens, options.max_paragraph_len);
+    let diagnostics = list_indentation_diagnostics(markdown_input, &tokens);
+    // Chunked and timed parses stay on one thread, since their callbacks and
+    // timings are about the blocks in order.
+    if let Some(executor) = executor.filter(|_| {
+        timing.is_none() && on_blocks.is_none() && tokens.len() >= 2 * MIN_EVENTS_PER_THREAD
+    }) {
+        let mut parsed = parse_in_parallel(
+            tokens,
+            markdown_input.into(),
+            file_location_directory,
+            options.base_url.clone(),
+            language_registry,
+            split_paragraphs,
+            executor,
+        )
+        .await;
+        parsed.diagnostics = diagnostics;
+        return parsed;
+    }
+    let mut parser = MarkdownParser::new(
+        tokens,
         file_location_directory,
+        options.base_url.clone(),
         language_registry,
     );
-    let renderer = parser.parse_document().await;
+    parser.split_paragraphs = Arc::new(split_paragraphs);
+    parser.source = Some(markdown_input.into());
+    parser.timing = timing.as_ref().map(|_| ParseTiming::default());
+    parser.on_blocks = on_blocks;
+    let mut renderer = parser.parse_document().await;
+    if let (Some(timing), Some(parser_timing)) = (timing, renderer.timing.take()) {
+        timing.add(parser_timing);
+    }
     ParsedMarkdown {
         children: renderer.parsed,
+        front_matter: renderer.front_matter,
+        includes: Vec::new(),
+        diagnostics,
+    }
+}
+
+/// Parses runs of whole top-level blocks of `tokens` on `executor`'s threads
+/// and puts the blocks back together in order.
+async fn parse_in_parallel(
+    tokens: Vec<(Event<'_>, Range<usize>)>,
+    source: Arc<str>,
+    file_location_directory: Option<PathBuf>,
+    base_url: Option<Url>,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    split_paragraphs: FxHashSet<usize>,
+    executor: &BackgroundExecutor,
+) -> ParsedMarkdown {
+    let split_paragraphs = Arc::new(split_paragraphs);
+    let parts = (tokens.len() / MIN_EVENTS_PER_THREAD).clamp(1, executor.num_cpus().max(1));
+    let runs = split_at_top_level_blocks(tokens, parts);
+    let mut results: Vec<Option<MarkdownParser>> = runs.iter().map(|_| None).collect();
+    executor
+        .scoped(|scope| {
+            for (run, result) in runs.into_iter().zip(&mut results) {
+                let mut parser = MarkdownParser::new(
+                    run,
+                    file_location_directory.clone(),
+                    base_url.clone(),
+                    language_registry.clone(),
+                );
+                parser.split_paragraphs = split_paragraphs.clone();
+                parser.source = Some(source.clone());
+                scope.spawn(async move {
+                    *result = Some(parser.parse_document().await);
+                });
+            }
+        })
+        .await;
+
+    let mut parsed = ParsedMarkdown {
+        children: Vec::new(),
+        front_matter: None,
+        includes: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    for parser in results.into_iter().flatten() {
+        parsed.children.extend(parser.parsed);
+        parsed.front_matter = parsed.front_matter.or(parser.front_matter);
+    }
+    parsed
+}
+
+/// Splits `tokens` into at most `parts` runs of about the same length, each
+/// ending where a top-level block does.
+fn split_at_top_level_blocks<'a>(
+    mut tokens: Vec<(Event<'a>, Range<usize>)>,
+    parts: usize,
+) -> Vec<Vec<(Event<'a>, Range<usize>)>> {
+    let run_len = tokens.len().div_ceil(parts.max(1));
+    let mut ends = Vec::new();
+    let mut depth = 0usize;
+    let mut run_start = 0;
+    for (ix, (event, _)) in tokens.iter().enumerate() {
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+                // Runs end on the end of a block, like the document itself,
+                // so that each one is parsed the way it would be in place.
+                if depth == 0 && ix + 1 - run_start >= run_len && ix + 1 < tokens.len() {
+                    ends.push(ix + 1);
+                    run_start = ix + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut runs: Vec<_> = ends
+        .into_iter()
+        .rev()
+        .map(|end| tokens.split_off(end))
+        .collect();
+    runs.push(tokens);
+    runs.reverse();
+    runs
+}
+
+/// Where [`expand_includes`] is in the files that include each other.
+struct Including {
+    /// The folder that included files have to be in.
+    root: PathBuf,
+    /// The files whose includes are being expanded, outermost first,
+    /// starting with the document's own file if it has one.
+    files: Vec<PathBuf>,
+    /// How many of `files` were included by another.
+    depth: usize,
+}
+
+/// Replaces the `!include(path)` paragraphs of `parsed` with the blocks of
+/// the files they name, relative to `directory`.
+///
+/// Directives that can't be followed are replaced with a block saying why.
+#[async_recursion]
+async fn expand_includes(
+    parsed: &mut ParsedMarkdown,
+    directory: &Path,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    options: &ParseOptions,
+    including: &mut Including,
+    mut timing: Option<&mut ParseTiming>,
+) {
+    // The included files are parsed into the other parts of `timing`, so
+    // only the rest of the time counts toward the includes.
+    let started_at = timing.is_some().then(Instant::now);
+    let accounted = timing.as_ref().map(|timing| timing.accounted());
+    let mut children = Vec::with_capacity(parsed.children.len());
+    let mut includes = Vec::new();
+    for block in std::mem::take(&mut parsed.children) {
+        let Some(target) = include_target(&block) else {
+            children.push(block);
+            continue;
+        };
+        let directive_range = block.source_range();
+        let path = match directory.join(target).canonicalize() {
+            Ok(path) if including.files.contains(&path) => {
+                children.push(include_error(
+                    format!("Could not include {}: it includes itself", target),
+                    directive_range,
+                ));
+                continue;
+            }
+            Ok(path) if !path.starts_with(&including.root) => {
+                children.push(include_error(
+                    format!(
+                        "Could not include {}: it's outside of the document's folder",
+                        target
+                    ),
+                    directive_range,
+                ));
+                continue;
+            }
+            Ok(_) if including.depth >= options.max_include_depth => {
+                children.push(include_error(
+                    format!(
+                        "Could not include {}: includes are nested more than {} deep",
+                        target, options.max_include_depth
+                    ),
+                    directive_range,
+                ));
+                continue;
+            }
+            Ok(path) => path,
+            Err(_) => {
+                children.push(include_error(
+                    format!("Could not include {}: the file doesn't exist", target),
+                    directive_range,
+                ));
+                continue;
+            }
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) => {
+                children.push(include_error(
+                    format!("Could not include {}: {}", target, error),
+                    directive_range,
+                ));
+                continue;
+            }
+        };
+
+        let included_directory = path.parent().map(Path::to_path_buf);
+        let mut included = parse_blocks(
+            &text,
+            included_directory.clone(),
+            language_registry.clone(),
+            options,
+            timing.as_deref_mut(),
+            None,
+            None,
+        )
+        .await;
+        if let Some(included_directory) = included_directory {
+            including.files.push(path.clone());
+            including.depth += 1;
+            expand_includes(
+                &mut included,
+                &included_directory,
+                language_registry.clone(),
+                options,
+                including,
+                timing.as_deref_mut(),
+            )
+            .await;
+            including.depth -= 1;
+            including.files.pop();
+        }
+
+        // The blocks are attributed to the innermost file they came from,
+        // so this file's runs are the ones between its own includes.
+        let start = children.len();
+        let mut covered = start;
+        for mut nested in included.includes {
+            nested.blocks = nested.blocks.start + start..nested.blocks.end + start;
+            if nested.blocks.start > covered {
+                includes.push(IncludedBlocks {
+                    path: path.clone(),
+                    directive_range: directive_range.clone(),
+                    blocks: covered..nested.blocks.start,
+                });
+            }
+            covered = nested.blocks.end;
+            includes.push(nested);
+        }
+        children.extend(included.children);
+        if children.len() > covered {
+            includes.push(IncludedBlocks {
+                path,
+                directive_range,
+                blocks: covered..children.len(),
+            });
+        }
+    }
+    parsed.children = children;
+    parsed.includes = includes;
+
+    if let (Some(timing), Some(started_at), Some(accounted)) = (timing, started_at, accounted) {
+        let nested = timing.accounted() - accounted;
+        *timing.extensions.entry("includes").or_default() +=
+            started_at.elapsed().saturating_sub(nested);
+    }
+}
+
+/// Lets the executor run other work before continuing.
+async fn yield_now() {
+    let mut yielded = false;
+    futures::future::poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Syntax highlights for `code` in `language`, which is a language's name or
+/// file extension, or `None` if the registry doesn't have the language.
+pub async fn highlight_code(
+    code: &str,
+    language: &str,
+    language_registry: &LanguageRegistry,
+) -> Option<Vec<(Range<usize>, HighlightId)>> {
+    let rope: language::Rope = code.into();
+    language_registry
+        .language_for_name_or_extension(language)
+        .await
+        .map(|language| language.highlight_text(&rope, 0..code.len()))
+        .ok()
+}
+
+/// The path in a paragraph that's only an `!include(path)` directive.
+fn include_target(block: &ParsedMarkdownElement) -> Option<&str> {
+    let ParsedMarkdownElement::Paragraph(text) = block else {
+        return None;
+    };
+    let target = text
+        .contents
+        .trim()
+        .strip_prefix("!include(")?
+        .strip_suffix(')')?
+        .trim();
+    (!target.is_empty()).then_some(target)
+}
+
+/// A block explaining why the directive at `source_range` was left out.
+fn include_error(message: String, source_range: Range<usize>) -> ParsedMarkdownElement {
+    let highlight = MarkdownHighlight::Style(MarkdownHighlightStyle {
+        italic: true,
+        ..Default::default()
+    });
+    ParsedMarkdownElement::BlockQuote(ParsedMarkdownBlockQuote {
+        source_range: source_range.clone(),
+        children: vec![ParsedMarkdownElement::Paragraph(ParsedMarkdownText {
+            source_range,
+            highlights: vec![(0..message.len(), highlight)],
+            contents: message.into(),
+            region_ranges: Vec::new(),
+            regions: Vec::new(),
+            images: Vec::new(),
+            continued: false,
+        })],
+    })
+}
+
+/// Converts a fragment of HTML, like one pasted from a web page, into the
+/// same elements Markdown is parsed into. See [`parse_html`] for which
+/// elements are understood.
+pub fn html_to_parsed_markdown(html: &str) -> ParsedMarkdown {
+    let mut parsed = ParsedMarkdown {
+        children: parse_html(html, 0, None, None),
+        front_matter: None,
+        includes: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    assign_heading_slugs(&mut parsed.children);
+    parsed
+}
+
+/// Makes the slugs of the headings in `children` unique, in document order.
+/// Repeated headings get `-1`, `-2`, ... appended, as on GitHub.
+pub fn assign_heading_slugs(children: &mut [ParsedMarkdownElement]) {
+    fn assign(children: &mut [ParsedMarkdownElement], counts: &mut FxHashMap<String, usize>) {
+        for child in children {
+            match child {
+                ParsedMarkdownElement::Heading(heading) => {
+                    let slug = heading_slug(&heading.contents.contents);
+                    let count = counts.entry(slug.clone()).or_default();
+                    heading.slug = if *count > 0 {
+                        format!("{}-{}", slug, count)
+                    } else {
+                        slug
+                    };
+                    *count += 1;
+                }
+                ParsedMarkdownElement::ListItem(item) => assign(&mut item.content, counts),
+                ParsedMarkdownElement::BlockQuote(quote) => assign(&mut quote.children, counts),
+                _ => {}
+            }
+        }
+    }
+
+    assign(children, &mut FxHashMap::default());
+}
+
+/// Turns heading text into an anchor the way GitHub does: lowercased, with
+/// spaces replaced by hyphens and punctuation other than `-` and `_` dropped.
+pub fn heading_slug(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// How a link of `link_type` was written.
+fn link_origin_of(link_type: LinkType) -> LinkOrigin {
+    match link_type {
+        LinkType::Inline => LinkOrigin::Explicit,
+        LinkType::Reference
+        | LinkType::ReferenceUnknown
+        | LinkType::Collapsed
+        | LinkType::CollapsedUnknown
+        | LinkType::Shortcut
+        | LinkType::ShortcutUnknown => LinkOrigin::ReferenceDef,
+        LinkType::Autolink | LinkType::Email => LinkOrigin::Autolink,
+    }
+}
+
+/// pulldown-cmark has no notion of `==highlighted==` text, so the `==`
+/// delimiters are split out of text events and replaced with inline `<mark>`
+/// HTML, which the inline parser then turns into highlight styling.
+fn split_highlight_delimiters<'a>(
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)>,
+) -> Vec<(Event<'a>, Range<usize>)> {
+    let mut tokens = Vec::new();
+    let mut code_block_depth = 0;
+    // The index of the `<mark>` token that has not been closed yet.
+    let mut open_highlight: Option<usize> = None;
+
+    for (event, range) in events {
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => code_block_depth += 1,
+            Event::End(TagEnd::CodeBlock) => code_block_depth -= 1,
+            // A highlight never continues past the end of its block, so an
+            // unmatched opening delimiter is put back as literal text.
+            Event::End(
+                TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::TableCell | TagEnd::Item,
+            ) => {
+                if let Some(ix) = open_highlight.take() {
+                    tokens[ix].0 = Event::Text("==".into());
+                }
+            }
+            Event::Text(text) if code_block_depth == 0 && text.contains("==") => {
+                // Each piece gets the part of the source it came from, unless
+                // the text isn't written the same in the source, like when it
+                // has escapes, and there's no telling where it is.
+                let sub_range = |pieces: Range<usize>| {
+                    if range.len() == text.len() {
+                        range.start + pieces.start..range.start + pieces.end
+                    } else {
+                        range.clone()
+                    }
+                };
+                let mut highlight_open = open_highlight.is_some();
+                let mut last_end = 0;
+                for delimiter in find_highlight_delimiters(text, &mut highlight_open) {
+                    if last_end < delimiter.start {
+                        tokens.push((
+                            Event::Text(text[last_end..delimiter.start].to_string().into()),
+                            sub_range(last_end..delimiter.start),
+                        ));
+                    }
+                    open_highlight = if delimiter.html == "<mark>" {
+                        Some(tokens.len())
+                    } else {
+                        None
+                    };
+                    tokens.push((
+                        Event::InlineHtml(delimiter.html.into()),
+                        sub_range(delimiter.start..delimiter.start + 2),
+                    ));
+                    last_end = delimiter.start + 2;
+                }
+                if last_end < text.len() {
+                    tokens.push((
+                        Event::Text(text[last_end..].to_string().into()),
+                        sub_range(last_end..text.len()),
+                    ));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        tokens.push((event, range));
+    }
+
+    if let Some(ix) = open_highlight {
+        tokens[ix].0 = Event::Text("==".into());
+    }
+
+    tokens
+}
+
+/// Finds the list items of `text`, parsed into `tokens`, that are indented
+/// differently from the items before them at the same depth. Lists next to
+/// each other count as one, since an item indented too little to nest, like
+/// a bullet under `1.` indented two spaces, starts a list of its own.
+///
+/// Items are compared by how far they're indented past where the text of
+/// the item they're nested in starts, so that `1.` and `10.` items can nest
+/// items under them the same way.
+fn list_indentation_diagnostics(
+    text: &str,
+    tokens: &[(Event<'_>, Range<usize>)],
+) -> Vec<ParseDiagnostic> {
+    let mut diagnostics = Vec::new();
+    // How far the first item at each depth of the lists was indented.
+    let mut indents: Vec<usize> = Vec::new();
+    // Where the text of each item the current one is nested in starts.
+    let mut content_columns: Vec<usize> = Vec::new();
+    let mut list_depth = 0usize;
+    for (event, range) in tokens {
+        match event {
+            Event::Start(Tag::List(_)) => list_depth += 1,
+            Event::End(TagEnd::List(_)) => list_depth = list_depth.saturating_sub(1),
+            Event::Start(Tag::Item) => {
+                // Items' ranges can start with the indentation before their
+                // marker.
+                let marker_ix = range.start
+                    + text[range.start..]
+                        .find(|ch: char| ch != ' ' && ch != '\t')
+                        .unwrap_or(0);
+                let line_start = text[..marker_ix].rfind('\n').map_or(0, |ix| ix + 1);
+                let line = &text[line_start..];
+                let column = display_column(&line[..marker_ix - line_start]);
+                content_columns.truncate(list_depth - 1);
+                let indent = match content_columns.last() {
+                    Some(parent_column) => column.saturating_sub(*parent_column),
+                    None => column,
+                };
+                match indents.get(list_depth - 1) {
+                    Some(expected) if *expected != indent => diagnostics.push(ParseDiagnostic {
+                        source_range: marker_ix..range.end,
+                        message: format!(
+                            "ambiguous list indentation; item interpreted at depth {}",
+                            list_depth
+                        ),
+                    }),
+                    Some(_) => {}
+                    None => indents.push(indent),
+                }
+                content_columns.push(item_content_column(line, marker_ix - line_start));
+            }
+            // Any other block between lists ends them.
+            Event::Start(_) if list_depth == 0 => indents.clear(),
+            _ => {}
+        }
+    }
+    diagnostics
+}
+
+/// The column `prefix` ends at, with tabs stopping every four columns.
+fn display_column(prefix: &str) -> usize {
+    prefix.chars().fold(0, |column, ch| match ch {
+        '\t' => column + 4 - column % 4,
+        _ => column + 1,
+    })
+}
+
+/// The column the text of the list item whose marker is at `marker_ix` in
+/// `line` starts at. Like CommonMark, text five or more spaces past the
+/// marker starts one space past it, since the rest is indented code.
+fn item_content_column(line: &str, marker_ix: usize) -> usize {
+    let line = line.split('\n').next().unwrap_or_default();
+    let after_marker = line[marker_ix..]
+        .find(char::is_whitespace)
+        .map_or(line.len(), |ix| marker_ix + ix);
+    let marker_end = display_column(&line[..after_marker]);
+    let text_start = line[after_marker..]
+        .find(|ch: char| !ch.is_whitespace())
+        .map(|ix| after_marker + ix);
+    match text_start {
+        Some(text_start) if display_column(&line[..text_start]) - marker_end <= 4 => {
+            display_column(&line[..text_start])
+        }
+        _ => marker_end + 1,
+    }
+}
+
+/// Turns the soft breaks in the paragraphs of `tokens` into hard breaks.
+/// Paragraphs in list items keep theirs, so that an item written over
+/// several lines is still one line.
+fn hard_wrap_paragraphs(
+    mut tokens: Vec<(Event<'_>, Range<usize>)>,
+) -> Vec<(Event<'_>, Range<usize>)> {
+    let mut item_depth = 0usize;
+    let mut in_paragraph = false;
+    for (event, _) in &mut tokens {
+        match event {
+            Event::Start(Tag::Item) => item_depth += 1,
+            Event::End(TagEnd::Item) => item_depth = item_depth.saturating_sub(1),
+            Event::Start(Tag::Paragraph) => in_paragraph = true,
+            Event::End(TagEnd::Paragraph) => in_paragraph = false,
+            Event::SoftBreak if in_paragraph && item_depth == 0 => *event = Event::HardBreak,
+            _ => {}
+        }
     }
+    tokens
+}
+
+/// Splits the paragraphs of `tokens` that are longer than `max_len` bytes
+/// into consecutive paragraphs, at the first whitespace past each `max_len`
+/// bytes of them. Only text outside of links, emphasis and other inline
+/// markup is split, and only text that's in the source as it is, without
+/// escapes or entities, so that each piece knows where it's from.
+///
+/// Returns the tokens, and where each paragraph split off from the one
+/// before it starts in the source.
+fn split_long_paragraphs<'a>(
+    tokens: Vec<(Event<'a>, Range<usize>)>,
+    max_len: usize,
+) -> (Vec<(Event<'a>, Range<usize>)>, FxHashSet<usize>) {
+    let mut split_starts = FxHashSet::default();
+    if tokens.iter().all(|(event, range)| {
+        !matches!(event, Event::Start(Tag::Paragraph)) || range.len() <= max_len
+    }) {
+        return (tokens, split_starts);
+    }
+
+    let mut split = Vec::with_capacity(tokens.len());
+    // The index of the start of the paragraph being split, and its end in
+    // the source.
+    let mut paragraph: Option<(usize, usize)> = None;
+    let mut inline_depth = 0usize;
+    // `==highlights==` are `<mark>` tags by now, from
+    // `split_highlight_delimiters`, and aren't cut either.
+    let mut highlight_depth = 0usize;
+    for (event, range) in tokens {
+        match &event {
+            Event::Start(Tag::Paragraph) if paragraph.is_none() => {
+                if range.len() > max_len {
+                    paragraph = Some((split.len(), range.end));
+                    inline_depth = 0;
+                    highlight_depth = 0;
+                }
+            }
+            Event::End(TagEnd::Paragraph) if inline_depth == 0 => paragraph = None,
+            Event::Start(_) if paragraph.is_some() => inline_depth += 1,
+            Event::End(_) if paragraph.is_some() => inline_depth -= 1,
+            Event::InlineHtml(html) if paragraph.is_some() => match html.as_ref() {
+                "<mark>" => highlight_depth += 1,
+                "</mark>" => highlight_depth = highlight_depth.saturating_sub(1),
+                _ => {}
+            },
+            Event::Text(text)
+                if inline_depth == 0
+                    && highlight_depth == 0
+                    && text.len() == range.len()
+                    && paragraph.is_some() =>
+            {
+                let mut text: &str = text;
+                let mut range = range.clone();
+                while let Some((start_ix, end)) = paragraph {
+                    let paragraph_start = split[start_ix].1.start;
+                    let cut_from = (paragraph_start + max_len).saturating_sub(range.start);
+                    let Some(cut) = text
+                        .char_indices()
+                        .skip_while(|(ix, _)| *ix < cut_from)
+                        .find(|(_, ch)| ch.is_whitespace())
+                        .map(|(ix, ch)| ix + ch.len_utf8())
+                        .filter(|cut| *cut < text.len())
+                    else {
+                        break;
+                    };
+                    let cut_at = range.start + cut;
+                    split.push((
+                        Event::Text(text[..cut].to_string().into()),
+                        range.start..cut_at,
+                    ));
+                    split.push((Event::End(TagEnd::Paragraph), paragraph_start..cut_at));
+                    split[start_ix].1.end = cut_at;
+                    paragraph = Some((split.len(), end));
+                    split.push((Event::Start(Tag::Paragraph), cut_at..end));
+                    split_starts.insert(cut_at);
+                    text = &text[cut..];
+                    range.start = cut_at;
+                }
+                split.push((Event::Text(text.to_string().into()), range));
+                continue;
+            }
+            _ => {}
+        }
+        split.push((event, range));
+    }
+    (split, split_starts)
+}
+
+struct HighlightDelimiter {
+    start: usize,
+    html: &'static str,
+}
+
+/// An opening `==` must be followed by a non-whitespace character and a closing
+/// one preceded by one, so that prose like `a == b` is left alone.
+fn find_highlight_delimiters(text: &str, highlight_open: &mut bool) -> Vec<HighlightDelimiter> {
+    let mut delimiters = Vec::new();
+    let mut search_from = 0;
+
+    while let Some(offset) = text[search_from..].find("==") {
+        let start = search_from + offset;
+        let before = text[..start].chars().next_back();
+        let after = text[start + 2..].chars().next();
+        search_from = start + 2;
+
+        if before == Some('=') || after == Some('=') {
+            continue;
+        }
+
+        let is_delimiter = if *highlight_open {
+            before.map_or(true, |c| !c.is_whitespace())
+        } else {
+            after.map_or(true, |c| !c.is_whitespace())
+        };
+
+        if is_delimiter {
+            delimiters.push(HighlightDelimiter {
+                start,
+                html: if *highlight_open { "</mark>" } else { "<mark>" },
+            });
+            *highlight_open = !*highlight_open;
+        }
+    }
+
+    delimiters
 }
 
 struct MarkdownParser<'a> {
@@ -32,25 +1074,62 @@ struct MarkdownParser<'a> {
     cursor: usize,
     /// The blocks that we have successfully parsed so far
     parsed: Vec<ParsedMarkdownElement>,
+    front_matter: Option<FrontMatter>,
     file_location_directory: Option<PathBuf>,
+    base_url: Option<Url>,
     language_registry: Option<Arc<LanguageRegistry>>,
+    /// Where the time went, when the parse is being timed.
+    timing: Option<ParseTiming>,
+    on_blocks: Option<BlocksCallback<'a>>,
+    /// Where the paragraphs [`split_long_paragraphs`] split off start.
+    split_paragraphs: Arc<FxHashSet<usize>>,
+    /// The Markdown the tokens are from, which text that's written out as
+    /// it's shown is kept as ranges of.
+    source: Option<Arc<str>>,
 }
 
 impl<'a> MarkdownParser<'a> {
     fn new(
         tokens: Vec<(Event<'a>, Range<usize>)>,
         file_location_directory: Option<PathBuf>,
+        base_url: Option<Url>,
         language_registry: Option<Arc<LanguageRegistry>>,
     ) -> Self {
         Self {
             tokens,
             file_location_directory,
+            base_url,
             language_registry,
             cursor: 0,
             parsed: vec![],
+            front_matter: None,
+            timing: None,
+            on_blocks: None,
+            split_paragraphs: Arc::default(),
+            source: None,
         }
     }
 
+    fn start_timer(&self) -> Option<Instant> {
+        self.timing.is_some().then(Instant::now)
+    }
+
+    fn record_time(
+        &mut self,
+        started_at: Option<Instant>,
+        part: impl FnOnce(&mut ParseTiming) -> &mut Duration,
+    ) {
+        record_time(self.timing.as_mut(), started_at, part);
+    }
+
+    fn link(&self, url: String) -> Option<Link> {
+        Link::resolve(
+            self.file_location_directory.clone(),
+            self.base_url.as_ref(),
+            url,
+        )
+    }
+
     fn eof(&self) -> bool {
         if self.tokens.is_empty() {
             return true;
@@ -86,6 +1165,7 @@ impl<'a> MarkdownParser<'a> {
             // Represent an inline code block
             | Event::Code(_)
             | Event::Html(_)
+            | Event::InlineHtml(_)
             | Event::FootnoteReference(_)
             | Event::Start(Tag::Link { link_type: _, dest_url: _, title: _, id: _ })
             | Event::Start(Tag::Emphasis)
@@ -98,11 +1178,23 @@ impl<'a> MarkdownParser<'a> {
         }
     }
 
+    /// Parses the blocks in chunks, yielding to the executor between them,
+    /// so that a long document doesn't hold up other work and a cancelled
+    /// parse stops right away.
     async fn parse_document(mut self) -> Self {
+        let mut chunk_start = (0, 0);
         while !self.eof() {
             if let Some(block) = self.parse_block().await {
                 self.parsed.extend(block);
             }
+            let (start_cursor, start_block) = chunk_start;
+            if self.cursor - start_cursor >= EVENTS_PER_CHUNK && !self.eof() {
+                if let Some(on_blocks) = &mut self.on_blocks {
+                    on_blocks(&self.parsed[start_block..]);
+                }
+                chunk_start = (self.cursor, self.parsed.len());
+                yield_now().await;
+            }
         }
         self
     }
@@ -115,7 +1207,13 @@ impl<'a> MarkdownParser<'a> {
             Event::Start(tag) => match tag {
                 Tag::Paragraph => {
                     self.cursor += 1;
-                    let text = self.parse_text(false, Some(source_range));
+                    if self.is_image_row() {
+                        let text = self.parse_image_row(source_range);
+                        return Some(vec![ParsedMarkdownElement::Paragraph(text)]);
+                    }
+                    let continued = self.split_paragraphs.contains(&source_range.start);
+                    let mut text = self.parse_text(false, Some(source_range));
+                    text.continued = continued;
                     Some(vec![ParsedMarkdownElement::Paragraph(text)])
                 }
                 Tag::Heading {
@@ -132,7 +1230,14 @@ impl<'a> MarkdownParser<'a> {
                 Tag::Table(alignment) => {
                     let alignment = alignment.clone();
                     self.cursor += 1;
+                    // The text in the cells counts toward the table.
+                    let started_at = self.start_timer();
+                    let inline = self.timing.as_ref().map(|timing| timing.inline);
                     let table = self.parse_table(alignment);
+                    self.record_time(started_at, |timing| &mut timing.tables);
+                    if let (Some(timing), Some(inline)) = (&mut self.timing, inline) {
+                        timing.inline = inline;
+                    }
                     Some(vec![ParsedMarkdownElement::Table(table)])
                 }
                 Tag::List(order) => {
@@ -147,33 +1252,52 @@ impl<'a> MarkdownParser<'a> {
                     Some(vec![ParsedMarkdownElement::BlockQuote(block_quote)])
                 }
                 Tag::CodeBlock(kind) => {
-                    let language = match kind {
-                        pulldown_cmark::CodeBlockKind::Indented => None,
-                        pulldown_cmark::CodeBlockKind::Fenced(language) => {
-                            if language.is_empty() {
-                                None
-                            } else {
-                                Some(language.to_string())
-                            }
-                        }
+                    let (language, emphasized_lines) = match kind {
+                        pulldown_cmark::CodeBlockKind::Indented => (None, Vec::new()),
+                        pulldown_cmark::CodeBlockKind::Fenced(info) => Self::parse_fence_info(info),
                     };
 
                     self.cursor += 1;
 
-                    let code_block = self.parse_code_block(language).await;
+                    let started_at = self.start_timer();
+                    let code_block = self.parse_code_block(language, emphasized_lines).await;
+                    self.record_time(started_at, |timing| &mut timing.code_blocks);
                     Some(vec![ParsedMarkdownElement::CodeBlock(code_block)])
                 }
-                Tag::Image { 
+                Tag::Image {
                     link_type: _,
                     dest_url,
                     title,
-                    id: _ 
+                    id: _,
                 } => {
-                    let image = self.parse_image(title.clone().to_string(), dest_url.clone().to_string());
-                    self.cursor += 1;
-                    println!("image {:?}", image);
+                    let image = self.parse_image(title.to_string(), dest_url.to_string());
                     Some(vec![ParsedMarkdownElement::Image(image)])
                 }
+                Tag::HtmlBlock => {
+                    self.cursor += 1;
+                    let started_at = self.start_timer();
+                    let html = self.parse_html_block();
+                    let blocks = parse_html(
+                        &html,
+                        source_range.start,
+                        self.file_location_directory.clone(),
+                        self.base_url.clone(),
+                    );
+                    self.record_time(started_at, |timing| {
+                        timing.extensions.entry("html").or_default()
+                    });
+                    Some(blocks)
+                }
+                Tag::MetadataBlock(kind) => {
+                    let kind = *kind;
+                    self.cursor += 1;
+                    let started_at = self.start_timer();
+                    self.front_matter = Some(self.parse_front_matter(kind, source_range));
+                    self.record_time(started_at, |timing| {
+                        timing.extensions.entry("front_matter").or_default()
+                    });
+                    None
+                }
                 _ => {
                     self.cursor += 1;
                     None
@@ -191,17 +1315,135 @@ impl<'a> MarkdownParser<'a> {
         }
     }
 
-    fn parse_image(&mut self,title: String , dest_url: String) -> ParsedMarkdownImage {
-        let (_event, source_range) = self.previous().unwrap();
-        let source_range = source_range.clone();
+    /// Collects the HTML of a block of it, up to its end.
+    fn parse_html_block(&mut self) -> String {
+        let mut html = String::new();
+        while !self.eof() {
+            match self.current_event() {
+                Some(Event::End(TagEnd::HtmlBlock)) => {
+                    self.cursor += 1;
+                    break;
+                }
+                Some(Event::Html(chunk)) => html.push_str(chunk),
+                _ => {}
+            }
+            self.cursor += 1;
+        }
+        html
+    }
 
-        ParsedMarkdownImage {
-            source_range: source_range.clone(),
+    fn parse_front_matter(
+        &mut self,
+        kind: MetadataBlockKind,
+        source_range: Range<usize>,
+    ) -> FrontMatter {
+        let mut text = String::new();
+        while !self.eof() {
+            match self.current_event() {
+                Some(Event::End(TagEnd::MetadataBlock(_))) => {
+                    self.cursor += 1;
+                    break;
+                }
+                Some(Event::Text(chunk)) => text.push_str(chunk),
+                _ => {}
+            }
+            self.cursor += 1;
+        }
+        FrontMatter::parse(kind, &text, source_range)
+    }
+
+    fn parse_image(&mut self, title: String, dest_url: String) -> ParsedMarkdownImage {
+        let (_event, source_range) = self.current().unwrap();
+        let source_range = source_range.clone();
+        self.cursor += 1;
+
+        // The alt text is made up of everything until the end of the image tag,
+        // with any formatting stripped.
+        let mut alt_text = String::new();
+        while !self.eof() {
+            match self.current_event() {
+                Some(Event::End(TagEnd::Image)) => {
+                    self.cursor += 1;
+                    break;
+                }
+                Some(Event::Text(text)) | Some(Event::Code(text)) => alt_text.push_str(text),
+                _ => {}
+            }
+            self.cursor += 1;
+        }
+
+        ParsedMarkdownImage {
+            source_range,
             title,
-            link: Link::identify(
-                self.file_location_directory.clone(),
-                dest_url.clone(),
-            )
+            alt_text,
+            link: self.link(dest_url),
+            target: None,
+        }
+    }
+
+    /// Whether the paragraph that starts at the cursor has more than one
+    /// image and nothing else but whitespace and links around them, like the
+    /// row of badges at the top of a README.
+    fn is_image_row(&self) -> bool {
+        let mut images = 0;
+        let mut in_image = false;
+        for (event, _) in &self.tokens[self.cursor..] {
+            match event {
+                Event::End(TagEnd::Paragraph) => break,
+                Event::Start(Tag::Image { .. }) => {
+                    images += 1;
+                    in_image = true;
+                }
+                Event::End(TagEnd::Image) => in_image = false,
+                // The alt text can be anything.
+                _ if in_image => {}
+                Event::Start(Tag::Link { .. })
+                | Event::End(TagEnd::Link)
+                | Event::SoftBreak
+                | Event::HardBreak => {}
+                Event::Text(text) if text.trim().is_empty() => {}
+                _ => return false,
+            }
+        }
+        images > 1
+    }
+
+    /// Parses a paragraph that [`Self::is_image_row`], keeping the link
+    /// around each image as where it goes.
+    fn parse_image_row(&mut self, source_range: Range<usize>) -> ParsedMarkdownText {
+        let mut images = Vec::new();
+        let mut target = None;
+        while !self.eof() {
+            match self.current_event() {
+                Some(Event::End(TagEnd::Paragraph)) => {
+                    self.cursor += 1;
+                    break;
+                }
+                Some(Event::Start(Tag::Image {
+                    dest_url, title, ..
+                })) => {
+                    let (title, dest_url) = (title.to_string(), dest_url.to_string());
+                    let mut image = self.parse_image(title, dest_url);
+                    image.target = target.clone();
+                    images.push(image);
+                    continue;
+                }
+                Some(Event::Start(Tag::Link { dest_url, .. })) => {
+                    target = self.link(dest_url.to_string());
+                }
+                Some(Event::End(TagEnd::Link)) => target = None,
+                _ => {}
+            }
+            self.cursor += 1;
+        }
+        ParsedMarkdownText {
+            source_range,
+            contents: Default::default(),
+            highlights: Vec::new(),
+            region_ranges: Vec::new(),
+            regions: Vec::new(),
+            images,
+            continued: false,
         }
     }
 
@@ -209,6 +1451,17 @@ impl<'a> MarkdownParser<'a> {
         &mut self,
         should_complete_on_soft_break: bool,
         source_range: Option<Range<usize>>,
+    ) -> ParsedMarkdownText {
+        let started_at = self.start_timer();
+        let text = self.parse_inline(should_complete_on_soft_break, source_range);
+        self.record_time(started_at, |timing| &mut timing.inline);
+        text
+    }
+
+    fn parse_inline(
+        &mut self,
+        should_complete_on_soft_break: bool,
+        source_range: Option<Range<usize>>,
     ) -> ParsedMarkdownText {
         let source_range = source_range.unwrap_or_else(|| {
             self.current()
@@ -220,20 +1473,25 @@ impl<'a> MarkdownParser<'a> {
         let mut bold_depth = 0;
         let mut italic_depth = 0;
         let mut strikethrough_depth = 0;
+        let mut highlight_depth = 0;
         let mut link: Option<Link> = None;
+        let mut link_origin = LinkOrigin::Explicit;
         let mut region_ranges: Vec<Range<usize>> = vec![];
         let mut regions: Vec<ParsedRegion> = vec![];
         let mut highlights: Vec<(Range<usize>, MarkdownHighlight)> = vec![];
 
         let mut link_urls: Vec<String> = vec![];
         let mut link_ranges: Vec<Range<usize>> = vec![];
+        // Where the text events are in the source, from the first to the
+        // last.
+        let mut text_range: Option<Range<usize>> = None;
 
         loop {
             if self.eof() {
                 break;
             }
 
-            let (current, _source_range) = self.current().unwrap();
+            let (current, current_range) = self.current().unwrap();
             let prev_len = text.len();
             match current {
                 Event::SoftBreak => {
@@ -250,7 +1508,23 @@ impl<'a> MarkdownParser<'a> {
                 }
 
                 Event::Text(t) => {
-                    text.push_str(t.as_ref());
+                    // Text is often split over several events, like at a `_`
+                    // or `[` that turned out not to be markup, and a URL is
+                    // only found whole in all of them together.
+                    let mut t = t.to_string();
+                    let mut t_range = current_range.clone();
+                    while let Some((Event::Text(next), next_range)) =
+                        self.tokens.get(self.cursor + 1)
+                    {
+                        t.push_str(next);
+                        t_range.end = next_range.end;
+                        self.cursor += 1;
+                    }
+                    text.push_str(&t);
+                    text_range = Some(match text_range {
+                        Some(range) => range.start..t_range.end,
+                        None => t_range,
+                    });
 
                     let mut style = MarkdownHighlightStyle::default();
 
@@ -266,11 +1540,16 @@ impl<'a> MarkdownParser<'a> {
                         style.strikethrough = true;
                     }
 
+                    if highlight_depth > 0 {
+                        style.highlight = true;
+                    }
+
                     let last_run_len = if let Some(link) = link.clone() {
                         region_ranges.push(prev_len..text.len());
                         regions.push(ParsedRegion {
                             code: false,
                             link: Some(link),
+                            origin: link_origin,
                         });
                         style.underline = true;
                         prev_len
@@ -279,19 +1558,17 @@ impl<'a> MarkdownParser<'a> {
                         let mut finder = linkify::LinkFinder::new();
                         finder.kinds(&[linkify::LinkKind::Url]);
                         let mut last_link_len = prev_len;
-                        for link in finder.links(t) {
-                            let start = link.start();
-                            let end = link.end();
-                            let range = (prev_len + start)..(prev_len + end);
+                        for link in finder.links(&t) {
+                            let range = (prev_len + link.start())..(prev_len + link.end());
                             link_ranges.push(range.clone());
                             link_urls.push(link.as_str().to_string());
 
                             // If there is a style before we match a link, we have to add this to the highlighted ranges
                             if style != MarkdownHighlightStyle::default()
-                                && last_link_len < link.start()
+                                && last_link_len < range.start
                             {
                                 highlights.push((
-                                    last_link_len..link.start(),
+                                    last_link_len..range.start,
                                     MarkdownHighlight::Style(style.clone()),
                                 ));
                             }
@@ -309,9 +1586,10 @@ impl<'a> MarkdownParser<'a> {
                                 link: Some(Link::Web {
                                     url: link.as_str().to_string(),
                                 }),
+                                origin: LinkOrigin::Autolink,
                             });
 
-                            last_link_len = end;
+                            last_link_len = range.end;
                         }
                         last_link_len
                     };
@@ -351,23 +1629,28 @@ impl<'a> MarkdownParser<'a> {
                     regions.push(ParsedRegion {
                         code: true,
                         link: link.clone(),
+                        origin: link_origin,
                     });
                 }
 
+                Event::InlineHtml(html) => match html.as_ref() {
+                    "<mark>" => highlight_depth += 1,
+                    "</mark>" if highlight_depth > 0 => highlight_depth -= 1,
+                    _ => {}
+                },
+
                 Event::Start(tag) => match tag {
                     Tag::Emphasis => italic_depth += 1,
                     Tag::Strong => bold_depth += 1,
                     Tag::Strikethrough => strikethrough_depth += 1,
                     Tag::Link {
-                        link_type: _,
+                        link_type,
                         dest_url,
                         title: _,
                         id: _,
                     } => {
-                        link = Link::identify(
-                            self.file_location_directory.clone(),
-                            dest_url.to_string(),
-                        );
+                        link = self.link(dest_url.to_string());
+                        link_origin = link_origin_of(*link_type);
                     }
                     _ => {
                         break;
@@ -404,12 +1687,28 @@ impl<'a> MarkdownParser<'a> {
             self.cursor += 1;
         }
 
+        // Documents have lots of these, so they don't keep the room they
+        // were given to grow.
+        highlights.shrink_to_fit();
+        regions.shrink_to_fit();
+        region_ranges.shrink_to_fit();
+        // The text is kept as a range of the source if that's what it is,
+        // with no markup, escapes or line breaks in between.
+        let contents = self
+            .source
+            .as_ref()
+            .zip(text_range)
+            .filter(|(source, range)| source.get(range.clone()) == Some(text.as_str()))
+            .and_then(|(source, range)| SourceText::from_source(source, range))
+            .unwrap_or_else(|| text.into());
         ParsedMarkdownText {
             source_range,
-            contents: text,
+            contents,
             highlights,
             regions,
             region_ranges,
+            images: Vec::new(),
+            continued: false,
         }
     }
 
@@ -422,6 +1721,8 @@ impl<'a> MarkdownParser<'a> {
         self.cursor += 1;
 
         ParsedMarkdownHeading {
+            // Made unique once the whole document is parsed.
+            slug: heading_slug(&text.contents),
             source_range: source_range.clone(),
             level: match level {
                 pulldown_cmark::HeadingLevel::H1 => HeadingLevel::H1,
@@ -698,15 +1999,61 @@ impl<'a> MarkdownParser<'a> {
         }
     }
 
-    async fn parse_code_block(&mut self, language: Option<String>) -> ParsedMarkdownCodeBlock {
+    /// Splits a fence info string like `rust {2-4,7}` into the language and
+    /// the zero-based line ranges listed in its attributes, sorted and merged.
+    fn parse_fence_info(info: &str) -> (Option<String>, Vec<Range<usize>>) {
+        let info = info.trim();
+        let attributes_start = info.find('{').unwrap_or(info.len());
+        let language = info[..attributes_start]
+            .split_whitespace()
+            .next()
+            .map(|language| language.to_string());
+
+        let mut lines: Vec<Range<usize>> = Vec::new();
+        if let Some(attributes) = info[attributes_start..]
+            .strip_prefix('{')
+            .and_then(|attributes| attributes.split('}').next())
+        {
+            for item in attributes.split(|c: char| c == ',' || c.is_whitespace()) {
+                let (start, end) = item.split_once('-').unwrap_or((item, item));
+                let (Ok(start), Ok(end)) = (start.trim().parse::<usize>(), end.trim().parse())
+                else {
+                    continue;
+                };
+                // Line numbers in fence attributes are one-based and inclusive.
+                if start >= 1 && start <= end {
+                    lines.push(start - 1..end);
+                }
+            }
+        }
+
+        lines.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for range in lines {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        (language, merged)
+    }
+
+    async fn parse_code_block(
+        &mut self,
+        language: Option<String>,
+        emphasized_lines: Vec<Range<usize>>,
+    ) -> ParsedMarkdownCodeBlock {
         let (_event, source_range) = self.previous().unwrap();
         let source_range = source_range.clone();
         let mut code = String::new();
+        let mut code_start = None;
 
         while !self.eof() {
-            let (current, _source_range) = self.current().unwrap();
+            let (current, text_range) = self.current().unwrap();
             match current {
                 Event::Text(text) => {
+                    code_start.get_or_insert(text_range.start);
                     code.push_str(text);
                     self.cursor += 1;
                 }
@@ -720,185 +2067,1181 @@ impl<'a> MarkdownParser<'a> {
             }
         }
 
-        let highlights = if let Some(language) = &language {
-            if let Some(registry) = &self.language_registry {
-                let rope: language::Rope = code.as_str().into();
-                registry
-                    .language_for_name_or_extension(language)
-                    .await
-                    .map(|l| l.highlight_text(&rope, 0..code.len()))
-                    .ok()
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+        // Emphasized lines are counted from the first line of the block, so
+        // the blank lines trimmed off its start move them up.
+        let trimmed_start = &code[..code.len() - code.trim_start().len()];
+        let trimmed_lines = trimmed_start.matches('\n').count();
+        let contents_offset = code_start.map_or(0, |start| {
+            start.saturating_sub(source_range.start) + trimmed_start.len()
+        });
+        let contents = code.trim().to_string();
+        let highlights = match (&language, &self.language_registry) {
+            (Some(language), Some(registry)) => highlight_code(&contents, language, registry).await,
+            _ => None,
+        };
+
+        // Ranges past the end of the block are ignored.
+        let line_count = contents.lines().count();
+        let emphasized_lines = emphasized_lines
+            .into_iter()
+            .map(|range| {
+                range.start.saturating_sub(trimmed_lines)..range.end.saturating_sub(trimmed_lines)
+            })
+            .filter(|range| !range.is_empty() && range.start < line_count)
+            .map(|range| range.start..range.end.min(line_count))
+            .collect();
+
+        ParsedMarkdownCodeBlock {
+            source_range,
+            contents: contents.into(),
+            contents_offset,
+            language,
+            highlights,
+            emphasized_lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_front_matter::FrontMatterValue;
+    use language::{tree_sitter_rust, HighlightId, Language, LanguageConfig, LanguageMatcher};
+    use pretty_assertions::assert_eq;
+
+    use ParsedMarkdownListItemType::*;
+
+    async fn parse(input: &str) -> ParsedMarkdown {
+        parse_markdown(input, None, None).await
+    }
+
+    #[gpui::test]
+    async fn test_headings() {
+        let parsed = parse("# Heading one\n## Heading two\n### Heading three").await;
+
+        assert_eq!(
+            parsed.children,
+            vec![
+                h1(text("Heading one", 2..13), 0..14),
+                h2(text("Heading two", 17..28), 14..29),
+                h3(text("Heading three", 33..46), 29..46),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_heading_slugs() {
+        let parsed = parse("# What's New?\n## Setup\n## Setup\n### C++ & `FFI`").await;
+
+        let slugs: Vec<_> = parsed
+            .children
+            .iter()
+            .filter_map(|block| match block {
+                ParsedMarkdownElement::Heading(heading) => Some(heading.slug.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(slugs, vec!["whats-new", "setup", "setup-1", "c--ffi"]);
+    }
+
+    #[gpui::test]
+    async fn test_sections() {
+        let parsed = parse("# One\n\nText\n\n## Two\n\nText\n\n# Three").await;
+
+        assert_eq!(parsed.section_end(0), 4);
+        assert_eq!(parsed.section_end(2), 4);
+        assert_eq!(parsed.section_end(4), 5);
+        assert_eq!(parsed.enclosing_headings(3), vec![0, 2]);
+        assert_eq!(parsed.enclosing_headings(4), Vec::<usize>::new());
+    }
+
+    #[gpui::test]
+    async fn test_section_source() {
+        let text =
+            "# One\n\nText [ref]\n\n[ref]: https://zed.dev\n\n## Two\n\n- item\n\n# Three\n\nEnd\n";
+        let parsed = parse(text).await;
+
+        assert_eq!(
+            parsed.section_source(text, 0).as_deref(),
+            Some("# One\n\nText [ref]\n\n[ref]: https://zed.dev\n\n## Two\n\n- item\n")
+        );
+        assert_eq!(
+            parsed.section_source(text, 2).as_deref(),
+            Some("## Two\n\n- item\n")
+        );
+        assert_eq!(
+            parsed.section_source(text, 4).as_deref(),
+            Some("# Three\n\nEnd\n")
+        );
+        // Only headings start sections.
+        assert_eq!(parsed.section_source(text, 1), None);
+    }
+
+    #[gpui::test]
+    async fn test_outline() {
+        let parsed = parse("# One\n\nText\n\n### Two\n\n## Three\n\n# Four").await;
+
+        let outline: Vec<_> = parsed
+            .outline()
+            .into_iter()
+            .map(|entry| (entry.block_ix, entry.depth, entry.text.to_string()))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                (0, 0, "One".to_string()),
+                (2, 1, "Two".to_string()),
+                (3, 1, "Three".to_string()),
+                (4, 0, "Four".to_string()),
+            ]
+        );
+        assert_eq!(section_numbers(&parsed.outline()), ["1", "1.1", "1.2", "2"]);
+    }
+
+    #[gpui::test]
+    async fn test_markdown_source() {
+        let text = "Some **bold** text\n\n```rust\nfn main() {}\n```\n\nAfter\n";
+        let parsed = parse(text).await;
+        let source = |selection: &str| {
+            let start = text.find(selection).unwrap();
+            parsed.markdown_source(text, start..start + selection.len())
+        };
+
+        assert_eq!(source("**bold** te"), "**bold** te");
+        assert_eq!(source(text), text);
+        assert_eq!(source("fn main"), "fn main");
+        assert_eq!(source("text\n\n```rust\nfn"), "text\n\nfn");
+        assert_eq!(source("() {}\n```\n\nAf"), "() {}\n\nAf");
+        assert_eq!(source("```\n\nAfter"), "\nAfter");
+    }
+
+    #[gpui::test]
+    async fn test_selected_text() {
+        let text =
+            "# Title\n\nSome **bold** text\n\n- one\n- two\n\n| a | b |\n|---|---|\n| 1 | 2 |\n";
+        let parsed = parse(text).await;
+        let selected = |selection: &str| {
+            let start = text.find(selection).unwrap();
+            parsed.selected_text(start..start + selection.len())
+        };
+
+        assert_eq!(selected("bold"), "Some bold text");
+        assert_eq!(selected("Title\n\nSome"), "Title\nSome bold text");
+        assert_eq!(selected("two\n\n| a"), "two\na\tb\n1\t2");
+
+        // Selecting from prose into a code block keeps the code's lines.
+        let text = "Run `foo`:\n\n```\nfoo\n  --bar\n```\n";
+        let parsed = parse(text).await;
+        assert_eq!(
+            parsed.selected_text(text.find("Run").unwrap()..text.find("bar").unwrap()),
+            "Run foo:\nfoo\n  --bar"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_word_count() {
+        let parsed = parse("# A title\n\nSome **bold** text — and `code`.\n\n- one\n- two\n").await;
+        let counts: Vec<usize> = parsed
+            .children
+            .iter()
+            .map(ParsedMarkdownElement::word_count)
+            .collect();
+        assert_eq!(counts, vec![2, 5, 1, 1]);
+        assert_eq!(reading_minutes(0), 0);
+        assert_eq!(reading_minutes(201), 2);
+    }
+
+    #[gpui::test]
+    async fn test_parsing_the_end_of_a_document_on_its_own() {
+        let text = "Intro\n\n# Heading\n\n- one\n- two\n";
+        let offset = text.find("# Heading").unwrap();
+
+        let mut tail = parse(&text[offset..]).await;
+        for block in &mut tail.children {
+            block.offset_source_ranges(offset as isize);
+        }
+
+        let full = parse(text).await;
+        assert_eq!(tail.children[..], full.children[1..]);
+    }
+
+    #[gpui::test]
+    async fn test_debug_tree() {
+        let parsed =
+            parse("# Heading one\n## Heading two\nCheckout this https://zed.dev link").await;
+
+        assert_eq!(
+            parsed.to_debug_tree(),
+            "\
+heading H1 #heading-one 0..14
+  text \"Heading one\" 2..13
+heading H2 #heading-two 14..29
+  text \"Heading two\" 17..28
+paragraph
+  text \"Checkout this https://zed.dev link\" 29..63
+    style 14..29 underline
+    link 14..29 -> https://zed.dev
+"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_example_document_snapshot() {
+        let parsed = parse(include_str!("../../assets/markdown/example.md")).await;
+        assert_snapshot("example", &parsed.to_debug_tree());
+    }
+
+    #[gpui::test]
+    async fn test_plain_text() {
+        let parsed = parse(
+            "# Title\n\nSee [the **docs**](https://zed.dev/docs) or https://zed.dev here\n\n- one\n\n  more\n  - [x] two\n1. three\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n> quoted\n>\n> twice\n\n```\nfn main() {}\n```\n\n![a cat](cat.png)\n",
+        )
+        .await;
+        let expected = "\
+Title
+
+See the docs or https://zed.dev here
+
+- one
+
+  more
+  - [x] two
+1. three
+
+a\tb
+1\t2
+
+> quoted
+>
+> twice
+
+    fn main() {}
+
+a cat
+";
+        assert_eq!(parsed.to_plain_text(), expected);
+
+        let with_urls = parsed.to_plain_text_with(&PlainTextOptions { link_urls: true });
+        assert!(with_urls.contains("See the docs <https://zed.dev/docs> or https://zed.dev here"));
+    }
+
+    #[gpui::test]
+    async fn test_example_document_plain_text_snapshot() {
+        let parsed = parse(include_str!("../../assets/markdown/example.md")).await;
+        assert_snapshot("example_plain_text", &parsed.to_plain_text());
+    }
+
+    /// Compares `actual` against the snapshot stored under `snapshots/`. Run
+    /// with `UPDATE_SNAPSHOTS=1` to accept changes or record new snapshots.
+    /// A missing snapshot fails, so that one that wasn't committed doesn't
+    /// pass by recording itself.
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/markdown_preview/snapshots")
+            .join(format!("{}.txt", name));
+
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!(
+                "snapshot {} is missing, run with UPDATE_SNAPSHOTS=1 to record it",
+                name
+            )
+        });
+        assert_eq!(actual, expected, "snapshot {} changed", name);
+    }
+
+    #[gpui::test]
+    async fn test_newlines_dont_new_paragraphs() {
+        let parsed = parse("Some text **that is bolded**\n and *italicized*").await;
+
+        assert_eq!(
+            parsed.children,
+            vec![p("Some text that is bolded and italicized", 0..46)]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_heading_with_paragraph() {
+        let parsed = parse("# Zed\nThe editor").await;
+
+        assert_eq!(
+            parsed.children,
+            vec![h1(text("Zed", 2..5), 0..6), p("The editor", 6..16),]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_double_newlines_do_new_paragraphs() {
+        let parsed = parse("Some text **that is bolded**\n\n and *italicized*").await;
+
+        assert_eq!(
+            parsed.children,
+            vec![
+                p("Some text that is bolded", 0..29),
+                p("and italicized", 31..47),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_bold_italic_text() {
+        let parsed = parse("Some text **that is bolded** and *italicized*").await;
+
+        assert_eq!(
+            parsed.children,
+            vec![p("Some text that is bolded and italicized", 0..45)]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_nested_bold_strikethrough_text() {
+        let parsed = parse("Some **bo~~strikethrough~~ld** text").await;
+
+        assert_eq!(parsed.children.len(), 1);
+        assert_eq!(
+            parsed.children[0],
+            ParsedMarkdownElement::Paragraph(ParsedMarkdownText {
+                source_range: 0..35,
+                contents: "Some bostrikethroughld text".into(),
+                highlights: Vec::new(),
+                region_ranges: Vec::new(),
+                regions: Vec::new(),
+                images: Vec::new(),
+                continued: false,
+            })
+        );
+
+        let paragraph = if let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] {
+            text
+        } else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(
+            paragraph.highlights,
+            vec![
+                (
+                    5..7,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        weight: FontWeight::BOLD,
+                        ..Default::default()
+                    }),
+                ),
+                (
+                    7..20,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        weight: FontWeight::BOLD,
+                        strikethrough: true,
+                        ..Default::default()
+                    }),
+                ),
+                (
+                    20..22,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        weight: FontWeight::BOLD,
+                        ..Default::default()
+                    }),
+                ),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_highlighted_text() {
+        let parsed = parse("Some ==marked **bold**== text").await;
+
+        assert_eq!(parsed.children, vec![p("Some marked bold text", 0..29)]);
+
+        let paragraph = if let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] {
+            text
+        } else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(
+            paragraph.highlights,
+            vec![
+                (
+                    5..12,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        highlight: true,
+                        ..Default::default()
+                    }),
+                ),
+                (
+                    12..16,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        weight: FontWeight::BOLD,
+                        highlight: true,
+                        ..Default::default()
+                    }),
+                ),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_highlight_delimiter_ranges() {
+        let text = "Some ==marked== text";
+        let parser = Parser::new_ext(text, ParseOptions::default().pulldown_options());
+        let ranges: Vec<_> = split_highlight_delimiters(parser.into_offset_iter())
+            .into_iter()
+            .filter(|(event, _)| matches!(event, Event::Text(_) | Event::InlineHtml(_)))
+            .map(|(_, range)| range)
+            .collect();
+        assert_eq!(ranges, vec![0..5, 5..7, 7..13, 13..15, 15..20]);
+
+        // A highlight doesn't carry on into the next list item.
+        let parsed = parse("- ==one\n- two==\n").await;
+        let ParsedMarkdownElement::ListItem(item) = &parsed.children[0] else {
+            panic!("expected a list item");
+        };
+        let ParsedMarkdownElement::Paragraph(text) = &item.content[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(&*text.contents, "==one");
+        assert_eq!(text.highlights, vec![]);
+    }
+
+    #[gpui::test]
+    async fn test_equality_operators_are_not_highlights() {
+        let parsed = parse("Check that a == b and c ==d").await;
+
+        assert_eq!(
+            parsed.children,
+            vec![p("Check that a == b and c ==d", 0..27)]
+        );
+
+        let paragraph = if let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] {
+            text
+        } else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(paragraph.highlights, vec![]);
+    }
+
+    #[gpui::test]
+    async fn test_raw_links_detection() {
+        let parsed = parse("Checkout this https://zed.dev link").await;
+
+        assert_eq!(
+            parsed.children,
+            vec![p("Checkout this https://zed.dev link", 0..34)]
+        );
+
+        let paragraph = if let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] {
+            text
+        } else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(
+            paragraph.highlights,
+            vec![(
+                14..29,
+                MarkdownHighlight::Style(MarkdownHighlightStyle {
+                    underline: true,
+                    ..Default::default()
+                }),
+            )]
+        );
+        assert_eq!(
+            paragraph.regions,
+            vec![ParsedRegion {
+                code: false,
+                link: Some(Link::Web {
+                    url: "https://zed.dev".to_string()
+                }),
+                origin: LinkOrigin::Autolink,
+            }]
+        );
+        assert_eq!(paragraph.region_ranges, vec![14..29]);
+    }
+
+    #[gpui::test]
+    async fn test_link_origins() {
+        let parsed = parse(
+            "[written](https://a.dev) [**styled** ref][ref] https://b.dev <https://c.dev>\n\n\
+             [ref]: https://d.dev\n",
+        )
+        .await;
+
+        let links: Vec<_> = parsed
+            .links()
+            .into_iter()
+            .map(|link| (link.text, link.link.url(), link.origin))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                (
+                    "written".to_string(),
+                    "https://a.dev".to_string(),
+                    LinkOrigin::Explicit
+                ),
+                (
+                    "styled ref".to_string(),
+                    "https://d.dev".to_string(),
+                    LinkOrigin::ReferenceDef
+                ),
+                (
+                    "https://b.dev".to_string(),
+                    "https://b.dev".to_string(),
+                    LinkOrigin::Autolink
+                ),
+                (
+                    "https://c.dev".to_string(),
+                    "https://c.dev".to_string(),
+                    LinkOrigin::Autolink
+                ),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_list_indentation_diagnostics() {
+        let diagnosed = |text: &'static str| async move {
+            parse(text)
+                .await
+                .diagnostics
+                .into_iter()
+                .map(|diagnostic| (&text[diagnostic.source_range.start..], diagnostic.message))
+                .map(|(item, message)| (item.lines().next().unwrap().to_string(), message))
+                .collect::<Vec<_>>()
+        };
+
+        // Lists indented the same way throughout are fine, however much
+        // that is, and however wide their markers are.
+        assert_eq!(diagnosed("- a\n  - b\n- c\n  - d\n").await, []);
+        assert_eq!(diagnosed("- a\n    - b\n- c\n    - d\n").await, []);
+        assert_eq!(diagnosed("1. a\n   - b\n10. c\n    - d\n").await, []);
+
+        // Two spaces in one place and four in another.
+        assert_eq!(
+            diagnosed("- a\n  - b\n- c\n    - d\n").await,
+            [(
+                "- d".to_string(),
+                "ambiguous list indentation; item interpreted at depth 2".to_string()
+            )]
+        );
+        // Three spaces and then two.
+        assert_eq!(
+            diagnosed("- a\n   - b\n     - c\n- d\n  - e\n").await,
+            [(
+                "- e".to_string(),
+                "ambiguous list indentation; item interpreted at depth 2".to_string()
+            )]
+        );
+        // Too little to nest under `1.`, so the bullet starts a list of its
+        // own instead.
+        assert_eq!(
+            diagnosed("1. a\n  - b\n").await,
+            [(
+                "- b".to_string(),
+                "ambiguous list indentation; item interpreted at depth 1".to_string()
+            )]
+        );
+        // Lists apart from each other are indented on their own.
+        assert_eq!(diagnosed("- a\n  - b\n\nText\n\n- c\n    - d\n").await, []);
+        assert_eq!(parse("- a\n  - b\n- c\n    - d\n").await.children.len(), 4);
+    }
+
+    #[gpui::test]
+    async fn test_hard_wrap() {
+        let text = "one\ntwo\n\n> three\n> four\n\n- five\n  six\n";
+        let contents = |parsed: &ParsedMarkdown| -> Vec<String> {
+            let mut contents = Vec::new();
+            let mut blocks: Vec<&ParsedMarkdownElement> = parsed.children.iter().collect();
+            while let Some(block) = blocks.pop() {
+                match block {
+                    ParsedMarkdownElement::Paragraph(text) => {
+                        contents.push(text.contents.to_string())
+                    }
+                    ParsedMarkdownElement::BlockQuote(quote) => {
+                        blocks.extend(quote.children.iter().rev())
+                    }
+                    ParsedMarkdownElement::ListItem(item) => {
+                        blocks.extend(item.content.iter().rev())
+                    }
+                    _ => {}
+                }
+            }
+            contents.sort();
+            contents
+        };
+
+        let parsed = parse(text).await;
+        assert_eq!(contents(&parsed), ["five six", "one two", "three four"]);
+
+        let options = ParseOptions {
+            hard_wrap: true,
+            ..Default::default()
+        };
+        let parsed = parse_markdown_with_options(text, None, None, &options).await;
+        assert_eq!(contents(&parsed), ["five six", "one\ntwo", "three\nfour"]);
+    }
+
+    #[gpui::test]
+    async fn test_split_long_paragraphs() {
+        let options = ParseOptions {
+            max_paragraph_len: 100,
+            ..Default::default()
+        };
+        let text = format!(
+            "{}\n\n*{}*\n\n=={}==\n\nshort\n",
+            "word ".repeat(100),
+            "a ".repeat(100).trim_end(),
+            "b ".repeat(100).trim_end()
+        );
+        let parsed = parse_markdown_with_options(&text, None, None, &options).await;
+        let paragraphs: Vec<_> = parsed
+            .children
+            .iter()
+            .map(|block| match block {
+                ParsedMarkdownElement::Paragraph(text) => text,
+                _ => panic!("expected a paragraph, got {block:?}"),
+            })
+            .collect();
+        assert_eq!(paragraphs.len(), 8);
+        assert_eq!(
+            paragraphs[..5]
+                .iter()
+                .map(|text| text.contents.as_ref())
+                .collect::<String>(),
+            "word ".repeat(100).trim_end()
+        );
+        assert_eq!(
+            paragraphs
+                .iter()
+                .map(|text| text.continued)
+                .collect::<Vec<_>>(),
+            [false, true, true, true, true, false, false, false]
+        );
+        for pair in paragraphs[..5].windows(2) {
+            assert_eq!(pair[0].source_range.end, pair[1].source_range.start);
+        }
+        assert_eq!(paragraphs[1].source_range, 105..210);
+        // Emphasized and highlighted text isn't split.
+        assert_eq!(paragraphs[5].contents.len(), 199);
+        assert_eq!(&*paragraphs[6].contents, "b ".repeat(100).trim_end());
+    }
+
+    #[gpui::test]
+    async fn test_autolinks_in_all_inline_text() {
+        let parsed = parse(
+            "# Docs at https://zed.dev\n\n\
+             - see (https://zed.dev/docs)\n\n\
+             | a |\n|---|\n| https://a.dev |\n\n\
+             > **Bold** https://b.dev/a_b_c\n\n\
+             Some https://en.wikipedia.org/wiki/Rust_(language).\n",
+        )
+        .await;
+        let links: Vec<_> = parsed
+            .links()
+            .into_iter()
+            .map(|link| (link.text, link.origin))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("https://zed.dev".to_string(), LinkOrigin::Autolink),
+                ("https://zed.dev/docs".to_string(), LinkOrigin::Autolink),
+                ("https://a.dev".to_string(), LinkOrigin::Autolink),
+                ("https://b.dev/a_b_c".to_string(), LinkOrigin::Autolink),
+                (
+                    "https://en.wikipedia.org/wiki/Rust_(language)".to_string(),
+                    LinkOrigin::Autolink
+                ),
+            ]
+        );
+
+        // The URL at the end of the heading is underlined where it is, after
+        // the rest of the text.
+        let ParsedMarkdownElement::Heading(heading) = &parsed.children[0] else {
+            panic!("Expected a heading");
+        };
+        assert_eq!(heading.contents.region_ranges, vec![8..23]);
+
+        // Text styled before a URL keeps its style, and the URL's underline
+        // is where the URL is.
+        let ParsedMarkdownElement::BlockQuote(quote) = &parsed.children[3] else {
+            panic!("Expected a block quote");
+        };
+        let ParsedMarkdownElement::Paragraph(paragraph) = &quote.children[0] else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(paragraph.region_ranges, vec![5..24]);
+        assert_eq!(
+            paragraph.highlights,
+            vec![
+                (
+                    0..4,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        weight: FontWeight::BOLD,
+                        ..Default::default()
+                    })
+                ),
+                (
+                    5..24,
+                    MarkdownHighlight::Style(MarkdownHighlightStyle {
+                        underline: true,
+                        ..Default::default()
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_fragment_links() {
+        let parsed = parse("See [setup](#setup)").await;
 
-        ParsedMarkdownCodeBlock {
-            source_range,
-            contents: code.trim().to_string().into(),
-            language,
-            highlights,
-        }
+        let ParsedMarkdownElement::Paragraph(paragraph) = &parsed.children[0] else {
+            panic!("Expected a paragraph");
+        };
+        assert_eq!(
+            paragraph.regions[0].link,
+            Some(Link::Fragment {
+                fragment: "setup".to_string()
+            })
+        );
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    use gpui::BackgroundExecutor;
-    use language::{tree_sitter_rust, HighlightId, Language, LanguageConfig, LanguageMatcher};
-    use pretty_assertions::assert_eq;
+    #[test]
+    fn test_expand_path() {
+        let home = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        let var = |name: &str| match name {
+            "DOCS" => Some("/srv/docs".to_string()),
+            name if name == home => Some("/home/ada".to_string()),
+            _ => None,
+        };
+        let expand = |text: &str| expand_path_with(text, None, var).into_owned();
+        assert_eq!(expand("$DOCS/guide.md"), "/srv/docs/guide.md");
+        assert_eq!(expand("${DOCS}-old/guide.md"), "/srv/docs-old/guide.md");
+        // Variables that aren't set, and lone dollar signs, stay as they are.
+        assert_eq!(expand("$UNSET/a.md"), "$UNSET/a.md");
+        assert_eq!(expand("costs $ and ${x.md"), "costs $ and ${x.md");
+        assert_eq!(expand("~/notes.md"), "/home/ada/notes.md");
+        assert_eq!(expand("~notes.md"), "~notes.md");
 
-    use ParsedMarkdownListItemType::*;
+        assert_eq!(
+            Link::identify(None, "file:///srv/docs/guide.md#L3".to_string()),
+            Some(Link::Path {
+                display_path: PathBuf::from("/srv/docs/guide.md"),
+                path: PathBuf::from("/srv/docs/guide.md"),
+                fragment: Some("L3".to_string()),
+            })
+        );
+    }
 
-    async fn parse(input: &str) -> ParsedMarkdown {
-        parse_markdown(input, None, None).await
+    #[test]
+    fn test_split_path_fragment() {
+        assert_eq!(
+            split_path_fragment("docs/guide.md#install", None),
+            ("docs/guide.md", Some("install"))
+        );
+        assert_eq!(split_path_fragment("guide.md", None), ("guide.md", None));
+        assert_eq!(split_path_fragment("#install", None), ("#install", None));
+
+        // A `#` in the name of a file that exists is part of the name.
+        let directory =
+            std::env::temp_dir().join(format!("markdown-fragments-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("C#.md"), "# C#\n").unwrap();
+        assert_eq!(
+            split_path_fragment("C#.md", Some(&directory)),
+            ("C#.md", None)
+        );
+        assert_eq!(
+            split_path_fragment("C#.md#usage", Some(&directory)),
+            ("C#.md", Some("usage"))
+        );
+        std::fs::remove_dir_all(&directory).ok();
     }
 
     #[gpui::test]
-    async fn test_headings() {
-        let parsed = parse("# Heading one\n## Heading two\n### Heading three").await;
+    async fn test_block_at_offset() {
+        let text = "First\n\n[zed]: https://zed.dev\n\nSecond [zed]\n";
+        let parsed = parse(text).await;
+        assert_eq!(parsed.children.len(), 2);
 
+        assert_eq!(parsed.block_at_offset(0), Some(0));
+        // The reference definition isn't a block of its own.
         assert_eq!(
-            parsed.children,
-            vec![
-                h1(text("Heading one", 2..13), 0..14),
-                h2(text("Heading two", 17..28), 14..29),
-                h3(text("Heading three", 33..46), 29..46),
-            ]
+            parsed.block_at_offset(text.find("[zed]:").unwrap()),
+            Some(0)
+        );
+        assert_eq!(
+            parsed.block_at_offset(text.find("Second").unwrap()),
+            Some(1)
         );
+        assert_eq!(parsed.block_at_offset(text.len()), Some(1));
+        let empty = ParsedMarkdown {
+            children: Vec::new(),
+            front_matter: None,
+            includes: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        assert_eq!(empty.block_at_offset(0), None);
     }
 
     #[gpui::test]
-    async fn test_newlines_dont_new_paragraphs() {
-        let parsed = parse("Some text **that is bolded**\n and *italicized*").await;
+    async fn test_front_matter() {
+        let text = "---\ntitle: Notes\n---\n\n# Heading\n";
+        let parsed = parse(text).await;
+        let front_matter = parsed.front_matter.unwrap();
+        assert_eq!(front_matter.source_range.start, 0);
+        assert_eq!(
+            front_matter.entries,
+            vec![(
+                "title".to_string(),
+                FrontMatterValue::Text("Notes".to_string())
+            )]
+        );
+        assert!(matches!(
+            parsed.children.as_slice(),
+            [ParsedMarkdownElement::Heading(_)]
+        ));
+
+        // Only the start of a document can be front matter.
+        let parsed = parse("# Heading\n\n---\ntitle: Notes\n---\n").await;
+        assert_eq!(parsed.front_matter, None);
+    }
 
+    #[gpui::test]
+    async fn test_image_rows() {
+        let text = "[![CI](https://ci.dev/badge.svg)](https://ci.dev)\n![](https://img.dev/license.svg \"MIT\") ![](https://img.dev/v1.svg)\n\n![Logo](logo.png) and text\n";
+        let parsed = parse(text).await;
+        let ParsedMarkdownElement::Paragraph(row) = &parsed.children[0] else {
+            panic!("unexpected blocks: {:?}", parsed.children);
+        };
+        assert_eq!(row.contents, "");
+        let captions: Vec<_> = row.images.iter().map(|image| image.caption()).collect();
+        assert_eq!(captions, vec!["CI", "MIT", "v1.svg"]);
         assert_eq!(
-            parsed.children,
-            vec![p("Some text that is bolded and italicized", 0..46)]
+            row.images[0].target,
+            Some(Link::Web {
+                url: "https://ci.dev".to_string()
+            })
+        );
+        assert_eq!(row.images[1].target, None);
+        assert_eq!(
+            &text[row.images[2].source_range.clone()],
+            "![](https://img.dev/v1.svg)"
         );
+
+        let links = parsed.links();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].text, "CI");
+
+        // An image with text next to it isn't a row.
+        assert!(parsed.children[1..].iter().all(|block| match block {
+            ParsedMarkdownElement::Paragraph(text) => text.images.is_empty(),
+            _ => true,
+        }));
     }
 
     #[gpui::test]
-    async fn test_heading_with_paragraph() {
-        let parsed = parse("# Zed\nThe editor").await;
+    async fn test_text_kept_as_source_ranges() {
+        let text =
+            "# Title\n\nPlain text\n\n| a | b |\n|---|---|\n| one | two |\n\nSome **bold** text\n\nAn \\*escaped\\* star\n";
+        let parsed = parse(text).await;
+        let [ParsedMarkdownElement::Heading(heading), ParsedMarkdownElement::Paragraph(plain), ParsedMarkdownElement::Table(table), ParsedMarkdownElement::Paragraph(bold), ParsedMarkdownElement::Paragraph(escaped)] =
+            parsed.children.as_slice()
+        else {
+            panic!("unexpected blocks: {:?}", parsed.children);
+        };
+        let is_source = |text: &SourceText| matches!(text, SourceText::Source { .. });
+
+        assert_eq!(heading.contents.contents, "Title");
+        assert!(is_source(&heading.contents.contents));
+        assert_eq!(plain.contents, "Plain text");
+        assert!(is_source(&plain.contents));
+        assert_eq!(table.body[0].children[0].contents, "one");
+        assert!(is_source(&table.body[0].children[0].contents));
+
+        // Text with markup or escapes in between isn't in the source as it's
+        // shown.
+        assert_eq!(bold.contents, "Some bold text");
+        assert!(!is_source(&bold.contents));
+        assert_eq!(escaped.contents, "An *escaped* star");
+        assert!(!is_source(&escaped.contents));
+    }
 
+    #[gpui::test]
+    async fn test_html_blocks() {
+        let text = "<p align=\"center\">\n  <img src=\"https://zed.dev/logo.png\" alt=\"Logo\">\n</p>\n\n<!-- A comment -->\n\n<h2>Usage</h2>\n\nText\n";
+        let parsed = parse(text).await;
+        let [ParsedMarkdownElement::Image(image), ParsedMarkdownElement::Heading(heading), ParsedMarkdownElement::Paragraph(paragraph)] =
+            parsed.children.as_slice()
+        else {
+            panic!("unexpected blocks: {:?}", parsed.children);
+        };
+        assert_eq!(image.alt_text, "Logo");
         assert_eq!(
-            parsed.children,
-            vec![h1(text("Zed", 2..5), 0..6), p("The editor", 6..16),]
+            &text[image.source_range.clone()],
+            "<img src=\"https://zed.dev/logo.png\" alt=\"Logo\">"
         );
+        assert_eq!(heading.slug, "usage");
+        assert_eq!(paragraph.contents, "Text");
     }
 
     #[gpui::test]
-    async fn test_double_newlines_do_new_paragraphs() {
-        let parsed = parse("Some text **that is bolded**\n\n and *italicized*").await;
+    async fn test_includes() {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-includes-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("parts")).unwrap();
+        for (file, text) in [
+            ("intro.md", "Intro text\n\n!include(parts/deep.md)\n"),
+            ("parts/deep.md", "## Deep\n"),
+            ("loop.md", "!include(loop.md)\n"),
+        ] {
+            std::fs::write(directory.join(file), text).unwrap();
+        }
+        let text =
+            "# Guide\n\n!include(./intro.md)\n\n!include(missing.md)\n\n!include(loop.md)\n\nEnd\n";
+        let options = ParseOptions {
+            includes: true,
+            ..Default::default()
+        };
+        let parsed =
+            parse_markdown_with_options(text, Some(directory.clone()), None, &options).await;
 
+        let [ParsedMarkdownElement::Heading(guide), ParsedMarkdownElement::Paragraph(intro), ParsedMarkdownElement::Heading(deep), ParsedMarkdownElement::BlockQuote(missing), ParsedMarkdownElement::BlockQuote(cycle), ParsedMarkdownElement::Paragraph(end)] =
+            parsed.children.as_slice()
+        else {
+            panic!("unexpected blocks: {:?}", parsed.children);
+        };
+        assert_eq!(guide.slug, "guide");
+        assert_eq!(intro.contents, "Intro text");
+        assert_eq!(intro.source_range.start, 0);
+        assert_eq!(deep.slug, "deep");
+        assert!(text[missing.source_range.clone()].starts_with("!include(missing.md)"));
+        let error_text = |block: &ParsedMarkdownBlockQuote| match &block.children[..] {
+            [ParsedMarkdownElement::Paragraph(text)] => text.contents.clone(),
+            children => panic!("unexpected blocks: {:?}", children),
+        };
         assert_eq!(
-            parsed.children,
+            error_text(missing),
+            "Could not include missing.md: the file doesn't exist"
+        );
+        assert_eq!(
+            error_text(cycle),
+            "Could not include loop.md: it includes itself"
+        );
+        assert_eq!(end.contents, "End");
+
+        let path = |file: &str| directory.join(file).canonicalize().unwrap();
+        let includes: Vec<_> = parsed
+            .includes
+            .iter()
+            .map(|include| (include.path.clone(), include.blocks.clone()))
+            .collect();
+        assert_eq!(
+            includes,
             vec![
-                p("Some text that is bolded", 0..29),
-                p("and italicized", 31..47),
+                (path("intro.md"), 1..2),
+                (path("parts/deep.md"), 2..3),
+                (path("loop.md"), 4..5),
             ]
         );
-    }
-
-    #[gpui::test]
-    async fn test_bold_italic_text() {
-        let parsed = parse("Some text **that is bolded** and *italicized*").await;
+        assert!(parsed.is_included(2));
+        assert!(!parsed.is_included(3));
 
+        let shallow = ParseOptions {
+            max_include_depth: 1,
+            ..options.clone()
+        };
+        let parsed =
+            parse_markdown_with_options(text, Some(directory.clone()), None, &shallow).await;
+        let ParsedMarkdownElement::BlockQuote(too_deep) = &parsed.children[2] else {
+            panic!("unexpected blocks: {:?}", parsed.children);
+        };
         assert_eq!(
-            parsed.children,
-            vec![p("Some text that is bolded and italicized", 0..45)]
+            error_text(too_deep),
+            "Could not include parts/deep.md: includes are nested more than 1 deep"
         );
+
+        // Without the option, directives are left as they're written.
+        let parsed = parse_markdown(text, Some(directory.clone()), None).await;
+        assert!(parsed.includes.is_empty());
+        assert_eq!(parsed.children.len(), 5);
+
+        std::fs::remove_dir_all(&directory).ok();
     }
 
     #[gpui::test]
-    async fn test_nested_bold_strikethrough_text() {
-        let parsed = parse("Some **bo~~strikethrough~~ld** text").await;
+    async fn test_include_cycles_and_confinement() {
+        let outside =
+            std::env::temp_dir().join(format!("markdown-include-root-{}", std::process::id()));
+        let directory = outside.join("docs");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(outside.join("secret.md"), "Secret\n").unwrap();
+        std::fs::write(directory.join("b.md"), "B text\n\n!include(a.md)\n").unwrap();
+        let text = "A text\n\n!include(b.md)\n\n!include(../secret.md)\n";
+        let document_path = directory.join("a.md");
+        std::fs::write(&document_path, text).unwrap();
+
+        let options = ParseOptions {
+            includes: true,
+            document_path: Some(document_path),
+            ..Default::default()
+        };
+        let parsed =
+            parse_markdown_with_options(text, Some(directory.clone()), None, &options).await;
+        let text_of = |block: &ParsedMarkdownElement| match block {
+            ParsedMarkdownElement::Paragraph(text) => text.contents.to_string(),
+            ParsedMarkdownElement::BlockQuote(quote) => match &quote.children[..] {
+                [ParsedMarkdownElement::Paragraph(text)] => text.contents.to_string(),
+                children => panic!("unexpected blocks: {:?}", children),
+            },
+            block => panic!("unexpected block: {:?}", block),
+        };
+        // The document being read counts as being included, so the cycle is
+        // broken before it's included again.
+        assert_eq!(
+            parsed.children.iter().map(text_of).collect::<Vec<_>>(),
+            vec![
+                "A text",
+                "B text",
+                "Could not include a.md: it includes itself",
+                "Could not include ../secret.md: it's outside of the document's folder",
+            ]
+        );
 
-        assert_eq!(parsed.children.len(), 1);
+        std::fs::remove_dir_all(&outside).ok();
+    }
+
+    #[test]
+    fn test_identify_relative_links() {
+        let directory = PathBuf::from("/docs");
         assert_eq!(
-            parsed.children[0],
-            ParsedMarkdownElement::Paragraph(ParsedMarkdownText {
-                source_range: 0..35,
-                contents: "Some bostrikethroughld text".to_string(),
-                highlights: Vec::new(),
-                region_ranges: Vec::new(),
-                regions: Vec::new(),
+            Link::identify(Some(directory.clone()), "my%20notes.md#usage".to_string()),
+            Some(Link::Path {
+                display_path: PathBuf::from("my notes.md"),
+                path: directory.join("my notes.md"),
+                fragment: Some("usage".to_string()),
+            })
+        );
+        assert_eq!(
+            Link::identify(Some(directory.clone()), "mailto:me@zed.dev".to_string()),
+            Some(Link::Web {
+                url: "mailto:me@zed.dev".to_string()
             })
         );
+        assert_eq!(Link::identify(None, "other.md".to_string()), None);
+    }
 
-        let paragraph = if let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] {
-            text
-        } else {
-            panic!("Expected a paragraph");
+    #[gpui::test]
+    async fn test_unicode_and_space_destinations() {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-destinations-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("docs")).unwrap();
+        std::fs::write(directory.join("my file with spaces.md"), "# Spaces\n").unwrap();
+        std::fs::write(directory.join("docs/設計.md"), "# 設計\n").unwrap();
+        let text =
+            "[a](<my file with spaces.md#usage>) [b](./docs/設計.md) [c](https://zed.dev/a%20b/設計)\n";
+        let parsed = parse_markdown(text, Some(directory.clone()), None).await;
+
+        let ParsedMarkdownElement::Paragraph(paragraph) = &parsed.children[0] else {
+            panic!("unexpected blocks: {:?}", parsed.children);
         };
+        let links: Vec<_> = paragraph
+            .regions
+            .iter()
+            .filter_map(|region| region.link.clone())
+            .collect();
         assert_eq!(
-            paragraph.highlights,
-            vec![
-                (
-                    5..7,
-                    MarkdownHighlight::Style(MarkdownHighlightStyle {
-                        weight: FontWeight::BOLD,
-                        ..Default::default()
-                    }),
-                ),
-                (
-                    7..20,
-                    MarkdownHighlight::Style(MarkdownHighlightStyle {
-                        weight: FontWeight::BOLD,
-                        strikethrough: true,
-                        ..Default::default()
-                    }),
-                ),
-                (
-                    20..22,
-                    MarkdownHighlight::Style(MarkdownHighlightStyle {
-                        weight: FontWeight::BOLD,
-                        ..Default::default()
-                    }),
-                ),
+            links[..2],
+            [
+                Link::Path {
+                    display_path: PathBuf::from("my file with spaces.md"),
+                    path: directory.join("my file with spaces.md"),
+                    fragment: Some("usage".to_string()),
+                },
+                Link::Path {
+                    display_path: PathBuf::from("./docs/設計.md"),
+                    path: directory.join("./docs/設計.md"),
+                    fragment: None,
+                },
             ]
         );
+        // Raw Unicode is kept as it's written, and only encoded once it's
+        // opened, leaving what was already encoded as it is.
+        assert_eq!(links[2].url(), "https://zed.dev/a%20b/設計");
+        assert_eq!(
+            links[2].os_url().as_deref(),
+            Some("https://zed.dev/a%20b/%E8%A8%AD%E8%A8%88")
+        );
+        assert!(links[0]
+            .os_url()
+            .unwrap()
+            .ends_with("/my%20file%20with%20spaces.md#usage"));
+        assert!(links[1]
+            .os_url()
+            .unwrap()
+            .ends_with("/docs/%E8%A8%AD%E8%A8%88.md"));
+        assert_eq!(encode_url("https://zed.dev/a b"), "https://zed.dev/a%20b");
+        assert_eq!(encode_url("https://zed.dev"), "https://zed.dev");
+
+        std::fs::remove_dir_all(&directory).ok();
     }
 
     #[gpui::test]
-    async fn test_raw_links_detection() {
-        let parsed = parse("Checkout this https://zed.dev link").await;
+    async fn test_base_url() {
+        let options = ParseOptions {
+            base_url: Some(
+                Url::parse("https://raw.githubusercontent.com/org/repo/main/docs/").unwrap(),
+            ),
+            ..Default::default()
+        };
+        let text = "[a](./usage.md) [b](../README.md#install) [c](/img.png) [d](#intro) [e](mailto:me@zed.dev)\n\n![logo](assets/logo.png)\n";
+        let parsed = parse_markdown_with_options(text, None, None, &options).await;
 
+        let ParsedMarkdownElement::Paragraph(paragraph) = &parsed.children[0] else {
+            panic!("unexpected blocks: {:?}", parsed.children);
+        };
+        let links: Vec<_> = paragraph
+            .regions
+            .iter()
+            .filter_map(|region| region.link.as_ref().map(Link::url))
+            .collect();
         assert_eq!(
-            parsed.children,
-            vec![p("Checkout this https://zed.dev link", 0..34)]
+            links,
+            vec![
+                "https://raw.githubusercontent.com/org/repo/main/docs/usage.md",
+                "https://raw.githubusercontent.com/org/repo/main/README.md#install",
+                "https://raw.githubusercontent.com/img.png",
+                "#intro",
+                "mailto:me@zed.dev",
+            ]
         );
-
-        let paragraph = if let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] {
-            text
-        } else {
-            panic!("Expected a paragraph");
-        };
+        let image = parsed
+            .children
+            .iter()
+            .find_map(|block| match block {
+                ParsedMarkdownElement::Image(image) => image.link.as_ref(),
+                _ => None,
+            })
+            .unwrap();
         assert_eq!(
-            paragraph.highlights,
-            vec![(
-                14..29,
-                MarkdownHighlight::Style(MarkdownHighlightStyle {
-                    underline: true,
-                    ..Default::default()
-                }),
-            )]
+            image.url(),
+            "https://raw.githubusercontent.com/org/repo/main/docs/assets/logo.png"
         );
+
+        // Documents on disk resolve links against their directory instead.
+        let parsed =
+            parse_markdown_with_options(text, Some(PathBuf::from("/docs")), None, &options).await;
+        let ParsedMarkdownElement::Paragraph(paragraph) = &parsed.children[0] else {
+            panic!("unexpected blocks: {:?}", parsed.children);
+        };
         assert_eq!(
-            paragraph.regions,
-            vec![ParsedRegion {
-                code: false,
-                link: Some(Link::Web {
-                    url: "https://zed.dev".to_string()
-                }),
-            }]
+            paragraph.regions[0].link.as_ref().map(Link::kind),
+            Some(LinkKind::Path)
         );
-        assert_eq!(paragraph.region_ranges, vec![14..29]);
     }
 
     #[gpui::test]
@@ -945,6 +3288,148 @@ Some other content
         );
     }
 
+    #[gpui::test]
+    async fn test_parse_options() {
+        let text = "| a |\n|---|\n| b |\n\n\"Quoted\"";
+        let parsed = parse(text).await;
+        assert!(matches!(
+            parsed.children[0],
+            ParsedMarkdownElement::Table(_)
+        ));
+
+        let options = ParseOptions {
+            tables: false,
+            smart_punctuation: false,
+            ..Default::default()
+        };
+        let parsed = parse_markdown_with_options(text, None, None, &options).await;
+        assert!(matches!(
+            parsed.children[0],
+            ParsedMarkdownElement::Paragraph(_)
+        ));
+        let Some(ParsedMarkdownElement::Paragraph(quoted)) = parsed.children.last() else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(quoted.contents, "\"Quoted\"");
+    }
+
+    #[gpui::test]
+    async fn test_parse_in_chunks() {
+        let text: String = (0..2000)
+            .map(|ix| format!("## Section\n\nParagraph {ix} with *emphasis*.\n\n"))
+            .collect();
+        let options = ParseOptions::default();
+        let mut chunks = Vec::new();
+        let parsed = parse_markdown_in_chunks(&text, None, None, &options, |blocks| {
+            chunks.push(blocks.to_vec())
+        })
+        .await;
+        assert!(chunks.len() > 1);
+
+        let mut blocks: Vec<_> = chunks.into_iter().flatten().collect();
+        assert!(blocks.len() < parsed.children.len());
+        assign_heading_slugs(&mut blocks);
+        assert_eq!(blocks, parsed.children[..blocks.len()]);
+        assert_eq!(
+            parsed,
+            parse_markdown_with_options(&text, None, None, &options).await
+        );
+    }
+
+    #[gpui::test]
+    async fn test_parse_in_parallel(executor: BackgroundExecutor) {
+        let text: String = (0..3000)
+            .map(|ix| {
+                format!(
+                    "## Section\n\nSee [the notes][notes] and [^{ix}].\n\n- item {ix}\n  - nested\n\n| a | b |\n|---|---|\n| {ix} | ==hi== |\n\n[^{ix}]: Footnote {ix}.\n\n"
+                )
+            })
+            .chain(["[notes]: https://zed.dev/notes\n".to_string()])
+            .collect();
+        let text = format!("---\ntitle: Parallel\n---\n{text}");
+        let options = ParseOptions::default();
+        let tokens: Vec<_> = Parser::new_ext(&text, options.pulldown_options()).collect();
+        assert!(tokens.len() >= 4 * MIN_EVENTS_PER_THREAD);
+
+        let runs = split_at_top_level_blocks(
+            tokens.iter().cloned().map(|event| (event, 0..0)).collect(),
+            4,
+        );
+        assert_eq!(runs.len(), 4);
+        assert!(runs
+            .iter()
+            .all(|run| matches!(run.last(), Some((Event::End(_), _)))));
+
+        let parsed = parse_markdown_parallel(&text, None, None, &options, &executor).await;
+        assert!(parsed.front_matter.is_some());
+        assert_eq!(
+            parsed,
+            parse_markdown_with_options(&text, None, None, &options).await
+        );
+    }
+
+    #[gpui::test]
+    async fn test_parse_timing() {
+        let text = "---\ntitle: Timed\n---\n# Heading\n\n| a |\n|---|\n| ==b== |\n\n<p>html</p>\n\n```rust\nfn main() {}\n```\n";
+        let options = ParseOptions::default();
+        let (parsed, timing) = parse_markdown_timed(text, None, None, &options).await;
+        assert_eq!(
+            parsed,
+            parse_markdown_with_options(text, None, None, &options).await
+        );
+
+        let extensions: Vec<_> = timing.extensions.keys().copied().collect();
+        assert_eq!(
+            extensions,
+            ["front_matter", "heading_slugs", "highlights", "html"]
+        );
+        assert!(timing.accounted() <= timing.total);
+
+        let json = serde_json::to_value(&timing).unwrap();
+        assert!(json["tables"].is_object());
+        assert!(json["extensions"]["html"].is_object());
+    }
+
+    #[gpui::test]
+    async fn test_table_to_tsv() {
+        let markdown = "\
+| Name | Notes |
+|------|-------|
+| **Zed** | [fast](https://zed.dev) |
+| a\\b | tab\there |";
+
+        let parsed = parse(markdown).await;
+        let ParsedMarkdownElement::Table(table) = &parsed.children[0] else {
+            panic!("Expected a table");
+        };
+
+        assert_eq!(
+            table.to_tsv(),
+            "Name\tNotes\nZed\tfast\na\\\\b\ttab\\there\n"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_table_sorted_row_indices() {
+        let markdown = "\
+| Name | Size |
+|------|------|
+| b    | 10   |
+| a    | 9.5  |
+| c    | 10   |";
+
+        let parsed = parse(markdown).await;
+        let ParsedMarkdownElement::Table(table) = &parsed.children[0] else {
+            panic!("Expected a table");
+        };
+
+        assert_eq!(table.sorted_row_indices(0, true), vec![1, 0, 2]);
+        assert_eq!(table.sorted_row_indices(0, false), vec![2, 0, 1]);
+        // Numbers are not compared as text, and ties keep their order.
+        assert_eq!(table.sorted_row_indices(1, true), vec![1, 0, 2]);
+        assert_eq!(table.sorted_row_indices(1, false), vec![0, 2, 1]);
+    }
+
     #[gpui::test]
     async fn test_list_basic() {
         let parsed = parse(
@@ -1248,6 +3733,43 @@ fn main() {
         );
     }
 
+    #[gpui::test]
+    async fn test_code_block_with_emphasized_lines() {
+        let parsed = parse(
+            "\
+```rust {4-5,2-3, 9}
+a
+b
+c
+d
+e
+```
+",
+        )
+        .await;
+
+        assert_eq!(
+            parsed.children,
+            vec![ParsedMarkdownElement::CodeBlock(ParsedMarkdownCodeBlock {
+                source_range: 0..35,
+                language: Some("rust".to_string()),
+                contents: "a\nb\nc\nd\ne".into(),
+                contents_offset: 21,
+                highlights: None,
+                emphasized_lines: vec![1..5],
+            })]
+        );
+
+        // The blank line at the start isn't shown, so the lines after it
+        // move up.
+        let parsed = parse("```rust {1,3}\n\na\nb\n```\n").await;
+        let ParsedMarkdownElement::CodeBlock(code_block) = &parsed.children[0] else {
+            panic!("expected a code block");
+        };
+        assert_eq!(&*code_block.contents, "a\nb");
+        assert_eq!(code_block.emphasized_lines, vec![1..2]);
+    }
+
     fn rust_lang() -> Arc<Language> {
         Arc::new(Language::new(
             LanguageConfig {
@@ -1267,6 +3789,7 @@ fn main() {
         ParsedMarkdownElement::Heading(ParsedMarkdownHeading {
             source_range,
             level: HeadingLevel::H1,
+            slug: heading_slug(&contents.contents),
             contents,
         })
     }
@@ -1275,6 +3798,7 @@ fn main() {
         ParsedMarkdownElement::Heading(ParsedMarkdownHeading {
             source_range,
             level: HeadingLevel::H2,
+            slug: heading_slug(&contents.contents),
             contents,
         })
     }
@@ -1283,6 +3807,7 @@ fn main() {
         ParsedMarkdownElement::Heading(ParsedMarkdownHeading {
             source_range,
             level: HeadingLevel::H3,
+            slug: heading_slug(&contents.contents),
             contents,
         })
     }
@@ -1297,7 +3822,9 @@ fn main() {
             region_ranges: Vec::new(),
             regions: Vec::new(),
             source_range,
-            contents: contents.to_string(),
+            contents: contents.to_string().into(),
+            images: Vec::new(),
+            continued: false,
         }
     }
 
@@ -1319,9 +3846,12 @@ fn main() {
     ) -> ParsedMarkdownElement {
         ParsedMarkdownElement::CodeBlock(ParsedMarkdownCodeBlock {
             source_range,
+            // After the ``` and the language of the fence.
+            contents_offset: 4 + language.as_ref().map_or(0, String::len),
             language,
             contents: code.to_string().into(),
             highlights,
+            emphasized_lines: Vec::new(),
         })
     }
 
@@ -0,0 +1,1013 @@
+use super::markdown_elements::{
+    HeadingLevel, LinkKind, MarkdownHighlight, ParsedMarkdown, ParsedMarkdownElement,
+    ParsedMarkdownImage, ParsedMarkdownListItemType, ParsedMarkdownTable, ParsedMarkdownText,
+};
+use gpui::FontWeight;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use std::ops::Range;
+
+/// Page sizes are in PostScript points, 72 to the inch.
+const A4: (f32, f32) = (595., 842.);
+
+const BODY_SIZE: f32 = 11.;
+const CODE_SIZE: f32 = 9.;
+const LINE_HEIGHT: f32 = 1.4;
+const LIST_INDENT: f32 = 18.;
+const QUOTE_INDENT: f32 = 14.;
+const CELL_PADDING: f32 = 4.;
+
+/// How [`export_pdf`] lays out the document.
+#[derive(Debug, Clone)]
+pub struct PdfExportOptions {
+    /// The width and height of each page, in points.
+    pub page_size: (f32, f32),
+    /// The space left blank around the text on every side, in points.
+    pub margin: f32,
+    /// Prints the title at the top of every page and the page number at the
+    /// bottom.
+    pub header_and_footer: bool,
+    /// The title for the header and the PDF's metadata. Falls back to the
+    /// text of the first heading.
+    pub title: Option<String>,
+    /// Outlines code blocks instead of shading them, since printers often
+    /// leave out backgrounds.
+    pub code_block_borders: bool,
+    /// Follows each link to a web page with its URL in parentheses, for
+    /// paper, where links can't be clicked.
+    pub link_urls: bool,
+}
+
+impl Default for PdfExportOptions {
+    fn default() -> Self {
+        Self {
+            page_size: A4,
+            margin: 56.,
+            header_and_footer: false,
+            title: None,
+            code_block_borders: false,
+            link_urls: false,
+        }
+    }
+}
+
+/// Lays the document out on fixed-size pages and writes them as a PDF.
+///
+/// Text is set in the standard PDF fonts, which every reader has, so nothing
+/// is embedded; characters outside of Latin-1 are replaced. Images are shown
+/// as their alt text.
+pub fn export_pdf(parsed: &ParsedMarkdown, options: &PdfExportOptions) -> Vec<u8> {
+    let title = options.title.clone().unwrap_or_else(|| {
+        parsed
+            .outline()
+            .first()
+            .map_or_else(|| "Document".to_string(), |entry| entry.text.to_string())
+    });
+    let pages = layout_pages(parsed, options);
+    write_pdf(&pages, &title, options)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+impl Font {
+    const ALL: [Font; 5] = [
+        Font::Regular,
+        Font::Bold,
+        Font::Italic,
+        Font::BoldItalic,
+        Font::Mono,
+    ];
+
+    fn resource_name(self) -> Name<'static> {
+        Name(match self {
+            Font::Regular => b"F1",
+            Font::Bold => b"F2",
+            Font::Italic => b"F3",
+            Font::BoldItalic => b"F4",
+            Font::Mono => b"F5",
+        })
+    }
+
+    fn base_font(self) -> Name<'static> {
+        Name(match self {
+            Font::Regular => b"Helvetica",
+            Font::Bold => b"Helvetica-Bold",
+            Font::Italic => b"Helvetica-Oblique",
+            Font::BoldItalic => b"Helvetica-BoldOblique",
+            Font::Mono => b"Courier",
+        })
+    }
+
+    /// The width of `text` set at `size`, from the fonts' metrics.
+    fn width(self, text: &str, size: f32) -> f32 {
+        let units: u32 = text
+            .chars()
+            .map(|c| match self {
+                Font::Mono => 600,
+                Font::Regular | Font::Italic => helvetica_width(c),
+                // The bold cut is a little wider.
+                Font::Bold | Font::BoldItalic => helvetica_width(c) * 21 / 20,
+            })
+            .sum();
+        units as f32 * size / 1000.
+    }
+}
+
+/// Helvetica's advance widths for printable ASCII, in thousandths of an em.
+const HELVETICA_WIDTHS: [u32; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+fn helvetica_width(c: char) -> u32 {
+    match c as u32 {
+        code @ 32..=126 => HELVETICA_WIDTHS[code as usize - 32],
+        _ => 556,
+    }
+}
+
+/// Encodes `text` for fonts with the WinAnsi encoding.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            '\t' => b' ',
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// A run of text at a position within a [`Chunk`], measured from the chunk's
+/// left edge and top to the text's baseline.
+#[derive(Debug, Clone)]
+struct Placed {
+    x: f32,
+    baseline: f32,
+    font: Font,
+    size: f32,
+    text: String,
+}
+
+/// A gray box within a [`Chunk`], for code backgrounds, rules, table borders
+/// and quote bars.
+#[derive(Debug, Clone)]
+struct Shape {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    gray: f32,
+}
+
+/// A piece of the document that can't be split across pages, like a line of
+/// text or a table row.
+#[derive(Debug, Clone, Default)]
+struct Chunk {
+    height: f32,
+    /// Blank space above the chunk, which is dropped at the top of a page.
+    space_before: f32,
+    texts: Vec<Placed>,
+    shapes: Vec<Shape>,
+}
+
+/// Chunks that should be on the same page if they fit on one, like a
+/// heading and the paragraph after it.
+#[derive(Debug, Clone, Default)]
+struct Group {
+    chunks: Vec<Chunk>,
+}
+
+impl Group {
+    fn single(chunk: Chunk) -> Self {
+        Self {
+            chunks: vec![chunk],
+        }
+    }
+
+    fn height(&self) -> f32 {
+        self.chunks
+            .iter()
+            .enumerate()
+            .map(|(ix, chunk)| chunk.height + if ix > 0 { chunk.space_before } else { 0. })
+            .sum()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Page {
+    /// The chunks on the page, with their distance from the top margin.
+    chunks: Vec<(f32, Chunk)>,
+}
+
+fn layout_pages(parsed: &ParsedMarkdown, options: &PdfExportOptions) -> Vec<Page> {
+    let (page_width, page_height) = options.page_size;
+    let width = (page_width - 2. * options.margin).max(72.);
+    let height = (page_height - 2. * options.margin).max(72.);
+
+    let mut groups = Vec::new();
+    let mut layout = Layout {
+        width,
+        code_block_borders: options.code_block_borders,
+        link_urls: options.link_urls,
+        groups: &mut groups,
+    };
+    layout.blocks(&parsed.children, 0.);
+    paginate(groups, height)
+}
+
+fn paginate(groups: Vec<Group>, page_height: f32) -> Vec<Page> {
+    let mut pages = vec![Page::default()];
+    let mut y = 0.;
+    for group in groups {
+        // Groups taller than a page are split between chunks like any other
+        // text.
+        let group_height = group.height();
+        let space_before = group.chunks.first().map_or(0., |chunk| chunk.space_before);
+        if group_height <= page_height && y > 0. && y + space_before + group_height > page_height {
+            pages.push(Page::default());
+            y = 0.;
+        }
+
+        for chunk in group.chunks {
+            if y > 0. && y + chunk.space_before + chunk.height > page_height {
+                pages.push(Page::default());
+                y = 0.;
+            }
+            if y > 0. {
+                y += chunk.space_before;
+            }
+            let height = chunk.height;
+            pages.last_mut().unwrap().chunks.push((y, chunk));
+            y += height;
+        }
+    }
+    pages
+}
+
+/// Turns blocks into groups of chunks that fit in `width`.
+struct Layout<'a> {
+    width: f32,
+    code_block_borders: bool,
+    link_urls: bool,
+    groups: &'a mut Vec<Group>,
+}
+
+impl Layout<'_> {
+    fn blocks(&mut self, blocks: &[ParsedMarkdownElement], indent: f32) {
+        let mut ix = 0;
+        while ix < blocks.len() {
+            match &blocks[ix] {
+                ParsedMarkdownElement::Heading(heading) => {
+                    let size = match heading.level {
+                        HeadingLevel::H1 => 22.,
+                        HeadingLevel::H2 => 17.,
+                        HeadingLevel::H3 => 14.,
+                        HeadingLevel::H4 => 12.,
+                        HeadingLevel::H5 | HeadingLevel::H6 => BODY_SIZE,
+                    };
+                    let mut lines = self.text_lines(&heading.contents, size, indent, true);
+                    if let Some(first) = lines.first_mut() {
+                        first.space_before = size;
+                    }
+
+                    // A heading stays with the paragraph after it.
+                    if let Some(ParsedMarkdownElement::Paragraph(text)) = blocks.get(ix + 1) {
+                        lines.extend(self.paragraph_lines(text, indent));
+                        ix += 1;
+                    }
+                    self.groups.push(Group { chunks: lines });
+                }
+                ParsedMarkdownElement::Paragraph(text) if !text.images.is_empty() => {
+                    let labels: Vec<_> = text.images.iter().map(image_label).collect();
+                    self.push_image_label(labels.join(" "), indent);
+                }
+                ParsedMarkdownElement::Paragraph(text) => {
+                    let lines = self.paragraph_lines(text, indent);
+                    self.groups.extend(lines.into_iter().map(Group::single));
+                }
+                ParsedMarkdownElement::ListItem(item) => {
+                    let item_indent = indent + LIST_INDENT * item.depth.saturating_sub(1) as f32;
+                    let marker = match item.item_type {
+                        ParsedMarkdownListItemType::Ordered(order) => format!("{}.", order),
+                        ParsedMarkdownListItemType::Task(true, _) => "[x]".to_string(),
+                        ParsedMarkdownListItemType::Task(false, _) => "[ ]".to_string(),
+                        ParsedMarkdownListItemType::Unordered => "•".to_string(),
+                    };
+
+                    let start = self.groups.len();
+                    self.blocks(&item.content, item_indent + LIST_INDENT);
+                    if start == self.groups.len() {
+                        self.groups.push(Group::single(Chunk {
+                            height: BODY_SIZE * LINE_HEIGHT,
+                            ..Default::default()
+                        }));
+                    }
+
+                    // Items follow each other closely, and the marker sits
+                    // on the first line of the item's text.
+                    let first = &mut self.groups[start].chunks[0];
+                    first.space_before = BODY_SIZE * 0.2;
+                    let baseline = first.texts.first().map_or(BODY_SIZE, |text| text.baseline);
+                    first.texts.push(Placed {
+                        x: item_indent + 2.,
+                        baseline,
+                        font: Font::Regular,
+                        size: BODY_SIZE,
+                        text: marker,
+                    });
+                }
+                ParsedMarkdownElement::Table(table) => self.table(table, indent),
+                ParsedMarkdownElement::BlockQuote(block_quote) => {
+                    let start = self.groups.len();
+                    self.blocks(&block_quote.children, indent + QUOTE_INDENT);
+                    for group in &mut self.groups[start..] {
+                        for chunk in &mut group.chunks {
+                            let height = chunk.height;
+                            chunk.shapes.push(Shape {
+                                x: indent,
+                                y: 0.,
+                                width: 3.,
+                                height,
+                                gray: 0.82,
+                            });
+                        }
+                    }
+                }
+                ParsedMarkdownElement::CodeBlock(code_block) => {
+                    // Each line is its own chunk, so long blocks carry on
+                    // onto the next page instead of being cut off.
+                    let line_height = CODE_SIZE * LINE_HEIGHT;
+                    let width = self.width - indent;
+                    let columns = ((width - 2. * CELL_PADDING) / Font::Mono.width("m", CODE_SIZE))
+                        .floor()
+                        .max(1.) as usize;
+                    let pieces: Vec<String> = code_block
+                        .contents
+                        .lines()
+                        .flat_map(|line| {
+                            let chars: Vec<char> = line.chars().collect();
+                            if chars.is_empty() {
+                                vec![String::new()]
+                            } else {
+                                chars
+                                    .chunks(columns)
+                                    .map(|piece| piece.iter().collect())
+                                    .collect()
+                            }
+                        })
+                        .collect();
+                    let last_ix = pieces.len().saturating_sub(1);
+                    for (ix, piece) in pieces.into_iter().enumerate() {
+                        let shapes = if self.code_block_borders {
+                            code_line_borders(indent, width, line_height, ix == 0, ix == last_ix)
+                        } else {
+                            vec![Shape {
+                                x: indent,
+                                y: 0.,
+                                width,
+                                height: line_height,
+                                gray: 0.96,
+                            }]
+                        };
+                        self.groups.push(Group::single(Chunk {
+                            height: line_height,
+                            space_before: if ix == 0 { BODY_SIZE * 0.6 } else { 0. },
+                            texts: vec![Placed {
+                                x: indent + CELL_PADDING,
+                                baseline: CODE_SIZE,
+                                font: Font::Mono,
+                                size: CODE_SIZE,
+                                text: piece,
+                            }],
+                            shapes,
+                        }));
+                    }
+                }
+                ParsedMarkdownElement::Image(image) => {
+                    self.push_image_label(image_label(image), indent)
+                }
+                ParsedMarkdownElement::HorizontalRule(_) => {
+                    self.groups.push(Group::single(Chunk {
+                        height: BODY_SIZE,
+                        space_before: BODY_SIZE * 0.6,
+                        texts: Vec::new(),
+                        shapes: vec![Shape {
+                            x: indent,
+                            y: BODY_SIZE / 2.,
+                            width: self.width - indent,
+                            height: 1.,
+                            gray: 0.8,
+                        }],
+                    }));
+                }
+            }
+            ix += 1;
+        }
+    }
+
+    /// Images can't be drawn yet, so they're shown by their `label`.
+    fn push_image_label(&mut self, label: String, indent: f32) {
+        self.groups.push(Group::single(Chunk {
+            height: BODY_SIZE * LINE_HEIGHT,
+            space_before: BODY_SIZE * 0.6,
+            texts: vec![Placed {
+                x: indent,
+                baseline: BODY_SIZE,
+                font: Font::Italic,
+                size: BODY_SIZE,
+                text: label,
+            }],
+            shapes: Vec::new(),
+        }));
+    }
+
+    fn paragraph_lines(&self, text: &ParsedMarkdownText, indent: f32) -> Vec<Chunk> {
+        let mut lines = self.text_lines(text, BODY_SIZE, indent, false);
+        if let Some(first) = lines.first_mut() {
+            first.space_before = BODY_SIZE * 0.6;
+        }
+        lines
+    }
+
+    /// Wraps `text` into lines in the space right of `indent`, one chunk per
+    /// line.
+    fn text_lines(
+        &self,
+        text: &ParsedMarkdownText,
+        size: f32,
+        indent: f32,
+        bold: bool,
+    ) -> Vec<Chunk> {
+        wrap_runs(
+            &styled_runs(text, bold, self.link_urls),
+            size,
+            self.width - indent,
+        )
+        .into_iter()
+        .map(|line| {
+            let mut x = indent;
+            let texts = line
+                .into_iter()
+                .map(|(font, text)| {
+                    let placed = Placed {
+                        x,
+                        baseline: size,
+                        font,
+                        size,
+                        text,
+                    };
+                    x += font.width(&placed.text, size);
+                    placed
+                })
+                .collect();
+            Chunk {
+                height: size * LINE_HEIGHT,
+                space_before: 0.,
+                texts,
+                shapes: Vec::new(),
+            }
+        })
+        .collect()
+    }
+
+    /// Lays out each row as one chunk with equal-width columns, so rows are
+    /// never split across pages.
+    fn table(&mut self, table: &ParsedMarkdownTable, indent: f32) {
+        let columns = table
+            .header
+            .children
+            .len()
+            .max(
+                table
+                    .body
+                    .iter()
+                    .map(|row| row.children.len())
+                    .max()
+                    .unwrap_or(0),
+            )
+            .max(1);
+        let width = self.width - indent;
+        let column_width = width / columns as f32;
+
+        let rows =
+            std::iter::once((&table.header, true)).chain(table.body.iter().map(|row| (row, false)));
+        for (ix, (row, is_header)) in rows.enumerate() {
+            let cells: Vec<Vec<Chunk>> = row
+                .children
+                .iter()
+                .map(|cell| {
+                    Layout {
+                        width: column_width - 2. * CELL_PADDING,
+                        code_block_borders: self.code_block_borders,
+                        link_urls: self.link_urls,
+                        groups: &mut Vec::new(),
+                    }
+                    .text_lines(cell, BODY_SIZE, 0., is_header)
+                })
+                .collect();
+            let line_count = cells.iter().map(Vec::len).max().unwrap_or(0).max(1);
+            let line_height = BODY_SIZE * LINE_HEIGHT;
+            let height = line_count as f32 * line_height + 2. * CELL_PADDING;
+
+            let mut chunk = Chunk {
+                height,
+                space_before: if ix == 0 { BODY_SIZE * 0.6 } else { 0. },
+                texts: Vec::new(),
+                shapes: Vec::new(),
+            };
+            if is_header {
+                chunk.shapes.push(Shape {
+                    x: indent,
+                    y: 0.,
+                    width,
+                    height,
+                    gray: 0.95,
+                });
+            }
+            // The row's top border, and its bottom one for the last row.
+            chunk.shapes.push(Shape {
+                x: indent,
+                y: 0.,
+                width,
+                height: 0.5,
+                gray: 0.75,
+            });
+            if ix == table.body.len() {
+                chunk.shapes.push(Shape {
+                    x: indent,
+                    y: height - 0.5,
+                    width,
+                    height: 0.5,
+                    gray: 0.75,
+                });
+            }
+
+            for (column, lines) in cells.into_iter().enumerate() {
+                let x = indent + column as f32 * column_width + CELL_PADDING;
+                for (line_ix, line) in lines.into_iter().enumerate() {
+                    let top = CELL_PADDING + line_ix as f32 * line_height;
+                    chunk.texts.extend(line.texts.into_iter().map(|mut text| {
+                        text.x += x;
+                        text.baseline += top;
+                        text
+                    }));
+                }
+            }
+            self.groups.push(Group::single(chunk));
+        }
+    }
+}
+
+fn image_label(image: &ParsedMarkdownImage) -> String {
+    if image.alt_text.is_empty() {
+        "[image]".to_string()
+    } else {
+        format!("[image: {}]", image.alt_text)
+    }
+}
+
+/// The border around a line of a code block: its sides, and its top or
+/// bottom if it's the first or the last line.
+fn code_line_borders(indent: f32, width: f32, height: f32, first: bool, last: bool) -> Vec<Shape> {
+    let border = |x, y, width, height| Shape {
+        x,
+        y,
+        width,
+        height,
+        gray: 0.8,
+    };
+    let mut shapes = vec![
+        border(indent, 0., 0.5, height),
+        border(indent + width - 0.5, 0., 0.5, height),
+    ];
+    if first {
+        shapes.push(border(indent, 0., width, 0.5));
+    }
+    if last {
+        shapes.push(border(indent, height - 0.5, width, 0.5));
+    }
+    shapes
+}
+
+/// Splits `text` into runs that share a font. With `link_urls`, links to web
+/// pages are followed by their URL, unless that's their text already.
+fn styled_runs(text: &ParsedMarkdownText, bold: bool, link_urls: bool) -> Vec<(Font, String)> {
+    let contents: &str = &text.contents;
+    let mut boundaries = vec![0, contents.len()];
+    for (range, _) in &text.highlights {
+        boundaries.extend([range.start, range.end]);
+    }
+    for range in &text.region_ranges {
+        boundaries.extend([range.start, range.end]);
+    }
+    boundaries.retain(|ix| *ix <= contents.len() && contents.is_char_boundary(*ix));
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    let link_ranges = if link_urls {
+        web_link_ranges(text)
+    } else {
+        Vec::new()
+    };
+
+    boundaries
+        .windows(2)
+        .flat_map(|piece| {
+            let (start, end) = (piece[0], piece[1]);
+            let code = text
+                .region_ranges
+                .iter()
+                .zip(&text.regions)
+                .any(|(range, region)| region.code && range.start <= start && end <= range.end);
+
+            let (mut is_bold, mut italic) = (bold, false);
+            for (range, highlight) in &text.highlights {
+                if let MarkdownHighlight::Style(style) = highlight {
+                    if range.start <= start && end <= range.end {
+                        is_bold |= style.weight > FontWeight::NORMAL;
+                        italic |= style.italic;
+                    }
+                }
+            }
+
+            let font = match (code, is_bold, italic) {
+                (true, _, _) => Font::Mono,
+                (false, true, true) => Font::BoldItalic,
+                (false, true, false) => Font::Bold,
+                (false, false, true) => Font::Italic,
+                (false, false, false) => Font::Regular,
+            };
+            let mut runs = vec![(font, contents[start..end].to_string())];
+
+            // A link can span several regions, so its URL goes after the
+            // last piece of text it covers.
+            let link_range = link_ranges
+                .iter()
+                .find(|(range, _)| range.start <= start && end <= range.end);
+            if let Some((range, url)) = link_range.filter(|(range, _)| range.end == end) {
+                if contents[range.clone()] != **url {
+                    runs.push((Font::Regular, format!(" ({})", url)));
+                }
+            }
+            runs
+        })
+        .collect()
+}
+
+/// The ranges of `text` that link to web pages, with their URLs. Adjacent
+/// regions with the same link are merged.
+fn web_link_ranges(text: &ParsedMarkdownText) -> Vec<(Range<usize>, String)> {
+    let mut ranges: Vec<(Range<usize>, String)> = Vec::new();
+    for (range, region) in text.region_ranges.iter().zip(&text.regions) {
+        let Some(link) = region
+            .link
+            .as_ref()
+            .filter(|link| link.kind() == LinkKind::Web)
+        else {
+            continue;
+        };
+        let url = link.url();
+        match ranges.last_mut() {
+            Some((last, last_url)) if last.end == range.start && *last_url == url => {
+                last.end = range.end;
+            }
+            _ => ranges.push((range.clone(), url)),
+        }
+    }
+    ranges
+}
+
+/// Breaks runs into lines no wider than `width`, between words where it can
+/// and within them where a word is wider than a line.
+fn wrap_runs(runs: &[(Font, String)], size: f32, width: f32) -> Vec<Vec<(Font, String)>> {
+    let mut lines: Vec<Vec<(Font, String)>> = vec![Vec::new()];
+    let mut line_width = 0.;
+
+    let push = |lines: &mut Vec<Vec<(Font, String)>>, font: Font, token: &str| {
+        let line = lines.last_mut().unwrap();
+        match line.last_mut() {
+            Some((last_font, text)) if *last_font == font => text.push_str(token),
+            _ => line.push((font, token.to_string())),
+        }
+    };
+
+    for (font, text) in runs {
+        for token in split_words(text) {
+            if token == "\n" {
+                lines.push(Vec::new());
+                line_width = 0.;
+                continue;
+            }
+
+            let token_width = font.width(token, size);
+            let is_space = token.chars().all(char::is_whitespace);
+            if line_width + token_width > width && line_width > 0. {
+                lines.push(Vec::new());
+                line_width = 0.;
+                if is_space {
+                    continue;
+                }
+            }
+
+            if token_width <= width || is_space {
+                push(&mut lines, *font, token);
+                line_width += token_width;
+                continue;
+            }
+
+            for c in token.chars() {
+                let char_width = font.width(c.encode_utf8(&mut [0; 4]), size);
+                if line_width + char_width > width && line_width > 0. {
+                    lines.push(Vec::new());
+                    line_width = 0.;
+                }
+                push(&mut lines, *font, c.encode_utf8(&mut [0; 4]));
+                line_width += char_width;
+            }
+        }
+    }
+
+    if lines.last().map_or(false, Vec::is_empty) && lines.len() > 1 {
+        lines.pop();
+    }
+    lines
+}
+
+/// Splits `text` into words, runs of spaces and line breaks.
+fn split_words(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let len = if first == '\n' {
+            1
+        } else {
+            let is_space = first.is_whitespace();
+            rest.find(|c: char| c == '\n' || c.is_whitespace() != is_space)
+                .unwrap_or(rest.len())
+        };
+        let (token, remaining) = rest.split_at(len);
+        rest = remaining;
+        Some(token)
+    })
+}
+
+fn write_pdf(pages: &[Page], title: &str, options: &PdfExportOptions) -> Vec<u8> {
+    let (page_width, page_height) = options.page_size;
+    let mut pdf = Pdf::new();
+
+    let mut next_id = 1;
+    let mut alloc = || {
+        let id = Ref::new(next_id);
+        next_id += 1;
+        id
+    };
+    let catalog_id = alloc();
+    let page_tree_id = alloc();
+    let info_id = alloc();
+    let font_ids: Vec<Ref> = Font::ALL.iter().map(|_| alloc()).collect();
+    let page_ids: Vec<(Ref, Ref)> = pages.iter().map(|_| (alloc(), alloc())).collect();
+
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().map(|(page_id, _)| *page_id))
+        .count(page_ids.len() as i32);
+    pdf.document_info(info_id).title(TextStr(title));
+    for (font, id) in Font::ALL.iter().zip(&font_ids) {
+        pdf.type1_font(*id)
+            .base_font(font.base_font())
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    let page_count = pages.len();
+    for (page_ix, (page, (page_id, content_id))) in pages.iter().zip(&page_ids).enumerate() {
+        let mut page_dict = pdf.page(*page_id);
+        page_dict
+            .media_box(Rect::new(0., 0., page_width, page_height))
+            .parent(page_tree_id)
+            .contents(*content_id);
+        let mut resources = page_dict.resources();
+        let mut fonts = resources.fonts();
+        for (font, id) in Font::ALL.iter().zip(&font_ids) {
+            fonts.pair(font.resource_name(), *id);
+        }
+        fonts.finish();
+        resources.finish();
+        page_dict.finish();
+
+        // PDF measures from the bottom of the page, the layout from the top
+        // margin.
+        let top = page_height - options.margin;
+        let mut content = Content::new();
+        for (y, chunk) in &page.chunks {
+            for shape in &chunk.shapes {
+                content.set_fill_gray(shape.gray);
+                content.rect(
+                    options.margin + shape.x,
+                    top - y - shape.y - shape.height,
+                    shape.width,
+                    shape.height,
+                );
+                content.fill_nonzero();
+            }
+        }
+        content.set_fill_gray(0.08);
+        for (y, chunk) in &page.chunks {
+            for text in &chunk.texts {
+                show_text(
+                    &mut content,
+                    text.font,
+                    text.size,
+                    options.margin + text.x,
+                    top - y - text.baseline,
+                    &text.text,
+                );
+            }
+        }
+
+        if options.header_and_footer {
+            let size = 8.;
+            content.set_fill_gray(0.45);
+            let title_width = Font::Regular.width(title, size);
+            show_text(
+                &mut content,
+                Font::Regular,
+                size,
+                (page_width - title_width) / 2.,
+                page_height - options.margin / 2.,
+                title,
+            );
+            let number = format!("{} / {}", page_ix + 1, page_count);
+            let number_width = Font::Regular.width(&number, size);
+            show_text(
+                &mut content,
+                Font::Regular,
+                size,
+                (page_width - number_width) / 2.,
+                options.margin / 2. - size,
+                &number,
+            );
+        }
+
+        pdf.stream(*content_id, &content.finish());
+    }
+
+    pdf.finish()
+}
+
+fn show_text(content: &mut Content, font: Font, size: f32, x: f32, y: f32, text: &str) {
+    content.begin_text();
+    content.set_font(font.resource_name(), size);
+    content.next_line(x, y);
+    content.show(Str(&win_ansi(text)));
+    content.end_text();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use pretty_assertions::assert_eq;
+
+    const EXAMPLE: &str = include_str!("../../assets/markdown/example.md");
+
+    fn small_pages() -> PdfExportOptions {
+        PdfExportOptions {
+            page_size: (300., 200.),
+            margin: 20.,
+            ..Default::default()
+        }
+    }
+
+    fn page_texts(page: &Page) -> Vec<String> {
+        page.chunks
+            .iter()
+            .flat_map(|(_, chunk)| chunk.texts.iter().map(|text| text.text.clone()))
+            .collect()
+    }
+
+    #[gpui::test]
+    async fn test_export_example_document() {
+        let parsed = parse_markdown(EXAMPLE, None, None).await;
+        let pdf = export_pdf(
+            &parsed,
+            &PdfExportOptions {
+                header_and_footer: true,
+                ..Default::default()
+            },
+        );
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(pdf.ends_with(b"%%EOF\n") || pdf.ends_with(b"%%EOF"));
+    }
+
+    #[gpui::test]
+    async fn test_long_code_blocks_continue_on_the_next_page() {
+        let code: Vec<String> = (0..40).map(|ix| format!("line {}", ix)).collect();
+        let text = format!("```\n{}\n```\n", code.join("\n"));
+        let parsed = parse_markdown(&text, None, None).await;
+
+        let pages = layout_pages(&parsed, &small_pages());
+        assert!(pages.len() > 1);
+        let lines: Vec<String> = pages.iter().flat_map(page_texts).collect();
+        assert_eq!(lines, code);
+    }
+
+    #[gpui::test]
+    async fn test_headings_stay_with_their_first_paragraph() {
+        let filler = "Filler text.\n\n".repeat(5);
+        let text = format!("{}# Heading\n\nFirst paragraph.\n", filler);
+        let parsed = parse_markdown(&text, None, None).await;
+
+        let pages = layout_pages(&parsed, &small_pages());
+        let page = pages
+            .iter()
+            .find(|page| page_texts(page).contains(&"Heading".to_string()))
+            .unwrap();
+        assert!(page_texts(page).contains(&"First paragraph.".to_string()));
+        assert!(pages.len() > 1);
+    }
+
+    #[gpui::test]
+    async fn test_table_rows_are_not_split() {
+        let rows: String = (0..20).map(|ix| format!("| {} | value |\n", ix)).collect();
+        let text = format!("| key | value |\n|---|---|\n{}", rows);
+        let parsed = parse_markdown(&text, None, None).await;
+
+        let options = small_pages();
+        let content_height = options.page_size.1 - 2. * options.margin;
+        let pages = layout_pages(&parsed, &options);
+        assert!(pages.len() > 1);
+        for page in &pages {
+            for (y, chunk) in &page.chunks {
+                assert!(y + chunk.height <= content_height);
+            }
+        }
+    }
+
+    #[gpui::test]
+    async fn test_printed_links_and_code_blocks() {
+        let text = "See [the **docs**](https://example.com/docs) or <https://example.com>.\n\n```\ncode\n```\n";
+        let parsed = parse_markdown(text, None, None).await;
+        let ParsedMarkdownElement::Paragraph(paragraph) = &parsed.children[0] else {
+            panic!("expected a paragraph");
+        };
+
+        let printed: String = styled_runs(paragraph, false, true)
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(
+            printed,
+            "See the docs (https://example.com/docs) or https://example.com."
+        );
+        let exported: String = styled_runs(paragraph, false, false)
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(exported, "See the docs or https://example.com.");
+
+        let pages = layout_pages(
+            &parsed,
+            &PdfExportOptions {
+                code_block_borders: true,
+                ..Default::default()
+            },
+        );
+        let (_, code_line) = pages[0].chunks.last().unwrap();
+        assert!(code_line.shapes.iter().all(|shape| shape.gray < 0.96));
+        assert_eq!(code_line.shapes.len(), 4);
+    }
+
+    #[test]
+    fn test_wrap_runs() {
+        let runs = vec![
+            (Font::Regular, "The quick brown ".to_string()),
+            (Font::Bold, "fox".to_string()),
+        ];
+        let width = Font::Regular.width("The quick brown", 10.) + 1.;
+        let lines = wrap_runs(&runs, 10., width);
+        assert_eq!(
+            lines,
+            vec![
+                vec![(Font::Regular, "The quick brown".to_string())],
+                vec![(Font::Bold, "fox".to_string())],
+            ]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use super::{
+    ClearRecent, Compare, CopyDiagnostics, NewFromClipboard, OpenFolder, OpenRecent,
+    OpenRecentFile, SaveAs,
+};
+use anyhow::Context as _;
+use gpui::{AppContext, Global, Menu, MenuItem};
+use std::path::{Path, PathBuf};
+
+/// How many files the list keeps.
+const MAX_RECENT_FILES: usize = 15;
+
+/// The files opened most recently, latest first.
+///
+/// [`crate::init`] starts an empty list that only lasts until the application
+/// quits. Applications that want it kept replace it with one from
+/// [`RecentFiles::load`].
+#[derive(Debug, Default)]
+pub struct RecentFiles {
+    paths: Vec<PathBuf>,
+    /// The file the list is saved to as it changes.
+    file: Option<PathBuf>,
+}
+
+impl Global for RecentFiles {}
+
+impl RecentFiles {
+    /// Reads the list saved to `file`, which it's saved back to from then
+    /// on. A missing or unreadable file starts an empty list.
+    pub fn load(file: PathBuf) -> Self {
+        let paths = match std::fs::read_to_string(&file) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                log::error!("failed to parse {}: {}", file.display(), error);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            paths,
+            file: Some(file),
+        }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Moves `path` to the front of the list, dropping the oldest file if
+    /// the list is full.
+    pub fn add(path: &Path, cx: &mut AppContext) {
+        cx.update_global::<Self, _>(|recent, cx| {
+            recent.paths.retain(|recent_path| recent_path != path);
+            recent.paths.insert(0, path.to_path_buf());
+            recent.paths.truncate(MAX_RECENT_FILES);
+            recent.save(cx);
+        });
+    }
+
+    pub fn clear(cx: &mut AppContext) {
+        cx.update_global::<Self, _>(|recent, cx| {
+            recent.paths.clear();
+            recent.save(cx);
+        });
+    }
+
+    /// Drops the files that no longer exist from the list, returning the
+    /// ones that are left.
+    pub fn prune(cx: &mut AppContext) -> Vec<PathBuf> {
+        let paths = cx.global::<Self>().paths.clone();
+        let (existing, missing): (Vec<_>, Vec<_>) =
+            paths.into_iter().partition(|path| path.exists());
+        // Only changes are saved, which also notifies the observers of the
+        // list, like the menus.
+        if !missing.is_empty() {
+            cx.update_global::<Self, _>(|recent, cx| {
+                recent.paths.retain(|path| !missing.contains(path));
+                recent.save(cx);
+            });
+        }
+        existing
+    }
+
+    fn save(&self, cx: &AppContext) {
+        let Some(file) = self.file.clone() else {
+            return;
+        };
+        let paths = self.paths.clone();
+        cx.background_executor()
+            .spawn(async move {
+                let content = serde_json::to_string_pretty(&paths)?;
+                if let Some(directory) = file.parent() {
+                    std::fs::create_dir_all(directory)?;
+                }
+                std::fs::write(&file, content)
+                    .with_context(|| format!("failed to write {}", file.display()))
+            })
+            .detach_and_log_err(cx);
+    }
+}
+
+/// The application's menus, with the recent files in "File → Open Recent".
+/// Build them again whenever [`RecentFiles`] changes, which can be observed
+/// as a global.
+pub fn app_menus(cx: &mut AppContext) -> Vec<Menu> {
+    let mut recent_items: Vec<MenuItem> = RecentFiles::prune(cx)
+        .into_iter()
+        .map(|path| MenuItem::action(recent_file_label(&path), OpenRecentFile { path }))
+        .collect();
+    if !recent_items.is_empty() {
+        recent_items.push(MenuItem::separator());
+    }
+    recent_items.push(MenuItem::action("More…", OpenRecent));
+    recent_items.push(MenuItem::action("Clear Recent", ClearRecent));
+
+    vec![
+        // macOS puts the first menu under the application's name.
+        Menu {
+            name: "Markdown Preview".into(),
+            items: Vec::new(),
+        },
+        Menu {
+            name: "File".into(),
+            items: vec![
+                MenuItem::action("New from Clipboard", NewFromClipboard),
+                MenuItem::action("Open Folder…", OpenFolder),
+                MenuItem::submenu(Menu {
+                    name: "Open Recent".into(),
+                    items: recent_items,
+                }),
+                MenuItem::separator(),
+                MenuItem::action("Compare…", Compare),
+                MenuItem::action("Save As…", SaveAs),
+            ],
+        },
+        Menu {
+            name: "Help".into(),
+            items: vec![MenuItem::action("Copy Diagnostics", CopyDiagnostics)],
+        },
+    ]
+}
+
+/// How a recent file is listed: its name, and the directory it's in.
+pub fn recent_file_label(path: &Path) -> String {
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => {
+            format!("{} — {}", name, directory.display())
+        }
+        _ => name,
+    }
+}
+
+/// Filters `paths` to those that fuzzily match `query`, best matches first.
+///
+/// A path matches if the characters of the query appear in it in order,
+/// ignoring case. Matches are ranked by how many of those characters are
+/// consecutive or start a word, and otherwise keep their order.
+pub fn filter_recent_files(paths: &[PathBuf], query: &str) -> Vec<PathBuf> {
+    let mut matches: Vec<(usize, &PathBuf)> = paths
+        .iter()
+        .filter_map(|path| Some((fuzzy_score(&path.to_string_lossy(), query)?, path)))
+        .collect();
+    matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+    matches.into_iter().map(|(_, path)| path.clone()).collect()
+}
+
+pub(crate) fn fuzzy_score(candidate: &str, query: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut candidate_chars = candidate.chars().flat_map(char::to_lowercase);
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+    for query_char in query.chars().flat_map(char::to_lowercase) {
+        if query_char.is_whitespace() {
+            continue;
+        }
+
+        loop {
+            let candidate_char = candidate_chars.next()?;
+            let at_word_start = previous.map_or(true, |previous| {
+                matches!(previous, '/' | '\\' | '_' | '-' | '.' | ' ')
+            });
+            previous = Some(candidate_char);
+            if candidate_char == query_char {
+                score += 1;
+                if previous_matched {
+                    score += 2;
+                }
+                if at_word_start {
+                    score += 2;
+                }
+                previous_matched = true;
+                break;
+            }
+            previous_matched = false;
+        }
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_filter_recent_files() {
+        let paths = vec![
+            PathBuf::from("/notes/meeting.md"),
+            PathBuf::from("/docs/readme.md"),
+            PathBuf::from("/docs/read-me-later.md"),
+        ];
+
+        assert_eq!(filter_recent_files(&paths, ""), paths);
+        assert_eq!(
+            filter_recent_files(&paths, "READ"),
+            vec![
+                PathBuf::from("/docs/readme.md"),
+                PathBuf::from("/docs/read-me-later.md"),
+            ]
+        );
+        // Matches at the starts of words outrank scattered ones.
+        assert_eq!(
+            filter_recent_files(&paths, "rm"),
+            vec![
+                PathBuf::from("/docs/read-me-later.md"),
+                PathBuf::from("/docs/readme.md"),
+            ]
+        );
+        assert!(filter_recent_files(&paths, "xyz").is_empty());
+    }
+
+    #[gpui::test]
+    fn test_recent_files(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-recent-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let paths: Vec<PathBuf> = (0..MAX_RECENT_FILES + 1)
+            .map(|ix| directory.join(format!("{}.md", ix)))
+            .collect();
+        for path in &paths {
+            std::fs::write(path, "").unwrap();
+        }
+
+        cx.update(|cx| {
+            cx.set_global(RecentFiles::default());
+            for path in &paths {
+                RecentFiles::add(path, cx);
+            }
+            RecentFiles::add(&paths[5], cx);
+
+            let recent = cx.global::<RecentFiles>().paths().to_vec();
+            assert_eq!(recent.len(), MAX_RECENT_FILES);
+            assert_eq!(recent[0], paths[5]);
+            assert_eq!(recent[1], paths[MAX_RECENT_FILES]);
+            assert!(!recent.contains(&paths[0]));
+
+            std::fs::remove_file(&paths[5]).unwrap();
+            let existing = RecentFiles::prune(cx);
+            assert_eq!(existing.len(), MAX_RECENT_FILES - 1);
+            assert_eq!(cx.global::<RecentFiles>().paths(), existing.as_slice());
+
+            RecentFiles::clear(cx);
+            assert!(cx.global::<RecentFiles>().paths().is_empty());
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
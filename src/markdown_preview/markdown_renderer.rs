@@ -1,43 +1,798 @@
 use super::markdown_elements::{
-    HeadingLevel, Link, ParsedMarkdown, ParsedMarkdownBlockQuote, ParsedMarkdownCodeBlock,
-    ParsedMarkdownElement, ParsedMarkdownHeading, ParsedMarkdownImage, ParsedMarkdownListItem,
-    ParsedMarkdownListItemType, ParsedMarkdownTable, ParsedMarkdownTableAlignment,
-    ParsedMarkdownTableRow, ParsedMarkdownText,
+    HeadingLevel, Link, LinkOrigin, MarkdownHighlight, ParsedMarkdown, ParsedMarkdownBlockQuote,
+    ParsedMarkdownCodeBlock, ParsedMarkdownElement, ParsedMarkdownHeading, ParsedMarkdownImage,
+    ParsedMarkdownListItem, ParsedMarkdownListItemType, ParsedMarkdownTable,
+    ParsedMarkdownTableAlignment, ParsedMarkdownTableRow, ParsedMarkdownText,
 };
+use super::markdown_fences::FenceHandlers;
+use super::markdown_images::{ImageLocation, ImagePolicy, ImageViewer, MarkdownImage};
+use super::markdown_parser::parse_markdown;
+use super::markdown_search::SearchHighlights;
+use super::markdown_spellcheck::Misspellings;
 // use editor::actions::Paste;
+use collections::{FxHashMap, FxHashSet};
 use gpui::{
-    div, img, px, rems, AbsoluteLength, AnyElement, DefiniteLength, Div, Element, ElementId,
-    HighlightStyle, Hsla, ImageSource, InteractiveText, IntoElement, Keystroke, Length, Modifiers,
-    ParentElement, SharedString, Styled, StyledText, TextStyle, WeakView, WindowContext,
+    canvas, div, fill, linear_color_stop, linear_gradient, percentage, point, px, rems, rgb, rgba,
+    AbsoluteLength, Animation, AnimationExt as _, AnyElement, Bounds, ClipboardItem,
+    DefiniteLength, Div, Element, ElementId, Global, HighlightStyle, Hsla, InteractiveText,
+    IntoElement, Keystroke, Length, Modifiers, ParentElement, Pixels, PromptLevel, SharedString,
+    Styled, StyledText, TextRun, TextStyle, Transformation, UnderlineStyle, WeakView,
+    WindowContext, WrappedLine,
 };
 use settings::Settings;
 use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    hash::{DefaultHasher, Hash, Hasher},
     ops::{Mul, Range},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    rc::Rc,
     sync::Arc,
+    time::Duration,
 };
 use theme::{ActiveTheme, SyntaxTheme, ThemeSettings};
 use ui::{
-    h_flex, relative, v_flex, Checkbox, FluentBuilder, InteractiveElement, LinkPreview, Selection,
-    StatefulInteractiveElement, Tooltip,
+    h_flex, relative, v_flex, Button, ButtonCommon, ButtonSize, ButtonStyle, Checkbox, Clickable,
+    Color, FluentBuilder, Icon, IconButton, IconName, IconSize, InteractiveElement, Label,
+    LabelCommon, LabelSize, LinkPreview, Selection, StatefulInteractiveElement, Tooltip,
+    VisibleOnHover,
 };
+use util::ResultExt as _;
 // use workspace::Workspace;
 
 type CheckboxClickedCallback = Arc<Box<dyn Fn(bool, Range<usize>, &mut WindowContext)>>;
+type LinkClickedCallback = Arc<Box<dyn Fn(&Link, LinkOrigin, Range<usize>, &mut WindowContext)>>;
+type ImageClickedCallback = Arc<Box<dyn Fn(&Link, Range<usize>, &mut WindowContext)>>;
+type ImageExpandedCallback = Arc<Box<dyn Fn(&Link, &mut WindowContext)>>;
+type SectionCallback = Arc<Box<dyn Fn(usize, &mut WindowContext)>>;
+type RunCodeCallback =
+    Arc<Box<dyn Fn(Range<usize>, SharedString, SharedString, &mut WindowContext)>>;
+pub type RenderOverrideCallback =
+    Arc<Box<dyn Fn(&ParsedMarkdownElement, &mut RenderContext) -> Option<AnyElement>>>;
+
+/// How tall images are laid out at most, unless the [`MarkdownStyle`] says
+/// otherwise.
+const DEFAULT_MAX_IMAGE_HEIGHT: Pixels = px(180.);
 
 pub struct RenderContext {
     // workspace: Option<WeakView<Workspace>>,
     next_id: usize,
+    /// The font of code blocks and inline code.
     buffer_font_family: SharedString,
     buffer_text_style: TextStyle,
+    /// The size of the text of code blocks before scaling, if it isn't the
+    /// document's.
+    code_font_size: Option<Pixels>,
     text_style: TextStyle,
+    /// The size of body text before scaling.
+    text_size: Pixels,
     border_color: Hsla,
     text_color: Hsla,
     text_muted_color: Hsla,
+    error_color: Hsla,
     code_block_background_color: Hsla,
     code_span_background_color: Hsla,
+    highlight_background_color: Hsla,
+    code_line_emphasis_color: Hsla,
+    search_match_color: Hsla,
+    active_search_match_color: Hsla,
     syntax_theme: Arc<SyntaxTheme>,
     indent: usize,
     checkbox_clicked_callback: Option<CheckboxClickedCallback>,
+    link_clicked_callback: Option<LinkClickedCallback>,
+    image_clicked_callback: Option<ImageClickedCallback>,
+    image_expanded_callback: Option<ImageExpandedCallback>,
+    copy_section_callback: Option<SectionCallback>,
+    run_code_callback: Option<RunCodeCallback>,
+    /// The languages of the code blocks that get a run button, lowercased.
+    runnable_languages: Rc<FxHashSet<SharedString>>,
+    render_override: Option<RenderOverrideCallback>,
+    fence_handlers: Rc<FenceHandlers>,
+    block_layouts: Option<Rc<RefCell<BlockLayouts>>>,
+    text_layouts: Option<Rc<RefCell<Vec<LaidOutText>>>>,
+    render_cache: Option<Rc<RefCell<RenderCache>>>,
+    /// The file the blocks being rendered were included from, if they were.
+    source_file: Option<PathBuf>,
+    search_highlights: Option<Rc<SearchHighlights>>,
+    misspellings: Option<Rc<Misspellings>>,
+    hovered_element: Option<Rc<RefCell<Option<HoveredElement>>>>,
+    style_key: u64,
+    presentation_state: Rc<RefCell<PresentationState>>,
+    max_code_block_lines: Option<usize>,
+    max_image_height: Option<Pixels>,
+    text_scale: f32,
+    animate_images: bool,
+    reduce_motion: bool,
+    adapt_images_to_theme: bool,
+    underline_autolinks_on_hover: bool,
+    image_policy: ImagePolicy,
+    link_policy: LinkPolicy,
+    broken_links: Option<Rc<FxHashMap<Link, SharedString>>>,
+    image_viewer: Option<ImageViewer>,
+    image_viewport: Option<Rc<Cell<Bounds<Pixels>>>>,
+    eager_images: bool,
+    limits: RenderLimits,
+    block_index: usize,
+    render_mode: RenderMode,
+}
+
+/// A text of the document as it was laid out, recorded with
+/// [`RenderContext::with_text_layouts`].
+#[derive(Debug, Clone)]
+pub struct LaidOutText {
+    /// The source range of the text.
+    pub source_range: Range<usize>,
+    pub bounds: Bounds<Pixels>,
+    pub font_size: Pixels,
+    /// The text of each line it wraps to, without the spaces at their end.
+    pub lines: Vec<String>,
+    /// The styles of the text, each for the next `len` bytes of it.
+    pub runs: Vec<TextRun>,
+}
+
+/// The text of each line `line` wraps to.
+fn wrapped_lines(line: &WrappedLine) -> Vec<String> {
+    let text = line.text.as_ref();
+    let mut starts = vec![0];
+    starts.extend(line.wrap_boundaries.iter().map(|boundary| {
+        line.unwrapped_layout.runs[boundary.run_ix].glyphs[boundary.glyph_ix].index
+    }));
+    starts.push(text.len());
+    starts
+        .windows(2)
+        .map(|range| {
+            text[range[0]..range[1]]
+                .replace(LimitedText::WORD_BREAK, "")
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// How far the background of `==highlighted==` text reaches past its ends.
+const MARK_PADDING: Pixels = px(2.);
+
+/// The bounds, relative to the text's, of each of `ranges` on each line
+/// `lines` wrap to, as tall as the line and padded at the ends.
+fn marked_bounds(
+    lines: &[WrappedLine],
+    ranges: &[Range<usize>],
+    line_height: Pixels,
+) -> Vec<Bounds<Pixels>> {
+    let mut marked = Vec::new();
+    let mut line_start = 0;
+    let mut top = Pixels::ZERO;
+    for line in lines {
+        let layout = &line.unwrapped_layout;
+        let mut row_starts = vec![0];
+        row_starts.extend(
+            line.wrap_boundaries
+                .iter()
+                .map(|boundary| layout.runs[boundary.run_ix].glyphs[boundary.glyph_ix].index),
+        );
+        row_starts.push(line.text.len());
+        for row in row_starts.windows(2) {
+            let row_left = layout.x_for_index(row[0]);
+            for range in ranges {
+                let start = range.start.saturating_sub(line_start).max(row[0]);
+                let end = range.end.saturating_sub(line_start).min(row[1]);
+                if start >= end {
+                    continue;
+                }
+                marked.push(Bounds::from_corners(
+                    point(layout.x_for_index(start) - row_left - MARK_PADDING, top),
+                    point(
+                        layout.x_for_index(end) - row_left + MARK_PADDING,
+                        top + line_height,
+                    ),
+                ));
+            }
+            top += line_height;
+        }
+        line_start += line.text.len() + 1;
+    }
+    marked
+}
+
+/// Whether blocks are laid out as a document of their own or inside other
+/// UI.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    #[default]
+    Document,
+    /// Inside other UI, like a chat message or a tooltip: with less space
+    /// around blocks, headings without their margins or their fold and copy
+    /// buttons, and a paragraph on its own as nothing but its text, so that
+    /// it lines up with the text next to it.
+    Inline,
+}
+
+/// An element under the mouse that a context menu can offer actions for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HoveredElement {
+    Link {
+        link: Link,
+        origin: LinkOrigin,
+        /// The source range of the text the link is in.
+        source_range: Range<usize>,
+    },
+    /// A code block, with its contents.
+    CodeBlock(SharedString),
+    /// An image, with where it's loaded from.
+    Image(Link),
+    /// A top-level heading, by its block index.
+    Heading(usize),
+    /// A word that's underlined as misspelled.
+    Misspelling {
+        word: SharedString,
+        /// The source range of the text the word is in.
+        source_range: Range<usize>,
+        /// How many times the word is in the text before this one.
+        occurrence: usize,
+    },
+}
+
+/// How documents are laid out, on top of the colors and fonts of the theme.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownStyle {
+    /// Code blocks with more lines than this are rendered collapsed, with a
+    /// control to show the rest.
+    pub max_code_block_lines: Option<usize>,
+    /// Images taller than this are scaled down to it, centered, with a
+    /// button to show them at their full size. Without it, images are laid
+    /// out at their own size.
+    pub max_image_height: Option<Pixels>,
+    /// Whether animated images play, or only show their first frame.
+    pub animate_images: bool,
+    /// Whether links picked out of bare URLs in the text are only underlined
+    /// while the mouse is over them, setting them apart from written ones.
+    pub underline_autolinks_on_hover: bool,
+    pub limits: RenderLimits,
+    /// The colors of the document, instead of the theme's. Code is still
+    /// highlighted with the theme's syntax colors.
+    pub colors: Option<MarkdownColors>,
+    /// The font of code blocks and inline code, instead of the buffer font.
+    /// Other text is in the UI font.
+    pub code_font_family: Option<SharedString>,
+    /// The size of the text of code blocks, instead of the document's.
+    /// Inline code is the size of the text around it.
+    pub code_font_size: Option<Pixels>,
+    pub code_font_features: Option<FontFeatures>,
+}
+
+impl Default for MarkdownStyle {
+    fn default() -> Self {
+        Self {
+            max_code_block_lines: Some(20),
+            max_image_height: Some(DEFAULT_MAX_IMAGE_HEIGHT),
+            animate_images: true,
+            underline_autolinks_on_hover: false,
+            limits: RenderLimits::default(),
+            colors: None,
+            code_font_family: None,
+            code_font_size: None,
+            code_font_features: None,
+        }
+    }
+}
+
+impl MarkdownStyle {
+    /// Dark text on white, whatever the theme is.
+    pub fn light() -> Self {
+        Self {
+            colors: Some(MarkdownColors::light()),
+            ..Self::default()
+        }
+    }
+
+    /// Light text on a dark background, whatever the theme is.
+    pub fn dark() -> Self {
+        Self {
+            colors: Some(MarkdownColors::dark()),
+            ..Self::default()
+        }
+    }
+}
+
+/// The colors a document is rendered with, for applications that embed the
+/// renderer without a theme of their own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarkdownColors {
+    pub background: Hsla,
+    pub text: Hsla,
+    /// Used for block quotes, captions and other secondary text.
+    pub text_muted: Hsla,
+    pub border: Hsla,
+    pub code_block_background: Hsla,
+    pub code_span_background: Hsla,
+    /// The background of `==highlighted==` text.
+    pub highlight_background: Hsla,
+    /// The background of the emphasized lines of code blocks.
+    pub code_line_emphasis: Hsla,
+    pub search_match: Hsla,
+    pub active_search_match: Hsla,
+}
+
+impl MarkdownColors {
+    /// The colors of the HTML export's stylesheet.
+    pub fn light() -> Self {
+        Self {
+            background: rgb(0xffffff).into(),
+            text: rgb(0x1f2328).into(),
+            text_muted: rgb(0x59636e).into(),
+            border: rgb(0xd1d9e0).into(),
+            code_block_background: rgb(0xf6f8fa).into(),
+            code_span_background: rgba(0x818b981f).into(),
+            highlight_background: rgb(0xfff8c5).into(),
+            code_line_emphasis: rgba(0xd4a72c33).into(),
+            search_match: rgba(0xfae17d99).into(),
+            active_search_match: rgba(0xbf870066).into(),
+        }
+    }
+
+    pub fn dark() -> Self {
+        Self {
+            background: rgb(0x0d1117).into(),
+            text: rgb(0xf0f6fc).into(),
+            text_muted: rgb(0x9198a1).into(),
+            border: rgb(0x3d444d).into(),
+            code_block_background: rgb(0x151b23).into(),
+            code_span_background: rgba(0x656c7633).into(),
+            highlight_background: rgba(0xbb800926).into(),
+            code_line_emphasis: rgba(0xbb800926).into(),
+            search_match: rgba(0xbb800966).into(),
+            active_search_match: rgba(0xf2cc6080).into(),
+        }
+    }
+}
+
+/// Limits on what a block renders, so that pathological documents, like a
+/// paragraph of megabytes without a space or a table with hundreds of
+/// columns, can't make layout hang or the element tree too deep. Blocks past
+/// a limit are shown cut down to fit it, and the limit is logged once for
+/// each of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderLimits {
+    /// How many levels of block quotes and lists are indented. Blocks nested
+    /// deeper are shown at this depth.
+    pub max_depth: usize,
+    /// The columns of tables past this many are left out, with a note saying
+    /// how many there are.
+    pub max_table_columns: usize,
+    /// Words longer than this many characters are broken up so they wrap.
+    pub max_word_len: usize,
+    /// Text with more styled runs than this, like a heading made of
+    /// thousands of code spans, is cut off after them.
+    pub max_inline_runs: usize,
+    /// Text longer than this many bytes, like a paragraph without a space
+    /// to split it at, is cut off with a control that shows this many more
+    /// bytes of it each time it's clicked.
+    pub max_text_len: usize,
+}
+
+impl Default for RenderLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 12,
+            max_table_columns: 64,
+            max_word_len: 256,
+            max_inline_runs: 2000,
+            max_text_len: 64 * 1024,
+        }
+    }
+}
+
+/// One of the [`RenderLimits`], which a block went past.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenderLimit {
+    Depth,
+    TableColumns,
+    WordLength,
+    InlineRuns,
+    TextLength,
+}
+
+impl RenderLimit {
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Depth => "nested too deeply",
+            Self::TableColumns => "a table with too many columns",
+            Self::WordLength => "text with words too long to wrap",
+            Self::InlineRuns => "text with too many styled runs",
+            Self::TextLength => "text too long to lay out at once",
+        }
+    }
+}
+
+/// Presentation state that has to survive re-renders and re-parses of the
+/// document, such as which collapsed code blocks were expanded. Views own one
+/// of these and share it with every [`RenderContext`] they create.
+#[derive(Default)]
+pub struct PresentationState {
+    /// The source ranges of the code blocks that are shown in full.
+    pub expanded_code_blocks: FxHashSet<Range<usize>>,
+    /// How each table is sorted, by its source range, if the user clicked
+    /// one of its headers. This is only valid for the document it was made
+    /// for, so views clear it whenever they re-parse all of it.
+    pub table_sorts: FxHashMap<Range<usize>, TableSort>,
+    /// The images the user chose to load even though the [`ImagePolicy`]
+    /// blocks them.
+    pub allowed_images: FxHashSet<ImageLocation>,
+    /// The animated images the user chose to play even though motion is
+    /// reduced.
+    pub playing_animations: FxHashSet<ImageLocation>,
+    /// The slugs of the headings whose sections are folded. Slugs stay the
+    /// same as long as the heading text does, so this survives re-parses.
+    pub folded_sections: FxHashSet<String>,
+    /// The [`RenderLimits`] blocks went past, by the source range of the
+    /// part of the block that did, so each is only logged once.
+    pub exceeded_limits: FxHashSet<(Range<usize>, RenderLimit)>,
+    /// The autolink under the mouse, by the source range of its text and its
+    /// index among the text's links, for
+    /// [`MarkdownStyle::underline_autolinks_on_hover`].
+    pub hovered_autolink: Option<(Range<usize>, usize)>,
+    /// How many times "Show more" was clicked under each text that's cut
+    /// off at [`RenderLimits::max_text_len`], by the text's source range.
+    pub text_shown_more: FxHashMap<Range<usize>, usize>,
+    /// The last run of each code block that was run, by the block's source
+    /// range. Editing a block forgets its run.
+    pub code_runs: FxHashMap<Range<usize>, CodeRun>,
+    /// The code blocks whose output is collapsed, by their source range.
+    pub collapsed_code_outputs: FxHashSet<Range<usize>>,
+}
+
+/// A run of a code block by the view's code runner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeRun {
+    /// Tells the run apart from the ones before it, so that a run that was
+    /// replaced can't report back.
+    pub id: usize,
+    /// What the code printed, or why it failed, once it's done.
+    pub output: Option<Result<SharedString, SharedString>>,
+}
+
+impl PresentationState {
+    /// Keeps the state of the blocks around a re-parsed part of the
+    /// document, where the `replaced` part of the source changed length by
+    /// `delta`.
+    pub fn splice(&mut self, replaced: Range<usize>, delta: isize) {
+        self.table_sorts = self
+            .table_sorts
+            .drain()
+            .filter_map(|(range, sort)| Some((splice_range(range, &replaced, delta)?, sort)))
+            .collect();
+        self.expanded_code_blocks = self
+            .expanded_code_blocks
+            .drain()
+            .filter_map(|range| splice_range(range, &replaced, delta))
+            .collect();
+        self.text_shown_more = self
+            .text_shown_more
+            .drain()
+            .filter_map(|(range, count)| Some((splice_range(range, &replaced, delta)?, count)))
+            .collect();
+        self.code_runs = self
+            .code_runs
+            .drain()
+            .filter_map(|(range, run)| Some((splice_range(range, &replaced, delta)?, run)))
+            .collect();
+        self.collapsed_code_outputs = self
+            .collapsed_code_outputs
+            .drain()
+            .filter_map(|range| splice_range(range, &replaced, delta))
+            .collect();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableSort {
+    pub column: usize,
+    pub ascending: bool,
+}
+
+/// The measured bounds of the top-level blocks of a document, keyed by block
+/// index and recorded as the blocks are laid out.
+///
+/// Views render documents lazily, so only blocks that have been near the
+/// viewport are measured; the offsets of the others are estimated from the
+/// average height of the measured ones.
+#[derive(Default)]
+pub struct BlockLayouts {
+    bounds: FxHashMap<usize, Bounds<Pixels>>,
+    /// The sum of the heights in `bounds`, kept as they're recorded so that
+    /// estimating a block's height doesn't add up all the others.
+    measured_height: Pixels,
+    /// Changes whenever a block's height does, or blocks are forgotten, so
+    /// that what's worked out from the heights is only worked out again when
+    /// it can be different.
+    version: usize,
+}
+
+/// Where a document's blocks are, from the top of the document, as they
+/// were last laid out. Hosts that draw beside the document, like a minimap,
+/// lay themselves out by it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutInfo {
+    pub total_height: Pixels,
+    /// Whether some blocks haven't been laid out yet, so that the total
+    /// height is estimated.
+    pub estimated: bool,
+    /// The offset of each block, in order.
+    pub block_offsets: Vec<BlockOffset>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockOffset {
+    pub block_ix: usize,
+    pub offset: Pixels,
+    /// Whether a block before this one hasn't been laid out yet, so that the
+    /// offset is estimated from the heights of the blocks that have.
+    pub estimated: bool,
+}
+
+impl LayoutInfo {
+    /// Whether `self` differs from `other` by more than rounding: by
+    /// another block, another estimate, or an offset that moved by a pixel
+    /// or more.
+    pub fn differs_from(&self, other: &LayoutInfo) -> bool {
+        let moved = |a: Pixels, b: Pixels| (a - b).abs() >= px(1.);
+        self.estimated != other.estimated
+            || moved(self.total_height, other.total_height)
+            || self.block_offsets.len() != other.block_offsets.len()
+            || self
+                .block_offsets
+                .iter()
+                .zip(&other.block_offsets)
+                .any(|(a, b)| a.estimated != b.estimated || moved(a.offset, b.offset))
+    }
+}
+
+impl BlockLayouts {
+    /// Used for blocks before any block has been measured.
+    const DEFAULT_BLOCK_HEIGHT: Pixels = px(24.);
+
+    pub fn record(&mut self, block_ix: usize, bounds: Bounds<Pixels>) {
+        let previous = self.bounds.insert(block_ix, bounds);
+        let previous_height = previous.map(|previous| previous.size.height);
+        if previous_height != Some(bounds.size.height) {
+            self.measured_height += bounds.size.height - previous_height.unwrap_or_default();
+            self.version += 1;
+        }
+    }
+
+    /// A number that changes whenever the heights of the blocks do.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Where the block at `block_ix` was laid out in the window the last
+    /// time it was on screen.
+    pub fn bounds(&self, block_ix: usize) -> Option<Bounds<Pixels>> {
+        self.bounds.get(&block_ix).copied()
+    }
+
+    pub fn height(&self, block_ix: usize) -> Option<Pixels> {
+        self.bounds(block_ix).map(|bounds| bounds.size.height)
+    }
+
+    pub fn estimated_height(&self, block_ix: usize) -> Pixels {
+        self.height(block_ix)
+            .unwrap_or_else(|| self.average_height())
+    }
+
+    /// The height of the blocks that haven't been measured.
+    fn average_height(&self) -> Pixels {
+        if self.bounds.is_empty() {
+            Self::DEFAULT_BLOCK_HEIGHT
+        } else {
+            self.measured_height / self.bounds.len() as f32
+        }
+    }
+
+    /// Where the first `block_count` blocks are, measured or estimated.
+    pub fn layout_info(&self, block_count: usize) -> LayoutInfo {
+        let average_height = self.average_height();
+        let mut offset = Pixels::ZERO;
+        let mut estimated = false;
+        let block_offsets = (0..block_count)
+            .map(|block_ix| {
+                let block_offset = BlockOffset {
+                    block_ix,
+                    offset,
+                    estimated,
+                };
+                match self.height(block_ix) {
+                    Some(height) => offset += height,
+                    None => {
+                        offset += average_height;
+                        estimated = true;
+                    }
+                }
+                block_offset
+            })
+            .collect();
+        LayoutInfo {
+            total_height: offset,
+            estimated,
+            block_offsets,
+        }
+    }
+
+    /// The estimated distance from the top of the document to the top of the
+    /// block at `block_ix`.
+    pub fn estimated_offset(&self, block_ix: usize) -> Pixels {
+        let average_height = self.average_height();
+        (0..block_ix).fold(Pixels::ZERO, |offset, ix| {
+            offset + self.height(ix).unwrap_or(average_height)
+        })
+    }
+
+    /// Finds the block at the estimated `offset` from the top of the
+    /// document, along with how far into the block `offset` is.
+    pub fn block_at_offset(&self, offset: Pixels, block_count: usize) -> (usize, Pixels) {
+        let average_height = self.average_height();
+        let mut remaining = offset.max(Pixels::ZERO);
+        for ix in 0..block_count {
+            let height = self.height(ix).unwrap_or(average_height);
+            if remaining < height {
+                return (ix, remaining);
+            }
+            remaining -= height;
+        }
+        (block_count.saturating_sub(1), Pixels::ZERO)
+    }
+
+    pub fn clear(&mut self) {
+        self.bounds.clear();
+        self.measured_height = Pixels::ZERO;
+        self.version += 1;
+    }
+
+    /// Forgets the bounds of the blocks in `blocks`, which were replaced by
+    /// `new_block_count` others, and renumbers the blocks after them.
+    pub fn splice(&mut self, blocks: Range<usize>, new_block_count: usize) {
+        self.bounds = self
+            .bounds
+            .drain()
+            .filter_map(|(ix, bounds)| {
+                let ix = splice_block_ix(ix, &blocks, new_block_count)?;
+                Some((ix, bounds))
+            })
+            .collect();
+        self.measured_height = self
+            .bounds
+            .values()
+            .fold(Pixels::ZERO, |total, bounds| total + bounds.size.height);
+        self.version += 1;
+    }
+
+    /// Forgets the bounds of the block at `block_ix`, so that it's known once
+    /// it has been laid out again.
+    pub fn forget(&mut self, block_ix: usize) {
+        if let Some(bounds) = self.bounds.remove(&block_ix) {
+            self.measured_height -= bounds.size.height;
+        }
+        self.version += 1;
+    }
+}
+
+/// Inline text styling resolved against the current theme.
+///
+/// Elements themselves can't outlive a frame, but resolving the highlights and
+/// links of every text run is repeated work for blocks that didn't change, so
+/// views keep one of these across frames and clear it whenever the document is
+/// replaced. It's also cleared automatically when the theme, text scale or
+/// viewport width change.
+///
+/// Only the resolved styles are kept. Elements, and the text they shape, are
+/// still built again every frame.
+#[derive(Default)]
+pub struct RenderCache {
+    style_key: u64,
+    texts: FxHashMap<TextKey, Arc<ResolvedText>>,
+}
+
+/// A text in a [`RenderCache`]: the file it's from, where it is in that
+/// file, and a hash of everything it's resolved from, so that text that was
+/// parsed differently is never mistaken for what was there before.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TextKey {
+    /// The file the text was included from, or `None` for the document's own.
+    source_file: Option<PathBuf>,
+    source_range: Range<usize>,
+    content_hash: u64,
+}
+
+impl TextKey {
+    fn new(parsed: &ParsedMarkdownText, source_file: Option<PathBuf>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        parsed.contents.hash(&mut hasher);
+        for (range, highlight) in &parsed.highlights {
+            range.hash(&mut hasher);
+            match highlight {
+                MarkdownHighlight::Style(style) => {
+                    style.italic.hash(&mut hasher);
+                    style.underline.hash(&mut hasher);
+                    style.strikethrough.hash(&mut hasher);
+                    style.weight.0.to_bits().hash(&mut hasher);
+                    style.highlight.hash(&mut hasher);
+                }
+                MarkdownHighlight::Code(id) => id.hash(&mut hasher),
+            }
+        }
+        for (range, region) in parsed.region_ranges.iter().zip(&parsed.regions) {
+            range.hash(&mut hasher);
+            region.code.hash(&mut hasher);
+            region.link.hash(&mut hasher);
+            (region.origin as u8).hash(&mut hasher);
+        }
+        Self {
+            source_file,
+            source_range: parsed.source_range.clone(),
+            content_hash: hasher.finish(),
+        }
+    }
+}
+
+impl RenderCache {
+    pub fn invalidate(&mut self) {
+        self.texts.clear();
+    }
+
+    /// Forgets the text in the `replaced` part of the source, which was
+    /// re-parsed, and moves the text after it by how much longer it became.
+    /// Text included from other files isn't in the source, so it stays.
+    pub fn splice(&mut self, replaced: Range<usize>, delta: isize) {
+        self.texts = self
+            .texts
+            .drain()
+            .filter_map(|(mut key, text)| {
+                if key.source_file.is_none() {
+                    key.source_range = splice_range(key.source_range, &replaced, delta)?;
+                }
+                Some((key, text))
+            })
+            .collect();
+    }
+}
+
+/// Where the block at `ix` is once the blocks in `blocks` are replaced by
+/// `new_block_count` others, or `None` if it was one of them.
+fn splice_block_ix(ix: usize, blocks: &Range<usize>, new_block_count: usize) -> Option<usize> {
+    if ix < blocks.start {
+        Some(ix)
+    } else if ix >= blocks.end {
+        Some(ix + new_block_count - blocks.len())
+    } else {
+        None
+    }
+}
+
+/// Where `range` is in the source once the `replaced` part of it changes
+/// length by `delta`, or `None` if it overlapped that part.
+fn splice_range(
+    range: Range<usize>,
+    replaced: &Range<usize>,
+    delta: isize,
+) -> Option<Range<usize>> {
+    if range.end <= replaced.start {
+        Some(range)
+    } else if range.start >= replaced.end {
+        let shift = |offset: usize| (offset as isize + delta) as usize;
+        Some(shift(range.start)..shift(range.end))
+    } else {
+        None
+    }
+}
+
+struct ResolvedText {
+    highlights: Vec<(Range<usize>, HighlightStyle)>,
+    /// The `==highlighted==` parts of the text, which are drawn on rounded
+    /// backgrounds behind it rather than with the text's own.
+    marked_ranges: Vec<Range<usize>>,
+    links: Vec<(Link, LinkOrigin)>,
+    link_ranges: Vec<Range<usize>>,
 }
 
 impl RenderContext {
@@ -46,8 +801,21 @@ impl RenderContext {
 
         let settings = ThemeSettings::get_global(cx);
         let buffer_font_family = settings.buffer_font.family.clone();
-        let mut buffer_text_style = cx.text_style();
+        let mut text_style = cx.text_style();
+        text_style.font_fallbacks = with_fallbacks(text_style.font_fallbacks, TEXT_FONT_FALLBACKS);
+        let mut buffer_text_style = text_style.clone();
         buffer_text_style.font_family = buffer_font_family.clone();
+        buffer_text_style.font_features = settings.buffer_font.features.clone();
+        buffer_text_style.font_fallbacks =
+            with_fallbacks(settings.buffer_font.fallbacks.clone(), CODE_FONT_FALLBACKS);
+
+        let mut hasher = DefaultHasher::new();
+        theme.name.hash(&mut hasher);
+        buffer_font_family.hash(&mut hasher);
+        f32::from(cx.rem_size()).to_bits().hash(&mut hasher);
+        let viewport_width = f32::from(cx.viewport_size().width);
+        viewport_width.to_bits().hash(&mut hasher);
+        let style_key = hasher.finish();
 
         RenderContext {
             // workspace,
@@ -55,15 +823,280 @@ impl RenderContext {
             indent: 0,
             buffer_font_family,
             buffer_text_style,
-            text_style: cx.text_style(),
+            code_font_size: None,
+            text_style,
+            text_size: cx.text_style().font_size.to_pixels(cx.rem_size()),
             syntax_theme: theme.syntax().clone(),
             border_color: theme.colors().border,
             text_color: theme.colors().text,
             text_muted_color: theme.colors().text_muted,
+            error_color: theme.status().error,
             code_block_background_color: theme.colors().surface_background,
             code_span_background_color: theme.colors().editor_document_highlight_read_background,
+            highlight_background_color: theme.colors().search_match_background,
+            code_line_emphasis_color: theme.colors().editor_highlighted_line_background,
+            search_match_color: theme.colors().search_match_background,
+            active_search_match_color: theme.players().local().selection,
             checkbox_clicked_callback: None,
+            link_clicked_callback: None,
+            image_clicked_callback: None,
+            image_expanded_callback: None,
+            copy_section_callback: None,
+            run_code_callback: None,
+            runnable_languages: Rc::default(),
+            render_override: None,
+            fence_handlers: Rc::default(),
+            block_layouts: None,
+            text_layouts: None,
+            render_cache: None,
+            source_file: None,
+            search_highlights: None,
+            misspellings: None,
+            hovered_element: None,
+            style_key,
+            presentation_state: Rc::default(),
+            max_code_block_lines: None,
+            max_image_height: Some(DEFAULT_MAX_IMAGE_HEIGHT),
+            text_scale: 1.0,
+            animate_images: true,
+            reduce_motion: false,
+            adapt_images_to_theme: false,
+            underline_autolinks_on_hover: false,
+            image_policy: ImagePolicy::default(),
+            link_policy: LinkPolicy::default(),
+            broken_links: None,
+            image_viewer: None,
+            image_viewport: None,
+            eager_images: false,
+            limits: RenderLimits::default(),
+            block_index: 0,
+            render_mode: RenderMode::default(),
+        }
+    }
+
+    pub fn with_render_mode(mut self, render_mode: RenderMode) -> Self {
+        self.render_mode = render_mode;
+        self
+    }
+
+    /// The index of the top-level block being rendered, which keys the
+    /// block's [`PresentationState`].
+    pub fn with_block_index(mut self, block_index: usize) -> Self {
+        self.block_index = block_index;
+        self
+    }
+
+    /// The file the blocks being rendered were included from, so that their
+    /// text isn't mistaken for the document's own at the same offsets.
+    pub fn with_source_file(mut self, source_file: Option<PathBuf>) -> Self {
+        self.source_file = source_file;
+        self
+    }
+
+    pub fn with_presentation_state(mut self, state: Rc<RefCell<PresentationState>>) -> Self {
+        self.presentation_state = state;
+        self
+    }
+
+    pub fn with_style(self, style: &MarkdownStyle) -> Self {
+        self.with_max_code_block_lines(style.max_code_block_lines)
+            .with_max_image_height(style.max_image_height)
+            .with_animated_images(style.animate_images)
+            .with_autolink_underlines_on_hover(style.underline_autolinks_on_hover)
+            .with_render_limits(style.limits.clone())
+            .with_colors(style.colors)
+            .with_code_font(
+                style.code_font_family.clone(),
+                style.code_font_size,
+                style.code_font_features.clone(),
+            )
+    }
+
+    /// Renders code blocks and inline code in `family`, at `size` and with
+    /// `features`, instead of the buffer font, for each of them that's set.
+    pub fn with_code_font(
+        mut self,
+        family: Option<SharedString>,
+        size: Option<Pixels>,
+        features: Option<FontFeatures>,
+    ) -> Self {
+        if let Some(family) = family {
+            self.buffer_font_family = family.clone();
+            self.buffer_text_style.font_family = family;
+        }
+        if let Some(features) = features {
+            self.buffer_text_style.font_features = features;
+        }
+        if size.is_some() {
+            self.code_font_size = size;
+        }
+        self
+    }
+
+    /// Renders the document in `colors` instead of the theme's, if there are
+    /// any.
+    pub fn with_colors(mut self, colors: Option<MarkdownColors>) -> Self {
+        let Some(colors) = colors else {
+            return self;
+        };
+        self.text_style.color = colors.text;
+        self.buffer_text_style.color = colors.text;
+        self.text_color = colors.text;
+        self.text_muted_color = colors.text_muted;
+        self.border_color = colors.border;
+        self.code_block_background_color = colors.code_block_background;
+        self.code_span_background_color = colors.code_span_background;
+        self.highlight_background_color = colors.highlight_background;
+        self.code_line_emphasis_color = colors.code_line_emphasis;
+        self.search_match_color = colors.search_match;
+        self.active_search_match_color = colors.active_search_match;
+
+        // Text is resolved with these colors, so it's cached apart from text
+        // in the theme's.
+        let mut hasher = DefaultHasher::new();
+        self.style_key.hash(&mut hasher);
+        format!("{:?}", colors).hash(&mut hasher);
+        self.style_key = hasher.finish();
+        if let Some(cache) = self.render_cache.take() {
+            self = self.with_render_cache(cache);
+        }
+        self
+    }
+
+    /// Underlines links picked out of bare URLs only while the mouse is over
+    /// them.
+    pub fn with_autolink_underlines_on_hover(mut self, on_hover: bool) -> Self {
+        self.underline_autolinks_on_hover = on_hover;
+        self
+    }
+
+    pub fn with_render_limits(mut self, limits: RenderLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Logs that the part of a block at `source_range` went past `limit`,
+    /// the first time it's rendered.
+    fn report_limit(&self, limit: RenderLimit, source_range: &Range<usize>) {
+        let is_new = self
+            .presentation_state
+            .borrow_mut()
+            .exceeded_limits
+            .insert((source_range.clone(), limit));
+        if is_new {
+            log::warn!(
+                "the block at {}..{} is {}, so it's shown cut down",
+                source_range.start,
+                source_range.end,
+                limit.description()
+            );
+        }
+    }
+
+    /// Code blocks with more lines than this are rendered collapsed, with a
+    /// control to show the rest.
+    pub fn with_max_code_block_lines(mut self, max_lines: Option<usize>) -> Self {
+        self.max_code_block_lines = max_lines;
+        self
+    }
+
+    /// Scales images taller than `max_height` down to it, or lays them out
+    /// at their own size when it's `None`.
+    pub fn with_max_image_height(mut self, max_height: Option<Pixels>) -> Self {
+        self.max_image_height = max_height;
+        self
+    }
+
+    /// Scales the document's text, leaving images and the spacing between
+    /// blocks as they are.
+    pub fn with_text_scale(mut self, scale: f32) -> Self {
+        self.text_scale = scale;
+        self
+    }
+
+    /// Whether animated images play, or only show their first frame.
+    pub fn with_animated_images(mut self, animate: bool) -> Self {
+        self.animate_images = animate;
+        self
+    }
+
+    /// Whether the document keeps still, with animated images paused until
+    /// they're clicked.
+    pub fn with_reduced_motion(mut self, reduce_motion: bool) -> Self {
+        self.reduce_motion = reduce_motion;
+        self
+    }
+
+    /// Whether small SVGs, like badges, are adapted to the document's
+    /// colors when its text is light, so that ones drawn for light
+    /// backgrounds can be read.
+    pub fn with_images_adapted_to_theme(mut self, adapt: bool) -> Self {
+        self.adapt_images_to_theme = adapt;
+        self
+    }
+
+    /// Whether the animated image at `location` plays. This is the one place
+    /// the renderer decides it, so every image follows
+    /// [`Self::with_reduced_motion`].
+    fn plays_animation(&self, location: &ImageLocation) -> bool {
+        self.animate_images
+            && (!self.reduce_motion
+                || self
+                    .presentation_state
+                    .borrow()
+                    .playing_animations
+                    .contains(location))
+    }
+
+    pub fn with_link_policy(mut self, policy: LinkPolicy) -> Self {
+        self.link_policy = policy;
+        self
+    }
+
+    /// Underlines the links that were found to be broken with a red squiggle,
+    /// with why in their tooltip.
+    pub fn with_broken_links(mut self, broken_links: Rc<FxHashMap<Link, SharedString>>) -> Self {
+        self.broken_links = Some(broken_links);
+        self
+    }
+
+    pub fn with_image_policy(mut self, policy: ImagePolicy) -> Self {
+        self.image_policy = policy;
+        self
+    }
+
+    /// Who is told when the document's images load, instead of every window
+    /// being redrawn.
+    pub fn with_image_viewer(mut self, viewer: ImageViewer) -> Self {
+        self.image_viewer = Some(viewer);
+        self
+    }
+
+    /// Loads images lazily, once they're laid out within a viewport height
+    /// of `viewport`, the bounds of the region the document is scrolled to.
+    pub fn with_image_viewport(mut self, viewport: Rc<Cell<Bounds<Pixels>>>) -> Self {
+        self.image_viewport = Some(viewport);
+        self
+    }
+
+    /// Whether every image loads as soon as it's rendered, even with an
+    /// image viewport, for exports and printing, where all of them have to
+    /// be resolved.
+    pub fn with_eager_images(mut self, eager: bool) -> Self {
+        self.eager_images = eager;
+        self
+    }
+
+    pub fn with_render_cache(mut self, cache: Rc<RefCell<RenderCache>>) -> Self {
+        {
+            let mut cache = cache.borrow_mut();
+            if cache.style_key != self.style_key {
+                cache.style_key = self.style_key;
+                cache.invalidate();
+            }
         }
+        self.render_cache = Some(cache);
+        self
     }
 
     pub fn with_checkbox_clicked_callback(
@@ -74,7 +1107,378 @@ impl RenderContext {
         self
     }
 
-    fn next_id(&mut self, span: &Range<usize>) -> ElementId {
+    /// Calls `callback` when a link is clicked, instead of opening it with
+    /// [`open_link`]. It's given the link, how it was written and the source
+    /// range of the text the link is in.
+    pub fn with_link_clicked_callback(
+        mut self,
+        callback: impl Fn(&Link, LinkOrigin, Range<usize>, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.link_clicked_callback = Some(Arc::new(Box::new(callback)));
+        self
+    }
+
+    /// Makes images clickable, calling `callback` with the image's link and
+    /// source range when one is clicked.
+    pub fn with_image_clicked_callback(
+        mut self,
+        callback: impl Fn(&Link, Range<usize>, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.image_clicked_callback = Some(Arc::new(Box::new(callback)));
+        self
+    }
+
+    /// Gives images that were scaled down to the maximum image height a
+    /// button that calls `callback` with the image's link, to show it at
+    /// its full size.
+    pub fn with_image_expanded_callback(
+        mut self,
+        callback: impl Fn(&Link, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.image_expanded_callback = Some(Arc::new(Box::new(callback)));
+        self
+    }
+
+    /// Gives top-level headings a button that calls `callback` with the
+    /// heading's block index, to copy its section.
+    pub fn with_copy_section_callback(
+        mut self,
+        callback: impl Fn(usize, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.copy_section_callback = Some(Arc::new(Box::new(callback)));
+        self
+    }
+
+    /// Gives the code blocks in `languages`, which are lowercase, a run
+    /// button, which calls `callback` with the block's source range,
+    /// language and code. What came of each run is shown under its block
+    /// from the [`PresentationState::code_runs`].
+    pub fn with_code_runner(
+        mut self,
+        languages: Rc<FxHashSet<SharedString>>,
+        callback: impl Fn(Range<usize>, SharedString, SharedString, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.runnable_languages = languages;
+        self.run_code_callback = Some(Arc::new(Box::new(callback)));
+        self
+    }
+
+    /// Lets the caller render elements itself. The callback is asked about
+    /// every element, nested ones included, and whatever it returns replaces
+    /// the default rendering; returning `None` keeps the default. To decorate
+    /// the default rendering, call [`render_default_markdown_block`] from it.
+    pub fn with_render_override(
+        mut self,
+        callback: impl Fn(&ParsedMarkdownElement, &mut RenderContext) -> Option<AnyElement> + 'static,
+    ) -> Self {
+        self.render_override = Some(Arc::new(Box::new(callback)));
+        self
+    }
+
+    pub fn with_render_override_callback(
+        mut self,
+        callback: Option<RenderOverrideCallback>,
+    ) -> Self {
+        self.render_override = callback;
+        self
+    }
+
+    /// Renders fenced code blocks in the languages `handlers` has handlers for
+    /// with those handlers.
+    pub fn with_fence_handlers(mut self, handlers: Rc<FenceHandlers>) -> Self {
+        self.fence_handlers = handlers;
+        self
+    }
+
+    /// Highlights the matches of a search of the document.
+    pub fn with_search_highlights(mut self, highlights: Option<Rc<SearchHighlights>>) -> Self {
+        self.search_highlights = highlights;
+        self
+    }
+
+    /// Highlights the matches of [`Self::with_search_highlights`] in `color`
+    /// rather than the theme's, for highlights that aren't search matches.
+    pub fn with_search_match_color(mut self, color: Hsla) -> Self {
+        self.search_match_color = color;
+        self
+    }
+
+    /// Underlines the misspelled words of the texts in `misspellings` with a
+    /// squiggle.
+    pub fn with_misspellings(mut self, misspellings: Option<Rc<Misspellings>>) -> Self {
+        self.misspellings = misspellings;
+        self
+    }
+
+    /// Keeps `hovered` set to the link, code block or image under the mouse,
+    /// if any.
+    pub fn with_hovered_element(mut self, hovered: Rc<RefCell<Option<HoveredElement>>>) -> Self {
+        self.hovered_element = Some(hovered);
+        self
+    }
+
+    /// A listener for hovering over `element`, which tracks it in the
+    /// [`RenderContext::with_hovered_element`] state.
+    fn hover_listener(
+        &self,
+        element: HoveredElement,
+    ) -> Option<impl Fn(&bool, &mut WindowContext)> {
+        let hovered = self.hovered_element.clone()?;
+        Some(move |is_hovered: &bool, _: &mut WindowContext| {
+            set_hovered_element(&hovered, &element, *is_hovered)
+        })
+    }
+
+    /// The search highlights for the text starting at `text_start` in the
+    /// source, clipped to `range` of its contents and made relative to it.
+    fn search_highlights(
+        &self,
+        text_start: usize,
+        range: Range<usize>,
+    ) -> Vec<(Range<usize>, HighlightStyle)> {
+        let Some(highlights) = &self.search_highlights else {
+            return Vec::new();
+        };
+
+        highlights
+            .for_text(text_start)
+            .iter()
+            .filter_map(|(match_range, active)| {
+                let start = match_range.start.max(range.start);
+                let end = match_range.end.min(range.end);
+                (start < end).then(|| {
+                    let color = if *active {
+                        self.active_search_match_color
+                    } else {
+                        self.search_match_color
+                    };
+                    (
+                        start - range.start..end - range.start,
+                        HighlightStyle {
+                            background_color: Some(color),
+                            ..Default::default()
+                        },
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Records the bounds of the blocks made into
+    /// [`RenderContext::track_block_layout`] in `layouts`.
+    pub fn with_block_layouts(mut self, layouts: Rc<RefCell<BlockLayouts>>) -> Self {
+        self.block_layouts = Some(layouts);
+        self
+    }
+
+    /// Wraps the element rendered for the current top-level block so that its
+    /// bounds are recorded in the [`BlockLayouts`] once it's laid out.
+    pub fn track_block_layout(&self, element: AnyElement) -> AnyElement {
+        let Some(layouts) = self.block_layouts.clone() else {
+            return element;
+        };
+
+        let block_ix = self.block_index;
+        div()
+            .relative()
+            .child(element)
+            .child(
+                canvas(
+                    move |bounds, _| layouts.borrow_mut().record(block_ix, bounds),
+                    |_, _, _| {},
+                )
+                .absolute()
+                .top_0()
+                .left_0()
+                .size_full(),
+            )
+            .into_any()
+    }
+
+    /// Records how the document's text is laid out in `layouts`, line by
+    /// line, as it's drawn.
+    pub fn with_text_layouts(mut self, layouts: Rc<RefCell<Vec<LaidOutText>>>) -> Self {
+        self.text_layouts = Some(layouts);
+        self
+    }
+
+    /// Wraps `element`, which draws `text` in `runs`, so that the lines the
+    /// text wraps to are recorded once it's laid out. The text is shaped
+    /// again at the width it was given, which wraps it the same way.
+    fn record_text_layout(
+        &self,
+        element: AnyElement,
+        source_range: Range<usize>,
+        text: SharedString,
+        runs: Vec<TextRun>,
+    ) -> AnyElement {
+        let Some(layouts) = self.text_layouts.clone() else {
+            return element;
+        };
+
+        div()
+            .relative()
+            .child(element)
+            .child(
+                canvas(
+                    move |bounds, cx| {
+                        let style = cx.text_style();
+                        let font_size = style.font_size.to_pixels(cx.rem_size());
+                        let lines: Vec<String> = cx
+                            .text_system()
+                            .shape_text(text, font_size, &runs, Some(bounds.size.width))
+                            .map(|lines| lines.iter().flat_map(wrapped_lines).collect())
+                            .log_err()
+                            .unwrap_or_default();
+                        layouts.borrow_mut().push(LaidOutText {
+                            source_range,
+                            bounds,
+                            font_size,
+                            lines,
+                            runs,
+                        });
+                    },
+                    |_, _, _| {},
+                )
+                .absolute()
+                .top_0()
+                .left_0()
+                .size_full(),
+            )
+            .into_any()
+    }
+
+    fn resolve_text(&self, parsed: &ParsedMarkdownText) -> Arc<ResolvedText> {
+        let key = TextKey::new(parsed, self.source_file.clone());
+        if let Some(cache) = &self.render_cache {
+            if let Some(resolved) = cache.borrow().texts.get(&key) {
+                return resolved.clone();
+            }
+        }
+
+        let resolved = Arc::new(self.resolve_text_uncached(parsed));
+        if let Some(cache) = &self.render_cache {
+            cache.borrow_mut().texts.insert(key, resolved.clone());
+        }
+        resolved
+    }
+
+    fn resolve_text_uncached(&self, parsed: &ParsedMarkdownText) -> ResolvedText {
+        let mut marked_ranges: Vec<Range<usize>> = Vec::new();
+        for (range, highlight) in &parsed.highlights {
+            if !matches!(highlight, MarkdownHighlight::Style(style) if style.highlight) {
+                continue;
+            }
+            // A highlight is split wherever its other styles change, but
+            // it's drawn as one.
+            match marked_ranges.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => marked_ranges.push(range.clone()),
+            }
+        }
+        let highlights = gpui::combine_highlights(
+            parsed.highlights.iter().filter_map(|(range, highlight)| {
+                let style = highlight.to_highlight_style(&self.syntax_theme)?;
+                Some((range.clone(), style))
+            }),
+            parsed
+                .regions
+                .iter()
+                .zip(&parsed.region_ranges)
+                .filter_map(|(region, range)| {
+                    if region.code {
+                        Some((
+                            range.clone(),
+                            HighlightStyle {
+                                background_color: Some(self.code_span_background_color),
+                                ..Default::default()
+                            },
+                        ))
+                    } else {
+                        None
+                    }
+                }),
+        )
+        .collect();
+
+        let mut links = Vec::new();
+        let mut link_ranges = Vec::new();
+        for (range, region) in parsed.region_ranges.iter().zip(&parsed.regions) {
+            if let Some(link) = region.link.clone() {
+                links.push((link, region.origin));
+                link_ranges.push(range.clone());
+            }
+        }
+
+        ResolvedText {
+            highlights,
+            marked_ranges,
+            links,
+            link_ranges,
+        }
+    }
+
+    /// Draws rounded backgrounds behind the `marked_ranges` of `text`, which
+    /// `element` draws in `runs`, with a little room around them. The text
+    /// is shaped again at the width it was given to find where they are.
+    fn paint_marked_ranges(
+        &self,
+        element: AnyElement,
+        text: SharedString,
+        runs: Vec<TextRun>,
+        marked_ranges: Vec<Range<usize>>,
+    ) -> AnyElement {
+        if marked_ranges.is_empty() {
+            return element;
+        }
+
+        let background = self.highlight_background_color;
+        div()
+            .relative()
+            .child(
+                canvas(
+                    move |bounds, cx| {
+                        let style = cx.text_style();
+                        let font_size = style.font_size.to_pixels(cx.rem_size());
+                        let line_height = style.line_height_in_pixels(cx.rem_size());
+                        cx.text_system()
+                            .shape_text(text, font_size, &runs, Some(bounds.size.width))
+                            .map(|lines| marked_bounds(&lines, &marked_ranges, line_height))
+                            .log_err()
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|marked| Bounds::new(bounds.origin + marked.origin, marked.size))
+                            .collect::<Vec<_>>()
+                    },
+                    move |_, marked, cx| {
+                        for bounds in marked {
+                            cx.paint_quad(fill(bounds, background).corner_radii(px(3.)));
+                        }
+                    },
+                )
+                .absolute()
+                .top_0()
+                .left_0()
+                .size_full(),
+            )
+            .child(element)
+            .into_any()
+    }
+
+    /// Sizes the text in `element` by the text scale. Headings set their own
+    /// size, and scale it themselves.
+    fn scale_text(&self, element: AnyElement) -> AnyElement {
+        if self.text_scale == 1.0 {
+            return element;
+        }
+
+        div()
+            .text_size(self.text_size * self.text_scale)
+            .child(element)
+            .into_any()
+    }
+
+    pub fn next_id(&mut self, span: &Range<usize>) -> ElementId {
         let id = format!("markdown-{}-{}-{}", self.next_id, span.start, span.end);
         self.next_id += 1;
         ElementId::from(SharedString::from(id))
@@ -94,10 +1498,10 @@ impl RenderContext {
     /// We give padding between "This is a block quote."
     /// and "And this is the next paragraph."
     fn with_common_p(&self, element: Div) -> Div {
-        if self.indent > 0 {
-            element.pb_3()
-        } else {
-            element
+        match (self.indent > 0, self.render_mode) {
+            (false, _) => element,
+            (true, RenderMode::Document) => element.pb_3(),
+            (true, RenderMode::Inline) => element.pb_1(),
         }
     }
 }
@@ -110,14 +1514,120 @@ pub fn render_parsed_markdown(
     let mut cx = RenderContext::new(cx);
     let mut elements = Vec::new();
 
-    for child in &parsed.children {
+    for (ix, child) in parsed.children.iter().enumerate() {
+        cx.block_index = ix;
         elements.push(render_markdown_block(child, &mut cx));
     }
 
     elements
 }
 
+/// Texts up to this long are parsed as they're rendered by
+/// [`render_markdown_inline`]. Longer ones are parsed in the background.
+pub const MAX_INLINE_PARSE_LEN: usize = 4 * 1024;
+
+/// Renders a short Markdown `text`, like a chat message or the body of a
+/// tooltip, in [`RenderMode::Inline`], without the space a document has
+/// around it.
+///
+/// Text up to [`MAX_INLINE_PARSE_LEN`] long is parsed on the spot, every
+/// time, so it's for snippets; to render a longer text often, parse it and
+/// use [`render_parsed_markdown`]. A longer text is shown as it's written
+/// until it's been parsed in the background.
+pub fn render_markdown_inline(text: &str, cx: &mut WindowContext) -> AnyElement {
+    let parsed = if text.len() <= MAX_INLINE_PARSE_LEN {
+        Arc::new(futures::executor::block_on(parse_markdown(
+            text, None, None,
+        )))
+    } else {
+        match InlineParses::get_or_parse(text, cx) {
+            Some(parsed) => parsed,
+            None => return div().child(text.to_string()).into_any(),
+        }
+    };
+
+    let mut render_cx = RenderContext::new(cx).with_render_mode(RenderMode::Inline);
+    let mut blocks: Vec<AnyElement> = parsed
+        .children
+        .iter()
+        .enumerate()
+        .map(|(ix, block)| {
+            render_cx.block_index = ix;
+            render_markdown_block(block, &mut render_cx)
+        })
+        .collect();
+    if blocks.len() == 1 {
+        return blocks.remove(0);
+    }
+    v_flex().gap_1().children(blocks).into_any()
+}
+
+/// How many of the longer texts [`render_markdown_inline`] parsed are kept.
+const MAX_INLINE_PARSES: usize = 32;
+
+/// The longer texts [`render_markdown_inline`] has parsed or is parsing in
+/// the background, by their hash, the ones parsed last at the back.
+#[derive(Default)]
+struct InlineParses {
+    parses: VecDeque<(u64, Option<Arc<ParsedMarkdown>>)>,
+}
+
+impl Global for InlineParses {}
+
+impl InlineParses {
+    /// The parsed `text`, or `None` while it's parsed, after which the
+    /// windows are drawn again.
+    fn get_or_parse(text: &str, cx: &mut WindowContext) -> Option<Arc<ParsedMarkdown>> {
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let parses = &mut cx.default_global::<Self>().parses;
+        if let Some((_, parsed)) = parses.iter().find(|(parse_key, _)| *parse_key == key) {
+            return parsed.clone();
+        }
+        if parses.len() == MAX_INLINE_PARSES {
+            parses.pop_front();
+        }
+        parses.push_back((key, None));
+
+        let text = text.to_string();
+        let parse = cx
+            .background_executor()
+            .spawn(async move { parse_markdown(&text, None, None).await });
+        cx.spawn(|mut cx| async move {
+            let parsed = Arc::new(parse.await);
+            cx.update(|cx| {
+                let parses = &mut cx.default_global::<Self>().parses;
+                if let Some((_, slot)) = parses.iter_mut().find(|(parse_key, _)| *parse_key == key)
+                {
+                    *slot = Some(parsed);
+                }
+                cx.refresh();
+            })
+            .ok();
+        })
+        .detach();
+        None
+    }
+}
+
 pub fn render_markdown_block(block: &ParsedMarkdownElement, cx: &mut RenderContext) -> AnyElement {
+    if let Some(render_override) = cx.render_override.clone() {
+        if let Some(element) = render_override(block, cx) {
+            return cx.scale_text(element);
+        }
+    }
+
+    let element = render_default_markdown_block(block, cx);
+    cx.scale_text(element)
+}
+
+/// Renders `block` the built-in way, skipping the render override for it.
+pub fn render_default_markdown_block(
+    block: &ParsedMarkdownElement,
+    cx: &mut RenderContext,
+) -> AnyElement {
     use ParsedMarkdownElement::*;
     match block {
         Paragraph(text) => render_markdown_paragraph(text, cx),
@@ -132,21 +1642,136 @@ pub fn render_markdown_block(block: &ParsedMarkdownElement, cx: &mut RenderConte
 }
 
 fn render_markdown_image(parsed: &ParsedMarkdownImage, cx: &mut RenderContext) -> AnyElement {
-    let image_elm = match &parsed.link {
-        Some(link) => {
-            let image = match link {
-                Link::Web { url } => {
-                    div().child(img(url.to_string()).h(px(180.)))
-                }
-                Link::Path { display_path, path } => {
-                    div().child(img(ImageSource::from(path.to_owned())).h(px(180.)))
-                }
-            };
-            div().child(image).into_any()
-        }
-        None => div().into_any(),
+    render_image(parsed, false, cx)
+}
+
+/// Renders an image on its own, or `in_row` with others, where it's shown
+/// compactly, captioned if it fails to load, and opens its link when it's
+/// wrapped in one.
+fn render_image(parsed: &ParsedMarkdownImage, in_row: bool, cx: &mut RenderContext) -> AnyElement {
+    let Some(link) = &parsed.link else {
+        return div().into_any();
     };
-    image_elm
+
+    let location = ImageLocation::from_link(link);
+    let is_allowed = cx.image_policy.allows(&location)
+        || cx
+            .presentation_state
+            .borrow()
+            .allowed_images
+            .contains(&location);
+    if !is_allowed {
+        return render_blocked_image(parsed, location, cx);
+    }
+
+    // An image in a row that links somewhere opens its link instead.
+    let target = parsed.target.clone().filter(|_| in_row);
+    let image_clicked_callback = cx
+        .image_clicked_callback
+        .clone()
+        .filter(|_| target.is_none());
+    let animate = cx.plays_animation(&location);
+    // Images that are paused because motion is reduced, rather than by the
+    // style, can be played one at a time.
+    let play_callback = (cx.animate_images && !animate).then(|| {
+        let state = cx.presentation_state.clone();
+        let location = location.clone();
+        move |cx: &mut WindowContext| {
+            state
+                .borrow_mut()
+                .playing_animations
+                .insert(location.clone());
+            cx.refresh();
+        }
+    });
+    div()
+        .id(cx.next_id(&parsed.source_range))
+        .child(
+            MarkdownImage::new(
+                location,
+                if in_row {
+                    parsed.caption().into()
+                } else {
+                    parsed.alt_text.clone().into()
+                },
+                cx.max_image_height,
+                cx.code_block_background_color,
+            )
+            .animated(animate)
+            .when_some(play_callback, |image, callback| image.on_play(callback))
+            .when_some(cx.image_expanded_callback.clone(), |image, callback| {
+                let link = link.clone();
+                image.on_expand(move |cx| callback(&link, cx))
+            })
+            .viewer(cx.image_viewer.clone())
+            // The document's background is dark when its text is light.
+            .adapted_to_dark_background(cx.adapt_images_to_theme && cx.text_color.l > 0.5)
+            .load_viewport(cx.image_viewport.clone().filter(|_| !cx.eager_images))
+            .compact(in_row),
+        )
+        .when_some(
+            cx.hover_listener(HoveredElement::Image(link.clone())),
+            |this, listener| this.on_hover(listener),
+        )
+        .when_some(target, |this, target| {
+            let callback = cx.link_clicked_callback.clone();
+            let link_policy = cx.link_policy.clone();
+            let source_range = parsed.source_range.clone();
+            let url = target.url();
+            this.cursor_pointer()
+                .tooltip(move |cx| Tooltip::text(url.clone(), cx))
+                .on_click(move |_, cx| match &callback {
+                    Some(callback) if link_policy.allows(&target) => {
+                        callback(&target, LinkOrigin::Explicit, source_range.clone(), cx)
+                    }
+                    _ => link_policy.open(&target, cx),
+                })
+        })
+        .when_some(image_clicked_callback, |this, callback| {
+            let link = link.clone();
+            let source_range = parsed.source_range.clone();
+            this.cursor_pointer()
+                .on_click(move |_, cx| callback(&link, source_range.clone(), cx))
+        })
+        .into_any()
+}
+
+fn render_blocked_image(
+    parsed: &ParsedMarkdownImage,
+    location: ImageLocation,
+    cx: &mut RenderContext,
+) -> AnyElement {
+    let load_button = Button::new(cx.next_id(&parsed.source_range), "Load")
+        .style(ButtonStyle::Subtle)
+        .size(ButtonSize::Compact)
+        .label_size(LabelSize::Small)
+        .on_click({
+            let state = cx.presentation_state.clone();
+            let location = location.clone();
+            move |_, cx| {
+                state.borrow_mut().allowed_images.insert(location.clone());
+                cx.refresh();
+            }
+        });
+
+    h_flex()
+        .gap_2()
+        .px_2()
+        .py_1()
+        .rounded_md()
+        .border_1()
+        .border_color(cx.border_color)
+        .child(
+            v_flex()
+                .child(Label::new(parsed.alt_text.clone()).size(LabelSize::Small))
+                .child(
+                    Label::new(location.to_string())
+                        .size(LabelSize::XSmall)
+                        .color(Color::Muted),
+                ),
+        )
+        .child(load_button)
+        .into_any()
 }
 
 fn render_markdown_heading(parsed: &ParsedMarkdownHeading, cx: &mut RenderContext) -> AnyElement {
@@ -164,16 +1789,87 @@ fn render_markdown_heading(parsed: &ParsedMarkdownHeading, cx: &mut RenderContex
         _ => cx.text_color,
     };
 
+    let size = size.mul(cx.text_scale);
     let line_height = DefiniteLength::from(size.mul(1.25));
 
-    div()
+    let heading = div()
         .line_height(line_height)
         .text_size(size)
         .text_color(color)
-        .pt(rems(0.15))
-        .pb_1()
+        .when(cx.render_mode == RenderMode::Document, |this| {
+            this.pt(rems(0.15)).pb_1()
+        })
         .child(render_markdown_text(&parsed.contents, cx))
         .whitespace_normal()
+        // Next to the fold and copy buttons, the heading has to be allowed
+        // to be narrower than its longest word for a long URL in it to wrap,
+        // rather than push the buttons past the viewport.
+        .min_w_0();
+
+    // Only top-level headings of documents start sections that can be
+    // folded.
+    if cx.indent > 0 || cx.render_mode == RenderMode::Inline {
+        return heading.into_any();
+    }
+
+    let is_folded = cx
+        .presentation_state
+        .borrow()
+        .folded_sections
+        .contains(&parsed.slug);
+    let icon = if is_folded {
+        IconName::ChevronRight
+    } else {
+        IconName::ChevronDown
+    };
+    let tooltip = if is_folded {
+        "Unfold Section"
+    } else {
+        "Fold Section"
+    };
+
+    let toggle = IconButton::new(cx.next_id(&parsed.source_range), icon)
+        .icon_size(IconSize::Small)
+        .icon_color(Color::Muted)
+        .on_click({
+            let state = cx.presentation_state.clone();
+            let slug = parsed.slug.clone();
+            move |_, cx| {
+                let mut state = state.borrow_mut();
+                if !state.folded_sections.remove(&slug) {
+                    state.folded_sections.insert(slug.clone());
+                }
+                cx.refresh();
+            }
+        })
+        .tooltip(move |cx| Tooltip::text(tooltip, cx));
+
+    let copy_section = cx.copy_section_callback.clone().map(|callback| {
+        let block_ix = cx.block_index;
+        div().visible_on_hover("markdown-heading").child(
+            IconButton::new(cx.next_id(&parsed.source_range), IconName::Copy)
+                .icon_size(IconSize::Small)
+                .icon_color(Color::Muted)
+                .on_click(move |_, cx| callback(block_ix, cx))
+                .tooltip(|cx| Tooltip::text("Copy Section as Markdown", cx)),
+        )
+    });
+
+    h_flex()
+        .id(cx.next_id(&parsed.source_range))
+        .group("markdown-heading")
+        .gap_1()
+        .when_some(
+            cx.hover_listener(HoveredElement::Heading(cx.block_index)),
+            |this, listener| this.on_hover(listener),
+        )
+        .child(heading)
+        .child(
+            div()
+                .when(!is_folded, |this| this.visible_on_hover("markdown-heading"))
+                .child(toggle),
+        )
+        .children(copy_section)
         .into_any()
 }
 
@@ -183,7 +1879,11 @@ fn render_markdown_list_item(
 ) -> AnyElement {
     use ParsedMarkdownListItemType::*;
 
-    let padding = rems((parsed.depth - 1) as f32);
+    let depth = usize::from(parsed.depth.saturating_sub(1));
+    if depth > cx.limits.max_depth {
+        cx.report_limit(RenderLimit::Depth, &parsed.source_range);
+    }
+    let padding = rems(depth.min(cx.limits.max_depth) as f32);
 
     let bullet = match &parsed.item_type {
         Ordered(order) => format!("{}.", order).into_any_element(),
@@ -245,21 +1945,40 @@ fn render_markdown_list_item(
     let item = h_flex()
         .pl(DefiniteLength::Absolute(AbsoluteLength::Rems(padding)))
         .items_start()
-        .children(vec![bullet, div().children(contents).pr_4().w_full()]);
+        .children(vec![
+            bullet,
+            // Like headings, so that a long URL or hash in the item wraps
+            // instead of widening the item past the viewport.
+            div().children(contents).pr_4().w_full().min_w_0(),
+        ]);
 
     cx.with_common_p(item).into_any()
 }
 
-fn render_markdown_table(parsed: &ParsedMarkdownTable, cx: &mut RenderContext) -> AnyElement {
-    let mut max_lengths: Vec<usize> = vec![0; parsed.header.children.len()];
+pub(crate) fn render_markdown_table(
+    parsed: &ParsedMarkdownTable,
+    cx: &mut RenderContext,
+) -> AnyElement {
+    let column_count = parsed.header.children.len();
+    let shown_columns = column_count.min(cx.limits.max_table_columns.max(1));
+    if shown_columns < column_count {
+        cx.report_limit(RenderLimit::TableColumns, &parsed.source_range);
+    }
+    let mut max_lengths: Vec<usize> = vec![0; shown_columns];
 
-    for (index, cell) in parsed.header.children.iter().enumerate() {
+    for (index, cell) in parsed
+        .header
+        .children
+        .iter()
+        .take(shown_columns)
+        .enumerate()
+    {
         let length = cell.contents.len();
         max_lengths[index] = length;
     }
 
     for row in &parsed.body {
-        for (index, cell) in row.children.iter().enumerate() {
+        for (index, cell) in row.children.iter().take(shown_columns).enumerate() {
             let length = cell.contents.len();
             if length > max_lengths[index] {
                 max_lengths[index] = length;
@@ -273,32 +1992,72 @@ fn render_markdown_table(parsed: &ParsedMarkdownTable, cx: &mut RenderContext) -
         .map(|&length| length as f32 / total_max_length as f32)
         .collect();
 
+    let sort = cx
+        .presentation_state
+        .borrow()
+        .table_sorts
+        .get(&parsed.source_range)
+        .copied();
+    let row_order = match sort {
+        Some(sort) => parsed.sorted_row_indices(sort.column, sort.ascending),
+        None => (0..parsed.body.len()).collect(),
+    };
+
     let header = render_markdown_table_row(
         &parsed.header,
         &parsed.column_alignments,
         &max_column_widths,
+        &parsed.source_range,
         true,
+        sort,
         cx,
     );
+    let hidden_columns = (shown_columns < column_count).then(|| {
+        div().px_2().py_1().child(
+            Label::new(format!("{} more columns", column_count - shown_columns))
+                .size(LabelSize::Small)
+                .color(Color::Muted),
+        )
+    });
 
-    let body: Vec<AnyElement> = parsed
-        .body
-        .iter()
-        .map(|row| {
+    let body: Vec<AnyElement> = row_order
+        .into_iter()
+        .map(|ix| {
             render_markdown_table_row(
-                row,
+                &parsed.body[ix],
                 &parsed.column_alignments,
                 &max_column_widths,
+                &parsed.source_range,
                 false,
+                None,
                 cx,
             )
         })
         .collect();
 
+    let copy_button = IconButton::new(cx.next_id(&parsed.source_range), IconName::Copy)
+        .icon_size(IconSize::Small)
+        .on_click({
+            let tsv = parsed.to_tsv();
+            move |_, cx| cx.write_to_clipboard(ClipboardItem::new_string(tsv.clone()))
+        })
+        .tooltip(|cx| Tooltip::text("Copy as TSV", cx));
+
     cx.with_common_p(v_flex())
+        .group("markdown-table")
+        .relative()
         .w_full()
         .child(header)
         .children(body)
+        .children(hidden_columns)
+        .child(
+            div()
+                .absolute()
+                .top_1()
+                .right_1()
+                .visible_on_hover("markdown-table")
+                .child(copy_button),
+        )
         .into_any()
 }
 
@@ -306,12 +2065,20 @@ fn render_markdown_table_row(
     parsed: &ParsedMarkdownTableRow,
     alignments: &Vec<ParsedMarkdownTableAlignment>,
     max_column_widths: &Vec<f32>,
+    table_range: &Range<usize>,
     is_header: bool,
+    sort: Option<TableSort>,
     cx: &mut RenderContext,
 ) -> AnyElement {
     let mut items = vec![];
 
-    for (index, cell) in parsed.children.iter().enumerate() {
+    // Columns that are left out have no width.
+    for (index, cell) in parsed
+        .children
+        .iter()
+        .take(max_column_widths.len())
+        .enumerate()
+    {
         let alignment = alignments
             .get(index)
             .copied()
@@ -327,21 +2094,52 @@ fn render_markdown_table_row(
 
         let max_width = max_column_widths.get(index).unwrap_or(&0.0);
 
-        let mut cell = container
+        let cell_container = container
             .w(Length::Definite(relative(*max_width)))
             .h_full()
-            .child(contents)
             .px_2()
             .py_1()
             .border_color(cx.border_color);
 
-        if is_header {
-            cell = cell.border_2()
-        } else {
-            cell = cell.border_1()
+        if !is_header {
+            items.push(cell_container.child(contents).border_1().into_any_element());
+            continue;
         }
 
-        items.push(cell);
+        let arrow = sort.filter(|sort| sort.column == index).map(|sort| {
+            Icon::new(if sort.ascending {
+                IconName::ArrowUp
+            } else {
+                IconName::ArrowDown
+            })
+            .size(IconSize::XSmall)
+        });
+
+        let state = cx.presentation_state.clone();
+        let table_range = table_range.clone();
+        items.push(
+            cell_container
+                .child(h_flex().gap_1().child(contents).children(arrow))
+                .border_2()
+                .id(cx.next_id(&cell.source_range))
+                .cursor_pointer()
+                .on_click(move |_, cx| {
+                    let mut state = state.borrow_mut();
+                    let ascending = match state.table_sorts.get(&table_range) {
+                        Some(sort) if sort.column == index => !sort.ascending,
+                        _ => true,
+                    };
+                    state.table_sorts.insert(
+                        table_range.clone(),
+                        TableSort {
+                            column: index,
+                            ascending,
+                        },
+                    );
+                    cx.refresh();
+                })
+                .into_any_element(),
+        );
     }
 
     h_flex().children(items).into_any_element()
@@ -351,6 +2149,10 @@ fn render_markdown_block_quote(
     parsed: &ParsedMarkdownBlockQuote,
     cx: &mut RenderContext,
 ) -> AnyElement {
+    let too_deep = cx.indent >= cx.limits.max_depth;
+    if too_deep {
+        cx.report_limit(RenderLimit::Depth, &parsed.source_range);
+    }
     cx.indent += 1;
 
     let children: Vec<AnyElement> = parsed
@@ -361,6 +2163,9 @@ fn render_markdown_block_quote(
 
     cx.indent -= 1;
 
+    if too_deep {
+        return div().children(children).into_any();
+    }
     cx.with_common_p(div())
         .child(
             div()
@@ -376,109 +2181,1057 @@ fn render_markdown_code_block(
     parsed: &ParsedMarkdownCodeBlock,
     cx: &mut RenderContext,
 ) -> AnyElement {
-    let body = if let Some(highlights) = parsed.highlights.as_ref() {
-        StyledText::new(parsed.contents.clone()).with_highlights(
-            &cx.buffer_text_style,
-            highlights.iter().filter_map(|(range, highlight_id)| {
-                highlight_id
-                    .style(cx.syntax_theme.as_ref())
-                    .map(|style| (range.clone(), style))
-            }),
-        )
+    if let Some(handler) = parsed
+        .language
+        .as_deref()
+        .and_then(|language| cx.fence_handlers.get(language))
+    {
+        // A broken handler should cost its own fence, not the whole preview.
+        match panic::catch_unwind(AssertUnwindSafe(|| handler(parsed, cx))) {
+            Ok(element) => return element,
+            Err(_) => log::error!(
+                "handler for {:?} fences panicked, rendering as code instead",
+                parsed.language
+            ),
+        }
+    }
+
+    let line_count = parsed.contents.lines().count();
+    let hidden_line_count = cx
+        .max_code_block_lines
+        .map_or(0, |max_lines| line_count.saturating_sub(max_lines));
+    let is_expanded = cx
+        .presentation_state
+        .borrow()
+        .expanded_code_blocks
+        .contains(&parsed.source_range);
+    let is_collapsed = hidden_line_count > 0 && !is_expanded;
+
+    let visible_len = if is_collapsed {
+        let visible_lines = line_count - hidden_line_count;
+        parsed
+            .contents
+            .split_inclusive('\n')
+            .take(visible_lines)
+            .map(str::len)
+            .sum::<usize>()
+            .saturating_sub(1)
+    } else {
+        parsed.contents.len()
+    };
+    let contents: SharedString = if is_collapsed {
+        parsed.contents[..visible_len].to_string().into()
     } else {
-        StyledText::new(parsed.contents.clone())
+        parsed.contents.clone()
     };
 
-    cx.with_common_p(div())
+    let body = if parsed.emphasized_lines.is_empty() {
+        render_code_text(parsed, contents, 0..visible_len, cx).into_any_element()
+    } else {
+        // Each line gets its own row so that emphasized lines can be filled
+        // across the whole width of the block, however short they are, and
+        // past it as far as the block scrolls for a line too long to wrap.
+        let mut line_start = 0;
+        let lines = contents.split('\n').enumerate().map(|(ix, line)| {
+            let range = line_start..line_start + line.len();
+            line_start = range.end + 1;
+            let is_emphasized = parsed.emphasized_lines.iter().any(|r| r.contains(&ix));
+            let text: SharedString = if line.is_empty() {
+                " ".into()
+            } else {
+                line.to_string().into()
+            };
+
+            div()
+                .px(rems(0.75))
+                .when(is_emphasized, |row| row.bg(cx.code_line_emphasis_color))
+                .child(render_code_text(parsed, text, range, cx))
+        });
+        let rows = v_flex().flex_grow().children(lines.collect::<Vec<_>>());
+        div()
+            .id(cx.next_id(&parsed.source_range))
+            .flex()
+            .mx(rems(-0.75))
+            .overflow_x_scroll()
+            .child(rows)
+            .into_any_element()
+    };
+
+    let copy_button = IconButton::new(cx.next_id(&parsed.source_range), IconName::Copy)
+        .icon_size(IconSize::Small)
+        .on_click({
+            // Always copy the full block, even if it is collapsed.
+            let contents = parsed.contents.clone();
+            move |_, cx| cx.write_to_clipboard(ClipboardItem::new_string(contents.to_string()))
+        })
+        .tooltip(|cx| Tooltip::text("Copy Code", cx));
+
+    let toggle = (hidden_line_count > 0).then(|| {
+        let label = if is_collapsed {
+            format!("Show {} more lines", hidden_line_count)
+        } else {
+            "Show fewer lines".to_string()
+        };
+
+        Button::new(cx.next_id(&parsed.source_range), label)
+            .style(ButtonStyle::Subtle)
+            .size(ButtonSize::Compact)
+            .label_size(LabelSize::Small)
+            .on_click({
+                let state = cx.presentation_state.clone();
+                let source_range = parsed.source_range.clone();
+                move |_, cx| {
+                    let mut state = state.borrow_mut();
+                    if !state.expanded_code_blocks.remove(&source_range) {
+                        state.expanded_code_blocks.insert(source_range.clone());
+                    }
+                    cx.refresh();
+                }
+            })
+    });
+
+    let fade = is_collapsed.then(|| {
+        div()
+            .absolute()
+            .left_0()
+            .right_0()
+            .bottom_0()
+            .h(rems(2.))
+            .bg(linear_gradient(
+                180.,
+                linear_color_stop(cx.code_block_background_color.opacity(0.), 0.),
+                linear_color_stop(cx.code_block_background_color, 1.),
+            ))
+    });
+
+    let run = cx
+        .presentation_state
+        .borrow()
+        .code_runs
+        .get(&parsed.source_range)
+        .cloned();
+    let run_button = render_run_code_button(parsed, run.as_ref(), cx);
+    let output = run
+        .as_ref()
+        .and_then(|run| run.output.as_ref())
+        .map(|output| render_code_output(parsed, output, cx));
+
+    let id = cx.next_id(&parsed.source_range);
+    let hover_listener = cx.hover_listener(HoveredElement::CodeBlock(parsed.contents.clone()));
+    let block = div()
+        .id(id)
+        .when_some(hover_listener, |this, listener| this.on_hover(listener))
+        .group("markdown-code-block")
+        .relative()
         .font_family(cx.buffer_font_family.clone())
+        .when_some(cx.code_font_size, |this, size| {
+            this.text_size(size * cx.text_scale)
+        })
         .px_3()
         .py_3()
         .bg(cx.code_block_background_color)
         .rounded_md()
-        .child(body)
+        .child(div().relative().child(body).children(fade))
+        .children(toggle.map(|toggle| div().pt_1().child(toggle)))
+        .child(
+            div().absolute().top_1().right_1().child(
+                h_flex()
+                    .gap_1()
+                    .child(
+                        div()
+                            .visible_on_hover("markdown-code-block")
+                            .child(copy_button),
+                    )
+                    .children(run_button),
+            ),
+        );
+    cx.with_common_p(v_flex())
+        .gap_1()
+        .child(block)
+        .children(output)
+        .into_any()
+}
+
+/// The button that runs a code block through the
+/// [`RenderContext::with_code_runner`] callback, if its language is one the
+/// callback runs, which spins while the block is running.
+fn render_run_code_button(
+    parsed: &ParsedMarkdownCodeBlock,
+    run: Option<&CodeRun>,
+    cx: &mut RenderContext,
+) -> Option<AnyElement> {
+    let callback = cx.run_code_callback.clone()?;
+    let language = parsed.language.as_deref()?.to_lowercase();
+    if !cx.runnable_languages.contains(language.as_str()) {
+        return None;
+    }
+
+    if run.is_some_and(|run| run.output.is_none()) {
+        let icon = Icon::new(IconName::ArrowCircle)
+            .size(IconSize::Small)
+            .color(Color::Muted);
+        let spinner = if cx.reduce_motion {
+            icon.into_any_element()
+        } else {
+            icon.with_animation(
+                cx.next_id(&parsed.source_range),
+                Animation::new(Duration::from_secs(2)).repeat(),
+                |icon, delta| icon.transform(Transformation::rotate(percentage(delta))),
+            )
+            .into_any_element()
+        };
+        return Some(
+            div()
+                .id(cx.next_id(&parsed.source_range))
+                .p_1()
+                .child(spinner)
+                .tooltip(|cx| Tooltip::text("Running…", cx))
+                .into_any_element(),
+        );
+    }
+
+    let tooltip = if run.is_some() {
+        "Run Again"
+    } else {
+        "Run Code"
+    };
+    let source_range = parsed.source_range.clone();
+    let language = SharedString::from(language);
+    let contents = parsed.contents.clone();
+    Some(
+        IconButton::new(cx.next_id(&parsed.source_range), IconName::Play)
+            .icon_size(IconSize::Small)
+            .on_click(move |_, cx| {
+                callback(source_range.clone(), language.clone(), contents.clone(), cx)
+            })
+            .tooltip(move |cx| Tooltip::text(tooltip, cx))
+            .into_any_element(),
+    )
+}
+
+/// What came of running a code block, under the block, which can be
+/// collapsed to its header.
+fn render_code_output(
+    parsed: &ParsedMarkdownCodeBlock,
+    output: &Result<SharedString, SharedString>,
+    cx: &mut RenderContext,
+) -> AnyElement {
+    let is_collapsed = cx
+        .presentation_state
+        .borrow()
+        .collapsed_code_outputs
+        .contains(&parsed.source_range);
+    let (label, text) = match output {
+        Ok(text) => ("Output", text.clone()),
+        Err(error) => ("Error", error.clone()),
+    };
+    let is_error = output.is_err();
+    let border_color = if is_error {
+        cx.error_color
+    } else {
+        cx.border_color
+    };
+
+    let toggle = IconButton::new(
+        cx.next_id(&parsed.source_range),
+        if is_collapsed {
+            IconName::ChevronRight
+        } else {
+            IconName::ChevronDown
+        },
+    )
+    .icon_size(IconSize::Small)
+    .on_click({
+        let state = cx.presentation_state.clone();
+        let source_range = parsed.source_range.clone();
+        move |_, cx| {
+            let mut state = state.borrow_mut();
+            if !state.collapsed_code_outputs.remove(&source_range) {
+                state.collapsed_code_outputs.insert(source_range.clone());
+            }
+            cx.refresh();
+        }
+    })
+    .tooltip(move |cx| {
+        Tooltip::text(
+            if is_collapsed {
+                "Show Output"
+            } else {
+                "Hide Output"
+            },
+            cx,
+        )
+    });
+    let copy_button = IconButton::new(cx.next_id(&parsed.source_range), IconName::Copy)
+        .icon_size(IconSize::Small)
+        .on_click({
+            let text = text.clone();
+            move |_, cx| cx.write_to_clipboard(ClipboardItem::new_string(text.to_string()))
+        })
+        .tooltip(|cx| Tooltip::text("Copy Output", cx));
+
+    let body = if text.trim().is_empty() {
+        div()
+            .text_color(cx.text_muted_color)
+            .child("No output")
+            .into_any_element()
+    } else {
+        div()
+            .font_family(cx.buffer_font_family.clone())
+            .when_some(cx.code_font_size, |this, size| {
+                this.text_size(size * cx.text_scale)
+            })
+            .when(is_error, |this| this.text_color(cx.error_color))
+            .child(text.trim_end().to_string())
+            .into_any_element()
+    };
+
+    v_flex()
+        .px_3()
+        .py_1()
+        .border_1()
+        .border_color(border_color)
+        .rounded_md()
+        .child(
+            h_flex()
+                .gap_1()
+                .child(toggle)
+                .child(Label::new(label).size(LabelSize::Small).color(if is_error {
+                    Color::Error
+                } else {
+                    Color::Muted
+                }))
+                .child(div().flex_1())
+                .child(copy_button),
+        )
+        .when(!is_collapsed, |this| this.child(div().pb_2().child(body)))
         .into_any()
 }
 
+/// Renders `text`, the slice `range` of the code block's contents, with the
+/// block's syntax highlights shifted to match.
+fn render_code_text(
+    parsed: &ParsedMarkdownCodeBlock,
+    text: SharedString,
+    range: Range<usize>,
+    cx: &RenderContext,
+) -> StyledText {
+    let search_highlights = cx.search_highlights(parsed.source_range.start, range.clone());
+    let Some(highlights) = parsed.highlights.as_ref() else {
+        return StyledText::new(text).with_highlights(&cx.buffer_text_style, search_highlights);
+    };
+
+    let syntax_highlights = highlights
+        .iter()
+        .filter_map(|(highlight_range, highlight_id)| {
+            let start = highlight_range.start.max(range.start);
+            let end = highlight_range.end.min(range.end);
+            if start >= end {
+                return None;
+            }
+            highlight_id
+                .style(cx.syntax_theme.as_ref())
+                .map(|style| (start - range.start..end - range.start, style))
+        });
+    StyledText::new(text).with_highlights(
+        &cx.buffer_text_style,
+        gpui::combine_highlights(syntax_highlights, search_highlights),
+    )
+}
+
+/// Splits `search_highlights` where they cross the inline code in
+/// `code_ranges`, and blends the match color over the code's background
+/// there, so that matches in code still show it.
+fn over_code_spans(
+    search_highlights: Vec<(Range<usize>, HighlightStyle)>,
+    code_ranges: &[Range<usize>],
+    code_background: Hsla,
+) -> Vec<(Range<usize>, HighlightStyle)> {
+    let mut split = Vec::new();
+    for (range, style) in search_highlights {
+        let mut start = range.start;
+        for code in code_ranges {
+            let code_start = code.start.max(start);
+            let code_end = code.end.min(range.end);
+            if code_start >= code_end {
+                continue;
+            }
+            if start < code_start {
+                split.push((start..code_start, style));
+            }
+            let background = style
+                .background_color
+                .map(|color| code_background.blend(color));
+            split.push((
+                code_start..code_end,
+                HighlightStyle {
+                    background_color: background,
+                    ..style
+                },
+            ));
+            start = code_end;
+        }
+        if start < range.end {
+            split.push((start..range.end, style));
+        }
+    }
+    split
+}
+
 fn render_markdown_paragraph(parsed: &ParsedMarkdownText, cx: &mut RenderContext) -> AnyElement {
+    // A paragraph of nothing but images, like the badges at the top of a
+    // README, is one row of them that wraps.
+    if !parsed.images.is_empty() {
+        let images: Vec<_> = parsed
+            .images
+            .iter()
+            .map(|image| render_image(image, true, cx))
+            .collect();
+        return cx
+            .with_common_p(h_flex().flex_wrap().gap_1())
+            .children(images)
+            .into_any_element();
+    }
+
     cx.with_common_p(div())
         .child(render_markdown_text(parsed, cx))
         .into_any_element()
 }
 
+/// Fonts tried in turn when the UI or code font isn't installed, ending
+/// with ones that macOS, Windows and Linux each have.
+const TEXT_FONT_FALLBACKS: &[&str] = &["Helvetica", "Segoe UI", "DejaVu Sans", "Arial"];
+const CODE_FONT_FALLBACKS: &[&str] = &["Menlo", "Consolas", "DejaVu Sans Mono", "Courier New"];
+
+/// Adds `defaults` to the end of `fallbacks`.
+fn with_fallbacks(fallbacks: Option<FontFallbacks>, defaults: &[&str]) -> Option<FontFallbacks> {
+    let mut fonts = fallbacks.map_or_else(Vec::new, |fallbacks| fallbacks.fallback_list().to_vec());
+    for font in defaults {
+        if !fonts.iter().any(|existing| existing == font) {
+            fonts.push(font.to_string());
+        }
+    }
+    Some(FontFallbacks::from_fonts(fonts))
+}
+
+/// Splits text of `len` bytes into runs in `style`, or in `code_style` in
+/// `code_ranges`, with `highlights`, which don't overlap, applied on top.
+fn text_runs(
+    len: usize,
+    style: &TextStyle,
+    code_style: &TextStyle,
+    highlights: &[(Range<usize>, HighlightStyle)],
+    code_ranges: &[Range<usize>],
+) -> Vec<TextRun> {
+    let mut boundaries = vec![0, len];
+    for range in highlights.iter().map(|(range, _)| range).chain(code_ranges) {
+        boundaries.extend([range.start, range.end]);
+    }
+    boundaries.retain(|ix| *ix <= len);
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let contains =
+        |range: &Range<usize>, start: usize, end: usize| range.start <= start && end <= range.end;
+    boundaries
+        .windows(2)
+        .map(|piece| {
+            let (start, end) = (piece[0], piece[1]);
+            let style = if code_ranges.iter().any(|range| contains(range, start, end)) {
+                code_style
+            } else {
+                style
+            };
+            let style = match highlights
+                .iter()
+                .find(|(range, _)| contains(range, start, end))
+            {
+                Some((_, highlight)) => style.clone().highlight(*highlight),
+                None => style.clone(),
+            };
+            style.to_run(end - start)
+        })
+        .collect()
+}
+
 fn render_markdown_text(parsed: &ParsedMarkdownText, cx: &mut RenderContext) -> AnyElement {
     let element_id = cx.next_id(&parsed.source_range);
 
-    let highlights = gpui::combine_highlights(
-        parsed.highlights.iter().filter_map(|(range, highlight)| {
-            let highlight = highlight.to_highlight_style(&cx.syntax_theme)?;
-            Some((range.clone(), highlight))
-        }),
-        parsed
-            .regions
+    let shown_more = cx
+        .presentation_state
+        .borrow()
+        .text_shown_more
+        .get(&parsed.source_range)
+        .copied()
+        .unwrap_or(0);
+    let max_len = cx.limits.max_text_len.saturating_mul(shown_more + 1);
+    let limited = LimitedText::new(parsed, &cx.limits, max_len);
+    for limit in &limited.exceeded {
+        cx.report_limit(*limit, &parsed.source_range);
+    }
+    // Text that's cut off at its length has a control to show more of it.
+    let cut_off_range = limited
+        .exceeded
+        .contains(&RenderLimit::TextLength)
+        .then(|| parsed.source_range.clone());
+    let search_highlights: Vec<_> = cx
+        .search_highlights(parsed.source_range.start, 0..limited.shown_len)
+        .into_iter()
+        .map(|(range, style)| (limited.map_range(range), style))
+        .collect();
+    // Misspelled words, by their range in the text that's shown, with how
+    // many times each is in the text before.
+    let misspellings: Vec<(Range<usize>, SharedString, usize)> = cx
+        .misspellings
+        .as_ref()
+        .map(|misspellings| {
+            misspellings
+                .for_text(parsed.source_range.start)
+                .iter()
+                .filter(|(range, _)| range.end <= limited.shown_len)
+                .map(|(range, word)| {
+                    let occurrence = parsed.contents[..range.start]
+                        .matches(word.as_ref())
+                        .count();
+                    (limited.map_range(range.clone()), word.clone(), occurrence)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let parsed = limited.text.as_ref().unwrap_or(parsed);
+
+    let resolved = cx.resolve_text(parsed);
+    let links = resolved.links.clone();
+    let link_ranges = resolved.link_ranges.clone();
+    let link_clicked_callback = cx.link_clicked_callback.clone();
+    let source_range = parsed.source_range.clone();
+    let hovered_element = cx.hovered_element.clone();
+
+    let mut highlights = resolved.highlights.clone();
+    if cx.underline_autolinks_on_hover {
+        let hovered = cx.presentation_state.borrow().hovered_autolink.clone();
+        let hidden_underlines: Vec<_> = links
             .iter()
-            .zip(&parsed.region_ranges)
-            .filter_map(|(region, range)| {
-                if region.code {
-                    Some((
-                        range.clone(),
-                        HighlightStyle {
-                            background_color: Some(cx.code_span_background_color),
-                            ..Default::default()
-                        },
-                    ))
-                } else {
-                    None
-                }
-            }),
-    );
+            .zip(&link_ranges)
+            .enumerate()
+            .filter(|(ix, ((_, origin), _))| {
+                *origin == LinkOrigin::Autolink
+                    && hovered.as_ref() != Some(&(source_range.clone(), *ix))
+            })
+            .map(|(_, (_, range))| range.clone())
+            .collect();
+        for (range, style) in &mut highlights {
+            if hidden_underlines
+                .iter()
+                .any(|link| link.start <= range.start && range.end <= link.end)
+            {
+                style.underline = None;
+            }
+        }
+    }
 
-    let mut links = Vec::new();
-    let mut link_ranges = Vec::new();
-    for (range, region) in parsed.region_ranges.iter().zip(&parsed.regions) {
-        if let Some(link) = region.link.clone() {
-            links.push(link);
-            link_ranges.push(range.clone());
+    let link_policy = cx.link_policy.clone();
+    let blocked_reasons: Vec<_> = links
+        .iter()
+        .map(|(link, _)| link_policy.blocked_reason(link))
+        .collect();
+    for (range, style) in &mut highlights {
+        let blocked = link_ranges
+            .iter()
+            .zip(&blocked_reasons)
+            .any(|(link, reason)| {
+                reason.is_some() && link.start <= range.start && range.end <= link.end
+            });
+        if blocked {
+            style.color = Some(cx.text_muted_color);
+            style.underline = Some(UnderlineStyle {
+                thickness: px(1.),
+                color: Some(cx.text_muted_color),
+                wavy: true,
+            });
+        }
+    }
+    let broken_reasons: Vec<_> = links
+        .iter()
+        .map(|(link, _)| {
+            cx.broken_links
+                .as_ref()
+                .and_then(|broken| broken.get(link).cloned())
+        })
+        .collect();
+    for (range, style) in &mut highlights {
+        let broken = link_ranges
+            .iter()
+            .zip(&broken_reasons)
+            .any(|(link, reason)| {
+                reason.is_some() && link.start <= range.start && range.end <= link.end
+            });
+        if broken {
+            style.underline = Some(UnderlineStyle {
+                thickness: px(1.),
+                color: Some(cx.error_color),
+                wavy: true,
+            });
         }
     }
 
     // let workspace = cx.workspace.clone();
 
-    InteractiveText::new(
-        element_id,
-        StyledText::new(parsed.contents.clone()).with_highlights(&cx.text_style, highlights),
-    )
-    .tooltip({
-        let links = links.clone();
-        let link_ranges = link_ranges.clone();
-        move |idx, cx| {
-            for (ix, range) in link_ranges.iter().enumerate() {
-                if range.contains(&idx) {
-                    return Some(LinkPreview::new(&links[ix].to_string(), cx));
+    // Highlights can't change the font, so text with inline code is split
+    // into runs, with the code in the code font.
+    let code_ranges: Vec<_> = parsed
+        .regions
+        .iter()
+        .zip(&parsed.region_ranges)
+        .filter(|(region, _)| region.code)
+        .map(|(_, range)| range.clone())
+        .collect();
+    let search_highlights = over_code_spans(
+        search_highlights,
+        &code_ranges,
+        cx.code_span_background_color,
+    );
+    let misspelling_underline = UnderlineStyle {
+        thickness: px(1.),
+        color: Some(cx.error_color),
+        wavy: true,
+    };
+    let highlights = gpui::combine_highlights(
+        highlights,
+        misspellings.iter().map(|(range, _, _)| {
+            (
+                range.clone(),
+                HighlightStyle {
+                    underline: Some(misspelling_underline),
+                    ..Default::default()
+                },
+            )
+        }),
+    );
+    let highlights = gpui::combine_highlights(highlights, search_highlights);
+    // Text that's recorded is given the runs it's recorded with, so that
+    // what's recorded is what's drawn.
+    let mut runs = None;
+    let marked_ranges = resolved.marked_ranges.clone();
+    let contents = parsed.contents.to_shared_string();
+    let styled_text =
+        if code_ranges.is_empty() && cx.text_layouts.is_none() && marked_ranges.is_empty() {
+            StyledText::new(contents.clone()).with_highlights(&cx.text_style, highlights)
+        } else {
+            let code_style = TextStyle {
+                font_family: cx.buffer_font_family.clone(),
+                font_features: cx.buffer_text_style.font_features.clone(),
+                font_fallbacks: cx.buffer_text_style.font_fallbacks.clone(),
+                ..cx.text_style.clone()
+            };
+            let highlights: Vec<_> = highlights.collect();
+            let text_runs = text_runs(
+                parsed.contents.len(),
+                &cx.text_style,
+                &code_style,
+                &highlights,
+                &code_ranges,
+            );
+            runs = Some(text_runs.clone());
+            StyledText::new(contents.clone()).with_runs(text_runs)
+        };
+    let mut text = InteractiveText::new(element_id, styled_text)
+        .tooltip({
+            let links = links.clone();
+            let link_ranges = link_ranges.clone();
+            move |idx, cx| {
+                let ix = link_ranges.iter().position(|range| range.contains(&idx))?;
+                match (&blocked_reasons[ix], &broken_reasons[ix]) {
+                    (Some(reason), _) => Some(Tooltip::text(reason.clone(), cx)),
+                    (None, Some(reason)) => Some(Tooltip::with_meta(
+                        reason.clone(),
+                        None,
+                        links[ix].0.to_string(),
+                        cx,
+                    )),
+                    (None, None) => Some(LinkPreview::new(&links[ix].0.to_string(), cx)),
+                }
+            }
+        })
+        .on_click(link_ranges.clone(), {
+            let links = links.clone();
+            let source_range = source_range.clone();
+            move |clicked_range_ix, window_cx| {
+                let (link, origin) = &links[clicked_range_ix];
+                if !link_policy.allows(link) {
+                    return link_policy.open(link, window_cx);
+                }
+                match &link_clicked_callback {
+                    Some(callback) => callback(link, *origin, source_range.clone(), window_cx),
+                    None => link_policy.open(link, window_cx),
+                }
+            }
+        });
+    let presentation_state = cx
+        .underline_autolinks_on_hover
+        .then(|| cx.presentation_state.clone());
+    if hovered_element.is_some() || presentation_state.is_some() {
+        text = text.on_hover(move |ix, _, cx| {
+            let link_ix =
+                ix.and_then(|ix| link_ranges.iter().position(|range| range.contains(&ix)));
+            if let Some(hovered) = &hovered_element {
+                let misspelling = ix.and_then(|ix| {
+                    misspellings
+                        .iter()
+                        .find(|(range, _, _)| range.contains(&ix))
+                });
+                // Leaving a link or a misspelled word clears it, whichever
+                // one it was.
+                let mut hovered = hovered.borrow_mut();
+                match (link_ix, misspelling) {
+                    (Some(link_ix), _) => {
+                        let (link, origin) = links[link_ix].clone();
+                        *hovered = Some(HoveredElement::Link {
+                            link,
+                            origin,
+                            source_range: source_range.clone(),
+                        })
+                    }
+                    (None, Some((_, word, occurrence))) => {
+                        *hovered = Some(HoveredElement::Misspelling {
+                            word: word.clone(),
+                            source_range: source_range.clone(),
+                            occurrence: *occurrence,
+                        })
+                    }
+                    (None, None)
+                        if matches!(
+                            *hovered,
+                            Some(HoveredElement::Link { .. } | HoveredElement::Misspelling { .. })
+                        ) =>
+                    {
+                        *hovered = None
+                    }
+                    (None, None) => {}
+                }
+            }
+            if let Some(state) = &presentation_state {
+                let autolink = link_ix
+                    .filter(|ix| links[*ix].1 == LinkOrigin::Autolink)
+                    .map(|ix| (source_range.clone(), ix));
+                let mut state = state.borrow_mut();
+                // Only this text's links are its to clear.
+                let is_own = state
+                    .hovered_autolink
+                    .as_ref()
+                    .map_or(true, |(range, _)| *range == source_range);
+                if (is_own || autolink.is_some()) && state.hovered_autolink != autolink {
+                    state.hovered_autolink = autolink;
+                    cx.refresh();
                 }
             }
-            None
+        });
+    }
+    let text = match runs {
+        Some(runs) => {
+            let text = cx.paint_marked_ranges(
+                text.into_any_element(),
+                contents.clone(),
+                runs.clone(),
+                marked_ranges,
+            );
+            cx.record_text_layout(text, parsed.source_range.clone(), contents.clone(), runs)
         }
-    })
-    .on_click(
-        link_ranges,
-        move |clicked_range_ix, window_cx| match &links[clicked_range_ix] {
-            Link::Web { url } => window_cx.open_url(url),
-            Link::Path {
-                path,
-                display_path: _,
-            } => {
-                // if let Some(workspace) = &workspace {
-                //     _ = workspace.update(window_cx, |workspace, cx| {
-                //         workspace.open_abs_path(path.clone(), false, cx).detach();
-                //     });
-                // }
+        None => text.into_any_element(),
+    };
+    let Some(source_range) = cut_off_range else {
+        return text;
+    };
+
+    let show_more = Button::new(cx.next_id(&source_range), "Show more")
+        .style(ButtonStyle::Subtle)
+        .size(ButtonSize::Compact)
+        .label_size(LabelSize::Small)
+        .on_click({
+            let state = cx.presentation_state.clone();
+            move |_, cx| {
+                *state
+                    .borrow_mut()
+                    .text_shown_more
+                    .entry(source_range.clone())
+                    .or_default() += 1;
+                cx.refresh();
             }
-        },
-    )
-    .into_any_element()
+        });
+    v_flex()
+        .items_start()
+        .gap_1()
+        .child(text)
+        .child(show_more)
+        .into_any_element()
+}
+
+/// Text cut down to fit the [`RenderLimits`].
+struct LimitedText {
+    /// The text to render instead, if it went past a limit.
+    text: Option<ParsedMarkdownText>,
+    /// How much of the original contents is shown.
+    shown_len: usize,
+    /// Where breaks were put into long words, in the original contents.
+    word_breaks: Vec<usize>,
+    exceeded: Vec<RenderLimit>,
+}
+
+impl LimitedText {
+    /// Lets long words wrap anywhere without being drawn any differently.
+    const WORD_BREAK: &'static str = "\u{200B}";
+
+    /// Cuts `parsed` down to fit `limits`, and to `max_len` bytes, which
+    /// grows past [`RenderLimits::max_text_len`] as more of it is shown.
+    fn new(parsed: &ParsedMarkdownText, limits: &RenderLimits, max_len: usize) -> Self {
+        let mut exceeded = Vec::new();
+        let mut shown_len = parsed.contents.len();
+        if parsed.highlights.len() + parsed.regions.len() > limits.max_inline_runs {
+            let mut starts: Vec<usize> = parsed
+                .highlights
+                .iter()
+                .map(|(range, _)| range.start)
+                .chain(parsed.region_ranges.iter().map(|range| range.start))
+                .collect();
+            starts.sort_unstable();
+            if let Some(cutoff) = starts.get(limits.max_inline_runs) {
+                shown_len = *cutoff;
+                exceeded.push(RenderLimit::InlineRuns);
+            }
+        }
+        if shown_len > max_len {
+            shown_len = max_len;
+            while !parsed.contents.is_char_boundary(shown_len) {
+                shown_len -= 1;
+            }
+            exceeded.push(RenderLimit::TextLength);
+        }
+
+        let shown = &parsed.contents[..shown_len];
+        let mut word_breaks = Vec::new();
+        // Text no longer than a word can't have a word that's too long.
+        if shown.len() > limits.max_word_len {
+            let mut word_len = 0;
+            for (ix, ch) in shown.char_indices() {
+                if ch.is_whitespace() {
+                    word_len = 0;
+                    continue;
+                }
+                if word_len == limits.max_word_len.max(1) {
+                    word_breaks.push(ix);
+                    word_len = 0;
+                }
+                word_len += 1;
+            }
+        }
+        if !word_breaks.is_empty() {
+            exceeded.push(RenderLimit::WordLength);
+        }
+        if exceeded.is_empty() {
+            return Self {
+                text: None,
+                shown_len,
+                word_breaks,
+                exceeded,
+            };
+        }
+
+        let mut contents = String::with_capacity(
+            shown_len + word_breaks.len() * Self::WORD_BREAK.len() + "…".len(),
+        );
+        let mut start = 0;
+        for &word_break in &word_breaks {
+            contents.push_str(&shown[start..word_break]);
+            contents.push_str(Self::WORD_BREAK);
+            start = word_break;
+        }
+        contents.push_str(&shown[start..]);
+        if shown_len < parsed.contents.len() {
+            contents.push('…');
+        }
+
+        let mut limited = Self {
+            text: None,
+            shown_len,
+            word_breaks,
+            exceeded,
+        };
+        let clip = |range: &Range<usize>| {
+            (range.start < shown_len)
+                .then(|| limited.map_range(range.start..range.end.min(shown_len)))
+        };
+        let highlights = parsed
+            .highlights
+            .iter()
+            .filter_map(|(range, highlight)| Some((clip(range)?, highlight.clone())))
+            .collect();
+        let (region_ranges, regions) = parsed
+            .region_ranges
+            .iter()
+            .zip(&parsed.regions)
+            .filter_map(|(range, region)| Some((clip(range)?, region.clone())))
+            .unzip();
+        limited.text = Some(ParsedMarkdownText {
+            source_range: parsed.source_range.clone(),
+            contents: contents.into(),
+            highlights,
+            region_ranges,
+            regions,
+            images: parsed.images.clone(),
+            continued: parsed.continued,
+        });
+        limited
+    }
+
+    /// Where `range` of the original contents is in the text that's shown.
+    fn map_range(&self, range: Range<usize>) -> Range<usize> {
+        let map = |offset: usize| {
+            offset
+                + self
+                    .word_breaks
+                    .partition_point(|&word_break| word_break < offset)
+                    * Self::WORD_BREAK.len()
+        };
+        map(range.start)..map(range.end)
+    }
+}
+
+/// Sets `hovered` to `element`, or clears it if `element` was hovered and
+/// no longer is. Elements can be nested, like links in a list item, so
+/// leaving one doesn't clear another that was entered since.
+fn set_hovered_element(
+    hovered: &RefCell<Option<HoveredElement>>,
+    element: &HoveredElement,
+    is_hovered: bool,
+) {
+    let mut hovered = hovered.borrow_mut();
+    if is_hovered {
+        *hovered = Some(element.clone());
+    } else if hovered.as_ref() == Some(element) {
+        *hovered = None;
+    }
+}
+
+/// Which links open when they're clicked. Documents can link to any URL, and
+/// ones with schemes like `javascript:` or `file:` shouldn't be handed to
+/// the platform just because a document says so. Links the policy blocks
+/// are shown in a muted color with a wavy underline, and clicking them only
+/// tells [`LinkPolicy::on_blocked`].
+#[derive(Clone)]
+pub struct LinkPolicy {
+    /// The URL schemes, in lowercase, that links can open.
+    pub allowed_schemes: Vec<SharedString>,
+    /// Asks before opening any URL outside the preview, for documents that
+    /// aren't trusted.
+    pub confirm_external: bool,
+    /// Called with the URL of a blocked link when it's clicked.
+    pub on_blocked: Option<Arc<dyn Fn(&str, &mut WindowContext)>>,
+}
+
+impl Default for LinkPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: vec!["http".into(), "https".into(), "mailto".into()],
+            confirm_external: false,
+            on_blocked: None,
+        }
+    }
+}
+
+impl LinkPolicy {
+    /// Calls `callback` with the URL of a blocked link when it's clicked.
+    pub fn on_blocked(mut self, callback: impl Fn(&str, &mut WindowContext) + 'static) -> Self {
+        self.on_blocked = Some(Arc::new(callback));
+        self
+    }
+
+    /// Whether `link` can be opened. Only URLs opened outside the preview
+    /// are checked; links to files and headings always are.
+    pub fn allows(&self, link: &Link) -> bool {
+        self.blocked_scheme(link).is_none()
+    }
+
+    /// The scheme of `link`, if the policy blocks it.
+    fn blocked_scheme<'a>(&self, link: &'a Link) -> Option<&'a str> {
+        let Link::Web { url } = link else {
+            return None;
+        };
+        let scheme = url
+            .split_once(':')
+            .map_or(url.as_str(), |(scheme, _)| scheme);
+        let allowed = self
+            .allowed_schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(scheme));
+        (!allowed).then_some(scheme)
+    }
+
+    /// Why clicking `link` does nothing, if the policy blocks it.
+    pub fn blocked_reason(&self, link: &Link) -> Option<String> {
+        let scheme = self.blocked_scheme(link)?;
+        Some(format!(
+            "Links to {}: URLs are blocked in this document",
+            scheme
+        ))
+    }
+
+    /// Opens `link` with [`open_link`] if the policy allows it, asking first
+    /// if it's to be confirmed.
+    pub fn open(&self, link: &Link, cx: &mut WindowContext) {
+        if !self.allows(link) {
+            if let (Some(callback), Link::Web { url }) = (&self.on_blocked, link) {
+                callback(url, cx);
+            }
+            return;
+        }
+
+        let Link::Web { url } = link else {
+            return open_link(link, cx);
+        };
+        if !self.confirm_external {
+            return open_link(link, cx);
+        }
+        let answer = cx.prompt(
+            PromptLevel::Warning,
+            "Open this link?",
+            Some(url.as_str()),
+            &["Open", "Cancel"],
+        );
+        let link = link.clone();
+        cx.spawn(|mut cx| async move {
+            if answer.await == Ok(0) {
+                cx.update(|cx| open_link(&link, cx)).ok();
+            }
+        })
+        .detach();
+    }
+}
+
+/// Opens `link` the way the renderer does when it isn't given a callback for
+/// clicked links. Links to headings need a view to scroll, so nothing happens
+/// for those.
+pub fn open_link(link: &Link, window_cx: &mut WindowContext) {
+    match link {
+        Link::Web { .. } => {
+            if let Some(url) = link.os_url() {
+                window_cx.open_url(&url)
+            }
+        }
+        Link::Path { path, .. } => {
+            // if let Some(workspace) = &workspace {
+            //     _ = workspace.update(window_cx, |workspace, cx| {
+            //         workspace.open_abs_path(path.clone(), false, cx).detach();
+            //     });
+            // }
+        }
+        Link::Data { .. } | Link::Fragment { .. } => {}
+    }
 }
 
 fn render_markdown_rule(cx: &mut RenderContext) -> AnyElement {
     let rule = div().w_full().h(px(2.)).bg(cx.border_color);
-    div().pt_3().pb_3().child(rule).into_any()
+    match cx.render_mode {
+        RenderMode::Document => div().pt_3().pb_3().child(rule).into_any(),
+        RenderMode::Inline => div().py_1().child(rule).into_any(),
+    }
 }
@@ -0,0 +1,228 @@
+use super::markdown_elements::{ParsedMarkdown, ParsedMarkdownElement, ParsedMarkdownText};
+use collections::FxHashMap;
+use std::ops::Range;
+
+/// An occurrence of a search query in the text of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    /// The top-level block the match is in.
+    pub block_ix: usize,
+    /// Where the text the match is in starts in the source. This identifies
+    /// the text, like a paragraph or a code block, while rendering.
+    pub text_start: usize,
+    /// The range of the match within the text's contents.
+    pub range: Range<usize>,
+}
+
+/// Finds every occurrence of `query` in the text of `parsed`, in document
+/// order.
+///
+/// This searches the text as it's shown rather than its Markdown source, so
+/// a match can span formatting, like `bold **text**` for `bold text`.
+pub fn search(parsed: &ParsedMarkdown, query: &str, case_sensitive: bool) -> Vec<SearchMatch> {
+    let mut matches = Vec::new();
+    if query.is_empty() {
+        return matches;
+    }
+
+    for (block_ix, block) in parsed.children.iter().enumerate() {
+        search_block(block, block_ix, query, case_sensitive, &mut matches);
+    }
+    matches
+}
+
+fn search_block(
+    block: &ParsedMarkdownElement,
+    block_ix: usize,
+    query: &str,
+    case_sensitive: bool,
+    matches: &mut Vec<SearchMatch>,
+) {
+    let search_text = |text: &ParsedMarkdownText, matches: &mut Vec<SearchMatch>| {
+        matches.extend(
+            find_all(&text.contents, query, case_sensitive).map(|range| SearchMatch {
+                block_ix,
+                text_start: text.source_range.start,
+                range,
+            }),
+        );
+    };
+
+    match block {
+        ParsedMarkdownElement::Heading(heading) => search_text(&heading.contents, matches),
+        ParsedMarkdownElement::Paragraph(text) => search_text(text, matches),
+        ParsedMarkdownElement::ListItem(item) => {
+            for child in &item.content {
+                search_block(child, block_ix, query, case_sensitive, matches);
+            }
+        }
+        ParsedMarkdownElement::BlockQuote(block_quote) => {
+            for child in &block_quote.children {
+                search_block(child, block_ix, query, case_sensitive, matches);
+            }
+        }
+        ParsedMarkdownElement::Table(table) => {
+            for row in std::iter::once(&table.header).chain(&table.body) {
+                for cell in &row.children {
+                    search_text(cell, matches);
+                }
+            }
+        }
+        ParsedMarkdownElement::CodeBlock(code_block) => {
+            matches.extend(
+                find_all(&code_block.contents, query, case_sensitive).map(|range| SearchMatch {
+                    block_ix,
+                    text_start: code_block.source_range.start,
+                    range,
+                }),
+            );
+        }
+        ParsedMarkdownElement::Image(_) | ParsedMarkdownElement::HorizontalRule(_) => {}
+    }
+}
+
+/// Finds the non-overlapping occurrences of `query` in `text`. Without
+/// `case_sensitive`, characters are compared by their lowercase forms, so the
+/// ranges stay byte ranges of `text` even where those forms differ in length.
+fn find_all<'a>(
+    text: &'a str,
+    query: &'a str,
+    case_sensitive: bool,
+) -> impl Iterator<Item = Range<usize>> + 'a {
+    let mut start = if query.is_empty() { text.len() } else { 0 };
+    std::iter::from_fn(move || {
+        while start < text.len() {
+            let candidate = start;
+            start += text[start..].chars().next().map_or(1, char::len_utf8);
+            if let Some(len) = match_len(&text[candidate..], query, case_sensitive) {
+                start = candidate + len.max(1);
+                return Some(candidate..candidate + len);
+            }
+        }
+        None
+    })
+}
+
+/// The length of `query` at the start of `text`, if it's there.
+fn match_len(text: &str, query: &str, case_sensitive: bool) -> Option<usize> {
+    if case_sensitive {
+        return text.starts_with(query).then_some(query.len());
+    }
+
+    let mut text_chars = text.char_indices();
+    for query_char in query.chars() {
+        let (_, text_char) = text_chars.next()?;
+        if !text_char.to_lowercase().eq(query_char.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(text_chars.next().map_or(text.len(), |(ix, _)| ix))
+}
+
+/// The search matches to highlight while rendering, looked up by the text
+/// they are in.
+#[derive(Debug, Default)]
+pub struct SearchHighlights {
+    texts: FxHashMap<usize, Vec<(Range<usize>, bool)>>,
+}
+
+impl SearchHighlights {
+    /// Highlights `matches`, with the one at `active_ix` set apart from the
+    /// others.
+    pub fn new(matches: &[SearchMatch], active_ix: Option<usize>) -> Self {
+        let mut texts: FxHashMap<usize, Vec<(Range<usize>, bool)>> = FxHashMap::default();
+        for (ix, search_match) in matches.iter().enumerate() {
+            texts
+                .entry(search_match.text_start)
+                .or_default()
+                .push((search_match.range.clone(), Some(ix) == active_ix));
+        }
+        Self { texts }
+    }
+
+    /// The matches in the text starting at `text_start` in the source, along
+    /// with whether each is the active one.
+    pub fn for_text(&self, text_start: usize) -> &[(Range<usize>, bool)] {
+        self.texts.get(&text_start).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use pretty_assertions::assert_eq;
+
+    fn matched_text<'a>(text: &'a str, query: &str, case_sensitive: bool) -> Vec<&'a str> {
+        find_all(text, query, case_sensitive)
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_find_all() {
+        assert_eq!(matched_text("aaaa", "aa", true), vec!["aa", "aa"]);
+        assert_eq!(matched_text("Zed zed ZED", "zed", true), vec!["zed"]);
+        assert_eq!(
+            matched_text("Zed zed ZED", "zed", false),
+            vec!["Zed", "zed", "ZED"]
+        );
+        assert_eq!(
+            matched_text("Straße STRASSE", "straße", false),
+            vec!["Straße"]
+        );
+        assert_eq!(matched_text("anything", "", false), Vec::<&str>::new());
+    }
+
+    #[gpui::test]
+    async fn test_search_spans_formatting() {
+        let parsed = parse_markdown(
+            "# Bold text\n\nSome **bold** text\n\n- [bold text](https://zed.dev)\n\n```\nbold text\n```\n",
+            None,
+            None,
+        )
+        .await;
+
+        let matches = search(&parsed, "BOLD TEXT", false);
+        let blocks: Vec<_> = matches.iter().map(|m| m.block_ix).collect();
+        assert_eq!(blocks, vec![0, 1, 2, 3]);
+        assert_eq!(matches[1].range, 5..14);
+
+        let highlights = SearchHighlights::new(&matches, Some(1));
+        assert_eq!(highlights.for_text(matches[1].text_start), &[(5..14, true)]);
+        assert!(search(&parsed, "BOLD TEXT", true).is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_search_into_inline_code() {
+        let parsed = parse_markdown(
+            "Then call `foo()` now\n\n```\nfn foo() {}\n```\n",
+            None,
+            None,
+        )
+        .await;
+
+        // A match that starts in prose and ends inside the code span.
+        let matches = search(&parsed, "call foo", false);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].block_ix, 0);
+        assert_eq!(matches[0].range, 5..13);
+        let ParsedMarkdownElement::Paragraph(text) = &parsed.children[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(&text.contents[matches[0].range.clone()], "call foo");
+        assert!(text
+            .region_ranges
+            .iter()
+            .zip(&text.regions)
+            .any(|(range, region)| region.code && range.start < 13 && 13 <= range.end));
+
+        // And matches inside code blocks.
+        let blocks: Vec<_> = search(&parsed, "foo()", false)
+            .iter()
+            .map(|m| m.block_ix)
+            .collect();
+        assert_eq!(blocks, vec![0, 1]);
+    }
+}
@@ -0,0 +1,279 @@
+use super::{markdown_tabs::MarkdownTabs, markdown_view::ScrollPosition};
+use anyhow::{Context as _, Result};
+use gpui::{AppContext, Global, Task};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::Duration};
+
+/// How long the session waits to be saved after it changes, so that
+/// scrolling through a document saves it once.
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// The windows that were open, to open again the next time the application
+/// starts.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub windows: Vec<WindowSession>,
+}
+
+/// A window of [`MarkdownTabs`].
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowSession {
+    /// The folder shown in the window's sidebar.
+    #[serde(default)]
+    pub folder: Option<PathBuf>,
+    #[serde(default)]
+    pub tabs: Vec<TabSession>,
+    #[serde(default)]
+    pub active_tab: usize,
+}
+
+/// A tab showing a file. Tabs that don't, like ones pasted from the
+/// clipboard, aren't kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabSession {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub scroll_position: ScrollPosition,
+    #[serde(default = "default_text_scale")]
+    pub text_scale: f32,
+    #[serde(default)]
+    pub follow: bool,
+}
+
+fn default_text_scale() -> f32 {
+    1.
+}
+
+impl Session {
+    /// The session of the application's windows of tabs.
+    pub fn capture(cx: &AppContext) -> Self {
+        let windows = cx
+            .windows()
+            .into_iter()
+            .filter_map(|window| window.downcast::<MarkdownTabs>()?.read(cx).ok())
+            .map(|tabs| tabs.session(cx))
+            .filter(|window| window.folder.is_some() || !window.tabs.is_empty())
+            .collect();
+        Self { windows }
+    }
+
+    /// Drops the files and folders that no longer exist, and the windows
+    /// that are left with nothing in them, returning the paths that were
+    /// dropped, each once.
+    pub fn prune(&mut self) -> Vec<PathBuf> {
+        let mut missing = Vec::new();
+        for window in &mut self.windows {
+            if let Some(folder) = window.folder.take() {
+                if folder.is_dir() {
+                    window.folder = Some(folder);
+                } else if !missing.contains(&folder) {
+                    missing.push(folder);
+                }
+            }
+            let active_path = window
+                .tabs
+                .get(window.active_tab)
+                .map(|tab| tab.path.clone());
+            window.tabs.retain(|tab| {
+                let exists = tab.path.is_file();
+                if !exists && !missing.contains(&tab.path) {
+                    missing.push(tab.path.clone());
+                }
+                exists
+            });
+            window.active_tab = active_path
+                .and_then(|path| window.tabs.iter().position(|tab| tab.path == path))
+                .unwrap_or(0);
+        }
+        self.windows
+            .retain(|window| window.folder.is_some() || !window.tabs.is_empty());
+        missing
+    }
+}
+
+/// Where the [`Session`] is saved, as it changes and when the application
+/// quits.
+///
+/// Like [`RecentFiles`](super::markdown_recent::RecentFiles), the session is
+/// only saved by applications that set this global.
+pub struct SessionStore {
+    file: PathBuf,
+    /// Saves the session once it has stopped changing.
+    pending_save: Option<Task<()>>,
+}
+
+impl Global for SessionStore {}
+
+impl SessionStore {
+    pub fn new(file: PathBuf) -> Self {
+        Self {
+            file,
+            pending_save: None,
+        }
+    }
+
+    /// Reads the session that was saved last. A missing or unreadable file
+    /// has none.
+    pub fn load(&self) -> Option<Session> {
+        let content = std::fs::read_to_string(&self.file).ok()?;
+        serde_json::from_str(&content)
+            .map_err(|error| log::error!("failed to parse {}: {}", self.file.display(), error))
+            .ok()
+    }
+
+    /// Saves the session a moment from now, unless it changes again before
+    /// then.
+    pub fn schedule_save(cx: &mut AppContext) {
+        if !cx.has_global::<Self>() {
+            return;
+        }
+        // The write is detached rather than awaited here, since this task is
+        // dropped, and so cancelled, as soon as the session changes again.
+        let task = cx.spawn(|cx| async move {
+            cx.background_executor().timer(SAVE_DEBOUNCE).await;
+            cx.update(|cx| Self::write(cx).detach_and_log_err(cx)).ok();
+        });
+        cx.global_mut::<Self>().pending_save = Some(task);
+    }
+
+    /// Saves the session of the windows that are open now, instead of the
+    /// save that was scheduled, like when the application quits.
+    ///
+    /// Closing the last window leaves nothing to save, so a session without
+    /// windows isn't, and quitting afterwards still reopens what was open.
+    pub fn save(cx: &mut AppContext) -> Task<Result<()>> {
+        if !cx.has_global::<Self>() {
+            return Task::ready(Ok(()));
+        }
+        cx.global_mut::<Self>().pending_save = None;
+        Self::write(cx)
+    }
+
+    fn write(cx: &mut AppContext) -> Task<Result<()>> {
+        let session = Session::capture(cx);
+        let store = cx.global::<Self>();
+        if session.windows.is_empty() {
+            return Task::ready(Ok(()));
+        }
+        let file = store.file.clone();
+        cx.background_executor().spawn(async move {
+            let content = serde_json::to_string_pretty(&session)?;
+            if let Some(directory) = file.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            std::fs::write(&file, content)
+                .with_context(|| format!("failed to write {}", file.display()))
+        })
+    }
+}
+
+/// How the files in `missing` are listed, one per line.
+pub fn missing_files_message(missing: &[PathBuf]) -> String {
+    let mut message = "These files from the last session no longer exist:".to_string();
+    for path in missing {
+        message.push_str("\n• ");
+        message.push_str(&path.display().to_string());
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+    use settings::SettingsStore;
+    use std::path::Path;
+    use theme::LoadThemes;
+
+    #[gpui::test]
+    async fn test_scheduled_save(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+        let directory =
+            std::env::temp_dir().join(format!("markdown-session-save-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let document = directory.join("one.md");
+        std::fs::write(&document, "# One\n").unwrap();
+        let file = directory.join("session.json");
+
+        let (tabs, cx) = cx.add_window_view(MarkdownTabs::new);
+        tabs.update(cx, |tabs, cx| tabs.open_paths(vec![document.clone()], cx));
+        cx.run_until_parked();
+
+        cx.update(|cx| {
+            cx.set_global(SessionStore::new(file.clone()));
+            SessionStore::schedule_save(cx);
+        });
+        cx.run_until_parked();
+        assert!(!file.exists());
+
+        cx.executor().advance_clock(SAVE_DEBOUNCE);
+        cx.run_until_parked();
+        let expected = tabs.update(cx, |tabs, cx| tabs.session(cx));
+        let saved = cx.update(|cx| cx.global::<SessionStore>().load());
+        assert_eq!(
+            saved,
+            Some(Session {
+                windows: vec![expected]
+            })
+        );
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn test_prune_session() {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-session-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let one = directory.join("one.md");
+        let two = directory.join("two.md");
+        std::fs::write(&one, "# One\n").unwrap();
+        let tab = |path: &Path| TabSession {
+            path: path.to_path_buf(),
+            scroll_position: ScrollPosition::default(),
+            text_scale: 1.,
+            follow: false,
+        };
+
+        let mut session = Session {
+            windows: vec![
+                WindowSession {
+                    folder: None,
+                    tabs: vec![tab(&two), tab(&one)],
+                    active_tab: 1,
+                },
+                WindowSession {
+                    folder: Some(directory.join("gone")),
+                    tabs: vec![tab(&two)],
+                    active_tab: 0,
+                },
+            ],
+        };
+        let missing = session.prune();
+        assert_eq!(missing, vec![two.clone(), directory.join("gone")]);
+        assert_eq!(
+            session.windows,
+            vec![WindowSession {
+                folder: None,
+                tabs: vec![tab(&one)],
+                active_tab: 0,
+            }]
+        );
+
+        // Sessions saved by older versions, without some of the fields, are
+        // read with their defaults.
+        let session: Session =
+            serde_json::from_str(r#"{"windows": [{"tabs": [{"path": "/a.md"}]}]}"#).unwrap();
+        assert_eq!(session.windows[0].tabs[0].text_scale, 1.);
+        assert_eq!(session.windows[0].folder, None);
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
@@ -0,0 +1,402 @@
+use super::{
+    markdown_locale::Locale,
+    markdown_theme::{DEFAULT_DARK_THEME, DEFAULT_LIGHT_THEME},
+};
+use anyhow::{Context as _, Result};
+use gpui::{AppContext, FontFeatures, Global};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsSources, SettingsStore};
+use std::path::PathBuf;
+
+const DEFAULT_SETTINGS: &str = include_str!("../../assets/settings/default.json");
+
+/// The preview's own settings, under the `markdown_preview` key of the
+/// settings file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownPreviewSettings {
+    /// How much larger than the UI's text the document's text is drawn.
+    pub text_scale: f32,
+    /// Whether j and k scroll the document, like in vim.
+    pub vim_scrolling: bool,
+    /// Whether printed links to web pages are followed by their URLs.
+    pub print_link_urls: bool,
+    /// Whether HTML exports start with a table of contents.
+    pub export_table_of_contents: bool,
+    /// Whether HTML exports write their table of contents to a `toc.json`
+    /// next to the page too.
+    pub export_toc_json: bool,
+    /// The levels of the headings the table of contents lists.
+    pub export_toc_min_level: u8,
+    pub export_toc_max_level: u8,
+    /// Whether a document's front matter is shown above it.
+    pub show_front_matter: bool,
+    /// Whether the status bar is shown below the document.
+    pub show_status_bar: bool,
+    /// Whether the outline and the heading picker number the headings by
+    /// section.
+    pub outline_numbers: bool,
+    /// Whether every line break in a paragraph is shown, like in GitHub
+    /// comments.
+    pub hard_wrap: bool,
+    /// Whether scrolling jumps instead of gliding and animated images wait
+    /// to be clicked before they play.
+    pub reduce_motion: bool,
+    /// Whether misspelled words in the document's text are underlined.
+    pub spellcheck: bool,
+    /// The language of the dictionary words are checked against.
+    pub spellcheck_language: String,
+    /// The language tag of the locale dates and numbers are written for,
+    /// instead of the system's.
+    pub locale: Option<String>,
+    /// How many megabytes of decoded images are kept in memory.
+    pub image_cache_size: usize,
+    /// How many seconds downloading an image can take.
+    pub image_fetch_timeout: u64,
+    /// The largest image, in megabytes, that's downloaded.
+    pub max_image_download_size: usize,
+    /// How many images are downloaded at once.
+    pub max_concurrent_image_fetches: usize,
+    /// Whether small SVGs, like badges, are made readable on dark themes.
+    pub adapt_images_to_theme: bool,
+    /// The font of code, instead of the buffer font.
+    pub code_font_family: Option<String>,
+    /// The size of the text of code blocks, instead of the document's.
+    pub code_font_size: Option<f32>,
+    pub code_font_features: Option<FontFeatures>,
+}
+
+impl Default for MarkdownPreviewSettings {
+    fn default() -> Self {
+        Self {
+            text_scale: 1.0,
+            vim_scrolling: false,
+            print_link_urls: true,
+            export_table_of_contents: false,
+            export_toc_json: false,
+            export_toc_min_level: 1,
+            export_toc_max_level: 6,
+            show_front_matter: true,
+            show_status_bar: true,
+            outline_numbers: false,
+            hard_wrap: false,
+            reduce_motion: false,
+            spellcheck: false,
+            spellcheck_language: "en_US".to_string(),
+            locale: None,
+            image_cache_size: 256,
+            image_fetch_timeout: 30,
+            max_image_download_size: 50,
+            max_concurrent_image_fetches: 6,
+            adapt_images_to_theme: false,
+            code_font_family: None,
+            code_font_size: None,
+            code_font_features: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct MarkdownPreviewSettingsContent {
+    /// The text scale of the preview, from 0.5 to 3.0.
+    ///
+    /// Default: 1.0
+    pub text_scale: Option<f32>,
+    /// Whether j and k scroll the document down and up a line.
+    ///
+    /// Default: false
+    pub vim_scrolling: Option<bool>,
+    /// Whether printing a link to a web page appends its URL in parentheses.
+    ///
+    /// Default: true
+    pub print_link_urls: Option<bool>,
+    /// Whether exporting the document to HTML puts a table of contents at
+    /// the top of the page, with a link to each heading.
+    ///
+    /// Default: false
+    pub export_table_of_contents: Option<bool>,
+    /// Whether exporting the document to HTML also writes its table of
+    /// contents to a `toc.json` in the same folder, as nested entries with
+    /// each heading's level, text and id, for static site tooling.
+    ///
+    /// Default: false
+    pub export_toc_json: Option<bool>,
+    /// The smallest heading level, from 1 to 6, the table of contents lists,
+    /// like 2 to leave out the title.
+    ///
+    /// Default: 1
+    pub export_toc_min_level: Option<u8>,
+    /// The largest heading level, from 1 to 6, the table of contents lists.
+    ///
+    /// Default: 6
+    pub export_toc_max_level: Option<u8>,
+    /// Whether the metadata at the top of a document, between `---` or `+++`
+    /// lines, is shown in a card above it.
+    ///
+    /// Default: true
+    pub show_front_matter: Option<bool>,
+    /// Whether a bar below the document shows its word count, reading time,
+    /// the section being read and the zoom level.
+    ///
+    /// Default: true
+    pub show_status_bar: Option<bool>,
+    /// Whether the entries of the outline panel and the heading picker are
+    /// numbered by section, like "2.1" for the first heading under the
+    /// second top-level one.
+    ///
+    /// Default: false
+    pub outline_numbers: Option<bool>,
+    /// Whether a single line break in a paragraph breaks the line, the way
+    /// GitHub shows comments, instead of joining the lines with a space.
+    /// List items, tables and code blocks are unaffected.
+    ///
+    /// Default: false
+    pub hard_wrap: Option<bool>,
+    /// Whether the preview keeps still: scrolling to a heading or a search
+    /// match jumps there instead of gliding, and animated images show their
+    /// first frame with a button to play them. gpui doesn't tell apps about
+    /// the system's reduced motion preference, so this doesn't follow it.
+    ///
+    /// Default: false
+    pub reduce_motion: Option<bool>,
+    /// Whether words of paragraphs, headings and list items that aren't in
+    /// the dictionary are underlined with a squiggle. Code, links' URLs and
+    /// the front matter aren't checked. Right-clicking a word suggests how
+    /// to spell it, or adds it to the dictionary, which is saved next to the
+    /// settings file.
+    ///
+    /// Default: false
+    pub spellcheck: Option<bool>,
+    /// The language words are checked in, as the name of a Hunspell
+    /// dictionary installed on the system, like "en_US" or "de_DE". Without
+    /// one, English is checked against the system's word list.
+    ///
+    /// Default: "en_US"
+    pub spellcheck_language: Option<String>,
+    /// The locale that dates, numbers and yes or no in the front matter and
+    /// the status bar are written for, as a language tag like "en-GB" or
+    /// "de". English, German, French and Spanish are supported; other
+    /// languages are written as in US English.
+    ///
+    /// Default: the locale of the environment, from `LC_ALL`, `LC_TIME` or
+    /// `LANG`
+    pub locale: Option<String>,
+    /// How many megabytes of decoded images are kept in memory, for every
+    /// open document together. Past this, the images used least recently are
+    /// dropped, and loaded again if they're shown again.
+    ///
+    /// Default: 256
+    pub image_cache_size: Option<usize>,
+    /// How many seconds downloading an image from the web can take before
+    /// it's shown as failed.
+    ///
+    /// Default: 30
+    pub image_fetch_timeout: Option<u64>,
+    /// How many megabytes an image from the web can be. Larger downloads are
+    /// stopped once they pass this, and the image is shown as failed.
+    ///
+    /// Default: 50
+    pub max_image_download_size: Option<usize>,
+    /// How many images are downloaded from the web at once. The others wait
+    /// for their turn.
+    ///
+    /// Default: 6
+    pub max_concurrent_image_fetches: Option<usize>,
+    /// Whether SVGs under 64 pixels tall, like the badges at the top of
+    /// READMEs, are adapted to dark themes, where the dark text they're
+    /// usually drawn with can't be read. Black and white ones are inverted,
+    /// and the others get a light plate behind them. Other images are
+    /// always shown as they are.
+    ///
+    /// Default: false
+    pub adapt_images_to_theme: Option<bool>,
+    /// The font family of code blocks and inline code. Other text is in the
+    /// UI font.
+    ///
+    /// Default: the buffer font
+    pub code_font_family: Option<String>,
+    /// The size of the text of code blocks, in pixels. Inline code is the
+    /// size of the text around it.
+    ///
+    /// Default: the size of the document's text
+    pub code_font_size: Option<f32>,
+    /// The OpenType features of the code font, like `{"calt": false}` to turn
+    /// off ligatures.
+    ///
+    /// Default: the buffer font's
+    pub code_font_features: Option<FontFeatures>,
+}
+
+impl MarkdownPreviewSettings {
+    /// The locale of the `locale` setting, or the system's when it isn't set
+    /// or isn't one of the supported ones.
+    pub fn locale(&self) -> Locale {
+        self.locale
+            .as_deref()
+            .and_then(Locale::parse)
+            .unwrap_or_else(Locale::system)
+    }
+}
+
+impl Settings for MarkdownPreviewSettings {
+    const KEY: Option<&'static str> = Some("markdown_preview");
+
+    type FileContent = MarkdownPreviewSettingsContent;
+
+    fn load(sources: SettingsSources<Self::FileContent>, _: &mut AppContext) -> Result<Self> {
+        let mut settings = Self::default();
+        for content in sources.defaults_and_customizations() {
+            if let Some(text_scale) = content.text_scale {
+                settings.text_scale = text_scale;
+            }
+            if let Some(vim_scrolling) = content.vim_scrolling {
+                settings.vim_scrolling = vim_scrolling;
+            }
+            if let Some(print_link_urls) = content.print_link_urls {
+                settings.print_link_urls = print_link_urls;
+            }
+            if let Some(export_table_of_contents) = content.export_table_of_contents {
+                settings.export_table_of_contents = export_table_of_contents;
+            }
+            if let Some(export_toc_json) = content.export_toc_json {
+                settings.export_toc_json = export_toc_json;
+            }
+            if let Some(export_toc_min_level) = content.export_toc_min_level {
+                settings.export_toc_min_level = export_toc_min_level;
+            }
+            if let Some(export_toc_max_level) = content.export_toc_max_level {
+                settings.export_toc_max_level = export_toc_max_level;
+            }
+            if let Some(show_front_matter) = content.show_front_matter {
+                settings.show_front_matter = show_front_matter;
+            }
+            if let Some(show_status_bar) = content.show_status_bar {
+                settings.show_status_bar = show_status_bar;
+            }
+            if let Some(outline_numbers) = content.outline_numbers {
+                settings.outline_numbers = outline_numbers;
+            }
+            if let Some(hard_wrap) = content.hard_wrap {
+                settings.hard_wrap = hard_wrap;
+            }
+            if let Some(reduce_motion) = content.reduce_motion {
+                settings.reduce_motion = reduce_motion;
+            }
+            if let Some(spellcheck) = content.spellcheck {
+                settings.spellcheck = spellcheck;
+            }
+            if let Some(spellcheck_language) = &content.spellcheck_language {
+                settings.spellcheck_language = spellcheck_language.clone();
+            }
+            if let Some(locale) = &content.locale {
+                settings.locale = Some(locale.clone());
+            }
+            if let Some(image_cache_size) = content.image_cache_size {
+                settings.image_cache_size = image_cache_size;
+            }
+            if let Some(image_fetch_timeout) = content.image_fetch_timeout {
+                settings.image_fetch_timeout = image_fetch_timeout;
+            }
+            if let Some(max_image_download_size) = content.max_image_download_size {
+                settings.max_image_download_size = max_image_download_size;
+            }
+            if let Some(max_concurrent_image_fetches) = content.max_concurrent_image_fetches {
+                settings.max_concurrent_image_fetches = max_concurrent_image_fetches;
+            }
+            if let Some(adapt_images_to_theme) = content.adapt_images_to_theme {
+                settings.adapt_images_to_theme = adapt_images_to_theme;
+            }
+            if let Some(code_font_family) = &content.code_font_family {
+                settings.code_font_family = Some(code_font_family.clone());
+            }
+            if let Some(code_font_size) = content.code_font_size {
+                settings.code_font_size = Some(code_font_size);
+            }
+            if let Some(code_font_features) = &content.code_font_features {
+                settings.code_font_features = Some(code_font_features.clone());
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// The file the user settings are saved to when they're changed from the
+/// preview. Applications set this to the file they load the user settings
+/// from; without it, changes only last until the application quits.
+pub struct UserSettingsFile(pub PathBuf);
+
+impl Global for UserSettingsFile {}
+
+/// The settings the user settings are merged over: the defaults of the
+/// settings the preview shares with Zed, with fonts every platform has.
+pub fn default_settings() -> Result<String> {
+    let mut value: serde_json::Value = serde_json_lenient::from_str(DEFAULT_SETTINGS)
+        .context("failed to parse the default settings")?;
+
+    util::merge_non_null_json_value_into(built_in_fonts(), &mut value);
+    value
+        .as_object_mut()
+        .context("the default settings aren't an object")?
+        .remove("languages");
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// The least the settings store needs to set up the preview and its themes,
+/// for when the [`default_settings`] can't be loaded.
+pub fn fallback_settings() -> String {
+    let mut value = serde_json::json!({
+        "theme": {
+            "mode": "system",
+            "light": DEFAULT_LIGHT_THEME,
+            "dark": DEFAULT_DARK_THEME,
+        },
+        "ui_font_weight": 400,
+        "buffer_font_weight": 400,
+        "buffer_line_height": "comfortable",
+    });
+    util::merge_non_null_json_value_into(built_in_fonts(), &mut value);
+    value.to_string()
+}
+
+fn built_in_fonts() -> serde_json::Value {
+    serde_json::json!({
+        "ui_font_family": "Courier",
+        "ui_font_features": {},
+        "ui_font_size": 14,
+        "ui_font_fallback": [],
+        "buffer_font_family": "Courier",
+        "buffer_font_features": {},
+        "buffer_font_size": 14,
+        "buffer_font_fallback": [],
+    })
+}
+
+/// Changes the user settings with `update`, which is given them as JSON, and
+/// saves them to the [`UserSettingsFile`].
+pub fn update_user_settings(
+    cx: &mut AppContext,
+    update: impl FnOnce(&mut serde_json::Value),
+) -> Result<()> {
+    let mut settings = cx.global::<SettingsStore>().raw_user_settings().clone();
+    if !settings.is_object() {
+        settings = serde_json::json!({});
+    }
+    update(&mut settings);
+
+    let content = serde_json::to_string_pretty(&settings)?;
+    cx.update_global::<SettingsStore, _>(|store, cx| store.set_user_settings(&content, cx))?;
+
+    if let Some(UserSettingsFile(path)) = cx.try_global::<UserSettingsFile>() {
+        let path = path.clone();
+        cx.background_executor()
+            .spawn(async move {
+                if let Some(directory) = path.parent() {
+                    std::fs::create_dir_all(directory)?;
+                }
+                std::fs::write(&path, content)
+                    .with_context(|| format!("failed to write {}", path.display()))
+            })
+            .detach_and_log_err(cx);
+    }
+    Ok(())
+}
@@ -0,0 +1,429 @@
+use super::markdown_view::is_markdown_file;
+use collections::{FxHashMap, FxHashSet};
+use futures::{channel::mpsc, StreamExt as _};
+use gpui::{div, prelude::*, px, ClickEvent, EventEmitter, MouseButton, Pixels, Task, ViewContext};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use theme::ActiveTheme;
+use ui::{h_flex, v_flex, Color, Icon, IconName, IconSize, Label, LabelCommon, LabelSize};
+
+/// Changes on disk tend to arrive in bursts, like when a directory is copied
+/// in, which are coalesced into one read of each directory.
+const TREE_RELOAD_DEBOUNCE: Duration = Duration::from_millis(100);
+
+const SIDEBAR_WIDTH: Pixels = px(220.);
+
+/// A file or directory in the tree.
+#[derive(Debug, Clone, PartialEq)]
+struct TreeEntry {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SidebarEvent {
+    /// A file was clicked, with the secondary modifier or the middle button
+    /// if `new_tab` is set.
+    OpenFile { path: PathBuf, new_tab: bool },
+}
+
+/// A tree of the Markdown files in a directory.
+///
+/// Directories are only read once they're expanded, so that huge ones open
+/// right away, and the directories that were read are read again when
+/// their files change on disk.
+pub struct MarkdownSidebar {
+    root: PathBuf,
+    /// The entries of every directory that has been read, directories first.
+    directories: FxHashMap<PathBuf, Vec<TreeEntry>>,
+    expanded: FxHashSet<PathBuf>,
+    /// The active file, as it was given and as it's found in the tree.
+    active_path: Option<(PathBuf, Option<PathBuf>)>,
+    watcher: Option<RecommendedWatcher>,
+    _reload_task: Task<()>,
+}
+
+impl EventEmitter<SidebarEvent> for MarkdownSidebar {}
+
+impl MarkdownSidebar {
+    pub fn new(root: PathBuf, cx: &mut ViewContext<Self>) -> Self {
+        let root = root.canonicalize().unwrap_or(root);
+        let (changes_tx, mut changes_rx) = mpsc::unbounded();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                for path in event.paths {
+                    changes_tx.unbounded_send(path).ok();
+                }
+            }
+        });
+        let watcher = match watcher {
+            Ok(watcher) => Some(watcher),
+            Err(error) => {
+                log::error!("failed to watch {}: {}", root.display(), error);
+                None
+            }
+        };
+
+        let reload_task = cx.spawn(|this, mut cx| async move {
+            while let Some(path) = changes_rx.next().await {
+                cx.background_executor().timer(TREE_RELOAD_DEBOUNCE).await;
+                let mut changed = vec![path];
+                while let Ok(Some(path)) = changes_rx.try_next() {
+                    changed.push(path);
+                }
+
+                let reloaded = this.update(&mut cx, |this, cx| {
+                    let mut directories: Vec<PathBuf> = changed
+                        .iter()
+                        .filter_map(|path| path.parent())
+                        .filter(|directory| this.directories.contains_key(*directory))
+                        .map(Path::to_path_buf)
+                        .collect();
+                    directories.sort();
+                    directories.dedup();
+                    for directory in directories {
+                        this.read_directory(directory, cx);
+                    }
+                });
+                if reloaded.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut this = Self {
+            root: root.clone(),
+            directories: FxHashMap::default(),
+            expanded: FxHashSet::default(),
+            active_path: None,
+            watcher,
+            _reload_task: reload_task,
+        };
+        this.expanded.insert(root.clone());
+        this.read_directory(root, cx);
+        this
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Highlights the file at `path`, expanding the directories it's in.
+    pub fn set_active_path(&mut self, path: Option<PathBuf>, cx: &mut ViewContext<Self>) {
+        if self.active_path.as_ref().map(|(given, _)| given) == path.as_ref() {
+            return;
+        }
+
+        let found = path
+            .as_deref()
+            .map(|path| path.canonicalize().unwrap_or(path.to_path_buf()))
+            .filter(|path| path.starts_with(&self.root));
+        if let Some(found) = &found {
+            for directory in found.ancestors().skip(1) {
+                if !directory.starts_with(&self.root) {
+                    break;
+                }
+                if self.expanded.insert(directory.to_path_buf()) {
+                    self.read_directory(directory.to_path_buf(), cx);
+                }
+            }
+        }
+        self.active_path = path.map(|path| (path, found));
+        cx.notify();
+    }
+
+    fn toggle_directory(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
+        if !self.expanded.remove(&path) {
+            self.expanded.insert(path.clone());
+            if !self.directories.contains_key(&path) {
+                self.read_directory(path, cx);
+            }
+        }
+        cx.notify();
+    }
+
+    /// Reads the entries of `directory` in the background, watching it for
+    /// changes from then on.
+    fn read_directory(&mut self, directory: PathBuf, cx: &mut ViewContext<Self>) {
+        let read = cx.background_executor().spawn({
+            let directory = directory.clone();
+            async move { read_tree_entries(&directory) }
+        });
+        cx.spawn(|this, mut cx| async move {
+            let entries = read.await;
+            this.update(&mut cx, |this, cx| {
+                match entries {
+                    Ok(entries) => {
+                        if !this.directories.contains_key(&directory) {
+                            if let Some(watcher) = &mut this.watcher {
+                                if let Err(error) =
+                                    watcher.watch(&directory, RecursiveMode::NonRecursive)
+                                {
+                                    log::error!(
+                                        "failed to watch {}: {}",
+                                        directory.display(),
+                                        error
+                                    );
+                                }
+                            }
+                        }
+                        this.directories.insert(directory, entries);
+                    }
+                    // A directory that was removed is left out of its
+                    // parent's entries when that's read again.
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                        this.directories.remove(&directory);
+                    }
+                    Err(error) => {
+                        log::error!("failed to read {}: {}", directory.display(), error);
+                    }
+                }
+                cx.notify();
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// The entries that are shown, in order, with how deeply each is nested.
+    fn visible_entries(&self) -> Vec<(usize, &TreeEntry)> {
+        fn push<'a>(
+            sidebar: &'a MarkdownSidebar,
+            directory: &Path,
+            depth: usize,
+            entries: &mut Vec<(usize, &'a TreeEntry)>,
+        ) {
+            let Some(children) = sidebar.directories.get(directory) else {
+                return;
+            };
+            for child in children {
+                entries.push((depth, child));
+                if child.is_dir && sidebar.expanded.contains(&child.path) {
+                    push(sidebar, &child.path, depth + 1, entries);
+                }
+            }
+        }
+
+        let mut entries = Vec::new();
+        push(self, &self.root, 0, &mut entries);
+        entries
+    }
+
+    fn open_file(&mut self, path: PathBuf, new_tab: bool, cx: &mut ViewContext<Self>) {
+        cx.emit(SidebarEvent::OpenFile { path, new_tab });
+    }
+}
+
+/// Reads the Markdown files and the directories in `directory`, leaving out
+/// hidden ones, sorted by name with directories first.
+fn read_tree_entries(directory: &Path) -> io::Result<Vec<TreeEntry>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        // Symbolic links count as what they point to.
+        let is_dir = path.is_dir();
+        if is_dir || is_markdown_file(&path) {
+            entries.push(TreeEntry { path, name, is_dir });
+        }
+    }
+    entries.sort_by_cached_key(|entry| (!entry.is_dir, entry.name.to_lowercase()));
+    Ok(entries)
+}
+
+impl Render for MarkdownSidebar {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let colors = cx.theme().colors();
+        let active = self
+            .active_path
+            .as_ref()
+            .and_then(|(_, found)| found.clone());
+        let root_name = self.root.file_name().map_or_else(
+            || self.root.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let secondary = |event: &ClickEvent| {
+            if cfg!(target_os = "macos") {
+                event.down.modifiers.platform
+            } else {
+                event.down.modifiers.control
+            }
+        };
+
+        let entries = self
+            .visible_entries()
+            .into_iter()
+            .enumerate()
+            .map(|(ix, (depth, entry))| {
+                let is_active = active.as_ref() == Some(&entry.path);
+                let icon = match (entry.is_dir, self.expanded.contains(&entry.path)) {
+                    (true, true) => IconName::FolderOpen,
+                    (true, false) => IconName::Folder,
+                    (false, _) => IconName::File,
+                };
+                let path = entry.path.clone();
+                let is_dir = entry.is_dir;
+                h_flex()
+                    .id(("markdown-sidebar-entry", ix))
+                    .w_full()
+                    .gap_1()
+                    .py_0p5()
+                    .pr_2()
+                    .pl(px(8.) + px(12.) * depth as f32)
+                    .when(is_active, |row| row.bg(colors.element_selected))
+                    .hover(|style| style.bg(colors.element_hover))
+                    .cursor_pointer()
+                    .child(Icon::new(icon).size(IconSize::Small).color(Color::Muted))
+                    .child(
+                        Label::new(entry.name.clone())
+                            .size(LabelSize::Small)
+                            .color(if is_active {
+                                Color::Default
+                            } else {
+                                Color::Muted
+                            })
+                            .single_line(),
+                    )
+                    .on_click(cx.listener({
+                        let path = path.clone();
+                        move |this, event, cx| {
+                            if is_dir {
+                                this.toggle_directory(path.clone(), cx);
+                            } else {
+                                this.open_file(path.clone(), secondary(event), cx);
+                            }
+                        }
+                    }))
+                    .when(!is_dir, |row| {
+                        row.on_mouse_down(
+                            MouseButton::Middle,
+                            cx.listener(move |this, _, cx| this.open_file(path.clone(), true, cx)),
+                        )
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        v_flex()
+            .id("markdown-sidebar")
+            .flex_none()
+            .w(SIDEBAR_WIDTH)
+            .h_full()
+            .py_2()
+            .overflow_y_scroll()
+            .border_r_1()
+            .border_color(colors.border)
+            .bg(colors.panel_background)
+            .child(
+                div().px_3().pb_1().child(
+                    Label::new(root_name)
+                        .size(LabelSize::Small)
+                        .color(Color::Muted)
+                        .single_line(),
+                ),
+            )
+            .when(entries.is_empty(), |panel| {
+                panel.child(
+                    div().px_3().child(
+                        Label::new("No Markdown files")
+                            .size(LabelSize::Small)
+                            .color(Color::Muted),
+                    ),
+                )
+            })
+            .children(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+    use settings::SettingsStore;
+    use theme::LoadThemes;
+
+    #[gpui::test]
+    async fn test_sidebar_tree(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+
+        let directory =
+            std::env::temp_dir().join(format!("markdown-sidebar-{}", std::process::id()));
+        for subdirectory in ["docs/deep", ".git"] {
+            std::fs::create_dir_all(directory.join(subdirectory)).unwrap();
+        }
+        for file in [
+            "README.md",
+            "notes.txt",
+            "docs/guide.md",
+            "docs/deep/faq.md",
+            ".git/x.md",
+        ] {
+            std::fs::write(directory.join(file), "").unwrap();
+        }
+
+        let (sidebar, cx) = cx.add_window_view(|cx| MarkdownSidebar::new(directory.clone(), cx));
+        cx.run_until_parked();
+        let visible = |cx: &mut gpui::VisualTestContext| {
+            sidebar.update(cx, |sidebar, _| {
+                sidebar
+                    .visible_entries()
+                    .into_iter()
+                    .map(|(depth, entry)| (depth, entry.name.clone()))
+                    .collect::<Vec<_>>()
+            })
+        };
+        // Directories aren't read until they're expanded.
+        assert_eq!(
+            visible(cx),
+            vec![(0, "docs".to_string()), (0, "README.md".to_string())]
+        );
+        sidebar.update(cx, |sidebar, _| assert_eq!(sidebar.directories.len(), 1));
+
+        let root = sidebar.update(cx, |sidebar, _| sidebar.root().to_path_buf());
+        sidebar.update(cx, |sidebar, cx| {
+            sidebar.toggle_directory(root.join("docs"), cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            visible(cx),
+            vec![
+                (0, "docs".to_string()),
+                (1, "deep".to_string()),
+                (1, "guide.md".to_string()),
+                (0, "README.md".to_string()),
+            ]
+        );
+
+        // The active file is revealed.
+        sidebar.update(cx, |sidebar, cx| {
+            sidebar.set_active_path(Some(directory.join("docs/deep/faq.md")), cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(visible(cx)[2], (2, "faq.md".to_string()));
+        sidebar.update(cx, |sidebar, _| {
+            assert_eq!(
+                sidebar
+                    .active_path
+                    .as_ref()
+                    .and_then(|(_, found)| found.clone()),
+                Some(root.join("docs/deep/faq.md"))
+            );
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
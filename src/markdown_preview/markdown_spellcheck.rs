@@ -0,0 +1,709 @@
+use super::{
+    markdown_elements::{ParsedMarkdownElement, ParsedMarkdownText},
+    markdown_settings::UserSettingsFile,
+};
+use anyhow::{anyhow, Context as _, Result};
+use collections::{FxHashMap, FxHashSet};
+use gpui::{AppContext, Global, SharedString};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// How many suggestions are offered for a misspelled word.
+const MAX_SUGGESTIONS: usize = 5;
+/// How many edits a suggestion can be away from the misspelled word.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+/// The file the words added to the dictionary are kept in, next to the
+/// [`UserSettingsFile`].
+const ADDED_WORDS_FILE_NAME: &str = "dictionary.txt";
+
+/// The words of a language that are spelled right, from a Hunspell
+/// dictionary installed on the system.
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    /// Every form of every word, lowercased.
+    words: FxHashSet<String>,
+}
+
+impl Dictionary {
+    /// A dictionary of exactly `words`.
+    pub fn new<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            words: words
+                .into_iter()
+                .map(|word| normalize(word.as_ref()))
+                .collect(),
+        }
+    }
+
+    /// Reads the dictionary of `language`, a tag like "en_US" or "de-DE",
+    /// from where Hunspell dictionaries are installed. English falls back
+    /// to the system's word list.
+    pub fn load(language: &str) -> Result<Self> {
+        let language = language.replace('-', "_");
+        for directory in dictionary_directories() {
+            let Some(dic_path) = find_dictionary(&directory, &language) else {
+                continue;
+            };
+            let dic = read_latin1_or_utf8(&dic_path)?;
+            let aff = read_latin1_or_utf8(&dic_path.with_extension("aff")).ok();
+            log::info!("spellchecking with {}", dic_path.display());
+            return Ok(Self::from_hunspell(&dic, aff.as_deref()));
+        }
+        if language == "en" || language.starts_with("en_") {
+            let words_path = Path::new("/usr/share/dict/words");
+            if let Ok(words) = std::fs::read_to_string(words_path) {
+                log::info!("spellchecking with {}", words_path.display());
+                return Ok(Self::new(words.lines()));
+            }
+        }
+        Err(anyhow!("no dictionary for {language} is installed"))
+    }
+
+    /// Reads a Hunspell dictionary: the stems of its `.dic` file, along with
+    /// the forms its `.aff` file's prefixes and suffixes make of them.
+    pub fn from_hunspell(dic: &str, aff: Option<&str>) -> Self {
+        let affixes = aff.map(Affixes::parse).unwrap_or_default();
+        let mut words = FxHashSet::default();
+        // The first line is how many words there are.
+        for line in dic.lines().skip(1) {
+            let entry = line.split(['\t', ' ']).next().unwrap_or_default();
+            let (stem, flags) = match entry.split_once('/') {
+                Some((stem, flags)) => (stem, affixes.flags(flags)),
+                None => (entry, Vec::new()),
+            };
+            if stem.is_empty() {
+                continue;
+            }
+            affixes.expand(stem, &flags, |word| {
+                words.insert(normalize(&word));
+            });
+        }
+        Self { words }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Whether `word` is spelled right, in any case. Possessives are
+    /// spelled right when the word they're of is.
+    pub fn contains(&self, word: &str) -> bool {
+        let word = normalize(word);
+        self.words.contains(&word)
+            || word
+                .strip_suffix("'s")
+                .is_some_and(|stem| self.words.contains(stem))
+    }
+
+    /// The words that are closest to `word`, closest first, in its case.
+    pub fn suggestions(&self, word: &str) -> Vec<String> {
+        let normalized = normalize(word);
+        let target: Vec<char> = normalized.chars().collect();
+        let mut candidates: Vec<(usize, &str)> = self
+            .words
+            .iter()
+            .filter(|candidate| {
+                candidate.chars().count().abs_diff(target.len()) <= MAX_SUGGESTION_DISTANCE
+            })
+            .filter_map(|candidate| {
+                let distance = edit_distance(&target, candidate, MAX_SUGGESTION_DISTANCE)?;
+                Some((distance, candidate.as_str()))
+            })
+            .collect();
+        candidates.sort_unstable();
+
+        let capitalized = word.chars().next().is_some_and(char::is_uppercase);
+        candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| {
+                if capitalized {
+                    capitalize(candidate)
+                } else {
+                    candidate.to_string()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Words are looked up lowercased, with curly apostrophes made straight.
+fn normalize(word: &str) -> String {
+    word.to_lowercase().replace('’', "'")
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+/// The number of insertions, deletions, substitutions and swaps of
+/// neighboring characters that turn `a` into `b`, if it's at most `max`.
+fn edit_distance(a: &[char], b: &str, max: usize) -> Option<usize> {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous_previous: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(previous_previous[j - 2] + 1);
+            }
+        }
+        // Every later row is at least the smallest of this one.
+        if current.iter().min().is_some_and(|&min| min > max) {
+            return None;
+        }
+        previous_previous = std::mem::replace(&mut previous, current);
+    }
+    Some(previous[b.len()]).filter(|&distance| distance <= max)
+}
+
+/// Where Hunspell dictionaries are installed, starting with the directories
+/// in `DICPATH`, like Hunspell does.
+fn dictionary_directories() -> Vec<PathBuf> {
+    let mut directories: Vec<PathBuf> = std::env::var_os("DICPATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    directories.extend(
+        [
+            "/usr/share/hunspell",
+            "/usr/share/myspell",
+            "/usr/share/myspell/dicts",
+            "/usr/local/share/hunspell",
+            "/Library/Spelling",
+        ]
+        .map(PathBuf::from),
+    );
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        directories.push(home.join("Library/Spelling"));
+        directories.push(home.join(".local/share/hunspell"));
+    }
+    directories
+}
+
+/// The `.dic` file of `language` in `directory`, or for a language like
+/// "en", of the first country it's spoken in there.
+fn find_dictionary(directory: &Path, language: &str) -> Option<PathBuf> {
+    let exact = directory.join(format!("{language}.dic"));
+    if exact.is_file() {
+        return Some(exact);
+    }
+    if language.contains('_') {
+        return None;
+    }
+    let mut regional: Vec<PathBuf> = std::fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|extension| extension == "dic")
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|stem| stem.starts_with(&format!("{language}_")))
+        })
+        .collect();
+    regional.sort();
+    regional.into_iter().next()
+}
+
+/// Reads `path` as UTF-8, or as Latin-1, which older dictionaries are in.
+fn read_latin1_or_utf8(path: &Path) -> Result<String> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(String::from_utf8(bytes)
+        .unwrap_or_else(|error| error.into_bytes().into_iter().map(char::from).collect()))
+}
+
+/// How the flags after a word's `/` are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum FlagFormat {
+    /// Each character is a flag.
+    #[default]
+    Char,
+    /// Each two characters are a flag.
+    Long,
+    /// Flags are numbers, separated by commas.
+    Number,
+}
+
+/// A prefix or suffix rule of an `.aff` file.
+#[derive(Debug)]
+struct Affix {
+    strip: String,
+    add: String,
+    /// What the end of the word has to be like for suffixes, or its start
+    /// for prefixes, one entry a character.
+    condition: Vec<CharClass>,
+}
+
+#[derive(Debug)]
+enum CharClass {
+    Any,
+    Set { chars: Vec<char>, negated: bool },
+}
+
+impl CharClass {
+    fn matches(&self, ch: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Set { chars, negated } => chars.contains(&ch) != *negated,
+        }
+    }
+}
+
+/// The prefixes and suffixes of an `.aff` file, by their flag, along with
+/// whether they combine with affixes of the other kind.
+#[derive(Debug, Default)]
+struct Affixes {
+    flag_format: FlagFormat,
+    prefixes: FxHashMap<String, (bool, Vec<Affix>)>,
+    suffixes: FxHashMap<String, (bool, Vec<Affix>)>,
+}
+
+impl Affixes {
+    fn parse(aff: &str) -> Self {
+        let mut affixes = Self::default();
+        for line in aff.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FLAG", "long", ..] => affixes.flag_format = FlagFormat::Long,
+                ["FLAG", "num", ..] => affixes.flag_format = FlagFormat::Number,
+                [kind @ ("PFX" | "SFX"), flag, cross, count] if count.parse::<usize>().is_ok() => {
+                    let rules = if *kind == "PFX" {
+                        &mut affixes.prefixes
+                    } else {
+                        &mut affixes.suffixes
+                    };
+                    rules.insert(flag.to_string(), (*cross == "Y", Vec::new()));
+                }
+                [kind @ ("PFX" | "SFX"), flag, strip, add, rest @ ..] => {
+                    let rules = if *kind == "PFX" {
+                        &mut affixes.prefixes
+                    } else {
+                        &mut affixes.suffixes
+                    };
+                    let Some((_, rules)) = rules.get_mut(*flag) else {
+                        continue;
+                    };
+                    let unless_zero = |text: &str| {
+                        if text == "0" {
+                            String::new()
+                        } else {
+                            text.to_string()
+                        }
+                    };
+                    // What's after a `/` are flags for the affixed word,
+                    // which aren't followed.
+                    let add = add.split('/').next().unwrap_or_default();
+                    rules.push(Affix {
+                        strip: unless_zero(*strip),
+                        add: unless_zero(add),
+                        condition: parse_condition(rest.first().copied().unwrap_or(".")),
+                    });
+                }
+                _ => {}
+            }
+        }
+        affixes
+    }
+
+    fn flags(&self, flags: &str) -> Vec<String> {
+        match self.flag_format {
+            FlagFormat::Char => flags.chars().map(String::from).collect(),
+            FlagFormat::Long => {
+                let chars: Vec<char> = flags.chars().collect();
+                chars.chunks(2).map(|pair| pair.iter().collect()).collect()
+            }
+            FlagFormat::Number => flags.split(',').map(str::to_string).collect(),
+        }
+    }
+
+    /// Calls `f` with `stem` and each form its `flags` make of it.
+    fn expand(&self, stem: &str, flags: &[String], mut f: impl FnMut(String)) {
+        f(stem.to_string());
+        let mut suffixed = Vec::new();
+        for flag in flags {
+            let Some((cross, rules)) = self.suffixes.get(flag) else {
+                continue;
+            };
+            for rule in rules {
+                if let Some(word) = apply_suffix(stem, rule) {
+                    if *cross {
+                        suffixed.push(word.clone());
+                    }
+                    f(word);
+                }
+            }
+        }
+        for flag in flags {
+            let Some((cross, rules)) = self.prefixes.get(flag) else {
+                continue;
+            };
+            for rule in rules {
+                if let Some(word) = apply_prefix(stem, rule) {
+                    f(word);
+                }
+                if *cross {
+                    for word in &suffixed {
+                        if let Some(word) = apply_prefix(word, rule) {
+                            f(word);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_condition(condition: &str) -> Vec<CharClass> {
+    let mut classes = Vec::new();
+    let mut chars = condition.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '.' => classes.push(CharClass::Any),
+            '[' => {
+                let mut set = Vec::new();
+                let mut negated = false;
+                for ch in chars.by_ref() {
+                    match ch {
+                        ']' => break,
+                        '^' if set.is_empty() && !negated => negated = true,
+                        ch => set.push(ch),
+                    }
+                }
+                classes.push(CharClass::Set {
+                    chars: set,
+                    negated,
+                });
+            }
+            ch => classes.push(CharClass::Set {
+                chars: vec![ch],
+                negated: false,
+            }),
+        }
+    }
+    classes
+}
+
+fn apply_suffix(stem: &str, rule: &Affix) -> Option<String> {
+    let chars: Vec<char> = stem.chars().collect();
+    let tail = chars.get(chars.len().checked_sub(rule.condition.len())?..)?;
+    if !tail
+        .iter()
+        .zip(&rule.condition)
+        .all(|(ch, class)| class.matches(*ch))
+    {
+        return None;
+    }
+    let base = stem.strip_suffix(rule.strip.as_str())?;
+    Some(format!("{base}{}", rule.add))
+}
+
+fn apply_prefix(stem: &str, rule: &Affix) -> Option<String> {
+    let chars: Vec<char> = stem.chars().collect();
+    let head = chars.get(..rule.condition.len())?;
+    if !head
+        .iter()
+        .zip(&rule.condition)
+        .all(|(ch, class)| class.matches(*ch))
+    {
+        return None;
+    }
+    let base = stem.strip_prefix(rule.strip.as_str())?;
+    Some(format!("{}{base}", rule.add))
+}
+
+/// A word that isn't in the dictionary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Misspelling {
+    /// Where the text the word is in starts in the source, from the start
+    /// of its top-level block, so that blocks that only moved keep theirs.
+    pub text_offset: usize,
+    /// The word's range in the text's contents.
+    pub range: Range<usize>,
+    pub word: SharedString,
+}
+
+/// Finds the misspelled words in the paragraphs, headings and list items of
+/// `block`. Code, URLs and email addresses aren't checked.
+pub fn check_block(block: &ParsedMarkdownElement, dictionary: &Dictionary) -> Vec<Misspelling> {
+    let mut misspellings = Vec::new();
+    let block_start = block.source_range().start;
+    check_element(block, block_start, dictionary, &mut misspellings);
+    misspellings
+}
+
+fn check_element(
+    element: &ParsedMarkdownElement,
+    block_start: usize,
+    dictionary: &Dictionary,
+    misspellings: &mut Vec<Misspelling>,
+) {
+    match element {
+        ParsedMarkdownElement::Paragraph(text) => {
+            check_text(text, block_start, dictionary, misspellings)
+        }
+        ParsedMarkdownElement::Heading(heading) => {
+            check_text(&heading.contents, block_start, dictionary, misspellings)
+        }
+        ParsedMarkdownElement::ListItem(item) => {
+            for child in &item.content {
+                check_element(child, block_start, dictionary, misspellings);
+            }
+        }
+        ParsedMarkdownElement::BlockQuote(block_quote) => {
+            for child in &block_quote.children {
+                check_element(child, block_start, dictionary, misspellings);
+            }
+        }
+        ParsedMarkdownElement::Table(_)
+        | ParsedMarkdownElement::CodeBlock(_)
+        | ParsedMarkdownElement::Image(_)
+        | ParsedMarkdownElement::HorizontalRule(_) => {}
+    }
+}
+
+fn check_text(
+    text: &ParsedMarkdownText,
+    block_start: usize,
+    dictionary: &Dictionary,
+    misspellings: &mut Vec<Misspelling>,
+) {
+    let code_ranges: Vec<&Range<usize>> = text
+        .regions
+        .iter()
+        .zip(&text.region_ranges)
+        .filter(|(region, _)| region.code)
+        .map(|(_, range)| range)
+        .collect();
+    for range in words_to_check(&text.contents) {
+        let in_code = code_ranges
+            .iter()
+            .any(|code| code.start < range.end && range.start < code.end);
+        let word = &text.contents[range.clone()];
+        if !in_code && !dictionary.contains(word) {
+            misspellings.push(Misspelling {
+                text_offset: text.source_range.start.saturating_sub(block_start),
+                range,
+                word: word.to_string().into(),
+            });
+        }
+    }
+}
+
+/// The words of `text` worth checking: runs of letters, with apostrophes
+/// inside them, that aren't part of a URL, an email address or an
+/// identifier. Single letters, acronyms and words with capitals inside them,
+/// like names of products, aren't either.
+fn words_to_check(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    for chunk in text.split(char::is_whitespace) {
+        let start = chunk.as_ptr() as usize - text.as_ptr() as usize;
+        if chunk.contains("://") || chunk.starts_with("www.") || chunk.contains('@') {
+            continue;
+        }
+
+        let chars: Vec<(usize, char)> = chunk.char_indices().collect();
+        let mut ix = 0;
+        while ix < chars.len() {
+            if !chars[ix].1.is_alphabetic() {
+                ix += 1;
+                continue;
+            }
+            let word_start = ix;
+            while ix < chars.len()
+                && (chars[ix].1.is_alphabetic()
+                    || (matches!(chars[ix].1, '\'' | '’')
+                        && chars.get(ix + 1).is_some_and(|(_, ch)| ch.is_alphabetic())))
+            {
+                ix += 1;
+            }
+            let touches_identifier = |ch: Option<&(usize, char)>| {
+                ch.is_some_and(|(_, ch)| ch.is_alphanumeric() || *ch == '_')
+            };
+            if touches_identifier(word_start.checked_sub(1).and_then(|ix| chars.get(ix)))
+                || touches_identifier(chars.get(ix))
+            {
+                continue;
+            }
+
+            let byte_start = chars[word_start].0;
+            let byte_end = chars.get(ix).map_or(chunk.len(), |(offset, _)| *offset);
+            let word = &chunk[byte_start..byte_end];
+            let has_inner_capital = word.chars().skip(1).any(char::is_uppercase);
+            if word.chars().count() > 1 && !has_inner_capital {
+                words.push(start + byte_start..start + byte_end);
+            }
+        }
+    }
+    words
+}
+
+/// The misspelled words to underline, by the text they're in, as the
+/// renderer looks them up.
+#[derive(Debug, Default)]
+pub struct Misspellings {
+    texts: FxHashMap<usize, Vec<(Range<usize>, SharedString)>>,
+}
+
+impl Misspellings {
+    /// Underlines `misspellings`, of the block starting at `block_start` in
+    /// the source.
+    pub fn new(block_start: usize, misspellings: &[Misspelling]) -> Self {
+        let mut texts: FxHashMap<usize, Vec<(Range<usize>, SharedString)>> = FxHashMap::default();
+        for misspelling in misspellings {
+            texts
+                .entry(block_start + misspelling.text_offset)
+                .or_default()
+                .push((misspelling.range.clone(), misspelling.word.clone()));
+        }
+        Self { texts }
+    }
+
+    /// The misspelled words in the text starting at `text_start` in the
+    /// source, by their range in its contents.
+    pub fn for_text(&self, text_start: usize) -> &[(Range<usize>, SharedString)] {
+        self.texts.get(&text_start).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// The dictionaries loaded so far, shared by every view, and the words the
+/// user added to them, which are spelled right in every language.
+#[derive(Default)]
+pub struct Spellchecker {
+    dictionaries: FxHashMap<String, Arc<Dictionary>>,
+    added_words: Option<Arc<FxHashSet<String>>>,
+}
+
+impl Global for Spellchecker {}
+
+impl Spellchecker {
+    pub fn dictionary(language: &str, cx: &AppContext) -> Option<Arc<Dictionary>> {
+        cx.try_global::<Self>()?.dictionaries.get(language).cloned()
+    }
+
+    pub fn insert_dictionary(language: String, dictionary: Arc<Dictionary>, cx: &mut AppContext) {
+        cx.default_global::<Self>()
+            .dictionaries
+            .insert(language, dictionary);
+    }
+
+    /// The words the user added, read from the settings directory the first
+    /// time they're needed.
+    pub fn added_words(cx: &mut AppContext) -> Arc<FxHashSet<String>> {
+        let path = added_words_path(cx);
+        cx.default_global::<Self>()
+            .added_words
+            .get_or_insert_with(|| {
+                let words = path
+                    .and_then(|path| std::fs::read_to_string(path).ok())
+                    .unwrap_or_default();
+                Arc::new(words.lines().map(normalize).collect())
+            })
+            .clone()
+    }
+
+    /// Whether `word` is one the user added.
+    pub fn is_added(word: &str, cx: &AppContext) -> bool {
+        cx.try_global::<Self>()
+            .and_then(|spellchecker| spellchecker.added_words.as_ref())
+            .is_some_and(|words| words.contains(&normalize(word)))
+    }
+
+    /// Adds `word` to the dictionary, saving it with the settings, and
+    /// redraws the windows so that it's no longer underlined.
+    pub fn add_word(word: &str, cx: &mut AppContext) {
+        let mut words = Self::added_words(cx);
+        Arc::make_mut(&mut words).insert(normalize(word));
+        cx.default_global::<Self>().added_words = Some(words.clone());
+        cx.refresh();
+
+        let Some(path) = added_words_path(cx) else {
+            return;
+        };
+        cx.background_executor()
+            .spawn(async move {
+                let mut words: Vec<&String> = words.iter().collect();
+                words.sort();
+                let mut content = String::new();
+                for word in words {
+                    content.push_str(word);
+                    content.push('\n');
+                }
+                if let Some(directory) = path.parent() {
+                    std::fs::create_dir_all(directory)?;
+                }
+                std::fs::write(&path, content)
+                    .with_context(|| format!("failed to write {}", path.display()))
+            })
+            .detach_and_log_err(cx);
+    }
+}
+
+fn added_words_path(cx: &AppContext) -> Option<PathBuf> {
+    let UserSettingsFile(settings_path) = cx.try_global::<UserSettingsFile>()?;
+    Some(settings_path.parent()?.join(ADDED_WORDS_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_hunspell_affixes() {
+        let aff = "SET UTF-8\nSFX S Y 2\nSFX S y ies [^aeiou]y\nSFX S 0 s [^y]\nPFX U Y 1\nPFX U 0 un .\n";
+        let dic = "3\ncity/S\nwalk/SU\nfriendly\n";
+        let dictionary = Dictionary::from_hunspell(dic, Some(aff));
+        for word in ["city", "cities", "walks", "unwalk", "unwalks", "Friendly"] {
+            assert!(dictionary.contains(word), "{word}");
+        }
+        for word in ["citys", "unfriendly", "walkies"] {
+            assert!(!dictionary.contains(word), "{word}");
+        }
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let dictionary = Dictionary::new(["receive", "recipe", "relieve", "the"]);
+        assert_eq!(
+            dictionary.suggestions("recieve"),
+            vec!["receive", "relieve", "recipe"]
+        );
+        assert_eq!(dictionary.suggestions("Teh"), vec!["The"]);
+    }
+
+    #[gpui::test]
+    async fn test_check_block() {
+        let dictionary = Dictionary::new(["the", "is", "see", "and", "or", "don't"]);
+        let text = "# The wrod\n\n- The `wrod` is hre, see https://exampel.com and me@exampel.com\n- Don't see NASA or GitHub or v2beta\n\n```\nwrod\n```\n";
+        let parsed = parse_markdown(text, None, None).await;
+        let words: Vec<Vec<String>> = parsed
+            .children
+            .iter()
+            .map(|block| {
+                check_block(block, &dictionary)
+                    .into_iter()
+                    .map(|misspelling| misspelling.word.to_string())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(words, vec![vec!["wrod"], vec!["hre"], vec![], vec![]]);
+    }
+}
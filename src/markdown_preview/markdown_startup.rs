@@ -0,0 +1,180 @@
+use super::{
+    init,
+    markdown_settings::{default_settings, fallback_settings, MarkdownPreviewSettings},
+};
+use anyhow::Result;
+use gpui::AppContext;
+use settings::{Settings as _, SettingsStore};
+use theme::{LoadThemes, SystemAppearance, ThemeRegistry, ThemeSettings};
+use util::ResultExt as _;
+
+/// Sets up the settings, with `user_settings` over the defaults, the themes
+/// and then [`init`], the way an application with a settings file does,
+/// without failing over any of them.
+///
+/// What can't be loaded falls back to what's built in: the default settings
+/// to the least the preview needs, invalid user settings to none, a theme
+/// that isn't installed to the base dark theme, and a font that isn't to the
+/// system's. Returns what fell back and why, for the first window to tell
+/// the reader.
+pub fn init_with_fallbacks(user_settings: &str, cx: &mut AppContext) -> Vec<String> {
+    init_with_defaults(default_settings(), user_settings, cx)
+}
+
+/// [`init_with_fallbacks`] with `defaults` as the default settings, or why
+/// they couldn't be loaded.
+fn init_with_defaults(
+    defaults: Result<String>,
+    user_settings: &str,
+    cx: &mut AppContext,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let mut store = SettingsStore::new(cx);
+    if let Err(error) = defaults.and_then(|defaults| store.set_default_settings(&defaults, cx)) {
+        log::error!(fallback = "built-in"; "failed to load the default settings: {:#}", error);
+        warnings.push(format!(
+            "The default settings couldn't be loaded, so built-in ones are used: {:#}",
+            error
+        ));
+        store
+            .set_default_settings(&fallback_settings(), cx)
+            .log_err();
+    }
+    if let Err(error) = store.set_user_settings(user_settings, cx) {
+        log::error!(fallback = "defaults"; "failed to load the user settings: {:#}", error);
+        warnings.push(format!(
+            "Your settings couldn't be loaded, so the defaults are used: {:#}",
+            error
+        ));
+        store.set_user_settings("{}", cx).log_err();
+    }
+    cx.set_global(store);
+
+    SystemAppearance::init(cx);
+    theme::init(LoadThemes::JustBase, cx);
+    init(cx);
+
+    warnings.extend(missing_theme(cx));
+    warnings.extend(missing_fonts(cx));
+    warnings
+}
+
+/// Why the configured theme isn't the one in use, if it isn't installed.
+/// [`ThemeSettings`] uses the base dark theme in its place.
+fn missing_theme(cx: &AppContext) -> Option<String> {
+    let settings = ThemeSettings::get_global(cx);
+    let appearance = SystemAppearance::global(cx).0;
+    let name = settings.theme_selection.as_ref()?.theme(appearance);
+    if ThemeRegistry::global(cx).get(name).is_ok() {
+        return None;
+    }
+    log::warn!(fallback = &*settings.active_theme.name; "theme {name:?} isn't installed");
+    Some(format!(
+        "The theme “{}” isn't installed, so “{}” is used instead.",
+        name, settings.active_theme.name
+    ))
+}
+
+/// The configured fonts that aren't installed, which gpui replaces with a
+/// system font.
+fn missing_fonts(cx: &AppContext) -> Vec<String> {
+    let theme_settings = ThemeSettings::get_global(cx);
+    let mut families = vec![
+        theme_settings.ui_font.family.to_string(),
+        theme_settings.buffer_font.family.to_string(),
+    ];
+    families.extend(
+        MarkdownPreviewSettings::get_global(cx)
+            .code_font_family
+            .clone(),
+    );
+    families.sort();
+    families.dedup();
+
+    let installed = cx.text_system().all_font_names();
+    families
+        .into_iter()
+        // Names like `.SystemUIFont` are gpui's own, and always there.
+        .filter(|family| !family.starts_with('.') && !installed.contains(family))
+        .map(|family| {
+            log::warn!(fallback = "system"; "font {family:?} isn't installed");
+            format!("The font “{family}” isn't installed, so a system font is used instead.")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_theme::{DEFAULT_DARK_THEME, DEFAULT_LIGHT_THEME};
+    use crate::MarkdownView;
+    use anyhow::anyhow;
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+    use theme::Appearance;
+
+    #[gpui::test]
+    async fn test_init_with_fallbacks(cx: &mut TestAppContext) {
+        let warnings = cx.update(|cx| {
+            init_with_fallbacks(
+                r#"{
+                    "theme": "No Such Theme",
+                    "buffer_font_family": "No Such Font",
+                    "markdown_preview": { "code_font_family": "No Such Font" }
+                }"#,
+                cx,
+            )
+        });
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.contains("“No Such Theme” isn't installed")));
+        assert_eq!(
+            warnings
+                .iter()
+                .filter(|warning| warning.contains("“No Such Font” isn't installed"))
+                .count(),
+            1
+        );
+
+        // The window still shows the document.
+        let (view, cx) = cx.add_window_view(|cx| {
+            MarkdownView::new("# Title\n\nSome `code`\n".to_string(), None, cx)
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| assert_eq!(view.outline().len(), 1));
+    }
+
+    #[gpui::test]
+    fn test_missing_default_settings(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let warnings = init_with_defaults(Err(anyhow!("no defaults")), "{}", cx);
+            assert_eq!(
+                warnings[0],
+                "The default settings couldn't be loaded, so built-in ones are used: no defaults"
+            );
+
+            // The built-in settings are used in their place.
+            let theme_settings = ThemeSettings::get_global(cx);
+            let theme_selection = theme_settings.theme_selection.as_ref().unwrap();
+            assert_eq!(
+                theme_selection.theme(Appearance::Light),
+                DEFAULT_LIGHT_THEME
+            );
+            assert_eq!(theme_selection.theme(Appearance::Dark), DEFAULT_DARK_THEME);
+            assert_eq!(&*theme_settings.ui_font.family, "Courier");
+            assert_eq!(&*theme_settings.buffer_font.family, "Courier");
+            assert_eq!(MarkdownPreviewSettings::get_global(cx).text_scale, 1.0);
+        });
+    }
+
+    #[gpui::test]
+    fn test_invalid_user_settings(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let warnings = init_with_fallbacks("{ not json", cx);
+            assert!(warnings[0].starts_with("Your settings couldn't be loaded"));
+            assert_eq!(MarkdownPreviewSettings::get_global(cx).text_scale, 1.0);
+        });
+    }
+}
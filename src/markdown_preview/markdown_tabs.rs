@@ -0,0 +1,795 @@
+use super::{
+    markdown_recent::{filter_recent_files, recent_file_label, RecentFiles},
+    markdown_session::{SessionStore, TabSession, WindowSession},
+    markdown_sidebar::{MarkdownSidebar, SidebarEvent},
+    markdown_theme::apply_system_appearance,
+    markdown_view::{typed_text, MarkdownView},
+    ActivateNextTab, ActivatePreviousTab, CloseTab, NewFromClipboard, NewTab, OpenFolder,
+    OpenRecent, OpenRecentFile, ToggleSidebar,
+};
+use gpui::{
+    div, prelude::*, px, AppContext, ExternalPaths, FocusHandle, FocusableView, KeyDownEvent,
+    MouseButton, PathPromptOptions, SharedString, Subscription, View, ViewContext,
+};
+use std::path::{Path, PathBuf};
+use theme::ActiveTheme;
+use ui::{
+    h_flex, v_flex, Clickable, Color, Icon, IconButton, IconName, IconSize, Label, LabelCommon,
+};
+
+/// The overlay for picking one of the [`RecentFiles`] to open.
+struct RecentFilePicker {
+    focus_handle: FocusHandle,
+    query: String,
+    /// The recent files that match the query, best first.
+    matches: Vec<PathBuf>,
+    selected_ix: usize,
+}
+
+/// Previews several documents in one window, each in a tab of its own with
+/// its own scroll position, zoom and find bar.
+pub struct MarkdownTabs {
+    tabs: Vec<View<MarkdownView>>,
+    active_ix: usize,
+    /// The title last given to the window, which follows the active tab.
+    window_title: Option<String>,
+    recent_picker: Option<RecentFilePicker>,
+    /// The files of the folder that was opened, if one was.
+    sidebar: Option<(View<MarkdownSidebar>, Subscription)>,
+    sidebar_visible: bool,
+    /// A message shown above the tabs until it's dismissed, like which files
+    /// of the last session couldn't be reopened.
+    notice: Option<SharedString>,
+    /// Focused while there are no tabs.
+    focus_handle: FocusHandle,
+}
+
+impl MarkdownTabs {
+    pub fn new(cx: &mut ViewContext<Self>) -> Self {
+        cx.observe_window_appearance(|_, cx| apply_system_appearance(cx))
+            .detach();
+        Self {
+            tabs: Vec::new(),
+            active_ix: 0,
+            window_title: None,
+            recent_picker: None,
+            sidebar: None,
+            sidebar_visible: true,
+            notice: None,
+            focus_handle: cx.focus_handle(),
+        }
+    }
+
+    pub fn tabs(&self) -> &[View<MarkdownView>] {
+        &self.tabs
+    }
+
+    pub fn active_tab(&self) -> Option<&View<MarkdownView>> {
+        self.tabs.get(self.active_ix)
+    }
+
+    /// Adds a tab showing `view` after the others and switches to it.
+    pub fn add_tab(&mut self, view: View<MarkdownView>, cx: &mut ViewContext<Self>) {
+        // Tab titles come from the documents, so the bar is redrawn whenever
+        // one of them changes.
+        // The session keeps each tab's scroll position and zoom, so it's
+        // saved again when they change too.
+        cx.observe(&view, |this, _, cx| {
+            this.update_window_title(cx);
+            this.update_sidebar(cx);
+            SessionStore::schedule_save(cx);
+            cx.notify();
+        })
+        .detach();
+        self.tabs.push(view);
+        self.activate_tab(self.tabs.len() - 1, cx);
+    }
+
+    pub fn activate_tab(&mut self, ix: usize, cx: &mut ViewContext<Self>) {
+        let Some(view) = self.tabs.get(ix) else {
+            return;
+        };
+        self.active_ix = ix;
+        cx.focus_view(view);
+        self.update_window_title(cx);
+        self.update_sidebar(cx);
+        SessionStore::schedule_save(cx);
+        cx.notify();
+    }
+
+    /// The folder and files open in the window, to open again with
+    /// [`Self::restore_session`].
+    pub fn session(&self, cx: &AppContext) -> WindowSession {
+        let mut active_tab = 0;
+        let mut tabs = Vec::new();
+        for (ix, tab) in self.tabs.iter().enumerate() {
+            let tab = tab.read(cx);
+            let Some(path) = tab.path() else {
+                continue;
+            };
+            if ix == self.active_ix {
+                active_tab = tabs.len();
+            }
+            tabs.push(TabSession {
+                path: path.to_path_buf(),
+                scroll_position: tab.scroll_position(),
+                text_scale: tab.text_scale(),
+                follow: tab.is_following(),
+            });
+        }
+        WindowSession {
+            folder: self
+                .sidebar()
+                .map(|sidebar| sidebar.read(cx).root().to_path_buf()),
+            tabs,
+            active_tab,
+        }
+    }
+
+    /// Opens the folder and files of a window from an earlier session, each
+    /// tab scrolled and zoomed the way it was.
+    pub fn restore_session(&mut self, session: WindowSession, cx: &mut ViewContext<Self>) {
+        if let Some(folder) = session.folder {
+            self.open_folder(folder, cx);
+        }
+        for tab in session.tabs {
+            let base_directory = tab.path.parent().map(Path::to_path_buf);
+            let view = cx.new_view(|cx| {
+                let mut view = MarkdownView::new(String::new(), base_directory, cx);
+                view.open_file(tab.path, cx);
+                view.restore_text_scale(tab.text_scale, cx);
+                // Following keeps the end of the file in view instead.
+                if tab.follow {
+                    view.set_follow(true, cx);
+                } else {
+                    view.restore_scroll_position(tab.scroll_position, cx);
+                }
+                view
+            });
+            self.add_tab(view, cx);
+        }
+        self.activate_tab(session.active_tab, cx);
+    }
+
+    /// Shows `message` above the tabs until it's dismissed.
+    pub fn show_notice(&mut self, message: impl Into<SharedString>, cx: &mut ViewContext<Self>) {
+        self.notice = Some(message.into());
+        cx.notify();
+    }
+
+    /// Shows the Markdown files in the directory at `path` in a sidebar,
+    /// replacing the folder that was open.
+    pub fn open_folder(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
+        let sidebar = cx.new_view(|cx| MarkdownSidebar::new(path, cx));
+        let subscription = cx.subscribe(&sidebar, |this, _, event, cx| match event {
+            SidebarEvent::OpenFile { path, new_tab } => {
+                this.open_from_sidebar(path.clone(), *new_tab, cx)
+            }
+        });
+        self.sidebar = Some((sidebar, subscription));
+        self.sidebar_visible = true;
+        self.update_sidebar(cx);
+        SessionStore::schedule_save(cx);
+        cx.notify();
+    }
+
+    pub fn sidebar(&self) -> Option<&View<MarkdownSidebar>> {
+        self.sidebar.as_ref().map(|(sidebar, _)| sidebar)
+    }
+
+    /// Opens a file clicked in the sidebar in the active tab, so that going
+    /// back returns to the file that was there, or in a tab of its own.
+    fn open_from_sidebar(&mut self, path: PathBuf, new_tab: bool, cx: &mut ViewContext<Self>) {
+        let path = path.canonicalize().unwrap_or(path);
+        match self.active_tab().cloned() {
+            Some(tab) if !new_tab && self.tab_for_path(&path, cx).is_none() => {
+                tab.update(cx, |tab, cx| tab.open_document(path, None, cx));
+            }
+            _ => self.open_path(path, cx),
+        }
+    }
+
+    /// Highlights the active tab's file in the sidebar.
+    fn update_sidebar(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(sidebar) = self.sidebar().cloned() else {
+            return;
+        };
+        let path = self
+            .active_tab()
+            .and_then(|tab| tab.read(cx).path().map(Path::to_path_buf));
+        sidebar.update(cx, |sidebar, cx| sidebar.set_active_path(path, cx));
+    }
+
+    fn toggle_sidebar(&mut self, _: &ToggleSidebar, cx: &mut ViewContext<Self>) {
+        self.sidebar_visible = !self.sidebar_visible;
+        cx.notify();
+    }
+
+    fn prompt_for_folder(&mut self, _: &OpenFolder, cx: &mut ViewContext<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: false,
+            directories: true,
+            multiple: false,
+        });
+        cx.spawn(|this, mut cx| async move {
+            let Some(path) = paths.await??.and_then(|paths| paths.into_iter().next()) else {
+                return Ok(());
+            };
+            this.update(&mut cx, |this, cx| this.open_folder(path, cx))
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn update_window_title(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(title) = self.active_tab().map(|tab| tab.read(cx).window_title()) else {
+            return;
+        };
+        if self.window_title.as_ref() != Some(&title) {
+            cx.set_window_title(&title);
+            self.window_title = Some(title);
+        }
+    }
+
+    /// Closes the tab at `ix`, and the window along with the last tab.
+    pub fn close_tab(&mut self, ix: usize, cx: &mut ViewContext<Self>) {
+        if ix >= self.tabs.len() {
+            return;
+        }
+
+        self.tabs.remove(ix);
+        SessionStore::schedule_save(cx);
+        if self.tabs.is_empty() {
+            cx.remove_window();
+            return;
+        }
+        if ix < self.active_ix || self.active_ix == self.tabs.len() {
+            self.active_ix -= 1;
+        }
+        self.activate_tab(self.active_ix, cx);
+    }
+
+    /// Opens each of `paths` in a tab, or switches to the tab it's already
+    /// open in.
+    pub fn open_paths(&mut self, paths: Vec<PathBuf>, cx: &mut ViewContext<Self>) {
+        for path in paths {
+            self.open_path(path, cx);
+        }
+    }
+
+    fn open_path(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
+        let path = path.canonicalize().unwrap_or(path);
+        if let Some(ix) = self.tab_for_path(&path, cx) {
+            self.activate_tab(ix, cx);
+            return;
+        }
+
+        // A file that can't be read still gets a tab, explaining why.
+        let base_directory = path.parent().map(Path::to_path_buf);
+        let view = cx.new_view(|cx| {
+            let mut view = MarkdownView::new(String::new(), base_directory, cx);
+            view.open_file(path, cx);
+            view
+        });
+        self.add_tab(view, cx);
+    }
+
+    fn tab_for_path(&self, path: &Path, cx: &AppContext) -> Option<usize> {
+        self.tabs.iter().position(|tab| {
+            tab.read(cx)
+                .path()
+                .and_then(|tab_path| tab_path.canonicalize().ok())
+                .map_or(false, |tab_path| tab_path == path)
+        })
+    }
+
+    fn new_tab(&mut self, _: &NewTab, cx: &mut ViewContext<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: true,
+        });
+        cx.spawn(|this, mut cx| async move {
+            let Some(paths) = paths.await?? else {
+                return Ok(());
+            };
+            this.update(&mut cx, |this, cx| this.open_paths(paths, cx))
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Previews the text on the clipboard in a new, unsaved tab.
+    fn new_from_clipboard(&mut self, _: &NewFromClipboard, cx: &mut ViewContext<Self>) {
+        // The clipboard is only read as plain text, which is the flavor
+        // that copied Markdown and web pages both have.
+        let Some(text) = cx.read_from_clipboard().and_then(|item| item.text()) else {
+            return;
+        };
+        let view = cx.new_view(|cx| MarkdownView::scratch(text, cx));
+        self.add_tab(view, cx);
+    }
+
+    fn close_active_tab(&mut self, _: &CloseTab, cx: &mut ViewContext<Self>) {
+        self.close_tab(self.active_ix, cx);
+    }
+
+    fn activate_next_tab(&mut self, _: &ActivateNextTab, cx: &mut ViewContext<Self>) {
+        if !self.tabs.is_empty() {
+            self.activate_tab((self.active_ix + 1) % self.tabs.len(), cx);
+        }
+    }
+
+    fn activate_previous_tab(&mut self, _: &ActivatePreviousTab, cx: &mut ViewContext<Self>) {
+        if !self.tabs.is_empty() {
+            let count = self.tabs.len();
+            self.activate_tab((self.active_ix + count - 1) % count, cx);
+        }
+    }
+
+    /// Shows the picker for the recent files, or hides it if it's shown.
+    /// It's bound to cmd-shift-o, or ctrl-shift-o off macOS, and listed as
+    /// "More…" under File → Open Recent.
+    fn open_recent(&mut self, _: &OpenRecent, cx: &mut ViewContext<Self>) {
+        if self.recent_picker.is_some() {
+            self.dismiss_recent_picker(cx);
+            return;
+        }
+
+        let picker = RecentFilePicker {
+            focus_handle: cx.focus_handle(),
+            query: String::new(),
+            matches: RecentFiles::prune(cx),
+            selected_ix: 0,
+        };
+        cx.focus(&picker.focus_handle);
+        self.recent_picker = Some(picker);
+        cx.notify();
+    }
+
+    fn open_recent_file(&mut self, action: &OpenRecentFile, cx: &mut ViewContext<Self>) {
+        self.open_paths(vec![action.path.clone()], cx);
+    }
+
+    fn dismiss_recent_picker(&mut self, cx: &mut ViewContext<Self>) {
+        if self.recent_picker.take().is_some() {
+            if let Some(tab) = self.active_tab() {
+                cx.focus_view(tab);
+            }
+            cx.notify();
+        }
+    }
+
+    fn confirm_recent_file(&mut self, cx: &mut ViewContext<Self>) {
+        let path = self
+            .recent_picker
+            .as_ref()
+            .and_then(|picker| picker.matches.get(picker.selected_ix).cloned());
+        self.dismiss_recent_picker(cx);
+        if let Some(path) = path {
+            self.open_paths(vec![path], cx);
+        }
+    }
+
+    fn update_recent_matches(&mut self, cx: &mut ViewContext<Self>) {
+        if let Some(picker) = &mut self.recent_picker {
+            picker.matches = filter_recent_files(cx.global::<RecentFiles>().paths(), &picker.query);
+            picker.selected_ix = 0;
+        }
+        cx.notify();
+    }
+
+    /// Edits the picker's query and moves its selection. Like the find bar,
+    /// the field only takes typed text and backspace.
+    fn handle_picker_key(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
+        let Some(picker) = &mut self.recent_picker else {
+            return;
+        };
+
+        let keystroke = &event.keystroke;
+        let secondary = if cfg!(target_os = "macos") {
+            keystroke.modifiers.platform
+        } else {
+            keystroke.modifiers.control
+        };
+        match keystroke.key.as_str() {
+            "escape" => self.dismiss_recent_picker(cx),
+            "enter" => self.confirm_recent_file(cx),
+            "up" => {
+                picker.selected_ix = picker.selected_ix.saturating_sub(1);
+                cx.notify();
+            }
+            "down" => {
+                picker.selected_ix =
+                    (picker.selected_ix + 1).min(picker.matches.len().saturating_sub(1));
+                cx.notify();
+            }
+            "backspace" => {
+                picker.query.pop();
+                self.update_recent_matches(cx);
+            }
+            _ => match typed_text(keystroke).filter(|_| !secondary) {
+                Some(text) => {
+                    picker.query.push_str(&text);
+                    self.update_recent_matches(cx);
+                }
+                None => return,
+            },
+        }
+        cx.stop_propagation();
+    }
+
+    fn render_recent_picker(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
+        let picker = self.recent_picker.as_ref()?;
+        let colors = cx.theme().colors();
+
+        let entries = picker.matches.iter().enumerate().map(|(ix, path)| {
+            h_flex()
+                .id(("markdown-recent-file", ix))
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .when(ix == picker.selected_ix, |entry| {
+                    entry.bg(colors.element_selected)
+                })
+                .hover(|style| style.bg(colors.element_hover))
+                .child(Label::new(recent_file_label(path)))
+                .on_click(cx.listener(move |this, _, cx| {
+                    if let Some(picker) = &mut this.recent_picker {
+                        picker.selected_ix = ix;
+                    }
+                    this.confirm_recent_file(cx);
+                }))
+        });
+
+        let picker_element = v_flex()
+            .key_context("MarkdownRecentPicker")
+            .track_focus(&picker.focus_handle)
+            .on_key_down(cx.listener(Self::handle_picker_key))
+            .on_mouse_down_out(cx.listener(|this, _, cx| this.dismiss_recent_picker(cx)))
+            .w(px(480.))
+            .max_h(px(360.))
+            .p_1()
+            .gap_1()
+            .rounded_lg()
+            .border_1()
+            .border_color(colors.border)
+            .bg(colors.elevated_surface_background)
+            .shadow_lg()
+            .child(
+                h_flex()
+                    .h_7()
+                    .px_2()
+                    .border_b_1()
+                    .border_color(colors.border_variant)
+                    .child(if picker.query.is_empty() {
+                        Label::new("Open recent file").color(Color::Placeholder)
+                    } else {
+                        Label::new(picker.query.clone())
+                    }),
+            )
+            .child(
+                v_flex()
+                    .id("markdown-recent-files")
+                    .overflow_y_scroll()
+                    .children(entries)
+                    .when(picker.matches.is_empty(), |list| {
+                        list.child(
+                            div()
+                                .px_2()
+                                .py_1()
+                                .child(Label::new("No recent files").color(Color::Muted)),
+                        )
+                    }),
+            );
+
+        Some(
+            h_flex()
+                .absolute()
+                .top_0()
+                .left_0()
+                .w_full()
+                .justify_center()
+                .pt_8()
+                .child(picker_element),
+        )
+    }
+
+    fn render_notice(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
+        let notice = self.notice.clone()?;
+        Some(
+            h_flex()
+                .flex_none()
+                .items_start()
+                .gap_2()
+                .px_2()
+                .py_1()
+                .bg(cx.theme().status().info_background)
+                .border_b_1()
+                .border_color(cx.theme().colors().border)
+                .child(Icon::new(IconName::Info).size(IconSize::Small))
+                .child(div().flex_1().child(Label::new(notice)))
+                .child(
+                    IconButton::new("markdown-dismiss-notice", IconName::Close)
+                        .icon_size(IconSize::XSmall)
+                        .on_click(cx.listener(|this, _, cx| {
+                            this.notice = None;
+                            cx.notify();
+                        })),
+                ),
+        )
+    }
+
+    fn render_tab_bar(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let colors = cx.theme().colors();
+        let tabs = self.tabs.iter().enumerate().map(|(ix, tab)| {
+            let active = ix == self.active_ix;
+            h_flex()
+                .id(("markdown-tab", ix))
+                .flex_none()
+                .gap_1()
+                .h_full()
+                .pl_3()
+                .pr_1()
+                .border_r_1()
+                .border_color(colors.border)
+                .bg(if active {
+                    colors.tab_active_background
+                } else {
+                    colors.tab_inactive_background
+                })
+                .child(Label::new(tab.read(cx).title()).color(if active {
+                    Color::Default
+                } else {
+                    Color::Muted
+                }))
+                .child(
+                    IconButton::new(("markdown-close-tab", ix), IconName::Close)
+                        .icon_size(IconSize::XSmall)
+                        .on_click(cx.listener(move |this, _, cx| {
+                            cx.stop_propagation();
+                            this.close_tab(ix, cx);
+                        })),
+                )
+                .on_click(cx.listener(move |this, _, cx| this.activate_tab(ix, cx)))
+                .on_mouse_down(
+                    MouseButton::Middle,
+                    cx.listener(move |this, _, cx| this.close_tab(ix, cx)),
+                )
+        });
+
+        // Files dropped onto a document replace it, while those dropped onto
+        // the bar open in tabs of their own.
+        h_flex()
+            .id("markdown-tab-bar")
+            .flex_none()
+            .w_full()
+            .h_8()
+            .overflow_x_scroll()
+            .border_b_1()
+            .border_color(colors.border)
+            .bg(colors.tab_bar_background)
+            .drag_over::<ExternalPaths>(|style, _, cx| {
+                style.bg(cx.theme().colors().drop_target_background)
+            })
+            .on_drop(cx.listener(|this, paths: &ExternalPaths, cx| {
+                this.open_paths(paths.paths().to_vec(), cx)
+            }))
+            .children(tabs)
+    }
+}
+
+impl FocusableView for MarkdownTabs {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.active_tab()
+            .map_or_else(|| self.focus_handle.clone(), |tab| tab.focus_handle(cx))
+    }
+}
+
+impl Render for MarkdownTabs {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex()
+            .key_context("MarkdownTabs")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::new_tab))
+            .on_action(cx.listener(Self::new_from_clipboard))
+            .on_action(cx.listener(Self::close_active_tab))
+            .on_action(cx.listener(Self::activate_next_tab))
+            .on_action(cx.listener(Self::activate_previous_tab))
+            .on_action(cx.listener(Self::open_recent))
+            .on_action(cx.listener(Self::open_recent_file))
+            .on_action(cx.listener(Self::prompt_for_folder))
+            .on_action(cx.listener(Self::toggle_sidebar))
+            .size_full()
+            .children(self.render_notice(cx))
+            .child(self.render_tab_bar(cx))
+            .child(
+                h_flex()
+                    .flex_1()
+                    .min_h_0()
+                    .children(self.sidebar().filter(|_| self.sidebar_visible).cloned())
+                    .child(
+                        div()
+                            .relative()
+                            .flex_1()
+                            .min_w_0()
+                            .h_full()
+                            .children(self.active_tab().cloned())
+                            .children(self.render_recent_picker(cx)),
+                    ),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+    use settings::SettingsStore;
+    use theme::LoadThemes;
+
+    #[gpui::test]
+    async fn test_opening_files_in_tabs(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+
+        let directory = std::env::temp_dir().join(format!("markdown-tabs-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let one = directory.join("one.md");
+        let two = directory.join("two.md");
+        std::fs::write(&one, "# One\n").unwrap();
+        std::fs::write(&two, "Text\n").unwrap();
+
+        let (tabs, cx) = cx.add_window_view(MarkdownTabs::new);
+        tabs.update(cx, |tabs, cx| {
+            tabs.open_paths(vec![one.clone(), two.clone()], cx)
+        });
+        cx.run_until_parked();
+
+        let titles = |cx: &mut gpui::VisualTestContext| {
+            tabs.update(cx, |tabs, cx| {
+                tabs.tabs()
+                    .iter()
+                    .map(|tab| tab.read(cx).title().to_string())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(titles(cx), vec!["One", "two.md"]);
+
+        // Files that are already open aren't opened again.
+        tabs.update(cx, |tabs, cx| tabs.open_paths(vec![one.clone()], cx));
+        cx.run_until_parked();
+        tabs.update(cx, |tabs, cx| {
+            assert_eq!(tabs.tabs().len(), 2);
+            assert_eq!(tabs.active_ix, 0);
+
+            tabs.activate_previous_tab(&ActivatePreviousTab, cx);
+            assert_eq!(tabs.active_ix, 1);
+            tabs.close_tab(0, cx);
+            assert_eq!(tabs.active_ix, 0);
+        });
+        assert_eq!(titles(cx), vec!["two.md"]);
+
+        // Closed files can be opened again from the recent files.
+        tabs.update(cx, |tabs, cx| {
+            tabs.open_recent(&OpenRecent, cx);
+            let picker = tabs.recent_picker.as_mut().unwrap();
+            assert_eq!(
+                picker.matches,
+                vec![two.canonicalize().unwrap(), one.canonicalize().unwrap()]
+            );
+            picker.query = "one".to_string();
+            tabs.update_recent_matches(cx);
+            tabs.confirm_recent_file(cx);
+            assert!(tabs.recent_picker.is_none());
+        });
+        cx.run_until_parked();
+        assert_eq!(titles(cx), vec!["two.md", "One"]);
+        tabs.update(cx, |tabs, _| assert_eq!(tabs.active_ix, 1));
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_session_round_trip(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+
+        let directory =
+            std::env::temp_dir().join(format!("markdown-tabs-session-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let one = directory.join("one.md");
+        let two = directory.join("two.md");
+        std::fs::write(&one, "# One\n").unwrap();
+        std::fs::write(&two, "# Two\n").unwrap();
+
+        let (tabs, cx) = cx.add_window_view(MarkdownTabs::new);
+        tabs.update(cx, |tabs, cx| {
+            tabs.open_folder(directory.clone(), cx);
+            tabs.open_paths(vec![one.clone(), two.clone()], cx);
+            tabs.tabs()[1].update(cx, |tab, cx| {
+                tab.restore_text_scale(1.5, cx);
+                tab.set_follow(true, cx);
+            });
+            tabs.activate_tab(0, cx);
+        });
+        cx.run_until_parked();
+
+        let session = tabs.update(cx, |tabs, cx| tabs.session(cx));
+        assert_eq!(session.folder, Some(directory.canonicalize().unwrap()));
+        assert_eq!(session.active_tab, 0);
+        assert_eq!(
+            session
+                .tabs
+                .iter()
+                .map(|tab| (tab.text_scale, tab.follow))
+                .collect::<Vec<_>>(),
+            vec![(1., false), (1.5, true)]
+        );
+
+        let restored = cx.new_view(|cx| {
+            let mut tabs = MarkdownTabs::new(cx);
+            tabs.restore_session(session.clone(), cx);
+            tabs
+        });
+        cx.run_until_parked();
+        restored.update(cx, |restored, cx| {
+            assert_eq!(restored.session(cx), session);
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_opening_files_from_the_sidebar(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+
+        let directory =
+            std::env::temp_dir().join(format!("markdown-tabs-sidebar-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let one = directory.join("one.md");
+        let two = directory.join("two.md");
+        std::fs::write(&one, "# One\n").unwrap();
+        std::fs::write(&two, "# Two\n").unwrap();
+
+        let (tabs, cx) = cx.add_window_view(MarkdownTabs::new);
+        tabs.update(cx, |tabs, cx| {
+            tabs.open_folder(directory.clone(), cx);
+            tabs.open_from_sidebar(one.clone(), false, cx);
+        });
+        cx.run_until_parked();
+
+        // Files replace the document in the active tab, unless they're asked
+        // to open in a new one.
+        tabs.update(cx, |tabs, cx| {
+            tabs.open_from_sidebar(two.clone(), false, cx)
+        });
+        cx.run_until_parked();
+        tabs.update(cx, |tabs, cx| {
+            assert_eq!(tabs.tabs().len(), 1);
+            assert_eq!(tabs.active_tab().unwrap().read(cx).title().as_ref(), "Two");
+            tabs.open_from_sidebar(one.clone(), true, cx);
+        });
+        cx.run_until_parked();
+        tabs.update(cx, |tabs, cx| {
+            assert_eq!(tabs.tabs().len(), 2);
+            let sidebar = tabs.sidebar().unwrap().read(cx);
+            assert_eq!(sidebar.root(), directory.canonicalize().unwrap());
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
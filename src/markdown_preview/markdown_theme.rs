@@ -0,0 +1,110 @@
+use super::{markdown_settings::update_user_settings, ToggleTheme};
+use gpui::{AppContext, WindowContext};
+use theme::{
+    ActiveTheme, Appearance, PlayerColors, StatusColors, SystemAppearance, Theme, ThemeColors,
+    ThemeRegistry, ThemeSettings, ThemeStyles,
+};
+
+/// The themes the `theme` setting starts out with, used for light and dark
+/// appearances respectively.
+pub const DEFAULT_LIGHT_THEME: &str = "One Light";
+pub const DEFAULT_DARK_THEME: &str = "One Dark";
+
+/// Makes sure there's a light theme to switch to.
+///
+/// The base themes that every application has are only dark ones, so unless
+/// the application loaded others, this adds a light theme built from the
+/// base light colors.
+pub(crate) fn register_light_theme(cx: &mut AppContext) {
+    let registry = ThemeRegistry::global(cx);
+    if registry.get(DEFAULT_LIGHT_THEME).is_ok() {
+        return;
+    }
+    let Ok(dark) = registry.get(DEFAULT_DARK_THEME) else {
+        return;
+    };
+
+    registry.insert_themes([Theme {
+        id: "markdown-preview-one-light".to_string(),
+        name: DEFAULT_LIGHT_THEME.into(),
+        appearance: Appearance::Light,
+        styles: ThemeStyles {
+            colors: ThemeColors::light(),
+            status: StatusColors::light(),
+            player: PlayerColors::light(),
+            ..dark.styles.clone()
+        },
+    }]);
+    ThemeSettings::reload_current_theme(cx);
+}
+
+/// Switches between the light and the dark theme, and saves the choice in
+/// the user settings.
+///
+/// With the `theme` setting's `"mode"` set to `"system"`, the theme follows
+/// the appearance of the OS instead, until it's toggled.
+pub(crate) fn toggle_theme(_: &ToggleTheme, cx: &mut AppContext) {
+    let appearance = cx.theme().appearance();
+    let current = cx.theme().name.to_string();
+    let mode = if appearance.is_light() {
+        "dark"
+    } else {
+        "light"
+    };
+
+    let updated = update_user_settings(cx, |settings| {
+        let theme = &mut settings["theme"];
+        // A single theme becomes the one for its appearance.
+        if !theme.is_object() {
+            let (light, dark) = if appearance.is_light() {
+                (current.as_str(), DEFAULT_DARK_THEME)
+            } else {
+                (DEFAULT_LIGHT_THEME, current.as_str())
+            };
+            *theme = serde_json::json!({ "light": light, "dark": dark });
+        }
+        theme["mode"] = mode.into();
+    });
+    match updated {
+        Ok(()) => cx.refresh(),
+        Err(error) => log::error!("failed to toggle the theme: {:#}", error),
+    }
+}
+
+/// Updates the theme for the appearance of the OS, which views that own a
+/// window call whenever it changes.
+pub fn apply_system_appearance(cx: &mut WindowContext) {
+    *SystemAppearance::global_mut(cx) = SystemAppearance(cx.appearance().into());
+    ThemeSettings::reload_current_theme(cx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+    use settings::SettingsStore;
+    use theme::LoadThemes;
+
+    #[gpui::test]
+    fn test_toggle_theme(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+
+            assert_eq!(cx.theme().appearance(), Appearance::Dark);
+            toggle_theme(&ToggleTheme, cx);
+            assert_eq!(cx.theme().name.as_ref(), DEFAULT_LIGHT_THEME);
+            assert_eq!(
+                cx.global::<SettingsStore>().raw_user_settings()["theme"]["mode"],
+                "light"
+            );
+
+            toggle_theme(&ToggleTheme, cx);
+            assert_eq!(cx.theme().appearance(), Appearance::Dark);
+        });
+    }
+}
@@ -0,0 +1,347 @@
+use super::markdown_elements::ParsedMarkdownElement;
+use anyhow::{Context as _, Result};
+use gpui::{AppContext, Global, Task};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The version of the saved state's schema. State saved with any other
+/// version is ignored rather than misread.
+const STATE_VERSION: u32 = 1;
+/// How long the state waits to be saved after it changes, so that scrolling
+/// through a document saves it once.
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(1);
+/// How many documents' state is kept. The documents shown longest ago are
+/// forgotten first.
+const MAX_DOCUMENTS: usize = 500;
+
+/// How a document was being read: what was folded and expanded, the zoom
+/// and the scroll position, to put it back the next time it's opened.
+///
+/// Blocks are remembered by their contents rather than where they are, so
+/// that state that no longer matches the document is dropped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentUiState {
+    /// The slugs of the headings whose sections were folded.
+    #[serde(default)]
+    pub folded_sections: Vec<String>,
+    /// The code blocks shown past the height cap, by [`source_hash`].
+    #[serde(default)]
+    pub expanded_code_blocks: Vec<u64>,
+    #[serde(default)]
+    pub front_matter_collapsed: bool,
+    #[serde(default = "default_text_scale")]
+    pub text_scale: f32,
+    #[serde(default)]
+    pub scroll: Option<SavedScroll>,
+}
+
+impl Default for DocumentUiState {
+    fn default() -> Self {
+        Self {
+            folded_sections: Vec::new(),
+            expanded_code_blocks: Vec::new(),
+            front_matter_collapsed: false,
+            text_scale: default_text_scale(),
+            scroll: None,
+        }
+    }
+}
+
+fn default_text_scale() -> f32 {
+    1.
+}
+
+/// The block at the top of the viewport.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedScroll {
+    pub block_ix: usize,
+    /// The [`source_hash`] of the block's Markdown source.
+    pub source_hash: u64,
+    /// The slug of the last heading before the block, and how many blocks
+    /// after it the block is.
+    #[serde(default)]
+    pub section: Option<(String, usize)>,
+    /// How far into the block the top of the viewport was, as a fraction of
+    /// the block's height.
+    #[serde(default)]
+    pub offset_fraction: f32,
+}
+
+impl SavedScroll {
+    /// The block in `blocks`, parsed from `text`, that the reader was at, if
+    /// it's still there and unchanged: the same number of blocks past its
+    /// heading, or else the one with the same source nearest to where it
+    /// was.
+    pub fn find_block(&self, blocks: &[ParsedMarkdownElement], text: &str) -> Option<usize> {
+        let is_match = |ix: usize| {
+            blocks
+                .get(ix)
+                .and_then(|block| text.get(block.source_range()))
+                .map_or(false, |source| source_hash(source) == self.source_hash)
+        };
+
+        if let Some((slug, past)) = &self.section {
+            let in_section = blocks
+                .iter()
+                .position(|block| {
+                    matches!(block, ParsedMarkdownElement::Heading(heading) if heading.slug == *slug)
+                })
+                .map(|heading_ix| heading_ix + past)
+                .filter(|ix| is_match(*ix));
+            if in_section.is_some() {
+                return in_section;
+            }
+        }
+        (0..blocks.len())
+            .filter(|ix| is_match(*ix))
+            .min_by_key(|ix| ix.abs_diff(self.block_ix))
+    }
+}
+
+/// A hash of some Markdown source that stays the same from one run of the
+/// application to the next, unlike the standard library's.
+pub fn source_hash(source: &str) -> u64 {
+    // 64-bit FNV-1a.
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateFile {
+    version: u32,
+    /// The documents shown longest ago first.
+    #[serde(default)]
+    documents: Vec<SavedDocument>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedDocument {
+    path: PathBuf,
+    #[serde(flatten)]
+    state: DocumentUiState,
+}
+
+/// Where the [`DocumentUiState`] of each file is kept, by its canonical
+/// path, as [`MarkdownView::watch_file`](super::markdown_view::MarkdownView::watch_file)
+/// resolves it.
+///
+/// Like [`SessionStore`](super::markdown_session::SessionStore), the state is
+/// only kept by applications that set this global.
+pub struct UiStateStore {
+    file: PathBuf,
+    documents: Vec<SavedDocument>,
+    /// Whether `documents` changed since they were last saved.
+    dirty: bool,
+    /// Saves the state once it has stopped changing.
+    pending_save: Option<Task<()>>,
+}
+
+impl Global for UiStateStore {}
+
+impl UiStateStore {
+    /// Reads the state saved in `file`. A missing or unreadable file, or one
+    /// saved with another version of the schema, has none.
+    pub fn load(file: PathBuf) -> Self {
+        let documents = match std::fs::read_to_string(&file) {
+            Ok(content) => match serde_json::from_str::<StateFile>(&content) {
+                Ok(state) if state.version == STATE_VERSION => state.documents,
+                Ok(state) => {
+                    log::info!(
+                        "ignoring {}, saved with version {} of the schema",
+                        file.display(),
+                        state.version
+                    );
+                    Vec::new()
+                }
+                Err(error) => {
+                    log::error!("failed to parse {}: {}", file.display(), error);
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        Self {
+            file,
+            documents,
+            dirty: false,
+            pending_save: None,
+        }
+    }
+
+    /// The state the file at `path` was last shown with.
+    pub fn get(path: &Path, cx: &AppContext) -> Option<DocumentUiState> {
+        cx.try_global::<Self>()?
+            .documents
+            .iter()
+            .find(|document| document.path == path)
+            .map(|document| document.state.clone())
+    }
+
+    /// Remembers the state the file at `path` is shown with, and saves it a
+    /// moment from now, unless it changes again before then.
+    pub fn set(path: &Path, state: DocumentUiState, cx: &mut AppContext) {
+        if !cx.has_global::<Self>() {
+            return;
+        }
+        // Like the session's, the write is detached, since this task is
+        // cancelled when the state changes again.
+        let task = cx.spawn(|cx| async move {
+            cx.background_executor().timer(SAVE_DEBOUNCE).await;
+            cx.update(|cx| Self::write(cx).detach_and_log_err(cx)).ok();
+        });
+        let store = cx.global_mut::<Self>();
+        store.documents.retain(|document| document.path != path);
+        store.documents.push(SavedDocument {
+            path: path.to_path_buf(),
+            state,
+        });
+        let excess = store.documents.len().saturating_sub(MAX_DOCUMENTS);
+        store.documents.drain(..excess);
+        store.dirty = true;
+        store.pending_save = Some(task);
+    }
+
+    /// Saves the state of every document now, like when the application
+    /// quits.
+    pub fn save(cx: &mut AppContext) -> Task<Result<()>> {
+        if !cx.has_global::<Self>() {
+            return Task::ready(Ok(()));
+        }
+        cx.global_mut::<Self>().pending_save = None;
+        Self::write(cx)
+    }
+
+    fn write(cx: &mut AppContext) -> Task<Result<()>> {
+        let store = cx.global_mut::<Self>();
+        if !std::mem::take(&mut store.dirty) {
+            return Task::ready(Ok(()));
+        }
+        let state = StateFile {
+            version: STATE_VERSION,
+            documents: store.documents.clone(),
+        };
+        let file = store.file.clone();
+        cx.background_executor().spawn(async move {
+            let content = serde_json::to_string(&state)?;
+            if let Some(directory) = file.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            std::fs::write(&file, content)
+                .with_context(|| format!("failed to write {}", file.display()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use gpui::TestAppContext;
+    use pretty_assertions::assert_eq;
+
+    #[gpui::test]
+    async fn test_saved_state_round_trip(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-ui-state-{}", std::process::id()));
+        let file = directory.join("state.json");
+        let path = Path::new("/docs/readme.md");
+        let state = DocumentUiState {
+            folded_sections: vec!["usage".to_string()],
+            expanded_code_blocks: vec![source_hash("```\nlong\n```\n")],
+            front_matter_collapsed: true,
+            text_scale: 1.5,
+            scroll: Some(SavedScroll {
+                block_ix: 3,
+                source_hash: source_hash("Intro\n"),
+                section: Some(("usage".to_string(), 1)),
+                offset_fraction: 0.25,
+            }),
+        };
+
+        cx.update(|cx| {
+            cx.set_global(UiStateStore::load(file.clone()));
+            UiStateStore::set(path, state.clone(), cx);
+        });
+        cx.update(UiStateStore::save).await.unwrap();
+        cx.update(|cx| cx.set_global(UiStateStore::load(file.clone())));
+        assert_eq!(cx.update(|cx| UiStateStore::get(path, cx)), Some(state));
+        assert_eq!(
+            cx.update(|cx| UiStateStore::get(Path::new("/docs/other.md"), cx)),
+            None
+        );
+
+        // State saved with another version of the schema isn't read.
+        let content = std::fs::read_to_string(&file).unwrap();
+        std::fs::write(&file, content.replace(r#""version":1"#, r#""version":2"#)).unwrap();
+        cx.update(|cx| cx.set_global(UiStateStore::load(file.clone())));
+        assert_eq!(cx.update(|cx| UiStateStore::get(path, cx)), None);
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_state_is_saved_once_it_stops_changing(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-ui-state-debounce-{}", std::process::id()));
+        let file = directory.join("state.json");
+        let path = Path::new("/docs/readme.md");
+        let state = |text_scale| DocumentUiState {
+            text_scale,
+            ..DocumentUiState::default()
+        };
+
+        cx.update(|cx| {
+            cx.set_global(UiStateStore::load(file.clone()));
+            UiStateStore::set(path, state(1.25), cx);
+        });
+        cx.executor().advance_clock(SAVE_DEBOUNCE / 2);
+        cx.update(|cx| UiStateStore::set(path, state(1.5), cx));
+        cx.executor().advance_clock(SAVE_DEBOUNCE / 2);
+        cx.run_until_parked();
+        assert!(!file.exists());
+
+        cx.executor().advance_clock(SAVE_DEBOUNCE);
+        cx.run_until_parked();
+        cx.update(|cx| cx.set_global(UiStateStore::load(file.clone())));
+        assert_eq!(
+            cx.update(|cx| UiStateStore::get(path, cx)),
+            Some(state(1.5))
+        );
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_saved_scroll_find_block() {
+        let scroll = SavedScroll {
+            block_ix: 1,
+            source_hash: source_hash("Second one\n"),
+            section: None,
+            offset_fraction: 0.5,
+        };
+
+        let text = "# New\n\nFirst\n\nSecond one\n";
+        let parsed = parse_markdown(text, None, None).await;
+        assert_eq!(scroll.find_block(&parsed.children, text), Some(2));
+
+        // Edited blocks aren't resolved, so the reader starts at the top.
+        let text = "First\n\nSecond, edited\n";
+        let parsed = parse_markdown(text, None, None).await;
+        assert_eq!(scroll.find_block(&parsed.children, text), None);
+
+        // Within its section, the block the same number of blocks past the
+        // heading is used over others with the same source.
+        let scroll = SavedScroll {
+            section: Some(("usage".to_string(), 2)),
+            ..scroll
+        };
+        let text = "Second one\n\n# Usage\n\nIntro\n\nSecond one\n";
+        let parsed = parse_markdown(text, None, None).await;
+        assert_eq!(scroll.find_block(&parsed.children, text), Some(3));
+    }
+}
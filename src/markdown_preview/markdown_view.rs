@@ -0,0 +1,7979 @@
+use super::{
+    markdown_accessibility::{accessibility_tree, AccessibilityNode},
+    markdown_diagnostics::{diagnostics_report, redact_url, DocumentStats},
+    markdown_elements::{
+        expand_path, reading_minutes, section_numbers, split_path_fragment, word_count,
+        HeadingLevel, Link, LinkKind, LinkOrigin, OutlineEntry, ParsedMarkdown,
+        ParsedMarkdownCodeBlock, ParsedMarkdownElement,
+    },
+    markdown_fences::FenceHandlers,
+    markdown_front_matter::{is_url, FrontMatterValue},
+    markdown_html::{self, HtmlExportOptions, TocLevels},
+    markdown_images::{ImageLocation, ImagePolicy, ImageViewer, MarkdownImage, RequestHeaders},
+    markdown_link_check::{check_path, check_urls, is_checked_online, LinkCheckCache},
+    markdown_locale::Locale,
+    markdown_parser::{
+        assign_heading_slugs, heading_slug, highlight_code, parse_markdown_in_chunks,
+        parse_markdown_parallel, parse_markdown_timed, ParseOptions, ParseTiming,
+    },
+    markdown_pdf::{self, PdfExportOptions},
+    markdown_recent::{fuzzy_score, RecentFiles},
+    markdown_renderer::{
+        render_markdown_block, BlockLayouts, CodeRun, HoveredElement, LayoutInfo, LinkPolicy,
+        MarkdownStyle, PresentationState, RenderCache, RenderContext, RenderOverrideCallback,
+    },
+    markdown_search::{search, SearchHighlights, SearchMatch},
+    markdown_settings::{update_user_settings, MarkdownPreviewSettings},
+    markdown_spellcheck::{check_block, Dictionary, Misspelling, Misspellings, Spellchecker},
+    markdown_ui_state::{source_hash, DocumentUiState, SavedScroll, UiStateStore},
+    CheckLinks, CloseLightbox, Copy, CopyAsHtml, CopyAsMarkdown, CopyDiagnostics,
+    DeployContextMenu, DismissFind, ExportHtml, ExportPdf, Find, FoldAllSections, GoBack,
+    GoForward, GoToHeading, Print, Reload, ResetZoom, SaveAs, ScrollLineDown, ScrollLineUp,
+    ScrollPageDown, ScrollPageUp, ScrollToBottom, ScrollToTop, SelectAll, SelectNextMatch,
+    SelectPreviousMatch, ToggleCaseSensitive, ToggleDebugOverlay, ToggleFollow, ToggleOutline,
+    UnfoldAllSections, ZoomIn, ZoomOut,
+};
+use anyhow::{Context as _, Result};
+use collections::{FxHashMap, FxHashSet};
+use futures::{channel::mpsc, StreamExt as _};
+use gpui::{
+    anchored, canvas, deferred, div, list, point, prelude::*, px, relative, AnyElement, AppContext,
+    Bounds, ClipboardItem, DismissEvent, DragMoveEvent, EventEmitter, ExternalPaths, FocusHandle,
+    FocusableView, KeyContext, KeyDownEvent, Keystroke, ListAlignment, ListOffset, ListState,
+    Modifiers, MouseButton, MouseDownEvent, NavigationDirection, Pixels, Point, ScrollHandle,
+    SharedString, Subscription, Task, View, ViewContext, WindowContext,
+};
+use language::{HighlightId, LanguageRegistry};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+use serde::{Deserialize, Serialize};
+use settings::{Settings as _, SettingsStore};
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, Read as _, Seek as _, SeekFrom},
+    mem::{self, Discriminant},
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use theme::ActiveTheme;
+use ui::{
+    h_flex, v_flex, Button, ButtonCommon, ButtonStyle, Clickable, Color, ContextMenu, Icon,
+    IconButton, IconName, IconSize, Label, LabelCommon, LabelSize, Selectable, Tooltip,
+    VisibleOnHover,
+};
+
+const MIN_TEXT_SCALE: f32 = 0.5;
+const MAX_TEXT_SCALE: f32 = 3.0;
+const TEXT_SCALE_STEP: f32 = 0.1;
+/// How long toasts, like the zoom level after it changes, stay on screen.
+const TOAST_DURATION: Duration = Duration::from_millis(1200);
+/// How long the heading a link jumped to stays highlighted.
+const FLASH_DURATION: Duration = Duration::from_millis(800);
+
+const SCROLL_ANIMATION_STEPS: usize = 12;
+const SCROLL_ANIMATION_STEP_DURATION: Duration = Duration::from_millis(16);
+
+/// The block at the top of the viewport, remembered so that the reader can
+/// be put back at it when the blocks around it change.
+struct ScrollAnchor {
+    block_ix: usize,
+    /// The Markdown source of the block.
+    source: String,
+    offset_in_item: Pixels,
+    /// How far into the block the top of the viewport was, as a fraction of
+    /// the block's height.
+    offset_fraction: f32,
+    /// The slug of the last heading before the block, and how many blocks
+    /// after it the block is.
+    section: Option<(String, usize)>,
+    /// How far into the document's source the block starts, as a fraction of
+    /// its length.
+    text_fraction: f32,
+}
+
+/// What [`DocumentUiState`] is computed from and is cheap to compare, so
+/// that it's only computed again once the reader has scrolled, folded or
+/// zoomed.
+#[derive(Clone, Copy, PartialEq)]
+struct UiStateKey {
+    parse_generation: usize,
+    scroll_top: (usize, Pixels),
+    folded_sections: usize,
+    expanded_code_blocks: usize,
+    front_matter_collapsed: bool,
+    text_scale: f32,
+}
+
+impl ScrollAnchor {
+    /// Finds the block in `blocks`, parsed from `text`, that the reader should
+    /// be put back at, and whether it's unchanged.
+    ///
+    /// Rewriting the file, like a formatter does, shifts and changes blocks
+    /// throughout, so the reader is put back in the same section: at the
+    /// block with the same source nearest to where the block was in it, or
+    /// otherwise the same number of blocks past its heading. Without the
+    /// heading, blocks are matched by their source anywhere in the document,
+    /// and as a last resort the reader is put the same fraction of the way
+    /// through it.
+    fn find_block(&self, blocks: &[ParsedMarkdownElement], text: &str) -> Option<(usize, bool)> {
+        let unchanged_near = |range: Range<usize>, ix: usize| {
+            blocks[range.clone()]
+                .iter()
+                .zip(range)
+                .filter(|(block, _)| text.get(block.source_range()) == Some(self.source.as_str()))
+                .min_by_key(|(_, block_ix)| block_ix.abs_diff(ix))
+                .map(|(_, block_ix)| block_ix)
+        };
+
+        if let Some(section) = self.find_section(blocks) {
+            let ix = (section.start + self.section.as_ref().map_or(0, |(_, past)| *past))
+                .min(section.end - 1);
+            return Some(match unchanged_near(section, ix) {
+                Some(ix) => (ix, true),
+                None => (ix, false),
+            });
+        }
+        if let Some(ix) = unchanged_near(0..blocks.len(), self.block_ix) {
+            return Some((ix, true));
+        }
+
+        let offset = (text.len() as f32 * self.text_fraction) as usize;
+        let ix = blocks
+            .iter()
+            .position(|block| block.source_range().end > offset)
+            .or_else(|| blocks.len().checked_sub(1))?;
+        Some((ix, false))
+    }
+
+    /// The blocks from the anchor's heading up to the next one, if the
+    /// heading is still there.
+    fn find_section(&self, blocks: &[ParsedMarkdownElement]) -> Option<Range<usize>> {
+        let (slug, _) = self.section.as_ref()?;
+        let start = blocks.iter().position(|block| {
+            matches!(block, ParsedMarkdownElement::Heading(heading) if heading.slug == *slug)
+        })?;
+        let end = blocks[start + 1..]
+            .iter()
+            .position(|block| matches!(block, ParsedMarkdownElement::Heading(_)))
+            .map_or(blocks.len(), |len| start + 1 + len);
+        Some(start..end)
+    }
+}
+
+/// What a background parse sends back to the view.
+enum ParseProgress {
+    /// More of the document's first blocks, to show until it's done.
+    Blocks(Vec<ParsedMarkdownElement>),
+    Done(ParsedMarkdown, Vec<usize>, String, Option<ParseTiming>),
+}
+
+/// The part of a document's text that a parse covers.
+struct ParseWindow {
+    text: String,
+    /// Where `text` starts in the document.
+    offset: usize,
+    /// The blocks that `text` replaces, or `None` if it's the whole
+    /// document.
+    blocks: Option<Range<usize>>,
+    /// The part of the parsed text that `text` replaces.
+    replaced: Range<usize>,
+    /// Where the last block of `text` has to end up, when blocks follow it,
+    /// for those blocks to be unaffected by the change. Changes that make the
+    /// block swallow text after it need the whole document parsed again.
+    last_block: Option<(Range<usize>, Discriminant<ParsedMarkdownElement>)>,
+    base_directory: Option<PathBuf>,
+    /// The version of the document `text` was taken from.
+    text_version: usize,
+}
+
+/// Whether a change to `text` may change how blocks after it are parsed, by
+/// opening or closing a construct that can span blank lines, like a code
+/// fence or an HTML comment.
+fn may_span_blocks(text: &str) -> bool {
+    const OPENERS_AND_CLOSERS: [&str; 13] = [
+        "```",
+        "~~~",
+        "$$",
+        "<!",
+        "<?",
+        "?>",
+        "-->",
+        "]]>",
+        "</",
+        "<pre",
+        "<script",
+        "<style",
+        "<textarea",
+    ];
+    OPENERS_AND_CLOSERS
+        .iter()
+        .any(|pattern| text.contains(pattern))
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix_len(a: &str, b: &str) -> usize {
+    a.bytes()
+        .rev()
+        .zip(b.bytes().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// Whether `text` may contain link reference or footnote definitions, like
+/// `[zed]: https://zed.dev`, including ones in block quotes and list items.
+fn may_define_references(text: &str) -> bool {
+    text.lines().any(|mut line| {
+        loop {
+            line = line.trim_start_matches([' ', '\t', '>']);
+            let numbered = line.trim_start_matches(|c: char| c.is_ascii_digit());
+            let after_marker = if numbered.len() < line.len() {
+                numbered.strip_prefix(['.', ')'])
+            } else {
+                line.strip_prefix(['-', '*', '+'])
+            };
+            match after_marker.filter(|rest| rest.is_empty() || rest.starts_with([' ', '\t'])) {
+                Some(rest) => line = rest,
+                None => break,
+            }
+        }
+        line.starts_with('[') && line.contains("]:")
+    })
+}
+
+/// Editors that save in several steps produce a burst of change events, which
+/// are coalesced into one reload.
+const FILE_RELOAD_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// How much of the end of the followed text is compared with the file, to
+/// tell whether text was only appended to it.
+const FOLLOWED_TAIL_LEN: usize = 256;
+
+/// Documents this long show their first blocks while the rest is parsed,
+/// when nothing else is on screen yet.
+const PARTIAL_CONTENTS_MIN_LEN: usize = 256 * 1024;
+
+/// How often the blocks shown while a document is parsed are brought up to
+/// date.
+const PARTIAL_CONTENTS_INTERVAL: Duration = Duration::from_millis(200);
+
+const DEFAULT_OUTLINE_WIDTH: Pixels = px(240.);
+const MIN_OUTLINE_WIDTH: Pixels = px(120.);
+const MAX_OUTLINE_WIDTH: Pixels = px(600.);
+
+/// Dragged to resize the outline panel.
+#[derive(Clone)]
+struct DraggedOutlineEdge;
+
+impl Render for DraggedOutlineEdge {
+    fn render(&mut self, _: &mut ViewContext<Self>) -> impl IntoElement {
+        gpui::Empty
+    }
+}
+
+/// What the reader did in the document, and how the document changed. Views
+/// emit these for every interaction, so that one subscription can take over
+/// or observe them all.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkdownEvent {
+    /// A link was clicked. Unless [`MarkdownView::set_suppress_default`] was
+    /// set, the view then opens it.
+    LinkActivated {
+        url: String,
+        kind: LinkKind,
+        /// Whether the link was written out or picked out of a bare URL, so
+        /// subscribers can treat the two differently.
+        origin: LinkOrigin,
+        /// The source range of the text the link is in.
+        source_range: Range<usize>,
+        modifiers: Modifiers,
+    },
+    /// The checkbox of a task list item was clicked, which is how it would
+    /// become `checked`. The source range is that of its `[ ]` marker.
+    /// Unless [`MarkdownView::set_suppress_default`] was set, the view then
+    /// writes the change to the file the document was opened from.
+    CheckboxToggled {
+        checked: bool,
+        source_range: Range<usize>,
+    },
+    ImageClicked {
+        url: String,
+        source_range: Range<usize>,
+        modifiers: Modifiers,
+    },
+    /// The document was parsed again, and its blocks, as indices into
+    /// [`ParsedMarkdown::children`], changed like this. Blocks that aren't
+    /// in any of the lists are the same as before, at the same index. A
+    /// block that was edited is removed and another inserted in its place.
+    ContentsChanged {
+        /// The new blocks, by their new index.
+        inserted: Vec<usize>,
+        /// The blocks that are gone, by their old index.
+        removed: Vec<usize>,
+        /// The blocks that are the same as before but at another index, as
+        /// their old and new index, in the order of the new one.
+        moved: Vec<(usize, usize)>,
+    },
+    /// The latest text given to [`MarkdownView::set_text`] or
+    /// [`MarkdownView::append_text`] was parsed, into this many blocks, and
+    /// [`MarkdownView::contents`] is up to date with it.
+    ///
+    /// It comes after the parse's [`Self::ContentsChanged`], and before the
+    /// frame that renders the new contents, so their
+    /// [`Self::LayoutChanged`] comes later. Text set while a parse is
+    /// running cancels it or is parsed after it, so only the parse of the
+    /// last text emits this.
+    ParseCompleted { blocks: usize, duration: Duration },
+    /// The blocks were laid out at other heights than before, so the
+    /// document's [`MarkdownView::layout_info`] changed.
+    LayoutChanged,
+    /// A suggestion for a misspelled word was picked from the context menu,
+    /// to replace the `word` at `source_range`. Unless
+    /// [`MarkdownView::set_suppress_default`] was set, the view then writes
+    /// the change to the file the document was opened from.
+    SpellingCorrected {
+        word: SharedString,
+        replacement: SharedString,
+        source_range: Range<usize>,
+    },
+}
+
+/// A place the reader can go back or forward to.
+enum HistoryEntry {
+    /// A position in the current document, from before a jump to a heading.
+    Position(ListOffset),
+    /// A document that was replaced by following a link to another.
+    Document(DocumentState),
+}
+
+struct DocumentState {
+    path: Option<PathBuf>,
+    text: String,
+    base_directory: Option<PathBuf>,
+    scroll_top: ListOffset,
+}
+
+/// What scrolls the document of a [`MarkdownView`].
+#[derive(Clone, Default)]
+enum ScrollMode {
+    /// The view, in a list that only lays out the blocks in view.
+    #[default]
+    Owned,
+    /// What the view is in, with the handle if the host gave one. The view
+    /// is as tall as the whole document.
+    Host(Option<ScrollHandle>),
+}
+
+/// What a [`MarkdownView`] shows for a document with nothing to preview.
+#[derive(Clone, Default)]
+enum EmptyState {
+    /// A note saying so, with the file's name and size.
+    #[default]
+    Message,
+    Hidden,
+    Custom(Rc<dyn Fn(&mut WindowContext) -> AnyElement>),
+}
+
+/// Where to scroll to once the document that's being parsed is shown.
+enum PendingScroll {
+    Fragment(String),
+    Offset(ListOffset),
+    Position(ScrollPosition),
+}
+
+/// Where the reader is in a document, in terms that outlast its layout, so
+/// that it can be restored after the application restarts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScrollPosition {
+    /// The block at the top of the viewport.
+    pub block_ix: usize,
+    /// How far into the block the top of the viewport is, as a fraction of
+    /// the block's height.
+    pub offset_fraction: f32,
+}
+
+/// Why a document couldn't be shown, in place of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadError {
+    pub message: SharedString,
+    /// The file that couldn't be read, which retrying reads again.
+    pub path: Option<PathBuf>,
+}
+
+/// The find-in-page bar, and the matches of its query.
+struct FindBar {
+    focus_handle: FocusHandle,
+    query: String,
+    case_sensitive: bool,
+    matches: Vec<SearchMatch>,
+    active_ix: Option<usize>,
+}
+
+/// The picker for jumping to a heading, opened with [`GoToHeading`].
+struct HeadingPicker {
+    focus_handle: FocusHandle,
+    query: String,
+    /// The indices of the outline entries that match the query, best first.
+    matches: Vec<usize>,
+    selected_ix: usize,
+}
+
+/// A pass over the document's links to find the broken ones, started with
+/// [`CheckLinks`].
+struct LinkCheck {
+    /// Why each broken link doesn't work, as the renderer shows it.
+    broken: Rc<FxHashMap<Link, SharedString>>,
+    checked: usize,
+    total: usize,
+    /// Checks the links to the web. Dropping it cancels the checks that are
+    /// still running.
+    task: Option<Task<()>>,
+}
+
+/// What follow mode knows of the file: how long it was, and how it ended.
+struct FollowedText {
+    len: usize,
+    tail: Vec<u8>,
+}
+
+impl FollowedText {
+    fn new(text: &str) -> Self {
+        let start = text.len().saturating_sub(FOLLOWED_TAIL_LEN);
+        Self {
+            len: text.len(),
+            tail: text.as_bytes()[start..].to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum FileChange {
+    /// Text was added to the end of the file.
+    Appended(String),
+    Replaced(String),
+}
+
+/// Reads what changed about the file at `path`. With `followed` given, the
+/// file is only read from where it ended before, unless it's shorter now or
+/// its end is different, in which case it was replaced and is read again.
+fn read_file_change(path: &Path, followed: Option<FollowedText>) -> io::Result<FileChange> {
+    if let Some(followed) = followed {
+        let mut file = std::fs::File::open(path)?;
+        if file.metadata()?.len() >= followed.len as u64 {
+            file.seek(SeekFrom::Start((followed.len - followed.tail.len()) as u64))?;
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            if bytes.starts_with(&followed.tail) {
+                bytes.drain(..followed.tail.len());
+                // A write that's still going on can end partway into a
+                // character, which is read along with the rest of it next
+                // time.
+                let valid_len = match std::str::from_utf8(&bytes) {
+                    Ok(_) => bytes.len(),
+                    Err(error) => error.valid_up_to(),
+                };
+                bytes.truncate(valid_len);
+                let chunk = String::from_utf8(bytes)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+                return Ok(FileChange::Appended(chunk));
+            }
+        }
+    }
+    std::fs::read_to_string(path).map(FileChange::Replaced)
+}
+
+struct FileWatch {
+    _watcher: RecommendedWatcher,
+    _task: Task<()>,
+}
+
+/// How many code blocks' highlights a view remembers.
+const HIGHLIGHT_CACHE_SIZE: usize = 1024;
+
+type CodeHighlights = Vec<(Range<usize>, HighlightId)>;
+
+/// The highlights of the code a view has shown, by a hash of the code, its
+/// language and the theme, so that code blocks that didn't change aren't
+/// highlighted again when the whole document is parsed again, like when its
+/// file is reloaded.
+#[derive(Default)]
+struct HighlightCache {
+    highlights: FxHashMap<u64, CodeHighlights>,
+    /// The keys, oldest first.
+    order: VecDeque<u64>,
+    /// How many code blocks were highlighted rather than found here.
+    computed: usize,
+}
+
+impl HighlightCache {
+    fn key(code: &str, language: &str, theme: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        code.hash(&mut hasher);
+        language.hash(&mut hasher);
+        theme.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get(&self, key: u64) -> Option<CodeHighlights> {
+        self.highlights.get(&key).cloned()
+    }
+
+    /// Remembers `highlights`, forgetting the oldest ones once there are too
+    /// many.
+    fn insert(&mut self, key: u64, highlights: CodeHighlights) {
+        self.computed += 1;
+        if self.highlights.insert(key, highlights).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > HIGHLIGHT_CACHE_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.highlights.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// A scrollable, lazily rendered preview of a Markdown document.
+pub struct MarkdownView {
+    raw_text: String,
+    contents: Option<ParsedMarkdown>,
+    /// Whether `contents` is only the start of the document, shown while the
+    /// rest of it is parsed.
+    partial_contents: bool,
+    /// The text `contents` was parsed from, which lags behind `raw_text`
+    /// while a parse is running.
+    contents_text: String,
+    base_directory: Option<PathBuf>,
+    /// The file the document was opened from, if it was.
+    path: Option<PathBuf>,
+    /// Whether the document was made from the clipboard and hasn't been
+    /// saved yet.
+    scratch: bool,
+    /// Why the last link to another document couldn't be followed.
+    navigation_error: Option<SharedString>,
+    /// Whether the banner with the document's parse diagnostics was closed.
+    /// It's shown again when a parse finds new ones.
+    diagnostics_dismissed: bool,
+    /// Why the document couldn't be read, shown instead of it.
+    load_error: Option<LoadError>,
+    file_watch: Option<FileWatch>,
+    /// The read of the file being opened, or of the document a link leads
+    /// to. Opening another, or going back or forward, drops it, which
+    /// cancels it, so that only the last place the reader went is shown.
+    pending_read: Option<Task<()>>,
+    /// The files the watched document includes, which are watched along
+    /// with it.
+    include_watch: Option<(Vec<PathBuf>, FileWatch)>,
+    /// Whether the watched file was deleted, in which case the last version
+    /// of it stays on screen.
+    file_removed: bool,
+    /// Bumped whenever the text changes.
+    text_version: usize,
+    /// Bumped whenever a parse starts, so that only the latest one applies.
+    parse_generation: usize,
+    parse_debounce: Duration,
+    auto_scroll: bool,
+    /// Whether text appended to the watched file is read on its own and
+    /// kept in view, like `tail -f`.
+    follow: bool,
+    /// Whether the end of the document was visible the last time the list
+    /// was scrolled or laid out.
+    at_bottom: Rc<Cell<bool>>,
+    list_state: ListState,
+    focus_handle: FocusHandle,
+    find_bar: Option<FindBar>,
+    heading_picker: Option<HeadingPicker>,
+    /// The find bar's matches, as the renderer highlights them.
+    search_highlights: Option<Rc<SearchHighlights>>,
+    /// The selected part of the document, as a range of its source.
+    selection: Option<Range<usize>>,
+    /// Whether the view leaves handling interactions to its
+    /// [`MarkdownEvent`] subscribers.
+    suppress_default: bool,
+    /// The document's headings, shown in the outline panel.
+    outline: Vec<OutlineEntry>,
+    /// How many words each block has, for the status bar.
+    word_counts: Vec<usize>,
+    outline_visible: bool,
+    front_matter_collapsed: bool,
+    /// The file whose [`DocumentUiState`] was restored, which the view keeps
+    /// in the [`UiStateStore`] from then on, and the state it last kept.
+    ui_state_path: Option<PathBuf>,
+    last_ui_state: Option<DocumentUiState>,
+    ui_state_key: Option<UiStateKey>,
+    /// Kept while the panel is hidden, so it comes back at the same width.
+    outline_width: Pixels,
+    /// The blocks hidden inside of folded sections, recomputed every frame
+    /// from the folded headings in the presentation state.
+    folded_blocks: Vec<Range<usize>>,
+    block_layouts: Rc<RefCell<BlockLayouts>>,
+    /// Where the blocks were laid out, along with the version of
+    /// [`Self::block_layouts`] and the block count it was worked out from.
+    layout_info: Option<(usize, usize, LayoutInfo)>,
+    /// Where the list the blocks are shown in was, as of the last frame, or
+    /// all of the blocks when the host scrolls them.
+    viewport_bounds: Rc<Cell<Bounds<Pixels>>>,
+    scroll_mode: ScrollMode,
+    empty_state: EmptyState,
+    /// The link, code block or image under the mouse, for the context menu.
+    hovered_element: Rc<RefCell<Option<HoveredElement>>>,
+    context_menu: Option<(View<ContextMenu>, Point<Pixels>, Subscription)>,
+    text_scale: f32,
+    /// A short message shown over the document, and the task that hides it
+    /// again.
+    toast: Option<(SharedString, Task<()>)>,
+    /// The block a link jumped to, highlighted for a moment.
+    flashed_block: Option<(usize, Task<()>)>,
+    /// The image shown at its full size over the document, after its
+    /// expand button was clicked.
+    lightbox: Option<Link>,
+    /// The last check of the document's links, shown in a banner until it's
+    /// dismissed.
+    link_check: Option<LinkCheck>,
+    /// Whether each block's bounds and the last parse and frame times are
+    /// drawn over the document, to debug how it renders.
+    debug_overlay: bool,
+    /// How long the last parse took, from starting it to its blocks being
+    /// shown.
+    last_parse_duration: Option<Duration>,
+    /// Where the time went in the last parse, which is only measured while
+    /// the debug overlay is shown.
+    last_parse_timing: Option<ParseTiming>,
+    /// How long the last frame took, from `render` to the end of its paint.
+    last_frame_duration: Rc<Cell<Option<Duration>>>,
+    /// Where the reader was before each jump to a heading or to another
+    /// document, latest last.
+    back_stack: Vec<HistoryEntry>,
+    /// Where the reader went back from, latest last.
+    forward_stack: Vec<HistoryEntry>,
+    pending_scroll: Option<PendingScroll>,
+    /// The width the document was last laid out at, to tell when the
+    /// window was resized.
+    layout_width: Option<Pixels>,
+    /// How far into a block the reader was, as a fraction of its height, to
+    /// restore once the block has been laid out again.
+    pending_scroll_fraction: Option<(usize, f32)>,
+    scroll_animation: Option<Task<()>>,
+    render_cache: Rc<RefCell<RenderCache>>,
+    presentation_state: Rc<RefCell<PresentationState>>,
+    image_policy: ImagePolicy,
+    link_policy: LinkPolicy,
+    /// Redraws the view when its images load, and keeps the ones it shows
+    /// from being evicted.
+    image_viewer: ImageViewer,
+    render_override: Option<RenderOverrideCallback>,
+    fence_handlers: Rc<FenceHandlers>,
+    code_runner: Option<CodeRunner>,
+    /// The code blocks being run, by the id of their [`CodeRun`]. Dropping
+    /// one cancels its run.
+    code_run_tasks: FxHashMap<usize, Task<()>>,
+    next_code_run_id: usize,
+    parse_options: ParseOptions,
+    /// Parses the document again when the `hard_wrap` setting changes,
+    /// unless the view was built with its own.
+    _hard_wrap_subscription: Option<Subscription>,
+    /// The misspelled words of the document, while the `spellcheck` setting
+    /// is on.
+    spellcheck: Option<SpellcheckState>,
+    _spellcheck_subscription: Subscription,
+    style: MarkdownStyle,
+    /// Whether the document can't be changed from the preview, like by
+    /// toggling its task list checkboxes.
+    read_only: bool,
+    /// Highlights code blocks once the document is parsed.
+    language_registry: Option<Arc<LanguageRegistry>>,
+    /// The running parse. Dropping it, like dropping the view does, cancels
+    /// the parse on the background executor too, so no work is left behind.
+    parsing_markdown_task: Option<Task<Result<()>>>,
+    highlighting_task: Option<Task<()>>,
+    highlight_cache: HighlightCache,
+}
+
+/// Configures a [`MarkdownView`] before it's created. Every option starts
+/// out the way [`MarkdownView::new`] sets it.
+pub struct MarkdownViewBuilder {
+    text: String,
+    base_directory: Option<PathBuf>,
+    parse_options: ParseOptions,
+    hard_wrap: Option<bool>,
+    style: MarkdownStyle,
+    image_policy: ImagePolicy,
+    link_policy: LinkPolicy,
+    read_only: bool,
+    language_registry: Option<Arc<LanguageRegistry>>,
+    code_runner: Option<CodeRunner>,
+    scroll_mode: ScrollMode,
+    request_headers: RequestHeaders,
+    empty_state: EmptyState,
+}
+
+/// Runs code blocks for the host, from [`MarkdownViewBuilder::on_run_code`].
+#[derive(Clone)]
+struct CodeRunner {
+    /// The languages it runs, lowercased.
+    languages: Rc<FxHashSet<SharedString>>,
+    run: Rc<dyn Fn(&str, &str, &mut WindowContext) -> Task<Result<String>>>,
+}
+
+struct SpellcheckState {
+    language: String,
+    /// The dictionary of the language, once it's loaded, if it's installed.
+    dictionary: Option<Arc<Dictionary>>,
+    /// The hashes of the document's blocks, by their index.
+    block_hashes: Vec<u64>,
+    /// The misspelled words of the blocks checked so far, by their hash, so
+    /// that only the blocks that change are checked again.
+    results: FxHashMap<u64, Arc<Vec<Misspelling>>>,
+    load_task: Option<Task<()>>,
+    check_task: Option<Task<()>>,
+}
+
+impl MarkdownViewBuilder {
+    /// Resolves relative links and images against `base_path`.
+    pub fn base_path(mut self, base_path: impl Into<PathBuf>) -> Self {
+        self.base_directory = Some(base_path.into());
+        self
+    }
+
+    pub fn parse_options(mut self, parse_options: ParseOptions) -> Self {
+        self.parse_options = parse_options;
+        self
+    }
+
+    /// Shows every line break in a paragraph, whatever the `hard_wrap`
+    /// setting is. Without this, the view follows the setting.
+    pub fn hard_wrap(mut self, hard_wrap: bool) -> Self {
+        self.hard_wrap = Some(hard_wrap);
+        self
+    }
+
+    pub fn style(mut self, style: MarkdownStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn image_policy(mut self, image_policy: ImagePolicy) -> Self {
+        self.image_policy = image_policy;
+        self
+    }
+
+    /// Sends `headers` with the requests for the document's web images on
+    /// the origin of `url`, like an `Authorization` header for an image host
+    /// that needs signing in to. Images anywhere else, and on other hosts a
+    /// server redirects to, are requested without them.
+    pub fn request_headers(mut self, url: &str, headers: Vec<(String, String)>) -> Self {
+        if let Err(error) = self.request_headers.insert(url, headers) {
+            log::error!(
+                "not sending request headers to {}: {:#}",
+                redact_url(url),
+                error
+            );
+        }
+        self
+    }
+
+    /// Which links open when they're clicked, like for documents that aren't
+    /// trusted.
+    pub fn link_policy(mut self, link_policy: LinkPolicy) -> Self {
+        self.link_policy = link_policy;
+        self
+    }
+
+    /// Keeps the document from being changed from the preview, like by
+    /// toggling its task list checkboxes.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Highlights code blocks in the languages of `language_registry`.
+    pub fn language_registry(mut self, language_registry: Arc<LanguageRegistry>) -> Self {
+        self.language_registry = Some(language_registry);
+        self
+    }
+
+    /// Gives the code blocks in `languages` a run button, which calls
+    /// `runner` with the block's language and code, and shows what the task
+    /// it returns resolves to under the block: the output, or the error.
+    /// Languages match whatever their case, and running a block again
+    /// replaces its output.
+    pub fn on_run_code<L: Into<SharedString>>(
+        mut self,
+        languages: impl IntoIterator<Item = L>,
+        runner: impl Fn(&str, &str, &mut WindowContext) -> Task<Result<String>> + 'static,
+    ) -> Self {
+        let languages = languages
+            .into_iter()
+            .map(|language| SharedString::from(language.into().to_lowercase()))
+            .collect();
+        self.code_runner = Some(CodeRunner {
+            languages: Rc::new(languages),
+            run: Rc::new(runner),
+        });
+        self
+    }
+
+    /// Lays out the whole document at its full height, without scrolling
+    /// it, for hosts that show it inside something of theirs that scrolls,
+    /// like a chat transcript. Every block is laid out each frame, so this
+    /// suits shorter documents. [`MarkdownView::content_height`] is how tall
+    /// it came out.
+    ///
+    /// Jumps to headings and the scrolling actions need a
+    /// [`Self::scroll_handle`] to scroll with.
+    pub fn auto_height(mut self) -> Self {
+        self.scroll_mode = ScrollMode::Host(None);
+        self
+    }
+
+    /// Like [`Self::auto_height`], inside something that scrolls with
+    /// `handle`, which jumps to headings and the scrolling actions then
+    /// scroll.
+    pub fn scroll_handle(mut self, handle: ScrollHandle) -> Self {
+        self.scroll_mode = ScrollMode::Host(Some(handle));
+        self
+    }
+
+    /// Shows what `render` returns instead of a document that has nothing
+    /// to preview, like an empty file, in place of the view's own note.
+    pub fn empty_state(
+        mut self,
+        render: impl Fn(&mut WindowContext) -> AnyElement + 'static,
+    ) -> Self {
+        self.empty_state = EmptyState::Custom(Rc::new(render));
+        self
+    }
+
+    /// Shows nothing for a document that has nothing to preview, rather
+    /// than saying so, like in a chat message that's still empty.
+    pub fn hide_empty_state(mut self) -> Self {
+        self.empty_state = EmptyState::Hidden;
+        self
+    }
+
+    pub fn build(self, cx: &mut WindowContext) -> View<MarkdownView> {
+        cx.new_view(|cx| MarkdownView::with_options(self, cx))
+    }
+}
+
+impl MarkdownView {
+    /// Previews `text`, which starts being parsed in the background right
+    /// away. The view emits [`MarkdownEvent::ParseCompleted`] once it's
+    /// parsed, and dropping the view cancels the parse.
+    pub fn from(text: String, cx: &mut ViewContext<Self>) -> Self {
+        Self::new(text, None, cx)
+    }
+
+    /// Previews `text`, resolving relative links and images against
+    /// `base_directory`.
+    pub fn new(text: String, base_directory: Option<PathBuf>, cx: &mut ViewContext<Self>) -> Self {
+        let mut builder = Self::builder(text);
+        builder.base_directory = base_directory;
+        Self::with_options(builder, cx)
+    }
+
+    /// Previews `text` as a new document that isn't saved anywhere yet, like
+    /// one pasted from the clipboard. It's called "Untitled" until
+    /// [`SaveAs`] writes it to a file.
+    pub fn scratch(text: String, cx: &mut ViewContext<Self>) -> Self {
+        let mut this = Self::new(text, None, cx);
+        this.scratch = true;
+        this
+    }
+
+    /// Starts configuring a view of `text`, which
+    /// [`MarkdownViewBuilder::build`] then creates.
+    pub fn builder(text: impl Into<String>) -> MarkdownViewBuilder {
+        MarkdownViewBuilder {
+            text: text.into(),
+            base_directory: None,
+            parse_options: ParseOptions::default(),
+            hard_wrap: None,
+            style: MarkdownStyle::default(),
+            image_policy: ImagePolicy::default(),
+            link_policy: LinkPolicy::default(),
+            read_only: false,
+            language_registry: None,
+            code_runner: None,
+            scroll_mode: ScrollMode::default(),
+            request_headers: RequestHeaders::default(),
+            empty_state: EmptyState::default(),
+        }
+    }
+
+    fn with_options(mut builder: MarkdownViewBuilder, cx: &mut ViewContext<Self>) -> Self {
+        // Only the blocks near the viewport are built each frame; the list
+        // measures them as they are laid out so the scrollbar stays stable.
+        let view = cx.view().downgrade();
+        let list_state = ListState::new(0, ListAlignment::Top, px(1000.), move |ix, cx| {
+            if let Some(view) = view.upgrade() {
+                view.update(cx, |this, cx| this.render_block(ix, cx))
+            } else {
+                div().into_any()
+            }
+        });
+
+        let at_bottom = Rc::new(Cell::new(true));
+        list_state.set_scroll_handler({
+            let at_bottom = at_bottom.clone();
+            move |event, _| at_bottom.set(event.visible_range.end >= event.count)
+        });
+
+        builder.parse_options.hard_wrap = builder
+            .hard_wrap
+            .unwrap_or(MarkdownPreviewSettings::get_global(cx).hard_wrap);
+        let hard_wrap_subscription = builder.hard_wrap.is_none().then(|| {
+            cx.observe_global::<SettingsStore>(|this, cx| {
+                let hard_wrap = MarkdownPreviewSettings::get_global(cx).hard_wrap;
+                if this.parse_options.hard_wrap != hard_wrap {
+                    this.parse_options.hard_wrap = hard_wrap;
+                    this.schedule_parse(true, cx);
+                }
+            })
+        });
+
+        let mut this = Self {
+            raw_text: String::new(),
+            contents: None,
+            partial_contents: false,
+            contents_text: String::new(),
+            base_directory: builder.base_directory,
+            path: None,
+            scratch: false,
+            navigation_error: None,
+            diagnostics_dismissed: false,
+            load_error: None,
+            file_watch: None,
+            pending_read: None,
+            include_watch: None,
+            file_removed: false,
+            text_version: 0,
+            parse_generation: 0,
+            parse_debounce: Duration::ZERO,
+            auto_scroll: false,
+            follow: false,
+            at_bottom,
+            list_state,
+            focus_handle: cx.focus_handle(),
+            find_bar: None,
+            heading_picker: None,
+            search_highlights: None,
+            selection: None,
+            suppress_default: false,
+            outline: Vec::new(),
+            word_counts: Vec::new(),
+            outline_visible: false,
+            front_matter_collapsed: false,
+            ui_state_path: None,
+            last_ui_state: None,
+            ui_state_key: None,
+            outline_width: DEFAULT_OUTLINE_WIDTH,
+            folded_blocks: Vec::new(),
+            block_layouts: Rc::default(),
+            layout_info: None,
+            viewport_bounds: Rc::default(),
+            scroll_mode: builder.scroll_mode,
+            empty_state: builder.empty_state,
+            hovered_element: Rc::default(),
+            context_menu: None,
+            text_scale: MarkdownPreviewSettings::get_global(cx)
+                .text_scale
+                .clamp(MIN_TEXT_SCALE, MAX_TEXT_SCALE),
+            toast: None,
+            lightbox: None,
+            link_check: None,
+            flashed_block: None,
+            debug_overlay: false,
+            last_parse_duration: None,
+            last_parse_timing: None,
+            last_frame_duration: Rc::default(),
+            back_stack: Vec::new(),
+            forward_stack: Vec::new(),
+            pending_scroll: None,
+            layout_width: None,
+            pending_scroll_fraction: None,
+            scroll_animation: None,
+            render_cache: Rc::default(),
+            presentation_state: Rc::default(),
+            image_policy: builder.image_policy,
+            link_policy: builder.link_policy,
+            image_viewer: ImageViewer::new({
+                let view = cx.view().downgrade();
+                move |cx| {
+                    view.update(cx, |_, cx| cx.notify()).ok();
+                }
+            }),
+            render_override: None,
+            fence_handlers: Rc::new(FenceHandlers::builtin()),
+            code_runner: builder.code_runner,
+            code_run_tasks: FxHashMap::default(),
+            next_code_run_id: 0,
+            parse_options: builder.parse_options,
+            _hard_wrap_subscription: hard_wrap_subscription,
+            spellcheck: None,
+            _spellcheck_subscription: cx
+                .observe_global::<SettingsStore>(|this, cx| this.update_spellcheck(cx)),
+            style: builder.style,
+            read_only: builder.read_only,
+            language_registry: builder.language_registry,
+            parsing_markdown_task: None,
+            highlighting_task: None,
+            highlight_cache: HighlightCache::default(),
+        };
+        this.image_viewer
+            .set_request_headers(builder.request_headers);
+        this.update_spellcheck(cx);
+        this.set_text(builder.text, cx);
+        this
+    }
+
+    /// Replaces the previewed document with `text`.
+    ///
+    /// The current document stays on screen until `text` has been parsed.
+    /// When this is called again before then, only the latest text is shown.
+    /// Only the blocks around the parts of `text` that changed are parsed
+    /// again, so editing a large document stays fast.
+    pub fn set_text(&mut self, text: String, cx: &mut ViewContext<Self>) {
+        self.update_text(text, false, cx);
+    }
+
+    /// Like [`MarkdownView::set_text`], but parses all of `text`, for when
+    /// the document is a different one and its links resolve differently.
+    fn replace_text(&mut self, text: String, cx: &mut ViewContext<Self>) {
+        self.image_viewer.cancel_loads();
+        self.update_text(text, true, cx);
+    }
+
+    fn update_text(&mut self, text: String, full: bool, cx: &mut ViewContext<Self>) {
+        self.load_error = None;
+        self.raw_text = text;
+        self.text_version += 1;
+        self.schedule_parse(full, cx);
+    }
+
+    /// Appends `chunk` to the previewed document, for documents that arrive
+    /// bit by bit, like streamed responses.
+    ///
+    /// Only the last blocks of the document are parsed again, and appends
+    /// that arrive while a parse is running are parsed together afterwards.
+    pub fn append_text(&mut self, chunk: &str, cx: &mut ViewContext<Self>) {
+        self.raw_text.push_str(chunk);
+        self.text_version += 1;
+        if self.parsing_markdown_task.is_none() {
+            self.schedule_parse(false, cx);
+        }
+    }
+
+    /// Keeps the end of the document in view as text is appended, as long as
+    /// it was in view before.
+    pub fn set_auto_scroll(&mut self, auto_scroll: bool) {
+        self.auto_scroll = auto_scroll;
+    }
+
+    /// Turns follow mode on or off. While it's on, text appended to the
+    /// watched file is parsed on its own and the end of the document is kept
+    /// in view, until the reader scrolls up.
+    pub fn toggle_follow(&mut self, _: &ToggleFollow, cx: &mut ViewContext<Self>) {
+        self.set_follow(!self.follow, cx);
+        if self.follow {
+            self.show_toast("Following the file", cx);
+        } else {
+            self.show_toast("Stopped following the file", cx);
+        }
+    }
+
+    /// Turns follow mode on or off like [`Self::toggle_follow`], without
+    /// saying so.
+    pub fn set_follow(&mut self, follow: bool, cx: &mut ViewContext<Self>) {
+        self.follow = follow;
+        if follow {
+            self.jump_to_bottom(cx);
+        }
+        cx.notify();
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.follow
+    }
+
+    /// Shows or hides the debug overlay, which outlines every block with its
+    /// kind, source lines and height, and shows how long the last parse and
+    /// frame took. Exports and printing work from the parsed document, so
+    /// the overlay never ends up in them.
+    pub fn toggle_debug_overlay(&mut self, _: &ToggleDebugOverlay, cx: &mut ViewContext<Self>) {
+        self.debug_overlay = !self.debug_overlay;
+        // The parts of the last parse weren't measured, so the document is
+        // parsed again to show them.
+        if self.debug_overlay && self.last_parse_timing.is_none() && self.contents.is_some() {
+            self.schedule_parse(true, cx);
+        }
+        cx.notify();
+    }
+
+    /// Shows the image at `link` over the document, as large as the window
+    /// allows.
+    fn show_lightbox(&mut self, link: Link, cx: &mut ViewContext<Self>) {
+        self.lightbox = Some(link);
+        cx.notify();
+    }
+
+    pub fn close_lightbox(&mut self, _: &CloseLightbox, cx: &mut ViewContext<Self>) {
+        if self.lightbox.take().is_some() {
+            cx.notify();
+        }
+    }
+
+    pub fn is_debug_overlay_visible(&self) -> bool {
+        self.debug_overlay
+    }
+
+    /// The debug overlay's label for the block at `block_ix`: its index,
+    /// kind and the lines of the source it came from, like `#3 paragraph
+    /// L5–7`. Blocks included from other files are marked as such instead of
+    /// with their lines.
+    pub fn block_debug_label(&self, block_ix: usize) -> Option<String> {
+        let contents = self.contents.as_ref()?;
+        let block = contents.children.get(block_ix)?;
+        let kind = match block {
+            ParsedMarkdownElement::Heading(_) => "heading",
+            ParsedMarkdownElement::ListItem(_) => "list item",
+            ParsedMarkdownElement::Table(_) => "table",
+            ParsedMarkdownElement::BlockQuote(_) => "block quote",
+            ParsedMarkdownElement::CodeBlock(_) => "code block",
+            ParsedMarkdownElement::Image(_) => "image",
+            ParsedMarkdownElement::Paragraph(_) => "paragraph",
+            ParsedMarkdownElement::HorizontalRule(_) => "rule",
+        };
+        if contents.is_included(block_ix) {
+            return Some(format!("#{block_ix} {kind} (included)"));
+        }
+        let range = block.source_range();
+        let line_at = |offset: usize| {
+            let offset = offset.min(self.contents_text.len());
+            self.contents_text.as_bytes()[..offset]
+                .iter()
+                .filter(|byte| **byte == b'\n')
+                .count()
+                + 1
+        };
+        let start = line_at(range.start);
+        // Blocks' ranges usually end after their last newline.
+        let end = line_at(range.end.saturating_sub(1)).max(start);
+        Some(format!("#{block_ix} {kind} L{start}–{end}"))
+    }
+
+    /// Scrolls to the end of the document, which is then kept in view as
+    /// text is appended again.
+    fn jump_to_bottom(&mut self, cx: &mut ViewContext<Self>) {
+        // Only scrolling by hand tells the list whether the end is visible.
+        self.at_bottom.set(true);
+        self.scroll_to_bottom(&ScrollToBottom, cx);
+    }
+
+    /// Waits until the text has stopped changing for `debounce` before
+    /// parsing it, so that bursts of changes are parsed once.
+    pub fn set_parse_debounce(&mut self, debounce: Duration) {
+        self.parse_debounce = debounce;
+    }
+
+    /// Starts parsing the current text, or only the part of it that changed
+    /// if `full` is false, cancelling the parse that was running.
+    fn schedule_parse(&mut self, full: bool, cx: &mut ViewContext<Self>) {
+        // Dropping the running task cancels it, and the generation makes sure
+        // a result that was already on its way isn't applied either.
+        self.parse_generation += 1;
+        let generation = self.parse_generation;
+        let parse_options = ParseOptions {
+            document_path: self.path.clone(),
+            ..self.parse_options.clone()
+        };
+        // Nothing is shown until the first parse lands, so it isn't delayed.
+        let debounce = if self.contents.is_none() {
+            Duration::ZERO
+        } else {
+            self.parse_debounce
+        };
+        let task = cx.spawn(|markdown_view, mut cx| async move {
+            if !debounce.is_zero() {
+                cx.background_executor().timer(debounce).await;
+            }
+
+            let (window, show_partial, timed) = markdown_view.update(&mut cx, |markdown, _| {
+                let window = markdown.parse_window(full);
+                // The debug overlay shows where the time went, so its parses
+                // are timed, which keeps them in order on one thread.
+                let timed = markdown.debug_overlay;
+                // The first blocks of a long document are shown while the
+                // rest is parsed, unless they'd cover up another document or
+                // the reader is waiting to be taken further down.
+                let show_partial = !timed
+                    && window.blocks.is_none()
+                    && window.text.len() >= PARTIAL_CONTENTS_MIN_LEN
+                    && (markdown.contents.is_none() || markdown.partial_contents)
+                    && markdown.pending_scroll.is_none();
+                (window, show_partial, timed)
+            })?;
+            let ParseWindow {
+                text,
+                offset,
+                blocks,
+                replaced,
+                last_block: expected_last_block,
+                base_directory,
+                text_version,
+            } = window;
+
+            let partial_text = show_partial.then(|| text.clone());
+            let (progress_tx, mut progress_rx) = mpsc::unbounded();
+            let executor = cx.background_executor().clone();
+            let parse_started = Instant::now();
+            log::debug!(
+                bytes = text.len(),
+                incremental = blocks.is_some(),
+                generation = generation;
+                "parse started"
+            );
+            // Dropping this task, along with the one it runs in, cancels the
+            // parse at its next chunk.
+            let _parse = cx.background_executor().spawn(async move {
+                // Blocks that are shown as they come are parsed in order,
+                // and the rest of long documents on all threads at once.
+                let mut timing = None;
+                let mut parsed = if timed {
+                    let (parsed, parse_timing) =
+                        parse_markdown_timed(&text, base_directory, None, &parse_options).await;
+                    timing = Some(parse_timing);
+                    parsed
+                } else if show_partial {
+                    let partial_tx = progress_tx.clone();
+                    parse_markdown_in_chunks(
+                        &text,
+                        base_directory,
+                        None,
+                        &parse_options,
+                        |blocks| {
+                            partial_tx
+                                .unbounded_send(ParseProgress::Blocks(blocks.to_vec()))
+                                .ok();
+                        },
+                    )
+                    .await
+                } else {
+                    parse_markdown_parallel(&text, base_directory, None, &parse_options, &executor)
+                        .await
+                };
+                for block in &mut parsed.children {
+                    block.offset_source_ranges(offset as isize);
+                }
+                for diagnostic in &mut parsed.diagnostics {
+                    diagnostic.offset_source_range(offset as isize);
+                }
+                let word_counts = parsed
+                    .children
+                    .iter()
+                    .map(ParsedMarkdownElement::word_count)
+                    .collect();
+                progress_tx
+                    .unbounded_send(ParseProgress::Done(parsed, word_counts, text, timing))
+                    .ok();
+            });
+
+            let mut partial_blocks = Vec::new();
+            let mut partial_shown_at: Option<Instant> = None;
+            let (parsed, word_counts, text, timing) = 'parse: loop {
+                match progress_rx.next().await {
+                    Some(ParseProgress::Done(parsed, word_counts, text, timing)) => {
+                        break (parsed, word_counts, text, timing)
+                    }
+                    Some(ParseProgress::Blocks(blocks)) => {
+                        partial_blocks.extend(blocks);
+                        // There's no point showing blocks that are already
+                        // out of date.
+                        while let Ok(Some(progress)) = progress_rx.try_next() {
+                            match progress {
+                                ParseProgress::Blocks(blocks) => partial_blocks.extend(blocks),
+                                ParseProgress::Done(parsed, word_counts, text, timing) => {
+                                    break 'parse (parsed, word_counts, text, timing)
+                                }
+                            }
+                        }
+                        if partial_shown_at
+                            .map_or(false, |at| at.elapsed() < PARTIAL_CONTENTS_INTERVAL)
+                        {
+                            continue;
+                        }
+                        partial_shown_at = Some(Instant::now());
+                        let blocks = partial_blocks.clone();
+                        let text = partial_text.clone().unwrap_or_default();
+                        markdown_view.update(&mut cx, |markdown, cx| {
+                            if markdown.parse_generation == generation {
+                                markdown.show_partial_contents(blocks, text, cx);
+                            }
+                        })?;
+                    }
+                    None => return Ok(()),
+                }
+            };
+
+            markdown_view.update(&mut cx, |markdown, cx| {
+                if markdown.parse_generation != generation {
+                    return;
+                }
+
+                markdown.parsing_markdown_task.take();
+                let parse_duration = parse_started.elapsed();
+                markdown.last_parse_duration = Some(parse_duration);
+                markdown.last_parse_timing = timing;
+                log::debug!(
+                    blocks = parsed.children.len(),
+                    milliseconds = parse_duration.as_secs_f64() * 1000.,
+                    generation = generation;
+                    "parse finished"
+                );
+                let last_block = parsed
+                    .children
+                    .last()
+                    .map(|block| (block.source_range(), mem::discriminant(block)));
+                match blocks {
+                    // Blocks from other files can't be spliced in by their
+                    // offsets, so adding an include parses everything.
+                    Some(_) if !parsed.includes.is_empty() => {
+                        markdown.schedule_parse(true, cx);
+                        return;
+                    }
+                    // Front matter is only recognized at the start of the
+                    // document, so a window that seems to have it doesn't.
+                    Some(_)
+                        if (offset > 0 && parsed.front_matter.is_some())
+                            || (expected_last_block.is_some()
+                                && expected_last_block != last_block) =>
+                    {
+                        markdown.schedule_parse(true, cx);
+                        return;
+                    }
+                    Some(blocks) => {
+                        markdown.splice_contents(blocks, replaced, &text, parsed, word_counts, cx)
+                    }
+                    None => {
+                        markdown.set_contents(parsed, word_counts, text, cx);
+                        markdown.restore_ui_state(cx);
+                    }
+                }
+
+                // Text appended while parsing is parsed next.
+                if markdown.text_version != text_version {
+                    markdown.schedule_parse(false, cx);
+                } else {
+                    cx.emit(MarkdownEvent::ParseCompleted {
+                        blocks: markdown.contents.as_ref().map_or(0, |c| c.children.len()),
+                        duration: parse_duration,
+                    });
+                }
+            })
+        });
+        self.parsing_markdown_task = Some(task);
+    }
+
+    /// Picks the part of the text to parse. Unless `full` is set, that's
+    /// only the blocks around the part of the text that changed since it was
+    /// last parsed.
+    fn parse_window(&self, full: bool) -> ParseWindow {
+        let full_window = || ParseWindow {
+            text: self.raw_text.clone(),
+            offset: 0,
+            blocks: None,
+            replaced: 0..self.contents_text.len(),
+            last_block: None,
+            base_directory: self.base_directory.clone(),
+            text_version: self.text_version,
+        };
+
+        // Included blocks' source ranges are into other files, so they
+        // can't be used to find where blocks start.
+        let Some(contents) = self
+            .contents
+            .as_ref()
+            .filter(|contents| !full && !self.partial_contents && contents.includes.is_empty())
+        else {
+            return full_window();
+        };
+        let old_text = self.contents_text.as_str();
+        let new_text = self.raw_text.as_str();
+        // Parsing the same text again is how it's re-parsed with new
+        // settings.
+        if old_text == new_text {
+            return full_window();
+        }
+
+        // The changed part of the text, in the parsed text, is what's left
+        // after the start and end they have in common.
+        let mut prefix_len = common_prefix_len(old_text, new_text);
+        while !old_text.is_char_boundary(prefix_len) || !new_text.is_char_boundary(prefix_len) {
+            prefix_len -= 1;
+        }
+        let max_suffix_len = old_text.len().min(new_text.len()) - prefix_len;
+        let mut suffix_len = common_suffix_len(old_text, new_text).min(max_suffix_len);
+        while !old_text.is_char_boundary(old_text.len() - suffix_len)
+            || !new_text.is_char_boundary(new_text.len() - suffix_len)
+        {
+            suffix_len -= 1;
+        }
+        let changed = prefix_len..old_text.len() - suffix_len;
+        let delta = new_text.len() as isize - old_text.len() as isize;
+
+        // Blocks can start partway into a line, like list items after their
+        // indentation, and that indentation matters to how they're parsed.
+        let children = &contents.children;
+        let line_start = |offset: usize| {
+            old_text[..offset]
+                .rfind('\n')
+                .map_or(0, |newline_ix| newline_ix + 1)
+        };
+        // Most blocks end at a blank line, so the window is only cut where
+        // there's one.
+        let follows_blank_line = |block_ix: usize| {
+            let start = line_start(children[block_ix].source_range().start);
+            start == 0
+                || old_text[..start - 1]
+                    .rsplit('\n')
+                    .next()
+                    .map_or(true, |line| line.trim().is_empty())
+        };
+        let is_list_item = |ix: usize| children.get(ix).map_or(false, |block| block.is_list_item());
+
+        // The blocks next to the ones that changed are parsed again too, as
+        // the change can still join them, e.g. by removing the blank line
+        // between two paragraphs. Lists are a run of items, which are
+        // re-parsed together.
+        let first_changed = children
+            .iter()
+            .position(|block| block.source_range().end >= changed.start)
+            .unwrap_or(children.len());
+        let mut first_block_ix = first_changed.saturating_sub(1);
+        while first_block_ix > 0
+            && ((is_list_item(first_block_ix) && is_list_item(first_block_ix - 1))
+                || !follows_blank_line(first_block_ix))
+        {
+            first_block_ix -= 1;
+        }
+        let after_changed = children
+            .iter()
+            .position(|block| block.source_range().start > changed.end)
+            .unwrap_or(children.len());
+        let mut end_block_ix = (after_changed + 1).min(children.len());
+        while end_block_ix < children.len()
+            && ((is_list_item(end_block_ix - 1) && is_list_item(end_block_ix))
+                || !follows_blank_line(end_block_ix))
+        {
+            end_block_ix += 1;
+        }
+        if first_block_ix == 0 && end_block_ix == children.len() {
+            return full_window();
+        }
+
+        let offset = match first_block_ix {
+            0 => 0,
+            ix => line_start(children[ix].source_range().start),
+        };
+        let replaced_end = match children.get(end_block_ix) {
+            Some(block) => line_start(block.source_range().start),
+            None => old_text.len(),
+        };
+        if offset > changed.start || replaced_end < changed.end {
+            return full_window();
+        }
+        let replaced = offset..replaced_end;
+
+        // Link reference definitions apply to the whole document, so no part
+        // of it can be parsed on its own when anything could have defined
+        // them, or a change removed one. Constructs that span blank lines
+        // could swallow the blocks after the change.
+        if may_define_references(new_text) || may_define_references(&old_text[replaced.clone()]) {
+            return full_window();
+        }
+        let last_block = children.get(end_block_ix).map(|_| {
+            let block = &children[end_block_ix - 1];
+            let range = block.source_range();
+            let shift = |offset: usize| (offset as isize + delta) as usize;
+            (
+                shift(range.start)..shift(range.end),
+                mem::discriminant(block),
+            )
+        });
+        if last_block.is_some()
+            && (may_span_blocks(&old_text[changed.clone()])
+                || may_span_blocks(
+                    &new_text[changed.start..(changed.end as isize + delta) as usize],
+                ))
+        {
+            return full_window();
+        }
+
+        ParseWindow {
+            text: new_text[offset..(replaced_end as isize + delta) as usize].to_string(),
+            offset,
+            blocks: Some(first_block_ix..end_block_ix),
+            replaced,
+            last_block,
+            base_directory: self.base_directory.clone(),
+            text_version: self.text_version,
+        }
+    }
+
+    /// Replaces the `blocks` parsed from the `replaced` part of the parsed
+    /// text with the ones `parsed` from `text`, and moves the blocks after
+    /// them to where they are in the new text.
+    fn splice_contents(
+        &mut self,
+        blocks: Range<usize>,
+        replaced: Range<usize>,
+        text: &str,
+        parsed: ParsedMarkdown,
+        word_counts: Vec<usize>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(contents) = &mut self.contents else {
+            return;
+        };
+
+        let old_hashes = contents.block_hashes(&self.contents_text);
+        let follow = (self.auto_scroll || self.follow) && self.at_bottom.get();
+        let delta = text.len() as isize - replaced.len() as isize;
+        let new_block_count = parsed.children.len();
+        for block in &mut contents.children[blocks.end..] {
+            block.offset_source_ranges(delta);
+        }
+        contents.children.splice(blocks.clone(), parsed.children);
+        contents
+            .diagnostics
+            .retain(|diagnostic| !replaced.contains(&diagnostic.source_range.start));
+        for diagnostic in &mut contents.diagnostics {
+            if diagnostic.source_range.start >= replaced.end {
+                diagnostic.offset_source_range(delta);
+            }
+        }
+        let insert_ix = contents
+            .diagnostics
+            .partition_point(|diagnostic| diagnostic.source_range.start < replaced.start);
+        if !parsed.diagnostics.is_empty() {
+            self.diagnostics_dismissed = false;
+        }
+        contents
+            .diagnostics
+            .splice(insert_ix..insert_ix, parsed.diagnostics);
+        if replaced.start == 0 {
+            contents.front_matter = parsed.front_matter;
+        }
+        self.word_counts.splice(blocks.clone(), word_counts);
+        assign_heading_slugs(&mut contents.children);
+        let block_count = contents.children.len();
+        self.contents_text.replace_range(replaced.clone(), text);
+        self.outline = contents.outline();
+        // The selection's range is into the old text, which only still holds
+        // before the change.
+        if self
+            .selection
+            .as_ref()
+            .map_or(false, |selection| selection.end > replaced.start)
+        {
+            self.selection = None;
+        }
+        self.update_search(false, cx);
+
+        self.list_state.splice(blocks.clone(), new_block_count);
+        self.render_cache
+            .borrow_mut()
+            .splice(replaced.clone(), delta);
+        self.block_layouts
+            .borrow_mut()
+            .splice(blocks, new_block_count);
+        self.presentation_state.borrow_mut().splice(replaced, delta);
+
+        if follow && block_count > 0 {
+            self.list_state.scroll_to_reveal_item(block_count - 1);
+        }
+        self.schedule_highlighting(cx);
+        self.emit_contents_changed(&old_hashes, cx);
+        cx.notify();
+    }
+
+    /// Tells subscribers which blocks changed since the document had blocks
+    /// with `old_hashes`, and spellchecks the ones that did.
+    fn emit_contents_changed(&mut self, old_hashes: &[u64], cx: &mut ViewContext<Self>) {
+        let new_hashes = self
+            .contents
+            .as_ref()
+            .map(|contents| contents.block_hashes(&self.contents_text))
+            .unwrap_or_default();
+        cx.emit(contents_changed(old_hashes, &new_hashes));
+        self.schedule_spellcheck(new_hashes, cx);
+    }
+
+    /// Starts or stops spellchecking as the settings say, loading the
+    /// dictionary of the language they name. Turning it off drops the
+    /// misspellings, without parsing the document again.
+    fn update_spellcheck(&mut self, cx: &mut ViewContext<Self>) {
+        let settings = MarkdownPreviewSettings::get_global(cx);
+        if !settings.spellcheck {
+            if self.spellcheck.take().is_some() {
+                cx.notify();
+            }
+            return;
+        }
+        let language = settings.spellcheck_language.clone();
+        if self
+            .spellcheck
+            .as_ref()
+            .is_some_and(|spellcheck| spellcheck.language == language)
+        {
+            return;
+        }
+
+        // The words added to the dictionary are read before they're needed.
+        Spellchecker::added_words(cx);
+        let dictionary = Spellchecker::dictionary(&language, cx);
+        let load_task = dictionary.is_none().then(|| {
+            let language = language.clone();
+            cx.spawn(|this, mut cx| async move {
+                let loaded = cx
+                    .background_executor()
+                    .spawn({
+                        let language = language.clone();
+                        async move { Dictionary::load(&language) }
+                    })
+                    .await;
+                this.update(&mut cx, |this, cx| {
+                    let dictionary = match loaded {
+                        Ok(dictionary) => Arc::new(dictionary),
+                        Err(error) => {
+                            log::error!("failed to load a dictionary: {:#}", error);
+                            this.show_toast(
+                                format!("There's no {language} dictionary to spellcheck with"),
+                                cx,
+                            );
+                            return;
+                        }
+                    };
+                    Spellchecker::insert_dictionary(language, dictionary.clone(), cx);
+                    if let Some(spellcheck) = &mut this.spellcheck {
+                        spellcheck.dictionary = Some(dictionary);
+                        let block_hashes = mem::take(&mut spellcheck.block_hashes);
+                        this.schedule_spellcheck(block_hashes, cx);
+                    }
+                })
+                .ok();
+            })
+        });
+        let block_hashes = self
+            .contents
+            .as_ref()
+            .map(|contents| contents.block_hashes(&self.contents_text))
+            .unwrap_or_default();
+        self.spellcheck = Some(SpellcheckState {
+            language,
+            dictionary,
+            block_hashes: Vec::new(),
+            results: FxHashMap::default(),
+            load_task,
+            check_task: None,
+        });
+        self.schedule_spellcheck(block_hashes, cx);
+        cx.notify();
+    }
+
+    /// Checks the spelling of the blocks with `block_hashes` that weren't
+    /// checked before, on the background executor, and forgets the blocks
+    /// that are gone. Blocks included from other files aren't checked.
+    fn schedule_spellcheck(&mut self, block_hashes: Vec<u64>, cx: &mut ViewContext<Self>) {
+        let Some(spellcheck) = &mut self.spellcheck else {
+            return;
+        };
+        let current: FxHashSet<u64> = block_hashes.iter().copied().collect();
+        spellcheck
+            .results
+            .retain(|block_hash, _| current.contains(block_hash));
+        spellcheck.block_hashes = block_hashes;
+        spellcheck.check_task = None;
+        let (Some(dictionary), Some(contents)) =
+            (spellcheck.dictionary.clone(), self.contents.as_ref())
+        else {
+            return;
+        };
+
+        let mut checked = FxHashSet::default();
+        let pending: Vec<(u64, ParsedMarkdownElement)> = spellcheck
+            .block_hashes
+            .iter()
+            .zip(&contents.children)
+            .enumerate()
+            .filter(|(block_ix, (block_hash, _))| {
+                !contents.is_included(*block_ix)
+                    && !spellcheck.results.contains_key(*block_hash)
+                    && checked.insert(**block_hash)
+            })
+            .map(|(_, (block_hash, block))| (*block_hash, block.clone()))
+            .collect();
+        if pending.is_empty() {
+            return;
+        }
+
+        spellcheck.check_task = Some(cx.spawn(|this, mut cx| async move {
+            let results: Vec<(u64, Arc<Vec<Misspelling>>)> = cx
+                .background_executor()
+                .spawn(async move {
+                    pending
+                        .into_iter()
+                        .map(|(block_hash, block)| {
+                            (block_hash, Arc::new(check_block(&block, &dictionary)))
+                        })
+                        .collect()
+                })
+                .await;
+            this.update(&mut cx, |this, cx| {
+                if let Some(spellcheck) = &mut this.spellcheck {
+                    spellcheck.results.extend(results);
+                    cx.notify();
+                }
+            })
+            .ok();
+        }));
+    }
+
+    /// The misspelled words of the block at `block_ix` that weren't added to
+    /// the dictionary, if it was checked.
+    fn block_misspellings(&self, block_ix: usize, cx: &AppContext) -> Option<Misspellings> {
+        let spellcheck = self.spellcheck.as_ref()?;
+        let block = self.contents.as_ref()?.children.get(block_ix)?;
+        let block_hash = spellcheck.block_hashes.get(block_ix)?;
+        let misspellings: Vec<Misspelling> = spellcheck
+            .results
+            .get(block_hash)?
+            .iter()
+            .filter(|misspelling| !Spellchecker::is_added(&misspelling.word, cx))
+            .cloned()
+            .collect();
+        Some(Misspellings::new(block.source_range().start, &misspellings))
+    }
+
+    /// The words that are underlined as misspelled, in the order they're in
+    /// the document, while spellchecking is on.
+    pub fn misspelled_words(&self, cx: &AppContext) -> Vec<SharedString> {
+        let Some(spellcheck) = &self.spellcheck else {
+            return Vec::new();
+        };
+        spellcheck
+            .block_hashes
+            .iter()
+            .filter_map(|block_hash| spellcheck.results.get(block_hash))
+            .flat_map(|misspellings| misspellings.iter())
+            .filter(|misspelling| !Spellchecker::is_added(&misspelling.word, cx))
+            .map(|misspelling| misspelling.word.clone())
+            .collect()
+    }
+
+    /// Highlights the code blocks in the languages that the view has,
+    /// starting from the ones in view, and shows each as soon as it's done.
+    ///
+    /// Documents are shown before their code is highlighted, since that
+    /// would hold up large ones. Highlighting that's still to do is dropped
+    /// when the document changes, and started again for the blocks that
+    /// still need it.
+    fn schedule_highlighting(&mut self, cx: &mut ViewContext<Self>) {
+        self.highlighting_task = None;
+        let top = self.list_state.logical_scroll_top().item_ix;
+        let (Some(language_registry), Some(contents)) =
+            (self.language_registry.clone(), self.contents.as_mut())
+        else {
+            return;
+        };
+
+        let theme = cx.theme().name.clone();
+        let block_count = contents.children.len();
+        let top = top.min(block_count);
+        let mut pending = Vec::new();
+        for block_ix in (top..block_count).chain(0..top) {
+            for code_block in contents.children[block_ix].code_blocks_mut() {
+                if let (None, Some(language)) = (&code_block.highlights, &code_block.language) {
+                    let key = HighlightCache::key(&code_block.contents, language, &theme);
+                    if let Some(highlights) = self.highlight_cache.get(key) {
+                        code_block.highlights = Some(highlights);
+                        continue;
+                    }
+                    pending.push((
+                        block_ix,
+                        code_block.source_range.clone(),
+                        language.clone(),
+                        code_block.contents.clone(),
+                        key,
+                    ));
+                }
+            }
+        }
+        if pending.is_empty() {
+            return;
+        }
+
+        self.highlighting_task = Some(cx.spawn(|this, mut cx| async move {
+            for (block_ix, source_range, language, code, key) in pending {
+                let language_registry = language_registry.clone();
+                let highlights = cx
+                    .background_executor()
+                    .spawn(
+                        async move { highlight_code(&code, &language, &language_registry).await },
+                    )
+                    .await
+                    // Code in languages the registry doesn't have isn't
+                    // tried again.
+                    .unwrap_or_default();
+
+                let updated = this.update(&mut cx, |this, cx| {
+                    this.highlight_cache.insert(key, highlights.clone());
+                    let code_block = this.contents.as_mut().and_then(|contents| {
+                        contents
+                            .children
+                            .get_mut(block_ix)?
+                            .code_blocks_mut()
+                            .into_iter()
+                            .find(|code_block| code_block.source_range == source_range)
+                    });
+                    if let Some(code_block) = code_block {
+                        code_block.highlights = Some(highlights);
+                        // Only the blocks in view are rendered again.
+                        cx.notify();
+                    }
+                });
+                if updated.is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+
+    /// Highlights code blocks in the languages of `language_registry`.
+    pub fn set_language_registry(
+        &mut self,
+        language_registry: Arc<LanguageRegistry>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.language_registry = Some(language_registry);
+        self.schedule_highlighting(cx);
+    }
+
+    fn set_contents(
+        &mut self,
+        mut content: ParsedMarkdown,
+        word_counts: Vec<usize>,
+        text: String,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let old_hashes = self
+            .contents
+            .as_ref()
+            .map(|contents| contents.block_hashes(&self.contents_text))
+            .unwrap_or_default();
+        // Resetting the list scrolls it back to the top, so the reader is put
+        // back at the block they were reading, wherever it is now.
+        let anchor = self.scroll_anchor();
+        self.list_state.reset(content.children.len());
+        self.render_cache.borrow_mut().invalidate();
+        self.block_layouts.borrow_mut().clear();
+        self.presentation_state.borrow_mut().table_sorts.clear();
+        self.pending_scroll_fraction = None;
+        // The selection's range is into the old text.
+        self.selection = None;
+        if self.contents.as_ref().map(|contents| &contents.diagnostics)
+            != Some(&content.diagnostics)
+        {
+            self.diagnostics_dismissed = false;
+        }
+
+        if let Some(anchor) = anchor {
+            if let Some((block_ix, unchanged)) = anchor.find_block(&content.children, &text) {
+                let offset_in_item = if unchanged {
+                    anchor.offset_in_item
+                } else {
+                    self.pending_scroll_fraction = Some((block_ix, anchor.offset_fraction));
+                    px(0.)
+                };
+                self.list_state.scroll_to(ListOffset {
+                    item_ix: block_ix,
+                    offset_in_item,
+                });
+            }
+        }
+
+        // Code that didn't change keeps its highlights, so that reloading the
+        // document doesn't flash it unhighlighted.
+        if let Some(old_contents) = &mut self.contents {
+            let mut highlights = FxHashMap::default();
+            for code_block in old_contents
+                .children
+                .iter_mut()
+                .flat_map(ParsedMarkdownElement::code_blocks_mut)
+            {
+                if let Some(block_highlights) = code_block.highlights.take() {
+                    let key = (code_block.language.clone(), code_block.contents.clone());
+                    highlights.insert(key, block_highlights);
+                }
+            }
+            for code_block in content
+                .children
+                .iter_mut()
+                .flat_map(ParsedMarkdownElement::code_blocks_mut)
+                .filter(|code_block| code_block.highlights.is_none())
+            {
+                let key = (code_block.language.clone(), code_block.contents.clone());
+                code_block.highlights = highlights.get(&key).cloned();
+            }
+        }
+
+        self.outline = content.outline();
+        self.contents = Some(content);
+        self.partial_contents = false;
+        self.word_counts = word_counts;
+        self.contents_text = text;
+        self.watch_includes(cx);
+        self.schedule_highlighting(cx);
+        self.update_search(false, cx);
+        self.apply_pending_scroll(cx);
+        self.emit_contents_changed(&old_hashes, cx);
+        cx.notify();
+    }
+
+    /// Shows `children`, the first blocks of `text`, while the rest of it is
+    /// parsed.
+    fn show_partial_contents(
+        &mut self,
+        mut children: Vec<ParsedMarkdownElement>,
+        text: String,
+        cx: &mut ViewContext<Self>,
+    ) {
+        assign_heading_slugs(&mut children);
+        let word_counts = children
+            .iter()
+            .map(ParsedMarkdownElement::word_count)
+            .collect();
+        let contents = ParsedMarkdown {
+            children,
+            front_matter: None,
+            includes: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        self.set_contents(contents, word_counts, text, cx);
+        self.partial_contents = true;
+    }
+
+    fn apply_pending_scroll(&mut self, cx: &mut ViewContext<Self>) {
+        match self.pending_scroll.take() {
+            Some(PendingScroll::Fragment(fragment)) => {
+                self.list_state.scroll_to(ListOffset::default());
+                if !self.flash_fragment(&fragment, cx) {
+                    self.show_toast("No such section", cx);
+                }
+            }
+            Some(PendingScroll::Offset(offset)) => {
+                self.pending_scroll_fraction = None;
+                self.list_state.scroll_to(offset);
+            }
+            Some(PendingScroll::Position(position)) => {
+                let block_ix = position
+                    .block_ix
+                    .min(self.list_state.item_count().saturating_sub(1));
+                self.list_state.scroll_to(ListOffset {
+                    item_ix: block_ix,
+                    offset_in_item: Pixels::ZERO,
+                });
+                self.pending_scroll_fraction = (block_ix == position.block_ix
+                    && position.offset_fraction > 0.)
+                    .then_some((block_ix, position.offset_fraction));
+            }
+            None => {}
+        }
+    }
+
+    /// Where the reader is in the document, so that they can be put back
+    /// there after the document or its layout changes.
+    fn scroll_anchor(&self) -> Option<ScrollAnchor> {
+        let contents = self.contents.as_ref()?;
+        let scroll_top = self.list_state.logical_scroll_top();
+        let block = contents.children.get(scroll_top.item_ix)?;
+        let source_range = block.source_range();
+        let offset_fraction = self
+            .block_layouts
+            .borrow()
+            .height(scroll_top.item_ix)
+            .filter(|height| *height > Pixels::ZERO)
+            .map_or(0., |height| {
+                (scroll_top.offset_in_item / height).clamp(0., 1.)
+            });
+
+        let section = contents.children[..=scroll_top.item_ix]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(heading_ix, block)| match block {
+                ParsedMarkdownElement::Heading(heading) => {
+                    Some((heading.slug.clone(), scroll_top.item_ix - heading_ix))
+                }
+                _ => None,
+            });
+        let text_fraction = if self.contents_text.is_empty() {
+            0.
+        } else {
+            source_range.start as f32 / self.contents_text.len() as f32
+        };
+
+        Some(ScrollAnchor {
+            block_ix: scroll_top.item_ix,
+            source: self
+                .contents_text
+                .get(source_range)
+                .unwrap_or_default()
+                .to_string(),
+            offset_in_item: scroll_top.offset_in_item,
+            offset_fraction,
+            section,
+            text_fraction,
+        })
+    }
+
+    /// Puts the file back the way it was last shown, the first time it's
+    /// shown in full. Folds and expanded code blocks that no longer match the
+    /// document are dropped, and the reader is put back at the block they
+    /// were at if it's unchanged, unless they were already sent elsewhere,
+    /// like to a link's fragment.
+    fn restore_ui_state(&mut self, cx: &mut ViewContext<Self>) {
+        if self.ui_state_path == self.path {
+            return;
+        }
+        self.ui_state_path = self.path.clone();
+        let saved = self
+            .path
+            .as_deref()
+            .and_then(|path| UiStateStore::get(path, cx));
+        self.last_ui_state = saved.clone();
+        self.ui_state_key = None;
+        let (Some(saved), Some(contents)) = (saved, &mut self.contents) else {
+            return;
+        };
+
+        {
+            let mut state = self.presentation_state.borrow_mut();
+            state.folded_sections = contents
+                .children
+                .iter()
+                .filter_map(|block| match block {
+                    ParsedMarkdownElement::Heading(heading)
+                        if saved.folded_sections.contains(&heading.slug) =>
+                    {
+                        Some(heading.slug.clone())
+                    }
+                    _ => None,
+                })
+                .collect();
+            state.expanded_code_blocks = contents
+                .children
+                .iter_mut()
+                .flat_map(ParsedMarkdownElement::code_blocks_mut)
+                .map(|code_block| code_block.source_range.clone())
+                .filter(|range| {
+                    self.contents_text
+                        .get(range.clone())
+                        .map_or(false, |source| {
+                            saved.expanded_code_blocks.contains(&source_hash(source))
+                        })
+                })
+                .collect();
+        }
+        self.front_matter_collapsed = saved.front_matter_collapsed;
+        self.restore_text_scale(saved.text_scale, cx);
+
+        let scroll_top = self.list_state.logical_scroll_top();
+        let at_top = scroll_top.item_ix == 0 && scroll_top.offset_in_item == Pixels::ZERO;
+        let block_ix = saved.scroll.filter(|_| at_top).and_then(|scroll| {
+            let contents = self.contents.as_ref()?;
+            let block_ix = scroll.find_block(&contents.children, &self.contents_text)?;
+            Some((block_ix, scroll.offset_fraction))
+        });
+        if let Some((block_ix, offset_fraction)) = block_ix {
+            self.list_state.scroll_to(ListOffset {
+                item_ix: block_ix,
+                offset_in_item: Pixels::ZERO,
+            });
+            self.pending_scroll_fraction =
+                (offset_fraction > 0.).then_some((block_ix, offset_fraction));
+        }
+        cx.notify();
+    }
+
+    /// Keeps the file's [`DocumentUiState`] in the [`UiStateStore`] as it
+    /// changes, once the state it was last shown with has been restored and
+    /// the reader has been put back where they were.
+    fn remember_ui_state(&mut self, cx: &mut ViewContext<Self>) {
+        if !cx.has_global::<UiStateStore>()
+            || self.partial_contents
+            || self.pending_scroll.is_some()
+            || self.pending_scroll_fraction.is_some()
+        {
+            return;
+        }
+        let Some(path) = self
+            .ui_state_path
+            .clone()
+            .filter(|_| self.ui_state_path == self.path)
+        else {
+            return;
+        };
+        let scroll_top = self.list_state.logical_scroll_top();
+        let key = {
+            let state = self.presentation_state.borrow();
+            UiStateKey {
+                parse_generation: self.parse_generation,
+                scroll_top: (scroll_top.item_ix, scroll_top.offset_in_item),
+                folded_sections: state.folded_sections.len(),
+                expanded_code_blocks: state.expanded_code_blocks.len(),
+                front_matter_collapsed: self.front_matter_collapsed,
+                text_scale: self.text_scale,
+            }
+        };
+        if self.ui_state_key == Some(key) {
+            return;
+        }
+        self.ui_state_key = Some(key);
+        let Some(state) = self.ui_state() else {
+            return;
+        };
+        if self.last_ui_state.as_ref() == Some(&state) {
+            return;
+        }
+        self.last_ui_state = Some(state.clone());
+        UiStateStore::set(&path, state, cx);
+    }
+
+    /// How the document is being read now.
+    fn ui_state(&self) -> Option<DocumentUiState> {
+        let contents = self.contents.as_ref()?;
+        let state = self.presentation_state.borrow();
+        let mut folded_sections = state.folded_sections.iter().cloned().collect::<Vec<_>>();
+        folded_sections.sort();
+        let mut expanded_code_blocks = state
+            .expanded_code_blocks
+            .iter()
+            .filter_map(|range| Some(source_hash(self.contents_text.get(range.clone())?)))
+            .collect::<Vec<_>>();
+        expanded_code_blocks.sort();
+        let scroll = self
+            .scroll_anchor()
+            .filter(|anchor| anchor.block_ix > 0 || anchor.offset_fraction > 0.)
+            .map(|anchor| SavedScroll {
+                block_ix: anchor.block_ix,
+                source_hash: source_hash(&anchor.source),
+                section: anchor.section,
+                offset_fraction: anchor.offset_fraction,
+            });
+        (!contents.children.is_empty()).then_some(DocumentUiState {
+            folded_sections,
+            expanded_code_blocks,
+            front_matter_collapsed: self.front_matter_collapsed,
+            text_scale: self.text_scale,
+            scroll,
+        })
+    }
+
+    /// Blocks change height when the window is resized, so the reader is
+    /// kept the same fraction of the way into the block they were reading.
+    fn preserve_scroll_on_resize(&mut self, width: Pixels) {
+        let previous_width = self.layout_width.replace(width);
+        if previous_width.map_or(true, |previous_width| previous_width == width)
+            || self.pending_scroll_fraction.is_some()
+        {
+            return;
+        }
+
+        if let Some(anchor) = self.scroll_anchor() {
+            if anchor.offset_fraction > 0. {
+                self.block_layouts.borrow_mut().forget(anchor.block_ix);
+                self.pending_scroll_fraction = Some((anchor.block_ix, anchor.offset_fraction));
+            }
+        }
+    }
+
+    /// Scrolls back into the block the reader was in, once it has been laid
+    /// out at its new height.
+    fn restore_scroll_fraction(&mut self, cx: &mut ViewContext<Self>) {
+        let Some((block_ix, fraction)) = self.pending_scroll_fraction.take() else {
+            return;
+        };
+        let Some(height) = self.block_layouts.borrow().height(block_ix) else {
+            return;
+        };
+        if self.list_state.logical_scroll_top().item_ix != block_ix {
+            return;
+        }
+
+        self.list_state.scroll_to(ListOffset {
+            item_ix: block_ix,
+            offset_in_item: height * fraction,
+        });
+        cx.notify();
+    }
+
+    /// Reads the document from the file at `path` and watches it for
+    /// changes. If it can't be read, the view explains why, with a button to
+    /// try again.
+    pub fn open_file(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
+        // Set right away, so that the file can be told apart from others
+        // while it's read.
+        self.path = Some(path.clone());
+        // The document is replaced, so the view shows that it's loading
+        // rather than what it showed before, and stops watching the file
+        // it was, which would otherwise put it back when it changes.
+        self.parse_generation += 1;
+        self.parsing_markdown_task = None;
+        self.contents = None;
+        self.contents_text.clear();
+        self.outline.clear();
+        self.load_error = None;
+        self.file_watch = None;
+        self.include_watch = None;
+        self.file_removed = false;
+        cx.notify();
+        let read = cx.background_executor().spawn({
+            let path = path.clone();
+            async move { std::fs::read_to_string(path) }
+        });
+        self.pending_read = Some(cx.spawn(|this, mut cx| async move {
+            let text = read.await;
+            this.update(&mut cx, |this, cx| match text {
+                Ok(text) => {
+                    this.base_directory = path.parent().map(Path::to_path_buf);
+                    this.replace_text(text, cx);
+                    this.watch_file(path, cx);
+                }
+                Err(error) => {
+                    log::error!("failed to read {}: {}", path.display(), error);
+                    this.show_load_error(read_error_message(&error), Some(path), cx);
+                }
+            })
+            .ok();
+        }));
+    }
+
+    /// Shows `message` instead of the document, with a button to read the
+    /// file at `path` again if there is one.
+    pub fn show_load_error(
+        &mut self,
+        message: impl Into<SharedString>,
+        path: Option<PathBuf>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.load_error = Some(LoadError {
+            message: message.into(),
+            path,
+        });
+        cx.notify();
+    }
+
+    pub fn load_error(&self) -> Option<&LoadError> {
+        self.load_error.as_ref()
+    }
+
+    /// Whether the document is still being read or parsed for the first
+    /// time. Later parses keep showing the previous version until they're
+    /// done.
+    pub fn is_loading(&self) -> bool {
+        self.contents.is_none() && self.load_error.is_none()
+    }
+
+    /// Reloads the preview whenever the file at `path` changes on disk, and
+    /// adds it to the [`RecentFiles`].
+    ///
+    /// Symlinks are resolved first, so that the real file is watched, even
+    /// through editors that save by replacing it, and so that a file is
+    /// titled and listed the same way however it was opened.
+    pub fn watch_file(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
+        let path = path.canonicalize().unwrap_or(path);
+        self.path = Some(path.clone());
+        RecentFiles::add(&path, cx);
+        let (changes_tx, mut changes_rx) = mpsc::unbounded();
+        let watcher = notify::recommended_watcher({
+            let path = path.clone();
+            move |event: notify::Result<notify::Event>| {
+                if event.map_or(false, |event| event.paths.contains(&path)) {
+                    changes_tx.unbounded_send(()).ok();
+                }
+            }
+        });
+
+        // Editors often save by renaming a new file over the old one, which
+        // ends a watch on the file itself, so its directory is watched instead.
+        let watched = watcher.and_then(|mut watcher| {
+            let directory = path.parent().unwrap_or(path.as_path());
+            watcher.watch(directory, RecursiveMode::NonRecursive)?;
+            Ok(watcher)
+        });
+        let watcher = match watched {
+            Ok(watcher) => watcher,
+            Err(error) => {
+                log::error!("failed to watch {}: {}", path.display(), error);
+                return;
+            }
+        };
+        log::debug!(path:% = path.display(); "watching file");
+
+        let task = cx.spawn(|this, mut cx| async move {
+            while changes_rx.next().await.is_some() {
+                // A single save tends to arrive as a burst of events.
+                cx.background_executor().timer(FILE_RELOAD_DEBOUNCE).await;
+                let mut events = 1;
+                while let Ok(Some(())) = changes_rx.try_next() {
+                    events += 1;
+                }
+                log::debug!(path:% = path.display(), events = events; "file changed");
+
+                let Ok(followed) = this.update(&mut cx, |this, _| {
+                    this.follow.then(|| FollowedText::new(&this.raw_text))
+                }) else {
+                    break;
+                };
+                let change = cx
+                    .background_executor()
+                    .spawn({
+                        let path = path.clone();
+                        async move { read_file_change(&path, followed) }
+                    })
+                    .await;
+
+                let updated = this.update(&mut cx, |this, cx| match change {
+                    Ok(FileChange::Appended(chunk)) => {
+                        log::debug!(bytes = chunk.len(); "file appended to");
+                        this.file_removed = false;
+                        if !chunk.is_empty() {
+                            this.append_text(&chunk, cx);
+                        }
+                    }
+                    Ok(FileChange::Replaced(text)) => {
+                        log::debug!(bytes = text.len(); "file replaced");
+                        this.file_removed = false;
+                        this.set_text(text, cx);
+                    }
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                        log::info!(path:% = path.display(); "file removed");
+                        this.file_removed = true;
+                        cx.notify();
+                    }
+                    Err(error) => log::error!("failed to reload {}: {}", path.display(), error),
+                });
+                if updated.is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.file_watch = Some(FileWatch {
+            _watcher: watcher,
+            _task: task,
+        });
+    }
+
+    /// Parses the document again whenever one of the files it includes
+    /// changes, as long as its own file is being watched.
+    fn watch_includes(&mut self, cx: &mut ViewContext<Self>) {
+        let mut paths: Vec<PathBuf> = match (&self.contents, &self.file_watch) {
+            (Some(contents), Some(_)) => contents
+                .includes
+                .iter()
+                .map(|include| include.path.clone())
+                .collect(),
+            _ => Vec::new(),
+        };
+        paths.sort();
+        paths.dedup();
+        let current = self.include_watch.as_ref().map(|(paths, _)| paths);
+        if current.map_or(paths.is_empty(), |current| *current == paths) {
+            return;
+        }
+        self.include_watch = None;
+        if paths.is_empty() {
+            return;
+        }
+
+        let (changes_tx, mut changes_rx) = mpsc::unbounded();
+        let watcher = notify::recommended_watcher({
+            let paths = paths.clone();
+            move |event: notify::Result<notify::Event>| {
+                if event.map_or(false, |event| {
+                    event.paths.iter().any(|path| paths.contains(path))
+                }) {
+                    changes_tx.unbounded_send(()).ok();
+                }
+            }
+        });
+        let mut directories: Vec<&Path> = paths.iter().filter_map(|path| path.parent()).collect();
+        directories.sort();
+        directories.dedup();
+        let watched = watcher.and_then(|mut watcher| {
+            for directory in directories {
+                watcher.watch(directory, RecursiveMode::NonRecursive)?;
+            }
+            Ok(watcher)
+        });
+        let watcher = match watched {
+            Ok(watcher) => watcher,
+            Err(error) => {
+                log::error!("failed to watch included files: {}", error);
+                return;
+            }
+        };
+
+        let task = cx.spawn(|this, mut cx| async move {
+            while changes_rx.next().await.is_some() {
+                cx.background_executor().timer(FILE_RELOAD_DEBOUNCE).await;
+                while let Ok(Some(())) = changes_rx.try_next() {}
+
+                let reloaded = this.update(&mut cx, |this, cx| {
+                    let text = this.raw_text.clone();
+                    this.set_text(text, cx);
+                });
+                if reloaded.is_err() {
+                    break;
+                }
+            }
+        });
+        self.include_watch = Some((
+            paths,
+            FileWatch {
+                _watcher: watcher,
+                _task: task,
+            },
+        ));
+    }
+
+    /// The Markdown source of the document being previewed.
+    pub fn text(&self) -> &str {
+        &self.raw_text
+    }
+
+    /// Whether the document is a [`MarkdownView::scratch`] one that hasn't
+    /// been saved yet.
+    pub fn is_scratch(&self) -> bool {
+        self.scratch && self.path.is_none()
+    }
+
+    /// Asks where to save the document's source, then previews it from
+    /// there.
+    fn save_as(&mut self, _: &SaveAs, cx: &mut ViewContext<Self>) {
+        let directory = self
+            .base_directory
+            .clone()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let path = cx.prompt_for_new_path(&directory);
+        cx.spawn(|this, mut cx| async move {
+            let Some(path) = path.await?? else {
+                return Ok(());
+            };
+            this.update(&mut cx, |this, cx| this.save_to(path, cx))
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Writes the document's source to `path`. Once it's written, the file
+    /// is watched for changes and relative links resolve against it.
+    pub fn save_to(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
+        let write = cx.background_executor().spawn({
+            let path = path.clone();
+            let text = self.raw_text.clone();
+            async move { std::fs::write(path, text) }
+        });
+        cx.spawn(|this, mut cx| async move {
+            let written = write.await;
+            this.update(&mut cx, |this, cx| match written {
+                Ok(()) => {
+                    this.scratch = false;
+                    this.base_directory = path.parent().map(Path::to_path_buf);
+                    this.watch_file(path, cx);
+                    // Links were resolved without a directory to resolve
+                    // them against.
+                    this.set_text(this.raw_text.clone(), cx);
+                    this.show_toast("Saved", cx);
+                }
+                Err(error) => {
+                    log::error!("failed to save {}: {}", path.display(), error);
+                    this.show_toast(format!("Could not save {}", file_name(&path)), cx);
+                }
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// The file the document was opened from, if it was.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// What to call the document: its first heading, or failing that, the
+    /// name of its file.
+    pub fn title(&self) -> SharedString {
+        if self.is_scratch() {
+            return "Untitled".into();
+        }
+        self.outline
+            .first()
+            .map(|entry| entry.text.clone())
+            .or_else(|| self.path.as_deref().map(|path| file_name(path).into()))
+            .unwrap_or_else(|| "Untitled".into())
+    }
+
+    /// The document's headings, as of the last parse.
+    pub fn outline(&self) -> &[OutlineEntry] {
+        &self.outline
+    }
+
+    /// The document as assistive technology sees it, as of the last parse.
+    pub fn accessibility_tree(&self) -> Option<AccessibilityNode> {
+        self.contents.as_ref().map(accessibility_tree)
+    }
+
+    /// The title for a window showing the document. See [`window_title`].
+    pub fn window_title(&self) -> String {
+        if self.is_scratch() {
+            return "Untitled".to_string();
+        }
+        let heading = self
+            .outline
+            .iter()
+            .find(|entry| entry.level == HeadingLevel::H1)
+            .map(|entry| entry.text.as_ref());
+        window_title(self.path.as_deref(), heading, false)
+    }
+
+    /// Changes which images are loaded. This only affects rendering, so the
+    /// document is not re-parsed.
+    pub fn set_image_policy(&mut self, policy: ImagePolicy, cx: &mut ViewContext<Self>) {
+        self.image_policy = policy;
+        cx.notify();
+    }
+
+    /// Changes which links open when they're clicked.
+    pub fn set_link_policy(&mut self, policy: LinkPolicy, cx: &mut ViewContext<Self>) {
+        self.link_policy = policy;
+        cx.notify();
+    }
+
+    /// Renders elements for which `callback` returns an element with that
+    /// element instead. See [`RenderContext::with_render_override`].
+    pub fn set_render_override(
+        &mut self,
+        callback: impl Fn(&ParsedMarkdownElement, &mut RenderContext) -> Option<AnyElement> + 'static,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.render_override = Some(Arc::new(Box::new(callback)));
+        cx.notify();
+    }
+
+    /// Renders fences in `language` with `handler` instead of as code.
+    pub fn register_fence_handler(
+        &mut self,
+        language: impl Into<SharedString>,
+        handler: impl Fn(&ParsedMarkdownCodeBlock, &mut RenderContext) -> AnyElement + 'static,
+        cx: &mut ViewContext<Self>,
+    ) {
+        Rc::make_mut(&mut self.fence_handlers).register(language, handler);
+        cx.notify();
+    }
+
+    /// Selects the part of the document that `range` of its source covers, or
+    /// nothing.
+    pub fn set_selection(&mut self, range: Option<Range<usize>>, cx: &mut ViewContext<Self>) {
+        self.selection = range;
+        cx.notify();
+    }
+
+    pub fn selection(&self) -> Option<Range<usize>> {
+        self.selection.clone()
+    }
+
+    /// Copies the text of the selected blocks, as it's shown.
+    pub fn copy(&mut self, _: &Copy, cx: &mut ViewContext<Self>) {
+        let (Some(contents), Some(selection)) = (&self.contents, self.selection.clone()) else {
+            return;
+        };
+
+        let text = contents.selected_text(selection);
+        if !text.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(text));
+        }
+    }
+
+    pub fn select_all(&mut self, _: &SelectAll, cx: &mut ViewContext<Self>) {
+        self.set_selection(Some(0..self.contents_text.len()), cx);
+    }
+
+    /// Reads the document from its file again, or parses it again if it
+    /// didn't come from one.
+    pub fn reload(&mut self, _: &Reload, cx: &mut ViewContext<Self>) {
+        let Some(path) = self.path.clone().filter(|_| self.file_watch.is_some()) else {
+            self.set_text(self.raw_text.clone(), cx);
+            return;
+        };
+
+        let read = cx.background_executor().spawn({
+            let path = path.clone();
+            async move { std::fs::read_to_string(path) }
+        });
+        cx.spawn(|this, mut cx| async move {
+            let text = read.await;
+            this.update(&mut cx, |this, cx| match text {
+                Ok(text) => this.set_text(text, cx),
+                Err(error) => {
+                    log::error!("failed to reload {}: {}", path.display(), error);
+                    this.show_toast(format!("Could not reload {}", file_name(&path)), cx);
+                }
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Opens the context menu near the top left corner of the document,
+    /// since the keyboard doesn't point at anything in particular.
+    fn deploy_context_menu_from_keyboard(
+        &mut self,
+        _: &DeployContextMenu,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let position = self.viewport_bounds.get().origin + point(px(16.), px(16.));
+        self.deploy_context_menu(position, None, cx);
+    }
+
+    /// Opens a menu at `position` of what can be done with `hovered`, the
+    /// selection and the document.
+    fn deploy_context_menu(
+        &mut self,
+        position: Point<Pixels>,
+        hovered: Option<HoveredElement>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let view = cx.view().downgrade();
+        let has_selection = self.selection.is_some();
+        let block_link = self
+            .block_at_position(position)
+            .and_then(|block_ix| self.block_link(block_ix));
+        let follow_label = if self.follow {
+            "Stop Following"
+        } else {
+            "Follow File"
+        };
+        let suggestions = match (&hovered, &self.spellcheck) {
+            (
+                Some(HoveredElement::Misspelling { word, .. }),
+                Some(SpellcheckState {
+                    dictionary: Some(dictionary),
+                    ..
+                }),
+            ) => dictionary.suggestions(word),
+            _ => Vec::new(),
+        };
+        let menu = ContextMenu::build(cx, |menu, _| {
+            let menu = match hovered {
+                Some(HoveredElement::Link {
+                    link,
+                    origin,
+                    source_range,
+                }) => {
+                    let url = link.url();
+                    menu.entry("Open Link", None, {
+                        let view = view.clone();
+                        move |cx| {
+                            view.update(cx, |view, cx| {
+                                view.activate_link(&link, origin, source_range.clone(), cx)
+                            })
+                            .ok();
+                        }
+                    })
+                    .entry("Copy Link Address", None, move |cx| {
+                        cx.write_to_clipboard(ClipboardItem::new_string(url.clone()))
+                    })
+                    .separator()
+                }
+                Some(HoveredElement::CodeBlock(contents)) => menu
+                    .entry("Copy Code", None, move |cx| {
+                        cx.write_to_clipboard(ClipboardItem::new_string(contents.to_string()))
+                    })
+                    .separator(),
+                Some(HoveredElement::Image(link)) => {
+                    let url = link.url();
+                    menu.entry("Copy Image Address", None, {
+                        let url = url.clone();
+                        move |cx| cx.write_to_clipboard(ClipboardItem::new_string(url.clone()))
+                    })
+                    .entry("Open Image", None, move |cx| {
+                        if let Some(url) = link.os_url() {
+                            cx.open_url(&url)
+                        }
+                    })
+                    .separator()
+                }
+                Some(HoveredElement::Heading(block_ix)) => menu
+                    .entry("Copy Section as Markdown", None, {
+                        let view = view.clone();
+                        move |cx| {
+                            view.update(cx, |view, cx| view.copy_section(block_ix, cx))
+                                .ok();
+                        }
+                    })
+                    .separator(),
+                Some(HoveredElement::Misspelling {
+                    word,
+                    source_range,
+                    occurrence,
+                }) => {
+                    let mut menu = if suggestions.is_empty() {
+                        menu.label("No Suggestions")
+                    } else {
+                        menu
+                    };
+                    for suggestion in suggestions {
+                        let view = view.clone();
+                        let word = word.clone();
+                        let source_range = source_range.clone();
+                        menu = menu.entry(suggestion.clone(), None, move |cx| {
+                            view.update(cx, |view, cx| {
+                                view.correct_spelling(
+                                    &word,
+                                    source_range.clone(),
+                                    occurrence,
+                                    suggestion.clone().into(),
+                                    cx,
+                                )
+                            })
+                            .ok();
+                        });
+                    }
+                    menu.entry("Add to Dictionary", None, move |cx| {
+                        Spellchecker::add_word(&word, cx)
+                    })
+                    .separator()
+                }
+                None => menu,
+            };
+            let menu = if has_selection {
+                menu.entry("Copy", Some(Box::new(Copy)), {
+                    let view = view.clone();
+                    move |cx| {
+                        view.update(cx, |view, cx| view.copy(&Copy, cx)).ok();
+                    }
+                })
+                .entry("Copy as Markdown", Some(Box::new(CopyAsMarkdown)), {
+                    let view = view.clone();
+                    move |cx| {
+                        view.update(cx, |view, cx| view.copy_as_markdown(&CopyAsMarkdown, cx))
+                            .ok();
+                    }
+                })
+                .entry("Copy as HTML", Some(Box::new(CopyAsHtml)), {
+                    let view = view.clone();
+                    move |cx| {
+                        view.update(cx, |view, cx| view.copy_as_html(&CopyAsHtml, cx))
+                            .ok();
+                    }
+                })
+                .separator()
+            } else {
+                menu
+            };
+            let menu = match block_link {
+                Some(link) => menu
+                    .entry("Copy Link to This Block", None, move |cx| {
+                        cx.write_to_clipboard(ClipboardItem::new_string(link.clone()))
+                    })
+                    .separator(),
+                None => menu,
+            };
+            menu.entry("Select All", Some(Box::new(SelectAll)), {
+                let view = view.clone();
+                move |cx| {
+                    view.update(cx, |view, cx| view.select_all(&SelectAll, cx))
+                        .ok();
+                }
+            })
+            .entry("Reload", Some(Box::new(Reload)), {
+                let view = view.clone();
+                move |cx| {
+                    view.update(cx, |view, cx| view.reload(&Reload, cx)).ok();
+                }
+            })
+            .entry("Check Links", Some(Box::new(CheckLinks)), {
+                let view = view.clone();
+                move |cx| {
+                    view.update(cx, |view, cx| view.check_links(&CheckLinks, cx))
+                        .ok();
+                }
+            })
+            .entry(follow_label, Some(Box::new(ToggleFollow)), move |cx| {
+                view.update(cx, |view, cx| view.toggle_follow(&ToggleFollow, cx))
+                    .ok();
+            })
+        });
+
+        let subscription = cx.subscribe(&menu, |this, _, _: &DismissEvent, cx| {
+            this.context_menu.take();
+            cx.focus(&this.focus_handle);
+            cx.notify();
+        });
+        cx.focus_view(&menu);
+        self.context_menu = Some((menu, position, subscription));
+        cx.notify();
+    }
+
+    /// Copies the Markdown source of the selection, so that pasting it into
+    /// another document keeps its formatting.
+    pub fn copy_as_markdown(&mut self, _: &CopyAsMarkdown, cx: &mut ViewContext<Self>) {
+        let (Some(contents), Some(selection)) = (&self.contents, self.selection.clone()) else {
+            return;
+        };
+
+        let source = contents.markdown_source(&self.contents_text, selection);
+        if !source.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(source));
+        }
+    }
+
+    /// Copies the selected blocks as HTML, to paste them into rich text
+    /// editors with their formatting, along with their plain text.
+    ///
+    /// gpui's clipboard only has a plain text flavor, so the HTML is written
+    /// as the text's metadata, where other gpui applications can read it.
+    pub fn copy_as_html(&mut self, _: &CopyAsHtml, cx: &mut ViewContext<Self>) {
+        let (Some(contents), Some(selection), Some(html)) =
+            (&self.contents, self.selection.clone(), self.selected_html())
+        else {
+            return;
+        };
+        let text = contents.selected_text(selection);
+        cx.write_to_clipboard(ClipboardItem::new_string_with_json_metadata(text, html));
+    }
+
+    /// The selected blocks converted to a fragment of HTML.
+    pub fn selected_html(&self) -> Option<String> {
+        let (Some(contents), Some(selection)) = (&self.contents, self.selection.clone()) else {
+            return None;
+        };
+        let html =
+            markdown_html::export_html_fragment(contents, selection, &HtmlExportOptions::default());
+        (!html.is_empty()).then_some(html)
+    }
+
+    /// What's known about the document shown, for bug reports, without any of
+    /// its text.
+    pub fn document_stats(&self) -> DocumentStats {
+        let mut stats = DocumentStats {
+            file_extension: self
+                .path
+                .as_deref()
+                .and_then(Path::extension)
+                .map(|extension| extension.to_string_lossy().into_owned()),
+            bytes: self.contents_text.len(),
+            words: self.word_counts.iter().sum(),
+            last_parse_duration: self.last_parse_duration,
+            load_error: self
+                .load_error
+                .as_ref()
+                .map(|error| error.message.to_string()),
+            ..Default::default()
+        };
+        if let Some(contents) = &self.contents {
+            stats.blocks = contents.children.len();
+            for block in &contents.children {
+                match block {
+                    ParsedMarkdownElement::Heading(_) => stats.headings += 1,
+                    ParsedMarkdownElement::CodeBlock(_) => stats.code_blocks += 1,
+                    _ => {}
+                }
+            }
+            stats.included_files = contents
+                .includes
+                .iter()
+                .map(|included| &included.path)
+                .collect::<FxHashSet<_>>()
+                .len();
+            stats.parse_diagnostics = contents.diagnostics.len();
+        }
+        stats
+    }
+
+    /// Copies a report to attach to a bug: the version, the platform, the
+    /// settings, the [`Self::document_stats`] and the last lines logged.
+    pub fn copy_diagnostics(&mut self, _: &CopyDiagnostics, cx: &mut ViewContext<Self>) {
+        let report = diagnostics_report(Some(&self.document_stats()), cx);
+        cx.write_to_clipboard(ClipboardItem::new_string(report));
+        self.show_toast("Copied diagnostics", cx);
+    }
+
+    /// Copies the Markdown source of the section started by the heading at
+    /// `heading_ix`, subsections included, to move it to another document.
+    pub fn copy_section(&mut self, heading_ix: usize, cx: &mut ViewContext<Self>) {
+        let Some(source) = self
+            .contents
+            .as_ref()
+            .and_then(|contents| contents.section_source(&self.contents_text, heading_ix))
+        else {
+            return;
+        };
+        cx.write_to_clipboard(ClipboardItem::new_string(source));
+        self.show_toast("Copied section", cx);
+    }
+
+    pub fn text_scale(&self) -> f32 {
+        self.text_scale
+    }
+
+    /// Scales the document's text, and saves the scale to the user settings
+    /// so that later previews open at it too. Images and the spacing between
+    /// blocks keep their size.
+    pub fn set_text_scale(&mut self, scale: f32, cx: &mut ViewContext<Self>) {
+        let scale = round_text_scale(scale);
+        self.show_toast(format!("{}%", (scale * 100.).round()), cx);
+        if scale == self.text_scale {
+            return;
+        }
+        self.restore_text_scale(scale, cx);
+
+        let saved = update_user_settings(cx, |settings| {
+            let preview = &mut settings["markdown_preview"];
+            if !preview.is_object() {
+                *preview = serde_json::json!({});
+            }
+            preview["text_scale"] = ((scale as f64 * 100.).round() / 100.).into();
+        });
+        if let Err(error) = saved {
+            log::error!("failed to save the text scale: {:#}", error);
+        }
+    }
+
+    /// Scales the document's text like [`Self::set_text_scale`], without
+    /// saving the scale or showing it, like when a tab is reopened at the
+    /// zoom it had.
+    pub fn restore_text_scale(&mut self, scale: f32, cx: &mut ViewContext<Self>) {
+        let scale = round_text_scale(scale);
+        if scale == self.text_scale {
+            return;
+        }
+
+        // Every block changes height, so the measured heights are dropped,
+        // and the reader is put back where they were once the block at the
+        // top has been laid out again.
+        let anchor = self.scroll_anchor();
+        self.text_scale = scale;
+        let block_count = self.list_state.item_count();
+        self.list_state.splice(0..block_count, block_count);
+        self.block_layouts.borrow_mut().clear();
+        if let Some(anchor) = anchor {
+            self.list_state.scroll_to(ListOffset {
+                item_ix: anchor.block_ix,
+                offset_in_item: Pixels::ZERO,
+            });
+            if anchor.offset_fraction > 0. {
+                self.pending_scroll_fraction = Some((anchor.block_ix, anchor.offset_fraction));
+            }
+        }
+        cx.notify();
+    }
+
+    /// Shows `message` over the document for a moment.
+    pub fn show_toast(&mut self, message: impl Into<SharedString>, cx: &mut ViewContext<Self>) {
+        let hide = cx.spawn(|this, mut cx| async move {
+            cx.background_executor().timer(TOAST_DURATION).await;
+            this.update(&mut cx, |this, cx| {
+                this.toast = None;
+                cx.notify();
+            })
+            .ok();
+        });
+        self.toast = Some((message.into(), hide));
+        cx.notify();
+    }
+
+    /// Stops the view from opening links itself, so that subscribers to its
+    /// [`MarkdownEvent`]s can decide what clicking one does.
+    pub fn set_suppress_default(&mut self, suppress_default: bool) {
+        self.suppress_default = suppress_default;
+    }
+
+    fn toggle_checkbox(
+        &mut self,
+        checked: bool,
+        source_range: Range<usize>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        cx.emit(MarkdownEvent::CheckboxToggled {
+            checked,
+            source_range: source_range.clone(),
+        });
+        if !self.suppress_default {
+            self.write_checkbox(checked, source_range, cx);
+        }
+    }
+
+    /// Rewrites the `[ ]` marker at `source_range` in the watched file,
+    /// leaving the rest of it as it is. If the file changed since it was
+    /// last read, nothing is written, since the marker may have moved.
+    fn write_checkbox(
+        &mut self,
+        checked: bool,
+        source_range: Range<usize>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let marker = if checked { "[x]" } else { "[ ]" };
+        self.write_edit(
+            source_range,
+            marker,
+            |marker| matches!(marker, "[ ]" | "[x]" | "[X]"),
+            cx,
+        );
+    }
+
+    /// Replaces the misspelled `word`, the one after `occurrence` others in
+    /// the text at `text_range` of the source, with `replacement`.
+    fn correct_spelling(
+        &mut self,
+        word: &str,
+        text_range: Range<usize>,
+        occurrence: usize,
+        replacement: SharedString,
+        cx: &mut ViewContext<Self>,
+    ) {
+        // The word's range is only known in the text without its formatting.
+        let Some(source_range) = self.contents_text.get(text_range.clone()).and_then(|text| {
+            let (offset, _) = text.match_indices(word).nth(occurrence)?;
+            let start = text_range.start + offset;
+            Some(start..start + word.len())
+        }) else {
+            return;
+        };
+        cx.emit(MarkdownEvent::SpellingCorrected {
+            word: word.to_string().into(),
+            replacement: replacement.clone(),
+            source_range: source_range.clone(),
+        });
+        if !self.suppress_default {
+            let word = word.to_string();
+            self.write_edit(source_range, &replacement, |text| text == word, cx);
+        }
+    }
+
+    /// Replaces `source_range` of the watched file with `replacement`, if
+    /// what's there passes `is_expected`, leaving the rest of it as it is.
+    /// If the file changed since it was last read, nothing is written, since
+    /// what's replaced may have moved.
+    fn write_edit(
+        &mut self,
+        source_range: Range<usize>,
+        replacement: &str,
+        is_expected: impl FnOnce(&str) -> bool,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(path) = self.path.clone().filter(|_| self.file_watch.is_some()) else {
+            return;
+        };
+        // Text in included blocks is in other files.
+        let in_document = self.contents.as_ref().map_or(false, |contents| {
+            contents
+                .children
+                .iter()
+                .enumerate()
+                .any(|(block_ix, block)| {
+                    let block_range = block.source_range();
+                    !contents.is_included(block_ix)
+                        && block_range.start <= source_range.start
+                        && source_range.end <= block_range.end
+                })
+        });
+        if !in_document {
+            return;
+        }
+        // The range is into the text that was parsed, which lags behind
+        // while a parse is running.
+        let text = self.contents_text.clone();
+        let is_expected = text.get(source_range.clone()).map_or(false, is_expected);
+        if !is_expected || text != self.raw_text {
+            return;
+        }
+        let mut updated = text.clone();
+        updated.replace_range(source_range, replacement);
+
+        let write = cx.background_executor().spawn({
+            let path = path.clone();
+            let updated = updated.clone();
+            async move {
+                let current = std::fs::read_to_string(&path)?;
+                if content_hash(&current) != content_hash(&text) {
+                    return Ok(false);
+                }
+                std::fs::write(&path, updated)?;
+                Ok::<_, io::Error>(true)
+            }
+        });
+        cx.spawn(|this, mut cx| async move {
+            let written = write.await;
+            this.update(&mut cx, |this, cx| match written {
+                // The watcher reloads the file too, which confirms the change.
+                Ok(true) => this.set_text(updated, cx),
+                Ok(false) => this.show_toast(
+                    format!("{} changed on disk, so it wasn't updated", file_name(&path)),
+                    cx,
+                ),
+                Err(error) => {
+                    log::error!("failed to update {}: {}", path.display(), error);
+                    this.show_toast(format!("Could not update {}", file_name(&path)), cx);
+                }
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn activate_link(
+        &mut self,
+        link: &Link,
+        origin: LinkOrigin,
+        source_range: Range<usize>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        cx.emit(MarkdownEvent::LinkActivated {
+            url: link.url(),
+            kind: link.kind(),
+            origin,
+            source_range,
+            modifiers: cx.modifiers(),
+        });
+        if !self.suppress_default {
+            self.open_link(link, cx);
+        }
+    }
+
+    /// Opens a link clicked in the document. Links to headings scroll to
+    /// them, and others open the way the renderer opens them.
+    fn open_link(&mut self, link: &Link, cx: &mut ViewContext<Self>) {
+        match link {
+            Link::Fragment { fragment } => {
+                if !self.navigate_to_fragment(fragment, cx) {
+                    self.show_toast("No such section", cx);
+                }
+            }
+            Link::Path { path, fragment, .. } if is_markdown_file(path) => {
+                self.open_document(path.clone(), fragment.clone(), cx);
+            }
+            Link::Path { path, .. } if path.exists() => cx.reveal_path(path),
+            Link::Path { display_path, .. } => {
+                self.navigation_error =
+                    Some(format!("{} does not exist", display_path.display()).into());
+                cx.notify();
+            }
+            link => self.link_policy.open(link, cx),
+        }
+    }
+
+    /// Replaces the document with the Markdown file at `path`, scrolling to
+    /// the heading `fragment` points to, if any. [`GoBack`] returns to the
+    /// current document. If the file can't be read, the current document
+    /// stays, with a banner saying why.
+    pub fn open_document(
+        &mut self,
+        path: PathBuf,
+        fragment: Option<String>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let read = cx.background_executor().spawn({
+            let path = path.clone();
+            async move { std::fs::read_to_string(path) }
+        });
+        self.pending_read = Some(cx.spawn(|this, mut cx| async move {
+            let text = read.await;
+            this.update(&mut cx, |this, cx| match text {
+                Ok(text) => {
+                    let current = this.document_state();
+                    this.back_stack.push(HistoryEntry::Document(current));
+                    this.forward_stack.clear();
+                    this.show_document(
+                        DocumentState {
+                            base_directory: path.parent().map(Path::to_path_buf),
+                            path: Some(path),
+                            text,
+                            scroll_top: ListOffset::default(),
+                        },
+                        fragment.map(PendingScroll::Fragment),
+                        cx,
+                    );
+                }
+                // Binary files fail to read as UTF-8.
+                Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                    this.show_toast(format!("{} is not a text file", file_name(&path)), cx);
+                }
+                Err(error) => {
+                    this.navigation_error =
+                        Some(format!("Could not open {}: {}", path.display(), error).into());
+                    cx.notify();
+                }
+            })
+            .ok();
+        }));
+    }
+
+    /// Opens the first of the files dropped onto the view, as long as it's a
+    /// Markdown or text file.
+    fn open_dropped_paths(&mut self, paths: &ExternalPaths, cx: &mut ViewContext<Self>) {
+        let Some(path) = paths.paths().first() else {
+            return;
+        };
+        let text = path.to_string_lossy();
+        let text = expand_path(&text, None);
+        let (path_text, fragment) = split_path_fragment(&text, None);
+        let path = Path::new(path_text);
+
+        let is_text = path
+            .extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("txt"));
+        if is_markdown_file(path) || is_text {
+            self.open_document(path.to_path_buf(), fragment.map(str::to_string), cx);
+        } else {
+            self.show_toast(
+                format!("{} is not a Markdown or text file", file_name(path)),
+                cx,
+            );
+        }
+    }
+
+    fn document_state(&self) -> DocumentState {
+        DocumentState {
+            path: self.path.clone(),
+            text: self.raw_text.clone(),
+            base_directory: self.base_directory.clone(),
+            scroll_top: self.list_state.logical_scroll_top(),
+        }
+    }
+
+    /// Runs the code block at `source_range` with the host's code runner,
+    /// cancelling the block's run before if it's still going.
+    fn run_code(
+        &mut self,
+        source_range: Range<usize>,
+        language: SharedString,
+        code: SharedString,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(runner) = self.code_runner.clone() else {
+            return;
+        };
+        self.next_code_run_id += 1;
+        let id = self.next_code_run_id;
+        let previous = self
+            .presentation_state
+            .borrow_mut()
+            .code_runs
+            .insert(source_range, CodeRun { id, output: None });
+        if let Some(previous) = previous {
+            self.code_run_tasks.remove(&previous.id);
+        }
+
+        let run = (runner.run)(&language, &code, cx);
+        let task = cx.spawn(|this, mut cx| async move {
+            let output = run.await.map(SharedString::from).map_err(|error| {
+                log::info!("running a {language} code block failed: {error:#}");
+                SharedString::from(format!("{error:#}"))
+            });
+            this.update(&mut cx, |this, cx| {
+                this.code_run_tasks.remove(&id);
+                // The block may have moved since, or been edited away.
+                if let Some(run) = this
+                    .presentation_state
+                    .borrow_mut()
+                    .code_runs
+                    .values_mut()
+                    .find(|run| run.id == id)
+                {
+                    run.output = Some(output);
+                }
+                cx.notify();
+            })
+            .ok();
+        });
+        self.code_run_tasks.insert(id, task);
+        cx.notify();
+    }
+
+    fn show_document(
+        &mut self,
+        document: DocumentState,
+        scroll: Option<PendingScroll>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.navigation_error = None;
+        self.link_check = None;
+        self.code_run_tasks.clear();
+        {
+            let mut state = self.presentation_state.borrow_mut();
+            state.code_runs.clear();
+            state.collapsed_code_outputs.clear();
+        }
+        self.file_removed = false;
+        self.file_watch = None;
+        self.include_watch = None;
+        self.path = None;
+        self.base_directory = document.base_directory;
+        if let Some(path) = document.path {
+            self.watch_file(path, cx);
+        }
+        self.pending_scroll = Some(scroll.unwrap_or(PendingScroll::Offset(document.scroll_top)));
+        self.replace_text(document.text, cx);
+    }
+
+    /// Makes `entry` current, returning the entry to take its place in the
+    /// opposite history stack.
+    fn visit_history_entry(
+        &mut self,
+        entry: HistoryEntry,
+        cx: &mut ViewContext<Self>,
+    ) -> HistoryEntry {
+        // A document a link leads to that's still being read would
+        // otherwise replace the one the reader went to.
+        self.pending_read = None;
+        match entry {
+            HistoryEntry::Position(offset) => {
+                let current = self.list_state.logical_scroll_top();
+                self.scroll_animation = None;
+                self.list_state.scroll_to(offset);
+                cx.notify();
+                HistoryEntry::Position(current)
+            }
+            HistoryEntry::Document(document) => {
+                let current = self.document_state();
+                self.show_document(document, None, cx);
+                HistoryEntry::Document(current)
+            }
+        }
+    }
+
+    /// The top-level heading a link fragment, like the `setup` in `#setup`,
+    /// points to. Fragments are matched against heading slugs, and failing
+    /// that, slugified, so that `#Setup` works too.
+    fn heading_for_fragment(&self, fragment: &str) -> Option<usize> {
+        let fragment = percent_encoding::percent_decode_str(fragment).decode_utf8_lossy();
+        let slug = heading_slug(&fragment);
+        self.outline
+            .iter()
+            .find(|entry| entry.slug == fragment)
+            .or_else(|| self.outline.iter().find(|entry| entry.slug == slug))
+            .map(|entry| entry.block_ix)
+    }
+
+    /// Scrolls to the heading or line `fragment` points to and highlights
+    /// it for a moment. [`GoBack`] returns to where the reader was before. Returns
+    /// whether the document has such a heading.
+    pub fn navigate_to_fragment(&mut self, fragment: &str, cx: &mut ViewContext<Self>) -> bool {
+        let scroll_top = self.list_state.logical_scroll_top();
+        if !self.flash_fragment(fragment, cx) {
+            return false;
+        }
+
+        self.back_stack.push(HistoryEntry::Position(scroll_top));
+        self.forward_stack.clear();
+        true
+    }
+
+    /// Where the reader is in the document.
+    pub fn scroll_position(&self) -> ScrollPosition {
+        let scroll_top = self.list_state.logical_scroll_top();
+        let offset_fraction = self
+            .block_layouts
+            .borrow()
+            .height(scroll_top.item_ix)
+            .filter(|height| *height > Pixels::ZERO)
+            .map_or(0., |height| {
+                (scroll_top.offset_in_item / height).clamp(0., 1.)
+            });
+        ScrollPosition {
+            block_ix: scroll_top.item_ix,
+            offset_fraction,
+        }
+    }
+
+    /// Scrolls back to `position` as soon as the document is shown. A
+    /// document that has fewer blocks than it did is shown from its last
+    /// block.
+    pub fn restore_scroll_position(
+        &mut self,
+        position: ScrollPosition,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.pending_scroll = Some(PendingScroll::Position(position));
+        if self.contents.is_some() {
+            self.apply_pending_scroll(cx);
+        }
+    }
+
+    /// Scrolls to the heading or line `fragment` points to as soon as the
+    /// document is shown, like the `#install` or `#L42` of a path given on
+    /// the command line. A fragment no heading matches leaves the document
+    /// at the top, with a toast saying so.
+    pub fn scroll_to_fragment(&mut self, fragment: String, cx: &mut ViewContext<Self>) {
+        self.pending_scroll = Some(PendingScroll::Fragment(fragment));
+        if self.contents.is_some() {
+            self.apply_pending_scroll(cx);
+        }
+    }
+
+    /// The block a link fragment points to: the block with the line of a
+    /// fragment like `L42`, which [`MarkdownView::block_link`] makes, or the
+    /// top-level heading of any other.
+    fn block_for_fragment(&self, fragment: &str) -> Option<usize> {
+        let Some(line) = line_fragment(fragment) else {
+            return self.heading_for_fragment(fragment);
+        };
+        let offset = line_start_offset(&self.contents_text, line);
+        self.contents.as_ref()?.block_at_offset(offset)
+    }
+
+    /// Checks whether every link in the document works: that the headings
+    /// and files they point to exist, and that the web pages they point to
+    /// answer. Web pages are checked a few at a time, and each only once a
+    /// session, with the results shown as they come in.
+    pub fn check_links(&mut self, _: &CheckLinks, cx: &mut ViewContext<Self>) {
+        let Some(contents) = self.contents.as_ref() else {
+            return;
+        };
+        // Other URLs, like `mailto:` ones, and embedded data can't be checked.
+        let mut links = contents
+            .links()
+            .into_iter()
+            .map(|link| link.link)
+            .filter(|link| match link {
+                Link::Web { .. } => is_checked_online(link),
+                Link::Data { .. } => false,
+                Link::Path { .. } | Link::Fragment { .. } => true,
+            })
+            .collect::<Vec<_>>();
+        links.sort_by_key(|link| link.url());
+        links.dedup();
+
+        let mut broken = FxHashMap::default();
+        let mut urls = Vec::new();
+        let cache = cx.global::<LinkCheckCache>();
+        for link in &links {
+            let health = match link {
+                Link::Fragment { fragment } => Some(
+                    self.block_for_fragment(fragment)
+                        .map(|_| ())
+                        .ok_or_else(|| "No heading with this name in the document".into()),
+                ),
+                Link::Path { .. } => check_path(link),
+                Link::Web { url } => match cache.get(url) {
+                    Some(health) => Some(health.clone()),
+                    None => {
+                        urls.push(url.clone());
+                        None
+                    }
+                },
+                Link::Data { .. } => None,
+            };
+            if let Some(Err(reason)) = health {
+                broken.insert(link.clone(), reason);
+            }
+        }
+
+        let pending = urls.len();
+        let task = (pending > 0).then(|| {
+            let results = check_urls(urls, cx.http_client(), cx.background_executor().clone());
+            cx.spawn(|this, mut cx| async move {
+                let mut results = std::pin::pin!(results);
+                while let Some((url, health)) = results.next().await {
+                    let updated = this.update(&mut cx, |this, cx| {
+                        cx.global_mut::<LinkCheckCache>()
+                            .insert(url.clone(), health.clone());
+                        let Some(check) = this.link_check.as_mut() else {
+                            return;
+                        };
+                        check.checked += 1;
+                        if let Err(reason) = health {
+                            Rc::make_mut(&mut check.broken).insert(Link::Web { url }, reason);
+                        }
+                        cx.notify();
+                    });
+                    if updated.is_err() {
+                        return;
+                    }
+                }
+                this.update(&mut cx, |this, cx| {
+                    if let Some(check) = this.link_check.as_mut() {
+                        check.task = None;
+                    }
+                    cx.notify();
+                })
+                .ok();
+            })
+        });
+        self.link_check = Some(LinkCheck {
+            broken: Rc::new(broken),
+            checked: links.len() - pending,
+            total: links.len(),
+            task,
+        });
+        cx.notify();
+    }
+
+    /// A `file://` link to the block at `block_ix`, by the line it starts
+    /// on, like `file:///docs/guide.md#L42`. Documents that aren't files,
+    /// and blocks included from other files, have none.
+    pub fn block_link(&self, block_ix: usize) -> Option<String> {
+        let contents = self.contents.as_ref()?;
+        if contents.is_included(block_ix) {
+            return None;
+        }
+        let start = contents.children.get(block_ix)?.source_range().start;
+        let line = self.contents_text[..start].matches('\n').count() + 1;
+        let mut url = url::Url::from_file_path(self.path.as_ref()?).ok()?;
+        url.set_fragment(Some(&format!("L{line}")));
+        Some(url.to_string())
+    }
+
+    /// The top-level block at `position` in the window, as it was laid out
+    /// last.
+    fn block_at_position(&self, position: Point<Pixels>) -> Option<usize> {
+        let layouts = self.block_layouts.borrow();
+        let scroll_top = self.list_state.logical_scroll_top().item_ix;
+        (scroll_top..self.list_state.item_count())
+            .map_while(|block_ix| Some((block_ix, layouts.bounds(block_ix)?)))
+            .take_while(|(_, bounds)| bounds.top() <= position.y)
+            .find(|(_, bounds)| position.y < bounds.bottom())
+            .map(|(block_ix, _)| block_ix)
+    }
+
+    fn flash_fragment(&mut self, fragment: &str, cx: &mut ViewContext<Self>) -> bool {
+        let Some(block_ix) = self.block_for_fragment(fragment) else {
+            return false;
+        };
+
+        self.scroll_to_block(block_ix, cx);
+        let unflash = cx.spawn(|this, mut cx| async move {
+            cx.background_executor().timer(FLASH_DURATION).await;
+            this.update(&mut cx, |this, cx| {
+                this.flashed_block = None;
+                cx.notify();
+            })
+            .ok();
+        });
+        self.flashed_block = Some((block_ix, unflash));
+        true
+    }
+
+    /// Returns to where the reader was before the last jump to a heading or
+    /// to another document.
+    pub fn go_back(&mut self, _: &GoBack, cx: &mut ViewContext<Self>) {
+        if let Some(entry) = self.back_stack.pop() {
+            let current = self.visit_history_entry(entry, cx);
+            self.forward_stack.push(current);
+        }
+    }
+
+    /// Undoes the last [`GoBack`].
+    pub fn go_forward(&mut self, _: &GoForward, cx: &mut ViewContext<Self>) {
+        if let Some(entry) = self.forward_stack.pop() {
+            let current = self.visit_history_entry(entry, cx);
+            self.back_stack.push(current);
+        }
+    }
+
+    pub fn zoom_in(&mut self, _: &ZoomIn, cx: &mut ViewContext<Self>) {
+        self.set_text_scale(self.text_scale + TEXT_SCALE_STEP, cx);
+    }
+
+    pub fn zoom_out(&mut self, _: &ZoomOut, cx: &mut ViewContext<Self>) {
+        self.set_text_scale(self.text_scale - TEXT_SCALE_STEP, cx);
+    }
+
+    pub fn reset_zoom(&mut self, _: &ResetZoom, cx: &mut ViewContext<Self>) {
+        self.set_text_scale(1., cx);
+    }
+
+    /// Shows the find bar and moves focus to it.
+    pub fn find(&mut self, _: &Find, cx: &mut ViewContext<Self>) {
+        let find_bar = self.find_bar.get_or_insert_with(|| FindBar {
+            focus_handle: cx.focus_handle(),
+            query: String::new(),
+            case_sensitive: false,
+            matches: Vec::new(),
+            active_ix: None,
+        });
+        cx.focus(&find_bar.focus_handle);
+        cx.notify();
+    }
+
+    /// Hides the find bar and clears its highlights.
+    pub fn dismiss_find(&mut self, _: &DismissFind, cx: &mut ViewContext<Self>) {
+        if self.find_bar.take().is_some() {
+            self.search_highlights = None;
+            cx.focus(&self.focus_handle);
+            cx.notify();
+        }
+    }
+
+    pub fn toggle_case_sensitive(&mut self, _: &ToggleCaseSensitive, cx: &mut ViewContext<Self>) {
+        if let Some(find_bar) = &mut self.find_bar {
+            find_bar.case_sensitive = !find_bar.case_sensitive;
+            self.update_search(true, cx);
+        }
+    }
+
+    pub fn select_next_match(&mut self, _: &SelectNextMatch, cx: &mut ViewContext<Self>) {
+        self.activate_adjacent_match(true, cx);
+    }
+
+    pub fn select_previous_match(&mut self, _: &SelectPreviousMatch, cx: &mut ViewContext<Self>) {
+        self.activate_adjacent_match(false, cx);
+    }
+
+    fn activate_adjacent_match(&mut self, forward: bool, cx: &mut ViewContext<Self>) {
+        let Some(find_bar) = &mut self.find_bar else {
+            return;
+        };
+        let count = find_bar.matches.len();
+        if count == 0 {
+            return;
+        }
+
+        find_bar.active_ix = Some(match (find_bar.active_ix, forward) {
+            (Some(ix), true) => (ix + 1) % count,
+            (Some(ix), false) => (ix + count - 1) % count,
+            (None, true) => 0,
+            (None, false) => count - 1,
+        });
+        self.update_search_highlights();
+        self.reveal_active_match(cx);
+    }
+
+    /// Searches the document for the find bar's query again. With `reveal`,
+    /// the first match from the top of the viewport on becomes the active one
+    /// and is scrolled into view; otherwise the active match stays put, which
+    /// keeps the document from jumping around as it's re-parsed.
+    fn update_search(&mut self, reveal: bool, cx: &mut ViewContext<Self>) {
+        let (Some(find_bar), Some(contents)) = (&mut self.find_bar, &self.contents) else {
+            return;
+        };
+
+        find_bar.matches = search(contents, &find_bar.query, find_bar.case_sensitive);
+        find_bar.active_ix = if find_bar.matches.is_empty() {
+            None
+        } else if reveal {
+            let scroll_top = self.list_state.logical_scroll_top().item_ix;
+            Some(
+                find_bar
+                    .matches
+                    .iter()
+                    .position(|search_match| search_match.block_ix >= scroll_top)
+                    .unwrap_or(0),
+            )
+        } else {
+            Some(
+                find_bar
+                    .active_ix
+                    .unwrap_or(0)
+                    .min(find_bar.matches.len() - 1),
+            )
+        };
+
+        self.update_search_highlights();
+        if reveal {
+            self.reveal_active_match(cx);
+        }
+        cx.notify();
+    }
+
+    fn update_search_highlights(&mut self) {
+        self.search_highlights = self
+            .find_bar
+            .as_ref()
+            .map(|find_bar| Rc::new(SearchHighlights::new(&find_bar.matches, find_bar.active_ix)));
+    }
+
+    /// Scrolls the active match into view, unfolding the section and
+    /// expanding the code block it's in if needed.
+    fn reveal_active_match(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(search_match) = self
+            .find_bar
+            .as_ref()
+            .and_then(|find_bar| find_bar.matches.get(find_bar.active_ix?))
+            .cloned()
+        else {
+            return;
+        };
+
+        if let Some(ParsedMarkdownElement::CodeBlock(code_block)) = self
+            .contents
+            .as_ref()
+            .and_then(|contents| contents.children.get(search_match.block_ix))
+        {
+            self.presentation_state
+                .borrow_mut()
+                .expanded_code_blocks
+                .insert(code_block.source_range.clone());
+        }
+        self.unfold_block(search_match.block_ix, cx);
+        self.scroll_animation = None;
+        self.list_state.scroll_to_reveal_item(search_match.block_ix);
+        cx.notify();
+    }
+
+    /// Edits the find bar's query. The field only takes typed and pasted
+    /// text, and backspace.
+    fn handle_find_key(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
+        let Some(find_bar) = &mut self.find_bar else {
+            return;
+        };
+
+        let keystroke = &event.keystroke;
+        let modifiers = keystroke.modifiers;
+        let secondary = if cfg!(target_os = "macos") {
+            modifiers.platform
+        } else {
+            modifiers.control
+        };
+        if keystroke.key == "backspace" {
+            if secondary || modifiers.alt {
+                find_bar.query.clear();
+            } else {
+                find_bar.query.pop();
+            }
+        } else if secondary && keystroke.key == "v" {
+            let Some(text) = cx.read_from_clipboard().and_then(|item| item.text()) else {
+                return;
+            };
+            find_bar
+                .query
+                .push_str(text.lines().next().unwrap_or_default());
+        } else if let Some(text) = typed_text(keystroke).filter(|_| !secondary) {
+            find_bar.query.push_str(&text);
+        } else {
+            return;
+        }
+
+        cx.stop_propagation();
+        self.update_search(true, cx);
+    }
+
+    fn render_find_bar(&self, cx: &mut ViewContext<Self>) -> Option<AnyElement> {
+        let find_bar = self.find_bar.as_ref()?;
+        let colors = cx.theme().colors();
+
+        let field_focused = find_bar.focus_handle.is_focused(cx);
+        let field = h_flex()
+            .id("markdown-find-field")
+            .key_context("MarkdownFindBar")
+            .track_focus(&find_bar.focus_handle)
+            .on_key_down(cx.listener(Self::handle_find_key))
+            .flex_1()
+            .min_w_0()
+            .h_7()
+            .px_2()
+            .rounded_md()
+            .border_1()
+            .border_color(if field_focused {
+                colors.border_focused
+            } else {
+                colors.border
+            })
+            .bg(colors.editor_background)
+            .child(if find_bar.query.is_empty() {
+                Label::new("Find in document").color(Color::Placeholder)
+            } else {
+                Label::new(find_bar.query.clone())
+            })
+            .when(field_focused, |field| {
+                field.child(div().w(px(1.)).h_4().bg(colors.text))
+            });
+
+        let count = match (find_bar.active_ix, find_bar.matches.len()) {
+            (_, 0) if find_bar.query.is_empty() => String::new(),
+            (_, 0) => "No matches".to_string(),
+            (Some(ix), count) => format!("{} of {}", ix + 1, count),
+            (None, count) => format!("{} matches", count),
+        };
+
+        Some(
+            h_flex()
+                .gap_1()
+                .mb_2()
+                .child(field)
+                .child(Label::new(count).size(LabelSize::Small).color(Color::Muted))
+                .child(
+                    IconButton::new("markdown-find-case", IconName::CaseSensitive)
+                        .selected(find_bar.case_sensitive)
+                        .tooltip(|cx| Tooltip::text("Match Case", cx))
+                        .on_click(cx.listener(|this, _, cx| {
+                            this.toggle_case_sensitive(&ToggleCaseSensitive, cx)
+                        })),
+                )
+                .child(
+                    IconButton::new("markdown-find-previous", IconName::ArrowUp)
+                        .tooltip(|cx| Tooltip::text("Previous Match", cx))
+                        .on_click(cx.listener(|this, _, cx| {
+                            this.select_previous_match(&SelectPreviousMatch, cx)
+                        })),
+                )
+                .child(
+                    IconButton::new("markdown-find-next", IconName::ArrowDown)
+                        .tooltip(|cx| Tooltip::text("Next Match", cx))
+                        .on_click(
+                            cx.listener(|this, _, cx| this.select_next_match(&SelectNextMatch, cx)),
+                        ),
+                )
+                .child(
+                    IconButton::new("markdown-find-close", IconName::Close)
+                        .tooltip(|cx| Tooltip::text("Close", cx))
+                        .on_click(cx.listener(|this, _, cx| this.dismiss_find(&DismissFind, cx))),
+                )
+                .into_any_element(),
+        )
+    }
+
+    /// Shows the picker for jumping to one of the document's headings, or
+    /// hides it if it's shown.
+    pub fn go_to_heading(&mut self, _: &GoToHeading, cx: &mut ViewContext<Self>) {
+        if self.heading_picker.is_some() {
+            self.dismiss_heading_picker(cx);
+            return;
+        }
+
+        let picker = HeadingPicker {
+            focus_handle: cx.focus_handle(),
+            query: String::new(),
+            matches: (0..self.outline.len()).collect(),
+            selected_ix: 0,
+        };
+        cx.focus(&picker.focus_handle);
+        self.heading_picker = Some(picker);
+        cx.notify();
+    }
+
+    fn dismiss_heading_picker(&mut self, cx: &mut ViewContext<Self>) {
+        if self.heading_picker.take().is_some() {
+            cx.focus(&self.focus_handle);
+            cx.notify();
+        }
+    }
+
+    fn confirm_heading(&mut self, cx: &mut ViewContext<Self>) {
+        let slug = self
+            .heading_picker
+            .as_ref()
+            .and_then(|picker| picker.matches.get(picker.selected_ix))
+            .and_then(|ix| self.outline.get(*ix))
+            .map(|entry| entry.slug.clone());
+        self.dismiss_heading_picker(cx);
+        if let Some(slug) = slug {
+            self.scroll_to_heading(&slug, cx);
+        }
+    }
+
+    /// Filters the outline to the entries that fuzzily match the picker's
+    /// query, by their text and, when they're numbered, their number.
+    fn update_heading_matches(&mut self, cx: &mut ViewContext<Self>) {
+        let numbers = self.outline_numbers(cx);
+        let Some(picker) = &mut self.heading_picker else {
+            return;
+        };
+        let mut matches: Vec<(usize, usize)> = self
+            .outline
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, entry)| {
+                let candidate = match &numbers {
+                    Some(numbers) => format!("{} {}", numbers[ix], entry.text),
+                    None => entry.text.to_string(),
+                };
+                Some((fuzzy_score(&candidate, &picker.query)?, ix))
+            })
+            .collect();
+        matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+        picker.matches = matches.into_iter().map(|(_, ix)| ix).collect();
+        picker.selected_ix = 0;
+        cx.notify();
+    }
+
+    /// Edits the picker's query and moves its selection. Like the find bar,
+    /// the field only takes typed text and backspace.
+    fn handle_heading_picker_key(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
+        let Some(picker) = &mut self.heading_picker else {
+            return;
+        };
+
+        let keystroke = &event.keystroke;
+        let secondary = if cfg!(target_os = "macos") {
+            keystroke.modifiers.platform
+        } else {
+            keystroke.modifiers.control
+        };
+        match keystroke.key.as_str() {
+            "escape" => self.dismiss_heading_picker(cx),
+            "enter" => self.confirm_heading(cx),
+            "up" => {
+                picker.selected_ix = picker.selected_ix.saturating_sub(1);
+                cx.notify();
+            }
+            "down" => {
+                picker.selected_ix =
+                    (picker.selected_ix + 1).min(picker.matches.len().saturating_sub(1));
+                cx.notify();
+            }
+            "backspace" => {
+                picker.query.pop();
+                self.update_heading_matches(cx);
+            }
+            _ => match typed_text(keystroke).filter(|_| !secondary) {
+                Some(text) => {
+                    picker.query.push_str(&text);
+                    self.update_heading_matches(cx);
+                }
+                None => return,
+            },
+        }
+        cx.stop_propagation();
+    }
+
+    /// The section number of each outline entry, if the setting numbers them.
+    fn outline_numbers(&self, cx: &AppContext) -> Option<Vec<String>> {
+        MarkdownPreviewSettings::get_global(cx)
+            .outline_numbers
+            .then(|| section_numbers(&self.outline))
+    }
+
+    fn render_heading_picker(&self, cx: &mut ViewContext<Self>) -> Option<AnyElement> {
+        let picker = self.heading_picker.as_ref()?;
+        let numbers = self.outline_numbers(cx);
+        let colors = cx.theme().colors();
+
+        // The matches are of the outline as it was when the query last
+        // changed, which a parse since may have shortened.
+        let entries = picker
+            .matches
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, entry_ix)| {
+                let entry = self.outline.get(*entry_ix)?;
+                Some(
+                    h_flex()
+                        .id(("markdown-heading-picker-entry", ix))
+                        .gap_2()
+                        .py_1()
+                        .pr_2()
+                        .pl(px(8.) + px(12.) * entry.depth as f32)
+                        .rounded_md()
+                        .when(ix == picker.selected_ix, |this| {
+                            this.bg(colors.element_selected)
+                        })
+                        .hover(|style| style.bg(colors.element_hover))
+                        .children(numbers.as_ref().map(|numbers| {
+                            Label::new(numbers[*entry_ix].clone()).color(Color::Muted)
+                        }))
+                        .child(Label::new(entry.text.clone()).single_line())
+                        .on_click(cx.listener(move |this, _, cx| {
+                            if let Some(picker) = &mut this.heading_picker {
+                                picker.selected_ix = ix;
+                            }
+                            this.confirm_heading(cx);
+                        })),
+                )
+            });
+
+        let picker_element = v_flex()
+            .key_context("MarkdownHeadingPicker")
+            .track_focus(&picker.focus_handle)
+            .on_key_down(cx.listener(Self::handle_heading_picker_key))
+            .on_mouse_down_out(cx.listener(|this, _, cx| this.dismiss_heading_picker(cx)))
+            .w(px(480.))
+            .max_h(px(360.))
+            .p_1()
+            .gap_1()
+            .rounded_lg()
+            .border_1()
+            .border_color(colors.border)
+            .bg(colors.elevated_surface_background)
+            .shadow_lg()
+            .child(
+                h_flex()
+                    .h_7()
+                    .px_2()
+                    .border_b_1()
+                    .border_color(colors.border_variant)
+                    .child(if picker.query.is_empty() {
+                        Label::new("Go to heading").color(Color::Placeholder)
+                    } else {
+                        Label::new(picker.query.clone())
+                    }),
+            )
+            .child(
+                v_flex()
+                    .id("markdown-heading-picker-entries")
+                    .overflow_y_scroll()
+                    .children(entries)
+                    .when(picker.matches.is_empty(), |list| {
+                        let message = if self.outline.is_empty() {
+                            "This document has no headings"
+                        } else {
+                            "No matching headings"
+                        };
+                        list.child(
+                            div()
+                                .px_2()
+                                .py_1()
+                                .child(Label::new(message).color(Color::Muted)),
+                        )
+                    }),
+            );
+
+        Some(
+            h_flex()
+                .absolute()
+                .top_0()
+                .left_0()
+                .w_full()
+                .justify_center()
+                .pt_8()
+                .child(picker_element)
+                .into_any_element(),
+        )
+    }
+
+    /// Shows or hides the panel listing the document's headings. It's bound
+    /// to alt-cmd-o, or alt-ctrl-o off macOS.
+    pub fn toggle_outline(&mut self, _: &ToggleOutline, cx: &mut ViewContext<Self>) {
+        self.outline_visible = !self.outline_visible;
+        cx.notify();
+    }
+
+    /// Asks where to save the document as a standalone HTML page and writes
+    /// it there, with a table of contents and its `toc.json` if the settings
+    /// ask for them.
+    pub fn export_html(&mut self, _: &ExportHtml, cx: &mut ViewContext<Self>) {
+        let Some(contents) = &self.contents else {
+            return;
+        };
+
+        let settings = MarkdownPreviewSettings::get_global(cx);
+        let levels = TocLevels {
+            min: settings.export_toc_min_level,
+            max: settings.export_toc_max_level,
+        };
+        let options = HtmlExportOptions {
+            table_of_contents: settings.export_table_of_contents.then_some(levels),
+            ..Default::default()
+        };
+        let html = markdown_html::export_html(contents, &options);
+        let toc_json = settings.export_toc_json.then(|| {
+            (
+                "toc.json",
+                markdown_html::export_toc_json(contents, levels).into_bytes(),
+            )
+        });
+        self.save_export(html.into_bytes(), toc_json, cx);
+    }
+
+    /// Asks where to save the document as a PDF and writes it there, with
+    /// its title and page numbers on every page.
+    pub fn export_pdf(&mut self, _: &ExportPdf, cx: &mut ViewContext<Self>) {
+        let Some(contents) = &self.contents else {
+            return;
+        };
+
+        let options = PdfExportOptions {
+            header_and_footer: true,
+            ..Default::default()
+        };
+        let pdf = markdown_pdf::export_pdf(contents, &options);
+        self.save_export(pdf, None, cx);
+    }
+
+    /// Prints the document, paginated like a PDF export but drawn for paper:
+    /// code blocks are outlined rather than shaded, and links to web pages
+    /// can be followed by their URLs.
+    ///
+    /// The PDF goes to the OS's default printer, through `lp` on macOS and
+    /// Linux and the shell's print verb on Windows.
+    pub fn print(&mut self, _: &Print, cx: &mut ViewContext<Self>) {
+        let Some(contents) = &self.contents else {
+            return;
+        };
+
+        let title = self.title();
+        let options = PdfExportOptions {
+            header_and_footer: true,
+            title: Some(title.to_string()),
+            code_block_borders: true,
+            link_urls: MarkdownPreviewSettings::get_global(cx).print_link_urls,
+            ..Default::default()
+        };
+        let pdf = markdown_pdf::export_pdf(contents, &options);
+        // The file name is what printer queues show for the job.
+        let file_name: String = title
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == ' ' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("{}.pdf", file_name));
+
+        let printed = cx.background_executor().spawn(async move {
+            std::fs::write(&path, pdf)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            let mut command = if cfg!(target_os = "windows") {
+                let mut command = std::process::Command::new("powershell");
+                // Quoted for PowerShell, which doubles single quotes.
+                let file = path.display().to_string().replace('\'', "''");
+                command.args([
+                    "-NoProfile".to_string(),
+                    "-Command".to_string(),
+                    format!("Start-Process -FilePath '{}' -Verb Print", file),
+                ]);
+                command
+            } else {
+                let mut command = std::process::Command::new("lp");
+                command.arg(&path);
+                command
+            };
+            let status = command
+                .status()
+                .context("failed to start the print command")?;
+            anyhow::ensure!(status.success(), "the print command failed: {}", status);
+            Ok(())
+        });
+        cx.spawn(|this, mut cx| async move {
+            let message = match printed.await {
+                Ok(()) => "Sent to the printer",
+                Err(error) => {
+                    log::error!("failed to print: {:#}", error);
+                    "Could not print"
+                }
+            };
+            this.update(&mut cx, |this, cx| this.show_toast(message, cx))
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Writes an exported document wherever the user picks.
+    /// Asks where to save `bytes`, and writes them there, along with the
+    /// `companion` file of the given name in the same folder, if any.
+    fn save_export(
+        &self,
+        bytes: Vec<u8>,
+        companion: Option<(&'static str, Vec<u8>)>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let directory = self
+            .base_directory
+            .clone()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let path = cx.prompt_for_new_path(&directory);
+        cx.spawn(|_, cx| async move {
+            let Some(path) = path.await?? else {
+                return Ok(());
+            };
+            cx.background_executor()
+                .spawn(async move {
+                    std::fs::write(&path, bytes)
+                        .with_context(|| format!("failed to write {}", path.display()))?;
+                    if let Some((file_name, bytes)) = companion {
+                        let path = path.with_file_name(file_name);
+                        std::fs::write(&path, bytes)
+                            .with_context(|| format!("failed to write {}", path.display()))?;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                })
+                .await
+        })
+        .detach_and_log_err(cx);
+    }
+
+    pub fn fold_all_sections(&mut self, _: &FoldAllSections, cx: &mut ViewContext<Self>) {
+        let Some(contents) = &self.contents else {
+            return;
+        };
+
+        let mut state = self.presentation_state.borrow_mut();
+        for block in &contents.children {
+            if let ParsedMarkdownElement::Heading(heading) = block {
+                state.folded_sections.insert(heading.slug.clone());
+            }
+        }
+        cx.notify();
+    }
+
+    pub fn unfold_all_sections(&mut self, _: &UnfoldAllSections, cx: &mut ViewContext<Self>) {
+        self.presentation_state.borrow_mut().folded_sections.clear();
+        cx.notify();
+    }
+
+    /// Unfolds the sections containing the block at `block_ix`, and the
+    /// block's own section if it is a heading, so that navigating to it
+    /// shows it.
+    pub fn unfold_block(&mut self, block_ix: usize, cx: &mut ViewContext<Self>) {
+        let Some(contents) = &self.contents else {
+            return;
+        };
+
+        let mut state = self.presentation_state.borrow_mut();
+        let headings = contents.enclosing_headings(block_ix).into_iter();
+        for heading_ix in headings.chain(Some(block_ix)) {
+            if let Some(ParsedMarkdownElement::Heading(heading)) = contents.children.get(heading_ix)
+            {
+                state.folded_sections.remove(&heading.slug);
+            }
+        }
+        cx.notify();
+    }
+
+    /// Scrolls to the heading with the given slug, returning whether the
+    /// document has such a heading.
+    pub fn scroll_to_heading(&mut self, slug: &str, cx: &mut ViewContext<Self>) -> bool {
+        let block_ix = self.contents.as_ref().and_then(|contents| {
+            contents.children.iter().position(|block| {
+                matches!(block, ParsedMarkdownElement::Heading(heading) if heading.slug == slug)
+            })
+        });
+
+        match block_ix {
+            Some(block_ix) => self.scroll_to_block(block_ix, cx),
+            None => false,
+        }
+    }
+
+    /// Where the document's blocks were laid out, from the top of the
+    /// document, as of the last frame. Blocks that haven't been near the
+    /// viewport yet aren't laid out, and their offsets are estimated.
+    pub fn layout_info(&self) -> Option<&LayoutInfo> {
+        self.layout_info.as_ref().map(|(_, _, info)| info)
+    }
+
+    /// Works out where the blocks are after they've been laid out, and tells
+    /// subscribers if that changed.
+    fn update_layout_info(&mut self, cx: &mut ViewContext<Self>) {
+        let version = self.block_layouts.borrow().version();
+        let block_count = self.block_count();
+        if self
+            .layout_info
+            .as_ref()
+            .map_or(false, |(old_version, old_count, _)| {
+                (*old_version, *old_count) == (version, block_count)
+            })
+        {
+            return;
+        }
+
+        let info = self.block_layouts.borrow().layout_info(block_count);
+        let changed = self
+            .layout_info
+            .as_ref()
+            .map_or(true, |(_, _, old_info)| info.differs_from(old_info));
+        self.layout_info = Some((version, block_count, info));
+        if changed {
+            cx.emit(MarkdownEvent::LayoutChanged);
+        }
+    }
+
+    /// Smoothly scrolls until the top of the block at `block_ix` is at the top
+    /// of the viewport, unfolding any sections hiding it. Returns whether the
+    /// document has such a block.
+    pub fn scroll_to_block(&mut self, block_ix: usize, cx: &mut ViewContext<Self>) -> bool {
+        let block_count = self.block_count();
+        if block_ix >= block_count {
+            return false;
+        }
+
+        self.unfold_block(block_ix, cx);
+        if self.scroll_host(None, block_ix, cx) {
+            return true;
+        }
+        let end = self.block_layouts.borrow().estimated_offset(block_ix);
+        self.animate_scroll(
+            end,
+            ListOffset {
+                item_ix: block_ix,
+                offset_in_item: px(0.),
+            },
+            cx,
+        );
+        true
+    }
+
+    /// Scrolls the host's [`MarkdownViewBuilder::scroll_handle`] by
+    /// `distance`, or until the top of the block at `block_ix` is at the top
+    /// of its viewport. Returns whether the view is scrolled by its host.
+    fn scroll_host(
+        &mut self,
+        distance: Option<Pixels>,
+        block_ix: usize,
+        cx: &mut ViewContext<Self>,
+    ) -> bool {
+        let ScrollMode::Host(handle) = &self.scroll_mode else {
+            return false;
+        };
+        let Some(handle) = handle else {
+            return true;
+        };
+        let distance = match distance {
+            Some(distance) => distance,
+            // Blocks are where they were when the window was last drawn.
+            None => match self.block_layouts.borrow().bounds(block_ix) {
+                Some(bounds) => bounds.top() - handle.bounds().top(),
+                None => return true,
+            },
+        };
+        let mut offset = handle.offset();
+        // The element clamps offsets past the end of what it scrolls.
+        offset.y = (offset.y - distance).min(Pixels::ZERO);
+        handle.set_offset(offset);
+        cx.notify();
+        true
+    }
+
+    /// Scrolls just far enough to show the block at `offset` in the source,
+    /// like the one the cursor is in when editing it.
+    pub fn reveal_source_offset(&mut self, offset: usize, cx: &mut ViewContext<Self>) {
+        let Some(block_ix) = self
+            .contents
+            .as_ref()
+            .and_then(|contents| contents.block_at_offset(offset))
+        else {
+            return;
+        };
+
+        self.unfold_block(block_ix, cx);
+        self.scroll_animation = None;
+        self.list_state.scroll_to_reveal_item(block_ix);
+        cx.notify();
+    }
+
+    /// How tall the document was laid out, for views built with
+    /// [`MarkdownViewBuilder::auto_height`], as of when the window was last
+    /// drawn.
+    pub fn content_height(&self) -> Option<Pixels> {
+        match self.scroll_mode {
+            ScrollMode::Owned => None,
+            ScrollMode::Host(_) => Some(self.viewport_bounds.get().size.height),
+        }
+    }
+
+    /// Where the block at the top of the viewport starts in the source.
+    pub fn top_source_offset(&self) -> Option<usize> {
+        let block_ix = self.list_state.logical_scroll_top().item_ix;
+        let block = self.contents.as_ref()?.children.get(block_ix)?;
+        Some(block.source_range().start)
+    }
+
+    fn block_count(&self) -> usize {
+        self.contents
+            .as_ref()
+            .map_or(0, |contents| contents.children.len())
+    }
+
+    /// The estimated distance from the top of the document to the top of the
+    /// viewport.
+    fn scroll_offset(&self) -> Pixels {
+        let scroll_top = self.list_state.logical_scroll_top();
+        self.block_layouts
+            .borrow()
+            .estimated_offset(scroll_top.item_ix)
+            + scroll_top.offset_in_item
+    }
+
+    /// Smoothly scrolls to `end`, an estimated offset from the top of the
+    /// document, and then exactly to `target`. Every smooth scroll goes
+    /// through here, so with
+    /// [`reduce_motion`](MarkdownPreviewSettings::reduce_motion) set they all
+    /// jump to `target` instead.
+    fn animate_scroll(&mut self, end: Pixels, target: ListOffset, cx: &mut ViewContext<Self>) {
+        if MarkdownPreviewSettings::get_global(cx).reduce_motion {
+            self.scroll_animation = None;
+            self.list_state.scroll_to(target);
+            cx.notify();
+            return;
+        }
+
+        // Blocks that haven't been rendered yet have no height, so the
+        // animation goes by estimated offsets and only the final step, which
+        // the list resolves itself, is exact.
+        let start = self.scroll_offset();
+        let block_count = self.block_count();
+
+        self.scroll_animation = Some(cx.spawn(|this, mut cx| async move {
+            for step in 1..=SCROLL_ANIMATION_STEPS {
+                cx.background_executor()
+                    .timer(SCROLL_ANIMATION_STEP_DURATION)
+                    .await;
+
+                let updated = this.update(&mut cx, |this, cx| {
+                    let offset = if step == SCROLL_ANIMATION_STEPS {
+                        target
+                    } else {
+                        let progress = step as f32 / SCROLL_ANIMATION_STEPS as f32;
+                        let eased = 1. - (1. - progress).powi(3);
+                        let (item_ix, offset_in_item) = this
+                            .block_layouts
+                            .borrow()
+                            .block_at_offset(start + (end - start) * eased, block_count);
+                        ListOffset {
+                            item_ix,
+                            offset_in_item,
+                        }
+                    };
+                    this.list_state.scroll_to(offset);
+                    cx.notify();
+                });
+                if updated.is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+
+    /// Moves the top of the viewport by `distance`, which is negative to
+    /// scroll up, either at once or smoothly.
+    fn scroll_by(&mut self, distance: Pixels, animate: bool, cx: &mut ViewContext<Self>) {
+        let block_count = self.block_count();
+        if block_count == 0 || self.scroll_host(Some(distance), 0, cx) {
+            return;
+        }
+
+        let end = (self.scroll_offset() + distance).max(Pixels::ZERO);
+        let (item_ix, offset_in_item) = self
+            .block_layouts
+            .borrow()
+            .block_at_offset(end, block_count);
+        let target = ListOffset {
+            item_ix,
+            offset_in_item,
+        };
+        if animate {
+            self.animate_scroll(end, target, cx);
+        } else {
+            self.scroll_animation = None;
+            self.list_state.scroll_to(target);
+            cx.notify();
+        }
+    }
+
+    /// Whether a keystroke should go to the query of the find bar or the
+    /// heading picker rather than scroll the document. Scrolling actions let
+    /// such keystrokes through.
+    fn typing_in_field(&self, cx: &mut ViewContext<Self>) -> bool {
+        let typing = self
+            .find_bar
+            .as_ref()
+            .is_some_and(|find_bar| find_bar.focus_handle.is_focused(cx))
+            || self
+                .heading_picker
+                .as_ref()
+                .is_some_and(|picker| picker.focus_handle.is_focused(cx));
+        if typing {
+            cx.propagate();
+        }
+        typing
+    }
+
+    /// The height of a line of body text.
+    fn line_height(&self, cx: &ViewContext<Self>) -> Pixels {
+        cx.text_style().line_height_in_pixels(cx.rem_size()) * self.text_scale
+    }
+
+    /// Page jumps keep a line of the previous page in view, for context.
+    fn page_height(&self, cx: &ViewContext<Self>) -> Pixels {
+        (self.viewport_bounds.get().size.height - self.line_height(cx)).max(self.line_height(cx))
+    }
+
+    pub fn scroll_line_up(&mut self, _: &ScrollLineUp, cx: &mut ViewContext<Self>) {
+        if !self.typing_in_field(cx) {
+            self.scroll_by(-self.line_height(cx), false, cx);
+        }
+    }
+
+    pub fn scroll_line_down(&mut self, _: &ScrollLineDown, cx: &mut ViewContext<Self>) {
+        if !self.typing_in_field(cx) {
+            self.scroll_by(self.line_height(cx), false, cx);
+        }
+    }
+
+    pub fn scroll_page_up(&mut self, _: &ScrollPageUp, cx: &mut ViewContext<Self>) {
+        if !self.typing_in_field(cx) {
+            self.scroll_by(-self.page_height(cx), true, cx);
+        }
+    }
+
+    pub fn scroll_page_down(&mut self, _: &ScrollPageDown, cx: &mut ViewContext<Self>) {
+        if !self.typing_in_field(cx) {
+            self.scroll_by(self.page_height(cx), true, cx);
+        }
+    }
+
+    pub fn scroll_to_top(&mut self, _: &ScrollToTop, cx: &mut ViewContext<Self>) {
+        if !self.typing_in_field(cx) {
+            self.scroll_to_block(0, cx);
+        }
+    }
+
+    pub fn scroll_to_bottom(&mut self, _: &ScrollToBottom, cx: &mut ViewContext<Self>) {
+        if self.typing_in_field(cx) {
+            return;
+        }
+
+        // The list keeps the end of the document at the bottom of the
+        // viewport when scrolled past it.
+        let block_count = self.block_count();
+        let end = self.block_layouts.borrow().estimated_offset(block_count)
+            - self.viewport_bounds.get().size.height;
+        self.animate_scroll(
+            end.max(Pixels::ZERO),
+            ListOffset {
+                item_ix: block_count,
+                offset_in_item: px(0.),
+            },
+            cx,
+        );
+    }
+
+    fn update_folded_blocks(&mut self) {
+        self.folded_blocks.clear();
+        let Some(contents) = &self.contents else {
+            return;
+        };
+
+        let state = self.presentation_state.borrow();
+        if state.folded_sections.is_empty() {
+            return;
+        }
+
+        let mut ix = 0;
+        while ix < contents.children.len() {
+            match &contents.children[ix] {
+                ParsedMarkdownElement::Heading(heading)
+                    if state.folded_sections.contains(&heading.slug) =>
+                {
+                    let end = contents.section_end(ix);
+                    self.folded_blocks.push(ix + 1..end);
+                    ix = end;
+                }
+                _ => ix += 1,
+            }
+        }
+    }
+
+    /// The debug overlay's readout of the last parse and frame times, and a
+    /// canvas that measures this frame once it's painted.
+    fn render_debug_stats(
+        &self,
+        frame_started: Instant,
+        cx: &mut ViewContext<Self>,
+    ) -> Option<impl IntoElement> {
+        if !self.debug_overlay {
+            return None;
+        }
+        let milliseconds = |duration: Option<Duration>| {
+            duration.map_or("–".to_string(), |duration| {
+                format!("{:.1} ms", duration.as_secs_f64() * 1000.)
+            })
+        };
+        let blocks = self.contents.as_ref().map_or(0, |c| c.children.len());
+        let stats = format!(
+            "parse {} · {} blocks · frame {}",
+            milliseconds(self.last_parse_duration),
+            blocks,
+            milliseconds(self.last_frame_duration.get())
+        );
+        let breakdown = self.last_parse_timing.as_ref().map(parse_timing_breakdown);
+        let last_frame_duration = self.last_frame_duration.clone();
+        Some(
+            div()
+                .absolute()
+                .bottom_2()
+                .left_2()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .border_1()
+                .border_color(cx.theme().status().info_border)
+                .bg(cx.theme().colors().elevated_surface_background)
+                .child(Label::new(stats).size(LabelSize::Small))
+                .children(breakdown.map(|breakdown| {
+                    Label::new(breakdown)
+                        .size(LabelSize::XSmall)
+                        .color(Color::Muted)
+                }))
+                .child(
+                    canvas(
+                        |_, _| {},
+                        move |_, _, _| last_frame_duration.set(Some(frame_started.elapsed())),
+                    )
+                    .absolute()
+                    .size_0(),
+                ),
+        )
+    }
+
+    /// Gray bars roughly in the shape of a document, while it loads.
+    fn render_loading(&self, cx: &mut ViewContext<Self>) -> AnyElement {
+        let bar = |width: f32| {
+            div()
+                .h_3()
+                .w(relative(width))
+                .rounded_sm()
+                .bg(cx.theme().colors().element_background)
+        };
+        v_flex()
+            .key_context("MarkdownPreview")
+            .track_focus(&self.focus_handle)
+            .size_full()
+            .p_4()
+            .gap_3()
+            .bg(cx.theme().colors().editor_background)
+            .child(bar(0.4).h_5().mb_2())
+            .children([0.9, 0.95, 0.85, 0.6].map(bar))
+            .child(div().h_2())
+            .children([0.9, 0.8, 0.7].map(bar))
+            .into_any_element()
+    }
+
+    /// What's shown in place of a document without blocks: an empty file, one
+    /// of whitespace, or one with only front matter, which the panel above
+    /// shows.
+    fn render_empty_state(&self, cx: &mut ViewContext<Self>) -> Option<AnyElement> {
+        let message = self.empty_state_message()?;
+        match &self.empty_state {
+            EmptyState::Hidden => None,
+            EmptyState::Custom(render) => Some(render(cx)),
+            EmptyState::Message => {
+                let locale = MarkdownPreviewSettings::get_global(cx).locale();
+                let file = self.path.as_deref().map(|path| {
+                    let size = match self.contents_text.len() {
+                        1 => "1 byte".to_string(),
+                        size => format!("{} bytes", locale.format_count(size)),
+                    };
+                    format!("{} · {}", file_name(path), size)
+                });
+                Some(
+                    v_flex()
+                        .w_full()
+                        .h_full()
+                        .py_8()
+                        .items_center()
+                        .justify_center()
+                        .gap_1()
+                        .child(Label::new(message).color(Color::Muted))
+                        .children(file.map(|file| {
+                            Label::new(file).size(LabelSize::Small).color(Color::Muted)
+                        }))
+                        .into_any_element(),
+                )
+            }
+        }
+    }
+
+    fn empty_state_message(&self) -> Option<&'static str> {
+        let contents = self
+            .contents
+            .as_ref()
+            .filter(|contents| contents.children.is_empty() && !self.partial_contents)?;
+        Some(if contents.front_matter.is_some() {
+            "This document has no content"
+        } else {
+            "Nothing to preview"
+        })
+    }
+
+    fn render_load_error(&self, error: LoadError, cx: &mut ViewContext<Self>) -> AnyElement {
+        let retry = error.path.clone().map(|path| {
+            Button::new("markdown-retry-load", "Retry")
+                .style(ButtonStyle::Filled)
+                .on_click(cx.listener(move |this, _, cx| this.open_file(path.clone(), cx)))
+        });
+        v_flex()
+            .key_context("MarkdownPreview")
+            .track_focus(&self.focus_handle)
+            .size_full()
+            .items_center()
+            .justify_center()
+            .gap_2()
+            .bg(cx.theme().colors().editor_background)
+            .child(
+                Icon::new(IconName::XCircle)
+                    .size(IconSize::Medium)
+                    .color(Color::Error),
+            )
+            .child(Label::new(error.message).size(LabelSize::Large))
+            .children(error.path.map(|path| {
+                Label::new(path.display().to_string())
+                    .size(LabelSize::Small)
+                    .color(Color::Muted)
+            }))
+            .children(retry)
+            .into_any_element()
+    }
+
+    fn render_outline(&self, cx: &mut ViewContext<Self>) -> AnyElement {
+        let panel = v_flex()
+            .id("markdown-outline")
+            .flex_none()
+            .w(self.outline_width)
+            .h_full()
+            .py_2()
+            .overflow_y_scroll()
+            .border_r_1()
+            .border_color(cx.theme().colors().border);
+
+        if self.outline.is_empty() {
+            return panel
+                .px_3()
+                .child(
+                    Label::new("This document has no headings.")
+                        .size(LabelSize::Small)
+                        .color(Color::Muted),
+                )
+                .into_any_element();
+        }
+
+        // The section being read is the one of the last heading above the
+        // top of the viewport.
+        let scroll_top = self.list_state.logical_scroll_top().item_ix;
+        let active_ix = self
+            .outline
+            .iter()
+            .rposition(|entry| entry.block_ix <= scroll_top);
+        let numbers = self.outline_numbers(cx);
+
+        panel
+            .children(self.outline.iter().enumerate().map(|(ix, entry)| {
+                let block_ix = entry.block_ix;
+                let active = Some(ix) == active_ix;
+                h_flex()
+                    .id(("outline-entry", ix))
+                    .group("markdown-outline-entry")
+                    .w_full()
+                    .gap_1()
+                    .py_0p5()
+                    .pr_2()
+                    .pl(px(12.) + px(12.) * entry.depth as f32)
+                    .when(active, |this| this.bg(cx.theme().colors().element_selected))
+                    .hover(|style| style.bg(cx.theme().colors().element_hover))
+                    .cursor_pointer()
+                    .children(numbers.as_ref().map(|numbers| {
+                        Label::new(numbers[ix].clone())
+                            .size(LabelSize::Small)
+                            .color(Color::Muted)
+                    }))
+                    .child(
+                        div().flex_1().min_w_0().child(
+                            Label::new(entry.text.clone())
+                                .size(LabelSize::Small)
+                                .color(if active { Color::Default } else { Color::Muted })
+                                .single_line(),
+                        ),
+                    )
+                    .child(
+                        div().visible_on_hover("markdown-outline-entry").child(
+                            IconButton::new(("outline-copy-section", ix), IconName::Copy)
+                                .icon_size(IconSize::XSmall)
+                                .icon_color(Color::Muted)
+                                .tooltip(|cx| Tooltip::text("Copy Section as Markdown", cx))
+                                .on_click(cx.listener(move |this, _, cx| {
+                                    this.copy_section(block_ix, cx);
+                                })),
+                        ),
+                    )
+                    .on_click(cx.listener(move |this, _, cx| {
+                        this.scroll_to_block(block_ix, cx);
+                    }))
+            }))
+            .into_any_element()
+    }
+
+    /// The banner above the document saying where it may not be parsed the
+    /// way it looks like it should be, with a button to scroll there.
+    fn render_diagnostics_banner(&self, cx: &mut ViewContext<Self>) -> Option<AnyElement> {
+        if self.diagnostics_dismissed {
+            return None;
+        }
+        let diagnostics = &self.contents.as_ref()?.diagnostics;
+        let first = diagnostics.first()?;
+        let offset = first.source_range.start;
+        let line = self
+            .contents_text
+            .get(..offset)
+            .map_or(1, |before| before.matches('\n').count() + 1);
+        let mut message = format!("Line {}: {}", line, first.message);
+        if diagnostics.len() > 1 {
+            message.push_str(&format!(", and {} more", diagnostics.len() - 1));
+        }
+
+        Some(
+            h_flex()
+                .gap_2()
+                .mb_2()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .bg(cx.theme().status().warning_background)
+                .child(Icon::new(IconName::Warning).size(IconSize::Small))
+                .child(div().flex_1().child(Label::new(message)))
+                .child(Button::new("markdown-show-diagnostic", "Show").on_click(
+                    cx.listener(move |this, _, cx| this.reveal_source_offset(offset, cx)),
+                ))
+                .child(
+                    IconButton::new("markdown-dismiss-diagnostics", IconName::Close).on_click(
+                        cx.listener(|this, _, cx| {
+                            this.diagnostics_dismissed = true;
+                            cx.notify();
+                        }),
+                    ),
+                )
+                .into_any_element(),
+        )
+    }
+
+    /// How the check of the document's links is going, or how many of them
+    /// are broken once it's done.
+    fn render_link_check_banner(&self, cx: &mut ViewContext<Self>) -> Option<AnyElement> {
+        let check = self.link_check.as_ref()?;
+        let broken = check.broken.len();
+        let (icon, background, message) = if check.task.is_some() {
+            (
+                IconName::ArrowCircle,
+                cx.theme().status().info_background,
+                format!("Checking links… {} of {}", check.checked, check.total),
+            )
+        } else if broken > 0 || check.checked < check.total {
+            let mut message = match broken {
+                1 => "1 broken link".to_string(),
+                broken => format!("{broken} broken links"),
+            };
+            if check.checked < check.total {
+                message.push_str(&format!(
+                    ", with {} of {} links checked",
+                    check.checked, check.total
+                ));
+            }
+            (
+                IconName::XCircle,
+                cx.theme().status().error_background,
+                message,
+            )
+        } else {
+            let message = match check.total {
+                1 => "The link works".to_string(),
+                total => format!("All {total} links work"),
+            };
+            (
+                IconName::Check,
+                cx.theme().status().success_background,
+                message,
+            )
+        };
+
+        Some(
+            h_flex()
+                .gap_2()
+                .mb_2()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .bg(background)
+                .child(Icon::new(icon).size(IconSize::Small))
+                .child(div().flex_1().child(Label::new(message)))
+                .when(check.task.is_some(), |this| {
+                    this.child(
+                        Button::new("markdown-cancel-link-check", "Cancel").on_click(cx.listener(
+                            |this, _, cx| {
+                                if let Some(check) = this.link_check.as_mut() {
+                                    check.task = None;
+                                }
+                                cx.notify();
+                            },
+                        )),
+                    )
+                })
+                .child(
+                    IconButton::new("markdown-dismiss-link-check", IconName::Close).on_click(
+                        cx.listener(|this, _, cx| {
+                            this.link_check = None;
+                            cx.notify();
+                        }),
+                    ),
+                )
+                .into_any_element(),
+        )
+    }
+
+    /// The bar along the bottom with the document's length, the section at
+    /// the top of the viewport and the zoom level.
+    fn render_status_bar(&self, cx: &mut ViewContext<Self>) -> Option<AnyElement> {
+        let settings = MarkdownPreviewSettings::get_global(cx);
+        if !settings.show_status_bar {
+            return None;
+        }
+        let locale = settings.locale();
+        let contents = self.contents.as_ref()?;
+
+        let total_words: usize = self.word_counts.iter().sum();
+        let words = match self
+            .selection
+            .clone()
+            .filter(|selection| !selection.is_empty())
+        {
+            Some(selection) => {
+                let selected_words = word_count(&contents.selected_text(selection));
+                format!(
+                    "{} of {} words",
+                    locale.format_count(selected_words),
+                    locale.format_count(total_words)
+                )
+            }
+            None if total_words == 1 => "1 word".to_string(),
+            None => format!("{} words", locale.format_count(total_words)),
+        };
+        let reading_time = format!(
+            "{} min read",
+            locale.format_count(reading_minutes(total_words).max(1))
+        );
+        let scroll_top = self.list_state.logical_scroll_top().item_ix;
+        let section = self
+            .outline
+            .iter()
+            .rfind(|entry| entry.block_ix <= scroll_top)
+            .map(|entry| entry.text.clone());
+        let label = |text: String| Label::new(text).size(LabelSize::XSmall).color(Color::Muted);
+
+        Some(
+            h_flex()
+                .flex_none()
+                .w_full()
+                .h_6()
+                .px_3()
+                .gap_3()
+                .border_t_1()
+                .border_color(cx.theme().colors().border)
+                .bg(cx.theme().colors().status_bar_background)
+                .child(div().flex_1().min_w_0().children(section.map(|section| {
+                    Label::new(section)
+                        .size(LabelSize::XSmall)
+                        .color(Color::Muted)
+                        .single_line()
+                })))
+                .child(label(words))
+                .child(label(reading_time))
+                .child(label(format!("{}%", (self.text_scale * 100.).round())))
+                .into_any_element(),
+        )
+    }
+
+    /// The card with the document's front matter, above the list of its
+    /// blocks so that it doesn't change their positions.
+    fn render_front_matter(&self, cx: &mut ViewContext<Self>) -> Option<AnyElement> {
+        if !MarkdownPreviewSettings::get_global(cx).show_front_matter {
+            return None;
+        }
+        let front_matter = self.contents.as_ref()?.front_matter.as_ref()?;
+        if front_matter.entries.is_empty() {
+            return None;
+        }
+
+        let collapsed = self.front_matter_collapsed;
+        let header = h_flex()
+            .id("markdown-front-matter-header")
+            .gap_1()
+            .cursor_pointer()
+            .child(
+                Icon::new(if collapsed {
+                    IconName::ChevronRight
+                } else {
+                    IconName::ChevronDown
+                })
+                .size(IconSize::Small)
+                .color(Color::Muted),
+            )
+            .child(
+                Label::new("Front Matter")
+                    .size(LabelSize::Small)
+                    .color(Color::Muted),
+            )
+            .on_click(cx.listener(|this, _, cx| {
+                this.front_matter_collapsed = !this.front_matter_collapsed;
+                cx.notify();
+            }));
+
+        let locale = MarkdownPreviewSettings::get_global(cx).locale();
+        let mut link_ix = 0;
+        let entries = (!collapsed).then(|| {
+            self.render_front_matter_entries(
+                &front_matter.entries,
+                front_matter.source_range.clone(),
+                locale,
+                &mut link_ix,
+                cx,
+            )
+        });
+        Some(
+            v_flex()
+                .mb_2()
+                .px_3()
+                .py_2()
+                .gap_1()
+                .rounded_md()
+                .border_1()
+                .border_color(cx.theme().colors().border)
+                .bg(cx.theme().colors().surface_background)
+                .child(header)
+                .children(entries)
+                .into_any_element(),
+        )
+    }
+
+    /// Lays `entries` out as rows of keys and their values, formatted for
+    /// `locale`.
+    fn render_front_matter_entries(
+        &self,
+        entries: &[(String, FrontMatterValue)],
+        source_range: Range<usize>,
+        locale: Locale,
+        link_ix: &mut usize,
+        cx: &mut ViewContext<Self>,
+    ) -> AnyElement {
+        v_flex()
+            .gap_0p5()
+            .children(entries.iter().map(|(key, value)| {
+                h_flex()
+                    .items_start()
+                    .gap_4()
+                    .child(
+                        div().w(px(120.)).flex_none().child(
+                            Label::new(key.clone())
+                                .size(LabelSize::Small)
+                                .color(Color::Muted),
+                        ),
+                    )
+                    .child(
+                        div()
+                            .flex_1()
+                            .min_w_0()
+                            .child(self.render_front_matter_value(
+                                value,
+                                source_range.clone(),
+                                locale,
+                                link_ix,
+                                cx,
+                            )),
+                    )
+            }))
+            .into_any_element()
+    }
+
+    /// `link_ix` numbers the value's links and buttons, to give each an id.
+    fn render_front_matter_value(
+        &self,
+        value: &FrontMatterValue,
+        source_range: Range<usize>,
+        locale: Locale,
+        link_ix: &mut usize,
+        cx: &mut ViewContext<Self>,
+    ) -> AnyElement {
+        match value {
+            FrontMatterValue::Text(text) if is_url(text) => {
+                *link_ix += 1;
+                let link = Link::Web { url: text.clone() };
+                div()
+                    .id(("markdown-front-matter-link", *link_ix))
+                    .cursor_pointer()
+                    .child(
+                        Label::new(text.clone())
+                            .size(LabelSize::Small)
+                            .color(Color::Accent),
+                    )
+                    .on_click(cx.listener(move |this, _, cx| {
+                        // Front matter values are plain text, so their URLs
+                        // are picked out like bare ones in the document.
+                        this.activate_link(&link, LinkOrigin::Autolink, source_range.clone(), cx);
+                    }))
+                    .into_any_element()
+            }
+            FrontMatterValue::Text(text) => match locale.format_value(text) {
+                Some(formatted) => {
+                    // Formatting loses how the value was written, so the
+                    // value is shown, and copied, as it was too.
+                    *link_ix += 1;
+                    let raw = text.clone();
+                    h_flex()
+                        .group("markdown-front-matter-value")
+                        .gap_1()
+                        .child(Label::new(formatted).size(LabelSize::Small))
+                        .child(
+                            IconButton::new(
+                                ("markdown-front-matter-copy", *link_ix),
+                                IconName::Copy,
+                            )
+                            .icon_size(IconSize::XSmall)
+                            .icon_color(Color::Muted)
+                            .tooltip(move |cx| Tooltip::text(format!("Copy Raw Value: {raw}"), cx))
+                            .on_click({
+                                let text = text.clone();
+                                move |_, cx| {
+                                    cx.write_to_clipboard(ClipboardItem::new_string(text.clone()))
+                                }
+                            })
+                            .visible_on_hover("markdown-front-matter-value"),
+                        )
+                        .into_any_element()
+                }
+                None => Label::new(text.clone())
+                    .size(LabelSize::Small)
+                    .into_any_element(),
+            },
+            FrontMatterValue::List(items) => h_flex()
+                .flex_wrap()
+                .gap_1()
+                .children(items.iter().map(|item| {
+                    div()
+                        .px_1p5()
+                        .rounded_md()
+                        .bg(cx.theme().colors().element_background)
+                        .child(self.render_front_matter_value(
+                            item,
+                            source_range.clone(),
+                            locale,
+                            link_ix,
+                            cx,
+                        ))
+                }))
+                .into_any_element(),
+            FrontMatterValue::Map(entries) => div()
+                .pl_2()
+                .child(self.render_front_matter_entries(entries, source_range, locale, link_ix, cx))
+                .into_any_element(),
+        }
+    }
+
+    fn render_block(&mut self, ix: usize, cx: &mut ViewContext<Self>) -> AnyElement {
+        let Some(block) = self.contents.as_ref().and_then(|c| c.children.get(ix)) else {
+            return div().into_any();
+        };
+
+        if self.folded_blocks.iter().any(|range| range.contains(&ix)) {
+            self.block_layouts
+                .borrow_mut()
+                .record(ix, Bounds::default());
+            return div().into_any();
+        }
+
+        let source_file = self.contents.as_ref().and_then(|contents| {
+            contents
+                .includes
+                .iter()
+                .find(|include| include.blocks.contains(&ix))
+                .map(|include| include.path.clone())
+        });
+        let view = cx.view().downgrade();
+        let settings = MarkdownPreviewSettings::get_global(cx);
+        let mut markdown_render_context = RenderContext::new(cx)
+            .with_render_cache(self.render_cache.clone())
+            .with_source_file(source_file)
+            .with_presentation_state(self.presentation_state.clone())
+            .with_block_index(ix)
+            .with_image_policy(self.image_policy)
+            .with_link_policy(self.link_policy.clone())
+            .with_image_viewer(self.image_viewer.clone())
+            .with_image_viewport(self.viewport_bounds.clone())
+            .with_render_override_callback(self.render_override.clone())
+            .with_fence_handlers(self.fence_handlers.clone())
+            .with_block_layouts(self.block_layouts.clone())
+            .with_link_clicked_callback({
+                let view = view.clone();
+                move |link, origin, source_range, cx| {
+                    view.update(cx, |view, cx| {
+                        view.activate_link(link, origin, source_range, cx)
+                    })
+                    .ok();
+                }
+            })
+            .with_copy_section_callback({
+                let view = view.clone();
+                move |block_ix, cx| {
+                    view.update(cx, |view, cx| view.copy_section(block_ix, cx))
+                        .ok();
+                }
+            })
+            .with_image_expanded_callback({
+                let view = view.clone();
+                move |link, cx| {
+                    view.update(cx, |view, cx| view.show_lightbox(link.clone(), cx))
+                        .ok();
+                }
+            })
+            .with_image_clicked_callback({
+                let view = view.clone();
+                move |link, source_range, cx| {
+                    view.update(cx, |_, cx| {
+                        cx.emit(MarkdownEvent::ImageClicked {
+                            url: link.url(),
+                            source_range,
+                            modifiers: cx.modifiers(),
+                        })
+                    })
+                    .ok();
+                }
+            })
+            .with_search_highlights(self.search_highlights.clone())
+            .with_hovered_element(self.hovered_element.clone())
+            .with_text_scale(self.text_scale)
+            .with_reduced_motion(settings.reduce_motion)
+            .with_images_adapted_to_theme(settings.adapt_images_to_theme)
+            // The view's style, from the application, wins over the fonts in
+            // the user settings.
+            .with_code_font(
+                settings.code_font_family.clone().map(SharedString::from),
+                settings.code_font_size.map(px),
+                settings.code_font_features.clone(),
+            )
+            .with_style(&self.style);
+        if let Some(check) = &self.link_check {
+            markdown_render_context =
+                markdown_render_context.with_broken_links(check.broken.clone());
+        }
+        if let Some(misspellings) = self.block_misspellings(ix, cx) {
+            markdown_render_context =
+                markdown_render_context.with_misspellings(Some(Rc::new(misspellings)));
+        }
+        if let Some(runner) = &self.code_runner {
+            let view = view.clone();
+            markdown_render_context = markdown_render_context.with_code_runner(
+                runner.languages.clone(),
+                move |source_range, language, code, cx| {
+                    view.update(cx, |view, cx| {
+                        view.run_code(source_range, language, code, cx)
+                    })
+                    .ok();
+                },
+            );
+        }
+        if !self.read_only {
+            markdown_render_context = markdown_render_context.with_checkbox_clicked_callback(
+                move |checked, source_range, cx| {
+                    view.update(cx, |view, cx| {
+                        view.toggle_checkbox(checked, source_range, cx)
+                    })
+                    .ok();
+                },
+            );
+        }
+
+        let block = render_markdown_block(block, &mut markdown_render_context);
+        let debug_overlay = self.debug_overlay.then(|| {
+            let mut label = self.block_debug_label(ix).unwrap_or_default();
+            if let Some(height) = self.block_layouts.borrow().height(ix) {
+                label.push_str(&format!(" · {:.0}px", f32::from(height)));
+            }
+            div()
+                .absolute()
+                .top_0()
+                .left_0()
+                .size_full()
+                .border_1()
+                .border_color(cx.theme().status().info_border)
+                .bg(cx.theme().status().info_background.opacity(0.2))
+                .child(
+                    div()
+                        .absolute()
+                        .top_0()
+                        .right_0()
+                        .px_1()
+                        .bg(cx.theme().status().info_background)
+                        .child(Label::new(label).size(LabelSize::XSmall)),
+                )
+        });
+        let flashed = self
+            .flashed_block
+            .as_ref()
+            .is_some_and(|(block_ix, _)| *block_ix == ix);
+        div()
+            .relative()
+            .when(flashed, |this| {
+                this.rounded_md()
+                    .bg(cx.theme().colors().search_match_background)
+            })
+            .child(
+                div()
+                    .relative()
+                    .child(markdown_render_context.track_block_layout(block))
+                    .children(debug_overlay),
+            )
+            .into_any()
+    }
+}
+
+/// `scale` rounded to whole percents, so that stepping doesn't accumulate
+/// floating point error, and kept to the scales the preview supports.
+fn round_text_scale(scale: f32) -> f32 {
+    ((scale * 100.).round() / 100.).clamp(MIN_TEXT_SCALE, MAX_TEXT_SCALE)
+}
+
+/// The debug overlay's line for where the time went in a parse, like
+/// `scan 1.2 ms · inline 0.8 ms · tables 0.0 ms · code 3.1 ms · html 0.1 ms`,
+/// with the extensions after the parts every parse has.
+fn parse_timing_breakdown(timing: &ParseTiming) -> String {
+    let parts = [
+        ("scan", timing.block_scan),
+        ("inline", timing.inline),
+        ("tables", timing.tables),
+        ("code", timing.code_blocks),
+    ];
+    parts
+        .into_iter()
+        .chain(
+            timing
+                .extensions
+                .iter()
+                .map(|(name, duration)| (*name, *duration)),
+        )
+        .map(|(name, duration)| format!("{} {:.1} ms", name, duration.as_secs_f64() * 1000.))
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+/// The line a fragment like `L42`, or `L42-L50` for a range of lines,
+/// points to, counting from 1.
+fn line_fragment(fragment: &str) -> Option<usize> {
+    let line = fragment.strip_prefix('L')?;
+    let line = line.split_once('-').map_or(line, |(start, _)| start);
+    line.parse().ok().filter(|line| *line > 0)
+}
+
+/// Where the line `line`, counting from 1, starts in `text`. Lines past the
+/// end of `text` start at its end.
+fn line_start_offset(text: &str, line: usize) -> usize {
+    if line <= 1 {
+        return 0;
+    }
+    text.match_indices('\n')
+        .nth(line - 2)
+        .map_or(text.len(), |(ix, _)| ix + 1)
+}
+
+/// The title for a window showing the document at `path`, whose first
+/// top-level heading is `heading`: `<file name> — <heading>`, or just the file
+/// name without one. Documents that weren't opened from a file, like the
+/// example document, are titled after the application.
+///
+/// Windows with `edited` content that hasn't been saved are marked as such in
+/// their title, except on macOS, where the close button shows it instead.
+pub fn window_title(path: Option<&Path>, heading: Option<&str>, edited: bool) -> String {
+    let mut title = match (path, heading) {
+        (Some(path), Some(heading)) => format!("{} — {}", file_name(path), heading),
+        (Some(path), None) => file_name(path),
+        (None, _) => "Markdown Preview".to_string(),
+    };
+    if edited && !cfg!(target_os = "macos") {
+        title.insert_str(0, "• ");
+    }
+    title
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// Matches the blocks of two parses of a document by their hashes: the runs
+/// they start and end with, and then the rest in the order they come.
+fn contents_changed(old_hashes: &[u64], new_hashes: &[u64]) -> MarkdownEvent {
+    let prefix_len = old_hashes
+        .iter()
+        .zip(new_hashes)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix_len = old_hashes[prefix_len..]
+        .iter()
+        .rev()
+        .zip(new_hashes[prefix_len..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_changed = prefix_len..old_hashes.len() - suffix_len;
+    let new_changed = prefix_len..new_hashes.len() - suffix_len;
+
+    let mut unmatched: FxHashMap<u64, VecDeque<usize>> = FxHashMap::default();
+    for old_ix in old_changed.clone() {
+        unmatched
+            .entry(old_hashes[old_ix])
+            .or_default()
+            .push_back(old_ix);
+    }
+    let mut matched = vec![false; old_hashes.len()];
+    let mut inserted = Vec::new();
+    let mut moved = Vec::new();
+    for new_ix in new_changed {
+        match unmatched
+            .get_mut(&new_hashes[new_ix])
+            .and_then(VecDeque::pop_front)
+        {
+            Some(old_ix) => {
+                matched[old_ix] = true;
+                if old_ix != new_ix {
+                    moved.push((old_ix, new_ix));
+                }
+            }
+            None => inserted.push(new_ix),
+        }
+    }
+    // The blocks after the change only move when it has more or fewer
+    // blocks than before.
+    for ix in 0..suffix_len {
+        let old_ix = old_hashes.len() - suffix_len + ix;
+        let new_ix = new_hashes.len() - suffix_len + ix;
+        if old_ix != new_ix {
+            moved.push((old_ix, new_ix));
+        }
+    }
+    let removed = old_changed.filter(|old_ix| !matched[*old_ix]).collect();
+
+    MarkdownEvent::ContentsChanged {
+        inserted,
+        removed,
+        moved,
+    }
+}
+
+fn content_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Describes why a file couldn't be read, for readers rather than for logs.
+fn read_error_message(error: &io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::NotFound => "The file does not exist".to_string(),
+        io::ErrorKind::PermissionDenied => "You don't have permission to read the file".to_string(),
+        // Binary files fail to read as UTF-8.
+        io::ErrorKind::InvalidData => "The file is not UTF-8 text".to_string(),
+        _ => format!("The file could not be read: {}", error),
+    }
+}
+
+pub(crate) fn is_markdown_file(path: &Path) -> bool {
+    path.extension().map_or(false, |extension| {
+        ["md", "markdown", "mdown", "mkd"]
+            .iter()
+            .any(|markdown| extension.eq_ignore_ascii_case(markdown))
+    })
+}
+
+/// The text a keystroke types. Platforms report it as the keystroke's IME
+/// key, but keystrokes that were made up, like simulated ones, may only have
+/// a key.
+pub(crate) fn typed_text(keystroke: &Keystroke) -> Option<String> {
+    if let Some(ime_key) = &keystroke.ime_key {
+        return Some(ime_key.clone());
+    }
+    if keystroke.modifiers.alt {
+        return None;
+    }
+
+    match keystroke.key.as_str() {
+        "space" => Some(" ".to_string()),
+        key if key.chars().count() == 1 => Some(if keystroke.modifiers.shift {
+            key.to_uppercase()
+        } else {
+            key.to_string()
+        }),
+        _ => None,
+    }
+}
+
+impl EventEmitter<MarkdownEvent> for MarkdownView {}
+
+impl FocusableView for MarkdownView {
+    fn focus_handle(&self, _cx: &AppContext) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for MarkdownView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        if let Some(error) = self.load_error.clone() {
+            return self.render_load_error(error, cx);
+        }
+        if self.contents.is_none() {
+            return self.render_loading(cx);
+        }
+
+        let frame_started = Instant::now();
+        self.image_viewer.start_frame();
+        self.update_folded_blocks();
+        self.preserve_scroll_on_resize(cx.viewport_size().width);
+        self.remember_ui_state(cx);
+        if self.pending_scroll_fraction.is_some() {
+            cx.on_next_frame(|this, cx| this.restore_scroll_fraction(cx));
+        }
+        // The blocks are laid out after this, so where they ended up is only
+        // known by the next frame.
+        cx.on_next_frame(|this, cx| this.update_layout_info(cx));
+
+        let find_bar = self.render_find_bar(cx);
+        let removed_banner = self.file_removed.then(|| {
+            h_flex()
+                .gap_2()
+                .mb_2()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .bg(cx.theme().status().warning_background)
+                .child(Icon::new(IconName::Warning).size(IconSize::Small))
+                .child(Label::new(
+                    "The file was removed. Its last version is shown below.",
+                ))
+        });
+        let navigation_banner = self.navigation_error.clone().map(|error| {
+            h_flex()
+                .gap_2()
+                .mb_2()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .bg(cx.theme().status().error_background)
+                .child(Icon::new(IconName::XCircle).size(IconSize::Small))
+                .child(div().flex_1().child(Label::new(error)))
+                .child(
+                    IconButton::new("markdown-dismiss-navigation-error", IconName::Close).on_click(
+                        cx.listener(|this, _, cx| {
+                            this.navigation_error = None;
+                            cx.notify();
+                        }),
+                    ),
+                )
+        });
+
+        let diagnostics_banner = self.render_diagnostics_banner(cx);
+        let link_check_banner = self.render_link_check_banner(cx);
+
+        let mut key_context = KeyContext::new_with_defaults();
+        key_context.add("MarkdownPreview");
+        if MarkdownPreviewSettings::get_global(cx).vim_scrolling {
+            key_context.add("vim_scrolling");
+        }
+        if self.lightbox.is_some() {
+            key_context.add("lightbox");
+        }
+
+        let viewport_bounds = self.viewport_bounds.clone();
+        let jump_to_bottom = (self.follow && !self.at_bottom.get()).then(|| {
+            h_flex()
+                .absolute()
+                .bottom_4()
+                .left_0()
+                .w_full()
+                .justify_center()
+                .child(
+                    Button::new("markdown-jump-to-bottom", "Jump to Bottom")
+                        .style(ButtonStyle::Filled)
+                        .on_click(cx.listener(|this, _, cx| this.jump_to_bottom(cx))),
+                )
+        });
+        let scrolled_by_host = matches!(self.scroll_mode, ScrollMode::Host(_));
+        let blocks = if let Some(empty_state) = self.render_empty_state(cx) {
+            empty_state
+        } else if scrolled_by_host {
+            v_flex()
+                .w_full()
+                .children((0..self.block_count()).map(|ix| self.render_block(ix, cx)))
+                .into_any_element()
+        } else {
+            list(self.list_state.clone()).size_full().into_any_element()
+        };
+        let document = div()
+            .relative()
+            .w_full()
+            .when(!scrolled_by_host, |this| this.flex_grow())
+            .child(blocks)
+            .child(
+                canvas(move |bounds, _| viewport_bounds.set(bounds), |_, _, _| {})
+                    .absolute()
+                    .top_0()
+                    .left_0()
+                    .size_full(),
+            )
+            .children(jump_to_bottom)
+            .children(self.render_debug_stats(frame_started, cx));
+
+        let toast = self.toast.as_ref().map(|(message, _)| {
+            div()
+                .absolute()
+                .top_4()
+                .right_4()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .border_1()
+                .border_color(cx.theme().colors().border)
+                .bg(cx.theme().colors().elevated_surface_background)
+                .child(Label::new(message.clone()))
+        });
+
+        let lightbox = self.lightbox.as_ref().map(|link| {
+            let url = link.url();
+            div()
+                .id("markdown-lightbox")
+                .absolute()
+                .top_0()
+                .left_0()
+                .size_full()
+                .p_8()
+                .flex()
+                .items_center()
+                .justify_center()
+                .bg(cx.theme().colors().editor_background.opacity(0.9))
+                .cursor_pointer()
+                .child(
+                    MarkdownImage::new(
+                        ImageLocation::from_link(link),
+                        url.clone().into(),
+                        Some(cx.viewport_size().height * 0.9),
+                        cx.theme().colors().surface_background,
+                    )
+                    .viewer(Some(self.image_viewer.clone())),
+                )
+                .tooltip(move |cx| Tooltip::text(url.clone(), cx))
+                .on_click(cx.listener(|this, _, cx| this.close_lightbox(&CloseLightbox, cx)))
+        });
+
+        let outline = self.outline_visible.then(|| {
+            h_flex().h_full().child(self.render_outline(cx)).child(
+                div()
+                    .id("markdown-outline-edge")
+                    .h_full()
+                    .w_1()
+                    .cursor_col_resize()
+                    .on_drag(DraggedOutlineEdge, |edge, _, cx| {
+                        cx.stop_propagation();
+                        cx.new_view(|_| edge.clone())
+                    }),
+            )
+        });
+
+        h_flex()
+            .id("markdown-preview-example")
+            .key_context(key_context)
+            .track_focus(&self.focus_handle(cx))
+            .on_action(cx.listener(Self::fold_all_sections))
+            .on_action(cx.listener(Self::unfold_all_sections))
+            .on_action(cx.listener(Self::toggle_outline))
+            .on_action(cx.listener(Self::go_to_heading))
+            .on_action(cx.listener(Self::export_html))
+            .on_action(cx.listener(Self::export_pdf))
+            .on_action(cx.listener(Self::print))
+            .on_action(cx.listener(Self::copy))
+            .on_action(cx.listener(Self::copy_as_markdown))
+            .on_action(cx.listener(Self::copy_diagnostics))
+            .on_action(cx.listener(Self::select_all))
+            .on_action(cx.listener(Self::reload))
+            .on_action(cx.listener(Self::save_as))
+            .on_action(cx.listener(Self::toggle_follow))
+            .on_action(cx.listener(Self::toggle_debug_overlay))
+            .on_action(cx.listener(Self::close_lightbox))
+            .on_action(cx.listener(Self::check_links))
+            .on_action(cx.listener(Self::deploy_context_menu_from_keyboard))
+            .on_action(cx.listener(Self::find))
+            .on_action(cx.listener(Self::dismiss_find))
+            .on_action(cx.listener(Self::toggle_case_sensitive))
+            .on_action(cx.listener(Self::select_next_match))
+            .on_action(cx.listener(Self::select_previous_match))
+            .on_action(cx.listener(Self::zoom_in))
+            .on_action(cx.listener(Self::zoom_out))
+            .on_action(cx.listener(Self::reset_zoom))
+            .on_action(cx.listener(Self::scroll_line_up))
+            .on_action(cx.listener(Self::scroll_line_down))
+            .on_action(cx.listener(Self::scroll_page_up))
+            .on_action(cx.listener(Self::scroll_page_down))
+            .on_action(cx.listener(Self::scroll_to_top))
+            .on_action(cx.listener(Self::scroll_to_bottom))
+            .on_action(cx.listener(Self::go_back))
+            .on_action(cx.listener(Self::go_forward))
+            .on_mouse_down(
+                MouseButton::Right,
+                cx.listener(|this, event: &MouseDownEvent, cx| {
+                    let hovered = this.hovered_element.borrow().clone();
+                    this.deploy_context_menu(event.position, hovered, cx);
+                }),
+            )
+            .on_mouse_down(
+                MouseButton::Navigate(NavigationDirection::Back),
+                cx.listener(|this, _, cx| this.go_back(&GoBack, cx)),
+            )
+            .on_mouse_down(
+                MouseButton::Navigate(NavigationDirection::Forward),
+                cx.listener(|this, _, cx| this.go_forward(&GoForward, cx)),
+            )
+            .on_drag_move(
+                cx.listener(|this, event: &DragMoveEvent<DraggedOutlineEdge>, cx| {
+                    this.outline_width = (event.event.position.x - event.bounds.left())
+                        .clamp(MIN_OUTLINE_WIDTH, MAX_OUTLINE_WIDTH);
+                    cx.notify();
+                }),
+            )
+            .on_drop(cx.listener(Self::open_dropped_paths))
+            .debug_selector(|| "foo".into())
+            .relative()
+            .bg(self
+                .style
+                .colors
+                .map_or(cx.theme().colors().editor_background, |colors| {
+                    colors.background
+                }))
+            .when_some(self.style.colors, |this, colors| {
+                this.text_color(colors.text)
+            })
+            .drag_over::<ExternalPaths>(|style, _, cx| {
+                style.bg(cx.theme().colors().drop_target_background)
+            })
+            // A host that scrolls the document leaves its height to it.
+            .map(|this| {
+                if scrolled_by_host {
+                    this.w_full()
+                } else {
+                    this.size_full()
+                }
+            })
+            .children(outline)
+            .child(
+                v_flex()
+                    .flex_1()
+                    .when(!scrolled_by_host, |this| this.h_full())
+                    .child(
+                        v_flex()
+                            .flex_1()
+                            .when(!scrolled_by_host, |this| this.min_h_0())
+                            .p_4()
+                            .children(find_bar)
+                            .children(removed_banner)
+                            .children(navigation_banner)
+                            .children(diagnostics_banner)
+                            .children(link_check_banner)
+                            .children(self.render_front_matter(cx))
+                            .child(document),
+                    )
+                    .children(self.render_status_bar(cx)),
+            )
+            .children(toast)
+            .children(self.render_heading_picker(cx))
+            .children(lightbox)
+            .children(self.context_menu.as_ref().map(|(menu, position, _)| {
+                deferred(anchored().position(*position).child(menu.clone())).with_priority(1)
+            }))
+            .into_any_element()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::markdown_preview::markdown_parser::parse_markdown;
+    use crate::markdown_preview::markdown_renderer::{MarkdownColors, RenderLimit, TableSort};
+    use gpui::{size, TestAppContext, VisualTestContext};
+    use pretty_assertions::assert_eq;
+    use theme::LoadThemes;
+
+    fn init_test(cx: &mut TestAppContext) -> (View<MarkdownView>, &mut VisualTestContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+        cx.add_window_view(|cx| MarkdownView::new(String::new(), None, cx))
+    }
+
+    async fn assert_contents(view: &View<MarkdownView>, text: &str, cx: &mut VisualTestContext) {
+        let expected = parse_markdown(text, None, None).await;
+        view.update(cx, |view, _| {
+            assert_eq!(view.text(), text);
+            assert_eq!(
+                view.contents.as_ref().map(|contents| &contents.children),
+                Some(&expected.children)
+            );
+        });
+    }
+
+    #[gpui::test]
+    async fn test_standalone_style_preset(cx: &mut TestAppContext) {
+        cx.update(crate::init_standalone);
+        let (view, cx) = cx.add_window_view(|cx| {
+            MarkdownView::with_options(
+                MarkdownView::builder("# Title\n\nSome `code`\n").style(MarkdownStyle::dark()),
+                cx,
+            )
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.style.colors, Some(MarkdownColors::dark()));
+            assert_eq!(view.block_count(), 2);
+        });
+    }
+
+    #[gpui::test]
+    async fn test_code_font_settings(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        cx.update(|cx| {
+            cx.update_global::<SettingsStore, _>(|store, cx| {
+                store
+                    .set_user_settings(
+                        r#"{"markdown_preview": {"code_font_family": "Fira Code", "code_font_size": 13}}"#,
+                        cx,
+                    )
+                    .unwrap()
+            });
+            let settings = MarkdownPreviewSettings::get_global(cx);
+            assert_eq!(settings.code_font_family.as_deref(), Some("Fira Code"));
+            assert_eq!(settings.code_font_size, Some(13.));
+            assert_eq!(settings.code_font_features, None);
+        });
+
+        view.update(cx, |view, cx| {
+            view.set_text("Some `code`\n\n```\nmore code\n```\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(view.update(cx, |view, _| view.block_count()), 2);
+    }
+
+    #[gpui::test(iterations = 10)]
+    async fn test_only_the_latest_text_is_shown(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+
+        for text in ["# One", "# Two\n\nText", "* Three"] {
+            view.update(cx, |view, cx| view.set_text(text.to_string(), cx));
+            cx.executor().simulate_random_delay().await;
+        }
+        cx.run_until_parked();
+        assert_contents(&view, "* Three", cx).await;
+
+        // Appends that race with a replacement apply to the replaced text.
+        view.update(cx, |view, cx| {
+            view.append_text("\n* Four", cx);
+            view.set_text("# Five\n\n".to_string(), cx);
+        });
+        for chunk in ["Para", "graph\n\n", "- item"] {
+            view.update(cx, |view, cx| view.append_text(chunk, cx));
+            cx.executor().simulate_random_delay().await;
+        }
+        cx.run_until_parked();
+        assert_contents(&view, "# Five\n\nParagraph\n\n- item", cx).await;
+    }
+
+    #[gpui::test]
+    async fn test_appended_definitions_link_earlier_uses(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let mut text = String::new();
+        for chunk in [
+            "See [the docs][x].\n\n",
+            "Some more text.\n\n",
+            "[x]: https://zed",
+            ".dev\n",
+        ] {
+            text.push_str(chunk);
+            view.update(cx, |view, cx| view.append_text(chunk, cx));
+            cx.run_until_parked();
+            assert_contents(&view, &text, cx).await;
+        }
+
+        view.update(cx, |view, _| {
+            let ParsedMarkdownElement::Paragraph(paragraph) =
+                &view.contents.as_ref().unwrap().children[0]
+            else {
+                panic!("expected a paragraph");
+            };
+            assert_eq!(&*paragraph.contents, "See the docs.");
+            assert_eq!(
+                paragraph.regions[0].link,
+                Some(Link::Web {
+                    url: "https://zed.dev".to_string()
+                })
+            );
+        });
+    }
+
+    #[gpui::test]
+    async fn test_only_changed_blocks_are_parsed_again(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let text = "# Title\n\nOne\n\nTwo\n\nThree\n\nFour\n\nFive\n";
+        view.update(cx, |view, cx| view.set_text(text.to_string(), cx));
+        cx.run_until_parked();
+
+        let edited = text.replace("Three", "Three and a half");
+        view.update(cx, |view, cx| {
+            view.raw_text = edited.clone();
+            let window = view.parse_window(false);
+            assert_eq!(window.blocks, Some(2..5));
+            assert_eq!(window.text, "Two\n\nThree and a half\n\nFour\n\n");
+            view.set_text(edited.clone(), cx);
+        });
+        cx.run_until_parked();
+        assert_contents(&view, &edited, cx).await;
+
+        // Definitions can be used anywhere, so they need the whole document.
+        let edited = format!("{edited}\n[one]: https://zed.dev\n");
+        view.update(cx, |view, _| {
+            view.raw_text = edited.clone();
+            assert_eq!(view.parse_window(false).blocks, None);
+        });
+    }
+
+    #[gpui::test]
+    async fn test_incremental_parses_match_full_parses(cx: &mut TestAppContext) {
+        const SNIPPETS: &[&str] = &[
+            "\n",
+            "\n\n",
+            "word ",
+            "# ",
+            "- ",
+            "1. ",
+            "> ",
+            "    ",
+            "**",
+            "`",
+            "```\n",
+            "---\n",
+            "===\n",
+            "| a | b |\n|---|---|\n",
+            "<!-- ",
+            "<div>\n",
+            "[link](#title)",
+            "- [ ] task\n",
+            "$$\n",
+            "\n    code\n",
+        ];
+        let base = "# Title\n\nIntro paragraph with *emphasis*.\n\n- one\n- two\n    - nested\n\n> quote\n> more\n\n```rust\nfn main() {}\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n## Section\n\nText with `code` and [a link](https://zed.dev).\n\n1. first\n2. second\n\n---\n\nLast paragraph.\n";
+        let (view, cx) = init_test(cx);
+        let mut text = base.to_string();
+        view.update(cx, |view, cx| view.set_text(text.clone(), cx));
+        cx.run_until_parked();
+
+        // A fixed seed, so that failures can be reproduced.
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % bound as u64) as usize
+        };
+        for _ in 0..300 {
+            let start = random(text.len() + 1);
+            if random(3) == 0 && start < text.len() {
+                let end = (start + 1 + random(12)).min(text.len());
+                text.replace_range(start..end, "");
+            } else {
+                text.insert_str(start, SNIPPETS[random(SNIPPETS.len())]);
+            }
+            if text.len() > 2000 {
+                text = base.to_string();
+            }
+
+            view.update(cx, |view, cx| view.set_text(text.clone(), cx));
+            cx.run_until_parked();
+            assert_contents(&view, &text, cx).await;
+            view.update(cx, |view, _| assert_eq!(view.contents_text, text));
+        }
+    }
+
+    #[gpui::test]
+    async fn test_incremental_parses_with_reference_definitions(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let base = "Intro with [a link][x].\n\n- one\n- two\n\n> quote\n\nLast [x] paragraph.\n";
+        view.update(cx, |view, cx| view.set_text(base.to_string(), cx));
+        cx.run_until_parked();
+
+        // Each edit adds a definition that the earlier uses resolve to, and
+        // then takes it out again.
+        for (after, definition) in [
+            ("Last [x] paragraph.\n", "\n[x]: https://zed.dev\n"),
+            ("- one\n", "- [x]: https://zed.dev\n"),
+            ("- two\n", "  1. [x]: https://zed.dev\n"),
+            ("> quote\n", "> [x]: https://zed.dev\n"),
+        ] {
+            let offset = base.find(after).unwrap() + after.len();
+            let mut text = base.to_string();
+            text.insert_str(offset, definition);
+            for text in [text.as_str(), base] {
+                view.update(cx, |view, cx| view.set_text(text.to_string(), cx));
+                cx.run_until_parked();
+                assert_contents(&view, text, cx).await;
+            }
+        }
+
+        assert!(may_define_references("> - [x]: https://zed.dev"));
+        assert!(may_define_references("10) [^note]: A footnote"));
+        assert!(!may_define_references("- [ ] task\n-[x]:"));
+    }
+
+    #[gpui::test]
+    async fn test_table_sort_follows_its_table(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let table = "| n |\n|---|\n| 2 |\n| 1 |\n";
+        let text = format!("Intro\n\nMiddle\n\n{table}\nEnd\n");
+        view.update(cx, |view, cx| view.set_text(text.clone(), cx));
+        cx.run_until_parked();
+
+        let sort = TableSort {
+            column: 0,
+            ascending: false,
+        };
+        let start = text.find(table).unwrap();
+        view.update(cx, |view, _| {
+            let table_range = view.contents.as_ref().unwrap().children[2].source_range();
+            assert_eq!(table_range.start, start);
+            view.presentation_state
+                .borrow_mut()
+                .table_sorts
+                .insert(table_range, sort);
+        });
+
+        // Editing the blocks before the table moves its sort along with it.
+        let text = text.replacen("Intro", "A longer intro", 1);
+        view.update(cx, |view, cx| view.set_text(text.clone(), cx));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            let table_range = view.contents.as_ref().unwrap().children[2].source_range();
+            assert_eq!(table_range.start, text.find(table).unwrap());
+            let sorts = &view.presentation_state.borrow().table_sorts;
+            assert_eq!(sorts.len(), 1);
+            assert_eq!(sorts.get(&table_range), Some(&sort));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_code_is_highlighted_after_the_first_render(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+        language_registry.add(Arc::new(language::Language::new(
+            language::LanguageConfig {
+                name: "Rust".into(),
+                ..Default::default()
+            },
+            Some(language::tree_sitter_rust::LANGUAGE.into()),
+        )));
+        let text: String = (0..50)
+            .map(|ix| format!("```rust\nfn f{ix}() {{}}\n```\n\n"))
+            .collect();
+        view.update(cx, |view, cx| {
+            view.set_language_registry(language_registry, cx);
+            view.set_text(text, cx);
+        });
+
+        let highlighted = |view: &View<MarkdownView>, cx: &mut VisualTestContext| {
+            view.update(cx, |view, _| {
+                let contents = view.contents.as_mut()?;
+                let code_blocks = contents
+                    .children
+                    .iter_mut()
+                    .flat_map(ParsedMarkdownElement::code_blocks_mut);
+                Some(
+                    code_blocks
+                        .filter(|code_block| code_block.highlights.is_some())
+                        .count(),
+                )
+            })
+        };
+        // The document is shown as soon as it's parsed, before any of its
+        // code is highlighted.
+        while highlighted(&view, cx).is_none() {
+            assert!(cx.executor().tick());
+        }
+        assert_eq!(highlighted(&view, cx), Some(0));
+        cx.run_until_parked();
+        assert_eq!(highlighted(&view, cx), Some(50));
+
+        // Highlights survive re-parses of code that didn't change.
+        view.update(cx, |view, cx| {
+            let text = format!("# Title\n\n{}", view.text());
+            view.set_text(text, cx);
+        });
+        cx.run_until_parked();
+        assert_eq!(highlighted(&view, cx), Some(50));
+    }
+
+    #[gpui::test]
+    async fn test_pathological_documents_render_quickly(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let documents = [
+            ("a".repeat(512 * 1024), RenderLimit::WordLength),
+            (
+                format!(
+                    "{}|\n{}|\n{}|\n",
+                    "| c ".repeat(300),
+                    "|---".repeat(300),
+                    "| x ".repeat(300)
+                ),
+                RenderLimit::TableColumns,
+            ),
+            (
+                (0..50)
+                    .map(|depth| format!("{}- item {depth}\n", "  ".repeat(depth)))
+                    .collect(),
+                RenderLimit::Depth,
+            ),
+            (format!("{} deep\n", ">".repeat(50)), RenderLimit::Depth),
+            (
+                format!("# {}\n", "`x` ".repeat(10_000)),
+                RenderLimit::InlineRuns,
+            ),
+        ];
+        for (text, limit) in documents {
+            view.update(cx, |view, cx| view.replace_text(text, cx));
+            cx.run_until_parked();
+
+            let started_at = Instant::now();
+            let block_count = view.update(cx, |view, _| {
+                view.contents
+                    .as_ref()
+                    .map_or(0, |contents| contents.children.len())
+            });
+            assert!(block_count > 0);
+            for ix in 0..block_count {
+                cx.draw(point(px(0.), px(0.)), size(px(800.), px(600.)), |cx| {
+                    view.update(cx, |view, cx| view.render_block(ix, cx))
+                });
+            }
+            assert!(started_at.elapsed() < Duration::from_secs(10));
+            view.update(cx, |view, _| {
+                let state = view.presentation_state.borrow();
+                assert!(
+                    state
+                        .exceeded_limits
+                        .iter()
+                        .any(|(_, exceeded)| *exceeded == limit),
+                    "{limit:?} wasn't reported"
+                );
+            });
+        }
+    }
+
+    #[gpui::test]
+    async fn test_long_single_line_document(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        // A minified document, 5 MB on one line.
+        let mut text = String::new();
+        while text.len() < 5 * 1024 * 1024 {
+            text.push_str(&format!("word{} ", text.len()));
+        }
+        text.push('\n');
+        view.update(cx, |view, cx| view.replace_text(text, cx));
+        cx.run_until_parked();
+
+        let (block_count, continued_count) = view.update(cx, |view, _| {
+            let children = &view.contents.as_ref().unwrap().children;
+            let continued_count = children
+                .iter()
+                .filter(|block| {
+                    matches!(block, ParsedMarkdownElement::Paragraph(text) if text.continued)
+                })
+                .count();
+            (children.len(), continued_count)
+        });
+        assert!(block_count > 300);
+        assert_eq!(continued_count, block_count - 1);
+        let started_at = Instant::now();
+        for ix in 0..10 {
+            cx.draw(point(px(0.), px(0.)), size(px(800.), px(600.)), |cx| {
+                view.update(cx, |view, cx| view.render_block(ix, cx))
+            });
+        }
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+
+        // Text without a space to split it at is cut off instead.
+        view.update(cx, |view, cx| view.replace_text("a".repeat(200 * 1024), cx));
+        cx.run_until_parked();
+        cx.draw(point(px(0.), px(0.)), size(px(800.), px(600.)), |cx| {
+            view.update(cx, |view, cx| view.render_block(0, cx))
+        });
+        view.update(cx, |view, _| {
+            let state = view.presentation_state.borrow();
+            assert!(state
+                .exceeded_limits
+                .iter()
+                .any(|(_, limit)| *limit == RenderLimit::TextLength));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_reloaded_code_reuses_highlights(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let language_registry = Arc::new(LanguageRegistry::test(cx.executor()));
+        language_registry.add(Arc::new(language::Language::new(
+            language::LanguageConfig {
+                name: "Rust".into(),
+                ..Default::default()
+            },
+            Some(language::tree_sitter_rust::LANGUAGE.into()),
+        )));
+        let document = |changed: &str| -> String {
+            (0..50)
+                .map(|ix| {
+                    let name = if ix == 10 { changed } else { "f" };
+                    format!("```rust\nfn {name}{ix}() {{}}\n```\n\n")
+                })
+                .collect()
+        };
+        view.update(cx, |view, cx| {
+            view.set_language_registry(language_registry, cx);
+            view.set_text(document("f"), cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| assert_eq!(view.highlight_cache.computed, 50));
+
+        // Parsing the whole document again, like a reload does, only
+        // highlights the block that changed.
+        view.update(cx, |view, cx| view.replace_text(document("g"), cx));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.highlight_cache.computed, 51);
+            let contents = view.contents.as_mut().unwrap();
+            assert!(contents
+                .children
+                .iter_mut()
+                .flat_map(ParsedMarkdownElement::code_blocks_mut)
+                .all(|code_block| code_block.highlights.is_some()));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_scroll_anchor_follows_moved_blocks() {
+        let anchor = ScrollAnchor {
+            block_ix: 1,
+            source: "Second one\n".to_string(),
+            offset_in_item: px(4.),
+            offset_fraction: 0.5,
+            section: None,
+            text_fraction: 7. / 19.,
+        };
+
+        // Blocks inserted before the anchored one move it down.
+        let text = "# New\n\nFirst\n\nSecond one\n";
+        let parsed = parse_markdown(text, None, None).await;
+        assert_eq!(anchor.find_block(&parsed.children, text), Some((2, true)));
+
+        // When it's edited, the block now in its place is used instead.
+        let text = "First\n\nSecond, edited\n\nThird\n";
+        let parsed = parse_markdown(text, None, None).await;
+        assert_eq!(anchor.find_block(&parsed.children, text), Some((1, false)));
+
+        // And when it's gone, the block closest to where it was.
+        let text = "First\n";
+        let parsed = parse_markdown(text, None, None).await;
+        assert_eq!(anchor.find_block(&parsed.children, text), Some((0, false)));
+        assert_eq!(anchor.find_block(&[], ""), None);
+
+        // Within a section, the block is looked for near where it was in it.
+        let anchor = ScrollAnchor {
+            section: Some(("usage".to_string(), 2)),
+            ..anchor
+        };
+        let text = "# Usage\n\nIntro\n\nSecond one\n\n# Other\n\nSecond one\n";
+        let parsed = parse_markdown(text, None, None).await;
+        assert_eq!(anchor.find_block(&parsed.children, text), Some((2, true)));
+        let text = "Before\n\n# Usage\n\nIntro\n\nRewritten\n\nMore\n\n# Other\n";
+        let parsed = parse_markdown(text, None, None).await;
+        assert_eq!(anchor.find_block(&parsed.children, text), Some((3, false)));
+    }
+
+    #[gpui::test]
+    async fn test_reload_keeps_the_section_in_view(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let document = |paragraph: &str, extra: usize| {
+            (0..20)
+                .map(|ix| {
+                    let extra = if ix == 5 {
+                        format!("{paragraph}\n\n").repeat(extra)
+                    } else {
+                        String::new()
+                    };
+                    format!("## Section {ix}\n\n{extra}{paragraph} a\n\n{paragraph} b\n\n")
+                })
+                .collect::<String>()
+        };
+        view.update(cx, |view, cx| view.set_text(document("Text", 0), cx));
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            view.list_state.scroll_to(ListOffset {
+                item_ix: 31,
+                offset_in_item: px(0.),
+            });
+            cx.notify();
+        });
+        cx.run_until_parked();
+
+        // Every paragraph changes and blocks are added in the middle, so the
+        // block at the top has a different index and source afterwards.
+        view.update(cx, |view, cx| view.set_text(document("Reformatted", 3), cx));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            let top = view.list_state.logical_scroll_top().item_ix;
+            assert_eq!(top, 34);
+            let heading = view.contents.as_ref().unwrap().children[..=top]
+                .iter()
+                .rev()
+                .find_map(|block| match block {
+                    ParsedMarkdownElement::Heading(heading) => Some(heading.slug.clone()),
+                    _ => None,
+                });
+            assert_eq!(heading.as_deref(), Some("section-10"));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_saving_a_scratch_document(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let directory =
+            std::env::temp_dir().join(format!("markdown-scratch-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("notes.md");
+        let text = "# Notes\n\nSee [the rest](other.md).\n";
+
+        view.update(cx, |view, cx| {
+            view.scratch = true;
+            view.set_text(text.to_string(), cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert!(view.is_scratch());
+            assert_eq!(view.title().as_ref(), "Untitled");
+            view.save_to(path.clone(), cx);
+        });
+        cx.run_until_parked();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+        view.update(cx, |view, _| {
+            assert!(!view.is_scratch());
+            assert_eq!(view.path(), Some(path.as_path()));
+            assert_eq!(view.title().as_ref(), "Notes");
+            let Some(ParsedMarkdownElement::Paragraph(paragraph)) = view
+                .contents
+                .as_ref()
+                .and_then(|contents| contents.children.get(1))
+            else {
+                panic!("expected a paragraph");
+            };
+            let link = paragraph
+                .regions
+                .iter()
+                .find_map(|region| region.link.clone());
+            assert!(
+                matches!(link, Some(Link::Path { path, .. }) if path == directory.join("other.md")),
+                "links resolve against the saved file"
+            );
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn test_reading_appended_text() {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-follow-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("build.md");
+        let text = "# Build\n\nStarted\n";
+        std::fs::write(&path, text).unwrap();
+        let append = |bytes: &[u8]| {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            std::io::Write::write_all(&mut file, bytes).unwrap();
+        };
+
+        append("\nFinished é".as_bytes());
+        // The first half of a character that's still being written.
+        append(&"ü".as_bytes()[..1]);
+        assert_eq!(
+            read_file_change(&path, Some(FollowedText::new(text))).unwrap(),
+            FileChange::Appended("\nFinished é".to_string())
+        );
+        assert_eq!(
+            read_file_change(&path, None).ok(),
+            None,
+            "the file isn't valid UTF-8 until the character is finished"
+        );
+
+        // Files that shrink, or whose end changed, were replaced.
+        std::fs::write(&path, "# Build\n").unwrap();
+        assert_eq!(
+            read_file_change(&path, Some(FollowedText::new(text))).unwrap(),
+            FileChange::Replaced("# Build\n".to_string())
+        );
+        std::fs::write(&path, "# Built\n\nStarted\nAnd more\n").unwrap();
+        assert_eq!(
+            read_file_change(&path, Some(FollowedText::new(text))).unwrap(),
+            FileChange::Replaced("# Built\n\nStarted\nAnd more\n".to_string())
+        );
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_toggling_follow_mode(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let text = (0..100)
+            .map(|ix| format!("Line {ix}\n\n"))
+            .collect::<String>();
+        view.update(cx, |view, cx| view.set_text(text, cx));
+        cx.run_until_parked();
+
+        // Following starts at the end of the document, even if the reader
+        // had scrolled away from it.
+        view.update(cx, |view, cx| {
+            view.at_bottom.set(false);
+            view.toggle_follow(&ToggleFollow, cx);
+            assert!(view.is_following());
+            assert!(view.at_bottom.get());
+            view.append_text("Line 100\n\nLine 101\n", cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert_eq!(view.block_count(), 102);
+            view.toggle_follow(&ToggleFollow, cx);
+            assert!(!view.is_following());
+        });
+    }
+
+    #[gpui::test]
+    async fn test_status_bar_word_count(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.set_text("# One two\n\nthree".to_string(), cx)
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.word_counts.iter().sum::<usize>(), 3)
+        });
+
+        // Appended blocks are counted without counting the rest again.
+        view.update(cx, |view, cx| view.append_text("\n\nfour five", cx));
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert_eq!(view.word_counts, vec![2, 1, 2]);
+            assert!(view.render_status_bar(cx).is_some());
+        });
+
+        cx.update(|cx| {
+            cx.update_global(|store: &mut SettingsStore, cx| {
+                store
+                    .set_user_settings(r#"{"markdown_preview": {"show_status_bar": false}}"#, cx)
+                    .unwrap()
+            })
+        });
+        view.update(cx, |view, cx| assert!(view.render_status_bar(cx).is_none()));
+    }
+
+    #[gpui::test]
+    async fn test_copy_diagnostics(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let text = "# Secret plans\n\nMeet at noon.\n\n```\nlaunch()\n```\n";
+        view.update(cx, |view, cx| view.set_text(text.to_string(), cx));
+        cx.run_until_parked();
+
+        view.update(cx, |view, _| {
+            let stats = view.document_stats();
+            assert_eq!(
+                (stats.bytes, stats.blocks, stats.headings, stats.code_blocks),
+                (text.len(), 3, 1, 1)
+            );
+            assert!(stats.last_parse_duration.is_some());
+        });
+
+        view.update(cx, |view, cx| view.copy_diagnostics(&CopyDiagnostics, cx));
+        let report = cx
+            .read_from_clipboard()
+            .and_then(|item| item.text())
+            .unwrap();
+        assert!(report.contains(env!("CARGO_PKG_VERSION")));
+        assert!(report.contains("text_scale"));
+        assert!(!report.contains("Secret"));
+        assert!(!report.contains("noon"));
+        assert!(!report.contains("launch"));
+    }
+
+    #[gpui::test]
+    async fn test_empty_state(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        for text in ["", "  \n\n\t\n"] {
+            view.update(cx, |view, cx| view.set_text(text.to_string(), cx));
+            cx.run_until_parked();
+            view.update(cx, |view, cx| {
+                assert_eq!(view.empty_state_message(), Some("Nothing to preview"));
+                assert!(view.render_empty_state(cx).is_some());
+            });
+        }
+
+        // Front matter is still shown, above a note that there's nothing
+        // after it.
+        view.update(cx, |view, cx| {
+            view.set_text("---\ntitle: Notes\n---\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert_eq!(
+                view.empty_state_message(),
+                Some("This document has no content")
+            );
+            assert!(view.render_front_matter(cx).is_some());
+        });
+
+        view.update(cx, |view, cx| view.set_text("Text\n".to_string(), cx));
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert_eq!(view.empty_state_message(), None);
+            assert!(view.render_empty_state(cx).is_none());
+        });
+
+        // Hosts can leave the note out.
+        let view = cx.update(|cx| MarkdownView::builder("").hide_empty_state().build(cx));
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert!(view.empty_state_message().is_some());
+            assert!(view.render_empty_state(cx).is_none());
+        });
+    }
+
+    #[gpui::test]
+    async fn test_front_matter_card(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.set_text("---\ntitle: Notes\n---\n\n# Notes\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert_eq!(view.contents.as_ref().unwrap().children.len(), 1);
+            assert!(view.render_front_matter(cx).is_some());
+        });
+
+        cx.update(|cx| {
+            cx.update_global(|store: &mut SettingsStore, cx| {
+                store
+                    .set_user_settings(r#"{"markdown_preview": {"show_front_matter": false}}"#, cx)
+                    .unwrap()
+            })
+        });
+        view.update(cx, |view, cx| {
+            assert!(view.render_front_matter(cx).is_none())
+        });
+
+        view.update(cx, |view, cx| view.set_text("# Notes\n".to_string(), cx));
+        cx.run_until_parked();
+        cx.update(|cx| {
+            cx.update_global(|store: &mut SettingsStore, cx| {
+                store.set_user_settings("{}", cx).unwrap()
+            })
+        });
+        view.update(cx, |view, cx| {
+            assert!(view.render_front_matter(cx).is_none())
+        });
+    }
+
+    #[gpui::test]
+    async fn test_diagnostics_banner(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let list = "- a\n  - b\n- c\n    - d\n";
+        view.update(cx, |view, cx| {
+            view.set_text(format!("# Intro\n\n{list}"), cx)
+        });
+        cx.run_until_parked();
+        let diagnosed = |view: &View<MarkdownView>, cx: &mut VisualTestContext| {
+            view.update(cx, |view, _| {
+                view.contents
+                    .as_ref()
+                    .unwrap()
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| {
+                        view.contents_text[diagnostic.source_range.clone()]
+                            .trim_end()
+                            .to_string()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(diagnosed(&view, cx), ["- d"]);
+        view.update(cx, |view, cx| {
+            assert!(view.render_diagnostics_banner(cx).is_some())
+        });
+
+        // Diagnostics move with the text before them as it's edited.
+        view.update(cx, |view, cx| {
+            view.set_text(format!("# Introduction\n\n{list}"), cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(diagnosed(&view, cx), ["- d"]);
+
+        view.update(cx, |view, cx| {
+            view.diagnostics_dismissed = true;
+            assert!(view.render_diagnostics_banner(cx).is_none());
+            view.set_text("# Introduction\n\n- a\n  - b\n".to_string(), cx);
+        });
+        cx.run_until_parked();
+        assert!(diagnosed(&view, cx).is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_hard_wrap_setting(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let paragraph = |view: &View<MarkdownView>, cx: &mut VisualTestContext| {
+            view.update(cx, |view, _| {
+                match &view.contents.as_ref().unwrap().children[0] {
+                    ParsedMarkdownElement::Paragraph(text) => text.contents.to_string(),
+                    block => panic!("expected a paragraph, got {block:?}"),
+                }
+            })
+        };
+        view.update(cx, |view, cx| view.set_text("one\ntwo\n".to_string(), cx));
+        cx.run_until_parked();
+        assert_eq!(paragraph(&view, cx), "one two");
+
+        cx.update(|cx| {
+            cx.update_global(|store: &mut SettingsStore, cx| {
+                store
+                    .set_user_settings(r#"{"markdown_preview": {"hard_wrap": true}}"#, cx)
+                    .unwrap()
+            })
+        });
+        cx.run_until_parked();
+        assert_eq!(paragraph(&view, cx), "one\ntwo");
+
+        // Views built with their own don't follow the setting.
+        let view = cx.update(|cx| {
+            MarkdownView::builder("one\ntwo\n")
+                .hard_wrap(false)
+                .build(cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(paragraph(&view, cx), "one two");
+    }
+
+    #[gpui::test]
+    async fn test_run_code(cx: &mut TestAppContext) {
+        let (_, cx) = init_test(cx);
+        let view = cx.update(|cx| {
+            MarkdownView::builder("```sh\necho hi\n```\n\n```Python\nfail\n```\n")
+                .on_run_code(["sh", "python"], |language, code, _| {
+                    Task::ready(if language == "python" {
+                        Err(anyhow::anyhow!("{code} isn't defined"))
+                    } else {
+                        Ok(format!("ran {code}"))
+                    })
+                })
+                .build(cx)
+        });
+        cx.run_until_parked();
+
+        let outputs = |view: &View<MarkdownView>, cx: &mut VisualTestContext| {
+            view.update(cx, |view, _| {
+                let state = view.presentation_state.borrow();
+                let mut outputs = state
+                    .code_runs
+                    .iter()
+                    .map(|(range, run)| (range.start, run.output.clone()))
+                    .collect::<Vec<_>>();
+                outputs.sort_by_key(|(start, _)| *start);
+                outputs
+            })
+        };
+        let blocks = view.update(cx, |view, _| {
+            view.contents
+                .as_ref()
+                .unwrap()
+                .children
+                .iter()
+                .map(|block| match block {
+                    ParsedMarkdownElement::CodeBlock(code) => {
+                        (code.source_range.clone(), code.contents.clone())
+                    }
+                    _ => panic!("expected a code block"),
+                })
+                .collect::<Vec<_>>()
+        });
+        for (range, code) in &blocks {
+            view.update(cx, |view, cx| {
+                view.run_code(range.clone(), "sh".into(), code.clone(), cx)
+            });
+        }
+        // Until the tasks resolve, the blocks are running.
+        view.update(cx, |view, _| {
+            assert!(view
+                .presentation_state
+                .borrow()
+                .code_runs
+                .values()
+                .all(|run| run.output.is_none()));
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            outputs(&view, cx),
+            vec![
+                (blocks[0].0.start, Some(Ok("ran echo hi".into()))),
+                (blocks[1].0.start, Some(Ok("ran fail".into()))),
+            ]
+        );
+
+        // Running a block again replaces its output.
+        view.update(cx, |view, cx| {
+            view.run_code(
+                blocks[1].0.clone(),
+                "python".into(),
+                blocks[1].1.clone(),
+                cx,
+            )
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            outputs(&view, cx),
+            vec![
+                (blocks[0].0.start, Some(Ok("ran echo hi".into()))),
+                (blocks[1].0.start, Some(Err("fail isn't defined".into()))),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_spellcheck(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        cx.update(|cx| {
+            let dictionary = Dictionary::new(["one", "two", "three"]);
+            Spellchecker::insert_dictionary("test".into(), Arc::new(dictionary), cx);
+            cx.update_global::<SettingsStore, _>(|store, cx| {
+                store
+                    .set_user_settings(
+                        r#"{"markdown_preview": {"spellcheck": true, "spellcheck_language": "test"}}"#,
+                        cx,
+                    )
+                    .unwrap()
+            });
+        });
+        view.update(cx, |view, cx| {
+            view.set_text("one twoo\n\nthree\n\n`fourr`\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        let misspelled_words =
+            |cx: &mut VisualTestContext| view.update(cx, |view, cx| view.misspelled_words(cx));
+        assert_eq!(misspelled_words(cx), vec![SharedString::from("twoo")]);
+
+        // Only the blocks that changed are checked again, which the
+        // dictionary changing in between gives away.
+        view.update(cx, |view, _| {
+            view.spellcheck.as_mut().unwrap().dictionary =
+                Some(Arc::new(Dictionary::new(["one", "two"])));
+        });
+        view.update(cx, |view, cx| {
+            view.set_text("one two\n\nthree\n\n`fourr`\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        assert!(misspelled_words(cx).is_empty());
+        view.update(cx, |view, cx| {
+            view.set_text("one two\n\nthree four\n\n`fourr`\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            misspelled_words(cx),
+            vec![SharedString::from("three"), SharedString::from("four")]
+        );
+
+        cx.update(|cx| Spellchecker::add_word("Four", cx));
+        assert_eq!(misspelled_words(cx), vec![SharedString::from("three")]);
+
+        // Turning it off doesn't parse the document again.
+        let parse_generation = view.update(cx, |view, _| view.parse_generation);
+        cx.update(|cx| {
+            cx.update_global::<SettingsStore, _>(|store, cx| {
+                store
+                    .set_user_settings(r#"{"markdown_preview": {"spellcheck": false}}"#, cx)
+                    .unwrap()
+            });
+        });
+        cx.run_until_parked();
+        assert!(misspelled_words(cx).is_empty());
+        view.update(cx, |view, _| {
+            assert!(view.spellcheck.is_none());
+            assert_eq!(view.parse_generation, parse_generation);
+        });
+    }
+
+    #[gpui::test]
+    async fn test_builder(cx: &mut TestAppContext) {
+        let (_, cx) = init_test(cx);
+        let view = cx.update(|cx| {
+            MarkdownView::builder("| a |\n|---|\n| b |")
+                .base_path("/docs")
+                .parse_options(ParseOptions {
+                    tables: false,
+                    ..Default::default()
+                })
+                .image_policy(ImagePolicy::Never)
+                .read_only(true)
+                .build(cx)
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.base_directory.as_deref(), Some(Path::new("/docs")));
+            assert_eq!(view.image_policy, ImagePolicy::Never);
+            assert!(view.read_only);
+            assert!(matches!(
+                view.contents.as_ref().unwrap().children.as_slice(),
+                [ParsedMarkdownElement::Paragraph(_)]
+            ));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_loading_until_the_first_parse(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        assert!(view.update(cx, |view, _| view.is_loading()));
+        cx.run_until_parked();
+        assert!(!view.update(cx, |view, _| view.is_loading()));
+
+        // Later parses keep the previous version on screen.
+        view.update(cx, |view, cx| view.set_text("# Next".to_string(), cx));
+        assert!(!view.update(cx, |view, _| view.is_loading()));
+        cx.run_until_parked();
+        assert_contents(&view, "# Next", cx).await;
+    }
+
+    #[gpui::test]
+    async fn test_parse_debounce(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        cx.run_until_parked();
+
+        view.update(cx, |view, cx| {
+            view.set_parse_debounce(Duration::from_millis(100));
+            view.set_text("# One".to_string(), cx);
+        });
+        cx.executor().advance_clock(Duration::from_millis(50));
+        view.update(cx, |view, cx| view.set_text("# Two".to_string(), cx));
+        cx.executor().advance_clock(Duration::from_millis(50));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(
+                view.contents
+                    .as_ref()
+                    .map(|contents| contents.children.len()),
+                Some(0)
+            );
+        });
+
+        cx.executor().advance_clock(Duration::from_millis(50));
+        cx.run_until_parked();
+        assert_contents(&view, "# Two", cx).await;
+    }
+
+    #[gpui::test]
+    async fn test_find_steps_through_matches(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.set_text("# Zed\n\nzed and ZED\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+
+        let active_match = |view: &MarkdownView| {
+            let find_bar = view.find_bar.as_ref().unwrap();
+            (find_bar.active_ix, find_bar.matches.len())
+        };
+        view.update(cx, |view, cx| {
+            view.find(&Find, cx);
+            view.find_bar.as_mut().unwrap().query = "zed".to_string();
+            view.update_search(true, cx);
+            assert_eq!(active_match(view), (Some(0), 3));
+
+            view.select_previous_match(&SelectPreviousMatch, cx);
+            assert_eq!(active_match(view), (Some(2), 3));
+            view.select_next_match(&SelectNextMatch, cx);
+            assert_eq!(active_match(view), (Some(0), 3));
+
+            view.toggle_case_sensitive(&ToggleCaseSensitive, cx);
+            assert_eq!(active_match(view), (Some(0), 1));
+            view.set_text("Nothing here".to_string(), cx);
+        });
+        cx.run_until_parked();
+
+        // The matches follow the document as it's re-parsed.
+        view.update(cx, |view, cx| {
+            assert_eq!(active_match(view), (None, 0));
+            view.dismiss_find(&DismissFind, cx);
+            assert!(view.find_bar.is_none());
+            assert!(view.search_highlights.is_none());
+        });
+    }
+
+    #[gpui::test]
+    async fn test_zoom(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            assert_eq!(view.text_scale(), 1.);
+            view.zoom_in(&ZoomIn, cx);
+            view.zoom_in(&ZoomIn, cx);
+            assert_eq!(view.text_scale(), 1.2);
+            assert_eq!(MarkdownPreviewSettings::get_global(cx).text_scale, 1.2);
+
+            view.set_text_scale(2.95, cx);
+            view.zoom_in(&ZoomIn, cx);
+            assert_eq!(view.text_scale(), 3.);
+            view.set_text_scale(0.5, cx);
+            view.zoom_out(&ZoomOut, cx);
+            assert_eq!(view.text_scale(), 0.5);
+
+            view.reset_zoom(&ResetZoom, cx);
+            assert_eq!(MarkdownPreviewSettings::get_global(cx).text_scale, 1.);
+            assert_eq!(
+                view.toast.as_ref().map(|(message, _)| message.as_ref()),
+                Some("100%")
+            );
+        });
+
+        // The zoom level is only shown for a moment.
+        cx.executor().advance_clock(TOAST_DURATION);
+        cx.run_until_parked();
+        view.update(cx, |view, _| assert!(view.toast.is_none()));
+
+        // And new previews open at the saved zoom.
+        view.update(cx, |view, cx| view.set_text_scale(1.5, cx));
+        let (view, cx) = cx.add_window_view(|cx| MarkdownView::new(String::new(), None, cx));
+        view.update(cx, |view, _| assert_eq!(view.text_scale(), 1.5));
+    }
+
+    #[gpui::test]
+    async fn test_keyboard_scrolling(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        cx.update(|cx| {
+            cx.update_global(|store: &mut SettingsStore, cx| {
+                store
+                    .set_user_settings(r#"{"markdown_preview": {"vim_scrolling": true}}"#, cx)
+                    .unwrap()
+            })
+        });
+        let text = (0..200)
+            .map(|ix| format!("Paragraph {ix}\n\n"))
+            .collect::<String>();
+        view.update(cx, |view, cx| {
+            view.set_text(text, cx);
+            cx.focus(&view.focus_handle);
+        });
+        cx.run_until_parked();
+
+        let scroll_top = |cx: &mut VisualTestContext| {
+            view.update(cx, |view, _| view.list_state.logical_scroll_top())
+        };
+        let finish_animation = |cx: &mut VisualTestContext| {
+            cx.executor()
+                .advance_clock(SCROLL_ANIMATION_STEP_DURATION * SCROLL_ANIMATION_STEPS as u32);
+            cx.run_until_parked();
+        };
+
+        cx.simulate_keystrokes("end");
+        finish_animation(cx);
+        assert!(scroll_top(cx).item_ix > 100);
+        cx.simulate_keystrokes("home");
+        finish_animation(cx);
+        assert_eq!(scroll_top(cx).item_ix, 0);
+
+        cx.simulate_keystrokes("j");
+        let top = scroll_top(cx);
+        assert!(top.item_ix > 0 || top.offset_in_item > Pixels::ZERO);
+        cx.simulate_keystrokes("k");
+        assert_eq!(scroll_top(cx).offset_in_item, Pixels::ZERO);
+
+        // Keys typed into the find bar don't scroll.
+        view.update(cx, |view, cx| view.find(&Find, cx));
+        cx.simulate_keystrokes("j space k");
+        finish_animation(cx);
+        assert_eq!(scroll_top(cx).item_ix, 0);
+        view.update(cx, |view, _| {
+            assert_eq!(view.find_bar.as_ref().unwrap().query, "j k");
+        });
+    }
+
+    #[gpui::test]
+    async fn test_reduce_motion(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        cx.update(|cx| {
+            cx.update_global(|store: &mut SettingsStore, cx| {
+                store
+                    .set_user_settings(r#"{"markdown_preview": {"reduce_motion": true}}"#, cx)
+                    .unwrap()
+            })
+        });
+        let text = (0..100)
+            .map(|ix| format!("Paragraph {ix}\n\n"))
+            .collect::<String>();
+        view.update(cx, |view, cx| view.set_text(text, cx));
+        cx.run_until_parked();
+
+        // The scroll is done before any animation frame would be.
+        view.update(cx, |view, cx| {
+            assert!(view.scroll_to_block(60, cx));
+            assert!(view.scroll_animation.is_none());
+            assert_eq!(view.list_state.logical_scroll_top().item_ix, 60);
+        });
+    }
+
+    struct ScrollingHost {
+        handle: ScrollHandle,
+        view: View<MarkdownView>,
+    }
+
+    impl Render for ScrollingHost {
+        fn render(&mut self, _: &mut ViewContext<Self>) -> impl IntoElement {
+            div()
+                .id("scrolling-host")
+                .size_full()
+                .overflow_y_scroll()
+                .track_scroll(&self.handle)
+                .child(self.view.clone())
+        }
+    }
+
+    #[gpui::test]
+    async fn test_scrolled_by_host(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            let settings_store = SettingsStore::test(cx);
+            cx.set_global(settings_store);
+            theme::init(LoadThemes::JustBase, cx);
+            crate::init(cx);
+        });
+        let handle = ScrollHandle::new();
+        let text = (0..100)
+            .map(|ix| format!("Paragraph {ix}\n\n"))
+            .collect::<String>();
+        let (host, cx) = cx.add_window_view(|cx| ScrollingHost {
+            view: MarkdownView::builder(text)
+                .scroll_handle(handle.clone())
+                .build(cx),
+            handle: handle.clone(),
+        });
+        cx.run_until_parked();
+        let view = host.update(cx, |host, _| host.view.clone());
+
+        // Every block is laid out, however far down it is.
+        let content_height = view.update(cx, |view, _| {
+            assert!(view.block_layouts.borrow().bounds(99).is_some());
+            view.content_height().unwrap()
+        });
+        assert!(content_height > handle.bounds().size.height);
+
+        // Jumps scroll the host.
+        view.update(cx, |view, cx| assert!(view.scroll_to_block(60, cx)));
+        cx.run_until_parked();
+        let block_top = view.update(cx, |view, _| {
+            view.block_layouts.borrow().bounds(60).unwrap().top()
+        });
+        assert!(handle.offset().y < Pixels::ZERO);
+        assert!((block_top - handle.bounds().top()).abs() < px(1.));
+    }
+
+    #[gpui::test]
+    async fn test_lightbox(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.set_text("A tall screenshot\n".to_string(), cx);
+            cx.focus(&view.focus_handle);
+        });
+        cx.run_until_parked();
+
+        view.update(cx, |view, cx| {
+            view.show_lightbox(
+                Link::Web {
+                    url: "https://example.com/shot.png".to_string(),
+                },
+                cx,
+            )
+        });
+        view.update(cx, |view, _| assert!(view.lightbox.is_some()));
+        cx.simulate_keystrokes("escape");
+        view.update(cx, |view, _| assert!(view.lightbox.is_none()));
+    }
+
+    #[gpui::test]
+    async fn test_heading_picker(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        cx.update(|cx| {
+            cx.update_global(|store: &mut SettingsStore, cx| {
+                store
+                    .set_user_settings(r#"{"markdown_preview": {"outline_numbers": true}}"#, cx)
+                    .unwrap()
+            })
+        });
+        let text = (0..50)
+            .map(|ix| format!("Paragraph {ix}\n\n"))
+            .collect::<String>();
+        view.update(cx, |view, cx| {
+            view.set_text(
+                format!("# Guide\n\n## Usage\n\n{text}## Installation\n\n### Linux\n"),
+                cx,
+            );
+            cx.focus(&view.focus_handle);
+        });
+        cx.run_until_parked();
+
+        view.update(cx, |view, cx| view.go_to_heading(&GoToHeading, cx));
+        view.update(cx, |view, cx| {
+            assert_eq!(view.heading_picker.as_ref().unwrap().matches, [0, 1, 2, 3]);
+            assert_eq!(
+                view.outline_numbers(cx).unwrap(),
+                ["1", "1.1", "1.2", "1.2.1"]
+            );
+        });
+
+        // Typed keys filter the headings instead of scrolling.
+        cx.simulate_keystrokes("i n s t");
+        view.update(cx, |view, _| {
+            let picker = view.heading_picker.as_ref().unwrap();
+            assert_eq!(picker.query, "inst");
+            assert_eq!(picker.matches, [2]);
+            assert_eq!(view.list_state.logical_scroll_top().item_ix, 0);
+        });
+        cx.simulate_keystrokes("enter");
+        cx.executor()
+            .advance_clock(SCROLL_ANIMATION_STEP_DURATION * SCROLL_ANIMATION_STEPS as u32);
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert!(view.heading_picker.is_none());
+            assert_eq!(view.list_state.logical_scroll_top().item_ix, 52);
+        });
+
+        // Section numbers match too.
+        view.update(cx, |view, cx| view.go_to_heading(&GoToHeading, cx));
+        cx.simulate_keystrokes("1 . 1");
+        view.update(cx, |view, _| {
+            assert_eq!(view.heading_picker.as_ref().unwrap().matches[0], 1);
+        });
+        cx.simulate_keystrokes("escape");
+        view.update(cx, |view, _| assert!(view.heading_picker.is_none()));
+    }
+
+    #[gpui::test]
+    async fn test_fragment_links(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let text = (0..50)
+            .map(|ix| format!("Paragraph {ix}\n\n"))
+            .collect::<String>();
+        let text = format!("{text}## Set up\n\n{text}");
+        view.update(cx, |view, cx| view.set_text(text, cx));
+        cx.run_until_parked();
+
+        view.update(cx, |view, cx| {
+            assert_eq!(view.heading_for_fragment("set-up"), Some(50));
+            assert_eq!(view.heading_for_fragment("Set%20Up"), Some(50));
+            view.open_link(
+                &Link::Fragment {
+                    fragment: "set-up".to_string(),
+                },
+                cx,
+            );
+            assert_eq!(
+                view.flashed_block.as_ref().map(|(block_ix, _)| *block_ix),
+                Some(50)
+            );
+        });
+        cx.executor()
+            .advance_clock(SCROLL_ANIMATION_STEP_DURATION * SCROLL_ANIMATION_STEPS as u32);
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert_eq!(view.list_state.logical_scroll_top().item_ix, 50);
+            view.go_back(&GoBack, cx);
+            assert_eq!(view.list_state.logical_scroll_top().item_ix, 0);
+
+            view.open_link(
+                &Link::Fragment {
+                    fragment: "missing".to_string(),
+                },
+                cx,
+            );
+            assert_eq!(
+                view.toast.as_ref().map(|(message, _)| message.as_ref()),
+                Some("No such section")
+            );
+        });
+
+        cx.executor().advance_clock(FLASH_DURATION);
+        cx.run_until_parked();
+        view.update(cx, |view, _| assert!(view.flashed_block.is_none()));
+    }
+
+    #[gpui::test]
+    async fn test_checking_links(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-view-link-check-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("other.md"), "# Other\n").unwrap();
+
+        let (view, cx) = init_test(cx);
+        cx.update(|cx| {
+            let cache = cx.global_mut::<LinkCheckCache>();
+            cache.insert("https://zed.dev".to_string(), Ok(()));
+            cache.insert(
+                "https://zed.dev/gone".to_string(),
+                Err("The server responded with 404 Not Found".into()),
+            );
+        });
+        view.update(cx, |view, cx| {
+            view.base_directory = Some(directory.clone());
+            view.set_text(
+                "# Setup\n\n[setup](#setup) [install](#install) [other](other.md) \
+                 [gone](gone.md) [site](https://zed.dev) [page](https://zed.dev/gone) \
+                 [mail](mailto:me@example.com)\n"
+                    .to_string(),
+                cx,
+            );
+        });
+        cx.run_until_parked();
+
+        view.update(cx, |view, cx| view.check_links(&CheckLinks, cx));
+        view.update(cx, |view, cx| {
+            let check = view.link_check.as_ref().unwrap();
+            assert!(check.task.is_none());
+            assert_eq!((check.checked, check.total), (6, 6));
+            let mut broken = check
+                .broken
+                .iter()
+                .map(|(link, reason)| (link.to_string(), reason.to_string()))
+                .collect::<Vec<_>>();
+            broken.sort();
+            assert_eq!(
+                broken,
+                [
+                    (
+                        "#install".to_string(),
+                        "No heading with this name in the document".to_string()
+                    ),
+                    ("gone.md".to_string(), "No such file".to_string()),
+                    (
+                        "https://zed.dev/gone".to_string(),
+                        "The server responded with 404 Not Found".to_string()
+                    ),
+                ]
+            );
+            assert!(view.render_link_check_banner(cx).is_some());
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_scroll_to_fragment(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let text = (0..50)
+            .map(|ix| format!("Paragraph {ix}\n\n"))
+            .collect::<String>();
+        view.update(cx, |view, cx| {
+            view.set_text(format!("{text}## Installation\n"), cx);
+            // Like a `doc.md#installation` from the command line, before the
+            // document is parsed.
+            view.scroll_to_fragment("installation".to_string(), cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert_eq!(
+                view.flashed_block.as_ref().map(|(block_ix, _)| *block_ix),
+                Some(50)
+            );
+
+            view.scroll_to_fragment("missing".to_string(), cx);
+            assert_eq!(view.list_state.logical_scroll_top().item_ix, 0);
+            assert_eq!(
+                view.toast.as_ref().map(|(message, _)| message.as_ref()),
+                Some("No such section")
+            );
+        });
+    }
+
+    #[gpui::test]
+    async fn test_only_the_last_navigation_is_shown(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-view-navigate-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let first = directory.join("first.md");
+        let second = directory.join("second.md");
+        std::fs::write(&first, "# First\n").unwrap();
+        std::fs::write(&second, "# Second\n").unwrap();
+
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| view.set_text("# Start\n".to_string(), cx));
+        cx.run_until_parked();
+
+        // Of two links followed in a row, the second is shown, with the
+        // document both were followed from to go back to.
+        view.update(cx, |view, cx| {
+            view.open_document(first.clone(), None, cx);
+            view.open_document(second.clone(), None, cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.text(), "# Second\n");
+            assert_eq!(view.back_stack.len(), 1);
+        });
+
+        // Going back before a link's document is read stays back.
+        view.update(cx, |view, cx| {
+            view.open_document(first.clone(), None, cx);
+            view.go_back(&GoBack, cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.text(), "# Start\n");
+            assert!(view.back_stack.is_empty());
+            assert_eq!(view.forward_stack.len(), 1);
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_block_links(cx: &mut TestAppContext) {
+        let path =
+            std::env::temp_dir().join(format!("markdown-view-lines-{}.md", std::process::id()));
+        std::fs::write(&path, "# Title\n\nFirst\nparagraph\n\n- item\n").unwrap();
+
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.open_document(path.clone(), Some("L4".to_string()), cx)
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(
+                view.flashed_block.as_ref().map(|(block_ix, _)| *block_ix),
+                Some(1)
+            );
+            let link = |line: usize| {
+                let mut url = url::Url::from_file_path(&path).unwrap();
+                url.set_fragment(Some(&format!("L{line}")));
+                url.to_string()
+            };
+            assert_eq!(view.block_link(0), Some(link(1)));
+            assert_eq!(view.block_link(2), Some(link(6)));
+            assert_eq!(view.block_link(3), None);
+            assert_eq!(view.block_for_fragment("L6-L7"), Some(2));
+            assert_eq!(view.block_for_fragment("L100"), Some(2));
+            assert_eq!(view.block_for_fragment("title"), Some(0));
+        });
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[gpui::test]
+    async fn test_layout_info(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let events = Rc::new(RefCell::new(0));
+        cx.update(|cx| {
+            let events = events.clone();
+            cx.subscribe(&view, move |_, event: &MarkdownEvent, _| {
+                if let MarkdownEvent::LayoutChanged = event {
+                    *events.borrow_mut() += 1;
+                }
+            })
+            .detach();
+        });
+        view.update(cx, |view, cx| {
+            view.set_text("# One\n\nTwo\n\nThree\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+
+        let block = |height: f32| Bounds::new(point(px(0.), px(0.)), size(px(100.), px(height)));
+        events.take();
+        view.update(cx, |view, cx| {
+            view.block_layouts.borrow_mut().clear();
+            view.block_layouts.borrow_mut().record(0, block(40.));
+            view.block_layouts.borrow_mut().record(2, block(20.));
+            view.update_layout_info(cx);
+            let info = view.layout_info().unwrap();
+            let offsets: Vec<_> = info
+                .block_offsets
+                .iter()
+                .map(|block| (block.block_ix, block.offset, block.estimated))
+                .collect();
+            // The second block is estimated at the average height.
+            assert_eq!(
+                offsets,
+                vec![(0, px(0.), false), (1, px(40.), false), (2, px(70.), true)]
+            );
+            assert_eq!(info.total_height, px(90.));
+            assert!(info.estimated);
+
+            // Laying the blocks out again at the same heights changes nothing.
+            view.block_layouts.borrow_mut().record(0, block(40.));
+            view.update_layout_info(cx);
+
+            view.block_layouts.borrow_mut().record(1, block(30.));
+            view.update_layout_info(cx);
+            assert!(!view.layout_info().unwrap().estimated);
+
+            // Estimates follow the heights as they change and as blocks are
+            // forgotten.
+            let mut layouts = view.block_layouts.borrow_mut();
+            layouts.forget(1);
+            assert_eq!(layouts.estimated_offset(2), px(70.));
+            layouts.record(0, block(60.));
+            assert_eq!(layouts.estimated_offset(2), px(100.));
+            assert_eq!(layouts.block_at_offset(px(99.), 3), (1, px(39.)));
+        });
+        assert_eq!(events.take(), 2);
+    }
+
+    #[gpui::test]
+    async fn test_parse_completed_events(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        cx.run_until_parked();
+        let (events_tx, mut events_rx) = mpsc::unbounded();
+        cx.update(|cx| {
+            cx.subscribe(&view, move |_, event: &MarkdownEvent, _| {
+                if let MarkdownEvent::ParseCompleted { blocks, .. } = event {
+                    events_tx.unbounded_send(*blocks).ok();
+                }
+            })
+            .detach();
+        });
+
+        // Setting the text again cancels the parse of the text before.
+        view.update(cx, |view, cx| {
+            view.set_text("# One\n".to_string(), cx);
+            view.set_text("# Two\n\nText\n".to_string(), cx);
+        });
+        assert_eq!(events_rx.next().await, Some(2));
+        assert_contents(&view, "# Two\n\nText\n", cx).await;
+        cx.draw(point(px(0.), px(0.)), size(px(800.), px(600.)), |cx| {
+            view.update(cx, |view, cx| view.render_block(1, cx))
+        });
+        view.update(cx, |view, _| {
+            assert!(view.block_layouts.borrow().height(1).is_some());
+        });
+        cx.run_until_parked();
+        assert!(events_rx.try_next().is_err());
+
+        // Closing the view cancels its parse, which never completes.
+        view.update(cx, |view, cx| view.set_text("# Three\n".to_string(), cx));
+        drop(view);
+        cx.update(|cx| cx.remove_window());
+        cx.run_until_parked();
+        assert_eq!(events_rx.next().await, None);
+    }
+
+    #[gpui::test]
+    async fn test_contents_changed_events(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        cx.update(|cx| {
+            let events = events.clone();
+            cx.subscribe(&view, move |_, event: &MarkdownEvent, _| {
+                if let MarkdownEvent::ContentsChanged { .. } = event {
+                    events.borrow_mut().push(event.clone())
+                }
+            })
+            .detach();
+        });
+        let changed = |inserted: Vec<usize>, removed: Vec<usize>, moved: Vec<(usize, usize)>| {
+            MarkdownEvent::ContentsChanged {
+                inserted,
+                removed,
+                moved,
+            }
+        };
+
+        view.update(cx, |view, cx| {
+            view.set_text("# One\n\nTwo\n\nThree\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(events.take(), vec![changed(vec![0, 1, 2], vec![], vec![])]);
+
+        view.update(cx, |view, cx| {
+            view.set_text("# One\n\nNew\n\nTwo\n\nThree\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            events.take(),
+            vec![changed(vec![1], vec![], vec![(1, 2), (2, 3)])]
+        );
+
+        view.update(cx, |view, cx| {
+            view.set_text("# One\n\nTwo\n\nNew\n\nThree\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            events.take(),
+            vec![changed(vec![], vec![], vec![(2, 1), (1, 2)])]
+        );
+
+        // Replacing everything still tells which blocks are gone.
+        view.update(cx, |view, cx| view.set_text("Other\n".to_string(), cx));
+        cx.run_until_parked();
+        assert_eq!(
+            events.take(),
+            vec![changed(vec![0], vec![0, 1, 2, 3], vec![])]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_link_activation_events(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| view.set_text("## Usage\n".to_string(), cx));
+        cx.run_until_parked();
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        cx.update(|cx| {
+            let events = events.clone();
+            cx.subscribe(&view, move |_, event: &MarkdownEvent, _| {
+                events.borrow_mut().push(event.clone())
+            })
+            .detach();
+        });
+
+        let link = Link::Fragment {
+            fragment: "usage".to_string(),
+        };
+        view.update(cx, |view, cx| {
+            view.activate_link(&link, LinkOrigin::Explicit, 3..8, cx);
+            assert_eq!(view.back_stack.len(), 1);
+
+            // Subscribers can take over what clicking a link does.
+            view.set_suppress_default(true);
+            view.activate_link(&link, LinkOrigin::Explicit, 3..8, cx);
+            assert_eq!(view.back_stack.len(), 1);
+        });
+
+        let event = MarkdownEvent::LinkActivated {
+            url: "#usage".to_string(),
+            kind: LinkKind::Fragment,
+            origin: LinkOrigin::Explicit,
+            source_range: 3..8,
+            modifiers: Modifiers::default(),
+        };
+        assert_eq!(*events.borrow(), vec![event.clone(), event]);
+    }
+
+    #[gpui::test]
+    async fn test_link_policy(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        let blocked = Rc::new(RefCell::new(Vec::new()));
+        let policy = LinkPolicy::default().on_blocked({
+            let blocked = blocked.clone();
+            move |url, _| blocked.borrow_mut().push(url.to_string())
+        });
+        let web = |url: &str| Link::Web {
+            url: url.to_string(),
+        };
+        assert!(policy.allows(&web("https://zed.dev")));
+        assert!(policy.allows(&web("MAILTO:me@zed.dev")));
+        assert_eq!(
+            policy
+                .blocked_reason(&web("javascript:alert(1)"))
+                .as_deref(),
+            Some("Links to javascript: URLs are blocked in this document")
+        );
+
+        view.update(cx, |view, cx| {
+            view.set_link_policy(policy, cx);
+            view.open_link(&web("file:///etc/passwd"), cx);
+        });
+        assert_eq!(*blocked.borrow(), vec!["file:///etc/passwd".to_string()]);
+    }
+
+    #[gpui::test]
+    async fn test_following_links_to_documents(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-view-links-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let other = directory.join("other.md");
+        std::fs::write(&other, "# Other\n\n## Usage\n").unwrap();
+
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.base_directory = Some(directory.clone());
+            view.set_text("[other](other.md#usage) [gone](gone.md)".to_string(), cx);
+        });
+        cx.run_until_parked();
+
+        let links = view.update(cx, |view, _| {
+            let Some(ParsedMarkdownElement::Paragraph(paragraph)) =
+                view.contents.as_ref().unwrap().children.first()
+            else {
+                panic!("expected a paragraph");
+            };
+            paragraph
+                .regions
+                .iter()
+                .filter_map(|region| region.link.clone())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(links.len(), 2);
+
+        view.update(cx, |view, cx| view.open_link(&links[0], cx));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.text(), "# Other\n\n## Usage\n");
+            assert_eq!(view.path.as_deref(), Some(other.as_path()));
+            assert_eq!(
+                view.flashed_block.as_ref().map(|(block_ix, _)| *block_ix),
+                Some(1)
+            );
+        });
+
+        view.update(cx, |view, cx| view.go_back(&GoBack, cx));
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            assert_eq!(view.text(), "[other](other.md#usage) [gone](gone.md)");
+            assert_eq!(view.path, None);
+
+            // Broken links leave the document as it is.
+            view.open_link(&links[1], cx);
+            assert!(view.navigation_error.is_some());
+            view.go_forward(&GoForward, cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.text(), "# Other\n\n## Usage\n");
+            assert_eq!(view.navigation_error, None);
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_context_menu(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.set_text("# Title\n\nSome **bold** text".to_string(), cx)
+        });
+        cx.run_until_parked();
+
+        view.update(cx, |view, cx| {
+            view.select_all(&SelectAll, cx);
+            view.copy(&Copy, cx);
+        });
+        assert_eq!(
+            cx.read_from_clipboard().and_then(|item| item.text()),
+            Some("Title\nSome bold text".to_string())
+        );
+        view.update(cx, |view, cx| view.copy_as_html(&CopyAsHtml, cx));
+        assert_eq!(
+            cx.read_from_clipboard().and_then(|item| item.text()),
+            Some("Title\nSome bold text".to_string())
+        );
+        assert_eq!(
+            view.update(cx, |view, _| view.selected_html()),
+            Some(
+                "<h1 id=\"title\">Title</h1>\n<p>Some <strong>bold</strong> text</p>\n".to_string()
+            )
+        );
+
+        let code = HoveredElement::CodeBlock("fn main() {}".into());
+        view.update(cx, |view, cx| {
+            view.deploy_context_menu(point(px(10.), px(10.)), Some(code), cx)
+        });
+        let menu = view.update(cx, |view, _| view.context_menu.as_ref().unwrap().0.clone());
+        menu.update(cx, |_, cx| cx.emit(DismissEvent));
+        assert!(view.update(cx, |view, _| view.context_menu.is_none()));
+    }
+
+    #[gpui::test]
+    async fn test_copy_section(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.set_text("# One\n\n## Two\n\nText\n\n# Three\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+
+        let heading = HoveredElement::Heading(1);
+        view.update(cx, |view, cx| {
+            view.deploy_context_menu(point(px(10.), px(10.)), Some(heading), cx);
+            view.copy_section(1, cx);
+        });
+        assert_eq!(
+            cx.read_from_clipboard().and_then(|item| item.text()),
+            Some("## Two\n\nText\n".to_string())
+        );
+
+        view.update(cx, |view, cx| view.copy_section(0, cx));
+        assert_eq!(
+            cx.read_from_clipboard().and_then(|item| item.text()),
+            Some("# One\n\n## Two\n\nText\n".to_string())
+        );
+    }
+
+    #[gpui::test]
+    async fn test_debug_overlay(cx: &mut TestAppContext) {
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.set_text("# Title\n\nOne\ntwo\n\n- item\n".to_string(), cx)
+        });
+        cx.run_until_parked();
+
+        view.update(cx, |view, cx| {
+            assert!(!view.is_debug_overlay_visible());
+            assert!(view.last_parse_duration.is_some());
+            assert_eq!(view.last_parse_timing, None);
+            view.toggle_debug_overlay(&ToggleDebugOverlay, cx);
+            assert!(view.is_debug_overlay_visible());
+            assert_eq!(
+                (0..3)
+                    .filter_map(|ix| view.block_debug_label(ix))
+                    .collect::<Vec<_>>(),
+                vec!["#0 heading L1–1", "#1 paragraph L3–4", "#2 list item L6–6"]
+            );
+            assert_eq!(view.block_debug_label(3), None);
+        });
+
+        // Showing the overlay parses the document again, timing its parts.
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            let timing = view.last_parse_timing.as_ref().unwrap();
+            assert!(timing.total > Duration::ZERO);
+        });
+
+        let timing = ParseTiming {
+            block_scan: Duration::from_micros(1200),
+            inline: Duration::from_micros(800),
+            code_blocks: Duration::from_millis(3),
+            extensions: [("html", Duration::from_micros(100))].into_iter().collect(),
+            ..ParseTiming::default()
+        };
+        assert_eq!(
+            parse_timing_breakdown(&timing),
+            "scan 1.2 ms · inline 0.8 ms · tables 0.0 ms · code 3.0 ms · html 0.1 ms"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_checkboxes_are_written_to_the_file(cx: &mut TestAppContext) {
+        let path =
+            std::env::temp_dir().join(format!("markdown-view-tasks-{}.md", std::process::id()));
+        std::fs::write(&path, "- [ ] one\n- [x] two\n").unwrap();
+
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| view.open_file(path.clone(), cx));
+        cx.run_until_parked();
+
+        view.update(cx, |view, cx| view.toggle_checkbox(true, 2..5, cx));
+        cx.run_until_parked();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "- [x] one\n- [x] two\n"
+        );
+        assert_eq!(
+            view.update(cx, |view, _| view.text().to_string()),
+            "- [x] one\n- [x] two\n"
+        );
+
+        // Changes made elsewhere since the file was read aren't overwritten.
+        std::fs::write(&path, "- [x] one\n- [x] two\n- [ ] three\n").unwrap();
+        view.update(cx, |view, cx| view.toggle_checkbox(false, 12..15, cx));
+        cx.run_until_parked();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "- [x] one\n- [x] two\n- [ ] three\n"
+        );
+        view.update(cx, |view, _| {
+            assert!(view
+                .toast
+                .as_ref()
+                .is_some_and(|(message, _)| message.ends_with("wasn't updated")));
+        });
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[gpui::test]
+    async fn test_ui_state_is_restored_when_reopened(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-view-ui-state-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("notes.md");
+        std::fs::write(&path, "# Intro\n\nText\n\n# Usage\n\nMore\n").unwrap();
+
+        let (view, cx) = init_test(cx);
+        cx.update(|cx| cx.set_global(UiStateStore::load(directory.join("state.json"))));
+        let open = |cx: &mut VisualTestContext| {
+            let view = cx.new_view(|cx| MarkdownView::new(String::new(), None, cx));
+            view.update(cx, |view, cx| view.open_file(path.clone(), cx));
+            cx.run_until_parked();
+            view
+        };
+        view.update(cx, |view, cx| view.open_file(path.clone(), cx));
+        cx.run_until_parked();
+        view.update(cx, |view, cx| {
+            view.presentation_state
+                .borrow_mut()
+                .folded_sections
+                .insert("usage".to_string());
+            view.restore_text_scale(1.5, cx);
+            view.remember_ui_state(cx);
+        });
+
+        let reopened = open(cx);
+        reopened.update(cx, |view, _| {
+            assert_eq!(view.text_scale, 1.5);
+            assert!(view
+                .presentation_state
+                .borrow()
+                .folded_sections
+                .contains("usage"));
+        });
+
+        // Folds of headings that are gone are dropped.
+        std::fs::write(&path, "# Intro\n\nText\n\n# How to use it\n\nMore\n").unwrap();
+        let reopened = open(cx);
+        reopened.update(cx, |view, _| {
+            assert_eq!(view.text_scale, 1.5);
+            assert!(view.presentation_state.borrow().folded_sections.is_empty());
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_opening_binary_files(cx: &mut TestAppContext) {
+        let path = std::env::temp_dir().join(format!("markdown-view-{}.md", std::process::id()));
+        std::fs::write(&path, [0xff, 0xfe, 0x00]).unwrap();
+
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.set_text("# Kept".to_string(), cx);
+            view.open_document(path.clone(), None, cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.text(), "# Kept");
+            assert!(view
+                .toast
+                .as_ref()
+                .is_some_and(|(message, _)| message.ends_with("is not a text file")));
+        });
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[gpui::test]
+    async fn test_load_errors_can_be_retried(cx: &mut TestAppContext) {
+        let path =
+            std::env::temp_dir().join(format!("markdown-view-missing-{}.md", std::process::id()));
+        std::fs::remove_file(&path).ok();
+
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| view.open_file(path.clone(), cx));
+        assert!(view.update(cx, |view, _| view.is_loading()));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert!(!view.is_loading());
+            assert_eq!(
+                view.load_error(),
+                Some(&LoadError {
+                    message: "The file does not exist".into(),
+                    path: Some(path.clone()),
+                })
+            );
+        });
+
+        std::fs::write(&path, "# Found\n").unwrap();
+        view.update(cx, |view, cx| view.open_file(path.clone(), cx));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.load_error(), None);
+            assert_eq!(view.title(), "Found");
+        });
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[gpui::test]
+    async fn test_only_the_file_opened_last_is_shown(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-view-open-twice-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let first = directory.join("first.md");
+        let second = directory.join("second.md");
+        std::fs::write(&first, "# First\n").unwrap();
+        std::fs::write(&second, "# Second\n").unwrap();
+
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| {
+            view.open_file(first.clone(), cx);
+            view.open_file(second.clone(), cx);
+        });
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert_eq!(view.title(), "Second");
+            assert_eq!(view.path, Some(second.canonicalize().unwrap()));
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[gpui::test]
+    async fn test_failed_open_stops_watching_the_previous_file(cx: &mut TestAppContext) {
+        let directory =
+            std::env::temp_dir().join(format!("markdown-view-open-failed-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let watched = directory.join("watched.md");
+        let missing = directory.join("missing.md");
+        std::fs::write(&watched, "# Watched\n").unwrap();
+
+        let (view, cx) = init_test(cx);
+        view.update(cx, |view, cx| view.open_file(watched.clone(), cx));
+        cx.run_until_parked();
+        assert!(view.update(cx, |view, _| view.file_watch.is_some()));
+
+        view.update(cx, |view, cx| view.open_file(missing.clone(), cx));
+        assert!(view.update(cx, |view, _| view.file_watch.is_none()));
+        cx.run_until_parked();
+        view.update(cx, |view, _| {
+            assert!(view.file_watch.is_none());
+            assert_eq!(
+                view.load_error().map(|error| error.path.clone()),
+                Some(Some(missing.clone()))
+            );
+        });
+
+        // Retrying takes the error down while the file is read again.
+        view.update(cx, |view, cx| view.open_file(missing.clone(), cx));
+        view.update(cx, |view, _| {
+            assert_eq!(view.load_error(), None);
+            assert!(view.is_loading());
+        });
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn test_window_title() {
+        let path = PathBuf::from("/docs/notes.md");
+        assert_eq!(
+            window_title(Some(&path), Some("Notes"), false),
+            "notes.md — Notes"
+        );
+        assert_eq!(window_title(Some(&path), None, false), "notes.md");
+        assert_eq!(
+            window_title(None, Some("Example"), false),
+            "Markdown Preview"
+        );
+
+        let edited = window_title(Some(&path), None, true);
+        if cfg!(target_os = "macos") {
+            assert_eq!(edited, "notes.md");
+        } else {
+            assert_eq!(edited, "• notes.md");
+        }
+    }
+}
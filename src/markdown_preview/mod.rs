@@ -1,16 +1,294 @@
-use gpui::{actions, AppContext};
+use gpui::{actions, impl_actions, AppContext, KeyBinding};
+use markdown_images::{FetchLimits, ImageStore};
+use markdown_link_check::LinkCheckCache;
+use markdown_recent::RecentFiles;
+use markdown_settings::MarkdownPreviewSettings;
+use markdown_spellcheck::Spellchecker;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use settings::Settings as _;
+use std::{path::PathBuf, time::Duration};
+use util::ResultExt as _;
 // use workspace::Workspace;
 
+pub mod markdown_accessibility;
+pub mod markdown_diagnostics;
+pub mod markdown_diff;
+pub mod markdown_editor;
 pub mod markdown_elements;
+pub mod markdown_fences;
+pub mod markdown_format;
+pub mod markdown_front_matter;
+pub mod markdown_html;
+pub mod markdown_html_parser;
+pub mod markdown_images;
+#[cfg(any(test, feature = "test-support"))]
+pub mod markdown_layout;
+pub mod markdown_link_check;
+pub mod markdown_locale;
 pub mod markdown_parser;
+pub mod markdown_pdf;
+pub mod markdown_recent;
 // pub mod markdown_preview_view;
 pub mod markdown_renderer;
+pub mod markdown_search;
+pub mod markdown_session;
+pub mod markdown_settings;
+pub mod markdown_sidebar;
+pub mod markdown_spellcheck;
+pub mod markdown_startup;
+pub mod markdown_tabs;
+pub mod markdown_theme;
+pub mod markdown_ui_state;
+pub mod markdown_view;
 
-actions!(markdown, [OpenPreview, OpenPreviewToTheSide]);
+actions!(
+    markdown,
+    [
+        OpenPreview,
+        OpenPreviewToTheSide,
+        FoldAllSections,
+        UnfoldAllSections,
+        ToggleOutline,
+        ExportHtml,
+        ExportPdf,
+        Print,
+        Copy,
+        CopyAsMarkdown,
+        CopyAsHtml,
+        SelectAll,
+        Reload,
+        ToggleFollow,
+        DeployContextMenu,
+        Find,
+        SelectNextMatch,
+        SelectPreviousMatch,
+        DismissFind,
+        ToggleCaseSensitive,
+        ZoomIn,
+        ZoomOut,
+        ResetZoom,
+        ScrollLineUp,
+        ScrollLineDown,
+        ScrollPageUp,
+        ScrollPageDown,
+        ScrollToTop,
+        ScrollToBottom,
+        GoBack,
+        GoForward,
+        NewTab,
+        CloseTab,
+        ActivateNextTab,
+        ActivatePreviousTab,
+        Save,
+        SaveAs,
+        NewFromClipboard,
+        OpenRecent,
+        ClearRecent,
+        OpenFolder,
+        ToggleSidebar,
+        ToggleTheme,
+        ToggleDebugOverlay,
+        CloseLightbox,
+        CheckLinks,
+        GoToHeading,
+        FormatDocument,
+        Compare,
+        CompareWithSaved,
+        NextChange,
+        PreviousChange,
+        CopyDiagnostics
+    ]
+);
 
-// pub fn init(cx: &mut AppContext) {
-//     cx.observe_new_views(|workspace: &mut Workspace, cx| {
-//         markdown_preview_view::MarkdownPreviewView::register(workspace, cx);
-//     })
-//     .detach();
-// }
+/// Sets up the preview for an application that has no settings or themes of
+/// its own: the default settings, the base themes, and then [`init`].
+///
+/// Nothing is read from or saved to the user's settings file, so documents
+/// look the same everywhere. Views built with a preset like
+/// [`MarkdownStyle::light`](markdown_renderer::MarkdownStyle::light) don't
+/// follow the theme either.
+pub fn init_standalone(cx: &mut AppContext) {
+    let mut store = settings::SettingsStore::new(cx);
+    if let Err(error) = markdown_settings::default_settings()
+        .and_then(|defaults| store.set_default_settings(&defaults, cx))
+    {
+        log::error!("failed to load the default settings: {:#}", error);
+        store
+            .set_default_settings(&markdown_settings::fallback_settings(), cx)
+            .log_err();
+    }
+    cx.set_global(store);
+    theme::SystemAppearance::init(cx);
+    theme::init(theme::LoadThemes::JustBase, cx);
+    init(cx);
+}
+
+/// Opens one of the [`RecentFiles`], like from the "Open Recent" menu.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, JsonSchema)]
+pub struct OpenRecentFile {
+    pub path: PathBuf,
+}
+
+impl_actions!(markdown, [OpenRecentFile]);
+
+/// Sets up the global state the preview needs. Call this once at startup.
+///
+/// The renderer reads the active theme and [`theme::ThemeSettings`], but
+/// leaves registering them to the application, after which this must run.
+/// [`init_standalone`] does both, for applications without settings.
+pub fn init(cx: &mut AppContext) {
+    cx.set_global(ImageStore::default());
+    cx.set_global(RecentFiles::default());
+    cx.set_global(LinkCheckCache::default());
+    cx.set_global(Spellchecker::default());
+    cx.on_action(|_: &ClearRecent, cx| RecentFiles::clear(cx));
+    cx.on_action(markdown_theme::toggle_theme);
+    MarkdownPreviewSettings::register(cx);
+    apply_image_settings(cx);
+    cx.observe_global::<settings::SettingsStore>(apply_image_settings)
+        .detach();
+    markdown_theme::register_light_theme(cx);
+    // The source editor of a `MarkdownEditor` reads these.
+    language::init(cx);
+    editor::init_settings(cx);
+    project::Project::init_settings(cx);
+
+    // Shortcuts use cmd on macOS and ctrl everywhere else.
+    let modifier = if cfg!(target_os = "macos") {
+        "cmd"
+    } else {
+        "ctrl"
+    };
+    let context = Some("MarkdownPreview");
+    cx.bind_keys([
+        KeyBinding::new(&format!("{modifier}-shift-d"), ToggleTheme, None),
+        KeyBinding::new(&format!("alt-{modifier}-o"), ToggleOutline, context),
+        KeyBinding::new(&format!("{modifier}-shift-j"), GoToHeading, context),
+        KeyBinding::new(&format!("{modifier}-shift-e"), ExportHtml, context),
+        KeyBinding::new(&format!("{modifier}-p"), Print, context),
+        KeyBinding::new(&format!("{modifier}-shift-p"), ExportPdf, context),
+        KeyBinding::new(&format!("{modifier}-c"), Copy, context),
+        KeyBinding::new(&format!("{modifier}-shift-c"), CopyAsMarkdown, context),
+        KeyBinding::new(&format!("alt-{modifier}-shift-c"), CopyAsHtml, context),
+        KeyBinding::new(&format!("{modifier}-a"), SelectAll, context),
+        KeyBinding::new(&format!("{modifier}-r"), Reload, context),
+        KeyBinding::new(&format!("{modifier}-shift-f"), ToggleFollow, context),
+        KeyBinding::new(&format!("{modifier}-shift-s"), SaveAs, context),
+        KeyBinding::new("shift-f10", DeployContextMenu, context),
+        KeyBinding::new(&format!("{modifier}-f"), Find, context),
+        KeyBinding::new(&format!("alt-{modifier}-c"), ToggleCaseSensitive, context),
+        KeyBinding::new(&format!("alt-{modifier}-i"), ToggleDebugOverlay, context),
+        KeyBinding::new(&format!("{modifier}-="), ZoomIn, context),
+        KeyBinding::new(&format!("{modifier}-+"), ZoomIn, context),
+        KeyBinding::new(&format!("{modifier}--"), ZoomOut, context),
+        KeyBinding::new(&format!("{modifier}-0"), ResetZoom, context),
+        KeyBinding::new("up", ScrollLineUp, context),
+        KeyBinding::new("down", ScrollLineDown, context),
+        KeyBinding::new("pageup", ScrollPageUp, context),
+        KeyBinding::new("pagedown", ScrollPageDown, context),
+        KeyBinding::new("shift-space", ScrollPageUp, context),
+        KeyBinding::new("space", ScrollPageDown, context),
+        KeyBinding::new("home", ScrollToTop, context),
+        KeyBinding::new("end", ScrollToBottom, context),
+        KeyBinding::new(&format!("{modifier}-up"), ScrollToTop, context),
+        KeyBinding::new(&format!("{modifier}-down"), ScrollToBottom, context),
+        KeyBinding::new("alt-left", GoBack, context),
+        KeyBinding::new("alt-right", GoForward, context),
+        // Only with the `vim_scrolling` setting.
+        KeyBinding::new("k", ScrollLineUp, Some("MarkdownPreview && vim_scrolling")),
+        KeyBinding::new(
+            "j",
+            ScrollLineDown,
+            Some("MarkdownPreview && vim_scrolling"),
+        ),
+        KeyBinding::new("escape", CloseLightbox, Some("MarkdownPreview && lightbox")),
+        KeyBinding::new("enter", SelectNextMatch, Some("MarkdownFindBar")),
+        KeyBinding::new("shift-enter", SelectPreviousMatch, Some("MarkdownFindBar")),
+        KeyBinding::new("escape", DismissFind, Some("MarkdownFindBar")),
+        KeyBinding::new(&format!("{modifier}-t"), NewTab, Some("MarkdownTabs")),
+        KeyBinding::new(&format!("{modifier}-w"), CloseTab, Some("MarkdownTabs")),
+        KeyBinding::new(
+            &format!("{modifier}-shift-v"),
+            NewFromClipboard,
+            Some("MarkdownTabs"),
+        ),
+        KeyBinding::new("ctrl-tab", ActivateNextTab, Some("MarkdownTabs")),
+        KeyBinding::new("ctrl-shift-tab", ActivatePreviousTab, Some("MarkdownTabs")),
+        KeyBinding::new(
+            &format!("{modifier}-b"),
+            ToggleSidebar,
+            Some("MarkdownTabs"),
+        ),
+        KeyBinding::new(
+            &format!("{modifier}-shift-o"),
+            OpenRecent,
+            Some("MarkdownTabs"),
+        ),
+        KeyBinding::new(&format!("{modifier}-s"), Save, Some("MarkdownEditor")),
+        KeyBinding::new(
+            &format!("{modifier}-shift-i"),
+            FormatDocument,
+            Some("MarkdownEditor"),
+        ),
+        KeyBinding::new(
+            &format!("alt-{modifier}-d"),
+            CompareWithSaved,
+            Some("MarkdownEditor"),
+        ),
+        KeyBinding::new("f7", NextChange, Some("MarkdownDiff")),
+        KeyBinding::new("shift-f7", PreviousChange, Some("MarkdownDiff")),
+    ]);
+    bind_editor_keys(modifier, cx);
+}
+
+/// Sizes the shared image cache and limits image downloads from the
+/// settings, whenever they change.
+fn apply_image_settings(cx: &mut AppContext) {
+    let settings = MarkdownPreviewSettings::get_global(cx);
+    let byte_budget = settings.image_cache_size.saturating_mul(1024 * 1024);
+    let fetch_limits = FetchLimits {
+        timeout: Duration::from_secs(settings.image_fetch_timeout),
+        max_bytes: settings.max_image_download_size.saturating_mul(1024 * 1024),
+        max_concurrent: settings.max_concurrent_image_fetches,
+    };
+    let store = cx.global_mut::<ImageStore>();
+    store.set_byte_budget(byte_budget);
+    store.set_fetch_limits(fetch_limits);
+}
+
+/// The editor crate leaves its key bindings to the application's keymap, so
+/// the source editor gets the basic ones here.
+fn bind_editor_keys(modifier: &str, cx: &mut AppContext) {
+    use editor::actions::*;
+
+    let context = Some("Editor");
+    cx.bind_keys([
+        KeyBinding::new("backspace", Backspace, context),
+        KeyBinding::new("delete", Delete, context),
+        KeyBinding::new("enter", Newline, context),
+        KeyBinding::new("tab", Tab, context),
+        KeyBinding::new("shift-tab", Backtab, context),
+        KeyBinding::new("left", MoveLeft, context),
+        KeyBinding::new("right", MoveRight, context),
+        KeyBinding::new("up", MoveUp, context),
+        KeyBinding::new("down", MoveDown, context),
+        KeyBinding::new("shift-left", SelectLeft, context),
+        KeyBinding::new("shift-right", SelectRight, context),
+        KeyBinding::new("shift-up", SelectUp, context),
+        KeyBinding::new("shift-down", SelectDown, context),
+        KeyBinding::new(&format!("{modifier}-up"), MoveToBeginning, context),
+        KeyBinding::new(&format!("{modifier}-down"), MoveToEnd, context),
+        // The preview has actions of the same names.
+        KeyBinding::new(
+            &format!("{modifier}-a"),
+            editor::actions::SelectAll,
+            context,
+        ),
+        KeyBinding::new(&format!("{modifier}-c"), editor::actions::Copy, context),
+        KeyBinding::new(&format!("{modifier}-x"), Cut, context),
+        KeyBinding::new(&format!("{modifier}-v"), Paste, context),
+        KeyBinding::new(&format!("{modifier}-z"), Undo, context),
+        KeyBinding::new(&format!("{modifier}-shift-z"), Redo, context),
+    ]);
+}